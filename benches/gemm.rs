@@ -0,0 +1,618 @@
+//! Criterion benchmarks: one group per kernel, one benchmark per size.
+//!
+//! ```text
+//! cargo bench                               # everything
+//! cargo bench -- 12x4                       # one kernel (substring filter)
+//! cargo bench -- --save-baseline before     # record, make a change, then
+//! cargo bench -- --baseline before          # compare against the record
+//! ```
+//!
+//! Throughput is set to 2·m·n·k elements per iteration (one multiply and one
+//! add per inner step), so criterion's "Gelem/s" reads directly as GFLOPS.
+//! `main.rs` stays the quick human-readable runner; both use the same seeded
+//! `matrix::generate::random` inputs.
+//!
+//! The `transpose` and `pack` groups time the primitives the blocked drivers
+//! run before the arithmetic, in bytes (read plus written) per second, like
+//! `--mode primitives`.
+//!
+//! The `prepacked` group multiplies one 2048×2048 A by a 64-column B, as
+//! `multiply_with_backend` (packing A every call) and as
+//! `multiply_prepacked_a` with A packed beforehand.
+//!
+//! The `fixed_shape` group repeats one small multiply through `multiply` and
+//! through a `MatmulPlan` made once, where the difference is the per-call
+//! setup (allocating and sizing buffers) the plan does up front.
+//!
+//! The `triangular` group multiplies a 1024×1024 A by an upper-triangular B,
+//! as `multiply` with B's zeros stored and as `trmm`, which skips them.
+//!
+//! The `low_rank` group multiplies 2048×k by k×2048 for k = 1, 2, 4, where
+//! `multiply` takes its low-rank path, against the blocked 12×4 driver.
+//!
+//! The `fixed_size` group multiplies 3×3 up to 8×8 matrices through
+//! `multiply` and through `multiply_fixed`, where the per-call overhead is
+//! most of the cost.
+//!
+//! The `batch_tiny` group multiplies 4096 4×4 (and 8×8) matrices, looping
+//! `multiply` over them and as one interleaved `multiply_batch_tiny`.
+//!
+//! The `tiny_stream` group runs a million 8×8 multiplies back to back
+//! through `multiply`, against the same loop written out by hand, for the
+//! per-call overhead of the tiny path.
+//!
+//! The `accumulation` group runs a deep 512×8192 by 8192×512 multiply with
+//! `Accumulation::Sequential` and `Accumulation::Pairwise`, for what the
+//! smaller error bound costs.
+//!
+//! The `thin_k_scaling` group runs a 4096×4096×32 `multiply_parallel`,
+//! memory-bound at about 8 FLOPs per byte, asking for 1 up to 32 threads.
+//! The thread heuristic stops at 4 for it, so past that the rows should
+//! stay flat rather than slow down as threads contend for bandwidth.
+//!
+//! The `denormals` group shows what denormal inputs cost and what
+//! `DenormalMode::FlushToZero` buys back.
+//!
+//! The `epilogue` group computes relu(A·B + bias) with a 2048×2048 C and a
+//! 2048-wide bias, fused into a `MatMul` and as a multiply followed by a
+//! pass over C, next to the bare multiply, at k = 64 (where C's traffic is
+//! much of the time) and k = 2048. At k = 64 the second pass costs about
+//! 15% over the multiply, the fused epilogue about half that.
+//!
+//! The `transposed_output` group writes a 4096×4096 Cᵀ, as a `MatMul` with
+//! `OutputLayout::Transposed` and as a row-major multiply followed by
+//! `transpose`, at k = 64 and k = 4096. The pass over C is about half the
+//! time at k = 64 and 5% at k = 4096.
+
+use criterion::measurement::WallTime;
+use criterion::{
+    BenchmarkGroup, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main,
+};
+use matmul::batch::{interleave, interleaved_len};
+use matmul::blocked::gemm_scalar::matmul_blocked_scalar;
+use matmul::blocked::pack::{pack_a_panel, pack_b_panel};
+use matmul::matrix::generate::random;
+use matmul::matrix::transpose::{transpose, transpose_naive, transpose_scalar};
+use matmul::{
+    Accumulation, Activation, DenormalMode, Diag, MatMul, MatmulPlan, OutputLayout, PackedA, Side,
+    Uplo, detected_backend, matmul_naive_ikj, multiply, multiply_batch_tiny, multiply_fixed,
+    multiply_parallel, multiply_prepacked_a, multiply_with_backend, trmm, with_denormal_mode,
+};
+use std::hint::black_box;
+
+const SIZES: [usize; 6] = [64, 128, 256, 512, 1024, 2048];
+
+/// Benchmarks `f` on square matrices of every size up to `max_size`.
+fn bench_kernel<F>(c: &mut Criterion, name: &str, max_size: usize, f: F)
+where
+    F: Fn(&[f64], &[f64], &mut [f64], usize, usize, usize),
+{
+    let mut group = c.benchmark_group(name);
+    for size in SIZES.into_iter().filter(|&size| size <= max_size) {
+        let (m, n, k) = (size, size, size);
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        // C accumulates across iterations; the values don't affect timing
+        let mut c = vec![0.0; m * n];
+
+        group.throughput(Throughput::Elements(2 * (m * n * k) as u64));
+        // A 2048³ multiply takes ~0.5 s single-threaded; 100 samples is too many
+        group.sample_size(if size >= 1024 { 10 } else { 50 });
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |bench, _| {
+            bench.iter(|| f(black_box(&a), black_box(&b), black_box(&mut c), m, n, k));
+        });
+    }
+    group.finish();
+}
+
+fn portable(c: &mut Criterion) {
+    // The naive loops take seconds past 512; they're here as the baseline
+    bench_kernel(c, "naive_ikj", 512, matmul_naive_ikj);
+    bench_kernel(c, "scalar_4x4", 1024, |a, b, c, m, n, k| {
+        matmul_blocked_scalar(a, b, c, m, n, k, None, None)
+    });
+    bench_kernel(c, "multiply", 2048, multiply);
+    bench_kernel(c, "multiply_parallel", 2048, |a, b, c, m, n, k| {
+        multiply_parallel(a, b, c, m, n, k, 4)
+    });
+}
+
+#[cfg(target_arch = "x86_64")]
+fn simd(c: &mut Criterion) {
+    use matmul::blocked::{gemm_4x4, gemm_8x8, gemm_12x4};
+    use matmul::features::{has_avx2, has_avx512};
+
+    if has_avx2() {
+        bench_kernel(c, "avx2_4x4", 2048, |a, b, c, m, n, k| {
+            gemm_4x4::run(a, b, c, m, n, k).unwrap()
+        });
+        bench_kernel(c, "avx2_12x4", 2048, |a, b, c, m, n, k| {
+            gemm_12x4::run(a, b, c, m, n, k).unwrap()
+        });
+    }
+    if has_avx512() {
+        bench_kernel(c, "avx512_8x8", 2048, |a, b, c, m, n, k| {
+            gemm_8x8::run(a, b, c, m, n, k).unwrap()
+        });
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn simd(_c: &mut Criterion) {}
+
+/// Sizes for the transpose and pack groups; 1024 is a power of two, the worst
+/// case for cache-set conflicts on the strided side
+const PRIMITIVE_SIZES: [usize; 3] = [256, 1000, 1024];
+
+fn primitives(c: &mut Criterion) {
+    type Transpose = fn(&[f64], &mut [f64], usize, usize);
+    #[allow(unused_mut)]
+    let mut transposes: Vec<(&str, Transpose)> = vec![
+        ("naive", transpose_naive),
+        ("blocked", transpose_scalar),
+        ("dispatch", transpose),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    {
+        use matmul::matrix::transpose::{transpose_avx, transpose_avx512};
+        if matmul::features::has_avx2() {
+            transposes.push(("avx", |s, d, r, c| unsafe { transpose_avx(s, d, r, c) }));
+        }
+        if matmul::features::has_avx512() {
+            transposes.push(("avx512", |s, d, r, c| unsafe {
+                transpose_avx512(s, d, r, c)
+            }));
+        }
+    }
+
+    let mut group = c.benchmark_group("transpose");
+    for size in PRIMITIVE_SIZES {
+        let src = random(size, size, 1);
+        let mut dst = vec![0.0; size * size];
+        group.throughput(Throughput::Bytes(2 * 8 * (size * size) as u64));
+        for &(name, f) in &transposes {
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |bench, _| {
+                bench.iter(|| f(black_box(&src), black_box(&mut dst), size, size));
+            });
+        }
+    }
+    group.finish();
+
+    // One kc-deep, mc-tall block, the unit the drivers pack at a time
+    let (kc, mc) = (256, 120);
+    let mut group = c.benchmark_group("pack");
+    group.throughput(Throughput::Bytes(2 * 8 * (mc * kc) as u64));
+    let a = random(mc, kc, 1);
+    let mut panel = vec![0.0; mc * kc];
+    group.bench_function("a_4row", |bench| {
+        bench.iter(|| pack_a_panel::<4>(black_box(&a), &mut panel, 0, 0, mc, kc, kc))
+    });
+    group.bench_function("a_8row", |bench| {
+        bench.iter(|| pack_a_panel::<8>(black_box(&a), &mut panel, 0, 0, mc, kc, kc))
+    });
+    group.bench_function("a_12row", |bench| {
+        bench.iter(|| pack_a_panel::<12>(black_box(&a), &mut panel, 0, 0, mc, kc, kc))
+    });
+    // The same 120×256 block read as Bᵀ (n×k): every column group, once
+    group.bench_function("b_4col", |bench| {
+        bench.iter(|| {
+            for j in (0..mc).step_by(4) {
+                pack_b_panel::<4>(black_box(&a), &mut panel[..4 * kc], j, 0, kc, kc);
+            }
+        })
+    });
+    group.bench_function("b_8col", |bench| {
+        bench.iter(|| {
+            for j in (0..mc).step_by(8) {
+                pack_b_panel::<8>(black_box(&a), &mut panel[..8 * kc], j, 0, kc, kc);
+            }
+        })
+    });
+    group.finish();
+}
+
+/// The same multiply on normal inputs, on denormal inputs, and on denormal
+/// inputs under `DenormalMode::FlushToZero`: the gap between the first two is
+/// the microcode-assist penalty, the third shows it gone.
+fn denormals(c: &mut Criterion) {
+    let size = 256;
+    let normal = random(size, size, 1);
+    let denormal: Vec<f64> = normal.iter().map(|x| x * 1e-310).collect();
+    let b = random(size, size, 2);
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("denormals");
+    group.throughput(Throughput::Elements(2 * (size * size * size) as u64));
+    for (name, a, mode) in [
+        ("normal", &normal, DenormalMode::Preserve),
+        ("preserve", &denormal, DenormalMode::Preserve),
+        ("flush_to_zero", &denormal, DenormalMode::FlushToZero),
+    ] {
+        group.bench_function(name, |bench| {
+            with_denormal_mode(mode, || {
+                bench.iter(|| multiply(black_box(a), black_box(&b), &mut out, size, size, size))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Repeated multiplies by one A, where most of A's traffic is the packing:
+/// with n = 64 each packed panel serves only 16 or fewer kernel columns.
+fn prepacked(c: &mut Criterion) {
+    let (m, n, k) = (2048, 64, 2048);
+    let backend = detected_backend();
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let mut out = vec![0.0; m * n];
+    let packed = PackedA::pack(&a, m, k, backend).unwrap();
+
+    let mut group = c.benchmark_group("prepacked");
+    group.throughput(Throughput::Elements(2 * (m * n * k) as u64));
+    group.bench_function("pack_every_call", |bench| {
+        bench.iter(|| {
+            multiply_with_backend(backend, black_box(&a), black_box(&b), &mut out, m, n, k)
+        })
+    });
+    group.bench_function("packed_once", |bench| {
+        bench.iter(|| {
+            multiply_prepacked_a(black_box(&packed), black_box(&b), &mut out, m, n, k, None)
+        })
+    });
+    group.finish();
+}
+
+/// Per-call overhead: the same shape over and over, planned once or not.
+fn fixed_shape(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fixed_shape");
+    for size in [8, 32, 128] {
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
+        let mut out = vec![0.0; size * size];
+        let mut plan = MatmulPlan::new(size, size, size, &MatMul::new()).unwrap();
+
+        group.throughput(Throughput::Elements(2 * (size * size * size) as u64));
+        group.bench_with_input(BenchmarkId::new("multiply", size), &size, |bench, &s| {
+            bench.iter(|| multiply(black_box(&a), black_box(&b), &mut out, s, s, s))
+        });
+        group.bench_with_input(BenchmarkId::new("plan", size), &size, |bench, _| {
+            bench.iter(|| {
+                plan.execute(black_box(&a), black_box(&b), &mut out)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// C += A·B for B upper-triangular, dense and skipping the zeros. Both count
+/// the dense FLOPs, so `trmm`'s Gelem/s is its speedup times `multiply`'s.
+fn triangular(c: &mut Criterion) {
+    let size = 1024;
+    let a = random(size, size, 1);
+    let mut b = random(size, size, 2);
+    for i in 0..size {
+        b[i * size..i * size + i].fill(0.0);
+    }
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("triangular");
+    group.throughput(Throughput::Elements(2 * (size * size * size) as u64));
+    group.bench_function("multiply", |bench| {
+        bench.iter(|| multiply(black_box(&a), black_box(&b), &mut out, size, size, size))
+    });
+    group.bench_function("trmm", |bench| {
+        bench.iter(|| {
+            trmm(
+                black_box(&a),
+                black_box(&b),
+                &mut out,
+                size,
+                size,
+                Side::Right,
+                Uplo::Upper,
+                Diag::NonUnit,
+            )
+        })
+    });
+    group.finish();
+}
+
+/// k ≤ 4, where `multiply` skips the blocking: against the 12×4 driver,
+/// which packs and tiles as it does for any k.
+fn low_rank(c: &mut Criterion) {
+    let size = 2048;
+    let mut group = c.benchmark_group("low_rank");
+    for k in [1, 2, 4] {
+        let a = random(size, k, 1);
+        let b = random(k, size, 2);
+        let mut out = vec![0.0; size * size];
+
+        group.throughput(Throughput::Elements(2 * (size * size * k) as u64));
+        group.bench_with_input(BenchmarkId::new("multiply", k), &k, |bench, &k| {
+            bench.iter(|| multiply(black_box(&a), black_box(&b), &mut out, size, size, k))
+        });
+        #[cfg(target_arch = "x86_64")]
+        if matmul::features::has_avx2() {
+            use matmul::blocked::gemm_12x4;
+            group.bench_with_input(BenchmarkId::new("blocked_12x4", k), &k, |bench, &k| {
+                bench.iter(|| {
+                    gemm_12x4::run(black_box(&a), black_box(&b), &mut out, size, size, k).unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Small compile-time shapes: `multiply_fixed` against `multiply` on the
+/// same values.
+fn fixed_size(c: &mut Criterion) {
+    fn sizes<const S: usize>(group: &mut BenchmarkGroup<'_, WallTime>) {
+        let (a_flat, b_flat) = (random(S, S, 1), random(S, S, 2));
+        let a: [[f64; S]; S] = std::array::from_fn(|i| std::array::from_fn(|p| a_flat[i * S + p]));
+        let b: [[f64; S]; S] = std::array::from_fn(|p| std::array::from_fn(|j| b_flat[p * S + j]));
+        let mut out = [[0.0; S]; S];
+        let mut out_flat = vec![0.0; S * S];
+
+        group.throughput(Throughput::Elements(2 * (S * S * S) as u64));
+        group.bench_with_input(BenchmarkId::new("multiply", S), &S, |bench, &s| {
+            bench.iter(|| {
+                multiply(
+                    black_box(&a_flat),
+                    black_box(&b_flat),
+                    &mut out_flat,
+                    s,
+                    s,
+                    s,
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("multiply_fixed", S), &S, |bench, _| {
+            bench.iter(|| multiply_fixed(black_box(&a), black_box(&b), &mut out))
+        });
+    }
+
+    let mut group = c.benchmark_group("fixed_size");
+    sizes::<3>(&mut group);
+    sizes::<4>(&mut group);
+    sizes::<6>(&mut group);
+    sizes::<8>(&mut group);
+    group.finish();
+}
+
+/// A batch of tiny matrices: `multiply` on each in turn, and
+/// `multiply_batch_tiny` on the interleaved batch.
+fn batch_tiny(c: &mut Criterion) {
+    let batch = 4096;
+    let mut group = c.benchmark_group("batch_tiny");
+    for dim in [4, 8] {
+        let size = dim * dim;
+        let (a, b) = (random(batch, size, 1), random(batch, size, 2));
+        let mut out = vec![0.0; batch * size];
+        let mut out_soa = vec![0.0; interleaved_len(dim, batch)];
+        let (a_soa, b_soa) = (interleave(&a, dim, batch), interleave(&b, dim, batch));
+
+        group.throughput(Throughput::Elements(2 * (batch * size * dim) as u64));
+        group.bench_with_input(BenchmarkId::new("looped", dim), &dim, |bench, &d| {
+            bench.iter(|| {
+                for ((a, b), c) in a
+                    .chunks_exact(size)
+                    .zip(b.chunks_exact(size))
+                    .zip(out.chunks_exact_mut(size))
+                {
+                    multiply(black_box(a), black_box(b), c, d, d, d);
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("interleaved", dim), &dim, |bench, &d| {
+            bench.iter(|| {
+                multiply_batch_tiny(black_box(&a_soa), black_box(&b_soa), &mut out_soa, d, batch)
+            })
+        });
+    }
+    group.finish();
+}
+
+/// A million 8×8 multiplies in a row: `multiply`, and the i-k-j loop
+/// inlined at the call site, the floor it's tracked against.
+fn tiny_stream(c: &mut Criterion) {
+    const DIM: usize = 8;
+    const CALLS: usize = 1_000_000;
+    let (a, b) = (random(DIM, DIM, 1), random(DIM, DIM, 2));
+    let mut out = vec![0.0; DIM * DIM];
+
+    let mut group = c.benchmark_group("tiny_stream");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((2 * DIM * DIM * DIM * CALLS) as u64));
+    group.bench_function("multiply", |bench| {
+        bench.iter(|| {
+            for _ in 0..CALLS {
+                multiply(black_box(&a), black_box(&b), &mut out, DIM, DIM, DIM);
+            }
+        })
+    });
+    group.bench_function("inlined", |bench| {
+        bench.iter(|| {
+            for _ in 0..CALLS {
+                let (a, b) = (black_box(&a), black_box(&b));
+                for (a_row, c_row) in a.chunks_exact(DIM).zip(out.chunks_exact_mut(DIM)) {
+                    for (&a_ip, b_row) in a_row.iter().zip(b.chunks_exact(DIM)) {
+                        for (x, &b_pj) in c_row.iter_mut().zip(b_row) {
+                            *x += a_ip * b_pj;
+                        }
+                    }
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+/// The same deep product summed left to right and pairwise across k blocks.
+fn accumulation(c: &mut Criterion) {
+    let (size, k) = (512, 8192);
+    let a = random(size, k, 1);
+    let b = random(k, size, 2);
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("accumulation");
+    group.throughput(Throughput::Elements(2 * (size * size * k) as u64));
+    for (name, accumulation) in [
+        ("sequential", Accumulation::Sequential),
+        ("pairwise", Accumulation::Pairwise),
+    ] {
+        let mut matmul = MatMul::new().accumulation(accumulation).build().unwrap();
+        group.bench_function(name, |bench| {
+            bench.iter(|| {
+                matmul
+                    .compute_into(black_box(&a), black_box(&b), &mut out, size, size, k)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+/// A memory-bound multiply asking for more and more threads.
+fn thin_k_scaling(c: &mut Criterion) {
+    let (size, k) = (4096, 32);
+    let a = random(size, k, 1);
+    let b = random(k, size, 2);
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("thin_k_scaling");
+    group.throughput(Throughput::Elements(2 * (size * size * k) as u64));
+    group.sample_size(10);
+    for threads in [1, 2, 4, 8, 16, 32] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(threads),
+            &threads,
+            |bench, &threads| {
+                bench.iter(|| {
+                    multiply_parallel(
+                        black_box(&a),
+                        black_box(&b),
+                        &mut out,
+                        size,
+                        size,
+                        k,
+                        threads,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// A layer's bias and ReLU, fused or as a second sweep over C.
+fn epilogue(c: &mut Criterion) {
+    let size = 2048;
+    let bias = random(1, size, 3);
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("epilogue");
+    group.sample_size(10);
+    for k in [64, 2048] {
+        let a = random(size, k, 1);
+        let b = random(k, size, 2);
+        group.throughput(Throughput::Elements(2 * (size * size * k) as u64));
+
+        let mut plain = MatMul::new().beta(0.0).build().unwrap();
+        group.bench_with_input(BenchmarkId::new("two_pass", k), &k, |bench, &k| {
+            bench.iter(|| {
+                plain
+                    .compute_into(black_box(&a), black_box(&b), &mut out, size, size, k)
+                    .unwrap();
+                for row in out.chunks_exact_mut(size) {
+                    for (x, &bias) in row.iter_mut().zip(&bias) {
+                        *x = Activation::Relu.apply(*x + bias);
+                    }
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("multiply_only", k), &k, |bench, &k| {
+            bench.iter(|| {
+                plain
+                    .compute_into(black_box(&a), black_box(&b), &mut out, size, size, k)
+                    .unwrap()
+            })
+        });
+        let mut fused = MatMul::new()
+            .beta(0.0)
+            .bias(bias.clone())
+            .activation(Activation::Relu)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("fused", k), &k, |bench, &k| {
+            bench.iter(|| {
+                fused
+                    .compute_into(black_box(&a), black_box(&b), &mut out, size, size, k)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn transposed_output(c: &mut Criterion) {
+    let size = 4096;
+    let mut row_major = vec![0.0; size * size];
+    let mut out = vec![0.0; size * size];
+
+    let mut group = c.benchmark_group("transposed_output");
+    group.sample_size(10);
+    for k in [64, 4096] {
+        let a = random(size, k, 1);
+        let b = random(k, size, 2);
+        group.throughput(Throughput::Elements(2 * (size * size * k) as u64));
+
+        let mut plain = MatMul::new().beta(0.0).build().unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("multiply_then_transpose", k),
+            &k,
+            |bench, &k| {
+                bench.iter(|| {
+                    plain
+                        .compute_into(black_box(&a), black_box(&b), &mut row_major, size, size, k)
+                        .unwrap();
+                    transpose(&row_major, &mut out, size, size);
+                })
+            },
+        );
+        let mut fused = MatMul::new()
+            .beta(0.0)
+            .output(OutputLayout::Transposed)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("transposed", k), &k, |bench, &k| {
+            bench.iter(|| {
+                fused
+                    .compute_into(black_box(&a), black_box(&b), &mut out, size, size, k)
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    portable,
+    simd,
+    primitives,
+    denormals,
+    prepacked,
+    fixed_shape,
+    triangular,
+    low_rank,
+    fixed_size,
+    batch_tiny,
+    tiny_stream,
+    accumulation,
+    thin_k_scaling,
+    epilogue,
+    transposed_output
+);
+criterion_main!(benches);
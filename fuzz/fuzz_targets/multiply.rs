@@ -0,0 +1,200 @@
+//! Decodes bytes into a small multiply (shape, operands, backend, threads, and
+//! row bands) and checks the result against the naive i-k-j loop.
+//!
+//! ```text
+//! cargo +nightly fuzz run multiply
+//! ```
+//!
+//! Input layout (missing bytes read as 0):
+//!
+//! | bytes   | meaning                                              |
+//! |---------|------------------------------------------------------|
+//! | 0, 1    | m, n (mod 50)                                        |
+//! | 2, 3    | k, little-endian (mod 300, so k crosses a KC block)  |
+//! | 4       | target, an index into [`targets`]                    |
+//! | 5       | thread count - 1 (mod 8)                             |
+//! | 6       | how many band split points follow (mod 8)            |
+//! | 7..     | split points (mod m + 1), then one byte per element  |
+//! |         | of A, B, and C, cycled if short                      |
+//!
+//! Element bytes map to NaN, ±Inf, ±0, denormals, or a multiple of 1/4 below
+//! 32 in magnitude, see [`element`]. No sum can overflow, so NaN and Inf come out
+//! the same in any summation order and the check can compare them exactly.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use matmul::matrix::naive_ikj::matmul_naive_ikj;
+use matmul::matrix::reference::error_bound;
+use matmul::threaded::BlockedGemm;
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::{Backend, MatMulError, multiply_parallel, multiply_with_backend, try_multiply};
+
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::{
+    gemm_4x4::matmul_blocked_4x4, gemm_8x8::matmul_blocked_8x8, gemm_12x4::matmul_blocked_12x4,
+};
+#[cfg(target_arch = "x86_64")]
+use matmul::features::{has_avx2, has_avx512};
+
+/// A forced backend, a row-band driver, or a whole multiply with a thread
+/// count
+enum Target {
+    Backend(Backend),
+    Bands(BlockedGemm),
+    Whole(Threaded),
+}
+
+/// C += A·B on m, n, k, with a thread count
+type Threaded = fn(&[f64], &[f64], &mut [f64], usize, usize, usize, usize);
+
+/// Every entry point this CPU can run.
+fn targets() -> Vec<Target> {
+    let mut targets = vec![
+        Target::Whole(multiply_parallel),
+        Target::Whole(matmul_naive_ikj_mt),
+        Target::Whole(|a, b, c, m, n, k, _| {
+            try_multiply(a, b, c, m, n, k).unwrap();
+        }),
+        Target::Bands(matmul::blocked::gemm_scalar::matmul_blocked_scalar),
+    ];
+    for backend in [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ] {
+        if backend.is_available() {
+            targets.push(Target::Backend(backend));
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            targets.push(Target::Bands(matmul_blocked_4x4));
+            targets.push(Target::Bands(|a, b, c, m, n, k, start, end| unsafe {
+                matmul_blocked_12x4(a, b, c, m, n, k, start, end, None, None)
+            }));
+        }
+        if has_avx512() {
+            targets.push(Target::Bands(|a, b, c, m, n, k, start, end| unsafe {
+                matmul_blocked_8x8(a, b, c, m, n, k, start, end, None, None)
+            }));
+        }
+    }
+    targets
+}
+
+/// NaN, ±Inf, ±0, the smallest and largest denormals, or (most of the time)
+/// a multiple of 1/4 below 32 in magnitude, which sums exactly.
+fn element(byte: u8) -> f64 {
+    match byte {
+        0 => f64::NAN,
+        1 => f64::INFINITY,
+        2 => f64::NEG_INFINITY,
+        3 => -0.0,
+        4 => f64::from_bits(1),
+        5 => -f64::from_bits(1),
+        6 => f64::MIN_POSITIVE - f64::from_bits(1),
+        _ => (byte as i32 - 128) as f64 / 4.0,
+    }
+}
+
+/// Reads bytes in order, yielding 0 past the end.
+struct Bytes<'a>(std::slice::Iter<'a, u8>);
+
+impl Bytes<'_> {
+    fn next(&mut self) -> u8 {
+        self.0.next().copied().unwrap_or(0)
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut bytes = Bytes(data.iter());
+    let m = bytes.next() as usize % 50;
+    let n = bytes.next() as usize % 50;
+    let k = u16::from_le_bytes([bytes.next(), bytes.next()]) as usize % 300;
+    let target = bytes.next() as usize;
+    let threads = bytes.next() as usize % 8 + 1;
+    let mut splits: Vec<usize> = (0..bytes.next() % 8)
+        .map(|_| bytes.next() as usize % (m + 1))
+        .chain([0, m])
+        .collect();
+    splits.sort_unstable();
+    splits.dedup();
+
+    let elements: Vec<f64> = bytes.0.as_slice().iter().map(|&b| element(b)).collect();
+    let fill = |len: usize, offset: usize| -> Vec<f64> {
+        if elements.is_empty() {
+            return vec![1.0; len];
+        }
+        (0..len)
+            .map(|i| elements[(offset + i) % elements.len()])
+            .collect()
+    };
+    let a = fill(m * k, 0);
+    let b = fill(k * n, m * k);
+    let c_initial = fill(m * n, m * k + k * n);
+
+    // A slice one element short is an error, not a panic, and leaves C alone
+    if m * k > 0 {
+        let mut c = c_initial.clone();
+        assert!(matches!(
+            try_multiply(&a[1..], &b, &mut c, m, n, k),
+            Err(MatMulError::WrongLength { operand: "A", .. })
+        ));
+        assert!(
+            c.iter()
+                .zip(&c_initial)
+                .all(|(x, y)| x.to_bits() == y.to_bits())
+        );
+    }
+
+    let mut expected = c_initial.clone();
+    matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+
+    let targets = targets();
+    let mut c = c_initial.clone();
+    match targets[target % targets.len()] {
+        Target::Backend(backend) => {
+            multiply_with_backend(backend, &a, &b, &mut c, m, n, k);
+        }
+        Target::Whole(f) => f(&a, &b, &mut c, m, n, k, threads),
+        Target::Bands(gemm) => {
+            for band in splits.windows(2) {
+                let (start, end) = (band[0], band[1]);
+                let c_band = &mut c[start * n..end * n];
+                unsafe { gemm(&a, &b, c_band, m, n, k, Some(start), Some(end)) };
+            }
+        }
+    }
+
+    // |A|·|B| + |C| per element, for the rounding bound
+    let abs = |x: &[f64]| x.iter().map(|v| v.abs()).collect::<Vec<f64>>();
+    let mut magnitude = abs(&c_initial);
+    matmul_naive_ikj(&abs(&a), &abs(&b), &mut magnitude, m, n, k);
+
+    for (i, ((&want, &got), &scale)) in expected.iter().zip(&c).zip(&magnitude).enumerate() {
+        let same = if want.is_nan() {
+            got.is_nan()
+        } else if want.is_infinite() {
+            got == want
+        } else {
+            // Denormal products can each lose up to one denormal step
+            let tolerance = 2.0 * error_bound(k + 1) * scale + (k + 1) as f64 * f64::from_bits(1);
+            got.is_finite() && (got - want).abs() <= tolerance
+        };
+        assert!(
+            same,
+            "{}x{}x{}, target {}, element {}: expected {:e}, got {:e}",
+            m,
+            n,
+            k,
+            target % targets.len(),
+            i,
+            want,
+            got
+        );
+    }
+});
@@ -0,0 +1,73 @@
+#ifndef MATMUL_H
+#define MATMUL_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+/**
+ * Row-major storage (CBLAS `CblasRowMajor`)
+ */
+#define CBLAS_ROW_MAJOR 101
+
+/**
+ * Column-major storage (CBLAS `CblasColMajor`)
+ */
+#define CBLAS_COL_MAJOR 102
+
+/**
+ * Use the matrix as stored (CBLAS `CblasNoTrans`)
+ */
+#define CBLAS_NO_TRANS 111
+
+/**
+ * Use the transpose (CBLAS `CblasTrans`)
+ */
+#define CBLAS_TRANS 112
+
+/**
+ * Conjugate transpose; same as `CBLAS_TRANS` for real matrices (CBLAS `CblasConjTrans`)
+ */
+#define CBLAS_CONJ_TRANS 113
+
+/**
+ * The call succeeded
+ */
+#define MATMUL_SUCCESS 0
+
+/**
+ * The computation panicked; C may be partially updated
+ */
+#define MATMUL_ERR_INTERNAL -1
+
+/**
+ * C = alpha * op(A) * op(B) + beta * C, with the CBLAS `dgemm` signature.
+ *
+ * op(A) is m×k, op(B) is k×n, and C is m×n. `lda`, `ldb`, and `ldc` are the
+ * distances between consecutive rows (row-major) or columns (column-major)
+ * of the matrices as stored. As in BLAS, when `beta` is 0, C is not read
+ * (NaNs in it are overwritten), and when `alpha` is 0, A and B are not read.
+ *
+ * Returns `MATMUL_SUCCESS`, the 1-based position of the first invalid
+ * argument, or `MATMUL_ERR_INTERNAL`; see the module docs.
+ *
+ * # Safety
+ *
+ * Every non-null pointer must be valid for the matrix it describes: `a` for
+ * the stored A with leading dimension `lda` (reads), likewise `b`, and `c`
+ * for C with `ldc` (reads and writes). C must not overlap A or B.
+ */
+int matmul_dgemm(int layout,
+                 int transa,
+                 int transb,
+                 int m,
+                 int n,
+                 int k,
+                 double alpha,
+                 const double *a,
+                 int lda,
+                 const double *b,
+                 int ldb,
+                 double beta,
+                 double *c,
+                 int ldc);
+
+#endif  /* MATMUL_H */
@@ -0,0 +1,229 @@
+//! How each element of C sums its k products.
+//!
+//! The blocked drivers add every kc-deep block's products straight into C,
+//! so each element is one long left-to-right sum, and its rounding error
+//! can grow like k·u (u = 2⁻⁵³). [`Accumulation::Pairwise`] multiplies each
+//! block into a partial product of its own instead, and combines the
+//! partials in a balanced tree, so the error grows like (kc + log₂(k/kc))·u.
+//! That's most of what full compensated summation would buy, for the cost
+//! of copying A's columns out a block at a time and adding up the partials.
+
+use crate::Backend;
+use crate::builder::run_rows;
+use crate::workspace::Workspace;
+
+/// The summation order of a [`MatMul`](crate::MatMul), set with
+/// [`MatMulBuilder::accumulation`](crate::MatMulBuilder::accumulation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Accumulation {
+    /// One running sum over all of k, as [`multiply`](crate::multiply)
+    /// does
+    #[default]
+    Sequential,
+    /// A running sum within each kc-deep block, then a balanced tree across
+    /// blocks. With k up to kc there's one block, and it's the same as
+    /// `Sequential`, bit for bit; so it is for the i-k-j backend, which has
+    /// no blocks unless [`Tuning::kc`](crate::Tuning::kc) sets some.
+    Pairwise,
+}
+
+/// [`run_rows`] with the kc-deep blocks of k combined pairwise: each block
+/// is a multiply of its own into a zeroed partial product, and partials of
+/// equal size are added together as soon as there are two.
+///
+/// A's block columns, the partial, and one partial per tree level live in
+/// the workspace's pairwise buffer, about `rows·(kc + n·(log₂(k/kc) + 2))`
+/// elements for the band.
+///
+/// # Safety
+///
+/// Same as [`run_rows`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn run_rows_pairwise(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+    kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
+) {
+    if k <= kc {
+        // One block: nothing to pair
+        unsafe {
+            run_rows(
+                backend, a, b, c, m, n, k, row_start, row_end, kc, mc, workspace,
+            )
+        };
+        return;
+    }
+    let start = row_start.unwrap_or(0);
+    let rows = row_end.unwrap_or(m) - start;
+    let size = rows * n;
+    let blocks = k.div_ceil(kc);
+    let levels = blocks.ilog2() as usize + 1;
+
+    // Taken out, so the driver can pack into the rest of the workspace; the
+    // epilogue too, which is for C, not the partials
+    let mut pairwise = std::mem::take(&mut workspace.pairwise);
+    let epilogue = workspace.epilogue.take();
+    pairwise.clear();
+    pairwise.resize(rows * kc + (levels + 1) * size, 0.0);
+    let (a_block, rest) = pairwise.split_at_mut(rows * kc);
+    let (partial, tree) = rest.split_at_mut(size);
+
+    // Bit l of `count` is set when tree level l holds the sum of 2^l blocks
+    let mut count = 0usize;
+    for kk in (0..k).step_by(kc) {
+        let depth = (kk + kc).min(k) - kk;
+        let a_block = &mut a_block[..rows * depth];
+        for (i, row) in a_block.chunks_exact_mut(depth).enumerate() {
+            row.copy_from_slice(&a[(start + i) * k + kk..][..depth]);
+        }
+        partial.fill(0.0);
+        unsafe {
+            run_rows(
+                backend,
+                a_block,
+                &b[kk * n..(kk + depth) * n],
+                partial,
+                rows,
+                n,
+                depth,
+                None,
+                None,
+                kc,
+                mc,
+                workspace,
+            )
+        };
+
+        let mut level = 0;
+        while count >> level & 1 == 1 {
+            for (x, &y) in partial.iter_mut().zip(&tree[level * size..][..size]) {
+                *x += y;
+            }
+            level += 1;
+        }
+        tree[level * size..][..size].copy_from_slice(partial);
+        count += 1;
+    }
+
+    // The levels still holding a sum, smallest first, then into C
+    partial.fill(0.0);
+    for level in (0..levels).filter(|level| count >> level & 1 == 1) {
+        for (x, &y) in partial.iter_mut().zip(&tree[level * size..][..size]) {
+            *x += y;
+        }
+    }
+    if workspace.overwrite {
+        // A sum from +0 is never -0, so this is adding it to a zeroed C
+        c.copy_from_slice(partial);
+    } else {
+        for (x, &y) in c.iter_mut().zip(partial.iter()) {
+            *x += y;
+        }
+    }
+    if let Some(epilogue) = &epilogue {
+        epilogue.apply(c, rows, n, 0..n);
+    }
+    workspace.pairwise = pairwise;
+    workspace.epilogue = epilogue;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::MatMul;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::{Tuning, available_backends, matmul_naive_ikj};
+
+    /// A·B with `accumulation` on `backend`, C starting at `c0`.
+    #[allow(clippy::too_many_arguments)]
+    fn compute(
+        backend: Backend,
+        accumulation: Accumulation,
+        kc: usize,
+        (a, b, c0): (&[f64], &[f64], &[f64]),
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Vec<f64> {
+        let mut matmul = MatMul::new()
+            .backend(backend)
+            .accumulation(accumulation)
+            .tuning(Tuning {
+                kc: Some(kc),
+                mc: None,
+            })
+            .build()
+            .unwrap();
+        let mut c = c0.to_vec();
+        matmul.compute_into(a, b, &mut c, m, n, k).unwrap();
+        c
+    }
+
+    #[test]
+    fn test_matches_naive() {
+        // Block counts that are and aren't powers of two, a short last
+        // block, and one a single element deep
+        let (m, n) = (13, 9);
+        let ks: &[usize] = if cfg!(miri) {
+            &[9, 17]
+        } else {
+            &[9, 16, 17, 29, 40, 65]
+        };
+        for &k in ks {
+            let (a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+            let mut want = c0.clone();
+            matmul_naive_ikj(&a, &b, &mut want, m, n, k);
+            for backend in available_backends() {
+                let got = compute(backend, Accumulation::Pairwise, 8, (&a, &b, &c0), m, n, k);
+                assert_close(&want, &got, 1e-12, 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_one_block_is_sequential() {
+        let (m, n, k) = (13, 9, 8);
+        let (a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+        for backend in available_backends() {
+            let run = |accumulation| compute(backend, accumulation, 8, (&a, &b, &c0), m, n, k);
+            assert_eq!(
+                run(Accumulation::Pairwise),
+                run(Accumulation::Sequential),
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_exact_tree() {
+        // Block b's products sum to 4·2^b, and every partial and tree sum of
+        // them is exact too, so C comes out at exactly 4·(2⁷ − 1)
+        let (m, n, kc) = (3, 2, 4);
+        let k = 7 * kc;
+        let a = vec![1.0; m * k];
+        let b: Vec<f64> = (0..k * n).map(|i| 2f64.powi((i / n / kc) as i32)).collect();
+        for backend in available_backends() {
+            let got = compute(
+                backend,
+                Accumulation::Pairwise,
+                kc,
+                (&a, &b, &[0.0; 6]),
+                m,
+                n,
+                k,
+            );
+            assert_eq!(got, [4.0 * 127.0; 6], "{}", backend);
+        }
+    }
+}
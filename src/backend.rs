@@ -0,0 +1,424 @@
+//! Which implementation a multiply runs on, and what it reports afterwards.
+//!
+//! [`multiply`](crate::multiply) picks a [`Backend`] from the CPU features
+//! and the shape; [`multiply_with_backend`](crate::multiply_with_backend)
+//! forces one, and the `*_with_stats` functions return a [`MultiplyStats`]
+//! saying what ran.
+//!
+//! [`GemmBackend`] is the same thing as a trait, for code that runs "some
+//! implementation" without caring which: the threaded driver, the benchmark,
+//! and the consistency tests all take a `&dyn GemmBackend`, and
+//! [`gemm_backends`] lists the built-in ones. A kernel from outside the crate
+//! (or a mock, in tests) implements it to get the same treatment.
+
+use crate::builder::{run_rows, run_rows_transposed};
+use crate::select::{CostModel, PACK_NS};
+use crate::workspace::with_scratch;
+use std::fmt;
+use std::ops::Range;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// A built-in GEMM implementation.
+///
+/// The `Display` names are the ones the benchmark binary prints.
+///
+/// # Rounding
+///
+/// The SIMD backends (`Avx2_4x4`, `Avx2_12x4`, and `Avx512_8x8`) all compute
+/// an element of C as its starting value plus one fused multiply-add per k
+/// step, in ascending k. Tiles, leftover rows and columns, k blocks, and row
+/// bands don't change that, so they agree bit for bit with each other, at
+/// any [`Tuning`](crate::Tuning) and thread count: a result from an AVX-512
+/// machine can be checked against one from an AVX2 machine with `==`. (The
+/// alpha and beta of a [`MatMul`](crate::MatMul), and pairwise
+/// [`Accumulation`](crate::Accumulation), are applied the same way on each.)
+/// The scalar backends round each product separately, so they differ in the
+/// last bits.
+///
+/// The guarantee costs nothing today; it rules out kernels that split k over
+/// several accumulators per element, which some CPUs run faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Portable 4×4 scalar blocked GEMM; [`multiply`](crate::multiply)'s
+    /// fallback without SIMD
+    Scalar,
+    /// Unblocked scalar i-k-j loop;
+    /// [`multiply_parallel`](crate::multiply_parallel)'s fallback without SIMD
+    ScalarIkj,
+    /// 4×4 AVX2 + FMA blocked GEMM
+    Avx2_4x4,
+    /// 12×4 AVX2 + FMA blocked GEMM
+    Avx2_12x4,
+    /// 8×8 AVX-512 blocked GEMM
+    Avx512_8x8,
+}
+
+impl Backend {
+    /// Every backend, available here or not, slowest first.
+    pub const ALL: [Backend; 5] = [
+        Backend::ScalarIkj,
+        Backend::Scalar,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ];
+
+    /// The backend [`multiply`](crate::multiply) uses on this CPU for large
+    /// matrices; the same as [`detected_backend`], without the caching.
+    pub fn detect() -> Backend {
+        if crate::features::has_avx512() {
+            Backend::Avx512_8x8
+        } else if crate::features::has_avx2() {
+            Backend::Avx2_12x4
+        } else {
+            Backend::Scalar
+        }
+    }
+
+    /// Whether this CPU (and target) can run the backend.
+    pub fn is_available(self) -> bool {
+        match self {
+            Backend::Scalar | Backend::ScalarIkj => true,
+            Backend::Avx2_4x4 | Backend::Avx2_12x4 => crate::features::has_avx2(),
+            Backend::Avx512_8x8 => crate::features::has_avx512(),
+        }
+    }
+
+    /// `(kc, mc)`: the depth and row count of the A panels the driver packs
+    /// for a multiply with inner dimension `k`.
+    ///
+    /// The i-k-j loop doesn't block; it walks one row of A over the whole
+    /// depth at a time, reported as `(k, 1)`.
+    pub(crate) fn block_sizes(self, k: usize) -> (usize, usize) {
+        use crate::blocked::gemm_scalar;
+        #[cfg(target_arch = "x86_64")]
+        use crate::blocked::{gemm_4x4, gemm_8x8, gemm_12x4};
+
+        match self {
+            Backend::Scalar => (k.min(gemm_scalar::KC), gemm_scalar::MC),
+            Backend::ScalarIkj => (k, 1),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_4x4 => (k.min(gemm_4x4::KC), gemm_4x4::MC),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_12x4 => (k.min(gemm_12x4::KC), gemm_12x4::MC),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx512_8x8 => (k.min(gemm_8x8::KC), gemm_8x8::MC),
+            #[cfg(not(target_arch = "x86_64"))]
+            Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8 => {
+                unreachable!("{} only exists on x86_64", self)
+            }
+        }
+    }
+
+    /// `(mr, nr)`: the C tile one microkernel call computes. Rows and columns
+    /// outside whole tiles go through the drivers' scalar edge paths.
+    pub(crate) fn tile(self) -> (usize, usize) {
+        match self {
+            Backend::ScalarIkj => (1, 1),
+            Backend::Scalar | Backend::Avx2_4x4 => (4, 4),
+            Backend::Avx2_12x4 => (12, 4),
+            Backend::Avx512_8x8 => (8, 8),
+        }
+    }
+}
+
+/// A GEMM implementation that computes any band of rows of C.
+///
+/// Implemented by [`Backend`] for the built-in kernels.
+pub trait GemmBackend: Sync {
+    /// Name for tables and messages, e.g. `12×4 AVX2`.
+    fn name(&self) -> &str;
+
+    /// Short name for command lines, e.g. `12x4`. Defaults to [`name`].
+    ///
+    /// [`name`]: GemmBackend::name
+    fn key(&self) -> &str {
+        self.name()
+    }
+
+    /// Whether this CPU (and target) can run it.
+    fn supported(&self) -> bool;
+
+    /// Rows per microkernel tile. The threaded driver starts row bands on
+    /// multiples of it, so a band's tiles fall where a single-threaded run's
+    /// would.
+    fn mr(&self) -> usize {
+        1
+    }
+
+    /// What [`select_backend`](crate::select_backend) predicts its speed
+    /// from. `None`, the default, leaves it out of the selection.
+    fn cost_model(&self) -> Option<CostModel> {
+        None
+    }
+
+    /// C += A·B on rows `rows` only: A (m×k) and B (k×n) are whole, and `c`
+    /// holds just those rows of C, row-major.
+    ///
+    /// # Safety
+    ///
+    /// [`supported`](GemmBackend::supported) must be true, `rows` must be
+    /// within `0..m`, and the slices must match m, n, k and the row count.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn run(
+        &self,
+        a: &[f64],
+        b: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        rows: Range<usize>,
+    );
+
+    /// Whether [`run_transposed`](GemmBackend::run_transposed) packs B from
+    /// its transpose for a k×n B, so the threaded driver makes one Bᵀ for
+    /// all the bands to share. `false`, the default, has every band
+    /// [`run`](GemmBackend::run) on B alone.
+    fn uses_transpose(&self, _k: usize, _n: usize) -> bool {
+        false
+    }
+
+    /// [`run`](GemmBackend::run), with `bt`, B transposed (n×k, row-major),
+    /// to pack B from rather than transposing B again. Defaults to `run`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`run`](GemmBackend::run), and `bt` must be n×k.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn run_transposed(
+        &self,
+        a: &[f64],
+        b: &[f64],
+        _bt: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        rows: Range<usize>,
+    ) {
+        unsafe { self.run(a, b, c, m, n, k, rows) }
+    }
+}
+
+impl GemmBackend for Backend {
+    fn name(&self) -> &str {
+        match self {
+            Backend::Scalar => "4×4 Scalar",
+            Backend::ScalarIkj => "Scalar (i-k-j)",
+            Backend::Avx2_4x4 => "4×4 AVX2",
+            Backend::Avx2_12x4 => "12×4 AVX2",
+            Backend::Avx512_8x8 => "8×8 AVX-512",
+        }
+    }
+
+    fn key(&self) -> &str {
+        match self {
+            Backend::Scalar => "4x4scalar",
+            Backend::ScalarIkj => "ikj",
+            Backend::Avx2_4x4 => "4x4",
+            Backend::Avx2_12x4 => "12x4",
+            Backend::Avx512_8x8 => "8x8",
+        }
+    }
+
+    fn supported(&self) -> bool {
+        self.is_available()
+    }
+
+    fn mr(&self) -> usize {
+        self.tile().0
+    }
+
+    /// The rates measured for the [`select`](crate::select) module. The
+    /// i-k-j loop has none: `multiply`'s scalar backend is the blocked 4×4.
+    fn cost_model(&self) -> Option<CostModel> {
+        let (kernel_gflops, edge_gflops, panel_rows) = match self {
+            Backend::ScalarIkj => return None,
+            Backend::Scalar => (9.0, 3.0, 128),
+            Backend::Avx2_4x4 => (17.0, 6.0, 128),
+            Backend::Avx2_12x4 => (27.0, 6.0, 120),
+            Backend::Avx512_8x8 => (32.0, 6.0, 128),
+        };
+        Some(CostModel {
+            kernel_gflops,
+            edge_gflops,
+            pack_ns: PACK_NS,
+            tile: self.tile(),
+            panel_rows,
+        })
+    }
+
+    unsafe fn run(
+        &self,
+        a: &[f64],
+        b: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        rows: Range<usize>,
+    ) {
+        let (kc, mc) = crate::config::block_sizes(*self, k);
+        let (start, end) = (Some(rows.start), Some(rows.end));
+        // Safety: the caller checked `supported` and the shapes
+        with_scratch(|workspace| unsafe {
+            run_rows(*self, a, b, c, m, n, k, start, end, kc, mc, workspace)
+        })
+    }
+
+    /// The blocked drivers' Bᵀ; the i-k-j loop and the low-rank path read B
+    /// as it is.
+    fn uses_transpose(&self, k: usize, _n: usize) -> bool {
+        *self != Backend::ScalarIkj && !crate::matrix::low_rank::uses_low_rank(*self, k)
+    }
+
+    unsafe fn run_transposed(
+        &self,
+        a: &[f64],
+        b: &[f64],
+        bt: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        rows: Range<usize>,
+    ) {
+        let (kc, mc) = crate::config::block_sizes(*self, k);
+        let (start, end) = (Some(rows.start), Some(rows.end));
+        // Safety: the caller's guarantees are run_rows'
+        with_scratch(|workspace| unsafe {
+            run_rows_transposed(
+                *self,
+                a,
+                b,
+                Some(bt),
+                c,
+                m,
+                n,
+                k,
+                start,
+                end,
+                kc,
+                mc,
+                workspace,
+            )
+        })
+    }
+}
+
+/// Every built-in backend as a [`GemmBackend`], supported here or not,
+/// slowest first (the order of [`Backend::ALL`]).
+///
+/// ```
+/// for backend in matmul::gemm_backends() {
+///     if backend.supported() {
+///         println!("{} ({})", backend.name(), backend.key());
+///     }
+/// }
+/// ```
+pub fn gemm_backends() -> &'static [&'static dyn GemmBackend] {
+    static REGISTRY: [&dyn GemmBackend; 5] = [
+        &Backend::ScalarIkj,
+        &Backend::Scalar,
+        &Backend::Avx2_4x4,
+        &Backend::Avx2_12x4,
+        &Backend::Avx512_8x8,
+    ];
+    &REGISTRY
+}
+
+/// The backend [`multiply`](crate::multiply) runs on this CPU, detected on
+/// the first call and cached, for all but the shapes
+/// [`backend_for`](crate::backend_for) sends elsewhere: small multiplies
+/// kept off AVX-512, and those the [cost model](crate::select) expects a
+/// narrower tile to finish sooner.
+/// [`MultiplyStats::backend`] reports the one that ran.
+///
+/// ```
+/// log_line(&format!("matmul backend: {}", matmul::detected_backend()));
+/// # fn log_line(_: &str) {}
+/// ```
+pub fn detected_backend() -> Backend {
+    static DETECTED: OnceLock<Backend> = OnceLock::new();
+    *DETECTED.get_or_init(Backend::detect)
+}
+
+/// The backends this CPU can run, slowest first (so the last is the one
+/// [`detected_backend`] picks). The two scalar ones are always there.
+pub fn available_backends() -> Vec<Backend> {
+    Backend::ALL
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .collect()
+}
+
+/// The debug event behind the `tracing` feature: one per multiply, with what
+/// a "why is it slow here" report needs first.
+#[cfg(feature = "tracing")]
+pub(crate) fn trace_dispatch(backend: Backend, threads: usize, m: usize, n: usize, k: usize) {
+    let (kc, mc) = crate::config::block_sizes(backend, k);
+    let (mr, nr) = backend.tile();
+    // Share of C computed by the edge paths instead of the microkernel
+    // (per whole matrix; row bands can add a few more edge rows)
+    let edge_fraction = if m * n == 0 {
+        0.0
+    } else {
+        1.0 - ((m - m % mr) * (n - n % nr)) as f64 / (m * n) as f64
+    };
+    tracing::debug!(
+        backend = %backend,
+        threads,
+        m,
+        n,
+        k,
+        kc,
+        mc,
+        edge_fraction,
+        "multiply dispatch"
+    );
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(GemmBackend::name(self))
+    }
+}
+
+/// What one multiply call did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MultiplyStats {
+    pub backend: Backend,
+    /// Threads that computed C, after the adaptive scale-down for small
+    /// matrices; 1 for the single-threaded calls and zero-sized shapes
+    pub threads_used: usize,
+    /// Depth of each packed panel (k block); k for the i-k-j loop, which
+    /// walks a row of A over the whole depth
+    pub kc: usize,
+    /// Rows of A per packed panel; 1 for the i-k-j loop, a row at a time
+    pub mc: usize,
+    /// Wall time of the call, argument checks included
+    pub elapsed: Duration,
+    /// `2mnk / elapsed`, in billions of floating-point operations per second
+    pub gflops: f64,
+}
+
+impl MultiplyStats {
+    pub(crate) fn new(
+        backend: Backend,
+        threads_used: usize,
+        (m, n, k): (usize, usize, usize),
+        elapsed: Duration,
+    ) -> Self {
+        let (kc, mc) = crate::config::block_sizes(backend, k);
+        MultiplyStats {
+            backend,
+            threads_used,
+            kc,
+            mc,
+            elapsed,
+            gflops: 2.0 * (m * n * k) as f64 / elapsed.as_secs_f64() / 1e9,
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Many tiny multiplies at once, one per SIMD lane.
+//!
+//! A 4×4 product is too small to fill a vector unit on its own, and looping
+//! [`multiply`](crate::multiply) over thousands of them is mostly per-call
+//! overhead. Interleaved instead, [`BATCH_LANES`] matrices at a time (element
+//! (i, j) of each of them side by side, then the next element), the batch
+//! becomes dim³ vertical multiply-adds per group, each a full vector: lane l
+//! of every vector belongs to matrix l. Each group is contiguous, so the
+//! kernel streams through memory. [`interleave`] and [`deinterleave`]
+//! convert from and to the usual one-matrix-after-another layout.
+
+/// Matrices per interleaved group: one AVX-512 vector, or two AVX2 ones.
+pub const BATCH_LANES: usize = 8;
+
+/// Length of `batch` interleaved dim×dim matrices: the last group is padded
+/// with zero matrices to a full [`BATCH_LANES`].
+///
+/// # Panics
+///
+/// Panics if the length overflows `usize`.
+pub fn interleaved_len(dim: usize, batch: usize) -> usize {
+    dim.checked_mul(dim)
+        .and_then(|size| size.checked_mul(batch.div_ceil(BATCH_LANES) * BATCH_LANES))
+        .expect("dim² × batch overflows usize")
+}
+
+/// Where element `e` (`i * dim + j`) of matrix `l` goes when interleaved.
+#[inline(always)]
+fn slot(size: usize, l: usize, e: usize) -> usize {
+    (l / BATCH_LANES * size + e) * BATCH_LANES + l % BATCH_LANES
+}
+
+/// The batch in interleaved order, from `batch` row-major dim×dim matrices
+/// stored one after another.
+///
+/// Matrices are taken [`BATCH_LANES`] at a time; within each group, element
+/// (i, j) of every matrix comes before element (i, j + 1) of any. The result
+/// is [`interleaved_len`] long, with zeros padding the last group.
+///
+/// # Panics
+///
+/// Panics if `matrices` doesn't hold `batch` dim×dim matrices.
+pub fn interleave(matrices: &[f64], dim: usize, batch: usize) -> Vec<f64> {
+    let size = dim * dim;
+    assert_eq!(matrices.len(), size * batch, "interleave: length mismatch");
+    let mut out = vec![0.0; interleaved_len(dim, batch)];
+    for (l, matrix) in matrices.chunks_exact(size.max(1)).enumerate() {
+        for (e, &x) in matrix.iter().enumerate() {
+            out[slot(size, l, e)] = x;
+        }
+    }
+    out
+}
+
+/// The inverse of [`interleave`]: `batch` row-major dim×dim matrices, one
+/// after another, without the padding.
+///
+/// # Panics
+///
+/// Panics if `interleaved` isn't [`interleaved_len`] long.
+pub fn deinterleave(interleaved: &[f64], dim: usize, batch: usize) -> Vec<f64> {
+    let size = dim * dim;
+    assert_eq!(
+        interleaved.len(),
+        interleaved_len(dim, batch),
+        "deinterleave: length mismatch"
+    );
+    let mut out = vec![0.0; size * batch];
+    for (l, matrix) in out.chunks_exact_mut(size.max(1)).enumerate() {
+        for (e, x) in matrix.iter_mut().enumerate() {
+            *x = interleaved[slot(size, l, e)];
+        }
+    }
+    out
+}
+
+/// C += A·B for each of `batch` dim×dim matrices, all [`interleave`]d.
+///
+/// Meant for dims up to about 8, where a general multiply per matrix is
+/// mostly overhead; each matrix's products are summed in the i-k-j loop's
+/// order. The AVX2 and AVX-512 versions fuse each multiply-add, so results
+/// can differ from the scalar fallback in the last bit. Padding lanes are
+/// multiplied too, and stay zero.
+///
+/// ```
+/// use matmul::batch::{deinterleave, interleave, interleaved_len};
+/// use matmul::multiply_batch_tiny;
+///
+/// // Two 2×2 matrices: the identity, and twice it
+/// let a = interleave(&[1.0, 0.0, 0.0, 1.0, 2.0, 0.0, 0.0, 2.0], 2, 2);
+/// let b = interleave(&[1.0, 2.0, 3.0, 4.0, 1.0, 2.0, 3.0, 4.0], 2, 2);
+/// let mut c = vec![0.0; interleaved_len(2, 2)];
+/// multiply_batch_tiny(&a, &b, &mut c, 2, 2);
+/// assert_eq!(deinterleave(&c, 2, 2), [1.0, 2.0, 3.0, 4.0, 2.0, 4.0, 6.0, 8.0]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if a slice isn't [`interleaved_len`]`(dim, batch)` long.
+pub fn multiply_batch_tiny(a: &[f64], b: &[f64], c: &mut [f64], dim: usize, batch: usize) {
+    let len = interleaved_len(dim, batch);
+    for (operand, slice_len) in [("A", a.len()), ("B", b.len()), ("C", c.len())] {
+        assert_eq!(
+            slice_len, len,
+            "multiply_batch_tiny: {} should hold {} interleaved {}x{} matrices",
+            operand, batch, dim, dim
+        );
+    }
+    if len == 0 {
+        return;
+    }
+    let group = dim * dim * BATCH_LANES;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            for ((a, b), c) in a
+                .chunks_exact(group)
+                .zip(b.chunks_exact(group))
+                .zip(c.chunks_exact_mut(group))
+            {
+                unsafe { group_avx512(a, b, c, dim) };
+            }
+            return;
+        }
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            for ((a, b), c) in a
+                .chunks_exact(group)
+                .zip(b.chunks_exact(group))
+                .zip(c.chunks_exact_mut(group))
+            {
+                unsafe { group_avx2(a, b, c, dim) };
+            }
+            return;
+        }
+    }
+
+    for ((a, b), c) in a
+        .chunks_exact(group)
+        .zip(b.chunks_exact(group))
+        .zip(c.chunks_exact_mut(group))
+    {
+        group_scalar(a, b, c, dim);
+    }
+}
+
+/// One group of [`BATCH_LANES`] matrices, a lane at a time.
+fn group_scalar(a: &[f64], b: &[f64], c: &mut [f64], dim: usize) {
+    const W: usize = BATCH_LANES;
+    for i in 0..dim {
+        for j in 0..dim {
+            for p in 0..dim {
+                let (x, y) = (&a[(i * dim + p) * W..][..W], &b[(p * dim + j) * W..][..W]);
+                for ((acc, &x), &y) in c[(i * dim + j) * W..][..W].iter_mut().zip(x).zip(y) {
+                    *acc += x * y;
+                }
+            }
+        }
+    }
+}
+
+/// One group, as two 4-wide halves.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn group_avx2(a: &[f64], b: &[f64], c: &mut [f64], dim: usize) {
+    use std::arch::x86_64::*;
+    const W: usize = BATCH_LANES;
+
+    let (a, b, c) = (a.as_ptr(), b.as_ptr(), c.as_mut_ptr());
+    for i in 0..dim {
+        for j in 0..dim {
+            unsafe {
+                let at = c.add((i * dim + j) * W);
+                let mut lo = _mm256_loadu_pd(at);
+                let mut hi = _mm256_loadu_pd(at.add(4));
+                for p in 0..dim {
+                    let x = a.add((i * dim + p) * W);
+                    let y = b.add((p * dim + j) * W);
+                    lo = _mm256_fmadd_pd(_mm256_loadu_pd(x), _mm256_loadu_pd(y), lo);
+                    hi = _mm256_fmadd_pd(_mm256_loadu_pd(x.add(4)), _mm256_loadu_pd(y.add(4)), hi);
+                }
+                _mm256_storeu_pd(at, lo);
+                _mm256_storeu_pd(at.add(4), hi);
+            }
+        }
+    }
+}
+
+/// One group, one vector per element.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn group_avx512(a: &[f64], b: &[f64], c: &mut [f64], dim: usize) {
+    use std::arch::x86_64::*;
+    const W: usize = BATCH_LANES;
+
+    let (a, b, c) = (a.as_ptr(), b.as_ptr(), c.as_mut_ptr());
+    for i in 0..dim {
+        for j in 0..dim {
+            unsafe {
+                let at = c.add((i * dim + j) * W);
+                let mut acc = _mm512_loadu_pd(at);
+                for p in 0..dim {
+                    let x = _mm512_loadu_pd(a.add((i * dim + p) * W));
+                    let y = _mm512_loadu_pd(b.add((p * dim + j) * W));
+                    acc = _mm512_fmadd_pd(x, y, acc);
+                }
+                _mm512_storeu_pd(at, acc);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matmul_naive_ikj;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+
+    #[test]
+    fn test_matches_looped_naive() {
+        // Batches that fill their last group and ones that don't
+        let batches: &[usize] = if cfg!(miri) {
+            &[1, 9]
+        } else {
+            &[1, 3, 8, 13, 16, 100]
+        };
+        for dim in 1..=8 {
+            for &batch in batches {
+                let size = dim * dim;
+                let (a, b) = (random(batch, size, 1), random(batch, size, 2));
+                let c0 = random(batch, size, 3);
+
+                let mut want = c0.clone();
+                for l in 0..batch {
+                    let at = l * size..(l + 1) * size;
+                    matmul_naive_ikj(&a[at.clone()], &b[at.clone()], &mut want[at], dim, dim, dim);
+                }
+
+                let mut c = interleave(&c0, dim, batch);
+                multiply_batch_tiny(
+                    &interleave(&a, dim, batch),
+                    &interleave(&b, dim, batch),
+                    &mut c,
+                    dim,
+                    batch,
+                );
+                let got = deinterleave(&c, dim, batch);
+                assert_close(&want, &got, 1e-14, 1e-14);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interleave_round_trip() {
+        let matrices = random(11, 9, 1);
+        let interleaved = interleave(&matrices, 3, 11);
+        assert_eq!(interleaved.len(), 2 * 9 * BATCH_LANES);
+        // Element (0, 1) of matrix 2, and of matrix 9 (the second group's
+        // second)
+        assert_eq!(interleaved[BATCH_LANES + 2], matrices[2 * 9 + 1]);
+        assert_eq!(
+            interleaved[9 * BATCH_LANES + BATCH_LANES + 1],
+            matrices[9 * 9 + 1]
+        );
+        assert_eq!(deinterleave(&interleaved, 3, 11), matrices);
+
+        assert!(interleave(&[], 0, 4).is_empty());
+        multiply_batch_tiny(&[], &[], &mut [], 3, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "B should hold 2 interleaved 3x3 matrices")]
+    fn test_wrong_length() {
+        let len = interleaved_len(3, 2);
+        multiply_batch_tiny(&vec![0.0; len], &[0.0; 18], &mut vec![0.0; len], 3, 2);
+    }
+}
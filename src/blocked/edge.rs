@@ -0,0 +1,323 @@
+//! The rows and columns a driver's tiles don't cover.
+//!
+//! Every SIMD kernel starts from C's value (or from zero, overwriting C) and
+//! adds one fused multiply-add per k step, in ascending k, whatever the block
+//! depth. The SIMD edges do
+//! the same for the leftovers, so an element of C comes out the same whether
+//! a 4×4, 12×4, or 8×8 tile covered it or an edge did; that's what makes the
+//! SIMD backends agree bit for bit (see [`Backend`](crate::Backend)). The
+//! scalar driver's edges round each product, as its kernel does.
+//!
+//! # Which elements
+//!
+//! A driver's tiles start at its band's first row and at column 0 (or the
+//! first column of its range), so they cover whole MR-row, NR-column blocks
+//! up to `m_end` and `n_main`. Every driver hands the rest to its edges in
+//! the same two pieces:
+//!
+//! - rows `m_end..end`, all of the band's columns, the bottom-right corner
+//!   included;
+//! - columns `n_main..`, only for the tiled rows `start..m_end`.
+//!
+//! Neither overlaps the tiles or the other, so each element of C is added to
+//! once. The edges run after the tiles, over all of k in one sweep rather
+//! than block by block, which for a given element is the same order:
+//!
+//! - SIMD backends: C's value, then a fused multiply-add per k step, in
+//!   ascending k, in a tile or an edge alike;
+//! - scalar backend, in a tile: each KC-deep block's products summed from
+//!   zero, the sum added to C, block after block;
+//! - scalar backend, in an edge: each product rounded and added to C, in
+//!   ascending k.
+//!
+//! A row band of a threaded multiply is a driver run of its own, so which
+//! rows its tiles cover (and so, on the scalar backend, how an element is
+//! rounded) depends on where the band starts; the SIMD backends don't
+//! notice.
+
+use std::ops::Range;
+
+/// C[i_start..i_end, cols] += A·B, one fused multiply-add per k step, in
+/// ascending k. `c` starts at row `i_start`, with rows `n` apart. With
+/// `overwrite`, the first step starts from zero rather than from C, which
+/// isn't read.
+///
+/// # Safety
+///
+/// The CPU must support FMA.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(miri), target_feature(enable = "fma"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn multiply_edge(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    i_start: usize,
+    i_end: usize,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+    overwrite: bool,
+) {
+    for i in i_start..i_end {
+        let c_row = &mut c[(i - i_start) * n..][..n];
+        for p in 0..k {
+            let a_ip = a[i * k + p];
+            let pairs = c_row[cols.clone()]
+                .iter_mut()
+                .zip(&b[p * n..][cols.clone()]);
+            if overwrite && p == 0 {
+                for (c_ij, &b_pj) in pairs {
+                    *c_ij = a_ip.mul_add(b_pj, 0.0);
+                }
+            } else {
+                for (c_ij, &b_pj) in pairs {
+                    *c_ij = a_ip.mul_add(b_pj, *c_ij);
+                }
+            }
+        }
+    }
+}
+
+/// [`multiply_edge`] reading B through Bᵀ (n×k), for a driver given only
+/// the transpose. The same sums in the same order, from C (or zero) and one
+/// fused multiply-add per k step in ascending k; eight columns at a time,
+/// so there are independent sums to overlap while Bᵀ's rows are read down
+/// their length.
+///
+/// # Safety
+///
+/// The CPU must support FMA.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(miri), target_feature(enable = "fma"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn multiply_edge_transposed(
+    a: &[f64],
+    bt: &[f64],
+    c: &mut [f64],
+    i_start: usize,
+    i_end: usize,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+    overwrite: bool,
+) {
+    for i in i_start..i_end {
+        let a_row = &a[i * k..][..k];
+        let c_row = &mut c[(i - i_start) * n..][..n];
+        for j in cols.clone().step_by(8) {
+            let width = (cols.end - j).min(8);
+            let mut acc = [0.0; 8];
+            if !overwrite {
+                acc[..width].copy_from_slice(&c_row[j..j + width]);
+            }
+            for (p, &a_ip) in a_row.iter().enumerate() {
+                for (t, x) in acc[..width].iter_mut().enumerate() {
+                    *x = a_ip.mul_add(bt[(j + t) * k + p], *x);
+                }
+            }
+            c_row[j..j + width].copy_from_slice(&acc[..width]);
+        }
+    }
+}
+
+/// [`multiply_edge`] for a driver handed `b` and maybe `bt`: from B if it's
+/// there, otherwise (`b` empty) from Bᵀ with [`multiply_edge_transposed`].
+///
+/// # Safety
+///
+/// The CPU must support FMA.
+#[cfg(target_arch = "x86_64")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn multiply_edge_either(
+    a: &[f64],
+    b: &[f64],
+    bt: Option<&[f64]>,
+    c: &mut [f64],
+    i_start: usize,
+    i_end: usize,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+    overwrite: bool,
+) {
+    match bt {
+        Some(bt) if b.is_empty() => unsafe {
+            multiply_edge_transposed(a, bt, c, i_start, i_end, cols, n, k, overwrite)
+        },
+        _ => unsafe { multiply_edge(a, b, c, i_start, i_end, cols, n, k, overwrite) },
+    }
+}
+
+/// Zeroes columns `cols` of every row of `c` (rows `n` apart): the product
+/// a driver overwriting C stores when k is 0.
+pub(crate) fn clear(c: &mut [f64], cols: Range<usize>, n: usize) {
+    if cols.is_empty() {
+        return;
+    }
+    for row in c.chunks_exact_mut(n) {
+        row[cols.clone()].fill(0.0);
+    }
+}
+
+/// C[rows, cols] += A·B for the loops that read A or B through an accessor
+/// (element (i, p) of A is `a(i, p)`), with C's rows `n` apart: fused like
+/// [`multiply_edge`] if `fused`, otherwise one rounded product at a time in
+/// the same order, as the scalar driver does.
+///
+/// # Safety
+///
+/// With `fused`, the CPU must support FMA.
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn multiply_edge_with(
+    fused: bool,
+    a: impl Fn(usize, usize) -> f64,
+    b: impl Fn(usize, usize) -> f64,
+    c: &mut [f64],
+    rows: Range<usize>,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+) {
+    #[cfg(target_arch = "x86_64")]
+    if fused {
+        return unsafe { fused_with(a, b, c, rows, cols, n, k) };
+    }
+    debug_assert!(!fused, "fused edges only exist on x86_64");
+    for i in rows {
+        for p in 0..k {
+            let a_ip = a(i, p);
+            for j in cols.clone() {
+                c[i * n + j] += a_ip * b(p, j);
+            }
+        }
+    }
+}
+
+/// The fused half of [`multiply_edge_with`].
+///
+/// # Safety
+///
+/// The CPU must support FMA.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(miri), target_feature(enable = "fma"))]
+unsafe fn fused_with(
+    a: impl Fn(usize, usize) -> f64,
+    b: impl Fn(usize, usize) -> f64,
+    c: &mut [f64],
+    rows: Range<usize>,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+) {
+    for i in rows {
+        for p in 0..k {
+            let a_ip = a(i, p);
+            for j in cols.clone() {
+                c[i * n + j] = a_ip.mul_add(b(p, j), c[i * n + j]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::GemmBackend;
+    use crate::matrix::generate::random;
+    use crate::{Backend, available_backends};
+    use std::ops::Range;
+
+    /// Rows `rows` of C after a driver run over that band, each element
+    /// worked out on its own in the order the module docs give.
+    fn documented(
+        backend: Backend,
+        a: &[f64],
+        b: &[f64],
+        c: &[f64],
+        (n, k): (usize, usize),
+        rows: Range<usize>,
+    ) -> Vec<f64> {
+        let (mr, nr) = backend.tile();
+        let (kc, _) = backend.block_sizes(k);
+        let m_end = rows.start + rows.len() / mr * mr;
+        let n_main = n / nr * nr;
+        let mut want = c[rows.start * n..rows.end * n].to_vec();
+        for i in rows.clone() {
+            for j in 0..n {
+                let x = &mut want[(i - rows.start) * n + j];
+                let product = |p: usize| a[i * k + p] * b[p * n + j];
+                if backend != Backend::Scalar {
+                    for p in 0..k {
+                        *x = a[i * k + p].mul_add(b[p * n + j], *x);
+                    }
+                } else if i < m_end && j < n_main {
+                    for kk in (0..k).step_by(kc) {
+                        *x += (kk..(kk + kc).min(k)).fold(0.0, |sum, p| sum + product(p));
+                    }
+                } else {
+                    for p in 0..k {
+                        *x += product(p);
+                    }
+                }
+            }
+        }
+        want
+    }
+
+    /// Every blocked backend over all of C and over bands starting mid-tile,
+    /// from a nonzero C, against [`documented`] bit for bit. An edge element
+    /// added to twice, or by the wrong piece, is off by a whole product.
+    fn check_documented_order(m: usize, n: usize, k: usize) {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let c_initial = random(m, n, 3);
+        // Band boundaries: all of C, then three bands starting mid-tile
+        let cuts: [&[usize]; 2] = [&[0, m], &[0, 5, m / 2 + 1, m]];
+        for backend in available_backends() {
+            if backend == Backend::ScalarIkj {
+                continue;
+            }
+            for cuts in cuts {
+                let bands: Vec<Range<usize>> = cuts.windows(2).map(|w| w[0]..w[1]).collect();
+                let mut c = c_initial.clone();
+                for rows in &bands {
+                    let band = &mut c[rows.start * n..rows.end * n];
+                    // Safety: the backend is available, and the shapes match
+                    unsafe { backend.run(&a, &b, band, m, n, k, rows.clone()) };
+                }
+                for rows in &bands {
+                    let want = documented(backend, &a, &b, &c_initial, (n, k), rows.clone());
+                    let got = &c[rows.start * n..rows.end * n];
+                    for (at, (&x, &y)) in got.iter().zip(&want).enumerate() {
+                        assert!(
+                            x.to_bits() == y.to_bits(),
+                            "{} on {}x{}x{}, band {:?}: C[{}][{}] is {}, expected {}",
+                            backend,
+                            m,
+                            n,
+                            k,
+                            rows,
+                            rows.start + at / n,
+                            at % n,
+                            x,
+                            y
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bottom_right_corner() {
+        check_documented_order(13, 13, 13);
+        check_documented_order(21, 17, 9);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri")]
+    fn test_bottom_right_corner_across_blocks() {
+        check_documented_order(130, 130, 130);
+        check_documented_order(29, 19, 600);
+    }
+}
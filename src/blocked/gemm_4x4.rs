@@ -1,7 +1,43 @@
 //! 4×4 blocked GEMM using AVX2.
 
-use crate::kernels::kernel_4x4::kernel_4x4_avx2;
+use crate::Unsupported;
+use crate::blocked::edge::multiply_edge_either;
+use crate::blocked::pack::{pack_a_panel, pack_b_panel, pack_b_panel_direct};
+use crate::kernels::kernel_4x4::{kernel_4x4_avx2, kernel_4x4_avx2_store};
 use crate::matrix::transpose::transpose;
+use crate::workspace::{Workspace, with_scratch};
+
+/// L1 blocking: depth of a packed panel, to keep the working set small
+pub(crate) const KC: usize = 256;
+/// L2 blocking: rows of A reused across columns (a multiple of 4)
+pub(crate) const MC: usize = 128;
+
+/// C += A·B with [`matmul_blocked_4x4`], checking for AVX2 and FMA first.
+///
+/// The safe way in: no `unsafe`, and no feature check to get wrong.
+/// [`multiply`](crate::multiply) picks the fastest backend by itself; this
+/// is for pinning one.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU lacks AVX2 and FMA, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn run(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Result<(), Unsupported> {
+    crate::error::assert_dims(a, b, c, m, n, k);
+    crate::features::require_avx2("matmul_blocked_4x4")?;
+    unsafe { matmul_blocked_4x4(a, b, c, m, n, k, None, None) };
+    Ok(())
+}
 
 /// Cache-blocked matrix multiplication using 4×4 AVX2 kernel.
 ///
@@ -15,176 +51,196 @@ use crate::matrix::transpose::transpose;
 /// - CPU supports AVX2 and FMA
 /// - All slice lengths match the provided dimensions
 ///
+/// [`run`] does both checks, over all of C.
+///
 /// # Arguments
 ///
-/// * `row_start`, `row_end` - Optional row range for multi-threaded use
-#[target_feature(enable = "avx2,fma")]
+/// * `c` - Rows `row_start..row_end` of C (all of C without a row range),
+///   so each thread can own a disjoint band
+/// * `row_start`, `row_end` - Optional row range for multi-threaded use. Any
+///   `[row_start, row_end)` works, aligned or not; each row in it is computed
+///   exactly once.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn matmul_blocked_4x4(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+) {
+    with_scratch(|workspace| unsafe {
+        matmul_blocked_4x4_with(
+            a, b, None, c, m, n, k, row_start, row_end, KC, MC, workspace,
+        )
+    })
+}
+
+/// [`matmul_blocked_4x4`] with its blocking and scratch buffers supplied:
+/// panels at most `max_kc` deep and `mc` rows tall (a multiple of 4),
+/// packed into `workspace`, which grows as needed. `bt` is all of B
+/// transposed (n×k), to pack from rather than making Bᵀ in `workspace`;
+/// threads running bands of one multiply share it. With `bt`, `b` may be
+/// empty, and the edges read Bᵀ as well.
+///
+/// # Safety
+///
+/// Same as [`matmul_blocked_4x4`].
+#[cfg_attr(not(miri), target_feature(enable = "avx2,fma"))]
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 #[allow(unsafe_op_in_unsafe_fn)]
 #[allow(clippy::too_many_arguments)]
-pub unsafe fn matmul_blocked_4x4(
+pub(crate) unsafe fn matmul_blocked_4x4_with(
     a: &[f64],
     b: &[f64],
+    bt: Option<&[f64]>,
     c: &mut [f64],
     m: usize,
     n: usize,
     k: usize,
     row_start: Option<usize>,
     row_end: Option<usize>,
+    max_kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
 ) {
     let start = row_start.unwrap_or(0);
     let end = row_end.unwrap_or(m);
-    // Step 1: Transpose B once at the start
-    // This lets us access B's columns as rows, which is way faster
-    let mut bt = vec![0.0; k * n];
-    transpose(b, &mut bt, k, n);
-
-    // Only process complete 4×4 tiles, handle leftovers separately
-    let m_start = (start / 4) * 4;
-    let m_end = (end / 4) * 4;
+    assert_eq!(
+        Some(c.len()),
+        (end - start).checked_mul(n),
+        "C: expected rows {}..{} of {} columns",
+        start,
+        end,
+        n
+    );
+    let overwrite = workspace.overwrite;
+    // Bᵀ as given, for the edges; `bt` below is the one packed from
+    let given_bt = bt;
+    let epilogue = workspace.epilogue.clone();
+    // No rows, no columns, or an all-zero product: nothing to add
+    if start == end || n == 0 || k == 0 {
+        if overwrite {
+            c.fill(0.0);
+        }
+        return;
+    }
+    // Only process complete 4×4 tiles, handle leftovers separately.
+    // Tiles start exactly at the band start (not rounded down to a multiple
+    // of 4), so adjacent bands never compute the same row twice.
+    let m_start = start;
+    let m_end = start + ((end - start) / 4) * 4;
     let n_main = (n / 4) * 4;
 
     // Cache blocking sizes - tuned to fit in L1/L2 cache
-    let kc = k.min(256); // L1 blocking: keep working set small
-    let mc = m.min(128); // L2 blocking: reuse A across columns
-
-    // Pre-allocate buffers for packed data
-    let mut a_panel = vec![0.0; mc * kc]; // Big panel that stays in L2
-    let mut b_pack = vec![0.0; 4 * kc]; // Small panel for L1
+    let kc = k.min(max_kc);
+
+    // Bᵀ, so B's columns can be packed from contiguous rows, unless it's
+    // too big to copy; B's rows then hold each panel's values in order too
+    let transpose_b = bt.is_none() && workspace.transposes_b(k, n);
+    let bt_len = if transpose_b { k * n } else { 0 };
+    // Buffers for packed data, from the workspace
+    // Big panel that stays in L2. A block never spans more than the band's
+    // full tiles, so size it from that rather than from m.
+    let (own_bt, a_panel, b_pack) = workspace.buffers(bt_len, mc.min(m_end - m_start) * kc, 4 * kc);
+    if transpose_b {
+        transpose(b, own_bt, k, n);
+    }
+    // The Bᵀ to pack from, if any
+    let bt = bt.or(transpose_b.then_some(&*own_bt));
 
     // Three nested loops for cache blocking
     // Outer: K dimension (process k in chunks)
     for kk in (0..k).step_by(kc) {
         let k_block = (kk + kc).min(k) - kk;
+        // The first k block stores into C when overwriting; later ones add
+        let kernel = if overwrite && kk == 0 {
+            kernel_4x4_avx2_store
+        } else {
+            kernel_4x4_avx2
+        };
 
         // Middle: M dimension (process rows in chunks)
         for ii in (m_start..m_end).step_by(mc) {
             let m_block = (ii + mc).min(m_end) - ii;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(kk, ii, k_block, m_block, "gemm block");
 
             // Pack a big chunk of A into cache-friendly layout
             // Do this ONCE, then reuse for all columns
-            pack_a_panel_large(a, &mut a_panel, ii, kk, m_block, k_block, k);
+            pack_a_panel::<4>(a, a_panel, ii, kk, m_block, k_block, k);
 
             // Inner: Loop over columns (process 4 at a time)
             for j in (0..n_main).step_by(4) {
                 // Pack 4 columns of B
-                pack_b_panel(&bt, &mut b_pack, j, kk, k_block, k);
+                match bt {
+                    Some(bt) => pack_b_panel::<4>(bt, b_pack, j, kk, k_block, k),
+                    None => pack_b_panel_direct::<4>(b, b_pack, j, kk, k_block, n),
+                }
 
                 // Now call the kernel for each 4-row chunk
                 for i in (0..m_block).step_by(4) {
                     // Figure out where this 4×4 tile starts in the big A panel
                     let a_pack_offset = i * k_block;
 
-                    kernel_4x4_avx2(
+                    // The tile's first element, and how much of C is left from it
+                    let at = (ii + i - start) * n + j;
+                    kernel(
                         a_panel.as_ptr().add(a_pack_offset),
                         b_pack.as_ptr(),
-                        c.as_mut_ptr().add((ii + i) * n + j),
+                        c.as_mut_ptr().add(at),
+                        c.len() - at,
                         k_block,
                         n,
                     );
+                    // The tile's last block: finish it while it's in cache
+                    if let Some(epilogue) = &epilogue
+                        && kk + k_block == k
+                    {
+                        epilogue.apply_tile::<4, 4>(&mut c[at..], n, j);
+                    }
                 }
             }
         }
     }
 
-    // Handle leftover rows and columns that don't fit in 4×4 tiles
+    // Leftover rows, every column (the bottom-right corner too), then
+    // leftover columns of the tiled rows only: each element once
     if m_end < end {
-        edge_case_rows(a, b, c, m_end, end, n, k);
-    }
-    if n_main < n {
-        edge_case_cols(a, b, c, m_start, m_end, n_main, n, k); // CHANGED
-    }
-}
-
-// Pack a big chunk of A (many rows × k columns) into a more cache-friendly layout
-// Original: row-major (rows are sequential)
-// Packed: column-major in groups of 4 (each k-position's 4 values are together)
-#[allow(clippy::identity_op)]
-fn pack_a_panel_large(
-    a: &[f64],
-    a_panel: &mut [f64],
-    i_start: usize,
-    k_start: usize,
-    m_block: usize,
-    k_block: usize,
-    k_total: usize,
-) {
-    // Process in groups of 4 rows (that's our kernel height)
-    for i_offset in (0..m_block).step_by(4) {
-        // For each k position in this block
-        for p in 0..k_block {
-            let k_idx = k_start + p;
-            let out_base = (i_offset * k_block) + (p * 4);
-
-            // Copy 4 row values for this k position
-            // Now they're right next to each other in memory!
-            a_panel[out_base + 0] = a[(i_start + i_offset + 0) * k_total + k_idx];
-            a_panel[out_base + 1] = a[(i_start + i_offset + 1) * k_total + k_idx];
-            a_panel[out_base + 2] = a[(i_start + i_offset + 2) * k_total + k_idx];
-            a_panel[out_base + 3] = a[(i_start + i_offset + 3) * k_total + k_idx];
-        }
-    }
-}
-
-// Pack 4 columns of transposed B into sequential layout
-// After transpose, B's columns are rows in bt, so we can read them easily
-#[allow(clippy::identity_op)]
-fn pack_b_panel(
-    bt: &[f64],
-    b_pack: &mut [f64],
-    j_start: usize,
-    k_start: usize,
-    k_block: usize,
-    k_total: usize,
-) {
-    for p in 0..k_block {
-        let k_idx = k_start + p;
-        // Grab 4 values from 4 consecutive rows of bt (which are columns of original B)
-        b_pack[p * 4 + 0] = bt[(j_start + 0) * k_total + k_idx];
-        b_pack[p * 4 + 1] = bt[(j_start + 1) * k_total + k_idx];
-        b_pack[p * 4 + 2] = bt[(j_start + 2) * k_total + k_idx];
-        b_pack[p * 4 + 3] = bt[(j_start + 3) * k_total + k_idx];
-    }
-}
-
-// Handle rows that don't fit in 4×4 tiles (just use simple scalar code)
-#[allow(clippy::too_many_arguments)]
-fn edge_case_rows(
-    a: &[f64],
-    b: &[f64],
-    c: &mut [f64],
-    i_start: usize,
-    m: usize,
-    n: usize,
-    k: usize,
-) {
-    for i in i_start..m {
-        for p in 0..k {
-            for j in 0..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
+        multiply_edge_either(
+            a,
+            b,
+            given_bt,
+            &mut c[(m_end - start) * n..],
+            m_end,
+            end,
+            0..n,
+            n,
+            k,
+            overwrite,
+        );
+        if let Some(epilogue) = &epilogue {
+            epilogue.apply(&mut c[(m_end - start) * n..], end - m_end, n, 0..n);
         }
     }
-}
-
-// Handle columns that don't fit in 4×4 tiles
-#[allow(clippy::too_many_arguments)]
-fn edge_case_cols(
-    a: &[f64],
-    b: &[f64],
-    c: &mut [f64],
-    i_start: usize,
-    i_end: usize,
-    j_start: usize,
-    n: usize,
-    k: usize,
-) {
-    for i in i_start..i_end {
-        for p in 0..k {
-            for j in j_start..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
+    if n_main < n {
+        multiply_edge_either(
+            a,
+            b,
+            given_bt,
+            c,
+            m_start,
+            m_end,
+            n_main..n,
+            n,
+            k,
+            overwrite,
+        );
+        if let Some(epilogue) = &epilogue {
+            epilogue.apply(&mut c[n_main..], m_end - m_start, n, n_main..n);
         }
     }
 }
@@ -1,7 +1,43 @@
 //! 8×8 blocked GEMM using AVX-512.
 
-use crate::kernels::kernel_8x8::kernel_8x8_avx512;
-use crate::matrix::transpose::transpose;
+use crate::Unsupported;
+use crate::blocked::edge::{clear, multiply_edge_either};
+use crate::blocked::pack::{pack_a_panel, pack_b_panel, pack_b_panel_direct};
+use crate::kernels::kernel_8x8::{kernel_8x8_avx512, kernel_8x8_avx512_store};
+use crate::matrix::transpose::{transpose, transpose_strided};
+use crate::workspace::{Workspace, with_scratch};
+
+/// Depth of a packed panel (k block)
+pub(crate) const KC: usize = 256;
+/// Rows of A per packed panel; a multiple of the kernel height
+pub(crate) const MC: usize = 128;
+
+/// C += A·B with [`matmul_blocked_8x8`], checking for AVX-512F and FMA first.
+///
+/// The safe way in: no `unsafe`, and no feature check to get wrong.
+/// [`multiply`](crate::multiply) picks the fastest backend by itself; this
+/// is for pinning one.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU lacks AVX-512F and FMA, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn run(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Result<(), Unsupported> {
+    crate::error::assert_dims(a, b, c, m, n, k);
+    crate::features::require_avx512("matmul_blocked_8x8")?;
+    unsafe { matmul_blocked_8x8(a, b, c, m, n, k, None, None, None, None) };
+    Ok(())
+}
 
 /// Cache-blocked matrix multiplication using 8×8 AVX-512 kernel.
 ///
@@ -12,151 +48,218 @@ use crate::matrix::transpose::transpose;
 /// # Safety
 ///
 /// Caller must ensure:
-/// - CPU supports AVX-512F, AVX-512DQ, and FMA
+/// - CPU supports AVX-512F and FMA
 /// - All slice lengths match the provided dimensions
 ///
+/// [`run`] does both checks, over all of C.
+///
 /// # Arguments
 ///
-/// * `row_start`, `row_end` - Optional row range for multi-threaded use
-#[target_feature(enable = "avx512f,avx512dq,fma")]
+/// * `c` - Rows `row_start..row_end` of C (all of C without a row range),
+///   so each thread can own a disjoint band
+/// * `row_start`, `row_end` - Optional row range for multi-threaded use. Any
+///   `[row_start, row_end)` works, aligned or not; each row in it is computed
+///   exactly once.
+/// * `col_start`, `col_end` - Optional column range, for 2-D splits: only
+///   those columns of C are written (`c` still holds whole rows) and only
+///   those columns of B are packed. Together with the rows, any rectangle
+///   works; each element in it is computed exactly once, and a grid of
+///   rectangles covering C matches one call over all of it bit for bit.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn matmul_blocked_8x8(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+    col_start: Option<usize>,
+    col_end: Option<usize>,
+) {
+    with_scratch(|workspace| unsafe {
+        matmul_blocked_8x8_with(
+            a, b, None, c, m, n, k, row_start, row_end, col_start, col_end, KC, MC, workspace,
+        )
+    })
+}
+
+/// [`matmul_blocked_8x8`] with its blocking and scratch buffers supplied:
+/// panels at most `max_kc` deep and `mc` rows tall (a multiple of 8),
+/// packed into `workspace`, which grows as needed. `bt` is all of B
+/// transposed (n×k), to pack from rather than making Bᵀ in `workspace`;
+/// threads running bands of one multiply share it. With `bt`, `b` may be
+/// empty, and the edges read Bᵀ as well.
+///
+/// # Safety
+///
+/// Same as [`matmul_blocked_8x8`].
+#[cfg_attr(not(miri), target_feature(enable = "avx512f,fma"))]
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 #[allow(unsafe_op_in_unsafe_fn)]
 #[allow(clippy::too_many_arguments)]
-pub unsafe fn matmul_blocked_8x8(
+pub(crate) unsafe fn matmul_blocked_8x8_with(
     a: &[f64],
     b: &[f64],
+    bt: Option<&[f64]>,
     c: &mut [f64],
     m: usize,
     n: usize,
     k: usize,
     row_start: Option<usize>,
     row_end: Option<usize>,
+    col_start: Option<usize>,
+    col_end: Option<usize>,
+    max_kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
 ) {
     let start = row_start.unwrap_or(0);
     let end = row_end.unwrap_or(m);
+    assert_eq!(
+        Some(c.len()),
+        (end - start).checked_mul(n),
+        "C: expected rows {}..{} of {} columns",
+        start,
+        end,
+        n
+    );
+    let col_start = col_start.unwrap_or(0);
+    let col_end = col_end.unwrap_or(n);
+    assert!(
+        col_start <= col_end && col_end <= n,
+        "columns {}..{} outside 0..{}",
+        col_start,
+        col_end,
+        n
+    );
+    let overwrite = workspace.overwrite;
+    // Bᵀ as given, for the edges; `bt` below is the one packed from
+    let given_bt = bt;
+    let epilogue = workspace.epilogue.clone();
+    // No rows, no columns, or an all-zero product: nothing to add
+    if start == end || col_start == col_end || k == 0 {
+        if overwrite {
+            clear(c, col_start..col_end, n);
+        }
+        return;
+    }
 
-    let mut bt = vec![0.0; k * n];
-    transpose(b, &mut bt, k, n);
-
-    let m_start = (start / 8) * 8;
-    let m_end = (end / 8) * 8;
-    let n_main = (n / 8) * 8;
+    // Tiles start exactly at the rectangle's corner, so any rows × columns
+    // rectangle is computed once: full tiles, then leftover rows, then
+    // leftover columns.
+    let m_start = start;
+    let m_end = start + ((end - start) / 8) * 8;
+    let width = col_end - col_start;
+    let n_main = col_start + (width / 8) * 8;
 
-    let kc = k.min(256);
-    let mc = (end - start).min(128);
+    let kc = k.min(max_kc);
 
     let mr: usize = 8;
     let nr = 8;
 
-    let mut a_panel = vec![0.0; mc * kc];
-    let mut b_panel = vec![0.0; 8 * kc];
+    // Bᵀ, so B's columns can be packed from contiguous rows, unless it's
+    // too big to copy; B's rows then hold each panel's values in order too
+    let transpose_b = bt.is_none() && workspace.transposes_b(k, width);
+    let bt_len = if transpose_b { k * width } else { 0 };
+    // A block never spans more than the band's full tiles
+    let (own_bt, a_panel, b_panel) =
+        workspace.buffers(bt_len, mc.min(m_end - m_start) * kc, 8 * kc);
+    if transpose_b && width == n {
+        transpose(b, own_bt, k, n);
+    } else if transpose_b {
+        // Just the range's columns, as a width×k Bᵀ
+        transpose_strided(&b[col_start..], n, own_bt, k, k, width);
+    }
+    // The Bᵀ to pack from, if any, and the column of B its first row is
+    let bt = match bt {
+        Some(bt) => Some((bt, 0)),
+        None => transpose_b.then_some((&*own_bt, col_start)),
+    };
 
     for kk in (0..k).step_by(kc) {
         let k_block = (kk + kc).min(k) - kk;
+        // The first k block stores into C when overwriting; later ones add
+        let kernel = if overwrite && kk == 0 {
+            kernel_8x8_avx512_store
+        } else {
+            kernel_8x8_avx512
+        };
 
         for ii in (m_start..m_end).step_by(mc) {
             let m_block = (ii + mc).min(m_end) - ii;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(kk, ii, k_block, m_block, "gemm block");
 
-            pack_big_a_panel(a, &mut a_panel, ii, kk, m_block, k_block, k);
+            pack_a_panel::<8>(a, a_panel, ii, kk, m_block, k_block, k);
 
-            for j in (0..n_main).step_by(nr) {
-                pack_b_panel(&bt, &mut b_panel, j, kk, k_block, k);
+            for j in (col_start..n_main).step_by(nr) {
+                match bt {
+                    Some((bt, first)) => pack_b_panel::<8>(bt, b_panel, j - first, kk, k_block, k),
+                    None => pack_b_panel_direct::<8>(b, b_panel, j, kk, k_block, n),
+                }
 
                 for i in (0..m_block).step_by(mr) {
                     let a_pack_offset = i * k_block;
 
-                    kernel_8x8_avx512(
+                    // The tile's first element, and how much of C is left from it
+                    let at = (ii + i - start) * n + j;
+                    kernel(
                         a_panel.as_ptr().add(a_pack_offset),
                         b_panel.as_ptr(),
-                        c.as_mut_ptr().add((ii + i) * n + j),
+                        c.as_mut_ptr().add(at),
+                        c.len() - at,
                         k_block,
                         n,
                     );
+                    // The tile's last block: finish it while it's in cache
+                    if let Some(epilogue) = &epilogue
+                        && kk + k_block == k
+                    {
+                        epilogue.apply_tile::<8, 8>(&mut c[at..], n, j);
+                    }
                 }
             }
         }
     }
 
+    // Leftover rows, every column (the bottom-right corner too), then
+    // leftover columns of the tiled rows only: each element once
     if m_end < end {
-        edge_case_rows(a, b, c, m_end, end, n, k);
-    }
-    if n_main < n {
-        edge_case_cols(a, b, c, m_start, m_end, n_main, n, k);
-    }
-}
-
-fn pack_big_a_panel(
-    a: &[f64],
-    a_panel: &mut [f64],
-    i_start: usize,
-    k_start: usize,
-    m_block: usize,
-    k_block: usize,
-    k_total: usize,
-) {
-    for i_offset in (0..m_block).step_by(8) {
-        for p in 0..k_block {
-            let k_idx = k_start + p;
-            let out_base = (i_offset * k_block) + (p * 8);
-
-            for idx in 0..8 {
-                a_panel[out_base + idx] = a[(i_start + i_offset + idx) * k_total + k_idx];
-            }
-        }
-    }
-}
-
-fn pack_b_panel(
-    bt: &[f64],
-    b_pack: &mut [f64],
-    j_start: usize,
-    k_start: usize,
-    k_block: usize,
-    k_total: usize,
-) {
-    for p in 0..k_block {
-        for idx in 0..8 {
-            b_pack[p * 8 + idx] = bt[(j_start + idx) * k_total + (k_start + p)];
-        }
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn edge_case_rows(
-    a: &[f64],
-    b: &[f64],
-    c: &mut [f64],
-    i_start: usize,
-    i_end: usize,
-    n: usize,
-    k: usize,
-) {
-    for i in i_start..i_end {
-        for p in 0..k {
-            for j in 0..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
+        multiply_edge_either(
+            a,
+            b,
+            given_bt,
+            &mut c[(m_end - start) * n..],
+            m_end,
+            end,
+            col_start..col_end,
+            n,
+            k,
+            overwrite,
+        );
+        if let Some(epilogue) = &epilogue {
+            let at = (m_end - start) * n + col_start;
+            epilogue.apply(&mut c[at..], end - m_end, n, col_start..col_end);
         }
     }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn edge_case_cols(
-    a: &[f64],
-    b: &[f64],
-    c: &mut [f64],
-    i_start: usize,
-    i_end: usize,
-    j_start: usize,
-    n: usize,
-    k: usize,
-) {
-    for i in i_start..i_end {
-        for p in 0..k {
-            for j in j_start..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
+    if n_main < col_end {
+        multiply_edge_either(
+            a,
+            b,
+            given_bt,
+            c,
+            m_start,
+            m_end,
+            n_main..col_end,
+            n,
+            k,
+            overwrite,
+        );
+        if let Some(epilogue) = &epilogue {
+            epilogue.apply(&mut c[n_main..], m_end - m_start, n, n_main..col_end);
         }
     }
 }
@@ -164,15 +267,12 @@ fn edge_case_cols(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::matrix::compare::assert_close;
     use crate::matrix::naive_ikj::matmul_naive_ikj;
 
     #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; tests/miri.rs covers it")]
     fn test_gemm_8x8_correctness() {
-        if !is_x86_feature_detected!("avx512f") {
-            println!("Skipping - AVX-512 not available");
-            return;
-        }
-
         let m = 144;
         let n = 128;
         let k = 256;
@@ -184,19 +284,12 @@ mod tests {
         matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
 
         let mut c_gemm = vec![0.0; m * n];
-        unsafe {
-            matmul_blocked_8x8(&a, &b, &mut c_gemm, m, n, k, None, None);
+        if let Err(e) = run(&a, &b, &mut c_gemm, m, n, k) {
+            println!("Skipping - {}", e);
+            return;
         }
 
-        for i in 0..m * n {
-            assert!(
-                (c_naive[i] - c_gemm[i]).abs() < 1e-8,
-                "Mismatch at {}: naive={}, gemm={}",
-                i,
-                c_naive[i],
-                c_gemm[i]
-            );
-        }
+        assert_close(&c_naive, &c_gemm, 1e-12, 1e-12);
 
         println!(" 8×8 GEMM test passed!");
     }
@@ -0,0 +1,240 @@
+//! 4×4 blocked GEMM in plain scalar Rust.
+//!
+//! Same blocking and packing as `gemm_4x4`, but the microkernel is ordinary
+//! code: 16 accumulators the compiler can keep in registers. No intrinsics and
+//! no `unsafe`, so it runs anywhere and reads as a walkthrough of what the SIMD
+//! drivers do.
+
+use crate::blocked::pack::{pack_a_panel, pack_b_panel, pack_b_panel_direct};
+use crate::matrix::transpose::transpose;
+use crate::workspace::{Workspace, with_scratch};
+use std::ops::Range;
+
+/// Kernel height and width
+const MR: usize = 4;
+const NR: usize = 4;
+
+/// Depth of a packed panel (k block)
+pub(crate) const KC: usize = 256;
+/// Rows of A per packed panel
+pub(crate) const MC: usize = 128;
+
+/// Cache-blocked matrix multiplication with a scalar 4×4 register-blocked kernel.
+///
+/// The portable fallback behind [`multiply`](crate::multiply) when no SIMD
+/// kernel is available. Keeping a 4×4 tile of C in locals for the whole k loop
+/// turns 2 loads per multiply-add (as in i-k-j) into 8 loads per 16, which is
+/// where the speedup over the naive loops comes from.
+///
+/// # Arguments
+///
+/// * `c` - Rows `row_start..row_end` of C (all of C without a row range),
+///   so each thread can own a disjoint band
+/// * `row_start`, `row_end` - Optional row range for multi-threaded use. Any
+///   `[row_start, row_end)` works; each row in it is computed exactly once.
+#[allow(clippy::too_many_arguments)]
+pub fn matmul_blocked_scalar(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+) {
+    with_scratch(|workspace| {
+        matmul_blocked_scalar_with(
+            a, b, None, c, m, n, k, row_start, row_end, KC, MC, workspace,
+        )
+    })
+}
+
+/// [`matmul_blocked_scalar`] with its blocking and scratch buffers supplied:
+/// panels at most `max_kc` deep and `mc` rows tall (a multiple of 4),
+/// packed into `workspace`, which grows as needed. `bt` is all of B
+/// transposed (n×k), to pack from rather than making Bᵀ in `workspace`;
+/// threads running bands of one multiply share it.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn matmul_blocked_scalar_with(
+    a: &[f64],
+    b: &[f64],
+    bt: Option<&[f64]>,
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+    max_kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
+) {
+    let start = row_start.unwrap_or(0);
+    let end = row_end.unwrap_or(m);
+    assert_eq!(
+        Some(c.len()),
+        (end - start).checked_mul(n),
+        "C: expected rows {}..{} of {} columns",
+        start,
+        end,
+        n
+    );
+    let overwrite = workspace.overwrite;
+    let epilogue = workspace.epilogue.clone();
+    // No rows, no columns, or an all-zero product: nothing to add
+    if start == end || n == 0 || k == 0 {
+        if overwrite {
+            c.fill(0.0);
+        }
+        return;
+    }
+    // Full 4×4 tiles only, starting exactly at the band start
+    let m_start = start;
+    let m_end = start + ((end - start) / MR) * MR;
+    let n_main = (n / NR) * NR;
+
+    // Same cache blocking as the 4×4 AVX2 driver
+    let kc = k.min(max_kc);
+
+    // Bᵀ, so B's columns can be packed from contiguous rows, unless it's
+    // too big to copy; B's rows then hold each panel's values in order too
+    let transpose_b = bt.is_none() && workspace.transposes_b(k, n);
+    let bt_len = if transpose_b { k * n } else { 0 };
+    let (own_bt, a_panel, b_pack) =
+        workspace.buffers(bt_len, mc.min(m_end - m_start) * kc, NR * kc);
+    if transpose_b {
+        transpose(b, own_bt, k, n);
+    }
+    // The Bᵀ to pack from, if any
+    let bt = bt.or(transpose_b.then_some(&*own_bt));
+
+    for kk in (0..k).step_by(kc) {
+        let k_block = (kk + kc).min(k) - kk;
+
+        for ii in (m_start..m_end).step_by(mc) {
+            let m_block = (ii + mc).min(m_end) - ii;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(kk, ii, k_block, m_block, "gemm block");
+
+            pack_a_panel::<MR>(a, a_panel, ii, kk, m_block, k_block, k);
+
+            for j in (0..n_main).step_by(NR) {
+                match bt {
+                    Some(bt) => pack_b_panel::<NR>(bt, b_pack, j, kk, k_block, k),
+                    None => pack_b_panel_direct::<NR>(b, b_pack, j, kk, k_block, n),
+                }
+
+                for i in (0..m_block).step_by(MR) {
+                    let c_tile = &mut c[(ii + i - start) * n + j..];
+                    kernel_4x4(
+                        &a_panel[i * k_block..(i + MR) * k_block],
+                        &b_pack[..NR * k_block],
+                        c_tile,
+                        n,
+                        overwrite && kk == 0,
+                    );
+                    // The tile's last block: finish it while it's in cache
+                    if let Some(epilogue) = &epilogue
+                        && kk + k_block == k
+                    {
+                        epilogue.apply_tile::<MR, NR>(c_tile, n, j);
+                    }
+                }
+            }
+        }
+    }
+
+    // Leftover rows, every column (the bottom-right corner too), then
+    // leftover columns of the tiled rows only: each element once
+    if m_end < end {
+        edge_case(
+            a,
+            b,
+            &mut c[(m_end - start) * n..],
+            m_end,
+            end,
+            0..n,
+            n,
+            k,
+            overwrite,
+        );
+        if let Some(epilogue) = &epilogue {
+            epilogue.apply(&mut c[(m_end - start) * n..], end - m_end, n, 0..n);
+        }
+    }
+    if n_main < n {
+        edge_case(a, b, c, m_start, m_end, n_main..n, n, k, overwrite);
+        if let Some(epilogue) = &epilogue {
+            epilogue.apply(&mut c[n_main..], m_end - m_start, n, n_main..n);
+        }
+    }
+}
+
+/// C[0..4, 0..4] += A_pack × B_pack, with C's rows `ldc` apart; with
+/// `overwrite`, C = A_pack × B_pack, without reading C.
+///
+/// `a_pack` holds 4 row values per k step, `b_pack` 4 column values per k
+/// step. The accumulator array stays in registers for the whole loop.
+pub(crate) fn kernel_4x4(
+    a_pack: &[f64],
+    b_pack: &[f64],
+    c: &mut [f64],
+    ldc: usize,
+    overwrite: bool,
+) {
+    let mut acc = [[0.0; NR]; MR];
+
+    for (a, b) in a_pack.chunks_exact(MR).zip(b_pack.chunks_exact(NR)) {
+        // Fixed-size views, so the inner loops unroll without bounds checks
+        let a: &[f64; MR] = a.try_into().unwrap();
+        let b: &[f64; NR] = b.try_into().unwrap();
+        for i in 0..MR {
+            for j in 0..NR {
+                acc[i][j] += a[i] * b[j];
+            }
+        }
+    }
+
+    for (i, row) in acc.iter().enumerate() {
+        for (j, &value) in row.iter().enumerate() {
+            // A sum from +0 is never -0, so storing it is adding it to zero
+            if overwrite {
+                c[i * ldc + j] = value;
+            } else {
+                c[i * ldc + j] += value;
+            }
+        }
+    }
+}
+
+/// C[i_start..i_end, cols] += A·B outside the 4×4 tiles, each product
+/// rounded and added to C in ascending k: the scalar counterpart of the
+/// SIMD drivers' `multiply_edge`. `c` starts at row `i_start`.
+#[allow(clippy::too_many_arguments)]
+fn edge_case(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    i_start: usize,
+    i_end: usize,
+    cols: Range<usize>,
+    n: usize,
+    k: usize,
+    overwrite: bool,
+) {
+    for i in i_start..i_end {
+        for p in 0..k {
+            for j in cols.clone() {
+                let product = a[i * k + p] * b[p * n + j];
+                let c_ij = &mut c[(i - i_start) * n + j];
+                if overwrite && p == 0 {
+                    // Added to zero, as if C had been cleared: -0 becomes +0
+                    *c_ij = 0.0 + product;
+                } else {
+                    *c_ij += product;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Cache-blocked scalar GEMM for a pre-transposed B.
+
+/// Rows of A and rows of B^T per block: a 64×256 slab of each is 128 KB,
+/// which stays in L2 while every pair of rows is dotted together.
+const MC: usize = 64;
+const NC: usize = 64;
+/// Depth per block
+const KC: usize = 256;
+
+/// Cache-blocked C += A * B, with B given as B^T.
+///
+/// Same dot-product formulation as
+/// [`matmul_ikj_transposed`](crate::matrix::naive_ikj::matmul_ikj_transposed),
+/// tiled over i, j and p so a block of A rows and a block of B^T rows are
+/// reused from cache instead of being streamed from memory once per element of
+/// C. Each dot product keeps four partial sums so the compiler can overlap the
+/// additions. Plain scalar code, no `unsafe`.
+///
+/// # Arguments
+///
+/// * `a` - Matrix A (m × k), row-major
+/// * `bt` - Transposed matrix B^T (n × k), row-major
+/// * `c` - Matrix C (m × n), row-major, accumulated into (C += A * B)
+pub fn matmul_blocked_transposed(
+    a: &[f64],
+    bt: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) {
+    assert_eq!(a.len(), m * k, "A: expected {}x{}={} elements", m, k, m * k);
+    assert_eq!(
+        bt.len(),
+        n * k,
+        "Bt: expected {}x{}={} elements",
+        n,
+        k,
+        n * k
+    );
+    assert_eq!(c.len(), m * n, "C: expected {}x{}={} elements", m, n, m * n);
+
+    for pp in (0..k).step_by(KC) {
+        let p_end = (pp + KC).min(k);
+
+        for ii in (0..m).step_by(MC) {
+            let i_end = (ii + MC).min(m);
+
+            for jj in (0..n).step_by(NC) {
+                let j_end = (jj + NC).min(n);
+
+                for i in ii..i_end {
+                    let a_row = &a[i * k + pp..i * k + p_end];
+                    for j in jj..j_end {
+                        let bt_row = &bt[j * k + pp..j * k + p_end];
+                        c[i * n + j] += dot(a_row, bt_row);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Dot product with four independent partial sums.
+fn dot(x: &[f64], y: &[f64]) -> f64 {
+    let mut sums = [0.0; 4];
+    let mut x_chunks = x.chunks_exact(4);
+    let mut y_chunks = y.chunks_exact(4);
+    for (xc, yc) in (&mut x_chunks).zip(&mut y_chunks) {
+        for l in 0..4 {
+            sums[l] += xc[l] * yc[l];
+        }
+    }
+
+    let mut total = (sums[0] + sums[1]) + (sums[2] + sums[3]);
+    for (xv, yv) in x_chunks.remainder().iter().zip(y_chunks.remainder()) {
+        total += xv * yv;
+    }
+    total
+}
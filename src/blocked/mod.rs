@@ -8,8 +8,23 @@
 //! - `gemm_4x4`: Uses 4×4 AVX2 kernel
 //! - `gemm_12x4`: Uses 12×4 AVX2 kernel (better throughput)
 //! - `gemm_8x8`: Uses 8×8 AVX-512 kernel
+//! - `gemm_scalar`: Portable 4×4 scalar kernel (fallback without SIMD)
+//! - `gemm_transposed`: Portable scalar GEMM for a pre-transposed B
+//! - `edge`: The drivers' leftover rows and columns, rounded like their tiles
+//! - `pack`: The A and B panel packing the drivers share
+//!
+//! The SIMD drivers (and `simple_simd`) only exist on x86_64; the scalar ones
+//! build everywhere.
 
+pub(crate) mod edge;
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_12x4;
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_4x4;
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_8x8;
+pub mod gemm_scalar;
+pub mod gemm_transposed;
+pub mod pack;
+#[cfg(target_arch = "x86_64")]
 pub mod simple_simd;
@@ -0,0 +1,96 @@
+//! Panel packing shared by the blocked drivers.
+//!
+//! Each driver copies a kc-deep block of A (and of Bᵀ) into the order its
+//! microkernel reads: for every k step, the `MR` row values (or `NR` column
+//! values) one kernel call needs sit next to each other. The tile height and
+//! width are const generics so the inner copy unrolls like a hand-written one.
+
+/// Packs rows `i_start..i_start + m_block` of A (row-major, `k_total`
+/// columns), depth `k_start..k_start + k_block`, in groups of `MR` rows: each
+/// k position's `MR` values are adjacent.
+///
+/// `m_block` must be a multiple of `MR`; the drivers leave leftover rows to
+/// their edge paths. Nothing is ever zero-padded: a padded row would multiply
+/// an Inf in B by 0 and put a NaN the reference never computes into the
+/// tile (harmless only as long as that row is discarded).
+pub fn pack_a_panel<const MR: usize>(
+    a: &[f64],
+    a_panel: &mut [f64],
+    i_start: usize,
+    k_start: usize,
+    m_block: usize,
+    k_block: usize,
+    k_total: usize,
+) {
+    for i_offset in (0..m_block).step_by(MR) {
+        for p in 0..k_block {
+            let out_base = i_offset * k_block + p * MR;
+            for r in 0..MR {
+                a_panel[out_base + r] = a[(i_start + i_offset + r) * k_total + k_start + p];
+            }
+        }
+    }
+}
+
+/// Packs `NR` columns of B starting at `j_start`, read as rows of `bt` (Bᵀ,
+/// `k_total` columns), over depth `k_start..k_start + k_block`: each k
+/// position's `NR` values are adjacent.
+pub fn pack_b_panel<const NR: usize>(
+    bt: &[f64],
+    b_pack: &mut [f64],
+    j_start: usize,
+    k_start: usize,
+    k_block: usize,
+    k_total: usize,
+) {
+    for p in 0..k_block {
+        for col in 0..NR {
+            b_pack[p * NR + col] = bt[(j_start + col) * k_total + k_start + p];
+        }
+    }
+}
+
+/// [`pack_b_panel`] reading B itself (row-major, `n` columns) rather than
+/// Bᵀ: the same panel, for a driver that skips the k×n transpose to save
+/// memory. Each k position's `NR` values are already adjacent in B's row.
+pub fn pack_b_panel_direct<const NR: usize>(
+    b: &[f64],
+    b_pack: &mut [f64],
+    j_start: usize,
+    k_start: usize,
+    k_block: usize,
+    n: usize,
+) {
+    for p in 0..k_block {
+        b_pack[p * NR..][..NR].copy_from_slice(&b[(k_start + p) * n + j_start..][..NR]);
+    }
+}
+
+/// [`pack_b_panel`] for a symmetric B (n×n) of which only the lower triangle
+/// is stored, row-major: B's column j is read down row j as far as the
+/// diagonal and down column j below it, so the upper triangle is never
+/// touched.
+pub fn pack_b_panel_symmetric<const NR: usize>(
+    b_lower: &[f64],
+    b_pack: &mut [f64],
+    j_start: usize,
+    k_start: usize,
+    k_block: usize,
+    n: usize,
+) {
+    for p in 0..k_block {
+        for col in 0..NR {
+            b_pack[p * NR + col] = symmetric_lower(b_lower, n, k_start + p, j_start + col);
+        }
+    }
+}
+
+/// B(i, j) of a symmetric n×n B stored as its lower triangle.
+#[inline(always)]
+pub(crate) fn symmetric_lower(b_lower: &[f64], n: usize, i: usize, j: usize) -> f64 {
+    if i >= j {
+        b_lower[i * n + j]
+    } else {
+        b_lower[j * n + i]
+    }
+}
@@ -1,11 +1,44 @@
 //! Simple SIMD matmul without cache blocking.
 //!
 //! This was an early experiment - it uses SIMD but doesn't pack matrices
-//! or block for cache. Kept for comparison/educational purposes.
+//! or block for cache. Kept for comparison/educational purposes, and as the
+//! no-packing path for matrices small enough to stay in cache anyway.
+//!
+//! It rounds like the SIMD backends (see [`Backend`](crate::Backend)): C's
+//! starting value plus one fused multiply-add per k step, tiles and edges
+//! alike, so its results match theirs bit for bit.
 
+use super::edge::multiply_edge;
+use crate::Unsupported;
 use std::arch::x86_64::*;
 
-/// Simple 4×4 SIMD matmul without packing or blocking.
+/// C += A·B with [`matmul_simple_simd`], checking for AVX2 and FMA first.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU lacks AVX2 or FMA, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn run(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Result<(), Unsupported> {
+    crate::error::assert_dims(a, b, c, m, n, k);
+    crate::features::require_avx2("matmul_simple_simd")?;
+    unsafe { matmul_simple_simd(a, b, c, m, n, k) };
+    Ok(())
+}
+
+/// Simple 4×4 SIMD matmul without packing or blocking: C += A·B.
+///
+/// Like every other path, it adds to C rather than overwriting it; zero C
+/// first for a plain product.
 ///
 /// Demonstrates basic AVX2 usage but doesn't achieve good performance
 /// on large matrices due to poor cache behavior. Use `gemm_4x4` or
@@ -13,7 +46,8 @@ use std::arch::x86_64::*;
 ///
 /// # Safety
 ///
-/// Caller must ensure CPU supports AVX2 and FMA.
+/// Caller must ensure CPU supports AVX2 and FMA, and that the slice lengths
+/// match m, n, k; [`run`] checks both.
 #[target_feature(enable = "avx2,fma")]
 #[allow(clippy::missing_safety_doc)]
 #[allow(unsafe_op_in_unsafe_fn)]
@@ -30,10 +64,12 @@ pub unsafe fn matmul_simple_simd(
 
     for i in (0..m_main).step_by(4) {
         for j in (0..n_main).step_by(4) {
-            let mut c0 = _mm256_setzero_pd();
-            let mut c1 = _mm256_setzero_pd();
-            let mut c2 = _mm256_setzero_pd();
-            let mut c3 = _mm256_setzero_pd();
+            // Start from C, so each element is one fused chain over k, as in
+            // the blocked drivers
+            let mut c0 = _mm256_loadu_pd(c.as_ptr().add(i * n + j));
+            let mut c1 = _mm256_loadu_pd(c.as_ptr().add((i + 1) * n + j));
+            let mut c2 = _mm256_loadu_pd(c.as_ptr().add((i + 2) * n + j));
+            let mut c3 = _mm256_loadu_pd(c.as_ptr().add((i + 3) * n + j));
 
             for p in 0..k {
                 let b_vec = _mm256_loadu_pd(b.as_ptr().add(p * n + j));
@@ -49,40 +85,16 @@ pub unsafe fn matmul_simple_simd(
                 c3 = _mm256_fmadd_pd(a3, b_vec, c3);
             }
 
-            let c0_orig = _mm256_loadu_pd(c.as_ptr().add(i * n + j));
-            let c1_orig = _mm256_loadu_pd(c.as_ptr().add((i + 1) * n + j));
-            let c2_orig = _mm256_loadu_pd(c.as_ptr().add((i + 2) * n + j));
-            let c3_orig = _mm256_loadu_pd(c.as_ptr().add((i + 3) * n + j));
-
-            _mm256_storeu_pd(c.as_mut_ptr().add(i * n + j), _mm256_add_pd(c0, c0_orig));
-            _mm256_storeu_pd(
-                c.as_mut_ptr().add((i + 1) * n + j),
-                _mm256_add_pd(c1, c1_orig),
-            );
-            _mm256_storeu_pd(
-                c.as_mut_ptr().add((i + 2) * n + j),
-                _mm256_add_pd(c2, c2_orig),
-            );
-            _mm256_storeu_pd(
-                c.as_mut_ptr().add((i + 3) * n + j),
-                _mm256_add_pd(c3, c3_orig),
-            );
+            _mm256_storeu_pd(c.as_mut_ptr().add(i * n + j), c0);
+            _mm256_storeu_pd(c.as_mut_ptr().add((i + 1) * n + j), c1);
+            _mm256_storeu_pd(c.as_mut_ptr().add((i + 2) * n + j), c2);
+            _mm256_storeu_pd(c.as_mut_ptr().add((i + 3) * n + j), c3);
         }
     }
 
-    for i in m_main..m {
-        for p in 0..k {
-            for j in 0..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
-        }
-    }
-
-    for i in 0..m_main {
-        for p in 0..k {
-            for j in n_main..n {
-                c[i * n + j] += a[i * k + p] * b[p * n + j];
-            }
-        }
-    }
+    // The leftover columns of the tiled rows, then the leftover rows in
+    // full: the two never share an element, so the bottom-right corner is
+    // done once, by the second
+    multiply_edge(a, b, c, 0, m_main, n_main..n, n, k, false);
+    multiply_edge(a, b, &mut c[m_main * n..], m_main, m, 0..n, n, k, false);
 }
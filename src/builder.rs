@@ -0,0 +1,1286 @@
+//! A configured, reusable multiply: [`MatMul`] and its builder.
+//!
+//! The free functions cover one setting each (`multiply_with_backend`,
+//! `multiply_parallel`, ...). [`MatMul`] takes all of them at once, checks
+//! them once in [`MatMulBuilder::build`], and keeps a [`Workspace`] between
+//! calls, so repeated single-threaded multiplies don't allocate.
+//!
+//! ```
+//! use matmul::{Backend, MatMul, Tuning};
+//!
+//! let (m, n, k) = (64, 48, 32);
+//! let a = vec![1.0; m * k];
+//! let b = vec![1.0; k * n];
+//! let mut c = vec![1.0; m * n];
+//!
+//! let mut matmul = MatMul::new()
+//!     .threads(4)
+//!     .backend(Backend::Scalar)
+//!     .alpha(2.0)
+//!     .beta(0.5)
+//!     .tuning(Tuning { kc: Some(128), mc: None })
+//!     .build()?;
+//! matmul.compute_into(&a, &b, &mut c, m, n, k)?; // C = 2·A·B + 0.5·C
+//! assert!(c.iter().all(|&x| x == 64.5));
+//! # Ok::<(), matmul::MatMulError>(())
+//! ```
+
+use crate::accumulation::{Accumulation, run_rows_pairwise};
+use crate::backend::GemmBackend;
+use crate::epilogue::{Activation, Epilogue};
+use crate::error::{check_dims, check_no_overlap, check_operands};
+use crate::matrix::transpose::transpose;
+use crate::matrix::{elementwise, low_rank};
+use crate::nested::Flattened;
+use crate::plan::Partition;
+use crate::threaded::schedule::{BandError, Schedule, for_each_band};
+use crate::threaded::{self, naive_ikj_mt};
+use crate::workspace::{Workspace, transpose_into_workspace, with_scratch};
+use crate::{Backend, MatMulError, blocked, detected_backend};
+use std::sync::Arc;
+
+/// Block sizes for the blocked backends; `None` keeps the backend's own.
+///
+/// The i-k-j backend doesn't block, and ignores both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tuning {
+    /// Depth of a packed panel (KC): how much of k one pass over C covers
+    pub kc: Option<usize>,
+    /// Rows of A per packed panel (MC), rounded down to whole kernel tiles
+    /// (at least one)
+    pub mc: Option<usize>,
+}
+
+impl Tuning {
+    /// `self`, with each size it leaves `None` taken from `outer`.
+    pub(crate) fn or(self, outer: Tuning) -> Tuning {
+        Tuning {
+            kc: self.kc.or(outer.kc),
+            mc: self.mc.or(outer.mc),
+        }
+    }
+}
+
+/// How a [`MatMul`] lays out C, set with [`MatMulBuilder::output`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputLayout {
+    /// C row-major, m×n, like every other entry point
+    #[default]
+    RowMajor,
+    /// Cᵀ row-major, n×m: C in column-major order, for a consumer that
+    /// wants it that way. The SIMD backends compute it as Bᵀ·Aᵀ, whose
+    /// tiles are Cᵀ's, so it's stored in place with no pass over C
+    /// afterwards, bit for bit C's values transposed. The scalar and i-k-j
+    /// backends, the low-rank path, and pairwise sums multiply into a
+    /// row-major copy and transpose that.
+    Transposed,
+}
+
+/// Settings for a [`MatMul`], from [`MatMul::new`].
+///
+/// The defaults are what [`multiply`](crate::multiply) does: one thread,
+/// the detected backend, its own block sizes, and C += A·B (alpha = beta =
+/// 1).
+#[derive(Debug, Clone)]
+pub struct MatMulBuilder {
+    threads: usize,
+    backend: Option<Backend>,
+    alpha: f64,
+    beta: f64,
+    tuning: Tuning,
+    accumulation: Accumulation,
+    max_workspace: Option<usize>,
+    bias: Option<Vec<f64>>,
+    activation: Option<Activation>,
+    output: OutputLayout,
+}
+
+impl MatMulBuilder {
+    /// Most threads to use (at least 1). Like
+    /// [`multiply_parallel`](crate::multiply_parallel), small products use
+    /// fewer.
+    pub fn threads(mut self, threads: usize) -> MatMulBuilder {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Runs on `backend` instead of the detected one.
+    pub fn backend(mut self, backend: Backend) -> MatMulBuilder {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Scales A·B: C = alpha·A·B + beta·C.
+    pub fn alpha(mut self, alpha: f64) -> MatMulBuilder {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Scales C before the product is added. With beta = 0, C's contents
+    /// are ignored: the kernels store their first k block rather than
+    /// adding it, so C is written without ever being read, and may hold
+    /// anything (NaN, say) beforehand.
+    pub fn beta(mut self, beta: f64) -> MatMulBuilder {
+        self.beta = beta;
+        self
+    }
+
+    /// Overrides the backend's block sizes, and the
+    /// [config](crate::config)'s.
+    pub fn tuning(mut self, tuning: Tuning) -> MatMulBuilder {
+        self.tuning = tuning;
+        self
+    }
+
+    /// How each element of C sums its k products: left to right (the
+    /// default), or [pairwise](Accumulation::Pairwise) across k blocks, for
+    /// a smaller error bound at some cost in speed.
+    pub fn accumulation(mut self, accumulation: Accumulation) -> MatMulBuilder {
+        self.accumulation = accumulation;
+        self
+    }
+
+    /// Caps the scratch buffers a multiply packs into at `bytes`, across all
+    /// its threads. A multiply that would need more packs B's panels
+    /// straight from B, without the k×n Bᵀ, if that fits, and otherwise
+    /// fails with [`MatMulError::OutOfMemory`] before touching C.
+    ///
+    /// Without a cap, only a failed allocation is an error; the
+    /// single-threaded calls then fall back the same way.
+    pub fn max_workspace(mut self, bytes: usize) -> MatMulBuilder {
+        self.max_workspace = Some(bytes);
+        self
+    }
+
+    /// Adds `bias[j]` to every element of column j of the result, once it's
+    /// complete: C = alpha·A·B + beta·C + bias, with a bias per column of C.
+    /// Each tile gets it as the kernels finish the tile, rather than in a
+    /// pass over C afterwards; see [`epilogue`](crate::epilogue).
+    ///
+    /// Every multiply must then have n = `bias.len()`, or it fails with
+    /// [`MatMulError::WrongLength`].
+    pub fn bias(mut self, bias: Vec<f64>) -> MatMulBuilder {
+        self.bias = Some(bias);
+        self
+    }
+
+    /// Applies `activation` to every element of the result, after the
+    /// [`bias`](MatMulBuilder::bias): C = act(alpha·A·B + beta·C + bias),
+    /// fused into the multiply the same way.
+    pub fn activation(mut self, activation: Activation) -> MatMulBuilder {
+        self.activation = Some(activation);
+        self
+    }
+
+    /// Writes C as Cᵀ (n×m row-major) with [`OutputLayout::Transposed`]:
+    /// C is then read and written that way, for beta too, and the slice
+    /// lengths are the same. Not with a [`bias`](MatMulBuilder::bias),
+    /// whose columns would be Cᵀ's rows.
+    pub fn output(mut self, output: OutputLayout) -> MatMulBuilder {
+        self.output = output;
+        self
+    }
+
+    /// Checks the settings and resolves the backend and block sizes.
+    ///
+    /// # Errors
+    ///
+    /// - [`MatMulError::Backend`] if this CPU can't run the chosen backend
+    /// - [`MatMulError::InvalidConfig`] for a block size of 0, or for a
+    ///   bias with a transposed output
+    pub fn build(self) -> Result<MatMul, MatMulError> {
+        let backend = self.backend.unwrap_or_else(detected_backend);
+        if !backend.is_available() {
+            return Err(MatMulError::Backend {
+                backend,
+                reason: "not available on this CPU",
+            });
+        }
+        if self.tuning.kc == Some(0) || self.tuning.mc == Some(0) {
+            return Err(MatMulError::InvalidConfig {
+                reason: "block sizes must be at least 1",
+            });
+        }
+        if self.bias.is_some() && self.output == OutputLayout::Transposed {
+            return Err(MatMulError::InvalidConfig {
+                reason: "a bias needs a row-major output",
+            });
+        }
+        // Any k works for the defaults: it only caps kc
+        let (kc, mc) = backend.block_sizes(usize::MAX);
+        let (mr, _) = backend.tile();
+        let tuning = self.tuning.or(crate::config::current().tuning);
+        Ok(MatMul {
+            backend,
+            threads: self.threads,
+            alpha: self.alpha,
+            beta: self.beta,
+            kc: tuning.kc.unwrap_or(kc),
+            mc: tuning.mc.map_or(mc, |mc| (mc / mr).max(1) * mr),
+            workspace: Workspace::new(),
+            product: Vec::new(),
+            nested: Flattened::default(),
+            accumulation: self.accumulation,
+            max_workspace: self.max_workspace,
+            epilogue: (self.bias.is_some() || self.activation.is_some()).then(|| {
+                Arc::new(Epilogue {
+                    bias: self.bias,
+                    activation: self.activation,
+                })
+            }),
+            output: self.output,
+            transposed: Vec::new(),
+        })
+    }
+}
+
+/// A multiply with its settings fixed: C = alpha·A·B + beta·C on one backend,
+/// with up to a given number of threads.
+///
+/// Keeps its scratch buffers (and, with alpha ≠ 1, a buffer for A·B) from
+/// one call to the next. Single-threaded calls reuse them, so after the
+/// first call of a given size they don't allocate; threaded calls pack into
+/// each worker's own, as [`multiply_parallel`](crate::multiply_parallel)
+/// does.
+///
+/// With the default settings, results are bit for bit those of
+/// [`multiply`](crate::multiply). A [`bias`](MatMulBuilder::bias) or
+/// [`activation`](MatMulBuilder::activation) is applied to each result
+/// element last.
+#[derive(Debug, Clone)]
+pub struct MatMul {
+    backend: Backend,
+    threads: usize,
+    pub(crate) alpha: f64,
+    pub(crate) beta: f64,
+    pub(crate) kc: usize,
+    pub(crate) mc: usize,
+    pub(crate) workspace: Workspace,
+    /// A·B, when alpha ≠ 1 means it can't go straight into C
+    pub(crate) product: Vec<f64>,
+    /// Flat copies for [`compute_nested`](MatMul::compute_nested)
+    pub(crate) nested: Flattened,
+    accumulation: Accumulation,
+    max_workspace: Option<usize>,
+    /// The bias and activation, if either is set
+    epilogue: Option<Arc<Epilogue>>,
+    output: OutputLayout,
+    /// Bᵀ, or a row-major copy of C, for a transposed output
+    transposed: Vec<f64>,
+}
+
+impl MatMul {
+    /// The default settings, to adjust and then [`build`](MatMulBuilder::build).
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> MatMulBuilder {
+        MatMulBuilder {
+            threads: 1,
+            backend: None,
+            alpha: 1.0,
+            beta: 1.0,
+            tuning: Tuning::default(),
+            accumulation: Accumulation::default(),
+            max_workspace: None,
+            bias: None,
+            activation: None,
+            output: OutputLayout::default(),
+        }
+    }
+
+    /// The backend every call runs on.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// `(kc, mc)`: the block sizes in effect, after rounding mc to whole
+    /// tiles. Each multiply uses a kc of at most its k.
+    pub fn block_sizes(&self) -> (usize, usize) {
+        (self.kc, self.mc)
+    }
+
+    /// The scratch buffers kept between calls.
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    /// alpha·A·B, in a new m×n matrix.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`try_multiply`](crate::try_multiply), for A and B and for
+    /// the size of C.
+    pub fn compute(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<Vec<f64>, MatMulError> {
+        check_operands([("A", a.len(), m, k), ("B", b.len(), k, n)])?;
+        let len = m.checked_mul(n).ok_or(MatMulError::Overflow {
+            operand: "C",
+            rows: m,
+            cols: n,
+        })?;
+        let mut c = vec![0.0; len];
+        // C is already 0, so there's nothing for beta to scale
+        self.run(a, b, &mut c, m, n, k, 1.0, None)?;
+        Ok(c)
+    }
+
+    /// C = alpha·A·B + beta·C.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`try_multiply`](crate::try_multiply). C is untouched on
+    /// error.
+    pub fn compute_into(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+    ) -> Result<(), MatMulError> {
+        self.run(a, b, c, m, n, k, self.beta, None)
+    }
+
+    /// C = alpha·A·B + beta·C, with beta given, on a fixed partition if
+    /// there is one.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn run(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        beta: f64,
+        partition: Option<&Partition>,
+    ) -> Result<(), MatMulError> {
+        check_dims(a, b, c, m, n, k)?;
+        check_no_overlap(a, b, c)?;
+        let _mode = crate::config::enter_denormal_mode();
+        if let Some(bias) = self.epilogue.as_ref().and_then(|e| e.bias.as_ref())
+            && bias.len() != n
+        {
+            return Err(MatMulError::WrongLength {
+                operand: "bias",
+                rows: 1,
+                cols: n,
+                len: bias.len(),
+            });
+        }
+        match self.output {
+            OutputLayout::RowMajor => {
+                let lean = !self.is_empty(m, n, k) && self.reserve(m, n, k, None)?;
+                self.run_checked(a, b, None, c, m, n, k, beta, partition, lean);
+                Ok(())
+            }
+            OutputLayout::Transposed => self.run_transposed(a, b, c, m, n, k, beta),
+        }
+    }
+
+    /// Whether there's no product to add: a zero dimension, or alpha = 0.
+    fn is_empty(&self, m: usize, n: usize, k: usize) -> bool {
+        m == 0 || n == 0 || k == 0 || self.alpha == 0.0
+    }
+
+    /// [`run`](Self::run) into a C laid out n×m, as Cᵀ, on no fixed
+    /// partition (a plan's is for C's rows, not Cᵀ's).
+    #[allow(clippy::too_many_arguments)]
+    fn run_transposed(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        beta: f64,
+    ) -> Result<(), MatMulError> {
+        let empty = self.is_empty(m, n, k);
+        let mut scratch = std::mem::take(&mut self.transposed);
+        scratch.clear();
+        if self.fuses_transpose(k) {
+            // Cᵀ = Bᵀ·Aᵀ: a row-major multiply the drivers run as it is, with
+            // Bᵀ on the left and A as the right side's transpose, so each
+            // tile lands in Cᵀ. A SIMD element is C's value plus one fused
+            // multiply-add per k step either way, so the bits are C's
+            let bt_bytes = k.saturating_mul(n).saturating_mul(size_of::<f64>());
+            let lean = match empty {
+                true => false,
+                false => match self.reserve(n, m, k, Some(bt_bytes)) {
+                    Ok(lean) => lean,
+                    Err(e) => {
+                        self.transposed = scratch;
+                        return Err(e);
+                    }
+                },
+            };
+            if !empty {
+                scratch.resize(k * n, 0.0);
+                transpose(b, &mut scratch, k, n);
+            }
+            self.run_checked(&scratch, &[], Some(a), c, n, m, k, beta, None, lean);
+        } else {
+            // Multiplied row-major and transposed back
+            let lean = match empty {
+                true => false,
+                false => match self.reserve(m, n, k, None) {
+                    Ok(lean) => lean,
+                    Err(e) => {
+                        self.transposed = scratch;
+                        return Err(e);
+                    }
+                },
+            };
+            scratch.resize(m * n, 0.0);
+            transpose(c, &mut scratch, n, m);
+            self.run_checked(a, b, None, &mut scratch, m, n, k, beta, None, lean);
+            transpose(&scratch, c, m, n);
+        }
+        self.transposed = scratch;
+        Ok(())
+    }
+
+    /// Whether a transposed output is computed as Bᵀ·Aᵀ, straight into Cᵀ:
+    /// on the SIMD drivers, summing left to right.
+    fn fuses_transpose(&self, k: usize) -> bool {
+        matches!(
+            self.backend,
+            Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8
+        ) && !low_rank::uses_low_rank(self.backend, k)
+            && self.accumulation == Accumulation::Sequential
+    }
+
+    /// C = alpha·A·B + beta·C for checked operands, with the scratch space
+    /// reserved (`lean` as [`reserve`](Self::reserve) says). With `bt`, B's
+    /// transpose, `b` may be empty: the drivers read B only through `bt`.
+    #[allow(clippy::too_many_arguments)]
+    fn run_checked(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        bt: Option<&[f64]>,
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        beta: f64,
+        partition: Option<&Partition>,
+        lean: bool,
+    ) {
+        let empty = self.is_empty(m, n, k);
+        // With beta = 0 and no alpha to apply, the drivers store the product
+        // rather than adding it, so C is written but never read
+        let overwrite = beta == 0.0 && self.alpha == 1.0 && !empty;
+        if beta == 0.0 && !overwrite {
+            // Overwrite rather than scale, so NaNs in C don't survive
+            c.fill(0.0);
+        } else if beta != 0.0 && beta != 1.0 {
+            elementwise::scale(c, beta);
+        }
+        if empty {
+            // Nothing to multiply, but C still gets its bias and activation
+            if let Some(epilogue) = &self.epilogue {
+                epilogue.apply(c, m, n, 0..n);
+            }
+            return;
+        }
+
+        if self.alpha == 1.0 {
+            self.accumulate(a, b, bt, c, m, n, k, partition, lean, overwrite, true);
+        } else {
+            let mut product = std::mem::take(&mut self.product);
+            product.clear();
+            product.resize(m * n, 0.0);
+            self.accumulate(
+                a,
+                b,
+                bt,
+                &mut product,
+                m,
+                n,
+                k,
+                partition,
+                lean,
+                false,
+                false,
+            );
+            // C isn't final until alpha·A·B is in, so the epilogue comes here
+            for (c_row, p_row) in c.chunks_exact_mut(n).zip(product.chunks_exact(n)) {
+                for (c, p) in c_row.iter_mut().zip(p_row) {
+                    *c += self.alpha * p;
+                }
+                if let Some(epilogue) = &self.epilogue {
+                    epilogue.apply(c_row, 1, n, 0..n);
+                }
+            }
+            self.product = product;
+        }
+    }
+
+    /// Checks the scratch space for an m×n×k multiply against
+    /// `max_workspace`, and single-threaded, allocates it in the
+    /// [`Workspace`]. Returns whether B has to be packed without its
+    /// transpose to fit.
+    fn reserve(
+        &mut self,
+        m: usize,
+        n: usize,
+        k: usize,
+        given_bt: Option<usize>,
+    ) -> Result<bool, MatMulError> {
+        let threads = self.thread_count(m, n, k);
+        let blocking = (self.kc.min(k), self.mc);
+        // Every band packs at most what all m rows would
+        let bytes = |lean| {
+            let bytes = if self.shares_transpose(k, n) {
+                Workspace::bytes_for_bands(self.backend, m, n, k, blocking, lean, threads)
+            } else {
+                Workspace::bytes_for(self.backend, m, n, k, blocking, lean).saturating_mul(threads)
+            };
+            bytes.saturating_add(given_bt.unwrap_or(0))
+        };
+        // Packing from a Bᵀ the caller made is as lean as it gets
+        let mut lean = given_bt.is_some();
+        if let Some(max) = self.max_workspace
+            && bytes(lean) > max
+        {
+            if bytes(true) > max {
+                return Err(MatMulError::OutOfMemory {
+                    requested_bytes: bytes(true),
+                });
+            }
+            lean = true;
+        }
+        if threads == 1 {
+            lean = self
+                .workspace
+                .try_reserve_or_lean(self.backend, m, n, k, blocking, lean)?;
+        }
+        Ok(lean)
+    }
+
+    /// How many threads a multiply of this shape splits across.
+    pub(crate) fn thread_count(&self, m: usize, n: usize, k: usize) -> usize {
+        threaded::thread_count(self.backend, m, n, k, self.threads)
+    }
+
+    /// Whether the bands of a threaded k-deep multiply pack from one Bᵀ in
+    /// this `MatMul`'s workspace. Pairwise bands multiply a block of B's
+    /// rows at a time, and transpose each block themselves.
+    pub(crate) fn shares_transpose(&self, k: usize, n: usize) -> bool {
+        self.accumulation == Accumulation::Sequential && self.backend.uses_transpose(k, n)
+    }
+
+    /// C += A·B on the configured backend and threads, for checked inputs
+    /// with no zero dimension. A partition fixes the bands and their
+    /// workspaces; without one, the thread count is decided here. `lean`
+    /// packs B without transposing it, in every band; `overwrite` sets C to
+    /// A·B without reading it; `finish` applies the bias and activation, if
+    /// any, as each tile is finished.
+    #[allow(clippy::too_many_arguments)]
+    fn accumulate(
+        &mut self,
+        a: &[f64],
+        b: &[f64],
+        bt: Option<&[f64]>,
+        c: &mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        partition: Option<&Partition>,
+        lean: bool,
+        overwrite: bool,
+        finish: bool,
+    ) {
+        let backend = self.backend;
+        let epilogue = self.epilogue.clone().filter(|_| finish);
+        let (kc, mc) = (self.kc, self.mc);
+        let run_rows = match self.accumulation {
+            Accumulation::Sequential => run_rows,
+            Accumulation::Pairwise => run_rows_pairwise,
+        };
+        let threads = partition.map_or_else(|| self.thread_count(m, n, k), Partition::threads);
+        #[cfg(feature = "tracing")]
+        crate::backend::trace_dispatch(backend, threads, m, n, k);
+
+        if threads == 1 {
+            self.workspace.lean = lean;
+            self.workspace.overwrite = overwrite;
+            self.workspace.epilogue = epilogue;
+            // Safety: `build` checked the backend, and the caller the shapes
+            unsafe {
+                match bt {
+                    Some(bt) => run_rows_transposed(
+                        backend,
+                        a,
+                        b,
+                        Some(bt),
+                        c,
+                        m,
+                        n,
+                        k,
+                        None,
+                        None,
+                        kc,
+                        mc,
+                        &mut self.workspace,
+                    ),
+                    None => run_rows(
+                        backend,
+                        a,
+                        b,
+                        c,
+                        m,
+                        n,
+                        k,
+                        None,
+                        None,
+                        kc,
+                        mc,
+                        &mut self.workspace,
+                    ),
+                }
+            };
+            self.workspace.lean = false;
+            self.workspace.overwrite = false;
+            self.workspace.epilogue = None;
+            return;
+        }
+        let bt = match bt {
+            Some(bt) => Some(bt),
+            None if !lean && self.shares_transpose(k, n) => {
+                transpose_into_workspace(b, k, n, &mut self.workspace)
+            }
+            None => None,
+        };
+        let (mr, _) = backend.tile();
+        let schedule = match partition {
+            Some(_) => Schedule::Static,
+            None => crate::config::current().schedule(),
+        };
+        let bands = for_each_band(
+            c,
+            m,
+            n,
+            mr,
+            threads,
+            schedule,
+            None,
+            |start, end, c_band| {
+                let mut run = |workspace: &mut Workspace| {
+                    workspace.lean = lean;
+                    workspace.overwrite = overwrite;
+                    workspace.epilogue = epilogue.clone();
+                    let (start, end) = (Some(start), Some(end));
+                    match bt {
+                        Some(bt) => unsafe {
+                            run_rows_transposed(
+                                backend,
+                                a,
+                                b,
+                                Some(bt),
+                                c_band,
+                                m,
+                                n,
+                                k,
+                                start,
+                                end,
+                                kc,
+                                mc,
+                                workspace,
+                            )
+                        },
+                        None => unsafe {
+                            run_rows(
+                                backend, a, b, c_band, m, n, k, start, end, kc, mc, workspace,
+                            )
+                        },
+                    }
+                    workspace.lean = false;
+                    workspace.overwrite = false;
+                    workspace.epilogue = None;
+                };
+                match partition {
+                    Some(partition) => run(&mut partition.workspace(start)),
+                    // The worker's own cache, reused for each chunk it takes
+                    None => with_scratch(run),
+                }
+            },
+        );
+        match bands {
+            Ok(()) => {}
+            Err(BandError::Panicked(err)) => panic!("{}", err),
+            Err(BandError::Cancelled) => unreachable!("no cancel flag was given"),
+        }
+    }
+}
+
+/// C += A·B over rows `row_start..row_end` (all of C without a range; `c`
+/// holds just those rows) on `backend`, with the given blocking and scratch
+/// buffers. For k up to [`LOW_RANK_MAX_K`](low_rank::LOW_RANK_MAX_K) there's
+/// nothing to block, and it takes the low-rank path instead.
+///
+/// # Safety
+///
+/// `backend` must be available on this CPU, the slices must match m, n, k,
+/// and `mc` must be a multiple of the backend's kernel height.
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn run_rows(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+    kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
+) {
+    unsafe {
+        run_rows_transposed(
+            backend, a, b, None, c, m, n, k, row_start, row_end, kc, mc, workspace,
+        )
+    }
+}
+
+/// [`run_rows`], packing B's panels from `bt`, all of B transposed (n×k), if
+/// it's given, rather than making Bᵀ in `workspace`. The bands of a threaded
+/// multiply share one.
+///
+/// # Safety
+///
+/// Same as [`run_rows`].
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn run_rows_transposed(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    bt: Option<&[f64]>,
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+    kc: usize,
+    mc: usize,
+    workspace: &mut Workspace,
+) {
+    // Neither path below has a store-only first step; zeroing C is a
+    // write, so its contents are still never read
+    let unblocked = low_rank::uses_low_rank(backend, k) || backend == Backend::ScalarIkj;
+    if unblocked && workspace.overwrite {
+        c.fill(0.0);
+    }
+    // Too little arithmetic per element for packing to pay off
+    if low_rank::uses_low_rank(backend, k) {
+        let rows = row_start.unwrap_or(0)..row_end.unwrap_or(m);
+        unsafe { low_rank::low_rank_rows(backend, a, b, c, n, k, rows.clone()) };
+        // No tiles to finish one at a time: the band is done now
+        if let Some(epilogue) = &workspace.epilogue {
+            epilogue.apply(c, rows.len(), n, 0..n);
+        }
+        return;
+    }
+    match backend {
+        Backend::Scalar => blocked::gemm_scalar::matmul_blocked_scalar_with(
+            a, b, bt, c, m, n, k, row_start, row_end, kc, mc, workspace,
+        ),
+        Backend::ScalarIkj => {
+            naive_ikj_mt::matmul_naive_ikj_rows(a, b, c, m, n, k, row_start, row_end);
+            if let Some(epilogue) = &workspace.epilogue {
+                let rows = row_end.unwrap_or(m) - row_start.unwrap_or(0);
+                epilogue.apply(c, rows, n, 0..n);
+            }
+        }
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2_4x4 => unsafe {
+            blocked::gemm_4x4::matmul_blocked_4x4_with(
+                a, b, bt, c, m, n, k, row_start, row_end, kc, mc, workspace,
+            )
+        },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx2_12x4 => unsafe {
+            blocked::gemm_12x4::matmul_blocked_12x4_with(
+                a, b, bt, c, m, n, k, row_start, row_end, None, None, kc, mc, workspace,
+            )
+        },
+        #[cfg(target_arch = "x86_64")]
+        Backend::Avx512_8x8 => unsafe {
+            blocked::gemm_8x8::matmul_blocked_8x8_with(
+                a, b, bt, c, m, n, k, row_start, row_end, None, None, kc, mc, workspace,
+            )
+        },
+        #[cfg(not(target_arch = "x86_64"))]
+        Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8 => {
+            unreachable!("{} only exists on x86_64", backend)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::{available_backends, matmul_naive_ikj, multiply, multiply_with_backend};
+
+    /// Edge rows and columns for every kernel, and (too slow for Miri) more
+    /// than one k block.
+    const SHAPES: [(usize, usize, usize); 3] = [
+        (13, 9, 5),
+        (1, 1, 1),
+        if cfg!(miri) {
+            (25, 17, 9)
+        } else {
+            (29, 19, 300)
+        },
+    ];
+
+    fn inputs(m: usize, n: usize, k: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        (random(m, k, 1), random(k, n, 2), random(m, n, 3))
+    }
+
+    #[test]
+    fn test_defaults_match_multiply() {
+        let mut matmul = MatMul::new().build().unwrap();
+        assert_eq!(matmul.backend(), detected_backend());
+        for (m, n, k) in SHAPES {
+            let (a, b, c0) = inputs(m, n, k);
+            let mut want = c0.clone();
+            multiply(&a, &b, &mut want, m, n, k);
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_eq!(c, want, "{}x{}x{}", m, n, k);
+
+            let mut want = vec![0.0; m * n];
+            multiply(&a, &b, &mut want, m, n, k);
+            assert_eq!(matmul.compute(&a, &b, m, n, k).unwrap(), want);
+        }
+    }
+
+    #[test]
+    fn test_backend() {
+        for backend in available_backends() {
+            let mut matmul = MatMul::new().backend(backend).build().unwrap();
+            for (m, n, k) in SHAPES {
+                let (a, b, c0) = inputs(m, n, k);
+                let mut want = c0.clone();
+                multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+                let mut c = c0.clone();
+                matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                assert_eq!(c, want, "{} on {}x{}x{}", backend, m, n, k);
+            }
+        }
+        for backend in Backend::ALL {
+            if !backend.is_available() {
+                assert!(matches!(
+                    MatMul::new().backend(backend).build(),
+                    Err(MatMulError::Backend { .. })
+                ));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; tests/miri.rs covers the bands")]
+    fn test_threads() {
+        // Enough work for the thread heuristic to split it
+        let (m, n, k) = (515, 203, 517);
+        let (a, b, c0) = inputs(m, n, k);
+        for backend in available_backends() {
+            let mut want = c0.clone();
+            multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+            let mut matmul = MatMul::new().backend(backend).threads(3).build().unwrap();
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            // Rows are independent, so splitting them changes nothing
+            assert_eq!(c, want, "{}", backend);
+            // The bands packed from one Bᵀ, made here rather than in each
+            if backend != Backend::ScalarIkj {
+                assert_eq!(matmul.workspace().bytes(), k * n * 8, "{}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn test_alpha() {
+        let mut matmul = MatMul::new().alpha(-2.5).build().unwrap();
+        for (m, n, k) in SHAPES {
+            let (a, b, c0) = inputs(m, n, k);
+            let mut product = vec![0.0; m * n];
+            multiply(&a, &b, &mut product, m, n, k);
+            let want: Vec<f64> = c0.iter().zip(&product).map(|(c, p)| c + -2.5 * p).collect();
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_eq!(c, want);
+        }
+
+        // alpha = 0 leaves just beta·C, and doesn't read A or B's values
+        let mut matmul = MatMul::new().alpha(0.0).beta(2.0).build().unwrap();
+        let mut c = vec![1.5; 4];
+        matmul
+            .compute_into(&[f64::NAN; 4], &[f64::NAN; 4], &mut c, 2, 2, 2)
+            .unwrap();
+        assert_eq!(c, [3.0; 4]);
+    }
+
+    #[test]
+    fn test_beta() {
+        let (m, n, k) = SHAPES[0];
+        let (a, b, c0) = inputs(m, n, k);
+        let mut product = vec![0.0; m * n];
+        multiply(&a, &b, &mut product, m, n, k);
+
+        let mut matmul = MatMul::new().beta(0.5).build().unwrap();
+        let mut want: Vec<f64> = c0.iter().map(|c| c * 0.5).collect();
+        multiply(&a, &b, &mut want, m, n, k);
+        let mut c = c0.clone();
+        matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+        assert_eq!(c, want);
+
+        // beta = 0 overwrites C without reading it
+        let mut matmul = MatMul::new().beta(0.0).build().unwrap();
+        let mut c = vec![f64::NAN; m * n];
+        matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+        assert_eq!(c, product);
+        // compute ignores beta: there's no C to scale
+        assert_eq!(matmul.compute(&a, &b, m, n, k).unwrap(), product);
+    }
+
+    fn bits(c: &[f64]) -> Vec<u64> {
+        c.iter().map(|x| x.to_bits()).collect()
+    }
+
+    #[test]
+    fn test_beta_zero_ignores_c() {
+        // Short k blocks, so the kernels store the first and add the rest
+        let tuning = Tuning {
+            kc: Some(7),
+            mc: None,
+        };
+        for backend in available_backends() {
+            for accumulation in [Accumulation::Sequential, Accumulation::Pairwise] {
+                for tuning in [Tuning::default(), tuning] {
+                    let mut matmul = MatMul::new()
+                        .backend(backend)
+                        .accumulation(accumulation)
+                        .tuning(tuning)
+                        .beta(0.0)
+                        .build()
+                        .unwrap();
+                    for (m, n, k) in SHAPES.into_iter().chain([(6, 5, 0)]) {
+                        let (a, b, _) = inputs(m, n, k);
+                        // compute starts from a zeroed C
+                        let want = matmul.compute(&a, &b, m, n, k).unwrap();
+                        let mut c = vec![f64::NAN; m * n];
+                        matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                        assert_eq!(
+                            bits(&c),
+                            bits(&want),
+                            "{} {:?} {:?} on {}x{}x{}",
+                            backend,
+                            accumulation,
+                            tuning,
+                            m,
+                            n,
+                            k
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; tests/miri.rs covers the bands")]
+    fn test_beta_zero_ignores_c_in_bands() {
+        let (m, n, k) = (515, 203, 517);
+        let (a, b, _) = inputs(m, n, k);
+        for backend in available_backends() {
+            let mut matmul = MatMul::new()
+                .backend(backend)
+                .threads(3)
+                .beta(0.0)
+                .build()
+                .unwrap();
+            let want = matmul.compute(&a, &b, m, n, k).unwrap();
+            let mut c = vec![f64::NAN; m * n];
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_eq!(bits(&c), bits(&want), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_tuning() {
+        let tuning = Tuning {
+            kc: Some(7),
+            mc: Some(30),
+        };
+        for backend in available_backends() {
+            let mut matmul = MatMul::new()
+                .backend(backend)
+                .tuning(tuning)
+                .build()
+                .unwrap();
+            let (mr, _) = backend.tile();
+            if backend != Backend::ScalarIkj {
+                assert_eq!(matmul.block_sizes(), (7, (30 / mr).max(1) * mr));
+            }
+            for (m, n, k) in SHAPES {
+                let (a, b, c0) = inputs(m, n, k);
+                let mut want = c0.clone();
+                matmul_naive_ikj(&a, &b, &mut want, m, n, k);
+                let mut c = c0.clone();
+                matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                // Other blocks, so other rounding
+                assert_close(&want, &c, 1e-12, 1e-12);
+            }
+        }
+
+        let zero = Tuning {
+            kc: Some(0),
+            mc: None,
+        };
+        assert!(matches!(
+            MatMul::new().tuning(zero).build(),
+            Err(MatMulError::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_workspace_is_reused() {
+        let mut matmul = MatMul::new().backend(Backend::Scalar).build().unwrap();
+        assert_eq!(matmul.workspace().bytes(), 0);
+        let (m, n, k) = SHAPES[2];
+        let (a, b, mut c) = inputs(m, n, k);
+        matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+        let bytes = matmul.workspace().bytes();
+        assert!(bytes >= k * n * 8, "B alone needs {} bytes", k * n * 8);
+        // Same shape, then a smaller one: nothing new
+        matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+        let (a, b, mut c) = inputs(13, 9, 5);
+        matmul.compute_into(&a, &b, &mut c, 13, 9, 5).unwrap();
+        assert_eq!(matmul.workspace().bytes(), bytes);
+    }
+
+    #[test]
+    fn test_one_column_is_not_transposed() {
+        // A k×1 B is laid out like its transpose already
+        let (m, n, k) = (29, 1, SHAPES[2].2);
+        let (a, b, c0) = inputs(m, n, k);
+        for backend in available_backends() {
+            let blocking = backend.block_sizes(k);
+            let lean = Workspace::bytes_for(backend, m, n, k, blocking, true);
+            assert_eq!(
+                Workspace::bytes_for(backend, m, n, k, blocking, false),
+                lean
+            );
+
+            let mut want = c0.clone();
+            multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+            let mut matmul = MatMul::new().backend(backend).build().unwrap();
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_eq!(c, want, "{}", backend);
+            assert_eq!(matmul.workspace().bytes(), lean, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_max_workspace() {
+        let (m, n, k) = SHAPES[2];
+        let (a, b, c0) = inputs(m, n, k);
+        for backend in available_backends() {
+            let mut want = c0.clone();
+            multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+            let blocking = backend.block_sizes(k);
+            let lean = Workspace::bytes_for(backend, m, n, k, blocking, true);
+            if backend != Backend::ScalarIkj {
+                assert!(lean < Workspace::bytes_for(backend, m, n, k, blocking, false));
+            }
+
+            // Room for the panels but not Bᵀ: B is packed straight from B,
+            // which changes nothing in C
+            let mut matmul = MatMul::new()
+                .backend(backend)
+                .max_workspace(lean)
+                .build()
+                .unwrap();
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_eq!(c, want, "{}", backend);
+            assert!(matmul.workspace().bytes() <= lean);
+
+            // Not even that: an error, as if the allocation had failed
+            let mut matmul = MatMul::new()
+                .backend(backend)
+                .beta(0.0)
+                .max_workspace(8)
+                .build()
+                .unwrap();
+            let mut c = c0.clone();
+            let result = matmul.compute_into(&a, &b, &mut c, m, n, k);
+            if lean > 8 {
+                assert_eq!(
+                    result,
+                    Err(MatMulError::OutOfMemory {
+                        requested_bytes: lean
+                    })
+                );
+                assert_eq!(c, c0);
+            } else {
+                // The i-k-j loop packs nothing
+                assert_eq!(result, Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors_leave_c_alone() {
+        let mut matmul = MatMul::new().beta(0.0).build().unwrap();
+        let (a, b, _) = inputs(4, 4, 4);
+        let mut c = vec![7.0; 16];
+        assert!(matches!(
+            matmul.compute_into(&a[1..], &b, &mut c, 4, 4, 4),
+            Err(MatMulError::WrongLength { operand: "A", .. })
+        ));
+        assert_eq!(c, [7.0; 16]);
+        assert!(matches!(
+            matmul.compute(&[], &[], usize::MAX, 2, 0),
+            Err(MatMulError::Overflow { operand: "C", .. })
+        ));
+    }
+
+    #[test]
+    fn test_combined() {
+        let tuning = Tuning {
+            kc: Some(100),
+            mc: Some(50),
+        };
+        let mut matmul = MatMul::new()
+            .threads(2)
+            .backend(Backend::Scalar)
+            .alpha(1.5)
+            .beta(-0.25)
+            .tuning(tuning)
+            .build()
+            .unwrap();
+        for (m, n, k) in SHAPES {
+            let (a, b, c0) = inputs(m, n, k);
+            let mut product = vec![0.0; m * n];
+            matmul_naive_ikj(&a, &b, &mut product, m, n, k);
+            let want: Vec<f64> = c0
+                .iter()
+                .zip(&product)
+                .map(|(c, p)| 1.5 * p - 0.25 * c)
+                .collect();
+            let mut c = c0.clone();
+            matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+            assert_close(&want, &c, 1e-12, 1e-12);
+        }
+    }
+
+    /// Every backend, alpha and beta, pairwise sums, threads, and an
+    /// activation, against the row-major multiply transposed afterwards:
+    /// the same bits, fused or not. (21, 11, 3) takes the low-rank path.
+    #[test]
+    #[cfg_attr(miri, ignore = "hundreds of multiplies")]
+    fn test_transposed_output() {
+        let shapes = [SHAPES[0], SHAPES[1], SHAPES[2], (21, 11, 3), (6, 7, 0)];
+        for backend in available_backends() {
+            for (alpha, beta) in [(1.0, 1.0), (1.0, 0.0), (2.0, 0.5)] {
+                for accumulation in [Accumulation::Sequential, Accumulation::Pairwise] {
+                    for threads in [1, 3] {
+                        for activation in [None, Some(Activation::Relu)] {
+                            let mut builder = MatMul::new()
+                                .backend(backend)
+                                .alpha(alpha)
+                                .beta(beta)
+                                .accumulation(accumulation)
+                                .threads(threads)
+                                .tuning(Tuning {
+                                    kc: Some(64),
+                                    mc: None,
+                                });
+                            if let Some(activation) = activation {
+                                builder = builder.activation(activation);
+                            }
+                            let mut row_major = builder.clone().build().unwrap();
+                            let mut transposed =
+                                builder.output(OutputLayout::Transposed).build().unwrap();
+                            for (m, n, k) in shapes {
+                                let (a, b, c0) = inputs(m, n, k);
+                                let mut want = c0.clone();
+                                row_major.compute_into(&a, &b, &mut want, m, n, k).unwrap();
+                                let mut want_t = vec![0.0; m * n];
+                                transpose(&want, &mut want_t, m, n);
+
+                                let mut c = vec![0.0; m * n];
+                                transpose(&c0, &mut c, m, n);
+                                transposed.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                                assert!(
+                                    c.iter()
+                                        .zip(&want_t)
+                                        .all(|(x, y)| x.to_bits() == y.to_bits()),
+                                    "{} {}x{}x{}, alpha {}, beta {}, {:?}, {} threads, {:?}",
+                                    backend,
+                                    m,
+                                    n,
+                                    k,
+                                    alpha,
+                                    beta,
+                                    accumulation,
+                                    threads,
+                                    activation
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transposed_output_with_bias() {
+        assert_eq!(
+            MatMul::new()
+                .bias(vec![1.0; 4])
+                .output(OutputLayout::Transposed)
+                .build()
+                .unwrap_err(),
+            MatMulError::InvalidConfig {
+                reason: "a bias needs a row-major output"
+            }
+        );
+    }
+
+    /// Fused, Bᵀ counts toward the cap like the drivers' own copy would.
+    #[test]
+    fn test_transposed_output_max_workspace() {
+        let (m, n, k) = SHAPES[2];
+        let (a, b, c0) = inputs(m, n, k);
+        let mut matmul = MatMul::new()
+            .max_workspace(8)
+            .output(OutputLayout::Transposed)
+            .build()
+            .unwrap();
+        let mut c = c0.clone();
+        assert!(matches!(
+            matmul.compute_into(&a, &b, &mut c, m, n, k),
+            Err(MatMulError::OutOfMemory { .. })
+        ));
+        assert_eq!(c, c0);
+    }
+}
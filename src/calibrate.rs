@@ -0,0 +1,404 @@
+//! Picking backends by timing them on this machine rather than by CPU
+//! features.
+//!
+//! The static order (AVX-512 over AVX2 12×4 over 4×4) is right for most
+//! CPUs, but not all: Zen 4 runs 512-bit FMAs as two 256-bit halves, so its
+//! 8×8 kernel can lose to 12×4 at mid sizes, and on some Intel parts 12×4
+//! only beats 4×4 past a few hundred rows. [`calibrate`] times each
+//! available SIMD backend on squares of the [`SIZES`] and builds a
+//! [`DispatchTable`]: for each bucket of sizes, the backend that was fastest
+//! there. From then on [`multiply`](crate::multiply) and the other plain
+//! entry points use it, through [`backend_for`](crate::backend_for), with
+//! the [AVX-512 crossover](crate::downclock) applied on top. Without
+//! calibration the [cost model](crate::select) picks, as before.
+//!
+//! Calibrating takes a few hundred milliseconds, so a program that starts
+//! often can keep the table in a file: [`calibrate_with_file`] reads it if
+//! it's there and still fits the CPU, and measures and writes it otherwise.
+//! The table is per process and set once; it can also be built by hand and
+//! installed with [`use_dispatch_table`].
+//!
+//! The SIMD backends agree bit for bit (see [`Backend`]), so the table
+//! changes how long a multiply takes, never its result.
+
+use crate::backend::GemmBackend;
+use crate::matrix::generate::random;
+use crate::{Backend, MatMulError};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The square sizes [`DispatchTable::measure`] times. Each is the top of a
+/// bucket: a multiply whose largest dimension is at most 64 (and more than
+/// 32) runs on the backend that won at 64³. Past the last, the last
+/// winner applies.
+pub const SIZES: [usize; 5] = [32, 64, 128, 256, 512];
+
+/// How long [`DispatchTable::measure`] keeps repeating one backend at one
+/// size before taking its fastest run.
+const BUDGET: Duration = Duration::from_millis(20);
+
+/// First line of a saved table; a file without it isn't one.
+const HEADER: &str = "# matmul dispatch table v1";
+
+/// The process's table, once calibrated or installed.
+static TABLE: OnceLock<DispatchTable> = OnceLock::new();
+
+/// The backend to run for each bucket of sizes, by the largest of m, n, and
+/// k.
+///
+/// Saved as text: a `# matmul dispatch table v1` line, then one
+/// `<largest> <backend key>` line per bucket, smallest first, with `max` for
+/// the last.
+///
+/// ```
+/// use matmul::Backend;
+/// use matmul::calibrate::DispatchTable;
+///
+/// let table = DispatchTable::new(vec![
+///     (64, Backend::Avx2_4x4),
+///     (usize::MAX, Backend::Avx2_12x4),
+/// ])
+/// .unwrap();
+/// assert_eq!(table.backend_for(64, 10, 3), Backend::Avx2_4x4);
+/// assert_eq!(table.backend_for(65, 10, 3), Backend::Avx2_12x4);
+/// assert_eq!(DispatchTable::parse(&table.to_string()).unwrap(), table);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DispatchTable {
+    /// `(largest, backend)`, `largest` strictly increasing and the last
+    /// `usize::MAX`
+    buckets: Vec<(usize, Backend)>,
+}
+
+impl DispatchTable {
+    /// A table from `(largest, backend)` buckets: a multiply runs on the
+    /// first bucket whose `largest` is at least its largest dimension.
+    ///
+    /// # Errors
+    ///
+    /// [`MatMulError::InvalidConfig`] if there are no buckets, their sizes
+    /// aren't increasing, or the last isn't `usize::MAX` (so some size would
+    /// have no backend).
+    pub fn new(buckets: Vec<(usize, Backend)>) -> Result<DispatchTable, MatMulError> {
+        let reason = if buckets
+            .last()
+            .is_none_or(|&(largest, _)| largest != usize::MAX)
+        {
+            Some("the last bucket must cover usize::MAX")
+        } else if buckets.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            Some("bucket sizes must increase")
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => Err(MatMulError::InvalidConfig { reason }),
+            None => Ok(DispatchTable { buckets }),
+        }
+    }
+
+    /// Times each available SIMD backend (or, without SIMD, just picks
+    /// [`Backend::Scalar`]) on a square multiply of each of the [`SIZES`],
+    /// taking the fastest of as many runs as fit in 20 ms, and keeps the
+    /// winner for each bucket. Adjacent buckets with the same winner are
+    /// merged. Doesn't install the table; [`calibrate`] does.
+    pub fn measure() -> DispatchTable {
+        let candidates: Vec<Backend> = [Backend::Avx2_4x4, Backend::Avx2_12x4, Backend::Avx512_8x8]
+            .into_iter()
+            .filter(|backend| backend.is_available())
+            .collect();
+        if candidates.is_empty() {
+            return DispatchTable {
+                buckets: vec![(usize::MAX, Backend::Scalar)],
+            };
+        }
+
+        let mut buckets: Vec<(usize, Backend)> = Vec::new();
+        for (i, &size) in SIZES.iter().enumerate() {
+            let a = random(size, size, 1);
+            let b = random(size, size, 2);
+            let mut c = vec![0.0; size * size];
+            let mut fastest = |backend: Backend| {
+                let mut best = Duration::MAX;
+                let start = Instant::now();
+                // One untimed run to fill the caches, then at least three
+                // timed ones
+                for run in 0.. {
+                    let begin = Instant::now();
+                    // Safety: only available backends are candidates, and
+                    // the slices are size×size
+                    unsafe { backend.run(&a, &b, &mut c, size, size, size, 0..size) };
+                    if run > 0 {
+                        best = best.min(begin.elapsed());
+                    }
+                    if run >= 3 && start.elapsed() >= BUDGET {
+                        break;
+                    }
+                }
+                best
+            };
+            let winner = candidates
+                .iter()
+                .map(|&backend| (fastest(backend), backend))
+                .min_by_key(|&(time, _)| time)
+                .map(|(_, backend)| backend)
+                .expect("at least one candidate");
+            let largest = if i + 1 == SIZES.len() {
+                usize::MAX
+            } else {
+                size
+            };
+            match buckets.last_mut() {
+                Some(last) if last.1 == winner => last.0 = largest,
+                _ => buckets.push((largest, winner)),
+            }
+        }
+        DispatchTable { buckets }
+    }
+
+    /// The backend for an m×n×k multiply.
+    pub fn backend_for(&self, m: usize, n: usize, k: usize) -> Backend {
+        let largest = m.max(n).max(k);
+        self.buckets
+            .iter()
+            .find(|&&(top, _)| largest <= top)
+            .expect("the last bucket covers every size")
+            .1
+    }
+
+    /// The `(largest, backend)` buckets, smallest first; the last is
+    /// `usize::MAX`.
+    pub fn buckets(&self) -> &[(usize, Backend)] {
+        &self.buckets
+    }
+
+    /// Reads a table written by [`save`](DispatchTable::save).
+    ///
+    /// # Errors
+    ///
+    /// [`CalibrationError::Io`] if the file can't be read, and
+    /// [`parse`](DispatchTable::parse)'s errors for its contents.
+    pub fn load(path: impl AsRef<Path>) -> Result<DispatchTable, CalibrationError> {
+        DispatchTable::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Writes the table to `path` as text, replacing any file there.
+    ///
+    /// # Errors
+    ///
+    /// [`CalibrationError::Io`] if the file can't be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CalibrationError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Parses a saved table.
+    ///
+    /// # Errors
+    ///
+    /// [`CalibrationError::Format`] for anything but [`DispatchTable`]'s
+    /// format, and [`CalibrationError::Unavailable`] if a bucket names a
+    /// backend this CPU can't run (the file came from another machine).
+    pub fn parse(text: &str) -> Result<DispatchTable, CalibrationError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(CalibrationError::Format {
+                line: 1,
+                reason: format!("expected '{}'", HEADER),
+            });
+        }
+
+        let mut buckets = Vec::new();
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let format_error = |reason: String| CalibrationError::Format {
+                line: number,
+                reason,
+            };
+            let (largest, key) = line.split_once(' ').ok_or_else(|| {
+                format_error(format!("expected '<size> <backend>', got '{}'", line))
+            })?;
+            let largest = match largest {
+                "max" => usize::MAX,
+                size => size
+                    .parse()
+                    .map_err(|_| format_error(format!("'{}' isn't a size", size)))?,
+            };
+            let key = key.trim();
+            let backend = Backend::ALL
+                .into_iter()
+                .find(|backend| backend.key() == key)
+                .ok_or_else(|| format_error(format!("unknown backend '{}'", key)))?;
+            if !backend.is_available() {
+                return Err(CalibrationError::Unavailable(backend));
+            }
+            buckets.push((largest, backend));
+        }
+        DispatchTable::new(buckets).map_err(|e| CalibrationError::Format {
+            line: text.lines().count(),
+            reason: e.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for DispatchTable {
+    /// The saved form: the header line, then a line per bucket.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for &(largest, backend) in &self.buckets {
+            match largest {
+                usize::MAX => writeln!(f, "max {}", backend.key())?,
+                size => writeln!(f, "{} {}", size, backend.key())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a saved [`DispatchTable`] couldn't be read or written.
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(std::io::Error),
+    /// Not a dispatch table, or a malformed line in one
+    Format {
+        line: usize,
+        reason: String,
+    },
+    /// A backend this CPU can't run
+    Unavailable(Backend),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(e) => write!(f, "{}", e),
+            CalibrationError::Format { line, reason } => {
+                write!(f, "not a valid dispatch table: line {}: {}", line, reason)
+            }
+            CalibrationError::Unavailable(backend) => write!(
+                f,
+                "the table uses {}, which this CPU can't run; recalibrate",
+                backend
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CalibrationError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CalibrationError {
+    fn from(e: std::io::Error) -> Self {
+        CalibrationError::Io(e)
+    }
+}
+
+/// Measures a [`DispatchTable`] on the first call and installs it for the
+/// plain entry points; later calls return it. If a table is already
+/// installed, returns that one without measuring.
+pub fn calibrate() -> &'static DispatchTable {
+    TABLE.get_or_init(DispatchTable::measure)
+}
+
+/// [`calibrate`], keeping the table in `path`: loads it from there if the
+/// file holds a table this CPU can run, and otherwise measures one and
+/// writes it there. Returns the installed table; if one was installed
+/// before, that's it, and the file isn't touched.
+///
+/// # Errors
+///
+/// [`CalibrationError::Io`] if writing the measured table failed. It's
+/// installed regardless; only the next process will measure again.
+pub fn calibrate_with_file(
+    path: impl AsRef<Path>,
+) -> Result<&'static DispatchTable, CalibrationError> {
+    let path = path.as_ref();
+    let mut measured = false;
+    let table = TABLE.get_or_init(|| {
+        DispatchTable::load(path).unwrap_or_else(|_| {
+            measured = true;
+            DispatchTable::measure()
+        })
+    });
+    if measured {
+        table.save(path)?;
+    }
+    Ok(table)
+}
+
+/// Installs `table` for the plain entry points, as [`calibrate`] would the
+/// measured one.
+///
+/// # Errors
+///
+/// Gives `table` back if a table is already installed: it's set once per
+/// process.
+pub fn use_dispatch_table(table: DispatchTable) -> Result<(), DispatchTable> {
+    TABLE.set(table)
+}
+
+/// The installed table, if [`calibrate`] or [`use_dispatch_table`] has run.
+pub fn dispatch_table() -> Option<&'static DispatchTable> {
+    TABLE.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_rejects_gaps() {
+        assert!(DispatchTable::new(vec![]).is_err());
+        assert!(DispatchTable::new(vec![(64, Backend::Scalar)]).is_err());
+        assert!(
+            DispatchTable::new(vec![
+                (64, Backend::Scalar),
+                (32, Backend::Scalar),
+                (usize::MAX, Backend::Scalar)
+            ])
+            .is_err()
+        );
+        let table = DispatchTable::new(vec![
+            (64, Backend::ScalarIkj),
+            (usize::MAX, Backend::Scalar),
+        ])
+        .unwrap();
+        assert_eq!(table.backend_for(0, 0, 0), Backend::ScalarIkj);
+        assert_eq!(table.backend_for(1, 1, 65), Backend::Scalar);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let format_line = |text: &str| match DispatchTable::parse(text) {
+            Err(CalibrationError::Format { line, .. }) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(format_line(""), 1);
+        assert_eq!(format_line("64 4x4scalar\n"), 1);
+        assert_eq!(format_line(&format!("{}\n64\n", HEADER)), 2);
+        assert_eq!(format_line(&format!("{}\nsixty 4x4scalar\n", HEADER)), 2);
+        assert_eq!(
+            format_line(&format!("{}\n64 4x4scalar\nmax 9x9\n", HEADER)),
+            3
+        );
+        // Parses, but leaves sizes past 64 without a backend
+        assert_eq!(format_line(&format!("{}\n64 4x4scalar\n", HEADER)), 2);
+
+        let table =
+            DispatchTable::parse(&format!("{}\n\n64 ikj\nmax 4x4scalar\n", HEADER)).unwrap();
+        assert_eq!(
+            table.buckets(),
+            [(64, Backend::ScalarIkj), (usize::MAX, Backend::Scalar)]
+        );
+    }
+}
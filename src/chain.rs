@@ -0,0 +1,309 @@
+//! Products of several matrices, in the cheapest order.
+//!
+//! Matrix multiplication is associative, but the cost isn't: for a
+//! 10×5000, 5000×20, 20×5000, 5000×10 chain, (AB)(CD) takes about two million
+//! multiply-adds and A((BC)D) three-quarters of a billion. [`chain_order`] finds the
+//! cheapest parenthesization with the classic O(n³) dynamic program over the
+//! dimensions, and [`multiply_chain`] evaluates it.
+
+use crate::MatMulError;
+use crate::builder::MatMul;
+use std::fmt;
+
+/// The cheapest way to parenthesize a matrix chain, from [`chain_order`].
+///
+/// Displays as the parenthesized product, matrices numbered from 0, e.g.
+/// `((A0(A1A2))A3)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainOrder {
+    /// Number of matrices
+    len: usize,
+    /// For the subchain i..=j, the last matrix of its left factor, at
+    /// `i * len + j`
+    split: Vec<usize>,
+    cost: usize,
+}
+
+impl ChainOrder {
+    /// Multiply-adds the order takes (saturating at `usize::MAX`).
+    pub fn cost(&self) -> usize {
+        self.cost
+    }
+
+    /// Where the product of matrices `first..=last` splits: its left factor
+    /// ends with the returned matrix.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `first < last` and `last` is within the chain.
+    pub fn split(&self, first: usize, last: usize) -> usize {
+        assert!(
+            first < last && last < self.len,
+            "no split for matrices {}..={} of {}",
+            first,
+            last,
+            self.len
+        );
+        self.split[first * self.len + last]
+    }
+
+    fn fmt_range(&self, f: &mut fmt::Formatter<'_>, first: usize, last: usize) -> fmt::Result {
+        if first == last {
+            return write!(f, "A{}", first);
+        }
+        let split = self.split(first, last);
+        f.write_str("(")?;
+        self.fmt_range(f, first, split)?;
+        self.fmt_range(f, split + 1, last)?;
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for ChainOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_range(f, 0, self.len - 1)
+    }
+}
+
+/// The cheapest parenthesization of a chain whose matrix i is
+/// `dims[i]`×`dims[i + 1]`.
+///
+/// ```
+/// use matmul::chain_order;
+///
+/// let order = chain_order(&[10, 5000, 20, 5000, 10]);
+/// assert_eq!(order.to_string(), "((A0A1)(A2A3))");
+/// assert_eq!(order.cost(), 10 * 5000 * 20 + 10 * 20 * 10 + 20 * 5000 * 10);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `dims` has fewer than two entries (no matrices).
+pub fn chain_order(dims: &[usize]) -> ChainOrder {
+    assert!(dims.len() >= 2, "a chain needs at least one matrix");
+    let len = dims.len() - 1;
+    let mut cost = vec![0usize; len * len];
+    let mut split = vec![0; len * len];
+    for span in 2..=len {
+        for first in 0..=len - span {
+            let last = first + span - 1;
+            let (best, at) = (first..last)
+                .map(|s| {
+                    let product = dims[first]
+                        .saturating_mul(dims[s + 1])
+                        .saturating_mul(dims[last + 1]);
+                    let total = cost[first * len + s]
+                        .saturating_add(cost[(s + 1) * len + last])
+                        .saturating_add(product);
+                    (total, s)
+                })
+                // The first split among equals, so ties go left to right
+                .min_by_key(|&(total, _)| total)
+                .unwrap();
+            cost[first * len + last] = best;
+            split[first * len + last] = at;
+        }
+    }
+    ChainOrder {
+        len,
+        split,
+        cost: cost[len - 1],
+    }
+}
+
+/// The product of a chain of matrices, each `(data, rows, cols)`, row-major.
+///
+/// Multiplies in the order [`chain_order`] picks for the shapes, on the
+/// detected backend. Intermediate products go into scratch matrices that are
+/// reused once their factors are consumed, and one packing workspace serves
+/// every step. The result is the same product as multiplying left to right,
+/// up to rounding.
+///
+/// ```
+/// use matmul::multiply_chain;
+///
+/// let a = [1.0, 2.0]; // 1×2
+/// let b = [3.0, 4.0]; // 2×1
+/// let c = [5.0, 6.0]; // 1×2
+/// let abc = multiply_chain(&[(&a, 1, 2), (&b, 2, 1), (&c, 1, 2)])?;
+/// assert_eq!(abc, [55.0, 66.0]);
+/// # Ok::<(), matmul::MatMulError>(())
+/// ```
+///
+/// # Errors
+///
+/// [`MatMulError::Chain`] if the chain is empty, a slice doesn't hold
+/// rows×cols elements, or a matrix's rows don't match the previous one's
+/// columns.
+pub fn multiply_chain(matrices: &[(&[f64], usize, usize)]) -> Result<Vec<f64>, MatMulError> {
+    if matrices.is_empty() {
+        return Err(MatMulError::Chain {
+            index: 0,
+            reason: "the chain is empty",
+        });
+    }
+    for (index, &(data, rows, cols)) in matrices.iter().enumerate() {
+        let reason = if rows.checked_mul(cols).is_none() {
+            "rows × cols overflows usize"
+        } else if data.len() != rows * cols {
+            "the slice doesn't hold rows × cols elements"
+        } else if index > 0 && rows != matrices[index - 1].2 {
+            "rows don't match the previous matrix's columns"
+        } else {
+            continue;
+        };
+        return Err(MatMulError::Chain { index, reason });
+    }
+
+    let dims: Vec<usize> = std::iter::once(matrices[0].1)
+        .chain(matrices.iter().map(|&(_, _, cols)| cols))
+        .collect();
+    let order = chain_order(&dims);
+    let mut chain = Chain {
+        matrices,
+        order: &order,
+        matmul: MatMul::new().beta(0.0).build()?,
+        spare: Vec::new(),
+    };
+    Ok(match chain.product(0, matrices.len() - 1)? {
+        Factor::Input(data) => data.to_vec(),
+        Factor::Product(data) => data,
+    })
+}
+
+/// An operand of one step: an input matrix, or an earlier step's product.
+enum Factor<'a> {
+    Input(&'a [f64]),
+    Product(Vec<f64>),
+}
+
+impl Factor<'_> {
+    fn data(&self) -> &[f64] {
+        match self {
+            Factor::Input(data) => data,
+            Factor::Product(data) => data,
+        }
+    }
+}
+
+/// Evaluation state for [`multiply_chain`].
+struct Chain<'a, 'o> {
+    matrices: &'a [(&'a [f64], usize, usize)],
+    order: &'o ChainOrder,
+    matmul: MatMul,
+    /// Products already consumed, to hold later ones
+    spare: Vec<Vec<f64>>,
+}
+
+impl<'a> Chain<'a, '_> {
+    /// The product of matrices `first..=last`.
+    fn product(&mut self, first: usize, last: usize) -> Result<Factor<'a>, MatMulError> {
+        if first == last {
+            return Ok(Factor::Input(self.matrices[first].0));
+        }
+        let split = self.order.split(first, last);
+        let left = self.product(first, split)?;
+        let right = self.product(split + 1, last)?;
+
+        let (m, k, n) = (
+            self.matrices[first].1,
+            self.matrices[split].2,
+            self.matrices[last].2,
+        );
+        let mut c = self.spare.pop().unwrap_or_default();
+        // beta = 0 overwrites, so the old contents don't matter
+        c.resize(m * n, 0.0);
+        self.matmul
+            .compute_into(left.data(), right.data(), &mut c, m, n, k)?;
+        for factor in [left, right] {
+            if let Factor::Product(buffer) = factor {
+                self.spare.push(buffer);
+            }
+        }
+        Ok(Factor::Product(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    #[test]
+    fn test_textbook_orders() {
+        // CLRS 15.2
+        let order = chain_order(&[30, 35, 15, 5, 10, 20, 25]);
+        assert_eq!(order.to_string(), "((A0(A1A2))((A3A4)A5))");
+        assert_eq!(order.cost(), 15125);
+        assert_eq!(order.split(0, 5), 2);
+
+        let order = chain_order(&[10, 100, 5, 50]);
+        assert_eq!(order.to_string(), "((A0A1)A2)");
+        assert_eq!(order.cost(), 7500);
+
+        let order = chain_order(&[40, 20, 30, 10, 30]);
+        assert_eq!(order.to_string(), "((A0(A1A2))A3)");
+        assert_eq!(order.cost(), 26000);
+
+        assert_eq!(chain_order(&[3, 4]).to_string(), "A0");
+        assert_eq!(chain_order(&[3, 4]).cost(), 0);
+    }
+
+    #[test]
+    fn test_matches_left_to_right() {
+        let chains: &[&[usize]] = if cfg!(miri) {
+            &[&[3, 5, 2, 4], &[2, 3]]
+        } else {
+            &[
+                &[10, 500, 20, 500, 10],
+                &[30, 35, 15, 5, 10, 20, 25],
+                &[7, 1, 9, 1, 7],
+                &[2, 3],
+            ]
+        };
+        for dims in chains {
+            let data: Vec<Vec<f64>> = dims
+                .windows(2)
+                .enumerate()
+                .map(|(i, w)| random(w[0], w[1], i as u64))
+                .collect();
+            let matrices: Vec<(&[f64], usize, usize)> = data
+                .iter()
+                .zip(dims.windows(2))
+                .map(|(d, w)| (d.as_slice(), w[0], w[1]))
+                .collect();
+
+            let mut want = data[0].clone();
+            for (i, w) in dims.windows(2).enumerate().skip(1) {
+                let mut next = vec![0.0; dims[0] * w[1]];
+                multiply(&want, &data[i], &mut next, dims[0], w[1], w[0]);
+                want = next;
+            }
+            let got = multiply_chain(&matrices).unwrap();
+            assert_close(&want, &got, 1e-10, 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_rejected_chains() {
+        assert!(matches!(
+            multiply_chain(&[]),
+            Err(MatMulError::Chain { index: 0, .. })
+        ));
+        let a = [0.0; 6];
+        assert!(matches!(
+            multiply_chain(&[(&a, 2, 3), (&a, 2, 3)]),
+            Err(MatMulError::Chain { index: 1, .. })
+        ));
+        assert!(matches!(
+            multiply_chain(&[(&a, 2, 3), (&a[..5], 3, 2)]),
+            Err(MatMulError::Chain { index: 1, .. })
+        ));
+        assert!(matches!(
+            multiply_chain(&[(&a, usize::MAX, 2)]),
+            Err(MatMulError::Chain { index: 0, .. })
+        ));
+    }
+}
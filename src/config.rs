@@ -0,0 +1,446 @@
+//! Process-wide defaults for the multiplies, and scoped overrides of them.
+//!
+//! Settings pile up: a thread cap, a denormal mode, a fixed schedule, block
+//! sizes, a debugging check. A library that multiplies on its users' behalf
+//! wants to set them once rather than at every call, and without overriding
+//! what another library in the same process chose. [`set_global`] sets the
+//! defaults for every thread; [`with_config`] overrides them for the length
+//! of a closure, on the calling thread only. Scopes nest and compose: a
+//! field an inner scope leaves `None` comes from the scope around it, and in
+//! the end from the global config.
+//!
+//! Every field is `None` by default, for the crate's own behavior.
+//! [`multiply`](crate::multiply) and its variants,
+//! [`MatMul`](crate::MatMul), [`MatmulPlan`](crate::MatmulPlan), and
+//! [`MultiplyJob`](crate::MultiplyJob) read the effective config
+//! ([`current`]) on the calling thread, and the threaded multiplies hand it
+//! to their workers, so a multiply runs under the config it was called
+//! under. A `MatMul` takes the config's tuning when it's built, and a plan
+//! its thread count when it's made. Until anything sets a config, reading
+//! one is a single atomic load.
+//!
+//! ```
+//! use matmul::config::{self, Config};
+//! use matmul::multiply_parallel_with_stats;
+//!
+//! let n = 512;
+//! let (a, b) = (vec![1.0; n * n], vec![1.0; n * n]);
+//! let mut c = vec![0.0; n * n];
+//! let one_thread = Config {
+//!     max_threads: Some(1),
+//!     ..Config::default()
+//! };
+//! let stats = config::with_config(one_thread, || {
+//!     multiply_parallel_with_stats(&a, &b, &mut c, n, n, n, 8)
+//! });
+//! assert_eq!(stats.threads_used, 1);
+//! assert_eq!(config::current(), config::global());
+//! ```
+
+use crate::Tuning;
+use crate::denormal::{DenormalMode, ModeGuard};
+use crate::threaded::Schedule;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+/// Settings the multiplies read from the [effective](current) config;
+/// `None` keeps the crate's own behavior.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Most threads a multiply runs on, whatever its caller asks for (at
+    /// least 1)
+    pub max_threads: Option<usize>,
+    /// The denormal mode multiplies run in, as if called inside
+    /// [`with_denormal_mode`](crate::with_denormal_mode)
+    pub denormal_mode: Option<DenormalMode>,
+    /// With `Some(true)`, a multiply does its work the same way every run:
+    /// the threaded multiplies hand out static row bands rather than chunks
+    /// to whichever thread is free (all but the
+    /// [cancellable](crate::multiply_parallel_cancellable) one, which
+    /// stops between chunks), and the plain entry points pick their backend
+    /// by the [cost model](crate::select) rather than a timed
+    /// [calibration](mod@crate::calibrate). C is the same bits either way;
+    /// this fixes which rows each thread computes and which kernel runs,
+    /// for profiling or chasing a failure.
+    pub deterministic: Option<bool>,
+    /// Block sizes for the blocked backends, for the multiplies that aren't
+    /// given any. A [`MatMul`](crate::MatMul)'s own
+    /// [`tuning`](crate::MatMulBuilder::tuning) wins, field by field, and a
+    /// [`PackedA`](crate::PackedA) keeps the backend's, which its panels
+    /// are laid out for.
+    pub tuning: Tuning,
+    /// With `Some(true)`, checks each multiply against the i-k-j loop and
+    /// panics at one that's off (see [`self_check`](crate::self_check));
+    /// `None` leaves it to the `MATMUL_SELF_CHECK` environment variable
+    pub self_check: Option<bool>,
+}
+
+impl Config {
+    /// Every field `None`.
+    const DEFAULT: Config = Config {
+        max_threads: None,
+        denormal_mode: None,
+        deterministic: None,
+        tuning: Tuning { kc: None, mc: None },
+        self_check: None,
+    };
+
+    /// `self`, with each field it leaves `None` taken from `outer`.
+    ///
+    /// ```
+    /// use matmul::config::Config;
+    ///
+    /// let outer = Config { max_threads: Some(4), deterministic: Some(true), ..Config::default() };
+    /// let inner = Config { max_threads: Some(2), ..Config::default() };
+    /// let both = inner.or(outer);
+    /// assert_eq!((both.max_threads, both.deterministic), (Some(2), Some(true)));
+    /// ```
+    pub fn or(self, outer: Config) -> Config {
+        Config {
+            max_threads: self.max_threads.or(outer.max_threads),
+            denormal_mode: self.denormal_mode.or(outer.denormal_mode),
+            deterministic: self.deterministic.or(outer.deterministic),
+            tuning: self.tuning.or(outer.tuning),
+            self_check: self.self_check.or(outer.self_check),
+        }
+    }
+
+    /// `threads`, capped at `max_threads`.
+    pub(crate) fn cap_threads(&self, threads: usize) -> usize {
+        self.max_threads.map_or(threads, |max| threads.min(max))
+    }
+
+    /// The schedule for a threaded multiply that doesn't fix one.
+    pub(crate) fn schedule(&self) -> Schedule {
+        if self.is_deterministic() {
+            Schedule::Static
+        } else {
+            Schedule::Dynamic
+        }
+    }
+
+    pub(crate) fn is_deterministic(&self) -> bool {
+        self.deterministic == Some(true)
+    }
+
+    /// Panics for a setting no multiply could run with.
+    fn check(&self) {
+        assert!(
+            self.max_threads != Some(0),
+            "max_threads must be at least 1"
+        );
+        assert!(
+            self.tuning.kc != Some(0) && self.tuning.mc != Some(0),
+            "block sizes must be at least 1"
+        );
+    }
+}
+
+/// The config every thread starts from.
+static GLOBAL: RwLock<Config> = RwLock::new(Config::DEFAULT);
+
+/// Set once anything has set a config, globally or in a scope; until then
+/// [`current`] needn't look.
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// This thread's scopes, innermost last, each composed with the ones
+    /// around it (but not with the global config, which can change while
+    /// they're open).
+    static SCOPES: RefCell<Vec<Config>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The config every thread starts from: the last [`set_global`]'s.
+pub fn global() -> Config {
+    *GLOBAL.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Sets the config for every thread, under any [`with_config`] scopes
+/// they have open. Multiplies already running keep the config they started
+/// with.
+///
+/// # Panics
+///
+/// Panics if `max_threads` or a block size is `Some(0)`.
+pub fn set_global(config: Config) {
+    config.check();
+    IN_USE.store(true, Ordering::Release);
+    *GLOBAL.write().unwrap_or_else(PoisonError::into_inner) = config;
+}
+
+/// The config a multiply called here and now runs under: the innermost
+/// [`with_config`] scope's, its `None` fields filled in from the scopes
+/// around it and then from the [`global`] config.
+pub fn current() -> Config {
+    if !IN_USE.load(Ordering::Acquire) {
+        return Config::DEFAULT;
+    }
+    let scoped = SCOPES.with_borrow(|scopes| scopes.last().copied());
+    scoped.unwrap_or_default().or(global())
+}
+
+/// Runs `f` with `config` over the calling thread's current one, then
+/// restores the previous config, also if `f` panics. Other threads don't
+/// see it, except the workers of a multiply called inside `f`.
+///
+/// # Panics
+///
+/// Panics if `max_threads` or a block size is `Some(0)`.
+pub fn with_config<R>(config: Config, f: impl FnOnce() -> R) -> R {
+    config.check();
+    let _scope = Scope::enter(config);
+    f()
+}
+
+/// A [`with_config`] scope, open until dropped.
+pub(crate) struct Scope {
+    /// Scopes are per thread, so one can't be closed on another
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Scope {
+    pub(crate) fn enter(config: Config) -> Scope {
+        IN_USE.store(true, Ordering::Release);
+        SCOPES.with_borrow_mut(|scopes| {
+            let outer = scopes.last().copied().unwrap_or_default();
+            scopes.push(config.or(outer));
+        });
+        Scope {
+            _not_send: PhantomData,
+        }
+    }
+
+    /// [`enter`](Self::enter) for a worker running its caller's
+    /// [`current`] config, unless that's the default anyway.
+    pub(crate) fn inherit(config: Config) -> Option<Scope> {
+        (config != Config::DEFAULT).then(|| Scope::enter(config))
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        SCOPES.with_borrow_mut(|scopes| scopes.pop());
+    }
+}
+
+/// Sets the [`current`] config's denormal mode, if it has one, on this
+/// thread until the guard is dropped. The entry points hold one while they
+/// run.
+pub(crate) fn enter_denormal_mode() -> Option<ModeGuard> {
+    current().denormal_mode.map(ModeGuard::set)
+}
+
+/// `backend`'s `(kc, mc)` for a k-deep multiply, with the [`current`]
+/// config's tuning in place of its own: [`Backend::block_sizes`] for the
+/// entry points that don't take block sizes.
+///
+/// [`Backend::block_sizes`]: crate::Backend
+pub(crate) fn block_sizes(backend: crate::Backend, k: usize) -> (usize, usize) {
+    let (kc, mc) = backend.block_sizes(k);
+    let tuning = current().tuning;
+    if backend == crate::Backend::ScalarIkj {
+        return (kc, mc);
+    }
+    let (mr, _) = backend.tile();
+    (
+        tuning.kc.map_or(kc, |kc| kc.min(k)),
+        tuning.mc.map_or(mc, |mc| (mc / mr).max(1) * mr),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::threaded::schedule::for_each_band;
+    use crate::{Backend, MatMul, available_backends, multiply_with_stats};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    fn threads(max_threads: usize) -> Config {
+        Config {
+            max_threads: Some(max_threads),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_scopes_compose() {
+        let before = current();
+        let fixed = Config {
+            deterministic: Some(true),
+            ..Config::default()
+        };
+        with_config(fixed.or(threads(4)), || {
+            assert_eq!(current().max_threads, Some(4));
+            with_config(threads(2), || {
+                assert_eq!(current().max_threads, Some(2));
+                assert_eq!(current().deterministic, Some(true));
+                assert_eq!(current().schedule(), Schedule::Static);
+            });
+            assert_eq!(current().max_threads, Some(4));
+        });
+        assert_eq!(current(), before);
+    }
+
+    #[test]
+    fn test_restored_after_a_panic() {
+        let before = current();
+        let result = panic::catch_unwind(|| {
+            with_config(threads(1), || {
+                with_config(threads(3), || panic!("inside two scopes"));
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(current(), before);
+    }
+
+    #[test]
+    #[should_panic(expected = "block sizes must be at least 1")]
+    fn test_zero_block_size_panics() {
+        let config = Config {
+            tuning: Tuning {
+                kc: Some(0),
+                mc: None,
+            },
+            ..Config::default()
+        };
+        with_config(config, || {});
+    }
+
+    /// Other threads, workers aside, don't see a scope.
+    #[test]
+    fn test_scope_is_per_thread() {
+        with_config(threads(1), || {
+            let elsewhere = std::thread::spawn(current).join().unwrap();
+            assert_eq!(elsewhere, global());
+        });
+    }
+
+    #[test]
+    fn test_workers_inherit_the_scope() {
+        let (m, n) = (40, 3);
+        let config = threads(3).or(Config {
+            deterministic: Some(true),
+            ..Config::default()
+        });
+        let seen = Mutex::new(Vec::new());
+        let mut c = vec![0.0; m * n];
+        with_config(config, || {
+            for_each_band(&mut c, m, n, 4, 3, Schedule::Static, None, |_, _, _| {
+                seen.lock().unwrap().push(current());
+            })
+            .unwrap();
+        });
+        let seen = seen.into_inner().unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(seen.iter().all(|&seen| seen == config.or(global())));
+    }
+
+    #[test]
+    fn test_tuning() {
+        let (m, n, k) = (30, 20, 100);
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let config = Config {
+            tuning: Tuning {
+                kc: Some(32),
+                mc: Some(50),
+            },
+            ..Config::default()
+        };
+        with_config(config, || {
+            let mut c = vec![0.0; m * n];
+            let stats = multiply_with_stats(&a, &b, &mut c, m, n, k);
+            if stats.backend != Backend::ScalarIkj {
+                assert_eq!(stats.kc, 32);
+            }
+            for backend in available_backends() {
+                let (mr, _) = backend.tile();
+                let matmul = MatMul::new().backend(backend).build().unwrap();
+                if backend != Backend::ScalarIkj {
+                    assert_eq!(matmul.block_sizes(), (32, 50 / mr * mr), "{}", backend);
+                }
+                // A MatMul's own tuning wins
+                let matmul = MatMul::new()
+                    .backend(backend)
+                    .tuning(Tuning {
+                        kc: Some(8),
+                        mc: None,
+                    })
+                    .build()
+                    .unwrap();
+                assert_eq!(matmul.block_sizes().0, 8);
+            }
+        });
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "a 512³ multiply")]
+    fn test_max_threads() {
+        let n = 512;
+        let (a, b) = (random(n, n, 1), random(n, n, 2));
+        let mut want = vec![0.0; n * n];
+        let unlimited = crate::multiply_parallel_with_stats(&a, &b, &mut want, n, n, n, 4);
+        assert!(unlimited.threads_used > 1);
+
+        let mut c = vec![0.0; n * n];
+        with_config(threads(1), || {
+            let stats = crate::multiply_parallel_with_stats(&a, &b, &mut c, n, n, n, 4);
+            assert_eq!(stats.threads_used, 1);
+            let matmul = MatMul::new().threads(4).build().unwrap();
+            assert_eq!(matmul.thread_count(n, n, n), 1);
+        });
+        assert_eq!(c, want);
+    }
+
+    #[test]
+    fn test_denormal_mode() {
+        let a = vec![1e-310; 4];
+        let b = vec![1.0; 4];
+        let mut c = vec![0.0; 4];
+        let flush = Config {
+            denormal_mode: Some(DenormalMode::FlushToZero),
+            ..Config::default()
+        };
+        with_config(flush, || {
+            crate::multiply(&a, &b, &mut c, 2, 2, 2);
+            // Only while a multiply runs
+            assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+        });
+        if cfg!(all(
+            any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "aarch64"
+            ),
+            not(miri)
+        )) {
+            assert_eq!(c, [0.0; 4]);
+        }
+    }
+
+    #[test]
+    fn test_block_sizes_without_tuning() {
+        for backend in available_backends() {
+            for k in [0, 5, 300, 100_000] {
+                assert_eq!(block_sizes(backend, k), backend.block_sizes(k));
+            }
+        }
+    }
+
+    /// Catches a scope left open by an unbalanced drop.
+    #[test]
+    fn test_scopes_unwind_in_order() {
+        let depth = || SCOPES.with_borrow(Vec::len);
+        let before = depth();
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _outer = Scope::enter(threads(2));
+            let _inner = Scope::enter(threads(1));
+            assert_eq!(depth(), before + 2);
+            panic!("unwinding");
+        }));
+        assert_eq!(depth(), before);
+    }
+}
@@ -0,0 +1,162 @@
+//! Opt-in flush-to-zero for denormal (subnormal) values.
+//!
+//! Arithmetic on denormals takes a microcode assist on most x86 cores, so a
+//! multiply whose inputs or partial sums are denormal can run an order of
+//! magnitude slower than the same multiply on normal values.
+//! [`DenormalMode::FlushToZero`] trades IEEE 754 gradual underflow for
+//! predictable speed: denormal inputs read as zero and denormal results are
+//! written as zero.
+//!
+//! The mode lives in each thread's floating-point control register (MXCSR's
+//! FTZ and DAZ bits on x86, FPCR's FZ bit on aarch64). [`with_denormal_mode`]
+//! sets it for the calling thread and restores it afterwards; the threaded
+//! multiplies copy the caller's mode into every worker for the duration of
+//! its band. On other targets, and under Miri (which can't run the inline
+//! assembly that reads the registers), the mode can't be changed and is
+//! always [`DenormalMode::Preserve`].
+//!
+//! The mode is for speed, not a guarantee about every operation's result.
+//! The register is switched by inline assembly that leaves it changed after
+//! the `asm!` block, which Rust's inline-assembly rules don't sanction: the
+//! compiler assumes the default floating-point environment throughout, so
+//! it may constant-fold a floating-point operation inside the scope, or
+//! move one across the switch. Such an operation can then keep gradual
+//! underflow inside the scope, or flush just outside it. Only denormal
+//! inputs and results can differ, but code that needs denormals handled
+//! exactly one way shouldn't rely on where the scope begins and ends.
+
+/// How floating-point operations treat denormal values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DenormalMode {
+    /// IEEE 754 gradual underflow (the default)
+    #[default]
+    Preserve,
+    /// Denormal inputs read as zero and denormal results flush to zero. Values
+    /// below `f64::MIN_POSITIVE` (about 2.2e-308) are lost.
+    FlushToZero,
+}
+
+impl DenormalMode {
+    /// The calling thread's mode.
+    pub fn current() -> DenormalMode {
+        if control::read() & control::FLUSH_BITS == control::FLUSH_BITS {
+            DenormalMode::FlushToZero
+        } else {
+            DenormalMode::Preserve
+        }
+    }
+}
+
+/// Runs `f` with `mode` set on the calling thread, then restores the previous
+/// mode, also if `f` panics.
+///
+/// Multiplies called inside `f`, threaded ones included, run entirely in
+/// `mode`.
+///
+/// ```
+/// use matmul::{DenormalMode, multiply, with_denormal_mode};
+///
+/// let a = vec![1e-310; 4];
+/// let b = vec![1.0; 4];
+/// let mut c = vec![0.0; 4];
+/// with_denormal_mode(DenormalMode::FlushToZero, || multiply(&a, &b, &mut c, 2, 2, 2));
+/// # #[cfg(all(
+/// #     any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"),
+/// #     not(miri)
+/// # ))]
+/// assert_eq!(c, [0.0; 4]);
+/// assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+/// ```
+pub fn with_denormal_mode<R>(mode: DenormalMode, f: impl FnOnce() -> R) -> R {
+    let _guard = ModeGuard::set(mode);
+    f()
+}
+
+/// Sets a mode on this thread until dropped.
+pub(crate) struct ModeGuard {
+    /// The register to restore, if setting the mode changed it
+    saved: Option<control::Register>,
+}
+
+impl ModeGuard {
+    pub(crate) fn set(mode: DenormalMode) -> ModeGuard {
+        let saved = control::read();
+        let wanted = match mode {
+            DenormalMode::Preserve => saved & !control::FLUSH_BITS,
+            DenormalMode::FlushToZero => saved | control::FLUSH_BITS,
+        };
+        if wanted == saved {
+            return ModeGuard { saved: None };
+        }
+        control::write(wanted);
+        ModeGuard { saved: Some(saved) }
+    }
+}
+
+impl Drop for ModeGuard {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved {
+            control::write(saved);
+        }
+    }
+}
+
+#[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), not(miri)))]
+mod control {
+    use std::arch::asm;
+
+    pub type Register = u32;
+
+    /// MXCSR flush-to-zero (bit 15) and denormals-are-zero (bit 6)
+    pub const FLUSH_BITS: Register = 1 << 15 | 1 << 6;
+
+    // The instructions behind the deprecated `_mm_getcsr`/`_mm_setcsr`. A
+    // write outlives its asm block, so it carries the caveat in the module
+    // docs; `_mm_setcsr` was deprecated for the same reason
+    pub fn read() -> Register {
+        let mut csr: Register = 0;
+        unsafe { asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack)) };
+        csr
+    }
+
+    pub fn write(csr: Register) {
+        unsafe { asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly)) };
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(miri)))]
+mod control {
+    use std::arch::asm;
+
+    pub type Register = u64;
+
+    /// FPCR flush-to-zero (bit 24), which covers inputs and results
+    pub const FLUSH_BITS: Register = 1 << 24;
+
+    pub fn read() -> Register {
+        let fpcr: Register;
+        unsafe { asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack)) };
+        fpcr
+    }
+
+    pub fn write(fpcr: Register) {
+        unsafe { asm!("msr fpcr, {}", in(reg) fpcr, options(nomem, nostack)) };
+    }
+}
+
+#[cfg(any(
+    miri,
+    not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))
+))]
+mod control {
+    pub type Register = u8;
+
+    /// Never set: `read` always returns 0, so the mode reads as `Preserve`
+    pub const FLUSH_BITS: Register = 1;
+
+    pub fn read() -> Register {
+        0
+    }
+
+    pub fn write(_: Register) {}
+}
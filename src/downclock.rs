@@ -0,0 +1,209 @@
+//! Keeping small multiplies off AVX-512 on CPUs that downclock for it.
+//!
+//! Intel's first AVX-512 server parts run heavy 512-bit FMA code under a
+//! lower frequency license: the core drops its clock soon after such code
+//! starts and keeps it down for about 2 ms after it stops. A big multiply
+//! earns that back. A small one can cost more in whatever runs next on that
+//! core, at the lower clock, than the wider kernel saved. So on those parts
+//! [`multiply`](crate::multiply) and the other plain entry points run a
+//! multiply whose m, n, and k are all at most the [`avx512_crossover`] on the
+//! 12×4 AVX2 kernel instead. The SIMD backends agree bit for bit (see
+//! [`Backend`]), so which one ran doesn't show in C.
+//!
+//! The default depends on the CPU:
+//!
+//! | CPU | Crossover |
+//! |---|---|
+//! | Intel Skylake-SP/X, Cascade Lake, Cooper Lake (family 6, model 0x55) | 256 |
+//! | Intel Ice Lake, Tiger Lake, Rocket Lake, Sapphire Rapids, and later | 0 |
+//! | AMD Zen 4 and Zen 5 | 0 |
+//! | Anything else | 0 |
+//!
+//! 0 means AVX-512 at every size. From Ice Lake on, Intel's 512-bit license
+//! costs a few percent of clock rather than a few hundred MHz, and AMD's
+//! cores don't change clock for AVX-512 at all. An unrecognized CPU keeps
+//! AVX-512 too, as it did before the heuristic existed.
+//!
+//! [`set_avx512_crossover`] overrides the default for the whole process.
+//! [`multiply_with_backend`](crate::multiply_with_backend) and a
+//! [`MatMul`](crate::MatMul) run the backend they're given (or built with)
+//! at every size. The benchmark's `--mode downclock` measures the trade-off
+//! on a given machine: a multiply on each kernel, then a scalar workload
+//! timed right after it.
+
+use crate::Backend;
+use crate::features::{has_avx2, has_avx512};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The crossover for the parts with a heavy AVX-512 license: past 256³, the
+/// 8×8 kernel's lead outweighs the clock it costs.
+pub const HEAVY_LICENSE_CROSSOVER: usize = 256;
+
+/// [`set_avx512_crossover`]'s value; `usize::MAX` for none.
+static OVERRIDE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// The largest m, n, and k that the plain entry points run on AVX2 rather
+/// than AVX-512: [`set_avx512_crossover`]'s value if set, otherwise
+/// [`default_avx512_crossover`].
+pub fn avx512_crossover() -> usize {
+    match OVERRIDE.load(Ordering::Relaxed) {
+        usize::MAX => default_avx512_crossover(),
+        size => size,
+    }
+}
+
+/// Sets the crossover for every thread; `None` goes back to this CPU's
+/// default. `Some(0)` runs AVX-512 at every size.
+///
+/// ```
+/// // Keep everything up to 512³ on AVX2, whatever the CPU
+/// matmul::set_avx512_crossover(Some(512));
+/// assert_eq!(matmul::avx512_crossover(), 512);
+/// matmul::set_avx512_crossover(None);
+/// assert_eq!(matmul::avx512_crossover(), matmul::default_avx512_crossover());
+/// ```
+pub fn set_avx512_crossover(size: Option<usize>) {
+    OVERRIDE.store(
+        size.map_or(usize::MAX, |size| size.min(usize::MAX - 1)),
+        Ordering::Relaxed,
+    );
+}
+
+/// The crossover for this CPU (see the [module docs](self) for the table),
+/// detected on the first call and cached. 0 on CPUs without AVX-512.
+pub fn default_avx512_crossover() -> usize {
+    static DEFAULT: OnceLock<usize> = OnceLock::new();
+    *DEFAULT.get_or_init(|| {
+        if has_avx512() {
+            cpu_signature().map_or(0, |(intel, family, model)| {
+                crossover_for(intel, family, model)
+            })
+        } else {
+            0
+        }
+    })
+}
+
+/// The backend [`multiply`](crate::multiply) runs an m×n×k multiply on:
+/// the [cost model](crate::select)'s pick, or the
+/// [calibrated](mod@crate::calibrate) table's once there is one (unless the
+/// [config](crate::config) is deterministic), except
+/// that AVX-512 multiplies within the [`avx512_crossover`] run on the 12×4
+/// AVX2 kernel.
+///
+/// ```
+/// use matmul::{Backend, backend_for, detected_backend};
+///
+/// assert_eq!(backend_for(4096, 4096, 4096), detected_backend());
+/// let small = backend_for(64, 64, 64);
+/// assert!(small == detected_backend() || small == Backend::Avx2_12x4);
+/// ```
+pub fn backend_for(m: usize, n: usize, k: usize) -> Backend {
+    let table =
+        crate::calibrate::dispatch_table().filter(|_| !crate::config::current().is_deterministic());
+    let chosen = match table {
+        Some(table) => table.backend_for(m, n, k),
+        None => crate::select_backend(m, n, k),
+    };
+    prefer(chosen, avx512_crossover(), m, n, k)
+}
+
+/// `detected` for an m×n×k multiply at the given crossover.
+pub(crate) fn prefer(detected: Backend, crossover: usize, m: usize, n: usize, k: usize) -> Backend {
+    if detected == Backend::Avx512_8x8 && m.max(n).max(k) <= crossover && has_avx2() {
+        Backend::Avx2_12x4
+    } else {
+        detected
+    }
+}
+
+/// The default crossover for a CPU, by vendor and (display) family and
+/// model.
+fn crossover_for(intel: bool, family: u32, model: u32) -> usize {
+    match (intel, family, model) {
+        // Skylake-SP and -X, Cascade Lake, Cooper Lake
+        (true, 6, 0x55) => HEAVY_LICENSE_CROSSOVER,
+        _ => 0,
+    }
+}
+
+/// `(is Intel, family, model)` from CPUID, with the extended family and
+/// model folded in the way Intel's and AMD's manuals say.
+#[cfg(all(target_arch = "x86_64", not(miri)))]
+fn cpu_signature() -> Option<(bool, u32, u32)> {
+    use std::arch::x86_64::__cpuid;
+
+    #[allow(unused_unsafe)]
+    let (vendor, leaf1) = unsafe { (__cpuid(0), __cpuid(1)) };
+    let intel = [vendor.ebx, vendor.edx, vendor.ecx] == [0x756e_6547, 0x4965_6e69, 0x6c65_746e];
+    let base_family = (leaf1.eax >> 8) & 0xf;
+    let mut family = base_family;
+    let mut model = (leaf1.eax >> 4) & 0xf;
+    if base_family == 0xf {
+        family += (leaf1.eax >> 20) & 0xff;
+    }
+    if base_family == 0x6 || base_family == 0xf {
+        model |= ((leaf1.eax >> 16) & 0xf) << 4;
+    }
+    Some((intel, family, model))
+}
+
+/// No CPUID: off x86_64, and under Miri, which doesn't emulate it.
+#[cfg(not(all(target_arch = "x86_64", not(miri))))]
+fn cpu_signature() -> Option<(bool, u32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossover_by_cpu() {
+        // Skylake-SP / Cascade Lake
+        assert_eq!(crossover_for(true, 6, 0x55), HEAVY_LICENSE_CROSSOVER);
+        // Ice Lake-SP, Sapphire Rapids
+        assert_eq!(crossover_for(true, 6, 0x6a), 0);
+        assert_eq!(crossover_for(true, 6, 0x8f), 0);
+        // Zen 4: family 0x19, and an AMD model 0x55 is nothing special
+        assert_eq!(crossover_for(false, 0x19, 0x11), 0);
+        assert_eq!(crossover_for(false, 6, 0x55), 0);
+    }
+
+    #[test]
+    fn test_prefer_only_moves_small_avx512_multiplies() {
+        let avx2 = has_avx2();
+        let small = if avx2 {
+            Backend::Avx2_12x4
+        } else {
+            Backend::Avx512_8x8
+        };
+        assert_eq!(prefer(Backend::Avx512_8x8, 256, 256, 100, 1), small);
+        assert_eq!(
+            prefer(Backend::Avx512_8x8, 256, 257, 100, 1),
+            Backend::Avx512_8x8
+        );
+        assert_eq!(
+            prefer(Backend::Avx512_8x8, 256, 1, 1, 1000),
+            Backend::Avx512_8x8
+        );
+        assert_eq!(prefer(Backend::Avx512_8x8, 0, 1, 1, 1), Backend::Avx512_8x8);
+        for other in [
+            Backend::Scalar,
+            Backend::ScalarIkj,
+            Backend::Avx2_4x4,
+            Backend::Avx2_12x4,
+        ] {
+            assert_eq!(prefer(other, 256, 8, 8, 8), other);
+        }
+    }
+
+    #[cfg(all(target_arch = "x86_64", not(miri)))]
+    #[test]
+    fn test_signature_matches_cpu() {
+        let (_, family, _) = cpu_signature().unwrap();
+        // Every x86_64 CPU is family 6 (Intel) or 15 and up (AMD, Hygon)
+        assert!(family == 6 || family >= 0xf, "family {}", family);
+    }
+}
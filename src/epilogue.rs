@@ -0,0 +1,298 @@
+//! A bias and activation applied to C as the multiply finishes it.
+//!
+//! A neural-net layer is C = act(A·B + bias), with one bias per column
+//! broadcast down the rows. As a second pass over C, that's another read and
+//! write of every element: at 2048², 64 MB of memory traffic on top of the
+//! multiply's. A [`MatMul`](crate::MatMul) with a
+//! [`bias`](crate::MatMulBuilder::bias) or an
+//! [`activation`](crate::MatMulBuilder::activation) applies them tile by
+//! tile instead, straight after a kernel stores a tile's last k block, while
+//! the tile is still in L1.
+//!
+//! Each element is finished once, by a tile or by an edge, so each gets the
+//! epilogue once, after everything else: C = act(alpha·A·B + beta·C +
+//! bias). The rounding is the unfused computation's, one addition for the
+//! bias and then the activation, so the two agree bit for bit. The paths
+//! that don't tile (the i-k-j loop, the low-rank path, and
+//! [pairwise](crate::Accumulation::Pairwise) sums, whose tiles aren't
+//! finished until the partials are added up) apply it to their band of rows
+//! once it's done.
+
+use std::ops::Range;
+
+/// The function a [`MatMul`](crate::MatMul) applies to each element of C,
+/// set with [`MatMulBuilder::activation`](crate::MatMulBuilder::activation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    /// max(x, 0): negative values become zero; zero keeps its sign, and
+    /// NaN stays NaN
+    Relu,
+    /// The hyperbolic tangent, as [`f64::tanh`]
+    Tanh,
+}
+
+impl Activation {
+    /// The activation of one value.
+    ///
+    /// ```
+    /// use matmul::Activation;
+    ///
+    /// assert_eq!(Activation::Relu.apply(-2.0), 0.0);
+    /// assert_eq!(Activation::Relu.apply(3.0), 3.0);
+    /// assert_eq!(Activation::Tanh.apply(0.0), 0.0);
+    /// ```
+    #[inline]
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            Activation::Relu => {
+                if x < 0.0 {
+                    0.0
+                } else {
+                    x
+                }
+            }
+            Activation::Tanh => x.tanh(),
+        }
+    }
+}
+
+/// A [`MatMul`](crate::MatMul)'s bias and activation, shared with the
+/// drivers through the [`Workspace`](crate::Workspace).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Epilogue {
+    /// One per column of C
+    pub(crate) bias: Option<Vec<f64>>,
+    pub(crate) activation: Option<Activation>,
+}
+
+impl Epilogue {
+    /// C = act(C + bias) over a `rows` × `cols` block of C: `c` starts at
+    /// the block's first element, column `cols.start`, with rows `ldc`
+    /// apart.
+    #[inline]
+    pub(crate) fn apply(&self, c: &mut [f64], rows: usize, ldc: usize, cols: Range<usize>) {
+        // A loop per activation, so each compiles to straight-line vector
+        // code rather than a branch per element
+        let bias = self.bias.as_ref().map(|bias| &bias[cols.clone()]);
+        match self.activation {
+            None => each(c, rows, ldc, cols.len(), bias, |x| x),
+            Some(Activation::Relu) => each(c, rows, ldc, cols.len(), bias, |x| {
+                Activation::Relu.apply(x)
+            }),
+            Some(Activation::Tanh) => each(c, rows, ldc, cols.len(), bias, f64::tanh),
+        }
+    }
+
+    /// [`apply`](Self::apply) on one MR×NR kernel tile at column `j`. With
+    /// the size known, the loops unroll into a few vector instructions per
+    /// row, which matters in the drivers' inner loop.
+    #[inline]
+    pub(crate) fn apply_tile<const MR: usize, const NR: usize>(
+        &self,
+        c: &mut [f64],
+        ldc: usize,
+        j: usize,
+    ) {
+        let bias = self
+            .bias
+            .as_ref()
+            .map(|bias| bias[j..j + NR].try_into().unwrap());
+        match self.activation {
+            None => each_tile::<MR, NR>(c, ldc, bias, |x| x),
+            Some(Activation::Relu) => {
+                each_tile::<MR, NR>(c, ldc, bias, |x| Activation::Relu.apply(x))
+            }
+            Some(Activation::Tanh) => each_tile::<MR, NR>(c, ldc, bias, f64::tanh),
+        }
+    }
+}
+
+/// [`each`] for an MR×NR tile.
+#[inline(always)]
+fn each_tile<const MR: usize, const NR: usize>(
+    c: &mut [f64],
+    ldc: usize,
+    bias: Option<&[f64; NR]>,
+    f: impl Fn(f64) -> f64,
+) {
+    let c = &mut c[..(MR - 1) * ldc + NR];
+    for r in 0..MR {
+        let row: &mut [f64; NR] = (&mut c[r * ldc..r * ldc + NR]).try_into().unwrap();
+        match bias {
+            Some(bias) => {
+                for (x, &bias) in row.iter_mut().zip(bias) {
+                    *x = f(*x + bias);
+                }
+            }
+            None => {
+                for x in row.iter_mut() {
+                    *x = f(*x);
+                }
+            }
+        }
+    }
+}
+
+/// C = f(C + bias) over `rows` rows `width` wide, `ldc` apart.
+#[inline(always)]
+fn each(
+    c: &mut [f64],
+    rows: usize,
+    ldc: usize,
+    width: usize,
+    bias: Option<&[f64]>,
+    f: impl Fn(f64) -> f64,
+) {
+    if width == 0 {
+        return;
+    }
+    for r in 0..rows {
+        let row = &mut c[r * ldc..][..width];
+        match bias {
+            Some(bias) => {
+                for (x, &bias) in row.iter_mut().zip(bias) {
+                    *x = f(*x + bias);
+                }
+            }
+            None => {
+                for x in row.iter_mut() {
+                    *x = f(*x);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::{Accumulation, MatMul, MatMulError, Tuning, available_backends};
+
+    #[test]
+    fn test_relu() {
+        let relu = |x: f64| Activation::Relu.apply(x);
+        assert_eq!(relu(-1e-300), 0.0);
+        assert_eq!(relu(f64::NEG_INFINITY), 0.0);
+        assert_eq!(relu(f64::INFINITY), f64::INFINITY);
+        assert!(relu(f64::NAN).is_nan());
+        assert!(relu(-0.0).is_sign_negative());
+    }
+
+    #[test]
+    fn test_apply_to_a_block() {
+        // A 2×2 block at column 1 of a 3-wide C
+        let epilogue = Epilogue {
+            bias: Some(vec![100.0, -1.0, -5.0]),
+            activation: Some(Activation::Relu),
+        };
+        let mut c = [0.0, 2.0, 3.0, 9.0, 0.5, 8.0];
+        epilogue.apply(&mut c[1..], 2, 3, 1..3);
+        assert_eq!(c, [0.0, 1.0, 0.0, 9.0, 0.0, 3.0]);
+    }
+
+    /// Every backend, with and without alpha and beta, pairwise sums, and
+    /// threads, fused against the multiply followed by a pass over C. The
+    /// shapes have edge rows and columns, several k blocks (kc is 64), the
+    /// low-rank path, and k = 0.
+    #[test]
+    #[cfg_attr(miri, ignore = "hundreds of multiplies")]
+    fn test_matches_two_passes() {
+        let shapes = [(29, 19, 150), (13, 9, 5), (21, 11, 3), (6, 7, 0)];
+        for backend in available_backends() {
+            for (alpha, beta) in [(1.0, 1.0), (1.0, 0.0), (2.0, 0.5)] {
+                for accumulation in [Accumulation::Sequential, Accumulation::Pairwise] {
+                    for threads in [1, 3] {
+                        let plain = MatMul::new()
+                            .backend(backend)
+                            .alpha(alpha)
+                            .beta(beta)
+                            .accumulation(accumulation)
+                            .threads(threads)
+                            .tuning(Tuning {
+                                kc: Some(64),
+                                mc: None,
+                            });
+                        for &(m, n, k) in &shapes {
+                            let (a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+                            let bias = random(1, n, 4);
+                            for activation in [None, Some(Activation::Relu), Some(Activation::Tanh)]
+                            {
+                                let mut want = c0.clone();
+                                let mut matmul = plain.clone().build().unwrap();
+                                matmul.compute_into(&a, &b, &mut want, m, n, k).unwrap();
+                                for row in want.chunks_exact_mut(n) {
+                                    for (x, &bias) in row.iter_mut().zip(&bias) {
+                                        *x += bias;
+                                        if let Some(activation) = activation {
+                                            *x = activation.apply(*x);
+                                        }
+                                    }
+                                }
+
+                                let mut fused = plain.clone().bias(bias.clone());
+                                if let Some(activation) = activation {
+                                    fused = fused.activation(activation);
+                                }
+                                let mut c = c0.clone();
+                                let mut matmul = fused.build().unwrap();
+                                matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                                assert!(
+                                    c.iter().zip(&want).all(|(x, y)| x.to_bits() == y.to_bits()),
+                                    "{} {}x{}x{}, alpha {}, beta {}, {:?}, {} threads, {:?}",
+                                    backend,
+                                    m,
+                                    n,
+                                    k,
+                                    alpha,
+                                    beta,
+                                    accumulation,
+                                    threads,
+                                    activation
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The epilogue is the `MatMul`'s; the thread's scratch workspace it
+    /// ran in doesn't keep it.
+    #[test]
+    fn test_later_multiplies_are_plain() {
+        let (m, n, k) = (20, 12, 30);
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let mut relu = MatMul::new()
+            .activation(Activation::Relu)
+            .threads(2)
+            .build()
+            .unwrap();
+        let c = relu.compute(&a, &b, m, n, k).unwrap();
+        assert!(c.iter().all(|&x| x >= 0.0));
+
+        let mut want = vec![0.0; m * n];
+        crate::multiply(&a, &b, &mut want, m, n, k);
+        assert!(want.iter().any(|&x| x < 0.0));
+        let mut c = vec![0.0; m * n];
+        crate::multiply_parallel(&a, &b, &mut c, m, n, k, 2);
+        assert_eq!(c, want);
+    }
+
+    #[test]
+    fn test_wrong_bias_length() {
+        let mut matmul = MatMul::new().bias(vec![1.0; 3]).build().unwrap();
+        let mut c = [7.0; 4];
+        assert_eq!(
+            matmul.compute_into(&[0.0; 4], &[0.0; 4], &mut c, 2, 2, 2),
+            Err(MatMulError::WrongLength {
+                operand: "bias",
+                rows: 1,
+                cols: 2,
+                len: 3
+            })
+        );
+        assert_eq!(c, [7.0; 4]);
+    }
+}
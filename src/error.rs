@@ -0,0 +1,247 @@
+//! Errors from the checked entry points.
+
+use crate::Backend;
+use std::fmt;
+
+/// Why [`try_multiply`](crate::try_multiply) (or another checked entry
+/// point) refused its arguments.
+///
+/// The panicking entry points ([`multiply`](crate::multiply) and friends)
+/// panic with the same messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MatMulError {
+    /// `rows × cols` for an operand doesn't fit in `usize`, so no slice can
+    /// hold it (a wrapped product could otherwise match a short slice)
+    Overflow {
+        /// `"A"`, `"B"`, or `"C"`
+        operand: &'static str,
+        rows: usize,
+        cols: usize,
+    },
+    /// An operand's slice doesn't hold `rows × cols` elements
+    WrongLength {
+        /// `"A"`, `"B"`, or `"C"`; or `"bias"`, a
+        /// [`MatMul`](crate::MatMul)'s bias, one row n wide
+        operand: &'static str,
+        rows: usize,
+        cols: usize,
+        len: usize,
+    },
+    /// C shares memory with A or B. The kernels read A and B while they
+    /// write C, so an in-place multiply like `A = A·B` would read values it
+    /// has already overwritten.
+    Overlap {
+        /// `"A"` or `"B"`
+        operand: &'static str,
+    },
+    /// A [`PackedA`](crate::PackedA) was used for a different shape of A
+    /// than it was packed from, or asked for more of its columns than it
+    /// holds
+    PackedShape {
+        /// `(m, k)` at packing time
+        packed: (usize, usize),
+        /// `(m, k)` asked for
+        requested: (usize, usize),
+    },
+    /// The backend can't do what was asked: this CPU can't run it, or (for
+    /// the i-k-j loop) it has no packed layout
+    Backend {
+        backend: Backend,
+        /// What's missing, e.g. `"not available on this CPU"`
+        reason: &'static str,
+    },
+    /// A [`MatMul`](crate::MatMul) setting that can't work
+    InvalidConfig { reason: &'static str },
+    /// Matrix `index` of a [`multiply_chain`](crate::multiply_chain) doesn't
+    /// fit the chain
+    Chain {
+        index: usize,
+        /// What's wrong, e.g. `"rows don't match the previous matrix's columns"`
+        reason: &'static str,
+    },
+    /// The multiply's scratch buffers couldn't be allocated, or would pass
+    /// a [`MatMul`](crate::MatMul)'s
+    /// [`max_workspace`](crate::MatMulBuilder::max_workspace)
+    OutOfMemory {
+        /// Bytes of scratch space the multiply needed
+        requested_bytes: usize,
+    },
+    /// Row `row` of a nested operand isn't as long as its first row, as
+    /// [`multiply_nested`](crate::multiply_nested) needs
+    Ragged {
+        /// `"A"` or `"B"`
+        operand: &'static str,
+        row: usize,
+        len: usize,
+        /// The first row's length
+        expected: usize,
+    },
+}
+
+impl fmt::Display for MatMulError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatMulError::Overflow {
+                operand,
+                rows,
+                cols,
+            } => write!(f, "{}: {}x{} elements overflows usize", operand, rows, cols),
+            MatMulError::WrongLength {
+                operand,
+                rows,
+                cols,
+                len,
+            } => write!(
+                f,
+                "{}: expected {}x{}={} elements, got {}",
+                operand,
+                rows,
+                cols,
+                rows * cols,
+                len
+            ),
+            MatMulError::Overlap { operand } => write!(
+                f,
+                "C overlaps {}: in-place multiply isn't supported; write into a separate buffer",
+                operand
+            ),
+            MatMulError::PackedShape { packed, requested } => write!(
+                f,
+                "A was packed as {}x{}, but this multiply needs {}x{}",
+                packed.0, packed.1, requested.0, requested.1
+            ),
+            MatMulError::Backend { backend, reason } => write!(f, "{}: {}", backend, reason),
+            MatMulError::InvalidConfig { reason } => write!(f, "invalid configuration: {}", reason),
+            MatMulError::Chain { index, reason } => {
+                write!(f, "matrix {} of the chain: {}", index, reason)
+            }
+            MatMulError::OutOfMemory { requested_bytes } => write!(
+                f,
+                "out of memory: the multiply needs {} bytes of scratch space",
+                requested_bytes
+            ),
+            MatMulError::Ragged {
+                operand,
+                row,
+                len,
+                expected,
+            } => write!(
+                f,
+                "{}: row {} has {} elements, but row 0 has {}",
+                operand, row, len, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MatMulError {}
+
+/// The CPU lacks the instructions a SIMD implementation needs, so its safe
+/// `run` wrapper refused to call it.
+///
+/// [`multiply`](crate::multiply) never hits this: it only picks backends the
+/// CPU has. It's for code that asks for one implementation by name, such as
+/// [`gemm_12x4::run`](crate::blocked::gemm_12x4::run).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsupported {
+    /// The function that was asked for, e.g. `"matmul_blocked_12x4"`
+    pub function: &'static str,
+    /// What it needs, e.g. `"AVX2 and FMA"`
+    pub requires: &'static str,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} needs {}, which this CPU doesn't have",
+            self.function, self.requires
+        )
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Checks that A, B, and C hold m×k, k×n, and m×n elements.
+///
+/// The products are checked, so dimensions whose product doesn't fit in
+/// `usize` are an error rather than a wrapped size that happens to match a
+/// slice. Once this passes, every offset the kernels compute (below m·k, k·n,
+/// or m·n) fits as well.
+pub(crate) fn check_dims(
+    a: &[f64],
+    b: &[f64],
+    c: &[f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Result<(), MatMulError> {
+    check_operands([
+        ("A", a.len(), m, k),
+        ("B", b.len(), k, n),
+        ("C", c.len(), m, n),
+    ])
+}
+
+/// [`check_dims`] for any set of `(operand, len, rows, cols)`, for entry
+/// points that don't take all three of A, B, and C as slices.
+pub(crate) fn check_operands<const N: usize>(
+    operands: [(&'static str, usize, usize, usize); N],
+) -> Result<(), MatMulError> {
+    for (operand, len, rows, cols) in operands {
+        match rows.checked_mul(cols) {
+            None => {
+                return Err(MatMulError::Overflow {
+                    operand,
+                    rows,
+                    cols,
+                });
+            }
+            Some(expected) if expected != len => {
+                return Err(MatMulError::WrongLength {
+                    operand,
+                    rows,
+                    cols,
+                    len,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Checks that C shares no memory with A or B.
+///
+/// Safe code can't build such slices; `unsafe` code that reinterprets one
+/// buffer as both (to try `A = A·B`, say) can.
+pub(crate) fn check_no_overlap(a: &[f64], b: &[f64], c: &[f64]) -> Result<(), MatMulError> {
+    let c_range = c.as_ptr_range();
+    for (operand, input) in [("A", a), ("B", b)] {
+        let range = input.as_ptr_range();
+        // Empty slices hold no elements, so they never overlap anything
+        if !input.is_empty()
+            && !c.is_empty()
+            && range.start < c_range.end
+            && c_range.start < range.end
+        {
+            return Err(MatMulError::Overlap { operand });
+        }
+    }
+    Ok(())
+}
+
+/// [`check_dims`], panicking with the error's message; in debug builds,
+/// [`check_no_overlap`] as well.
+#[track_caller]
+pub(crate) fn assert_dims(a: &[f64], b: &[f64], c: &[f64], m: usize, n: usize, k: usize) {
+    if let Err(e) = check_dims(a, b, c, m, n, k) {
+        panic!("{}", e);
+    }
+    if cfg!(debug_assertions)
+        && let Err(e) = check_no_overlap(a, b, c)
+    {
+        panic!("{}", e);
+    }
+}
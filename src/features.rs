@@ -0,0 +1,172 @@
+//! Runtime CPU feature checks that compile on every target.
+//!
+//! `is_x86_feature_detected!` only exists when building for x86, so calling
+//! it outside a `#[cfg(target_arch = "x86_64")]` block breaks aarch64 and
+//! wasm builds. These wrappers are safe to call anywhere and are simply
+//! `false` off x86_64. Code that goes on to call an x86-only kernel still
+//! needs the `cfg`, since those modules don't exist on other targets.
+//!
+//! Detection runs once, on the first call to any of them; see
+//! [`cpu_features`].
+//!
+//! Under Miri [`has_avx2`] and [`has_avx512`] report `true`: the f64 kernels
+//! are scalar stand-ins there (see [`kernels`](crate::kernels)), so the SIMD
+//! drivers, and the dispatch that picks them, run like on an AVX-512
+//! machine. [`has_avx512vnni`] and [`has_avx512dq`] report `false`, so the
+//! int8 code takes its portable path.
+
+#[cfg(target_arch = "x86_64")]
+use crate::Unsupported;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// AVX2 and FMA: what the 4×4 and 12×4 kernels need.
+pub fn has_avx2() -> bool {
+    let features = cpu_features();
+    features.avx2 && features.fma
+}
+
+/// AVX-512F and FMA: what the 8×8 kernel needs.
+pub fn has_avx512() -> bool {
+    let features = cpu_features();
+    features.avx512f && features.fma
+}
+
+/// AVX-512F and AVX-512 VNNI: what the 8-bit integer kernel needs.
+pub fn has_avx512vnni() -> bool {
+    let features = cpu_features();
+    features.avx512f && features.avx512vnni
+}
+
+/// AVX-512F and AVX-512DQ: what the int8 requantizing store needs, for its
+/// i64 to f64 conversions.
+pub fn has_avx512dq() -> bool {
+    let features = cpu_features();
+    features.avx512f && features.avx512dq
+}
+
+/// The CPU features this crate cares about, or might soon: what its kernels
+/// need, plus the ones that decide what's worth adding next.
+///
+/// Every field is `false` on targets it doesn't apply to (all the x86 ones on
+/// aarch64, say). `Display` lists the features present, for a startup log
+/// line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CpuFeatures {
+    pub avx: bool,
+    pub avx2: bool,
+    pub fma: bool,
+    pub avx512f: bool,
+    pub avx512dq: bool,
+    pub avx512vl: bool,
+    /// AVX-512 VNNI: 8-bit dot products in 512-bit registers
+    pub avx512vnni: bool,
+    /// AVX-VNNI: the same in 256-bit registers, without AVX-512
+    pub avxvnni: bool,
+    /// aarch64 Advanced SIMD
+    pub neon: bool,
+}
+
+impl fmt::Display for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = [
+            ("avx", self.avx),
+            ("avx2", self.avx2),
+            ("fma", self.fma),
+            ("avx512f", self.avx512f),
+            ("avx512dq", self.avx512dq),
+            ("avx512vl", self.avx512vl),
+            ("avx512vnni", self.avx512vnni),
+            ("avxvnni", self.avxvnni),
+            ("neon", self.neon),
+        ];
+        let present: Vec<&str> = all
+            .iter()
+            .filter(|(_, has)| *has)
+            .map(|(name, _)| *name)
+            .collect();
+        if present.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&present.join(" "))
+        }
+    }
+}
+
+/// This CPU's [`CpuFeatures`], detected on the first call and cached.
+///
+/// ```
+/// println!("CPU features: {}", matmul::cpu_features());
+/// ```
+pub fn cpu_features() -> CpuFeatures {
+    static FEATURES: OnceLock<CpuFeatures> = OnceLock::new();
+    *FEATURES.get_or_init(detect)
+}
+
+#[cfg(all(target_arch = "x86_64", not(miri)))]
+fn detect() -> CpuFeatures {
+    CpuFeatures {
+        avx: is_x86_feature_detected!("avx"),
+        avx2: is_x86_feature_detected!("avx2"),
+        fma: is_x86_feature_detected!("fma"),
+        avx512f: is_x86_feature_detected!("avx512f"),
+        avx512dq: is_x86_feature_detected!("avx512dq"),
+        avx512vl: is_x86_feature_detected!("avx512vl"),
+        avx512vnni: is_x86_feature_detected!("avx512vnni"),
+        avxvnni: is_x86_feature_detected!("avxvnni"),
+        neon: false,
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", not(miri)))]
+fn detect() -> CpuFeatures {
+    CpuFeatures {
+        neon: std::arch::is_aarch64_feature_detected!("neon"),
+        ..CpuFeatures::default()
+    }
+}
+
+/// What the kernels' Miri stand-ins (and the drivers' `target_feature`s)
+/// need, so every driver runs
+#[cfg(miri)]
+fn detect() -> CpuFeatures {
+    CpuFeatures {
+        avx: true,
+        avx2: true,
+        fma: true,
+        avx512f: true,
+        ..CpuFeatures::default()
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", miri)))]
+fn detect() -> CpuFeatures {
+    CpuFeatures::default()
+}
+
+/// `Ok` if [`has_avx2`], else an [`Unsupported`] naming `function`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn require_avx2(function: &'static str) -> Result<(), Unsupported> {
+    if has_avx2() {
+        Ok(())
+    } else {
+        Err(Unsupported {
+            function,
+            requires: "AVX2 and FMA",
+        })
+    }
+}
+
+/// `Ok` if [`has_avx512`], else an [`Unsupported`] naming `function`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn require_avx512(function: &'static str) -> Result<(), Unsupported> {
+    if has_avx512() {
+        Ok(())
+    } else {
+        Err(Unsupported {
+            function,
+            requires: "AVX-512F and FMA",
+        })
+    }
+}
@@ -0,0 +1,232 @@
+//! C API (`capi` feature): a `cblas_dgemm`-style entry point.
+//!
+//! Build the shared library with `cargo build --release --features capi` and
+//! include `include/matmul.h` (generated by cbindgen, see `just header`).
+//!
+//! Unlike CBLAS, which aborts through `xerbla` on bad arguments, the function
+//! returns an error code, and no Rust panic ever crosses the FFI boundary:
+//!
+//! - `MATMUL_SUCCESS` (0) on success
+//! - a positive value `i` when argument `i` (1-based, in signature order) is
+//!   invalid, like the `info` value BLAS passes to `xerbla`
+//! - `MATMUL_ERR_INTERNAL` if the computation itself panicked (a bug)
+
+use std::ffi::c_int;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Row-major storage (CBLAS `CblasRowMajor`)
+pub const CBLAS_ROW_MAJOR: c_int = 101;
+/// Column-major storage (CBLAS `CblasColMajor`)
+pub const CBLAS_COL_MAJOR: c_int = 102;
+/// Use the matrix as stored (CBLAS `CblasNoTrans`)
+pub const CBLAS_NO_TRANS: c_int = 111;
+/// Use the transpose (CBLAS `CblasTrans`)
+pub const CBLAS_TRANS: c_int = 112;
+/// Conjugate transpose; same as `CBLAS_TRANS` for real matrices (CBLAS `CblasConjTrans`)
+pub const CBLAS_CONJ_TRANS: c_int = 113;
+
+/// The call succeeded
+pub const MATMUL_SUCCESS: c_int = 0;
+/// The computation panicked; C may be partially updated
+pub const MATMUL_ERR_INTERNAL: c_int = -1;
+
+/// C = alpha * op(A) * op(B) + beta * C, with the CBLAS `dgemm` signature.
+///
+/// op(A) is m×k, op(B) is k×n, and C is m×n. `lda`, `ldb`, and `ldc` are the
+/// distances between consecutive rows (row-major) or columns (column-major)
+/// of the matrices as stored. As in BLAS, when `beta` is 0, C is not read
+/// (NaNs in it are overwritten), and when `alpha` is 0, A and B are not read.
+///
+/// Returns `MATMUL_SUCCESS`, the 1-based position of the first invalid
+/// argument, or `MATMUL_ERR_INTERNAL`; see the module docs.
+///
+/// # Safety
+///
+/// Every non-null pointer must be valid for the matrix it describes: `a` for
+/// the stored A with leading dimension `lda` (reads), likewise `b`, and `c`
+/// for C with `ldc` (reads and writes). C must not overlap A or B.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn matmul_dgemm(
+    layout: c_int,
+    transa: c_int,
+    transb: c_int,
+    m: c_int,
+    n: c_int,
+    k: c_int,
+    alpha: f64,
+    a: *const f64,
+    lda: c_int,
+    b: *const f64,
+    ldb: c_int,
+    beta: f64,
+    c: *mut f64,
+    ldc: c_int,
+) -> c_int {
+    let args = match validate(
+        layout, transa, transb, m, n, k, alpha, a, lda, b, ldb, c, ldc,
+    ) {
+        Ok(args) => args,
+        Err(position) => return position,
+    };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        dgemm(args, alpha, a, b, beta, c)
+    })) {
+        Ok(()) => MATMUL_SUCCESS,
+        Err(_) => MATMUL_ERR_INTERNAL,
+    }
+}
+
+/// Validated arguments, converted to a row-major problem.
+#[derive(Clone, Copy)]
+struct Args {
+    trans_a: bool,
+    trans_b: bool,
+    m: usize,
+    n: usize,
+    k: usize,
+    lda: usize,
+    ldb: usize,
+    ldc: usize,
+    /// Column-major call rewritten as the row-major C^T = op(B)^T op(A)^T:
+    /// A and B (with their transposes and leading dimensions) trade places.
+    swapped: bool,
+}
+
+/// Checks every argument in signature order and returns the position of the
+/// first bad one.
+#[allow(clippy::too_many_arguments)]
+fn validate(
+    layout: c_int,
+    transa: c_int,
+    transb: c_int,
+    m: c_int,
+    n: c_int,
+    k: c_int,
+    alpha: f64,
+    a: *const f64,
+    lda: c_int,
+    b: *const f64,
+    ldb: c_int,
+    c: *mut f64,
+    ldc: c_int,
+) -> Result<Args, c_int> {
+    let row_major = match layout {
+        CBLAS_ROW_MAJOR => true,
+        CBLAS_COL_MAJOR => false,
+        _ => return Err(1),
+    };
+    let trans_a = parse_trans(transa).ok_or(2)?;
+    let trans_b = parse_trans(transb).ok_or(3)?;
+    let m = usize::try_from(m).map_err(|_| 4)?;
+    let n = usize::try_from(n).map_err(|_| 5)?;
+    let k = usize::try_from(k).map_err(|_| 6)?;
+
+    // Stored shapes: A is m×k or k×m, B is k×n or n×k, C is m×n. The leading
+    // dimension must cover a stored row (row-major) or column (column-major).
+    let (a_rows, a_cols) = if trans_a { (k, m) } else { (m, k) };
+    let (b_rows, b_cols) = if trans_b { (n, k) } else { (k, n) };
+    let min_ld = |rows: usize, cols: usize| (if row_major { cols } else { rows }).max(1);
+
+    // A and B aren't read when alpha is 0, so they may be null then
+    let reads_ab = alpha != 0.0;
+    if a.is_null() && m * k > 0 && reads_ab {
+        return Err(8);
+    }
+    let lda = usize::try_from(lda)
+        .ok()
+        .filter(|&ld| ld >= min_ld(a_rows, a_cols))
+        .ok_or(9)?;
+    if b.is_null() && k * n > 0 && reads_ab {
+        return Err(10);
+    }
+    let ldb = usize::try_from(ldb)
+        .ok()
+        .filter(|&ld| ld >= min_ld(b_rows, b_cols))
+        .ok_or(11)?;
+    if c.is_null() && m * n > 0 {
+        return Err(13);
+    }
+    let ldc = usize::try_from(ldc)
+        .ok()
+        .filter(|&ld| ld >= min_ld(m, n))
+        .ok_or(14)?;
+
+    Ok(if row_major {
+        Args {
+            trans_a,
+            trans_b,
+            m,
+            n,
+            k,
+            lda,
+            ldb,
+            ldc,
+            swapped: false,
+        }
+    } else {
+        Args {
+            trans_a: trans_b,
+            trans_b: trans_a,
+            m: n,
+            n: m,
+            k,
+            lda: ldb,
+            ldb: lda,
+            ldc,
+            swapped: true,
+        }
+    })
+}
+
+fn parse_trans(trans: c_int) -> Option<bool> {
+    match trans {
+        CBLAS_NO_TRANS => Some(false),
+        CBLAS_TRANS | CBLAS_CONJ_TRANS => Some(true),
+        _ => None,
+    }
+}
+
+/// Row-major dgemm on validated arguments.
+///
+/// Hands op(A), op(B), and C to the strided [`dgemm`] as row and column
+/// strides: a transposed operand is column-stored, and a leading dimension
+/// is the stride between rows. It uses operands as they lie when they're
+/// contiguous and copies or transposes them otherwise; see its docs.
+///
+/// [`dgemm`]: crate::interop::matrixmultiply::dgemm
+unsafe fn dgemm(args: Args, alpha: f64, a: *const f64, b: *const f64, beta: f64, c: *mut f64) {
+    let (a, b) = if args.swapped { (b, a) } else { (a, b) };
+    let Args { m, n, k, .. } = args;
+    let (rsa, csa) = strides(args.trans_a, args.lda);
+    let (rsb, csb) = strides(args.trans_b, args.ldb);
+    unsafe {
+        crate::interop::matrixmultiply::dgemm(
+            m,
+            k,
+            n,
+            alpha,
+            a,
+            rsa,
+            csa,
+            b,
+            rsb,
+            csb,
+            beta,
+            c,
+            args.ldc as isize,
+            1,
+        )
+    };
+}
+
+/// The row and column strides of op(X) for X stored row-major with leading
+/// dimension `ld`.
+fn strides(trans: bool, ld: usize) -> (isize, isize) {
+    if trans {
+        (1, ld as isize)
+    } else {
+        (ld as isize, 1)
+    }
+}
@@ -0,0 +1,111 @@
+//! Multiplies whose shape is known at compile time.
+//!
+//! For a 3×3 or 4×4 product the math is a few dozen FLOPs, about what even
+//! [`multiply`](crate::multiply)'s [tiny path](crate::tiny) spends on
+//! checking lengths and picking a loop. [`multiply_fixed`] takes the shape
+//! as const generics and the matrices as arrays, so there's nothing to
+//! check, and every loop has a constant trip count the compiler unrolls. On
+//! x86_64 with AVX2 the same loops are compiled for 256-bit vectors, so a
+//! 4-wide row of C is one register.
+
+/// C += A·B for A M×K, B K×N, and C M×N, as arrays of rows.
+///
+/// Sums in the same order as [`matmul_naive_ikj`](crate::matmul_naive_ikj),
+/// with separate multiplies and adds, so the result is bit for bit the
+/// same, with or without AVX2.
+///
+/// ```
+/// use matmul::multiply_fixed;
+///
+/// let rotate = [[0.0, -1.0], [1.0, 0.0]];
+/// let point = [[2.0], [3.0]];
+/// let mut out = [[0.0]; 2];
+/// multiply_fixed(&rotate, &point, &mut out);
+/// assert_eq!(out, [[-3.0], [2.0]]);
+/// ```
+#[inline]
+pub fn multiply_fixed<const M: usize, const N: usize, const K: usize>(
+    a: &[[f64; K]; M],
+    b: &[[f64; N]; K],
+    c: &mut [[f64; N]; M],
+) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // Nothing to vectorize in a single column
+        if N > 1 && std::is_x86_feature_detected!("avx2") {
+            unsafe { fixed_avx2(a, b, c) };
+            return;
+        }
+    }
+    fixed(a, b, c);
+}
+
+/// The loops [`multiply_fixed`] runs: each row of C held in a local while
+/// the K rows of B are added into it.
+#[inline(always)]
+fn fixed<const M: usize, const N: usize, const K: usize>(
+    a: &[[f64; K]; M],
+    b: &[[f64; N]; K],
+    c: &mut [[f64; N]; M],
+) {
+    for (a_row, c_row) in a.iter().zip(c.iter_mut()) {
+        let mut acc = *c_row;
+        for (&a_ip, b_row) in a_row.iter().zip(b) {
+            for (x, &b_pj) in acc.iter_mut().zip(b_row) {
+                *x += a_ip * b_pj;
+            }
+        }
+        *c_row = acc;
+    }
+}
+
+/// [`fixed`] compiled for AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn fixed_avx2<const M: usize, const N: usize, const K: usize>(
+    a: &[[f64; K]; M],
+    b: &[[f64; N]; K],
+    c: &mut [[f64; N]; M],
+) {
+    fixed(a, b, c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matmul_naive_ikj;
+    use crate::matrix::generate::random;
+
+    /// `multiply_fixed` against the i-k-j loop on the flattened matrices.
+    fn check<const M: usize, const N: usize, const K: usize>() {
+        let (a_flat, b_flat, c_flat) = (random(M, K, 1), random(K, N, 2), random(M, N, 3));
+        let a: [[f64; K]; M] = std::array::from_fn(|i| std::array::from_fn(|p| a_flat[i * K + p]));
+        let b: [[f64; N]; K] = std::array::from_fn(|p| std::array::from_fn(|j| b_flat[p * N + j]));
+        let mut c: [[f64; N]; M] =
+            std::array::from_fn(|i| std::array::from_fn(|j| c_flat[i * N + j]));
+
+        let mut want = c_flat.clone();
+        matmul_naive_ikj(&a_flat, &b_flat, &mut want, M, N, K);
+        multiply_fixed(&a, &b, &mut c);
+        assert_eq!(c.as_flattened(), want, "{}x{}x{}", M, N, K);
+
+        // The portable loops too, wherever the AVX2 ones ran above
+        let mut c: [[f64; N]; M] =
+            std::array::from_fn(|i| std::array::from_fn(|j| c_flat[i * N + j]));
+        fixed(&a, &b, &mut c);
+        assert_eq!(c.as_flattened(), want, "{}x{}x{}", M, N, K);
+    }
+
+    #[test]
+    fn test_matches_naive() {
+        check::<3, 3, 3>();
+        check::<4, 4, 4>();
+        check::<6, 6, 6>();
+        check::<8, 8, 8>();
+        check::<1, 1, 1>();
+        check::<2, 5, 3>();
+        check::<5, 1, 7>();
+        check::<3, 4, 0>();
+        check::<0, 4, 3>();
+    }
+}
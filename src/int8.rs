@@ -0,0 +1,844 @@
+//! 8-bit integer GEMM: C += A·B for i8 A and B, summed in i32 or i64.
+//!
+//! A product of two i8s is at most 2¹⁴ in magnitude ((-128)·(-128)), so an
+//! i32 sum of them is exact for up to [`ACC32_MAX_K`] products and can wrap
+//! past that. [`multiply_i8_acc32`] keeps C in i32 and is the faster of the
+//! two; [`multiply_i8_acc64`] sums each [`KC`]-deep block of k in i32, where
+//! it can't overflow, and adds the block's sums into an i64 C, exact for any
+//! k a machine can hold.
+//!
+//! With AVX-512 VNNI, each block runs on `vpdpbusd`, four i8 products per
+//! 32-bit lane per instruction; elsewhere, on a scalar loop. Integer sums
+//! are exact or wrap the same way in any order, so the two agree exactly.
+//!
+//! [`multiply_i8_requantize`] is the multiply quantized inference runs: the
+//! same kernels on the stored values, then a store that takes out the zero
+//! points, adds a bias, scales each column, and saturates to i8 or u8, tile
+//! by tile as the kernels finish them.
+
+use crate::error::check_operands;
+use std::ops::Range;
+
+/// The largest k for which an i32 sum of k i8 products can't overflow,
+/// whatever the inputs: `i32::MAX / 2¹⁴`, 131,071. [`multiply_i8_acc32`] is
+/// exact up to here, from a zero C.
+pub const ACC32_MAX_K: usize = i32::MAX as usize / (128 * 128);
+
+/// Depth of the blocks of k summed in i32 before they're added to C; at
+/// most [`ACC32_MAX_K`], so a block's sums are exact.
+pub const KC: usize = 4096;
+
+/// Columns of C per VNNI tile: one 512-bit register of i32 sums
+const NR: usize = 16;
+
+/// Rows of C per tile, each its own accumulator register
+const MR: usize = 4;
+
+/// C's element type for [`multiply_i8`]: i32 or i64. Sealed.
+pub trait Accumulator: Copy + private::Sealed {
+    /// Where a tile's sums start.
+    const ZERO: Self;
+
+    /// Adds a block's i32 sums into a tile.
+    fn add_block(tile: &mut [Self], sums: &[i32]);
+
+    /// Adds a finished tile into C.
+    fn add_tile(c: &mut [Self], tile: &[Self]);
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for i32 {}
+    impl Sealed for i64 {}
+    impl Sealed for i8 {}
+    impl Sealed for u8 {}
+}
+
+/// Wrapping, like the sums within a block: the result is the true one
+/// modulo 2³² whatever k is.
+impl Accumulator for i32 {
+    const ZERO: i32 = 0;
+
+    fn add_block(tile: &mut [i32], sums: &[i32]) {
+        Self::add_tile(tile, sums);
+    }
+
+    fn add_tile(c: &mut [i32], tile: &[i32]) {
+        for (c, &sum) in c.iter_mut().zip(tile) {
+            *c = c.wrapping_add(sum);
+        }
+    }
+}
+
+/// Widened and added: exact until C itself passes i64, about 2⁴⁹ worst-case
+/// products.
+impl Accumulator for i64 {
+    const ZERO: i64 = 0;
+
+    fn add_block(tile: &mut [i64], sums: &[i32]) {
+        for (x, &sum) in tile.iter_mut().zip(sums) {
+            *x = x.wrapping_add(i64::from(sum));
+        }
+    }
+
+    fn add_tile(c: &mut [i64], tile: &[i64]) {
+        for (c, &sum) in c.iter_mut().zip(tile) {
+            *c = c.wrapping_add(sum);
+        }
+    }
+}
+
+/// C += A·B with i32 sums. Matrices are row-major: A is m×k, B is k×n, and
+/// C is m×n.
+///
+/// Exact while every element of C stays within i32: from a zero C, for any
+/// inputs with k up to [`ACC32_MAX_K`]. Past that, worst-case inputs (all
+/// -128, say) wrap in two's complement, leaving the true sum modulo 2³²;
+/// use [`multiply_i8_acc64`] if they can occur.
+///
+/// ```
+/// use matmul::int8::{ACC32_MAX_K, multiply_i8_acc32};
+///
+/// let k = ACC32_MAX_K;
+/// let (a, b) = (vec![-128i8; k], vec![-128i8; k]);
+/// let mut c = [0i32];
+/// multiply_i8_acc32(&a, &b, &mut c, 1, 1, k);
+/// assert_eq!(c[0], 16384 * k as i32);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_i8_acc32(a: &[i8], b: &[i8], c: &mut [i32], m: usize, n: usize, k: usize) {
+    multiply_i8(a, b, c, m, n, k);
+}
+
+/// C += A·B with each [`KC`]-deep block summed in i32 and added into an i64
+/// C, so no input can overflow it short of about 2⁴⁹ worst-case products per
+/// element. Layout as for [`multiply_i8_acc32`].
+///
+/// ```
+/// use matmul::int8::{ACC32_MAX_K, multiply_i8_acc64};
+///
+/// let k = ACC32_MAX_K + 1;
+/// let (a, b) = (vec![-128i8; k], vec![-128i8; k]);
+/// let mut c = [0i64];
+/// multiply_i8_acc64(&a, &b, &mut c, 1, 1, k);
+/// assert_eq!(c[0], 1 << 31);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_i8_acc64(a: &[i8], b: &[i8], c: &mut [i64], m: usize, n: usize, k: usize) {
+    multiply_i8(a, b, c, m, n, k);
+}
+
+/// C += A·B over i8 A and B, summing in `Acc`; what [`multiply_i8_acc32`]
+/// and [`multiply_i8_acc64`] call.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_i8<Acc: Accumulator>(
+    a: &[i8],
+    b: &[i8],
+    c: &mut [Acc],
+    m: usize,
+    n: usize,
+    k: usize,
+) {
+    if let Err(e) = check_operands([
+        ("A", a.len(), m, k),
+        ("B", b.len(), k, n),
+        ("C", c.len(), m, n),
+    ]) {
+        panic!("{}", e);
+    }
+
+    tiles(a, b, m, n, k, |rows, tile: &[Acc]| {
+        Acc::add_tile(&mut c[rows.start * n..rows.end * n], tile)
+    });
+}
+
+/// How [`multiply_i8_requantize`] maps A·B back to 8 bits: the affine
+/// quantization of A, B, and C, with a scale per column of C.
+///
+/// A stored value q stands for `scale·(q - zero_point)`. With A and B's
+/// zero points taken off, the exact integer sum for Cᵢⱼ is
+/// Σₚ (Aᵢₚ - a_zero_point)·(Bₚⱼ - b_zero_point), plus `bias[j]`;
+/// `scales[j]` (A's scale times B's column scale over C's, usually) takes
+/// that to C's units.
+#[derive(Debug, Clone, Copy)]
+pub struct Requantize<'a> {
+    /// The stored value of A meaning zero
+    pub a_zero_point: i8,
+    /// The stored value of B meaning zero
+    pub b_zero_point: i8,
+    /// One per column of C (output channel); finite
+    pub scales: &'a [f32],
+    /// One per column of C, added to the sums before scaling, if any
+    pub bias: Option<&'a [i32]>,
+    /// The stored value of C meaning zero
+    pub c_zero_point: i32,
+}
+
+/// C's element type for [`multiply_i8_requantize`]: i8 or u8. Sealed.
+pub trait Quantized: Copy + private::Sealed {
+    /// The smallest value, as f64
+    const MIN: f64;
+    /// The largest value, as f64
+    const MAX: f64;
+
+    /// A whole number from `MIN` to `MAX`, converted.
+    fn from_f64(x: f64) -> Self;
+}
+
+impl Quantized for i8 {
+    const MIN: f64 = i8::MIN as f64;
+    const MAX: f64 = i8::MAX as f64;
+
+    fn from_f64(x: f64) -> i8 {
+        x as i8
+    }
+}
+
+impl Quantized for u8 {
+    const MIN: f64 = u8::MIN as f64;
+    const MAX: f64 = u8::MAX as f64;
+
+    fn from_f64(x: f64) -> u8 {
+        x as u8
+    }
+}
+
+/// Cᵢⱼ = clamp(round(scaleⱼ·(Σₚ (Aᵢₚ - a_zp)·(Bₚⱼ - b_zp) + biasⱼ)) +
+/// c_zp), with the zero points, scales, and bias from `requantize`, and the
+/// clamp to Q's range. Layout as for [`multiply_i8_acc32`]; C is
+/// overwritten, not added to.
+///
+/// The kernels multiply the stored values as they are, summing in i64 like
+/// [`multiply_i8_acc64`], and the zero points come out in the store:
+/// Σ (a - a_zp)(b - b_zp) = Σ ab - b_zp·Σₚ a - a_zp·Σₚ b + k·a_zp·b_zp, a
+/// sum per row of A and per column of B. That store, with AVX-512DQ, runs
+/// eight columns at a time on each tile as it leaves the kernel. The scaling is
+/// one f64 multiply of the exact sum, rounded half to even, so the result
+/// is the same on every path, and the same as dequantizing to f64,
+/// multiplying, and requantizing, while the sums stay below 2⁵³.
+///
+/// ```
+/// use matmul::int8::{Requantize, multiply_i8_requantize};
+///
+/// // A row of 10s times a column of 3s, zero points 1 and -1
+/// let (a, b) = ([10i8; 4], [3i8; 4]);
+/// let mut c = [0u8];
+/// let requantize = Requantize {
+///     a_zero_point: 1,
+///     b_zero_point: -1,
+///     scales: &[0.25],
+///     bias: Some(&[-4]),
+///     c_zero_point: 100,
+/// };
+/// multiply_i8_requantize(&a, &b, &mut c, 1, 1, 4, &requantize);
+/// // 4·(9·4) - 4 = 140, a quarter of that is 35, plus 100
+/// assert_eq!(c, [135]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k, if there isn't a scale
+/// (and bias, if given) per column, or if a scale isn't finite.
+pub fn multiply_i8_requantize<Q: Quantized>(
+    a: &[i8],
+    b: &[i8],
+    c: &mut [Q],
+    m: usize,
+    n: usize,
+    k: usize,
+    requantize: &Requantize,
+) {
+    if let Err(e) = check_operands([
+        ("A", a.len(), m, k),
+        ("B", b.len(), k, n),
+        ("C", c.len(), m, n),
+    ]) {
+        panic!("{}", e);
+    }
+    let Requantize {
+        a_zero_point,
+        b_zero_point,
+        scales,
+        bias,
+        c_zero_point,
+    } = *requantize;
+    assert!(
+        scales.len() == n,
+        "{} scales for {} columns of C",
+        scales.len(),
+        n
+    );
+    assert!(
+        scales.iter().all(|s| s.is_finite()),
+        "the scales must be finite"
+    );
+    if let Some(bias) = bias {
+        assert!(
+            bias.len() == n,
+            "{} biases for {} columns of C",
+            bias.len(),
+            n
+        );
+    }
+
+    // What each column adds to its sums: the bias, less a_zp·Σₚ B[p][j],
+    // plus k·a_zp·b_zp
+    let (a_zero_point, b_zero_point) = (i64::from(a_zero_point), i64::from(b_zero_point));
+    let mut column_offsets = vec![k as i64 * a_zero_point * b_zero_point; n];
+    if let Some(bias) = bias {
+        for (offset, &bias) in column_offsets.iter_mut().zip(bias) {
+            *offset += i64::from(bias);
+        }
+    }
+    for b_row in b.chunks_exact(n.max(1)).take(k) {
+        for (offset, &b_pj) in column_offsets.iter_mut().zip(b_row) {
+            *offset -= a_zero_point * i64::from(b_pj);
+        }
+    }
+    let epilogue = Epilogue {
+        column_offsets,
+        scales: scales.iter().map(|&s| f64::from(s)).collect(),
+        c_zero_point: f64::from(c_zero_point),
+    };
+
+    tiles(a, b, m, n, k, |rows, tile: &[i64]| {
+        for (i, tile_row) in rows.zip(tile.chunks_exact(n)) {
+            // And what the row takes off: b_zp·Σₚ A[i][p]
+            let row_sum: i64 = a[i * k..(i + 1) * k].iter().map(|&x| i64::from(x)).sum();
+            epilogue.store(
+                tile_row,
+                -b_zero_point * row_sum,
+                &mut c[i * n..(i + 1) * n],
+            );
+        }
+    });
+}
+
+/// A·B (A m×k, B k×n, checked) MR rows at a time: `finish(rows, tile)` gets
+/// each tile of rows, rows × n, summed from zero in `Acc`. On the VNNI
+/// kernel where the CPU has it, on [`rows_scalar`] where it doesn't.
+fn tiles<Acc: Accumulator>(
+    a: &[i8],
+    b: &[i8],
+    m: usize,
+    n: usize,
+    k: usize,
+    finish: impl FnMut(Range<usize>, &[Acc]),
+) {
+    #[cfg(target_arch = "x86_64")]
+    if crate::features::has_avx512vnni() {
+        // Every row tile runs through every block, so each is packed once
+        let blocks: Vec<vnni::PackedB> = (0..k)
+            .step_by(KC)
+            .map(|kk| vnni::PackedB::new(b, n, kk..(kk + KC).min(k)))
+            .collect();
+        blocked(
+            m,
+            n,
+            k,
+            |depth, rows, sums| {
+                // Safety: just checked
+                unsafe { vnni::rows(a, &blocks[depth.start / KC], sums, rows, k) }
+            },
+            finish,
+        );
+        return;
+    }
+    blocked(
+        m,
+        n,
+        k,
+        |depth, rows, sums| rows_scalar(a, b, sums, rows, n, k, depth),
+        finish,
+    );
+}
+
+/// The blocking around `sum_rows(depth, rows, sums)`, which sets `sums`
+/// (rows × n, zeroed) to A[rows, depth]·B[depth, :] for one KC-deep block
+/// `depth` of k. MR rows at a time, each block's sums are added into a tile
+/// of `Acc`, and the finished tile goes to `finish(rows, tile)`; for k = 0,
+/// a tile of zeros.
+fn blocked<Acc: Accumulator>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mut sum_rows: impl FnMut(Range<usize>, Range<usize>, &mut [i32]),
+    mut finish: impl FnMut(Range<usize>, &[Acc]),
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+    let mut sums = vec![0; MR * n];
+    let mut tile = vec![Acc::ZERO; MR * n];
+    for i in (0..m).step_by(MR) {
+        let rows = i..(i + MR).min(m);
+        let tile = &mut tile[..rows.len() * n];
+        tile.fill(Acc::ZERO);
+        for kk in (0..k).step_by(KC) {
+            let sums = &mut sums[..rows.len() * n];
+            sums.fill(0);
+            sum_rows(kk..(kk + KC).min(k), rows.clone(), sums);
+            Acc::add_block(tile, sums);
+        }
+        finish(rows, tile);
+    }
+}
+
+/// `sums` (rows × n) += A[rows, depth]·B[depth, :] in plain Rust, one
+/// product at a time in ascending k, wrapping.
+fn rows_scalar(
+    a: &[i8],
+    b: &[i8],
+    sums: &mut [i32],
+    rows: Range<usize>,
+    n: usize,
+    k: usize,
+    depth: Range<usize>,
+) {
+    for (i, sums_row) in rows.zip(sums.chunks_exact_mut(n)) {
+        for p in depth.clone() {
+            let a_ip = i32::from(a[i * k + p]);
+            for (sum, &b_pj) in sums_row.iter_mut().zip(&b[p * n..(p + 1) * n]) {
+                *sum = sum.wrapping_add(a_ip * i32::from(b_pj));
+            }
+        }
+    }
+}
+
+/// The per-column half of [`multiply_i8_requantize`]'s store.
+struct Epilogue {
+    /// Added to every sum in the column, with the row's offset
+    column_offsets: Vec<i64>,
+    scales: Vec<f64>,
+    c_zero_point: f64,
+}
+
+impl Epilogue {
+    /// Sets `out` to a row's `sums` plus `row_offset` and the column
+    /// offsets, scaled, rounded, shifted by C's zero point, and clamped.
+    fn store<Q: Quantized>(&self, sums: &[i64], row_offset: i64, out: &mut [Q]) {
+        #[cfg(target_arch = "x86_64")]
+        if crate::features::has_avx512dq() {
+            // Safety: just checked
+            unsafe { self.store_avx512(sums, row_offset, out) };
+            return;
+        }
+        self.store_scalar(sums, row_offset, out);
+    }
+
+    /// [`store`](Self::store) a column at a time.
+    fn store_scalar<Q: Quantized>(&self, sums: &[i64], row_offset: i64, out: &mut [Q]) {
+        for (j, (&sum, out)) in sums.iter().zip(out).enumerate() {
+            let scaled = ((sum + row_offset + self.column_offsets[j]) as f64 * self.scales[j])
+                .round_ties_even();
+            *out = Q::from_f64((scaled + self.c_zero_point).clamp(Q::MIN, Q::MAX));
+        }
+    }
+
+    /// [`store`](Self::store) eight columns at a time, each step the scalar
+    /// loop's: an exact i64 add, a rounded conversion and multiply, an exact
+    /// rounding, add, and clamp. The last few columns are masked.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX-512F and AVX-512DQ.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx512f,avx512dq")]
+    unsafe fn store_avx512<Q: Quantized>(&self, sums: &[i64], row_offset: i64, out: &mut [Q]) {
+        use std::arch::x86_64::*;
+
+        let n = sums.len();
+        assert!(out.len() == n && self.scales.len() == n && self.column_offsets.len() == n);
+        let row_offset = _mm512_set1_epi64(row_offset);
+        let c_zero_point = _mm512_set1_pd(self.c_zero_point);
+        let (min, max) = (_mm512_set1_pd(Q::MIN), _mm512_set1_pd(Q::MAX));
+        for j in (0..n).step_by(8) {
+            let mask: __mmask8 = (0xff_u16 >> 8usize.saturating_sub(n - j)) as u8;
+            // Safety: the mask stops the loads and the store at n, which
+            // all four slices are
+            unsafe {
+                let sums = _mm512_maskz_loadu_epi64(mask, sums.as_ptr().add(j));
+                let offsets = _mm512_maskz_loadu_epi64(mask, self.column_offsets.as_ptr().add(j));
+                let scales = _mm512_maskz_loadu_pd(mask, self.scales.as_ptr().add(j));
+                let sums = _mm512_add_epi64(_mm512_add_epi64(sums, row_offset), offsets);
+                let scaled = _mm512_roundscale_pd::<
+                    { _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC },
+                >(_mm512_mul_pd(_mm512_cvtepi64_pd(sums), scales));
+                let clamped =
+                    _mm512_max_pd(_mm512_min_pd(_mm512_add_pd(scaled, c_zero_point), max), min);
+                // Q is one byte, i8 or u8, and the values are in its range,
+                // so their low bytes are Q's bits
+                _mm512_mask_cvtepi64_storeu_epi8(
+                    out.as_mut_ptr().add(j).cast(),
+                    mask,
+                    _mm512_cvtpd_epi64(clamped),
+                );
+            }
+        }
+    }
+}
+
+/// The `vpdpbusd` path. The instruction multiplies four unsigned bytes by
+/// four signed ones and adds the four products to an i32 lane. A is made
+/// unsigned by adding 128 (flipping its top bit), which adds 128·Σₚ B[p][j]
+/// to every sum in column j; that's taken off again at the end. Both sides
+/// wrap, so the sums are [`rows_scalar`]'s exactly.
+#[cfg(target_arch = "x86_64")]
+mod vnni {
+    use super::{KC, MR, NR};
+    use std::ops::Range;
+
+    /// One block of B, for every 16-column panel of C: four k steps at a
+    /// time, the four values of each column adjacent, one register per
+    /// group of four. Columns past n are zero.
+    pub(super) struct PackedB {
+        n: usize,
+        /// The block of k packed
+        depth: Range<usize>,
+        values: Vec<i8>,
+        /// 128·Σₚ B[p][j] over the block, per column
+        corrections: Vec<i32>,
+    }
+
+    impl PackedB {
+        /// The block `depth` of B (k×n), packed.
+        pub(super) fn new(b: &[i8], n: usize, depth: Range<usize>) -> PackedB {
+            let groups = depth.len().div_ceil(4);
+            let panels = n.div_ceil(NR);
+            let mut values = vec![0; panels * groups * 4 * NR];
+            let mut corrections = vec![0i32; panels * NR];
+            for (p, b_row) in b[depth.start * n..depth.end * n]
+                .chunks_exact(n)
+                .enumerate()
+            {
+                for (j, &b_pj) in b_row.iter().enumerate() {
+                    let (panel, jj) = (j / NR, j % NR);
+                    values[((panel * groups + p / 4) * NR + jj) * 4 + p % 4] = b_pj;
+                    corrections[j] = corrections[j].wrapping_add(128 * i32::from(b_pj));
+                }
+            }
+            PackedB {
+                n,
+                depth,
+                values,
+                corrections,
+            }
+        }
+    }
+
+    /// `sums` (rows × n) += A[rows, depth]·B[depth, :] for the block `b`
+    /// holds.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX-512F and AVX-512 VNNI.
+    #[target_feature(enable = "avx512f,avx512vnni")]
+    pub(super) unsafe fn rows(
+        a: &[i8],
+        b: &PackedB,
+        sums: &mut [i32],
+        rows: Range<usize>,
+        k: usize,
+    ) {
+        use std::arch::x86_64::*;
+
+        let (n, kk, depth) = (b.n, b.depth.start, b.depth.len());
+        let groups = depth.div_ceil(4);
+        // A's rows for the block as unsigned bytes, padded to whole groups
+        // (the padding meets B's zeros)
+        let mut a_pack = [[0u8; KC]; MR];
+        for (a_row, i) in a_pack.iter_mut().zip(rows.clone()) {
+            for (x, &a_ip) in a_row.iter_mut().zip(&a[i * k + kk..][..depth]) {
+                *x = a_ip as u8 ^ 0x80;
+            }
+        }
+
+        for (panel, j) in (0..n).step_by(NR).enumerate() {
+            let width = NR.min(n - j);
+            let values = &b.values[panel * groups * 4 * NR..][..groups * 4 * NR];
+            let mut acc = [_mm512_setzero_si512(); MR];
+            for (g, b_group) in values.chunks_exact(4 * NR).enumerate() {
+                // Safety: a group is one register's worth
+                let b_vec = unsafe { _mm512_loadu_si512(b_group.as_ptr().cast()) };
+                for (acc, a_row) in acc.iter_mut().zip(&a_pack).take(rows.len()) {
+                    let a_group = i32::from_le_bytes(a_row[4 * g..4 * g + 4].try_into().unwrap());
+                    *acc = _mm512_dpbusd_epi32(*acc, _mm512_set1_epi32(a_group), b_vec);
+                }
+            }
+
+            let corrections = &b.corrections[panel * NR..][..NR];
+            // Safety: a panel's corrections are one register's worth
+            let correction = unsafe { _mm512_loadu_si512(corrections.as_ptr().cast()) };
+            for (r, &acc) in acc.iter().enumerate().take(rows.len()) {
+                let mut out = [0i32; NR];
+                // Safety: `out` is one register's worth
+                unsafe {
+                    _mm512_storeu_si512(out.as_mut_ptr().cast(), _mm512_sub_epi32(acc, correction))
+                };
+                for (sum, &x) in sums[r * n + j..][..width].iter_mut().zip(&out) {
+                    *sum = sum.wrapping_add(x);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::{random, random_of};
+
+    /// C += A·B in i64, one product at a time.
+    fn reference(a: &[i8], b: &[i8], c: &mut [i64], m: usize, n: usize, k: usize) {
+        for i in 0..m {
+            for p in 0..k {
+                for j in 0..n {
+                    c[i * n + j] += i64::from(a[i * k + p]) * i64::from(b[p * n + j]);
+                }
+            }
+        }
+    }
+
+    /// [`multiply_i8`] on [`rows_scalar`], whatever the CPU.
+    fn multiply_scalar<Acc: Accumulator>(
+        a: &[i8],
+        b: &[i8],
+        c: &mut [Acc],
+        m: usize,
+        n: usize,
+        k: usize,
+    ) {
+        blocked(
+            m,
+            n,
+            k,
+            |depth, rows, sums| rows_scalar(a, b, sums, rows, n, k, depth),
+            |rows, tile: &[Acc]| Acc::add_tile(&mut c[rows.start * n..rows.end * n], tile),
+        );
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        // Edge rows and columns, k not a whole number of groups, and (the
+        // last) more than one block
+        let shapes = [
+            (1, 1, 1),
+            (5, 17, 3),
+            (4, 16, 4),
+            (9, 33, 71),
+            (6, 20, KC + 5),
+        ];
+        for (seed, &(m, n, k)) in shapes.iter().enumerate() {
+            let seed = 3 * seed as u64;
+            let a: Vec<i8> = random_of(m, k, seed);
+            let b: Vec<i8> = random_of(k, n, seed + 1);
+            let c_initial: Vec<i8> = random_of(m, n, seed + 2);
+
+            let mut want: Vec<i64> = c_initial.iter().map(|&x| i64::from(x)).collect();
+            reference(&a, &b, &mut want, m, n, k);
+
+            let mut c64: Vec<i64> = c_initial.iter().map(|&x| i64::from(x)).collect();
+            multiply_i8_acc64(&a, &b, &mut c64, m, n, k);
+            assert_eq!(c64, want, "acc64 on {}x{}x{}", m, n, k);
+
+            let mut c32: Vec<i32> = c_initial.iter().map(|&x| i32::from(x)).collect();
+            multiply_i8_acc32(&a, &b, &mut c32, m, n, k);
+            let c32: Vec<i64> = c32.into_iter().map(i64::from).collect();
+            assert_eq!(c32, want, "acc32 on {}x{}x{}", m, n, k);
+
+            let mut scalar: Vec<i64> = c_initial.iter().map(|&x| i64::from(x)).collect();
+            multiply_scalar(&a, &b, &mut scalar, m, n, k);
+            assert_eq!(scalar, want, "scalar on {}x{}x{}", m, n, k);
+        }
+    }
+
+    /// All-extreme inputs at the last k an i32 holds and the first it
+    /// doesn't, on two rows and 17 columns so the VNNI path's edges run too.
+    #[test]
+    #[cfg_attr(miri, ignore = "2×17×131,072 products")]
+    fn test_adversarial_inputs_around_the_i32_bound() {
+        let (m, n) = (2, 17);
+        for (a_value, b_value) in [(-128i8, -128i8), (127, -128), (127, 127)] {
+            let product = i64::from(a_value) * i64::from(b_value);
+            for k in [ACC32_MAX_K, ACC32_MAX_K + 1] {
+                let (a, b) = (vec![a_value; m * k], vec![b_value; k * n]);
+                let exact = product * k as i64;
+
+                let mut c64 = vec![0i64; m * n];
+                multiply_i8_acc64(&a, &b, &mut c64, m, n, k);
+                assert!(c64.iter().all(|&x| x == exact), "acc64 at k {}", k);
+
+                let mut c32 = vec![0i32; m * n];
+                multiply_i8_acc32(&a, &b, &mut c32, m, n, k);
+                // Wrapped past the bound, exact up to it
+                assert!(c32.iter().all(|&x| x == exact as i32), "acc32 at k {}", k);
+                let mut scalar = vec![0i32; m * n];
+                multiply_scalar(&a, &b, &mut scalar, m, n, k);
+                assert_eq!(scalar, c32, "scalar acc32 at k {}", k);
+                // Only (-128)² products pass i32 one past the bound
+                let fits = i32::try_from(exact).is_ok();
+                assert_eq!(fits, k <= ACC32_MAX_K || product.abs() < 128 * 128);
+            }
+        }
+    }
+
+    /// C for [`multiply_i8_requantize`] the long way: A and B dequantized
+    /// (less their zero points) to f64, multiplied, the bias added, then
+    /// scaled, rounded, and shifted back per element.
+    fn requantize_reference<Q: Quantized>(
+        a: &[i8],
+        b: &[i8],
+        m: usize,
+        n: usize,
+        k: usize,
+        requantize: &Requantize,
+    ) -> Vec<Q> {
+        let a: Vec<f64> = a
+            .iter()
+            .map(|&x| f64::from(x) - f64::from(requantize.a_zero_point))
+            .collect();
+        let b: Vec<f64> = b
+            .iter()
+            .map(|&x| f64::from(x) - f64::from(requantize.b_zero_point))
+            .collect();
+        let mut c = vec![0.0; m * n];
+        crate::matrix::naive_ikj::matmul_naive_ikj(&a, &b, &mut c, m, n, k);
+        c.iter()
+            .enumerate()
+            .map(|(at, &x)| {
+                let j = at % n;
+                let bias = requantize.bias.map_or(0.0, |bias| f64::from(bias[j]));
+                let scaled = ((x + bias) * f64::from(requantize.scales[j])).round_ties_even();
+                Q::from_f64((scaled + f64::from(requantize.c_zero_point)).clamp(Q::MIN, Q::MAX))
+            })
+            .collect()
+    }
+
+    /// Random zero points, scales spanning a few powers of ten (so some
+    /// elements clamp), and biases, on shapes with edge rows and columns.
+    #[test]
+    fn test_requantize_matches_reference() {
+        let shapes = [
+            (1, 1, 1),
+            (5, 17, 3),
+            (9, 33, 71),
+            (6, 20, KC + 5),
+            (3, 9, 0),
+        ];
+        for (seed, &(m, n, k)) in shapes.iter().enumerate() {
+            let seed = 10 * seed as u64;
+            let a: Vec<i8> = random_of(m, k, seed);
+            let b: Vec<i8> = random_of(k, n, seed + 1);
+            let zero_points: Vec<i8> = random_of(1, 3, seed + 2);
+            let scales: Vec<f32> = random(1, n, seed + 3)
+                .iter()
+                .map(|&x| 10f64.powf(1.5 * x - 3.0) as f32)
+                .collect();
+            let bias: Vec<i32> = random(1, n, seed + 4)
+                .iter()
+                .map(|&x| (x * 50_000.0) as i32)
+                .collect();
+            for bias in [None, Some(&bias[..])] {
+                let requantize = Requantize {
+                    a_zero_point: zero_points[0],
+                    b_zero_point: zero_points[1],
+                    scales: &scales,
+                    bias,
+                    c_zero_point: i32::from(zero_points[2]),
+                };
+                let want: Vec<i8> = requantize_reference(&a, &b, m, n, k, &requantize);
+                let mut c = vec![0i8; m * n];
+                multiply_i8_requantize(&a, &b, &mut c, m, n, k, &requantize);
+                assert_eq!(c, want, "i8 on {}x{}x{}", m, n, k);
+
+                let requantize = Requantize {
+                    c_zero_point: i32::from(zero_points[2]) + 128,
+                    ..requantize
+                };
+                let want: Vec<u8> = requantize_reference(&a, &b, m, n, k, &requantize);
+                let mut c = vec![0u8; m * n];
+                multiply_i8_requantize(&a, &b, &mut c, m, n, k, &requantize);
+                assert_eq!(c, want, "u8 on {}x{}x{}", m, n, k);
+            }
+        }
+    }
+
+    /// The vector store against the scalar one, over sums rounding to
+    /// ties (scale ½ on odd sums), clamping both ways, and every column
+    /// count up to a few vectors.
+    #[test]
+    fn test_requantize_stores_agree() {
+        #[cfg(target_arch = "x86_64")]
+        if crate::features::has_avx512dq() {
+            for n in 0..=19 {
+                let sums: Vec<i64> = (0..n as i64).map(|j| (j - 9) * 37 + 1).collect();
+                let epilogue = Epilogue {
+                    column_offsets: (0..n as i64).map(|j| j * 1000 - 8000).collect(),
+                    scales: (0..n)
+                        .map(|j| if j % 2 == 0 { 0.5 } else { 0.01 })
+                        .collect(),
+                    c_zero_point: -3.0,
+                };
+                for row_offset in [-5, 0, 7, 100_000] {
+                    let mut scalar = vec![0i8; n];
+                    epilogue.store_scalar(&sums, row_offset, &mut scalar);
+                    let mut vector = vec![0i8; n + 1];
+                    unsafe { epilogue.store_avx512(&sums, row_offset, &mut vector[..n]) };
+                    assert_eq!(vector[..n], scalar, "{} columns", n);
+                    assert_eq!(vector[n], 0, "a store past the row");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_requantize_ties_to_even() {
+        let requantize = Requantize {
+            a_zero_point: 0,
+            b_zero_point: 0,
+            scales: &[0.5],
+            bias: None,
+            c_zero_point: 0,
+        };
+        let mut c = [0i8; 4];
+        multiply_i8_requantize(&[1, 3, 5, -3], &[1], &mut c, 4, 1, 1, &requantize);
+        assert_eq!(c, [0, 2, 2, -2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "3 scales for 2 columns of C")]
+    fn test_requantize_wrong_scales_panics() {
+        let requantize = Requantize {
+            a_zero_point: 0,
+            b_zero_point: 0,
+            scales: &[1.0; 3],
+            bias: None,
+            c_zero_point: 0,
+        };
+        multiply_i8_requantize(&[0; 4], &[0; 4], &mut [0u8; 4], 2, 2, 2, &requantize);
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut c = vec![7i32; 6];
+        multiply_i8_acc32(&[], &[], &mut c, 2, 3, 0);
+        assert_eq!(c, [7; 6]);
+        multiply_i8_acc64(&[], &[0; 4], &mut [], 0, 4, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "B")]
+    fn test_wrong_length_panics() {
+        let mut c = [0i64; 4];
+        multiply_i8_acc64(&[0; 4], &[0; 3], &mut c, 2, 2, 2);
+    }
+}
@@ -0,0 +1,106 @@
+//! System BLAS reference (`blas-bench` feature).
+//!
+//! Links the system OpenBLAS (`libopenblas`, e.g. from `libopenblas-dev`) so
+//! the benchmark can print our kernels next to `cblas_dgemm` and the tests
+//! can check our results against an implementation that shares no code with
+//! this crate. Nothing else depends on it; the default build has no native
+//! dependencies.
+
+use cblas_sys::{CBLAS_LAYOUT, CBLAS_TRANSPOSE, cblas_dgemm};
+use std::ffi::c_int;
+
+// cblas-sys only declares the functions; this pulls in the library itself
+#[link(name = "openblas")]
+unsafe extern "C" {
+    fn openblas_set_num_threads(num_threads: c_int);
+}
+
+/// C += A * B with the system BLAS `cblas_dgemm`.
+///
+/// Same row-major layout and accumulate semantics as
+/// [`multiply`](crate::multiply), so the two are interchangeable in a
+/// benchmark.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k, or a dimension doesn't fit
+/// in a C `int`.
+pub fn multiply_blas(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    crate::error::assert_dims(a, b, c, m, n, k);
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let int = |x: usize| c_int::try_from(x).expect("dimension too large for BLAS");
+    // BLAS wants leading dimensions of at least 1, even for empty matrices
+    unsafe {
+        cblas_dgemm(
+            CBLAS_LAYOUT::CblasRowMajor,
+            CBLAS_TRANSPOSE::CblasNoTrans,
+            CBLAS_TRANSPOSE::CblasNoTrans,
+            int(m),
+            int(n),
+            int(k),
+            1.0,
+            a.as_ptr(),
+            int(k.max(1)),
+            b.as_ptr(),
+            int(n),
+            1.0,
+            c.as_mut_ptr(),
+            int(n),
+        )
+    };
+}
+
+/// Sets how many threads OpenBLAS uses for later calls.
+///
+/// OpenBLAS defaults to one thread per core; set 1 to compare against the
+/// single-threaded kernels.
+pub fn set_blas_threads(num_threads: usize) {
+    let num_threads = c_int::try_from(num_threads.max(1)).unwrap_or(c_int::MAX);
+    unsafe { openblas_set_num_threads(num_threads) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+
+    // Cross-check against an independent implementation: ragged sizes hit
+    // every kernel's edge handling, larger ones the cache blocking
+    #[test]
+    fn test_multiply_matches_blas() {
+        for (m, n, k) in [
+            (1, 1, 1),
+            (7, 5, 3),
+            (13, 17, 19),
+            (64, 64, 64),
+            (130, 67, 300),
+            (257, 129, 513),
+        ] {
+            let a = random(m, k, 1);
+            let b = random(k, n, 2);
+            let c0 = random(m, n, 3);
+
+            let mut expected = c0.clone();
+            multiply_blas(&a, &b, &mut expected, m, n, k);
+
+            let mut c = c0.clone();
+            crate::multiply(&a, &b, &mut c, m, n, k);
+            assert_close(&expected, &c, 1e-10, 1e-10);
+
+            let mut c = c0.clone();
+            crate::multiply_parallel(&a, &b, &mut c, m, n, k, 4);
+            assert_close(&expected, &c, 1e-10, 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_empty_inner_dimension_leaves_c() {
+        let mut c = vec![3.0; 6];
+        multiply_blas(&[], &[], &mut c, 2, 3, 0);
+        assert_eq!(c, vec![3.0; 6]);
+    }
+}
@@ -0,0 +1,691 @@
+//! `matrixmultiply`-compatible `dgemm`.
+//!
+//! Same signature and semantics as `matrixmultiply::dgemm`, which ndarray and
+//! nalgebra call with arbitrary row and column strides, so callers can swap
+//! backends by changing one path. No dependency on `matrixmultiply` itself.
+//!
+//! The kernels only understand contiguous row-major buffers, so each operand
+//! is brought to that form the cheapest way its strides allow:
+//!
+//! - C decides the orientation. A C stored by columns is computed as the
+//!   row-major Cᵀ = Bᵀ·Aᵀ, so row-major and column-major C both have their
+//!   unit stride along the kernel's rows.
+//! - An operand stored that way with no gaps is used in place.
+//! - One whose rows are contiguous but spaced out (a padded submatrix, or
+//!   a flipped view with a negative row stride) is copied a row at a time.
+//! - One stored the other way round (a transposed view) goes through the
+//!   blocked SIMD [`transpose_strided`], padded or not.
+//! - Anything else (zero, interleaved, or negative column strides) is
+//!   gathered element by element.
+//!
+//! A C the kernels can't accumulate into in place (padded, strided, or with
+//! alpha ≠ 1) gets the product in a temporary first, written back through its
+//! strides.
+
+use crate::matrix::elementwise;
+use crate::matrix::transpose::transpose_strided;
+use std::borrow::Cow;
+
+/// C = alpha * A * B + beta * C, with `matrixmultiply::dgemm`'s signature.
+///
+/// A is m×k, B is k×n, and C is m×n. Element (i, j) of A is at
+/// `a.offset(i * rsa + j * csa)`, and likewise for B and C, so any strides
+/// work: row-major or column-major, padded, transposed views, negative ones
+/// (a flipped view), and zero ones in A or B (a broadcast). When `beta` is 0,
+/// C is not read, so it may hold NaNs or be uninitialized; when k is 0, C is
+/// just scaled by `beta`.
+///
+/// # Panics
+///
+/// Panics if a stride of C is 0 along a dimension longer than 1: C's
+/// elements would share memory, and there's no single answer to write there.
+///
+/// # Safety
+///
+/// Same contract as `matrixmultiply::dgemm`: every element address the
+/// strides describe must be valid for reads (A, B) or reads and writes (C),
+/// and C's elements must be distinct (no overlapping C strides) and must not
+/// overlap A or B. With m, n, or k zero, the corresponding pointers are not
+/// dereferenced.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn dgemm(
+    m: usize,
+    k: usize,
+    n: usize,
+    alpha: f64,
+    a: *const f64,
+    rsa: isize,
+    csa: isize,
+    b: *const f64,
+    rsb: isize,
+    csb: isize,
+    beta: f64,
+    c: *mut f64,
+    rsc: isize,
+    csc: isize,
+) {
+    for (stride, len, dim) in [(rsc, m, "row"), (csc, n, "column")] {
+        assert!(
+            stride != 0 || len <= 1,
+            "C's {} stride is 0 with {} {}s; its elements must be distinct",
+            dim,
+            len,
+            dim
+        );
+    }
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    if k == 0 || alpha == 0.0 {
+        unsafe { scale_strided(c, m, n, rsc, csc, beta) };
+        return;
+    }
+
+    let (rsa, csa) = normalize(m, k, rsa, csa);
+    let (rsb, csb) = normalize(k, n, rsb, csb);
+    let (rsc, csc) = normalize(m, n, rsc, csc);
+    if csc != 1 && rsc == 1 {
+        // Cᵀ = Bᵀ·Aᵀ: n×m, with every operand's strides swapped
+        unsafe { dgemm_rows(n, k, m, alpha, b, csb, rsb, a, csa, rsa, beta, c, csc, rsc) };
+    } else {
+        unsafe { dgemm_rows(m, k, n, alpha, a, rsa, csa, b, rsb, csb, beta, c, rsc, csc) };
+    }
+}
+
+/// [`dgemm`] for non-empty operands with [`normalize`]d strides, computed
+/// into C row by row.
+#[allow(clippy::too_many_arguments)]
+unsafe fn dgemm_rows(
+    m: usize,
+    k: usize,
+    n: usize,
+    alpha: f64,
+    a: *const f64,
+    rsa: isize,
+    csa: isize,
+    b: *const f64,
+    rsb: isize,
+    csb: isize,
+    beta: f64,
+    c: *mut f64,
+    rsc: isize,
+    csc: isize,
+) {
+    let a = unsafe { row_major(a, m, k, rsa, csa) };
+    let b = unsafe { row_major(b, k, n, rsb, csb) };
+
+    // In place: C is one contiguous row-major buffer the kernel can
+    // accumulate into
+    if alpha == 1.0 && csc == 1 && rsc == n as isize {
+        let c = unsafe { std::slice::from_raw_parts_mut(c, m * n) };
+        prepare_c(c, beta);
+        crate::multiply(&a, &b, c, m, n, k);
+        return;
+    }
+
+    let mut product = vec![0.0; m * n];
+    crate::multiply(&a, &b, &mut product, m, n, k);
+    for i in 0..m {
+        for j in 0..n {
+            let cij = unsafe { c.offset(i as isize * rsc + j as isize * csc) };
+            let p = alpha * product[i * n + j];
+            // beta == 0 must not read C
+            unsafe { *cij = if beta == 0.0 { p } else { p + beta * *cij } };
+        }
+    }
+}
+
+/// Strides with a dimension of 1 made canonical: a single row's row stride
+/// and a single column's column stride never move to a second element, so
+/// they're set to what a contiguous row-major matrix would have.
+fn normalize(rows: usize, cols: usize, rs: isize, cs: isize) -> (isize, isize) {
+    (
+        if rows == 1 { cols as isize } else { rs },
+        if cols == 1 { 1 } else { cs },
+    )
+}
+
+/// The rows × cols matrix at `x` with [`normalize`]d strides, as a
+/// contiguous row-major slice: `x` itself if it's stored that way, otherwise
+/// a copy, made the cheapest way the strides allow.
+///
+/// # Safety
+///
+/// Every element address the strides describe must be valid for reads.
+unsafe fn row_major<'a>(
+    x: *const f64,
+    rows: usize,
+    cols: usize,
+    rs: isize,
+    cs: isize,
+) -> Cow<'a, [f64]> {
+    if cs == 1 && rs == cols as isize {
+        return Cow::Borrowed(unsafe { std::slice::from_raw_parts(x, rows * cols) });
+    }
+    let mut out = vec![0.0; rows * cols];
+    if cs == 1 {
+        // Contiguous rows, anywhere: padded, flipped, or even overlapping
+        for (i, row) in out.chunks_exact_mut(cols).enumerate() {
+            row.copy_from_slice(unsafe {
+                std::slice::from_raw_parts(x.offset(i as isize * rs), cols)
+            });
+        }
+    } else if rs == 1 && cs >= rows as isize {
+        // Stored by columns: a transpose of the cols × rows matrix there
+        let stored = unsafe { std::slice::from_raw_parts(x, (cols - 1) * cs as usize + rows) };
+        transpose_strided(stored, cs as usize, &mut out, cols, cols, rows);
+    } else {
+        for (i, row) in out.chunks_exact_mut(cols).enumerate() {
+            for (j, x_ij) in row.iter_mut().enumerate() {
+                *x_ij = unsafe { *x.offset(i as isize * rs + j as isize * cs) };
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// C = beta * C ahead of accumulating the product into C.
+fn prepare_c(c: &mut [f64], beta: f64) {
+    if beta == 0.0 {
+        // Overwrite rather than scale, so NaNs in C don't survive
+        c.fill(0.0);
+    } else if beta != 1.0 {
+        elementwise::scale(c, beta);
+    }
+}
+
+/// C = beta * C through C's strides, without reading C when beta is 0.
+unsafe fn scale_strided(c: *mut f64, m: usize, n: usize, rsc: isize, csc: isize, beta: f64) {
+    if beta == 1.0 {
+        return;
+    }
+    for i in 0..m {
+        for j in 0..n {
+            let cij = unsafe { c.offset(i as isize * rsc + j as isize * csc) };
+            unsafe { *cij = if beta == 0.0 { 0.0 } else { beta * *cij } };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+
+    /// A logical rows × cols matrix stored with the given strides inside a
+    /// buffer, starting at `offset` (so negative strides stay in bounds).
+    struct Strided {
+        data: Vec<f64>,
+        offset: usize,
+        rs: isize,
+        cs: isize,
+    }
+
+    /// Layouts from matrixmultiply's stride tests: row-major ("C") and
+    /// column-major ("F"), each with the major and minor strides spread out
+    /// by a factor, plus flipped (negative stride) variants.
+    #[derive(Clone, Copy, Debug)]
+    enum Layout {
+        C,
+        F,
+    }
+
+    fn strided(
+        values: &[f64],
+        rows: usize,
+        cols: usize,
+        layout: Layout,
+        spread: (isize, isize),
+        flip: bool,
+    ) -> Strided {
+        let (mut rs, cs) = match layout {
+            Layout::C => (cols as isize * spread.0 * spread.1, spread.1),
+            Layout::F => (spread.0, rows as isize * spread.0 * spread.1),
+        };
+        let len = ((rows.max(1) - 1) as isize * rs + (cols.max(1) - 1) as isize * cs + 1) as usize;
+        // Padding stays NaN, so reading it would poison the result
+        let mut data = vec![f64::NAN; len];
+        let mut offset = 0;
+        if flip {
+            // Walk rows backwards: row i is stored where row rows-1-i would be
+            offset = (rows.max(1) - 1) * rs as usize;
+            rs = -rs;
+        }
+        for i in 0..rows {
+            for j in 0..cols {
+                data[(offset as isize + i as isize * rs + j as isize * cs) as usize] =
+                    values[i * cols + j];
+            }
+        }
+        Strided {
+            data,
+            offset,
+            rs,
+            cs,
+        }
+    }
+
+    impl Strided {
+        fn get(&self, i: usize, j: usize) -> f64 {
+            self.data[(self.offset as isize + i as isize * self.rs + j as isize * self.cs) as usize]
+        }
+
+        fn to_row_major(&self, rows: usize, cols: usize) -> Vec<f64> {
+            (0..rows)
+                .flat_map(|i| (0..cols).map(move |j| (i, j)))
+                .map(|(i, j)| self.get(i, j))
+                .collect()
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn reference(
+        a: &[f64],
+        b: &[f64],
+        c: &[f64],
+        m: usize,
+        k: usize,
+        n: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> Vec<f64> {
+        let mut out = vec![0.0; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                let dot: f64 = (0..k).map(|p| a[i * k + p] * b[p * n + j]).sum();
+                let prior = if beta == 0.0 {
+                    0.0
+                } else {
+                    beta * c[i * n + j]
+                };
+                out[i * n + j] = alpha * dot + prior;
+            }
+        }
+        out
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        m: usize,
+        k: usize,
+        n: usize,
+        alpha: f64,
+        beta: f64,
+        layouts: [Layout; 3],
+        spread: (isize, isize),
+        flip: bool,
+    ) {
+        let a_vals = random(m, k, 1);
+        let b_vals = random(k, n, 2);
+        let c_vals = random(m, n, 3);
+        let expected = reference(&a_vals, &b_vals, &c_vals, m, k, n, alpha, beta);
+
+        let a = strided(&a_vals, m, k, layouts[0], spread, flip);
+        let b = strided(&b_vals, k, n, layouts[1], spread, flip);
+        let mut c = strided(&c_vals, m, n, layouts[2], spread, flip);
+        let (rsc, csc) = (c.rs, c.cs);
+        let c_ptr = unsafe { c.data.as_mut_ptr().add(c.offset) };
+
+        unsafe {
+            dgemm(
+                m,
+                k,
+                n,
+                alpha,
+                a.data.as_ptr().add(a.offset),
+                a.rs,
+                a.cs,
+                b.data.as_ptr().add(b.offset),
+                b.rs,
+                b.cs,
+                beta,
+                c_ptr,
+                rsc,
+                csc,
+            )
+        };
+
+        assert_close(&expected, &c.to_row_major(m, n), 1e-10, 1e-10);
+        // Nothing outside C's elements was written
+        let touched = c.data.iter().filter(|x| !x.is_nan()).count();
+        assert_eq!(touched, m * n, "{:?} {:?} {}", layouts, spread, flip);
+    }
+
+    // matrixmultiply's `test_mul_with_id`
+    #[test]
+    fn test_mul_with_identity() {
+        for n in [1, 2, 3, 8, 17, 33] {
+            let a = random(n, n, 1);
+            let id = crate::matrix::generate::identity(n);
+            let mut c = vec![0.0; n * n];
+            unsafe {
+                dgemm(
+                    n,
+                    n,
+                    n,
+                    1.0,
+                    a.as_ptr(),
+                    n as isize,
+                    1,
+                    id.as_ptr(),
+                    n as isize,
+                    1,
+                    0.0,
+                    c.as_mut_ptr(),
+                    n as isize,
+                    1,
+                )
+            };
+            assert_eq!(a, c);
+        }
+    }
+
+    // matrixmultiply's size sweep, contiguous row-major, various alpha/beta
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; test_strides covers the same path")]
+    fn test_sizes_and_scaling() {
+        for (m, k, n) in [
+            (1, 1, 1),
+            (4, 4, 4),
+            (5, 7, 3),
+            (16, 1, 9),
+            (33, 65, 17),
+            (128, 64, 96),
+        ] {
+            for (alpha, beta) in [(1.0, 0.0), (1.0, 1.0), (2.5, 0.0), (-0.5, 3.0), (1.0, 0.5)] {
+                run(m, k, n, alpha, beta, [Layout::C; 3], (1, 1), false);
+            }
+        }
+    }
+
+    // matrixmultiply's `test_strides`: every C/F combination, spread strides
+    #[test]
+    fn test_strides() {
+        use Layout::{C, F};
+        let (m, k, n) = (13, 9, 11);
+        for layouts in [
+            [C, C, C],
+            [F, F, F],
+            [C, F, C],
+            [F, C, F],
+            [C, C, F],
+            [F, F, C],
+        ] {
+            for spread in [(1, 1), (2, 1), (1, 3), (2, 2)] {
+                for (alpha, beta) in [(1.0, 0.0), (1.0, 1.0), (0.5, 2.0)] {
+                    run(m, k, n, alpha, beta, layouts, spread, false);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_negative_strides() {
+        use Layout::{C, F};
+        for layouts in [[C, C, C], [F, F, F], [C, F, C]] {
+            for spread in [(1, 1), (2, 2)] {
+                run(10, 7, 6, 1.0, 1.0, layouts, spread, true);
+                run(10, 7, 6, 2.0, 0.0, layouts, spread, true);
+            }
+        }
+    }
+
+    /// Where a rows × cols operand lives in its buffer: element (i, j) at
+    /// `offset + i·rs + j·cs`.
+    #[derive(Clone, Copy, Debug)]
+    struct View {
+        offset: usize,
+        rs: isize,
+        cs: isize,
+        len: usize,
+    }
+
+    impl View {
+        fn at(&self, i: usize, j: usize) -> usize {
+            (self.offset as isize + i as isize * self.rs + j as isize * self.cs) as usize
+        }
+    }
+
+    /// The stride patterns callers hand over, for a rows × cols operand.
+    fn views(rows: usize, cols: usize) -> [(&'static str, View); 6] {
+        let (r, c) = (rows as isize, cols as isize);
+        let view = |offset, rs, cs, len| View {
+            offset,
+            rs,
+            cs,
+            len,
+        };
+        [
+            ("row-major", view(0, c, 1, rows * cols)),
+            ("column-major", view(0, 1, r, rows * cols)),
+            // Rows 2.. and columns 1.. of a row-major matrix 5 rows and 3
+            // columns bigger
+            (
+                "padded submatrix",
+                view(2 * (cols + 3) + 1, c + 3, 1, (rows + 5) * (cols + 3)),
+            ),
+            // The transpose of a row-major cols × (rows + 4) matrix's left part
+            ("transposed view", view(0, 1, r + 4, cols * (rows + 4))),
+            // Row-major, last row first
+            ("flipped rows", view((rows - 1) * cols, -c, 1, rows * cols)),
+            // Every other element of every other row
+            ("strided", view(0, 4 * c, 2, 4 * rows * cols)),
+        ]
+    }
+
+    /// A buffer holding `values` (row-major) where `view` puts them, and
+    /// NaN everywhere else.
+    fn place(values: &[f64], cols: usize, view: View) -> Vec<f64> {
+        let mut buffer = vec![f64::NAN; view.len];
+        for (index, &value) in values.iter().enumerate() {
+            buffer[view.at(index / cols, index % cols)] = value;
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_stride_patterns() {
+        let (m, k, n) = if cfg!(miri) { (5, 3, 4) } else { (13, 9, 11) };
+        let (a_vals, b_vals, c_vals) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+        for (a_name, av) in views(m, k) {
+            for (b_name, bv) in views(k, n) {
+                for (c_name, cv) in views(m, n) {
+                    for (alpha, beta) in [(1.0, 1.0), (1.0, 0.0), (0.5, 2.0)] {
+                        let a = place(&a_vals, k, av);
+                        let b = place(&b_vals, n, bv);
+                        let mut c = place(&c_vals, n, cv);
+
+                        // Indexing through the strides themselves
+                        let mut want = c.clone();
+                        for i in 0..m {
+                            for j in 0..n {
+                                let dot: f64 =
+                                    (0..k).map(|p| a[av.at(i, p)] * b[bv.at(p, j)]).sum();
+                                let prior = if beta == 0.0 {
+                                    0.0
+                                } else {
+                                    beta * want[cv.at(i, j)]
+                                };
+                                want[cv.at(i, j)] = alpha * dot + prior;
+                            }
+                        }
+
+                        unsafe {
+                            dgemm(
+                                m,
+                                k,
+                                n,
+                                alpha,
+                                a.as_ptr().add(av.offset),
+                                av.rs,
+                                av.cs,
+                                b.as_ptr().add(bv.offset),
+                                bv.rs,
+                                bv.cs,
+                                beta,
+                                c.as_mut_ptr().add(cv.offset),
+                                cv.rs,
+                                cv.cs,
+                            )
+                        };
+                        let case = format!(
+                            "A {}, B {}, C {}, alpha {}, beta {}",
+                            a_name, b_name, c_name, alpha, beta
+                        );
+                        for (x, y) in c.iter().zip(&want) {
+                            // Outside C, still NaN: nothing else was written
+                            assert_eq!(x.is_nan(), y.is_nan(), "{}", case);
+                            if !x.is_nan() {
+                                assert!((x - y).abs() <= 1e-12 * (1.0 + y.abs()), "{}", case);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "C's row stride is 0 with 3 rows")]
+    fn test_zero_c_stride_panics() {
+        let (a, b) = (random(3, 2, 1), random(2, 2, 2));
+        let mut c = vec![0.0; 2];
+        unsafe {
+            dgemm(
+                3,
+                2,
+                2,
+                1.0,
+                a.as_ptr(),
+                2,
+                1,
+                b.as_ptr(),
+                2,
+                1,
+                0.0,
+                c.as_mut_ptr(),
+                0,
+                1,
+            )
+        };
+    }
+
+    #[test]
+    fn test_broadcast_zero_stride() {
+        // A row vector repeated down every row of A
+        let (m, k, n) = (5, 4, 3);
+        let row = [1.0, 2.0, 3.0, 4.0];
+        let b = random(k, n, 2);
+        let mut c = vec![0.0; m * n];
+        unsafe {
+            dgemm(
+                m,
+                k,
+                n,
+                1.0,
+                row.as_ptr(),
+                0,
+                1,
+                b.as_ptr(),
+                n as isize,
+                1,
+                0.0,
+                c.as_mut_ptr(),
+                n as isize,
+                1,
+            )
+        };
+        let a: Vec<f64> = (0..m).flat_map(|_| row).collect();
+        assert_close(&reference(&a, &b, &c, m, k, n, 1.0, 0.0), &c, 1e-12, 1e-12);
+    }
+
+    #[test]
+    fn test_beta_zero_overwrites_nan() {
+        for layout in [Layout::C, Layout::F] {
+            let (m, k, n) = (6, 5, 7);
+            let a = random(m, k, 1);
+            let b = random(k, n, 2);
+            let mut c = vec![f64::NAN; m * n];
+            let (rsc, csc) = match layout {
+                Layout::C => (n as isize, 1),
+                Layout::F => (1, m as isize),
+            };
+            unsafe {
+                dgemm(
+                    m,
+                    k,
+                    n,
+                    1.0,
+                    a.as_ptr(),
+                    k as isize,
+                    1,
+                    b.as_ptr(),
+                    n as isize,
+                    1,
+                    0.0,
+                    c.as_mut_ptr(),
+                    rsc,
+                    csc,
+                )
+            };
+            assert!(c.iter().all(|x| x.is_finite()), "{:?}", layout);
+        }
+    }
+
+    // matrixmultiply's `test_zero` cases: empty dimensions
+    #[test]
+    fn test_zero_dimensions() {
+        // m or n zero: nothing is touched, even through dangling pointers
+        let dangling = std::ptr::NonNull::<f64>::dangling().as_ptr();
+        unsafe {
+            dgemm(
+                0, 3, 0, 1.0, dangling, 3, 1, dangling, 0, 1, 0.0, dangling, 0, 1,
+            )
+        };
+
+        // k zero: C = beta * C
+        let mut c = vec![2.0; 6];
+        unsafe {
+            dgemm(
+                2,
+                0,
+                3,
+                1.0,
+                dangling,
+                0,
+                1,
+                dangling,
+                3,
+                1,
+                0.5,
+                c.as_mut_ptr(),
+                3,
+                1,
+            )
+        };
+        assert_eq!(c, vec![1.0; 6]);
+
+        let mut c = vec![f64::NAN; 6];
+        unsafe {
+            dgemm(
+                2,
+                0,
+                3,
+                1.0,
+                dangling,
+                0,
+                1,
+                dangling,
+                3,
+                1,
+                0.0,
+                c.as_mut_ptr(),
+                3,
+                1,
+            )
+        };
+        assert_eq!(c, vec![0.0; 6]);
+    }
+}
@@ -0,0 +1,16 @@
+//! Adapters for other crates' matrix types and calling conventions.
+//!
+//! - `blas`: the system BLAS `cblas_dgemm`, as a benchmark and test reference
+//! - `matrixmultiply`: a `dgemm` with `matrixmultiply::dgemm`'s signature
+//!   (always available; it needs no dependency)
+//! - `peers`: `faer` and `matrixmultiply` behind `multiply`'s signature, for
+//!   benchmarks and as test references (`compare-rust` feature)
+//! - `nalgebra`: multiply `DMatrix<f64>`s in place, without copying
+
+#[cfg(feature = "blas-bench")]
+pub mod blas;
+pub mod matrixmultiply;
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra;
+#[cfg(feature = "compare-rust")]
+pub mod peers;
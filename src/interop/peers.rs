@@ -0,0 +1,137 @@
+//! Pure-Rust peers (`compare-rust` feature): `faer` and `matrixmultiply`.
+//!
+//! Shims that put both crates behind [`multiply`](crate::multiply)'s
+//! signature, so the benchmark can time them side by side and the tests can
+//! use them as independent references. Both wrap the caller's row-major
+//! buffers as strided views; nothing is copied.
+
+use faer::linalg::matmul::matmul;
+use faer::{Accum, MatMut, MatRef, Par};
+
+/// C += A * B with faer, single-threaded.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_faer(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    crate::error::assert_dims(a, b, c, m, n, k);
+
+    let a = MatRef::from_row_major_slice(a, m, k);
+    let b = MatRef::from_row_major_slice(b, k, n);
+    let c = MatMut::from_row_major_slice_mut(c, m, n);
+    matmul(c, Accum::Add, a, b, 1.0, Par::Seq);
+}
+
+/// C += A * B with `matrixmultiply::dgemm` (single-threaded by default).
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_matrixmultiply(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    crate::error::assert_dims(a, b, c, m, n, k);
+
+    // Row-major strides: a row is k (or n) elements, a column step is 1
+    unsafe {
+        ::matrixmultiply::dgemm(
+            m,
+            k,
+            n,
+            1.0,
+            a.as_ptr(),
+            k as isize,
+            1,
+            b.as_ptr(),
+            n as isize,
+            1,
+            1.0,
+            c.as_mut_ptr(),
+            n as isize,
+            1,
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+
+    #[test]
+    fn test_multiply_matches_peers() {
+        for (m, n, k) in [
+            (1, 1, 1),
+            (7, 5, 3),
+            (13, 17, 19),
+            (64, 64, 64),
+            (130, 67, 300),
+            (257, 129, 513),
+        ] {
+            let a = random(m, k, 1);
+            let b = random(k, n, 2);
+            let c0 = random(m, n, 3);
+
+            let mut c_faer = c0.clone();
+            multiply_faer(&a, &b, &mut c_faer, m, n, k);
+            let mut c_mm = c0.clone();
+            multiply_matrixmultiply(&a, &b, &mut c_mm, m, n, k);
+            assert_close(&c_faer, &c_mm, 1e-10, 1e-10);
+
+            let mut c = c0.clone();
+            crate::multiply(&a, &b, &mut c, m, n, k);
+            assert_close(&c_faer, &c, 1e-10, 1e-10);
+
+            let mut c = c0.clone();
+            crate::multiply_parallel(&a, &b, &mut c, m, n, k, 4);
+            assert_close(&c_faer, &c, 1e-10, 1e-10);
+        }
+    }
+
+    // Our matrixmultiply-compatible entry point against the real one,
+    // with a column-major C
+    #[test]
+    fn test_dgemm_matches_matrixmultiply() {
+        let (m, n, k) = (37, 23, 41);
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let c0 = random(m, n, 3);
+
+        let mut expected = c0.clone();
+        let mut c = c0.clone();
+        unsafe {
+            ::matrixmultiply::dgemm(
+                m,
+                k,
+                n,
+                0.5,
+                a.as_ptr(),
+                k as isize,
+                1,
+                b.as_ptr(),
+                n as isize,
+                1,
+                2.0,
+                expected.as_mut_ptr(),
+                1,
+                m as isize,
+            );
+            crate::interop::matrixmultiply::dgemm(
+                m,
+                k,
+                n,
+                0.5,
+                a.as_ptr(),
+                k as isize,
+                1,
+                b.as_ptr(),
+                n as isize,
+                1,
+                2.0,
+                c.as_mut_ptr(),
+                1,
+                m as isize,
+            );
+        }
+        assert_close(&expected, &c, 1e-12, 1e-12);
+    }
+}
@@ -0,0 +1,354 @@
+//! Minimal `.npy` reader and writer for 2-D f64 matrices.
+//!
+//! Enough of NumPy's format to exchange matrices with the Python benchmarks:
+//! `np.save("a.npy", a)` on a float64 matrix produces a file `read_npy` can
+//! load, and `write_npy` output loads with `np.load`. The format is a magic
+//! string, a version, a Python dict literal describing dtype, order, and
+//! shape, then the raw data.
+//!
+//! Only little-endian f64 (`<f8`), C order, and 2-D shapes are accepted;
+//! anything else is an error rather than a silent conversion.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// A row-major matrix loaded from a `.npy` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyMatrix {
+    pub data: Vec<f64>,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// Why a `.npy` file couldn't be read or written.
+#[derive(Debug)]
+pub enum NpyError {
+    Io(std::io::Error),
+    /// Missing magic string, unknown version, or a header that doesn't parse
+    Format(String),
+    /// A dtype other than `<f8`
+    Dtype(String),
+    /// `fortran_order: True`
+    FortranOrder,
+    /// A shape that isn't 2-D
+    Shape(String),
+    /// Fewer data bytes than the shape needs
+    Truncated {
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl fmt::Display for NpyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NpyError::Io(e) => write!(f, "{}", e),
+            NpyError::Format(msg) => write!(f, "not a valid .npy file: {}", msg),
+            NpyError::Dtype(descr) => write!(
+                f,
+                "unsupported dtype '{}': expected '<f8' (little-endian float64)",
+                descr
+            ),
+            NpyError::FortranOrder => write!(
+                f,
+                "array is in Fortran order; save a C-order copy (np.ascontiguousarray)"
+            ),
+            NpyError::Shape(shape) => write!(f, "expected a 2-D shape, got {}", shape),
+            NpyError::Truncated { expected, got } => write!(
+                f,
+                "truncated data: shape needs {} bytes, file has {}",
+                expected, got
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NpyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NpyError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for NpyError {
+    fn from(e: std::io::Error) -> Self {
+        NpyError::Io(e)
+    }
+}
+
+/// Loads a 2-D float64 matrix from a `.npy` file.
+pub fn read_npy(path: impl AsRef<Path>) -> Result<NpyMatrix, NpyError> {
+    parse_npy(&fs::read(path)?)
+}
+
+/// Saves a row-major rows × cols matrix as a `.npy` file.
+///
+/// # Panics
+///
+/// Panics if `data.len() != rows * cols`.
+pub fn write_npy(
+    path: impl AsRef<Path>,
+    data: &[f64],
+    rows: usize,
+    cols: usize,
+) -> Result<(), NpyError> {
+    fs::write(path, encode_npy(data, rows, cols))?;
+    Ok(())
+}
+
+/// Parses the bytes of a `.npy` file.
+pub fn parse_npy(bytes: &[u8]) -> Result<NpyMatrix, NpyError> {
+    let format = |msg: &str| NpyError::Format(msg.to_string());
+
+    if !bytes.starts_with(MAGIC) {
+        return Err(format("missing \\x93NUMPY magic string"));
+    }
+    // Version 1 has a 2-byte header length; versions 2 and 3 a 4-byte one
+    let (len_bytes, header_start) = match bytes.get(MAGIC.len()) {
+        Some(1) => (2, MAGIC.len() + 4),
+        Some(2 | 3) => (4, MAGIC.len() + 6),
+        Some(v) => return Err(NpyError::Format(format!("unsupported version {}", v))),
+        None => return Err(format("file ends inside the preamble")),
+    };
+    let len_field = bytes
+        .get(MAGIC.len() + 2..MAGIC.len() + 2 + len_bytes)
+        .ok_or_else(|| format("file ends inside the preamble"))?;
+    let header_len = len_field
+        .iter()
+        .rev()
+        .fold(0usize, |acc, &byte| (acc << 8) | byte as usize);
+    let data_start = header_start + header_len;
+    let header = bytes
+        .get(header_start..data_start)
+        .ok_or_else(|| format("file ends inside the header"))?;
+    let header = std::str::from_utf8(header).map_err(|_| format("header is not text"))?;
+
+    let descr = header_value(header, "descr")?;
+    let descr = descr.trim_matches(|c| c == '\'' || c == '"');
+    if descr != "<f8" {
+        return Err(NpyError::Dtype(descr.to_string()));
+    }
+    match header_value(header, "fortran_order")? {
+        "False" => {}
+        "True" => return Err(NpyError::FortranOrder),
+        other => {
+            return Err(NpyError::Format(format!(
+                "bad fortran_order value '{}'",
+                other
+            )));
+        }
+    }
+    let shape = header_value(header, "shape")?;
+    let (rows, cols) = parse_shape(shape)?;
+
+    let data = &bytes[data_start..];
+    let expected = rows
+        .checked_mul(cols)
+        .and_then(|elements| elements.checked_mul(8))
+        .ok_or_else(|| NpyError::Format(format!("shape ({}, {}) is too large", rows, cols)))?;
+    if data.len() < expected {
+        return Err(NpyError::Truncated {
+            expected,
+            got: data.len(),
+        });
+    }
+    let data = data[..expected]
+        .chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+
+    Ok(NpyMatrix { data, rows, cols })
+}
+
+/// Encodes a row-major rows × cols matrix as `.npy` (version 1.0) bytes.
+///
+/// # Panics
+///
+/// Panics if `data.len() != rows * cols`.
+pub fn encode_npy(data: &[f64], rows: usize, cols: usize) -> Vec<u8> {
+    assert_eq!(
+        data.len(),
+        rows * cols,
+        "expected {}x{}={} elements",
+        rows,
+        cols,
+        rows * cols
+    );
+
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, cols
+    );
+    // NumPy pads with spaces and a newline so the data starts 64-byte aligned
+    let preamble = MAGIC.len() + 4;
+    let padded = (preamble + header.len() + 1).next_multiple_of(64);
+    header.extend(std::iter::repeat_n(
+        ' ',
+        padded - preamble - header.len() - 1,
+    ));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(padded + data.len() * 8);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for &x in data {
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+    out
+}
+
+/// The raw text of `key`'s value in the header dict.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, NpyError> {
+    let missing = || NpyError::Format(format!("header has no '{}' key", key));
+    let start = header
+        .find(&format!("'{}'", key))
+        .or_else(|| header.find(&format!("\"{}\"", key)))
+        .ok_or_else(missing)?;
+    let rest = &header[start + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':').ok_or_else(missing)?;
+    let rest = rest.trim_start();
+
+    // A tuple runs to its closing parenthesis; anything else to the next comma
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    }
+    .ok_or_else(|| NpyError::Format(format!("unterminated '{}' value", key)))?;
+    Ok(rest[..end].trim())
+}
+
+fn parse_shape(shape: &str) -> Result<(usize, usize), NpyError> {
+    let bad = || NpyError::Shape(shape.to_string());
+    let dims: Vec<usize> = shape
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(bad)?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_end_matches('L').parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    match dims[..] {
+        [rows, cols] => Ok((rows, cols)),
+        _ => Err(bad()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header bytes as NumPy writes them, with the real preamble.
+    fn npy_with_header(header: &str, data: &[f64]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&[1, 0]);
+        out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        out.extend_from_slice(header.as_bytes());
+        for &x in data {
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = [1.0, -2.5, 3.25, f64::MAX, f64::MIN_POSITIVE, 0.0];
+        let bytes = encode_npy(&data, 2, 3);
+
+        let m = parse_npy(&bytes).unwrap();
+        assert_eq!((m.rows, m.cols), (2, 3));
+        assert_eq!(m.data, data);
+    }
+
+    #[test]
+    fn test_header_is_aligned_like_numpy() {
+        let bytes = encode_npy(&[0.0; 4], 2, 2);
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(bytes[10 + header_len - 1], b'\n');
+        assert_eq!(bytes.len(), 10 + header_len + 4 * 8);
+    }
+
+    #[test]
+    fn test_reads_numpy_written_header() {
+        // The header np.save writes for np.arange(6.0).reshape(3, 2)
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (3, 2), }                                                          \n";
+        let data = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let m = parse_npy(&npy_with_header(header, &data)).unwrap();
+        assert_eq!((m.rows, m.cols), (3, 2));
+        assert_eq!(m.data, data);
+    }
+
+    #[test]
+    fn test_empty_matrix() {
+        let m = parse_npy(&encode_npy(&[], 0, 5)).unwrap();
+        assert_eq!((m.rows, m.cols, m.data.len()), (0, 5, 0));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_files() {
+        let cases = [
+            (
+                "{'descr': '<f4', 'fortran_order': False, 'shape': (1, 1), }\n",
+                "dtype '<f4'",
+            ),
+            (
+                "{'descr': '>f8', 'fortran_order': False, 'shape': (1, 1), }\n",
+                "dtype '>f8'",
+            ),
+            (
+                "{'descr': '<f8', 'fortran_order': True, 'shape': (1, 1), }\n",
+                "Fortran order",
+            ),
+            (
+                "{'descr': '<f8', 'fortran_order': False, 'shape': (4,), }\n",
+                "2-D shape, got (4,)",
+            ),
+            (
+                "{'descr': '<f8', 'fortran_order': False, 'shape': (1, 2, 2), }\n",
+                "2-D shape",
+            ),
+            (
+                "{'descr': '<f8', 'fortran_order': False, }\n",
+                "no 'shape' key",
+            ),
+            (
+                "{'descr': '<f8', 'fortran_order': False, 'shape': (4611686018427387904, 4), }\n",
+                "too large",
+            ),
+        ];
+        for (header, expected) in cases {
+            let err = parse_npy(&npy_with_header(header, &[0.0; 4])).unwrap_err();
+            assert!(err.to_string().contains(expected), "{}: {}", header, err);
+        }
+
+        let err = parse_npy(b"PK\x03\x04 not numpy").unwrap_err();
+        assert!(err.to_string().contains("magic"), "{}", err);
+    }
+
+    #[test]
+    fn test_rejects_truncated_data() {
+        let mut bytes = encode_npy(&[1.0; 6], 2, 3);
+        bytes.truncate(bytes.len() - 4);
+        let err = parse_npy(&bytes).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                NpyError::Truncated {
+                    expected: 48,
+                    got: 44
+                }
+            ),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! A multiply run a piece at a time, for callers that can't block.
+//!
+//! A 4096² multiply takes a few hundred milliseconds on one core. A GUI
+//! thread can't spend that in one call, and where there are no threads to
+//! hand it to (wasm, say), [`MultiplyJob`] splits it up instead: each
+//! [`step`](MultiplyJob::step) runs a band of C's rows through the blocked
+//! driver, about as much work as asked for, and returns. The event loop gets
+//! control back between steps.
+//!
+//! Row bands are what the threaded driver already splits C into, so the
+//! result is [`multiply`](crate::multiply)'s bit for bit, however many
+//! steps it takes.
+
+use crate::backend::GemmBackend;
+use crate::matrix::transpose::transpose;
+use crate::workspace::worth_transposing;
+use crate::{Backend, backend_for, config, error, tiny};
+
+/// C += A·B, a band of rows per [`step`](MultiplyJob::step).
+///
+/// The job borrows C exclusively until it's dropped, so nothing else can
+/// read a half-finished C. Dropping it early is fine: the rows already
+/// stepped through hold their share of A·B, and the rest keep their values.
+///
+/// ```
+/// use matmul::{MultiplyJob, multiply};
+///
+/// let (m, n, k) = (300, 200, 100);
+/// let a = vec![0.5; m * k];
+/// let b = vec![2.0; k * n];
+/// let mut c = vec![0.0; m * n];
+/// let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, 1_000_000);
+/// let mut steps = 1;
+/// while !job.step() {
+///     steps += 1; // yield to the event loop here
+/// }
+/// assert!(steps > 1);
+/// assert!(c.iter().all(|&x| x == 100.0));
+/// ```
+pub struct MultiplyJob<'a> {
+    a: &'a [f64],
+    b: &'a [f64],
+    c: &'a mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    backend: Backend,
+    /// Rows per step: a multiple of the backend's tile height
+    band: usize,
+    /// Bᵀ, once the first step has made it, for a backend that packs from it
+    bt: Option<Vec<f64>>,
+    transposes_b: bool,
+    /// Rows of C done
+    next_row: usize,
+}
+
+impl<'a> MultiplyJob<'a> {
+    /// A job computing C += A·B (row-major, A m×k, B k×n, C m×n) on the
+    /// backend [`multiply`](crate::multiply) would pick, in steps of about
+    /// `chunk_flops` floating-point operations.
+    ///
+    /// A step is at least one tile-high band of rows (2·n·k FLOPs per row),
+    /// however small `chunk_flops` is. A backend that packs B from its
+    /// transpose has the first step make Bᵀ, a k×n copy, and nothing else;
+    /// a multiply small enough for the [`tiny`] path runs in one step.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slice sizes don't match m, n, k.
+    pub fn new(
+        a: &'a [f64],
+        b: &'a [f64],
+        c: &'a mut [f64],
+        m: usize,
+        n: usize,
+        k: usize,
+        chunk_flops: u64,
+    ) -> MultiplyJob<'a> {
+        error::assert_dims(a, b, c, m, n, k);
+        let backend = backend_for(m, n, k);
+        let row_flops = 2.0 * n as f64 * k as f64;
+        let mr = backend.mr();
+        let band = if tiny::is_tiny(m, n, k) {
+            m
+        } else {
+            (chunk_flops as f64 / row_flops) as usize / mr * mr
+        };
+        #[cfg(feature = "tracing")]
+        if m != 0 && n != 0 && k != 0 {
+            crate::backend::trace_dispatch(backend, 1, m, n, k);
+        }
+        MultiplyJob {
+            a,
+            b,
+            c,
+            m,
+            n,
+            k,
+            backend,
+            band: band.max(mr),
+            bt: None,
+            transposes_b: !tiny::is_tiny(m, n, k)
+                && backend.uses_transpose(k, n)
+                && worth_transposing(k, n),
+            next_row: if n == 0 || k == 0 { m } else { 0 },
+        }
+    }
+
+    /// Runs the next band of rows; `true` once C is complete (and on every
+    /// call after that).
+    pub fn step(&mut self) -> bool {
+        if self.is_done() {
+            return true;
+        }
+        let (m, n, k) = (self.m, self.n, self.k);
+        let _mode = config::enter_denormal_mode();
+        if tiny::is_tiny(m, n, k) {
+            tiny::run(self.a, self.b, self.c, m, n, k);
+            self.next_row = m;
+            return true;
+        }
+        if self.transposes_b && self.bt.is_none() {
+            let mut bt = vec![0.0; k * n];
+            transpose(self.b, &mut bt, k, n);
+            self.bt = Some(bt);
+            return false;
+        }
+
+        let rows = self.next_row..(self.next_row + self.band).min(m);
+        let c_band = &mut self.c[rows.start * n..rows.end * n];
+        // Safety: `backend_for` only picks available backends, the shapes
+        // are checked, and the band starts on a tile boundary
+        unsafe {
+            match &self.bt {
+                Some(bt) => {
+                    self.backend
+                        .run_transposed(self.a, self.b, bt, c_band, m, n, k, rows.clone())
+                }
+                None => self
+                    .backend
+                    .run(self.a, self.b, c_band, m, n, k, rows.clone()),
+            }
+        }
+        self.next_row = rows.end;
+        self.is_done()
+    }
+
+    /// Whether every row of C is done.
+    pub fn is_done(&self) -> bool {
+        self.next_row == self.m
+    }
+
+    /// The share of C's rows done so far, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        if self.m == 0 {
+            1.0
+        } else {
+            self.next_row as f64 / self.m as f64
+        }
+    }
+
+    /// The backend the steps run on.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    /// Steps the job to the end, returning how many steps it took.
+    fn run_to_end(job: &mut MultiplyJob) -> usize {
+        let mut steps = 1;
+        while !job.step() {
+            steps += 1;
+        }
+        assert!(job.step(), "a finished job stays finished");
+        assert_eq!(job.progress(), 1.0);
+        steps
+    }
+
+    fn check_matches_multiply(shapes: &[(usize, usize, usize)]) {
+        for &(m, n, k) in shapes {
+            let a = random(m, k, 1);
+            let b = random(k, n, 2);
+            let c_initial = random(m, n, 3);
+            let mut want = c_initial.clone();
+            multiply(&a, &b, &mut want, m, n, k);
+
+            for chunk_flops in [0, 10_000, 1_000_000, u64::MAX] {
+                let mut c = c_initial.clone();
+                let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, chunk_flops);
+                let steps = run_to_end(&mut job);
+                assert_eq!(c, want, "{}x{}x{} in {} steps", m, n, k, steps);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_multiply() {
+        // Tiny, low-rank, blocked with edge rows and columns, and empty
+        check_matches_multiply(&[
+            (5, 7, 3),
+            (50, 20, 3),
+            (37, 29, 41),
+            (0, 4, 4),
+            (4, 0, 4),
+            (20, 20, 0),
+        ]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri")]
+    fn test_matches_multiply_across_blocks() {
+        check_matches_multiply(&[(261, 19, 600), (130, 70, 300)]);
+    }
+
+    #[test]
+    fn test_step_size() {
+        let (m, n, k) = (100, 30, 40);
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let mut c = vec![0.0; m * n];
+        let mr = MultiplyJob::new(&a, &b, &mut c, m, n, k, 0).backend().mr();
+        let transposes = usize::from(worth_transposing(k, n));
+
+        // One tile-high band per step, however small the chunk
+        let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, 1);
+        assert_eq!(run_to_end(&mut job), transposes + m.div_ceil(mr));
+        // Everything at once
+        let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, u64::MAX);
+        assert_eq!(run_to_end(&mut job), transposes + 1);
+        // About 24 rows' worth, rounded down to whole tiles
+        let rows = 24 / mr * mr;
+        let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, 24 * 2 * 30 * 40);
+        assert_eq!(run_to_end(&mut job), transposes + m.div_ceil(rows));
+    }
+
+    #[test]
+    fn test_drop_half_finished() {
+        let (m, n, k) = (64, 24, 32);
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let c_initial = random(m, n, 3);
+        let mut want = c_initial.clone();
+        multiply(&a, &b, &mut want, m, n, k);
+
+        let mut c = c_initial.clone();
+        let mut job = MultiplyJob::new(&a, &b, &mut c, m, n, k, 0);
+        while job.progress() == 0.0 {
+            assert!(!job.step());
+        }
+        let done = job.next_row;
+        assert!(0 < done && done < m);
+        drop(job);
+
+        // Rows up to the band boundary are finished, the rest untouched
+        assert_eq!(c[..done * n], want[..done * n]);
+        assert_eq!(c[done * n..], c_initial[done * n..]);
+    }
+
+    #[test]
+    #[should_panic(expected = "B")]
+    fn test_wrong_length_panics() {
+        let mut c = [0.0; 4];
+        MultiplyJob::new(&[0.0; 4], &[0.0; 3], &mut c, 2, 2, 2, 0);
+    }
+}
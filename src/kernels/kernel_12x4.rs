@@ -1,5 +1,7 @@
 //! 12×4 AVX2 microkernel for matrix multiplication.
 
+use crate::Unsupported;
+
 /// Computes a 12×4 tile: C[0:12, 0:4] += A_packed × B_packed
 ///
 /// Uses 12 AVX2 registers as accumulators (one per row of C). This larger
@@ -14,37 +16,99 @@
 /// # Safety
 ///
 /// Caller must ensure:
-/// - CPU supports AVX2 and FMA (checked via `#[target_feature]`)
+/// - CPU supports AVX2 and FMA (`#[target_feature]`
+///   only enables them; [`run`] checks)
 /// - `a_pack` points to `k * 12` contiguous f64 values (packed A panel)
 /// - `b_pack` points to `k * 4` contiguous f64 values (packed B panel)
-/// - `c` points to valid memory with stride `ldc`
+/// - `c` points to `c_len` valid f64s, with rows `ldc` apart (debug builds
+///   check that the tile fits)
 /// - `c.add(row * ldc)` is valid for row in 0..12, each allowing read/write of 4 f64s
+#[cfg(not(miri))]
+#[target_feature(enable = "avx2,fma")]
+#[inline]
+pub unsafe fn kernel_12x4_avx2(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// Computes a 12×4 tile without reading C: C[0:12, 0:4] = A_packed ×
+/// B_packed. The accumulators start at zero rather than at C's values, so
+/// whatever C held (NaN, say) doesn't matter; the result is the same as
+/// [`kernel_12x4_avx2`]'s on a zeroed tile, bit for bit.
+///
+/// # Safety
+///
+/// Same as [`kernel_12x4_avx2`], except that C is only written.
+#[cfg(not(miri))]
+#[target_feature(enable = "avx2,fma")]
+#[inline]
+pub(crate) unsafe fn kernel_12x4_avx2_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_12x4_avx2`] if `ADD`, otherwise [`kernel_12x4_avx2_store`].
+///
+/// # Safety
+///
+/// Same as [`kernel_12x4_avx2`].
+#[cfg(not(miri))]
 #[target_feature(enable = "avx2,fma")]
+#[inline]
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 #[allow(unsafe_op_in_unsafe_fn)]
-pub unsafe fn kernel_12x4_avx2(
+unsafe fn tile<const ADD: bool>(
     a_pack: *const f64,
     b_pack: *const f64,
     c: *mut f64,
+    c_len: usize,
     k: usize,
     ldc: usize,
 ) {
     use std::arch::x86_64::*;
 
-    // 12 accumulators, one per output row
-    let mut c0 = _mm256_loadu_pd(c.add(0 * ldc));
-    let mut c1 = _mm256_loadu_pd(c.add(1 * ldc));
-    let mut c2 = _mm256_loadu_pd(c.add(2 * ldc));
-    let mut c3 = _mm256_loadu_pd(c.add(3 * ldc));
-    let mut c4 = _mm256_loadu_pd(c.add(4 * ldc));
-    let mut c5 = _mm256_loadu_pd(c.add(5 * ldc));
-    let mut c6 = _mm256_loadu_pd(c.add(6 * ldc));
-    let mut c7 = _mm256_loadu_pd(c.add(7 * ldc));
-    let mut c8 = _mm256_loadu_pd(c.add(8 * ldc));
-    let mut c9 = _mm256_loadu_pd(c.add(9 * ldc));
-    let mut c10 = _mm256_loadu_pd(c.add(10 * ldc));
-    let mut c11 = _mm256_loadu_pd(c.add(11 * ldc));
+    super::debug_assert_tile::<12, 4>(c_len, ldc);
+
+    // 12 accumulators, one per output row, starting from C or from zero
+    let mut c0 = _mm256_setzero_pd();
+    let mut c1 = _mm256_setzero_pd();
+    let mut c2 = _mm256_setzero_pd();
+    let mut c3 = _mm256_setzero_pd();
+    let mut c4 = _mm256_setzero_pd();
+    let mut c5 = _mm256_setzero_pd();
+    let mut c6 = _mm256_setzero_pd();
+    let mut c7 = _mm256_setzero_pd();
+    let mut c8 = _mm256_setzero_pd();
+    let mut c9 = _mm256_setzero_pd();
+    let mut c10 = _mm256_setzero_pd();
+    let mut c11 = _mm256_setzero_pd();
+    if ADD {
+        c0 = _mm256_loadu_pd(c.add(0 * ldc));
+        c1 = _mm256_loadu_pd(c.add(1 * ldc));
+        c2 = _mm256_loadu_pd(c.add(2 * ldc));
+        c3 = _mm256_loadu_pd(c.add(3 * ldc));
+        c4 = _mm256_loadu_pd(c.add(4 * ldc));
+        c5 = _mm256_loadu_pd(c.add(5 * ldc));
+        c6 = _mm256_loadu_pd(c.add(6 * ldc));
+        c7 = _mm256_loadu_pd(c.add(7 * ldc));
+        c8 = _mm256_loadu_pd(c.add(8 * ldc));
+        c9 = _mm256_loadu_pd(c.add(9 * ldc));
+        c10 = _mm256_loadu_pd(c.add(10 * ldc));
+        c11 = _mm256_loadu_pd(c.add(11 * ldc));
+    }
 
     for p in 0..k {
         let b_vec = _mm256_loadu_pd(b_pack.add(p * 4));
@@ -77,17 +141,97 @@ pub unsafe fn kernel_12x4_avx2(
     _mm256_storeu_pd(c.add(11 * ldc), c11);
 }
 
+/// [`kernel_12x4_avx2`] under Miri, which can't run its intrinsics.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub unsafe fn kernel_12x4_avx2(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<12, 4, true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_12x4_avx2_store`] under Miri.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub(crate) unsafe fn kernel_12x4_avx2_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<12, 4, false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_12x4_avx2`] on slices: checks the CPU and the slice lengths, then
+/// adds A_packed × B_packed to the 12×4 tile at the start of `c`, whose
+/// rows are `ldc` apart.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU can't run the kernel, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if `a_pack` or `b_pack` holds fewer than `k * 12` or `k * 4`
+/// values, if `ldc < 4`, or if `c` is too short for 12 rows.
+pub fn run(
+    a_pack: &[f64],
+    b_pack: &[f64],
+    c: &mut [f64],
+    k: usize,
+    ldc: usize,
+) -> Result<(), Unsupported> {
+    // Checked, so a huge k or ldc can't wrap around to a short length
+    let holds = |len: usize, needed: Option<usize>| needed.is_some_and(|n| len >= n);
+    assert!(
+        holds(a_pack.len(), k.checked_mul(12)),
+        "a_pack: expected 12 values per k step"
+    );
+    assert!(
+        holds(b_pack.len(), k.checked_mul(4)),
+        "b_pack: expected 4 values per k step"
+    );
+    assert!(ldc >= 4, "ldc {} is narrower than the tile", ldc);
+    assert!(
+        holds(c.len(), ldc.checked_mul(11).and_then(|n| n.checked_add(4))),
+        "c: expected 12 rows of 4, {} apart",
+        ldc
+    );
+    crate::features::require_avx2("kernel_12x4_avx2")?;
+    unsafe {
+        kernel_12x4_avx2(
+            a_pack.as_ptr(),
+            b_pack.as_ptr(),
+            c.as_mut_ptr(),
+            c.len(),
+            k,
+            ldc,
+        )
+    };
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kernels::testing::check_kernel;
+    use crate::matrix::compare::assert_close;
 
     #[test]
     fn test_kernel_12x4_correctness() {
-        if !is_x86_feature_detected!("avx2") {
-            println!("Skipping - AVX2 not available");
-            return;
-        }
-
         let k = 16;
         let a: Vec<f64> = (0..12 * k).map(|i| i as f64).collect();
         let b: Vec<f64> = (0..k * 4).map(|i| (i % 10) as f64).collect();
@@ -109,8 +253,9 @@ mod tests {
             }
         }
 
-        unsafe {
-            kernel_12x4_avx2(a_pack.as_ptr(), b_pack.as_ptr(), c.as_mut_ptr(), k, 4);
+        if let Err(e) = run(&a_pack, &b_pack, &mut c, k, 4) {
+            println!("Skipping - {}", e);
+            return;
         }
 
         // Naive reference
@@ -123,14 +268,48 @@ mod tests {
             }
         }
 
-        for i in 0..12 * 4 {
-            assert!(
-                (c[i] - c_expected[i]).abs() < 1e-10,
-                "Mismatch at {}: got {}, expected {}",
-                i,
-                c[i],
-                c_expected[i]
-            );
+        assert_close(&c_expected, &c, 1e-12, 1e-12);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_short_c_trips_debug_assertion() {
+        if let Err(e) = crate::features::require_avx2("kernel_12x4_avx2") {
+            println!("Skipping - {}", e);
+            return;
+        }
+        // One short of 12 rows of 4, 5 apart: the last row's store would
+        // write past the buffer
+        let mut c = vec![0.0; 58];
+        let (a_pack, b_pack) = ([1.0; 24], [1.0; 8]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            kernel_12x4_avx2(a_pack.as_ptr(), b_pack.as_ptr(), c.as_mut_ptr(), 58, 2, 5)
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "C: 12 rows of 4, 5 apart, don't fit in the 58 values left"
+        );
+        assert!(c.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "c: expected 12 rows of 4, 5 apart")]
+    fn test_run_rejects_short_c() {
+        // The last row starts at 11 * 5 = 55 and needs 4 values
+        let mut c = vec![0.0; 58];
+        let _ = run(&[1.0; 24], &[1.0; 8], &mut c, 2, 5);
+    }
+
+    #[test]
+    fn test_shared_check() {
+        if let Err(e) = crate::features::require_avx2("kernel_12x4_avx2") {
+            println!("Skipping - {}", e);
+            return;
+        }
+        unsafe {
+            check_kernel::<12, 4>("kernel_12x4_avx2", kernel_12x4_avx2, true);
+            check_kernel::<12, 4>("kernel_12x4_avx2_store", kernel_12x4_avx2_store, false);
         }
     }
 }
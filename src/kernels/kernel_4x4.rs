@@ -1,5 +1,7 @@
 //! 4×4 AVX2 microkernel for matrix multiplication.
 
+use crate::Unsupported;
+
 /// Computes a 4×4 tile: C[0:4, 0:4] += A_packed × B_packed
 ///
 /// This is the inner kernel called by the blocked GEMM. It keeps 4 AVX2
@@ -9,30 +11,84 @@
 /// # Safety
 ///
 /// Caller must ensure:
-/// - CPU supports AVX2 and FMA (checked via `#[target_feature]`)
+/// - CPU supports AVX2 and FMA (`#[target_feature]`
+///   only enables them; [`run`] checks)
 /// - `a_pack` points to `k * 4` contiguous f64 values (packed A panel)
 /// - `b_pack` points to `k * 4` contiguous f64 values (packed B panel)
-/// - `c` points to valid memory with stride `ldc`
+/// - `c` points to `c_len` valid f64s, with rows `ldc` apart (debug builds
+///   check that the tile fits)
 /// - `c.add(row * ldc)` is valid for row in 0..4, each allowing read/write of 4 f64s
 ///
+#[cfg(not(miri))]
+#[target_feature(enable = "avx2,fma")]
+#[inline]
+pub unsafe fn kernel_4x4_avx2(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// Computes a 4×4 tile without reading C: C[0:4, 0:4] = A_packed ×
+/// B_packed. The accumulators start at zero rather than at C's values, so
+/// whatever C held (NaN, say) doesn't matter; the result is the same as
+/// [`kernel_4x4_avx2`]'s on a zeroed tile, bit for bit.
+///
+/// # Safety
+///
+/// Same as [`kernel_4x4_avx2`], except that C is only written.
+#[cfg(not(miri))]
+#[target_feature(enable = "avx2,fma")]
+#[inline]
+pub(crate) unsafe fn kernel_4x4_avx2_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_4x4_avx2`] if `ADD`, otherwise [`kernel_4x4_avx2_store`].
+///
+/// # Safety
+///
+/// Same as [`kernel_4x4_avx2`].
+#[cfg(not(miri))]
 #[target_feature(enable = "avx2,fma")]
+#[inline]
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 #[allow(unsafe_op_in_unsafe_fn)]
-pub unsafe fn kernel_4x4_avx2(
+unsafe fn tile<const ADD: bool>(
     a_pack: *const f64,
     b_pack: *const f64,
     c: *mut f64,
+    c_len: usize,
     k: usize,
     ldc: usize,
 ) {
     use std::arch::x86_64::*;
 
-    // Load existing C values (we accumulate, not overwrite)
-    let mut c0 = _mm256_loadu_pd(c.add(0 * ldc));
-    let mut c1 = _mm256_loadu_pd(c.add(1 * ldc));
-    let mut c2 = _mm256_loadu_pd(c.add(2 * ldc));
-    let mut c3 = _mm256_loadu_pd(c.add(3 * ldc));
+    super::debug_assert_tile::<4, 4>(c_len, ldc);
+
+    // Start from C to accumulate, or from zero to overwrite it
+    let mut c0 = _mm256_setzero_pd();
+    let mut c1 = _mm256_setzero_pd();
+    let mut c2 = _mm256_setzero_pd();
+    let mut c3 = _mm256_setzero_pd();
+    if ADD {
+        c0 = _mm256_loadu_pd(c.add(0 * ldc));
+        c1 = _mm256_loadu_pd(c.add(1 * ldc));
+        c2 = _mm256_loadu_pd(c.add(2 * ldc));
+        c3 = _mm256_loadu_pd(c.add(3 * ldc));
+    }
 
     // Main loop: for each k, load B once, broadcast A values, FMA into C
     for p in 0..k {
@@ -55,3 +111,104 @@ pub unsafe fn kernel_4x4_avx2(
     _mm256_storeu_pd(c.add(2 * ldc), c2);
     _mm256_storeu_pd(c.add(3 * ldc), c3);
 }
+
+/// [`kernel_4x4_avx2`] under Miri, which can't run its intrinsics.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub unsafe fn kernel_4x4_avx2(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<4, 4, true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_4x4_avx2_store`] under Miri.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub(crate) unsafe fn kernel_4x4_avx2_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<4, 4, false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_4x4_avx2`] on slices: checks the CPU and the slice lengths, then
+/// adds A_packed × B_packed to the 4×4 tile at the start of `c`, whose
+/// rows are `ldc` apart.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU can't run the kernel, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if `a_pack` or `b_pack` holds fewer than `k * 4` or `k * 4`
+/// values, if `ldc < 4`, or if `c` is too short for 4 rows.
+pub fn run(
+    a_pack: &[f64],
+    b_pack: &[f64],
+    c: &mut [f64],
+    k: usize,
+    ldc: usize,
+) -> Result<(), Unsupported> {
+    // Checked, so a huge k or ldc can't wrap around to a short length
+    let holds = |len: usize, needed: Option<usize>| needed.is_some_and(|n| len >= n);
+    assert!(
+        holds(a_pack.len(), k.checked_mul(4)),
+        "a_pack: expected 4 values per k step"
+    );
+    assert!(
+        holds(b_pack.len(), k.checked_mul(4)),
+        "b_pack: expected 4 values per k step"
+    );
+    assert!(ldc >= 4, "ldc {} is narrower than the tile", ldc);
+    assert!(
+        holds(c.len(), ldc.checked_mul(3).and_then(|n| n.checked_add(4))),
+        "c: expected 4 rows of 4, {} apart",
+        ldc
+    );
+    crate::features::require_avx2("kernel_4x4_avx2")?;
+    unsafe {
+        kernel_4x4_avx2(
+            a_pack.as_ptr(),
+            b_pack.as_ptr(),
+            c.as_mut_ptr(),
+            c.len(),
+            k,
+            ldc,
+        )
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::testing::check_kernel;
+
+    #[test]
+    fn test_shared_check() {
+        if let Err(e) = crate::features::require_avx2("kernel_4x4_avx2") {
+            println!("Skipping - {}", e);
+            return;
+        }
+        unsafe {
+            check_kernel::<4, 4>("kernel_4x4_avx2", kernel_4x4_avx2, true);
+            check_kernel::<4, 4>("kernel_4x4_avx2_store", kernel_4x4_avx2_store, false);
+        }
+    }
+}
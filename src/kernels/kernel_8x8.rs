@@ -1,5 +1,7 @@
 //! 8×8 AVX-512 microkernel for matrix multiplication.
 
+use crate::Unsupported;
+
 /// Computes an 8×8 tile: C[0:8, 0:8] += A_packed × B_packed
 ///
 /// Uses 8 ZMM registers (512-bit) as accumulators. AVX-512 processes 8 f64
@@ -9,33 +11,92 @@
 /// # Safety
 ///
 /// Caller must ensure:
-/// - CPU supports AVX-512F, AVX-512DQ, and FMA (checked via `#[target_feature]`)
+/// - CPU supports AVX-512F and FMA (`#[target_feature]`
+///   only enables them; [`run`] checks)
 /// - `a_pack` points to `k * 8` contiguous f64 values (packed A panel)
 /// - `b_pack` points to `k * 8` contiguous f64 values (packed B panel)
-/// - `c` points to valid memory with stride `ldc`
+/// - `c` points to `c_len` valid f64s, with rows `ldc` apart (debug builds
+///   check that the tile fits)
 /// - `c.add(row * ldc)` is valid for row in 0..8, each allowing read/write of 8 f64s
-#[target_feature(enable = "avx512f,avx512dq,fma")]
+#[cfg(not(miri))]
+#[target_feature(enable = "avx512f,fma")]
+#[inline]
+pub unsafe fn kernel_8x8_avx512(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// Computes a 8×8 tile without reading C: C[0:8, 0:8] = A_packed ×
+/// B_packed. The accumulators start at zero rather than at C's values, so
+/// whatever C held (NaN, say) doesn't matter; the result is the same as
+/// [`kernel_8x8_avx512`]'s on a zeroed tile, bit for bit.
+///
+/// # Safety
+///
+/// Same as [`kernel_8x8_avx512`], except that C is only written.
+#[cfg(not(miri))]
+#[target_feature(enable = "avx512f,fma")]
+#[inline]
+pub(crate) unsafe fn kernel_8x8_avx512_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { tile::<false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_8x8_avx512`] if `ADD`, otherwise [`kernel_8x8_avx512_store`].
+///
+/// # Safety
+///
+/// Same as [`kernel_8x8_avx512`].
+#[cfg(not(miri))]
+#[target_feature(enable = "avx512f,fma")]
+#[inline]
 #[allow(clippy::identity_op)]
 #[allow(clippy::erasing_op)]
 #[allow(unsafe_op_in_unsafe_fn)]
-pub unsafe fn kernel_8x8_avx512(
+unsafe fn tile<const ADD: bool>(
     a_pack: *const f64,
     b_pack: *const f64,
     c: *mut f64,
+    c_len: usize,
     k: usize,
     ldc: usize,
 ) {
     use std::arch::x86_64::*;
 
-    // 8 accumulators, one per output row (512 bits = 8 f64 each)
-    let mut c0 = _mm512_loadu_pd(c.add(0 * ldc));
-    let mut c1 = _mm512_loadu_pd(c.add(1 * ldc));
-    let mut c2 = _mm512_loadu_pd(c.add(2 * ldc));
-    let mut c3 = _mm512_loadu_pd(c.add(3 * ldc));
-    let mut c4 = _mm512_loadu_pd(c.add(4 * ldc));
-    let mut c5 = _mm512_loadu_pd(c.add(5 * ldc));
-    let mut c6 = _mm512_loadu_pd(c.add(6 * ldc));
-    let mut c7 = _mm512_loadu_pd(c.add(7 * ldc));
+    super::debug_assert_tile::<8, 8>(c_len, ldc);
+
+    // 8 accumulators, one per output row (512 bits = 8 f64 each), starting
+    // from C or from zero
+    let mut c0 = _mm512_setzero_pd();
+    let mut c1 = _mm512_setzero_pd();
+    let mut c2 = _mm512_setzero_pd();
+    let mut c3 = _mm512_setzero_pd();
+    let mut c4 = _mm512_setzero_pd();
+    let mut c5 = _mm512_setzero_pd();
+    let mut c6 = _mm512_setzero_pd();
+    let mut c7 = _mm512_setzero_pd();
+    if ADD {
+        c0 = _mm512_loadu_pd(c.add(0 * ldc));
+        c1 = _mm512_loadu_pd(c.add(1 * ldc));
+        c2 = _mm512_loadu_pd(c.add(2 * ldc));
+        c3 = _mm512_loadu_pd(c.add(3 * ldc));
+        c4 = _mm512_loadu_pd(c.add(4 * ldc));
+        c5 = _mm512_loadu_pd(c.add(5 * ldc));
+        c6 = _mm512_loadu_pd(c.add(6 * ldc));
+        c7 = _mm512_loadu_pd(c.add(7 * ldc));
+    }
 
     for p in 0..k {
         let b_vec = _mm512_loadu_pd(b_pack.add(p * 8));
@@ -60,17 +121,97 @@ pub unsafe fn kernel_8x8_avx512(
     _mm512_storeu_pd(c.add(7 * ldc), c7);
 }
 
+/// [`kernel_8x8_avx512`] under Miri, which can't run its intrinsics.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub unsafe fn kernel_8x8_avx512(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<8, 8, true>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_8x8_avx512_store`] under Miri.
+///
+/// # Safety
+///
+/// Same as the SIMD version.
+#[cfg(miri)]
+pub(crate) unsafe fn kernel_8x8_avx512_store(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe { super::tile_scalar::<8, 8, false>(a_pack, b_pack, c, c_len, k, ldc) }
+}
+
+/// [`kernel_8x8_avx512`] on slices: checks the CPU and the slice lengths, then
+/// adds A_packed × B_packed to the 8×8 tile at the start of `c`, whose
+/// rows are `ldc` apart.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the CPU can't run the kernel, leaving C untouched.
+///
+/// # Panics
+///
+/// Panics if `a_pack` or `b_pack` holds fewer than `k * 8` or `k * 8`
+/// values, if `ldc < 8`, or if `c` is too short for 8 rows.
+pub fn run(
+    a_pack: &[f64],
+    b_pack: &[f64],
+    c: &mut [f64],
+    k: usize,
+    ldc: usize,
+) -> Result<(), Unsupported> {
+    // Checked, so a huge k or ldc can't wrap around to a short length
+    let holds = |len: usize, needed: Option<usize>| needed.is_some_and(|n| len >= n);
+    assert!(
+        holds(a_pack.len(), k.checked_mul(8)),
+        "a_pack: expected 8 values per k step"
+    );
+    assert!(
+        holds(b_pack.len(), k.checked_mul(8)),
+        "b_pack: expected 8 values per k step"
+    );
+    assert!(ldc >= 8, "ldc {} is narrower than the tile", ldc);
+    assert!(
+        holds(c.len(), ldc.checked_mul(7).and_then(|n| n.checked_add(8))),
+        "c: expected 8 rows of 8, {} apart",
+        ldc
+    );
+    crate::features::require_avx512("kernel_8x8_avx512")?;
+    unsafe {
+        kernel_8x8_avx512(
+            a_pack.as_ptr(),
+            b_pack.as_ptr(),
+            c.as_mut_ptr(),
+            c.len(),
+            k,
+            ldc,
+        )
+    };
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kernels::testing::check_kernel;
+    use crate::matrix::compare::assert_close;
 
     #[test]
     fn test_kernel_8x8_correctness() {
-        if !is_x86_feature_detected!("avx512f") {
-            println!("Skipping - AVX-512 not available");
-            return;
-        }
-
         let k = 16;
         let a: Vec<f64> = (0..8 * k).map(|i| i as f64).collect();
         let b: Vec<f64> = (0..k * 8).map(|i| (i % 10) as f64).collect();
@@ -92,8 +233,9 @@ mod tests {
             }
         }
 
-        unsafe {
-            kernel_8x8_avx512(a_pack.as_ptr(), b_pack.as_ptr(), c.as_mut_ptr(), k, 8);
+        if let Err(e) = run(&a_pack, &b_pack, &mut c, k, 8) {
+            println!("Skipping - {}", e);
+            return;
         }
 
         // Naive reference
@@ -106,14 +248,36 @@ mod tests {
             }
         }
 
-        for i in 0..64 {
-            assert!(
-                (c[i] - c_expected[i]).abs() < 1e-10,
-                "Mismatch at {}: got {}, expected {}",
-                i,
-                c[i],
-                c_expected[i]
-            );
+        assert_close(&c_expected, &c, 1e-12, 1e-12);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_narrow_ldc_trips_debug_assertion() {
+        if let Err(e) = crate::features::require_avx512("kernel_8x8_avx512") {
+            println!("Skipping - {}", e);
+            return;
+        }
+        // Rows 6 apart overlap an 8-wide tile's, though C is long enough
+        let mut c = vec![0.0; 64];
+        let (a_pack, b_pack) = ([1.0; 8], [1.0; 8]);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            kernel_8x8_avx512(a_pack.as_ptr(), b_pack.as_ptr(), c.as_mut_ptr(), 64, 1, 6)
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(message, "ldc 6 is narrower than the 8-wide tile");
+        assert!(c.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_shared_check() {
+        if let Err(e) = crate::features::require_avx512("kernel_8x8_avx512") {
+            println!("Skipping - {}", e);
+            return;
+        }
+        unsafe {
+            check_kernel::<8, 8>("kernel_8x8_avx512", kernel_8x8_avx512, true);
+            check_kernel::<8, 8>("kernel_8x8_avx512_store", kernel_8x8_avx512_store, false);
         }
     }
 }
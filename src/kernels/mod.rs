@@ -8,7 +8,75 @@
 //! - `kernel_4x4`: 4×4 tile, AVX2 (4 registers)
 //! - `kernel_12x4`: 12×4 tile, AVX2 (12 registers, better throughput)
 //! - `kernel_8x8`: 8×8 tile, AVX-512 (8 registers, 64 outputs per iteration)
+//!
+//! Each kernel's tests run it through the shared check in `testing`, which
+//! compares tiles bit for bit against a scalar reference and watches the
+//! values around the tile for stray stores.
+//!
+//! x86_64 only; the module doesn't exist on other targets. Under Miri, which
+//! can't execute the intrinsics, each kernel is `tile_scalar` instead, so
+//! the drivers around it can still be checked.
 
 pub mod kernel_12x4;
 pub mod kernel_4x4;
 pub mod kernel_8x8;
+#[cfg(test)]
+pub(crate) mod testing;
+
+/// Scalar stand-in for the kernels under Miri: the same MR×NR tile from the
+/// same packed layouts, with one fused multiply-add per k step in the same
+/// order, so it gives the SIMD kernels' results bit for bit. Without `ADD`
+/// it starts from zero instead of C's values, like the `_store` kernels.
+///
+/// # Safety
+///
+/// Same as the kernel it replaces: `a_pack` holds `k * MR` values, `b_pack`
+/// `k * NR`, and `c` points to `c_len` values, which hold `NR` for each row
+/// in `0..MR`, `ldc` apart.
+#[cfg(miri)]
+#[allow(unsafe_op_in_unsafe_fn)]
+unsafe fn tile_scalar<const MR: usize, const NR: usize, const ADD: bool>(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    debug_assert_tile::<MR, NR>(c_len, ldc);
+    for row in 0..MR {
+        for col in 0..NR {
+            let out = c.add(row * ldc + col);
+            let mut acc = if ADD { *out } else { 0.0 };
+            for p in 0..k {
+                acc = (*a_pack.add(p * MR + row)).mul_add(*b_pack.add(p * NR + col), acc);
+            }
+            *out = acc;
+        }
+    }
+}
+
+/// In debug builds, panics unless `MR` rows of `NR` values, `ldc` apart,
+/// fit in the `c_len` values of C from the tile's first one on: a stride
+/// narrower than the tile, or a C too short for its last row, fails here
+/// rather than writing past the buffer.
+#[inline(always)]
+pub(crate) fn debug_assert_tile<const MR: usize, const NR: usize>(c_len: usize, ldc: usize) {
+    debug_assert!(
+        ldc >= NR,
+        "ldc {} is narrower than the {}-wide tile",
+        ldc,
+        NR
+    );
+    debug_assert!(
+        (MR - 1)
+            .checked_mul(ldc)
+            .and_then(|reach| reach.checked_add(NR))
+            .is_some_and(|reach| reach <= c_len),
+        "C: {} rows of {}, {} apart, don't fit in the {} values left",
+        MR,
+        NR,
+        ldc,
+        c_len
+    );
+}
@@ -0,0 +1,172 @@
+//! The check every microkernel's tests run: random packed panels over a
+//! range of depths, the tile compared bit for bit against a scalar
+//! reference, and C embedded in a larger buffer whose other values must come
+//! through untouched.
+//!
+//! A store one row or column past the tile, a row stride taken as the tile
+//! width, or a load from outside the tile all show up here as a changed
+//! guard value or a NaN in the tile, whatever k is.
+
+use crate::matrix::generate::random;
+use crate::packed::Kernel;
+
+/// Depths to run each kernel at: none at all, a single step, and lengths
+/// around the unrolling and blocking a kernel might do.
+const DEPTHS: [usize; 10] = [0, 1, 2, 3, 4, 7, 8, 17, 64, 257];
+
+/// Values around the tile, on every side and between its rows. A NaN, so a
+/// kernel that reads one into a sum shows it in the tile too.
+const GUARD: u64 = 0x7ff8_dead_beef_0001;
+
+/// Guard values before the tile and after its last row.
+const PAD: usize = 5;
+
+/// Runs `kernel`, an MR×NR microkernel, at each of [`DEPTHS`] and at row
+/// strides of exactly NR and a few wider, and panics naming the kernel,
+/// k, ldc, and the value if a tile value differs from [`reference`]'s or a
+/// guard value changed. With `add`, the kernel adds to C; without, it
+/// overwrites C (the `_store` kernels).
+///
+/// # Safety
+///
+/// The CPU must be able to run `kernel`.
+pub(crate) unsafe fn check_kernel<const MR: usize, const NR: usize>(
+    name: &str,
+    kernel: Kernel,
+    add: bool,
+) {
+    for (seed, &k) in DEPTHS.iter().enumerate() {
+        let seed = 4 * seed as u64;
+        let a_pack = random(k, MR, seed);
+        let b_pack = random(k, NR, seed + 1);
+        for ldc in [NR, NR + 1, NR + 3, 2 * NR] {
+            let tile_len = (MR - 1) * ldc + NR;
+            let in_tile = |at: usize| (PAD..PAD + tile_len).contains(&at) && (at - PAD) % ldc < NR;
+
+            let mut c = vec![f64::from_bits(GUARD); PAD + tile_len + PAD];
+            let c_tile = random(MR, NR, seed + 2);
+            for (row, values) in c_tile.chunks_exact(NR).enumerate() {
+                let at = PAD + row * ldc;
+                c[at..at + NR].copy_from_slice(values);
+            }
+            let want = reference::<MR, NR>(&a_pack, &b_pack, &c_tile, k, add);
+
+            // Safety: the panels hold k steps, and the tile fits in what's
+            // left of C past PAD; the caller vouches for the CPU
+            unsafe {
+                kernel(
+                    a_pack.as_ptr(),
+                    b_pack.as_ptr(),
+                    c.as_mut_ptr().add(PAD),
+                    c.len() - PAD,
+                    k,
+                    ldc,
+                )
+            };
+
+            for (at, &x) in c.iter().enumerate() {
+                if in_tile(at) {
+                    let (row, col) = ((at - PAD) / ldc, (at - PAD) % ldc);
+                    let expected = want[row * NR + col];
+                    assert!(
+                        x.to_bits() == expected.to_bits(),
+                        "{}, k {}, ldc {}: C[{}][{}] is {}, expected {}",
+                        name,
+                        k,
+                        ldc,
+                        row,
+                        col,
+                        x,
+                        expected
+                    );
+                } else {
+                    assert!(
+                        x.to_bits() == GUARD,
+                        "{}, k {}, ldc {}: guard value {} places from the tile's \
+                         first changed to {}",
+                        name,
+                        k,
+                        ldc,
+                        at as isize - PAD as isize,
+                        x
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The MR×NR tile (row-major, NR wide) the SIMD kernels compute: C's value,
+/// or zero without `add`, plus one fused multiply-add per k step in
+/// ascending k.
+pub(crate) fn reference<const MR: usize, const NR: usize>(
+    a_pack: &[f64],
+    b_pack: &[f64],
+    c: &[f64],
+    k: usize,
+    add: bool,
+) -> Vec<f64> {
+    let mut tile = if add { c.to_vec() } else { vec![0.0; MR * NR] };
+    for (row, values) in tile.chunks_exact_mut(NR).enumerate() {
+        for (col, x) in values.iter_mut().enumerate() {
+            for p in 0..k {
+                *x = a_pack[p * MR + row].mul_add(b_pack[p * NR + col], *x);
+            }
+        }
+    }
+    tile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// [`reference`] behind the kernel signature, then writing `EXTRA`
+    /// zeros past the end of each row.
+    unsafe fn scalar<const EXTRA: usize>(
+        a_pack: *const f64,
+        b_pack: *const f64,
+        c: *mut f64,
+        c_len: usize,
+        k: usize,
+        ldc: usize,
+    ) {
+        let (a_pack, b_pack, c) = unsafe {
+            (
+                std::slice::from_raw_parts(a_pack, k * 4),
+                std::slice::from_raw_parts(b_pack, k * 4),
+                std::slice::from_raw_parts_mut(c, c_len),
+            )
+        };
+        let tile: Vec<f64> = c
+            .chunks(ldc)
+            .flat_map(|row| &row[..4])
+            .copied()
+            .take(16)
+            .collect();
+        let tile = reference::<4, 4>(a_pack, b_pack, &tile, k, true);
+        for (row, values) in tile.chunks_exact(4).enumerate() {
+            c[row * ldc..row * ldc + 4].copy_from_slice(values);
+            c[row * ldc + 4..row * ldc + 4 + EXTRA].fill(0.0);
+        }
+    }
+
+    #[test]
+    fn test_accepts_the_reference() {
+        unsafe { check_kernel::<4, 4>("reference", scalar::<0>, true) };
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "off by one, k 0, ldc 4: guard value 16 places from the tile's first changed to 0"
+    )]
+    fn test_catches_a_store_past_the_row() {
+        unsafe { check_kernel::<4, 4>("off by one", scalar::<1>, true) };
+    }
+
+    #[test]
+    #[should_panic(expected = "adds, k 0, ldc 4: C[0][0] is")]
+    fn test_catches_an_add_for_a_store() {
+        unsafe { check_kernel::<4, 4>("adds", scalar::<0>, false) };
+    }
+}
@@ -35,46 +35,282 @@
 //! - Cache blocking tuned for L1/L2
 //! - Adaptive multi-threading (scales down for small matrices)
 
+pub mod accumulation;
+pub mod backend;
+pub mod batch;
 pub mod blocked;
+pub mod builder;
+pub mod calibrate;
+pub mod chain;
+pub mod config;
+pub mod denormal;
+pub mod downclock;
+pub mod epilogue;
+pub mod error;
+pub mod features;
+#[cfg(feature = "capi")]
+pub mod ffi;
+pub mod fixed;
+pub mod int8;
+pub mod interop;
+pub mod io;
+pub mod job;
+#[cfg(target_arch = "x86_64")]
 pub mod kernels;
 pub mod matrix;
+pub mod nested;
+pub mod packed;
+pub mod plan;
+pub mod power;
+pub mod region;
+pub mod select;
+pub mod self_check;
+pub mod sparse;
+pub mod symm;
 pub mod threaded;
+pub mod tiny;
+pub mod trmm;
+pub mod warmup;
+pub mod workspace;
 
+pub use accumulation::Accumulation;
+pub use backend::{
+    Backend, GemmBackend, MultiplyStats, available_backends, detected_backend, gemm_backends,
+};
+pub use batch::multiply_batch_tiny;
+pub use blocked::gemm_transposed::matmul_blocked_transposed;
+pub use builder::{MatMul, MatMulBuilder, OutputLayout, Tuning};
+pub use calibrate::{DispatchTable, calibrate, calibrate_with_file};
+pub use chain::{ChainOrder, chain_order, multiply_chain};
+pub use denormal::{DenormalMode, with_denormal_mode};
+pub use downclock::{
+    avx512_crossover, backend_for, default_avx512_crossover, set_avx512_crossover,
+};
+pub use epilogue::Activation;
+pub use error::{MatMulError, Unsupported};
+pub use features::{CpuFeatures, cpu_features};
+pub use fixed::multiply_fixed;
+pub use job::MultiplyJob;
 pub use matrix::naive_ijk::matmul_naive_ijk;
-pub use matrix::naive_ikj::matmul_naive_ikj;
+pub use matrix::naive_ikj::{matmul_ikj_transposed, matmul_naive_ikj};
+pub use matrix::naive_jik::matmul_naive_jik;
+pub use matrix::naive_jki::matmul_naive_jki;
+pub use matrix::naive_kij::matmul_naive_kij;
+pub use matrix::naive_kji::matmul_naive_kji;
+pub use nested::multiply_nested;
+pub use packed::{PackedA, multiply_prepacked_a};
+pub use plan::{ExecutionPlan, MatmulPlan, plan};
+pub use power::matrix_power;
+pub use region::multiply_region;
+pub use select::{CostModel, select_backend};
+pub use sparse::{SparseCsr, spmm};
+pub use symm::symm;
+pub use threaded::Cancelled;
+pub use trmm::{Diag, Side, Uplo, trmm};
+pub use warmup::{warmup, warmup_for};
+pub use workspace::{Workspace, clear_scratch_cache, set_scratch_cache_cap};
+
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
 
 /// Matrix multiply: C += A * B
 ///
-/// Picks the fastest available kernel for your CPU (AVX-512 > AVX2 > scalar).
+/// Picks the available kernel a [cost model](select) predicts is fastest for
+/// the shape (for large matrices, AVX-512 > AVX2 > scalar), or, after
+/// [`calibrate()`], the one measured fastest at this size, except
+/// that small multiplies stay on AVX2 on CPUs that downclock for AVX-512
+/// ([`backend_for`] says which runs; see [`downclock`]).
 /// Matrices are row-major: A is m×k, B is k×n, C is m×n.
 ///
+/// NaN and ±Inf propagate as IEEE 754 says, the same as the naive i-k-j
+/// loop: an element of C is NaN, +Inf, -Inf, or finite exactly when the
+/// reference's is (barring an intermediate sum that overflows in one order
+/// and not the other). Summation order only changes finite values' rounding
+/// and, with several NaNs, which payload comes out.
+///
+/// Every backend sums each element of C in some order over k products, so
+/// each is within `γ(k)·(|A|·|B|)[i][j]` of the exact product, where
+/// `γ(k) = k·u / (1 − k·u)` and `u = 2⁻⁵³`
+/// ([`error_bound`](matrix::reference::error_bound)). `tests/accuracy.rs`
+/// checks this against an extended-precision reference.
+///
+/// C must not overlap A or B: there's no in-place multiply (`A = A·B`). Safe
+/// code can't build such slices anyway; debug builds check for it.
+///
+/// Multiplies with m, n, and k all at most 16 skip the dispatch and the
+/// blocked drivers' setup for a plain register-blocked loop on the operands
+/// as given; see [`tiny`]. It rounds like the SIMD backends with AVX2 and
+/// FMA and like [`Backend::Scalar`] without, whatever a
+/// [calibrated](mod@calibrate) table would pick for the shape.
+///
+/// Any dimension may be zero. With m = 0 or n = 0, C is empty; with k = 0,
+/// A·B is all zeros, so C keeps its values. Either way the call returns
+/// without allocating or spawning threads. The other entry points, and the
+/// blocked drivers, behave the same.
+///
 /// # Panics
 ///
-/// Panics if the slice sizes don't match m, n, k.
+/// Panics if the slice sizes don't match m, n, k, or if m·k, k·n, or m·n
+/// overflows `usize`; [`try_multiply`] returns these as errors instead.
 pub fn multiply(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
-    assert_eq!(a.len(), m * k, "A: expected {}x{}={} elements", m, k, m * k);
-    assert_eq!(b.len(), k * n, "B: expected {}x{}={} elements", k, n, k * n);
-    assert_eq!(c.len(), m * n, "C: expected {}x{}={} elements", m, n, m * n);
-
-    #[cfg(target_arch = "x86_64")]
-    {
-        if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("fma") {
-            unsafe { blocked::gemm_8x8::matmul_blocked_8x8(a, b, c, m, n, k, None, None) };
+    let _mode = config::enter_denormal_mode();
+    self_check::checked("multiply", a, b, c, m, n, k, |c| {
+        if tiny::is_tiny(m, n, k) {
+            tiny::multiply_tiny(a, b, c, m, n, k);
             return;
         }
-        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
-            unsafe { blocked::gemm_12x4::matmul_blocked_12x4(a, b, c, m, n, k, None, None) };
-            return;
+        error::assert_dims(a, b, c, m, n, k);
+
+        unsafe { run_backend(backend_for(m, n, k), a, b, c, m, n, k) };
+    })
+}
+
+/// Same as [`multiply`], but returns an error instead of panicking when the
+/// slice sizes don't match m, n, k or their products overflow `usize`. It
+/// also checks, in release builds too, that C doesn't overlap A or B.
+///
+/// Its scratch buffers are allocated fallibly. If there's no memory for the
+/// k×n copy of Bᵀ the blocked drivers pack from, it packs straight from B
+/// instead (which the drivers also do on their own past
+/// [`TRANSPOSE_B_MAX_BYTES`](workspace::TRANSPOSE_B_MAX_BYTES)); if there's
+/// none for that either, the error is [`MatMulError::OutOfMemory`] and C is
+/// untouched. The result is the same bit for bit either way.
+///
+/// ```
+/// use matmul::{MatMulError, try_multiply};
+///
+/// let a = vec![1.0; 6];
+/// let b = vec![1.0; 6];
+/// let mut c = vec![0.0; 4];
+/// assert_eq!(try_multiply(&a, &b, &mut c, 2, 2, 3), Ok(()));
+/// assert!(matches!(
+///     try_multiply(&a, &b, &mut c, 2, 2, 4),
+///     Err(MatMulError::WrongLength { operand: "A", .. })
+/// ));
+/// ```
+pub fn try_multiply(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Result<(), MatMulError> {
+    error::check_dims(a, b, c, m, n, k)?;
+    error::check_no_overlap(a, b, c)?;
+    let _mode = config::enter_denormal_mode();
+    self_check::checked("try_multiply", a, b, c, m, n, k, |c| {
+        if tiny::is_tiny(m, n, k) {
+            tiny::run(a, b, c, m, n, k);
+            return Ok(());
+        }
+
+        let backend = backend_for(m, n, k);
+        if m == 0 || n == 0 || k == 0 {
+            return Ok(());
         }
+        #[cfg(feature = "tracing")]
+        backend::trace_dispatch(backend, 1, m, n, k);
+
+        let (kc, mc) = config::block_sizes(backend, k);
+        workspace::with_scratch(|workspace| {
+            let lean = workspace.try_reserve_or_lean(backend, m, n, k, (kc, mc), false)?;
+            workspace.lean = lean;
+            // Safety: `backend_for` only picks available backends, and the
+            // shapes are checked
+            unsafe { builder::run_rows(backend, a, b, c, m, n, k, None, None, kc, mc, workspace) };
+            workspace.lean = false;
+            Ok(())
+        })
+    })
+}
+
+/// Same as [`multiply`], and reports which backend ran and how long it took.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn multiply_with_stats(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> MultiplyStats {
+    multiply_with_backend(backend_for(m, n, k), a, b, c, m, n, k)
+}
+
+/// Same as [`multiply`], but on the given backend instead of the detected one.
+///
+/// Useful for comparing backends on one machine, or for pinning results to
+/// one rounding behavior. For k up to
+/// [`LOW_RANK_MAX_K`](matrix::low_rank::LOW_RANK_MAX_K), every backend but
+/// the i-k-j loop skips its blocking and adds each row of C in one pass, at
+/// the backend's own vector width.
+///
+/// # Panics
+///
+/// Panics if the CPU can't run `backend` (see [`Backend::is_available`]), or
+/// if the slice sizes don't match m, n, k.
+pub fn multiply_with_backend(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> MultiplyStats {
+    assert!(
+        backend.is_available(),
+        "{} is not available on this CPU",
+        backend
+    );
+    let start = Instant::now();
+    error::assert_dims(a, b, c, m, n, k);
+
+    let _mode = config::enter_denormal_mode();
+    self_check::checked("multiply_with_backend", a, b, c, m, n, k, |c| unsafe {
+        run_backend(backend, a, b, c, m, n, k)
+    });
+    MultiplyStats::new(backend, 1, (m, n, k), start.elapsed())
+}
+
+/// Single-threaded C += A * B on `backend`, over all of C.
+///
+/// # Safety
+///
+/// `backend` must be available on this CPU, and the slices must match m, n, k.
+unsafe fn run_backend(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) {
+    if m == 0 || n == 0 || k == 0 {
+        return;
     }
+    #[cfg(feature = "tracing")]
+    backend::trace_dispatch(backend, 1, m, n, k);
 
-    matrix::naive_ikj::matmul_naive_ikj(a, b, c, m, n, k);
+    unsafe { backend.run(a, b, c, m, n, k, 0..m) };
 }
 
 /// Same as [`multiply`] but uses multiple threads.
 ///
 /// Thread count adapts to matrix size - small matrices use fewer threads
-/// because the overhead isn't worth it.
+/// because the overhead isn't worth it. Without a SIMD kernel this still
+/// runs multi-threaded, using the scalar i-k-j loop on each row band.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k. If a worker thread panics,
+/// the other workers are joined first and the re-raised panic names the row
+/// band that failed; C's contents are unspecified afterwards.
 pub fn multiply_parallel(
     a: &[f64],
     b: &[f64],
@@ -84,21 +320,123 @@ pub fn multiply_parallel(
     k: usize,
     num_threads: usize,
 ) {
-    assert_eq!(a.len(), m * k, "A: expected {}x{}={} elements", m, k, m * k);
-    assert_eq!(b.len(), k * n, "B: expected {}x{}={} elements", k, n, k * n);
-    assert_eq!(c.len(), m * n, "C: expected {}x{}={} elements", m, n, m * n);
-
-    #[cfg(target_arch = "x86_64")]
-    {
-        if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("fma") {
-            threaded::gemm_8x8_mt::matmul_blocked_8x8_mt(a, b, c, m, n, k, num_threads);
-            return;
-        }
-        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
-            threaded::gemm_12x4_mt::matmul_blocked_12x4_mt(a, b, c, m, n, k, num_threads);
-            return;
+    error::assert_dims(a, b, c, m, n, k);
+
+    run_parallel(a, b, c, m, n, k, num_threads);
+}
+
+/// Same as [`multiply_parallel`], and reports which backend ran, how many
+/// threads it actually used, and how long it took.
+///
+/// # Panics
+///
+/// Same as [`multiply_parallel`].
+pub fn multiply_parallel_with_stats(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+) -> MultiplyStats {
+    let start = Instant::now();
+    error::assert_dims(a, b, c, m, n, k);
+
+    let (backend, threads_used) = run_parallel(a, b, c, m, n, k, num_threads);
+    MultiplyStats::new(backend, threads_used, (m, n, k), start.elapsed())
+}
+
+/// The [`multiply_parallel`] dispatch. Returns the backend and the number of
+/// threads the threaded layer settles on.
+fn run_parallel(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+) -> (Backend, usize) {
+    let backend = parallel_backend(m, n, k);
+    if m == 0 || n == 0 || k == 0 {
+        return (backend, 1);
+    }
+    let _mode = config::enter_denormal_mode();
+    let threads = threaded::thread_count(backend, m, n, k, num_threads);
+    #[cfg(feature = "tracing")]
+    backend::trace_dispatch(backend, threads, m, n, k);
+
+    self_check::checked("multiply_parallel", a, b, c, m, n, k, |c| match backend {
+        Backend::ScalarIkj => {
+            threaded::naive_ikj_mt::matmul_naive_ikj_mt(a, b, c, m, n, k, num_threads)
         }
+        // Safety: the parallel backend is a detected one
+        simd => unsafe {
+            threaded::parallel_rows(
+                &simd,
+                a,
+                b,
+                c,
+                m,
+                n,
+                k,
+                num_threads,
+                config::current().schedule(),
+            )
+        },
+    });
+    (backend, threads)
+}
+
+/// The backend the threaded entry points use for an m×n×k multiply: the
+/// SIMD kernel [`backend_for`] picks, or i-k-j row bands without SIMD.
+fn parallel_backend(m: usize, n: usize, k: usize) -> Backend {
+    match backend_for(m, n, k) {
+        Backend::Scalar => Backend::ScalarIkj,
+        simd => simd,
     }
+}
+
+/// Same as [`multiply_parallel`], but can be abandoned part-way through.
+///
+/// Workers check `cancel` each time they pick up the next chunk of rows
+/// (roughly one cache block of A), so the call returns promptly after the
+/// flag is set from another thread, with `Err(Cancelled)`.
+///
+/// After cancellation C's contents are unspecified: some rows may be fully
+/// updated, others partially or not at all. Discard or re-zero it.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+#[allow(clippy::too_many_arguments)]
+pub fn multiply_parallel_cancellable(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+    cancel: &AtomicBool,
+) -> Result<(), Cancelled> {
+    error::assert_dims(a, b, c, m, n, k);
+    if m == 0 || n == 0 || k == 0 {
+        return Ok(());
+    }
+
+    let backend = parallel_backend(m, n, k);
+    let _mode = config::enter_denormal_mode();
+    #[cfg(feature = "tracing")]
+    backend::trace_dispatch(
+        backend,
+        threaded::parallel_rows::choose_thread_count(m, n, k, num_threads),
+        m,
+        n,
+        k,
+    );
 
-    matrix::naive_ikj::matmul_naive_ikj(a, b, c, m, n, k);
+    // Safety: the parallel backend is a detected one
+    unsafe { threaded::parallel_rows_cancellable(&backend, a, b, c, m, n, k, num_threads, cancel) }
 }
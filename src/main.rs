@@ -1,191 +1,3052 @@
 //! Benchmark runner for matmul implementations.
+//!
+//! Run with no arguments for the default comparison; `--help` lists options.
 
-use matmul::blocked::gemm_4x4::matmul_blocked_4x4;
-use matmul::blocked::gemm_8x8::matmul_blocked_8x8;
-use matmul::blocked::gemm_12x4::matmul_blocked_12x4;
+use matmul::blocked::gemm_transposed::matmul_blocked_transposed;
+use matmul::blocked::pack::{pack_a_panel, pack_b_panel};
+use matmul::gemm_backends;
+use matmul::int8::{Accumulator, multiply_i8};
+use matmul::io::{read_npy, write_npy};
+use matmul::matrix::compare::{Mismatch, check_close, max_abs_diff};
+use matmul::matrix::elementwise::{add_assign, axpy, scale};
+use matmul::matrix::generate::{RandomElement, random, random_of};
 use matmul::matrix::naive_ijk::matmul_naive_ijk;
-use matmul::matrix::naive_ikj::matmul_naive_ikj;
-use matmul::threaded::gemm_4x4_mt::matmul_blocked_4x4_mt;
-use matmul::threaded::gemm_8x8_mt::matmul_blocked_8x8_mt;
-use matmul::threaded::gemm_12x4_mt::matmul_blocked_12x4_mt;
+use matmul::matrix::naive_ikj::{matmul_ikj_transposed, matmul_naive_ikj};
+use matmul::matrix::naive_jik::matmul_naive_jik;
+use matmul::matrix::naive_jki::matmul_naive_jki;
+use matmul::matrix::naive_kij::matmul_naive_kij;
+use matmul::matrix::naive_kji::matmul_naive_kji;
+use matmul::matrix::transpose::{transpose, transpose_naive, transpose_scalar};
+use matmul::threaded::{Schedule, parallel_rows};
+use std::sync::OnceLock;
 use std::time::Instant;
 
+#[cfg(feature = "blas-bench")]
+use matmul::interop::blas::{multiply_blas, set_blas_threads};
+#[cfg(feature = "compare-rust")]
+use matmul::interop::peers::{multiply_faer, multiply_matrixmultiply};
+
+/// Row name for the system BLAS reference (`blas-bench` feature)
+#[cfg(feature = "blas-bench")]
+const BLAS_ROW: &str = "OpenBLAS dgemm";
+
+// SIMD transposes only exist on x86_64; elsewhere their rows are left out
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::simple_simd;
+#[cfg(target_arch = "x86_64")]
+use matmul::matrix::transpose::{transpose_avx, transpose_avx512};
+
+// Counts heap bytes so each benchmark row can report its peak memory
+#[global_allocator]
+static ALLOCATOR: memory::CountingAllocator = memory::CountingAllocator;
+
+const USAGE: &str = "\
+Usage: matmul [OPTIONS]
+
+Benchmark options:
+  --mode MODE         latency (default): time methods one call at a time;
+                      throughput: back-to-back calls reusing B and C, for
+                      sustained GFLOPS and latency percentiles;
+                      scaling: the MT methods at 1, 2, 4, ... threads up to
+                      the available cores, checked against 1 thread;
+                      primitives: transposes and panel packing, in GB/s;
+                      first-call: multiply()'s first call in a fresh
+                      process, cold and after warmup() / warmup_for(),
+                      against its steady state (--iters processes each);
+                      downclock: a multiply on the AVX-512 and AVX2
+                      kernels, then a scalar workload, against that
+                      workload alone (--iters rounds, medians)
+  --sizes N,N,...     Square matrix sizes (default 256,512,1024)
+  --shapes SHAPES     MxNxK,... (A is MxK, B is KxN), or 'aspect' for the
+                      tall / wide / shallow-k / deep-k sweep
+  --iters N           Timed runs per method and shape (default 3)
+  --min-time SECS     Keep running past --iters until this much time is spent
+  --calls N           Throughput mode: calls per method and shape (default
+                      100; --min-time extends it)
+  --seed N            Seed for the random inputs (default 1): A uses N,
+                      B uses N+1
+  --threads N         Threads for the MT methods (default 4)
+  --only METHOD,...   Only run these methods, by key or table name (see
+                      --list); also skips the transpose and elementwise
+                      sections. --methods is the same flag
+  --exclude METHOD,.. Skip these methods (and those sections)
+  --dtype TYPE,...    Element types to run sections for: f64 (default), f32,
+                      i8 (i32 or i64 sums); latency mode, table format only
+  --baseline METHOD   Speedups are relative to this method (default: the
+                      first one run); if it doesn't run, to the first one
+  --list              List the methods this CPU runs, then exit
+  --explain           For each shape, print the cost model's estimate on
+                      every backend and which one multiply() runs, then exit
+  --verify            Check every result against the i-k-j reference and
+                      report the max error (outside the timed runs)
+  --verify-strict     Like --verify, but exit with an error on the first
+                      mismatch
+  --format FORMAT     table (default), csv, or json; csv and json go to
+                      stdout and progress to stderr
+
+Multiply saved matrices instead:
+  --input-a FILE      A as .npy (float64, C order, 2-D)
+  --input-b FILE      B as .npy
+  --output FILE       Save C as .npy
+
+  -h, --help          Show this message
+";
+
+/// Command-line options.
+struct Options {
+    mode: Mode,
+    shapes: Vec<Shape>,
+    timing: Timing,
+    /// Calls per method and shape in throughput mode
+    calls: usize,
+    /// A is `random(m, k, seed)` and B `random(k, n, seed + 1)`
+    seed: u64,
+    threads: usize,
+    /// Method keys to run, in table order; `None` runs everything
+    methods: Option<Vec<String>>,
+    /// Method keys not to run
+    exclude: Vec<String>,
+    /// Key of the method speedups are relative to; `None` for the first
+    baseline: Option<String>,
+    /// Element types to run, in order
+    dtypes: Vec<Dtype>,
+    verify: Verify,
+    format: Format,
+    input_a: Option<String>,
+    input_b: Option<String>,
+    output: Option<String>,
+    list: bool,
+    explain: bool,
+    help: bool,
+}
+
+/// One problem size: A is m×k, B is k×n, C is m×n.
+#[derive(Clone, Copy)]
+struct Shape {
+    m: usize,
+    n: usize,
+    k: usize,
+}
+
+/// `--shapes aspect`: one square reference and the lopsided shapes where the
+/// edge handling, the packing, or the k blocking, rather than the microkernel,
+/// sets the speed.
+const ASPECT_SWEEP: [Shape; 7] = [
+    Shape::new(1024, 1024, 1024),
+    // Few rows of C: mostly packing B, little reuse of it
+    Shape::new(64, 1024, 1024),
+    // Few columns: the kernels' edge columns dominate
+    Shape::new(1024, 64, 1024),
+    // Shallow k: each C tile gets little work per load and store
+    Shape::new(1024, 1024, 64),
+    // Deep k: a small C, each tile the sum of many k blocks
+    Shape::new(64, 64, 4096),
+    // Tall-skinny and short-wide products
+    Shape::new(4096, 16, 256),
+    Shape::new(16, 4096, 256),
+];
+
+impl Shape {
+    const fn new(m: usize, n: usize, k: usize) -> Self {
+        Shape { m, n, k }
+    }
+
+    fn square(size: usize) -> Self {
+        Shape::new(size, size, size)
+    }
+
+    /// `MxNxK` (or with `×`), every dimension positive.
+    fn parse(text: &str) -> Option<Self> {
+        let dims: Vec<usize> = text
+            .split(['x', '×'])
+            .map(|d| d.parse().ok().filter(|&d| d > 0))
+            .collect::<Option<_>>()?;
+        match dims[..] {
+            [m, n, k] => Some(Shape::new(m, n, k)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Shape {
+    /// `N×N` for squares, like the original tables, and `M×N×K` otherwise.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.m == self.n && self.n == self.k {
+            write!(f, "{}×{}", self.m, self.m)
+        } else {
+            write!(f, "{}×{}×{}", self.m, self.n, self.k)
+        }
+    }
+}
+
+/// `--mode`: what the benchmark measures.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// Average time per call from fresh output buffers (the default)
+    Latency,
+    /// Back-to-back calls reusing B and C, the way a service or training loop
+    /// calls it: sustained GFLOPS and the latency distribution
+    Throughput,
+    /// The threaded methods at 1, 2, 4, … threads: speedup and parallel
+    /// efficiency
+    Scaling,
+    /// Transposes and panel packing on their own, in GB/s
+    Primitives,
+    /// `multiply`'s first call in a fresh process, with and without warming
+    /// up, against later calls
+    FirstCall,
+    /// What a multiply on each SIMD kernel costs the scalar code after it
+    Downclock,
+}
+
+/// `--verify` / `--verify-strict`.
+#[derive(Clone, Copy, PartialEq)]
+enum Verify {
+    Off,
+    /// Report the max error and any mismatch, keep going
+    Report,
+    /// Exit with status 1 on the first mismatch
+    Strict,
+}
+
+/// `--dtype`: an element type with its own section of the latency tables.
+#[derive(Clone, Copy, PartialEq)]
+enum Dtype {
+    F64,
+    F32,
+    /// i8 inputs, i32 or i64 sums
+    I8,
+}
+
+impl Dtype {
+    fn parse(text: &str) -> Option<Dtype> {
+        match text {
+            "f64" => Some(Dtype::F64),
+            "f32" => Some(Dtype::F32),
+            "i8" => Some(Dtype::I8),
+            _ => None,
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Dtype::F64 => "f64",
+            Dtype::F32 => "f32",
+            Dtype::I8 => "i8",
+        }
+    }
+
+    /// What the rate counts: the 2·m·n·k multiplies and adds, in floating
+    /// point or not
+    fn unit(self) -> &'static str {
+        match self {
+            Dtype::I8 => "GOPS",
+            Dtype::F64 | Dtype::F32 => "GFLOPS",
+        }
+    }
+}
+
+/// `--format`: how benchmark results are reported.
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    /// Human-readable tables (the default)
+    Table,
+    /// One line per method and shape, on stdout
+    Csv,
+    /// One document with results and run settings, on stdout
+    Json,
+}
+
+/// How long to time each method.
+#[derive(Clone, Copy)]
+struct Timing {
+    iterations: usize,
+    /// Seconds; timing continues past `iterations` runs until it's reached
+    min_time: f64,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            mode: Mode::Latency,
+            shapes: [256, 512, 1024].map(Shape::square).to_vec(),
+            timing: Timing {
+                iterations: 3,
+                min_time: 0.0,
+            },
+            calls: 100,
+            seed: 1,
+            threads: 4,
+            methods: None,
+            exclude: Vec::new(),
+            baseline: None,
+            dtypes: vec![Dtype::F64],
+            verify: Verify::Off,
+            format: Format::Table,
+            input_a: None,
+            input_b: None,
+            output: None,
+            list: false,
+            explain: false,
+            help: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            // Switches first; every other flag takes a value
+            match flag.as_str() {
+                "-h" | "--help" => options.help = true,
+                "--list" => options.list = true,
+                "--explain" => options.explain = true,
+                "--verify" => options.verify = Verify::Report,
+                "--verify-strict" => options.verify = Verify::Strict,
+                _ => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{} needs a value", flag))?;
+                    options.set(&flag, value)?;
+                }
+            }
+        }
+        if options.dtypes != [Dtype::F64]
+            && (options.mode != Mode::Latency || options.format != Format::Table)
+        {
+            return Err(
+                "--dtype: the f32 and i8 sections only run in latency mode, as tables".to_string(),
+            );
+        }
+        Ok(options)
+    }
+
+    /// Applies one `--flag value` pair.
+    fn set(&mut self, flag: &str, value: String) -> Result<(), String> {
+        match flag {
+            "--mode" => {
+                self.mode = match value.as_str() {
+                    "latency" => Mode::Latency,
+                    "throughput" => Mode::Throughput,
+                    "scaling" => Mode::Scaling,
+                    "primitives" => Mode::Primitives,
+                    "first-call" => Mode::FirstCall,
+                    "downclock" => Mode::Downclock,
+                    _ => {
+                        return Err(format!(
+                            "--mode: expected latency, throughput, scaling, primitives, first-call, or downclock, got '{}'",
+                            value
+                        ));
+                    }
+                }
+            }
+            "--sizes" => {
+                self.shapes = parse_list(flag, &value)?
+                    .into_iter()
+                    .map(Shape::square)
+                    .collect()
+            }
+            "--shapes" if value == "aspect" => self.shapes = ASPECT_SWEEP.to_vec(),
+            "--shapes" => {
+                self.shapes = value
+                    .split(',')
+                    .map(|item| Shape::parse(item.trim()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| {
+                        format!("--shapes: expected MxNxK,... or 'aspect', got '{}'", value)
+                    })?
+            }
+            "--iters" => self.timing.iterations = parse_positive(flag, &value)?,
+            "--min-time" => {
+                self.timing.min_time = value
+                    .parse()
+                    .ok()
+                    .filter(|secs: &f64| *secs >= 0.0)
+                    .ok_or_else(|| format!("--min-time: '{}' is not a duration", value))?
+            }
+            "--calls" => self.calls = parse_positive(flag, &value)?,
+            "--seed" => {
+                self.seed = value
+                    .parse()
+                    .map_err(|_| format!("--seed: '{}' is not an unsigned integer", value))?
+            }
+            "--threads" => self.threads = parse_positive(flag, &value)?,
+            "--only" | "--methods" => self.methods = Some(resolve_methods(flag, &value)?),
+            "--exclude" => self.exclude = resolve_methods(flag, &value)?,
+            "--dtype" => {
+                self.dtypes = value
+                    .split(',')
+                    .map(|item| Dtype::parse(item.trim()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| {
+                        format!(
+                            "--dtype: expected f64, f32, or i8 (comma-separated), got '{}'",
+                            value
+                        )
+                    })?
+            }
+            "--baseline" => self.baseline = Some(resolve_method(flag, value.trim())?.to_string()),
+            "--format" => {
+                self.format = match value.as_str() {
+                    "table" => Format::Table,
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    _ => {
+                        return Err(format!(
+                            "--format: expected table, csv, or json, got '{}'",
+                            value
+                        ));
+                    }
+                }
+            }
+            "--input-a" => self.input_a = Some(value),
+            "--input-b" => self.input_b = Some(value),
+            "--output" => self.output = Some(value),
+            _ => return Err(format!("unknown argument '{}'", flag)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_positive(flag: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|&x| x > 0)
+        .ok_or_else(|| format!("{}: '{}' is not a positive integer", flag, value))
+}
+
+fn parse_list(flag: &str, value: &str) -> Result<Vec<usize>, String> {
+    value
+        .split(',')
+        .map(|item| parse_positive(flag, item.trim()))
+        .collect()
+}
+
+/// The keys of a comma-separated list of methods, each given by key or by
+/// table name (see [`resolve_method`]).
+fn resolve_methods(flag: &str, value: &str) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(|item| resolve_method(flag, item.trim()).map(str::to_string))
+        .collect()
+}
+
+/// The key of one method this build has, given by key (`12x4mt`) or by its
+/// name in the table (`12×4 AVX2 MT`), either ignoring case, and with `x`
+/// accepted for `×`.
+fn resolve_method(flag: &str, method: &str) -> Result<&'static str, String> {
+    let normalize = |text: &str| text.to_lowercase().replace('×', "x");
+    let wanted = normalize(method);
+    let known = methods(&[], 1);
+    known
+        .iter()
+        .find(|m| m.key == wanted || normalize(m.name) == wanted)
+        .map(|m| m.key)
+        .ok_or_else(|| {
+            let keys: Vec<&str> = known.iter().map(|m| m.key).collect();
+            format!(
+                "{}: unknown method '{}'; valid methods: {} (or their names in --list)",
+                flag,
+                method,
+                keys.join(", ")
+            )
+        })
+}
+
+type MatmulFn<'a> = Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize) + 'a>;
+
+/// One benchmark row.
+struct Method<'a> {
+    /// Name for `--methods`
+    key: &'static str,
+    /// Name in the results table
+    name: &'static str,
+    /// Whether this CPU can run it
+    available: bool,
+    /// Whether it uses `--threads`; `--mode scaling` sweeps these
+    threaded: bool,
+    run: MatmulFn<'a>,
+}
+
+impl<'a> Method<'a> {
+    fn new(
+        key: &'static str,
+        name: &'static str,
+        run: impl Fn(&[f64], &[f64], &mut [f64], usize, usize, usize) + 'a,
+    ) -> Self {
+        Method {
+            key,
+            name,
+            available: true,
+            threaded: false,
+            run: Box::new(run),
+        }
+    }
+
+    fn threaded(mut self) -> Self {
+        self.threaded = true;
+        self
+    }
+
+    fn requires(mut self, available: bool) -> Self {
+        self.available = available;
+        self
+    }
+}
+
+/// `(key, name)` of the threaded row for each of [`gemm_backends`], in
+/// order: `12x4mt`, `12×4 AVX2 MT`. Made once, so rows can borrow them for
+/// `'static`.
+fn threaded_labels() -> &'static [(String, String)] {
+    static LABELS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+    LABELS.get_or_init(|| {
+        gemm_backends()
+            .iter()
+            .map(|backend| {
+                (
+                    format!("{}mt", backend.key()),
+                    format!("{} MT", backend.name()),
+                )
+            })
+            .collect()
+    })
+}
+
+/// Every method this build can benchmark, in table order. `bt` is B
+/// pre-transposed for the Bᵀ methods, like a caller that reuses B would have.
+fn methods(bt: &[f64], threads: usize) -> Vec<Method<'_>> {
+    let mut list = vec![
+        Method::new("naive", "Naive (i-j-k)", matmul_naive_ijk),
+        Method::new("ikj", "Scalar (i-k-j)", matmul_naive_ikj),
+        Method::new("jik", "Naive (j-i-k)", matmul_naive_jik),
+        Method::new("jki", "Naive (j-k-i)", matmul_naive_jki),
+        Method::new("kij", "Scalar (k-i-j)", matmul_naive_kij),
+        Method::new("kji", "Naive (k-j-i)", matmul_naive_kji),
+        Method::new("ikj-bt", "Scalar (Bᵀ)", move |a, _b, c, m, n, k| {
+            matmul_ikj_transposed(a, bt, c, m, n, k)
+        }),
+        Method::new("blocked-bt", "Blocked (Bᵀ)", move |a, _b, c, m, n, k| {
+            matmul_blocked_transposed(a, bt, c, m, n, k)
+        }),
+    ];
+    // SIMD with no packing or blocking, for what those buy
+    #[cfg(target_arch = "x86_64")]
+    list.push(
+        Method::new("simple", "Simple SIMD (4×4)", |a, b, c, m, n, k| {
+            simple_simd::run(a, b, c, m, n, k).expect("listed only with AVX2")
+        })
+        .requires(matmul::features::has_avx2()),
+    );
+
+    // The blocked backends, each alone and on `--threads` threads; the
+    // i-k-j one is already listed with the other loop orders
+    for (backend, (mt_key, mt_name)) in gemm_backends().iter().zip(threaded_labels()) {
+        if backend.mr() == 1 {
+            continue;
+        }
+        let backend = *backend;
+        list.extend([
+            Method::new(
+                backend.key(),
+                backend.name(),
+                move |a, b, c, m, n, k| unsafe { backend.run(a, b, c, m, n, k, 0..m) },
+            )
+            .requires(backend.supported()),
+            Method::new(mt_key, mt_name, move |a, b, c, m, n, k| unsafe {
+                parallel_rows(backend, a, b, c, m, n, k, threads, Schedule::default())
+            })
+            .threaded()
+            .requires(backend.supported()),
+        ]);
+    }
+
+    // Other libraries go last, so `print_blas_ratio` only ranks our rows
+    #[cfg(feature = "blas-bench")]
+    list.push(Method::new("blas", BLAS_ROW, multiply_blas));
+    #[cfg(feature = "compare-rust")]
+    list.extend([
+        Method::new("faer", "faer", multiply_faer),
+        Method::new("matrixmultiply", "matrixmultiply", multiply_matrixmultiply),
+    ]);
+
+    list
+}
+
+/// A and B for one shape from `--seed`.
+fn inputs(shape: Shape, seed: u64) -> (Vec<f64>, Vec<f64>) {
+    let Shape { m, n, k } = shape;
+    (random(m, k, seed), random(k, n, seed.wrapping_add(1)))
+}
+
+/// Fingerprint of one C, so two runs (or machines) can confirm they computed
+/// the same thing without shipping the matrix.
+#[derive(Clone, Copy)]
+struct Checksum {
+    /// Different kernels agree on this up to rounding
+    sum: f64,
+    /// FNV-1a over the elements' bit patterns; only bit-identical results
+    /// match, e.g. the same kernel on the same inputs elsewhere
+    bits: u64,
+}
+
+impl Checksum {
+    fn of(c: &[f64]) -> Self {
+        Checksum {
+            sum: c.iter().sum(),
+            bits: c.iter().fold(0xcbf2_9ce4_8422_2325, |hash, x| {
+                (hash ^ x.to_bits()).wrapping_mul(0x0000_0100_0000_01b3)
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Σ {:+.12e} #{:016x}", self.sum, self.bits)
+    }
+}
+
+/// The methods `--only` selects (all by default) and `--exclude` doesn't
+/// that run on this CPU.
+fn selected_methods<'a>(bt: &'a [f64], options: &Options) -> Vec<Method<'a>> {
+    methods(bt, options.threads)
+        .into_iter()
+        .filter(|method| method.available)
+        .filter(|method| match &options.methods {
+            Some(keys) => keys.iter().any(|key| key == method.key),
+            None => true,
+        })
+        .filter(|method| !options.exclude.iter().any(|key| key == method.key))
+        .collect()
+}
+
+/// Where `--baseline` is among the selected methods' keys: the first one
+/// without the flag, or if that method doesn't run here.
+fn baseline_index(keys: &[&str], options: &Options) -> usize {
+    options
+        .baseline
+        .as_ref()
+        .and_then(|baseline| keys.iter().position(|key| key == baseline))
+        .unwrap_or(0)
+}
+
+/// `--list`: every method this CPU runs, then the ones it doesn't.
+fn print_method_list() {
+    let all = methods(&[], 1);
+    println!("{:<16} {:<18} Threaded", "Key", "Name");
+    for method in all.iter().filter(|method| method.available) {
+        let threaded = if method.threaded { "yes" } else { "" };
+        let line = format!("{:<16} {:<18} {}", method.key, method.name, threaded);
+        println!("{}", line.trim_end());
+    }
+    let missing: Vec<&str> = all
+        .iter()
+        .filter(|method| !method.available)
+        .map(|method| method.key)
+        .collect();
+    if !missing.is_empty() {
+        println!("\nNot available on this CPU: {}", missing.join(", "));
+    }
+}
+
+/// `--explain`: per shape, what the [cost model](matmul::select) predicts on
+/// each backend, and why `multiply` runs the one it does.
+fn print_explanation(shapes: &[Shape]) {
+    use matmul::{Backend, GemmBackend};
+
+    for (i, shape) in shapes.iter().enumerate() {
+        let (m, n, k) = (shape.m, shape.n, shape.k);
+        let selected = matmul::select_backend(m, n, k);
+        let runs = matmul::backend_for(m, n, k);
+        let fastest = selected
+            .cost_model()
+            .map_or(0.0, |model| model.estimate(m, n, k).seconds);
+
+        if i > 0 {
+            println!();
+        }
+        println!("{}: multiply() runs {}", shape, runs);
+        println!(
+            "  {:<16} {:>10} {:>8} {:>9} {:>8}  Verdict",
+            "Backend", "Model µs", "GFLOPS", "In tiles", "Packing"
+        );
+        for backend in Backend::ALL.into_iter().rev() {
+            let name = GemmBackend::name(&backend);
+            let model = backend.cost_model();
+            let (estimate, verdict) = match model {
+                _ if !backend.is_available() => (None, "not available on this CPU".to_string()),
+                None => (
+                    None,
+                    "no cost model; not a multiply() candidate".to_string(),
+                ),
+                Some(model) => {
+                    let estimate = model.estimate(m, n, k);
+                    let verdict = if backend == selected {
+                        "chosen: fastest estimate".to_string()
+                    } else if estimate.seconds == fastest {
+                        "same estimate; ties go to the wider kernel".to_string()
+                    } else {
+                        format!("{:.2}× the fastest estimate", estimate.seconds / fastest)
+                    };
+                    (Some(estimate), verdict)
+                }
+            };
+            match estimate {
+                Some(estimate) => println!(
+                    "  {:<16} {:>10.1} {:>8.1} {:>8.1}% {:>7.1}%  {}",
+                    name,
+                    estimate.seconds * 1e6,
+                    2.0 * (m * n * k) as f64 / estimate.seconds / 1e9,
+                    estimate.tile_share * 100.0,
+                    estimate.packing_seconds / estimate.seconds * 100.0,
+                    verdict
+                ),
+                None => println!(
+                    "  {:<16} {:>10} {:>8} {:>9} {:>8}  {}",
+                    name, "-", "-", "-", "-", verdict
+                ),
+            }
+        }
+        if runs != selected {
+            let reason = if matmul::calibrate::dispatch_table().is_some() {
+                "the calibrated dispatch table picks it".to_string()
+            } else {
+                format!(
+                    "within the AVX-512 crossover ({})",
+                    matmul::avx512_crossover()
+                )
+            };
+            println!("  Runs {} instead: {}", runs, reason);
+        }
+    }
+}
+
+/// Prints progress to stdout in table mode and to stderr otherwise, so the
+/// csv/json document is the only thing on stdout.
+macro_rules! note {
+    ($format:expr, $($arg:tt)*) => {
+        if $format == Format::Table {
+            println!($($arg)*)
+        } else {
+            eprintln!($($arg)*)
+        }
+    };
+}
+
 fn main() {
-    println!("=== Matrix Multiplication Benchmark ===\n");
+    // Before anything else here can detect features or allocate scratch
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(FIRST_CALL_CHILD) {
+        if let Err(e) = first_call_child(&args[1..]) {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+        return;
+    }
+
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", USAGE);
+        let keys: Vec<&str> = methods(&[], 1).iter().map(|m| m.key).collect();
+        println!("\nMethods: {}", keys.join(", "));
+        return;
+    }
+    if options.list {
+        print_method_list();
+        return;
+    }
+
+    if options.explain {
+        print_explanation(&options.shapes);
+        return;
+    }
+
+    if options.input_a.is_some() || options.input_b.is_some() {
+        if let Err(e) = multiply_files(&options) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let format = options.format;
+    note!(format, "=== Matrix Multiplication Benchmark ===\n");
+
+    let has_avx2 = matmul::features::has_avx2();
+    let has_avx512 = matmul::features::has_avx512();
+
+    note!(
+        format,
+        "CPU features: {}; multiply() runs {}\n",
+        matmul::cpu_features(),
+        matmul::detected_backend()
+    );
+
+    let machine = roofline::Machine::estimate(has_avx2, has_avx512);
+    if let Some(machine) = &machine {
+        note!(
+            format,
+            "Peak (estimate): {:.2} GHz ({}) × {} FLOP/cycle ({}: {} lanes × {} FMA units × 2) = {:.1} GFLOPS per core, {:.1} on {} core(s)",
+            machine.ghz,
+            machine.ghz_source,
+            machine.flops_per_cycle(),
+            machine.isa,
+            machine.lanes,
+            roofline::FMA_UNITS,
+            machine.single_core_gflops(),
+            machine.all_core_gflops(),
+            machine.cores
+        );
+        note!(
+            format,
+            "  Assumes {} FMA units per core and that clock on every core; memory ceilings use {:.1} GB/s single-thread copy bandwidth\n",
+            roofline::FMA_UNITS,
+            machine.bandwidth_gbs
+        );
+    }
+
+    note!(
+        format,
+        "Seed: {} (A from {}, B from {})\n",
+        options.seed,
+        options.seed,
+        options.seed.wrapping_add(1)
+    );
+
+    // Compare single-threaded against single-threaded, like numpy_benchmark.py
+    #[cfg(feature = "blas-bench")]
+    set_blas_threads(1);
+
+    match options.mode {
+        Mode::Latency => {
+            let f64_results = if options.dtypes.contains(&Dtype::F64) {
+                bench_latency(&options, machine.as_ref(), has_avx2, has_avx512)
+            } else {
+                Vec::new()
+            };
+            bench_precisions(&options, &f64_results);
+        }
+        Mode::Throughput => bench_throughput(&options),
+        Mode::Scaling => bench_scaling(&options),
+        Mode::Primitives => bench_primitives(&options),
+        Mode::FirstCall => bench_first_call(&options),
+        Mode::Downclock => bench_downclock(&options),
+    }
+}
+
+/// `--mode latency` (the default): time each method per shape from cold
+/// operands, one call at a time, then the summary tables. Returns the rows,
+/// for the precision summary.
+fn bench_latency(
+    options: &Options,
+    machine: Option<&roofline::Machine>,
+    has_avx2: bool,
+    has_avx512: bool,
+) -> Vec<(Shape, Vec<Timed>)> {
+    let format = options.format;
+    let mut counters = perf::Counters::open();
+
+    // The same methods run at every shape, so the baseline's row is too
+    let keys: Vec<&str> = selected_methods(&[], options)
+        .iter()
+        .map(|method| method.key)
+        .collect();
+    let baseline = baseline_index(&keys, options);
+    if let Some(wanted) = &options.baseline
+        && keys.get(baseline) != Some(&wanted.as_str())
+        && let Some(first) = keys.first()
+    {
+        note!(
+            format,
+            "Baseline {} doesn't run here (filtered out or unsupported); speedups are against {}\n",
+            wanted,
+            first
+        );
+    }
+
+    let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        note!(format, "Matrix: {}", shape);
+        note!(format, "{}", "-".repeat(50));
+
+        let Shape { m, n, k } = shape;
+        if let Some(machine) = machine {
+            let ceiling = machine.memory_ceiling_gflops(m, n, k);
+            let bound = if ceiling < machine.all_core_gflops() {
+                "memory"
+            } else {
+                "compute"
+            };
+            note!(
+                format,
+                "Roofline: {:.1} FLOP/byte, memory ceiling {:.1} GFLOPS ({}-bound at peak)",
+                roofline::intensity(m, n, k),
+                ceiling,
+                bound
+            );
+        }
+        // The default seed gives the criterion suite's inputs in benches/
+        let (a, b) = inputs(shape, options.seed);
+        let mut bt = vec![0.0; k * n];
+        transpose(&b, &mut bt, k, n);
+
+        let selected = selected_methods(&bt, options);
+        // The i-k-j loop is the reference: simple enough to trust, and its
+        // rounding is close to the other scalar loops
+        let reference = (options.verify != Verify::Off).then(|| {
+            let mut c = vec![0.0; m * n];
+            matmul_naive_ikj(&a, &b, &mut c, m, n, k);
+            c
+        });
+
+        let results: Vec<Timed> = selected
+            .into_iter()
+            .map(|method| {
+                let Measured {
+                    time_ms,
+                    gflops,
+                    perf,
+                    memory,
+                    checksum,
+                } = bench_fn(&a, &b, m, n, k, options.timing, &mut counters, &method.run);
+                let verified = reference.as_ref().map(|expected| {
+                    let mut c = vec![0.0; m * n];
+                    (method.run)(&a, &b, &mut c, m, n, k);
+                    Verified {
+                        max_abs_err: max_abs_diff(expected, &c),
+                        mismatch: check_close(expected, &c, VERIFY_RTOL, VERIFY_ATOL).err(),
+                    }
+                });
+                if let Some(Verified {
+                    mismatch: Some(mismatch),
+                    ..
+                }) = &verified
+                    && options.verify == Verify::Strict
+                {
+                    eprintln!("error: {} at {}: {}", method.name, shape, mismatch);
+                    std::process::exit(1);
+                }
+                Timed {
+                    key: method.key,
+                    name: method.name,
+                    time_ms,
+                    gflops,
+                    verified,
+                    perf,
+                    memory,
+                    checksum,
+                }
+            })
+            .collect();
+        if results.is_empty() {
+            note!(format, "(none of the selected methods run on this CPU)\n");
+            continue;
+        }
+
+        // Print results
+        let baseline_time = results[baseline].time_ms;
+        for (i, row) in results.iter().enumerate() {
+            let check = match &row.verified {
+                None => String::new(),
+                Some(Verified {
+                    mismatch: Some(mismatch),
+                    ..
+                }) => format!("  FAILED: {}", mismatch),
+                Some(v) => format!("  ok (max err {:.1e})", v.max_abs_err),
+            };
+            let efficiency = match machine {
+                Some(machine) => format!(
+                    "  [{:.0}% 1-core, {:.0}% all-core peak]",
+                    100.0 * row.gflops / machine.single_core_gflops(),
+                    100.0 * row.gflops / machine.all_core_gflops()
+                ),
+                None => String::new(),
+            };
+            note!(
+                format,
+                "{}. {:16} {:8.2} ms  {:6.2} GFLOPS  ({:.1}×){}  {}{}",
+                i + 1,
+                row.name,
+                row.time_ms,
+                row.gflops,
+                baseline_time / row.time_ms,
+                efficiency,
+                row.checksum,
+                check
+            );
+            if let Some(perf) = &row.perf {
+                note!(format, "   {}", perf);
+            }
+        }
+        #[cfg(feature = "blas-bench")]
+        if let Some(ratio) = blas_ratio(&results) {
+            note!(format, "{}", ratio);
+        }
+        note!(format, "");
+
+        all_results.push((shape, results));
+    }
+
+    let failures = all_results
+        .iter()
+        .flat_map(|(_, results)| results)
+        .filter(|row| matches!(&row.verified, Some(v) if v.mismatch.is_some()))
+        .count();
+    if failures > 0 {
+        eprintln!("warning: {} result(s) failed verification", failures);
+    }
+
+    match format {
+        Format::Table => {
+            if !all_results.is_empty() {
+                print_summary_table(&all_results, baseline);
+                print_memory_table(&all_results);
+            }
+            if options.methods.is_none() && options.exclude.is_empty() {
+                bench_transpose(has_avx2, has_avx512);
+                bench_elementwise();
+            }
+        }
+        Format::Csv => print_csv(&all_results, options.verify, baseline),
+        Format::Json => print_json(
+            &all_results,
+            options,
+            baseline,
+            has_avx2,
+            has_avx512,
+            machine,
+        ),
+    }
+    all_results
+}
+
+/// A row of an f32 or i8 section: one method, timed at a shape on
+/// `--seed`'s inputs of its type.
+struct PrecisionMethod {
+    key: &'static str,
+    name: &'static str,
+    /// Seconds per call
+    time: fn(Shape, &Options) -> f64,
+}
+
+/// The methods for one `--dtype` besides f64 (whose section is the
+/// [`methods`] table).
+fn precision_methods(dtype: Dtype) -> Vec<PrecisionMethod> {
+    match dtype {
+        Dtype::F64 => Vec::new(),
+        Dtype::F32 => vec![PrecisionMethod {
+            key: "ikj-f32",
+            name: "Scalar (i-k-j)",
+            time: time_ikj::<f32, f32>,
+        }],
+        Dtype::I8 => vec![
+            PrecisionMethod {
+                key: "ikj-i8",
+                name: "Scalar (i-k-j)",
+                time: time_ikj::<i8, i32>,
+            },
+            PrecisionMethod {
+                key: "i8-acc32",
+                name: "Blocked, i32",
+                time: time_i8::<i32>,
+            },
+            PrecisionMethod {
+                key: "i8-acc64",
+                name: "Blocked, i64",
+                time: time_i8::<i64>,
+            },
+        ],
+    }
+}
+
+/// [`time_per_call`] for the i-k-j loop on `T` inputs, summing in `Acc`.
+fn time_ikj<T, Acc>(shape: Shape, options: &Options) -> f64
+where
+    T: RandomElement,
+    Acc: Copy + Default + From<T> + std::ops::Mul<Output = Acc> + std::ops::AddAssign,
+{
+    let Shape { m, n, k } = shape;
+    let a: Vec<T> = random_of(m, k, options.seed);
+    let b: Vec<T> = random_of(k, n, options.seed.wrapping_add(1));
+    let mut c = vec![Acc::default(); m * n];
+    time_per_call(options.timing, || {
+        c.fill(Acc::default());
+        for i in 0..m {
+            let c_row = &mut c[i * n..(i + 1) * n];
+            for p in 0..k {
+                let a_ip = Acc::from(a[i * k + p]);
+                for (c_ij, &b_pj) in c_row.iter_mut().zip(&b[p * n..(p + 1) * n]) {
+                    *c_ij += a_ip * Acc::from(b_pj);
+                }
+            }
+        }
+        std::hint::black_box(&mut c);
+    })
+}
+
+/// [`time_per_call`] for `multiply_i8`, summing in `Acc`.
+fn time_i8<Acc: Accumulator + Default>(shape: Shape, options: &Options) -> f64 {
+    let Shape { m, n, k } = shape;
+    let a: Vec<i8> = random_of(m, k, options.seed);
+    let b: Vec<i8> = random_of(k, n, options.seed.wrapping_add(1));
+    let mut c = vec![Acc::default(); m * n];
+    time_per_call(options.timing, || {
+        c.fill(Acc::default());
+        multiply_i8(&a, &b, &mut c, m, n, k);
+        std::hint::black_box(&mut c);
+    })
+}
+
+/// The f32 and i8 sections `--dtype` asks for, each a table of rate per
+/// method and shape; then, with more than one type, the best rate of each
+/// per shape and against f64's best (`f64_results`, if it ran).
+fn bench_precisions(options: &Options, f64_results: &[(Shape, Vec<Timed>)]) {
+    let width = 24 + options.shapes.len() * 17;
+    // Best rate per shape, per type
+    let mut best: Vec<(Dtype, Vec<f64>)> = Vec::new();
+    if !f64_results.is_empty() {
+        let rates = f64_results
+            .iter()
+            .map(|(_, results)| results.iter().map(|row| row.gflops).fold(0.0, f64::max))
+            .collect();
+        best.push((Dtype::F64, rates));
+    }
+
+    for &dtype in options.dtypes.iter().filter(|&&dtype| dtype != Dtype::F64) {
+        let methods = precision_methods(dtype);
+        println!(
+            "{} ({}, 2·m·n·k operations per multiply)",
+            dtype.key(),
+            dtype.unit()
+        );
+        println!("{}", "=".repeat(width));
+        print!("{:<24}", "Method");
+        for shape in &options.shapes {
+            print!(" {:>16}", shape.to_string());
+        }
+        println!();
+        println!("{}", "-".repeat(width));
+        let mut rates = vec![0.0f64; options.shapes.len()];
+        for method in &methods {
+            print!("{:<24}", format!("{} [{}]", method.name, method.key));
+            for (rate, &shape) in rates.iter_mut().zip(&options.shapes) {
+                let Shape { m, n, k } = shape;
+                let ops = 2.0 * (m * n * k) as f64 / (method.time)(shape, options) / 1e9;
+                *rate = rate.max(ops);
+                print!(" {:>16}", format!("{:.2} {}", ops, dtype.unit()));
+            }
+            println!();
+        }
+        println!("{}\n", "=".repeat(width));
+        best.push((dtype, rates));
+    }
+
+    if best.len() < 2 {
+        return;
+    }
+    println!("OPS/SEC BY PRECISION (best method per type)");
+    println!("{}", "=".repeat(width + 12));
+    print!("{:<24}", "Type");
+    for shape in &options.shapes {
+        print!(" {:>16}", shape.to_string());
+    }
+    println!(" {:>11}", "vs f64");
+    println!("{}", "-".repeat(width + 12));
+    let f64_best = best
+        .iter()
+        .find(|(dtype, _)| *dtype == Dtype::F64)
+        .map(|(_, rates)| rates.clone());
+    for (dtype, rates) in &best {
+        print!("{:<24}", dtype.key());
+        for rate in rates {
+            print!(" {:>16}", format!("{:.2} {}", rate, dtype.unit()));
+        }
+        match &f64_best {
+            Some(f64_best) => {
+                let ratio = rates.iter().zip(f64_best).map(|(x, y)| x / y).sum::<f64>()
+                    / rates.len() as f64;
+                println!(" {:>10.1}×", ratio);
+            }
+            None => println!(" {:>11}", "n/a"),
+        }
+    }
+    println!("{}", "=".repeat(width + 12));
+    println!(
+        "\nEach SIMD halving of the element size doubles the lanes, so a kernel\nfor it should show up as roughly 2× (f32) and 4× (i8) of f64.\n"
+    );
+}
+
+/// How many different A matrices throughput mode cycles through, so each
+/// call streams a fresh A against the same B, the way a batch of requests
+/// against one weight matrix would
+const THROUGHPUT_A_POOL: usize = 4;
+
+/// `--mode throughput`: per shape and method, `--calls` back-to-back
+/// multiplies into one reused C, each timed on its own.
+fn bench_throughput(options: &Options) {
+    let format = options.format;
+    let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        note!(format, "Matrix: {} (throughput)", shape);
+        note!(format, "{}", "-".repeat(50));
+
+        let Shape { m, n, k } = shape;
+        let (a, b) = inputs(shape, options.seed);
+        // The rest of the pool skips B's seed
+        let a_pool: Vec<Vec<f64>> = std::iter::once(a)
+            .chain(
+                (1..THROUGHPUT_A_POOL as u64)
+                    .map(|i| random(m, k, options.seed.wrapping_add(2 * i))),
+            )
+            .collect();
+        let mut bt = vec![0.0; k * n];
+        transpose(&b, &mut bt, k, n);
+
+        let results: Vec<Sustained> = selected_methods(&bt, options)
+            .into_iter()
+            .map(|method| Sustained {
+                key: method.key,
+                name: method.name,
+                ..sustain(&a_pool, &b, shape, options, &method.run)
+            })
+            .collect();
+        if results.is_empty() {
+            note!(format, "(none of the selected methods run on this CPU)\n");
+            continue;
+        }
+        for (i, row) in results.iter().enumerate() {
+            let [p50, p90, p99, max] = row.latency_ms;
+            note!(
+                format,
+                "{}. {:16} {:6.2} GFLOPS sustained  p50 {:.3} / p90 {:.3} / p99 {:.3} / max {:.3} ms  ({} calls, {:.1} allocs/call)",
+                i + 1,
+                row.name,
+                row.gflops,
+                p50,
+                p90,
+                p99,
+                max,
+                row.calls,
+                row.allocs_per_call
+            );
+        }
+        note!(format, "");
+        all_results.push((shape, results));
+    }
+
+    match format {
+        Format::Table => {}
+        Format::Csv => print_throughput_csv(&all_results),
+        Format::Json => print_throughput_json(&all_results, options),
+    }
+}
+
+/// One method's sustained numbers at one shape.
+struct Sustained {
+    key: &'static str,
+    name: &'static str,
+    calls: usize,
+    /// Total flops over total time in the timed calls
+    gflops: f64,
+    /// p50, p90, p99, and max of the per-call times
+    latency_ms: [f64; 4],
+    /// Heap allocations per call (C is reused, so these are the method's own)
+    allocs_per_call: f64,
+}
+
+/// One warmup call, then at least `options.calls` timed calls (continuing
+/// until `--min-time` is spent), cycling through `a_pool` into the same C.
+fn sustain<F>(a_pool: &[Vec<f64>], b: &[f64], shape: Shape, options: &Options, f: F) -> Sustained
+where
+    F: Fn(&[f64], &[f64], &mut [f64], usize, usize, usize),
+{
+    let Shape { m, n, k } = shape;
+    // C accumulates across calls; the values grow but stay far from overflow
+    let mut c = vec![0.0; m * n];
+    f(&a_pool[0], b, &mut c, m, n, k);
+
+    let mut latencies = Vec::with_capacity(options.calls);
+    let mut total = 0.0;
+    let mut allocations = 0;
+    while latencies.len() < options.calls || total < options.timing.min_time {
+        let a = &a_pool[latencies.len() % a_pool.len()];
+        let before = memory::allocations();
+        let start = Instant::now();
+        f(a, b, &mut c, m, n, k);
+        let elapsed = start.elapsed().as_secs_f64();
+        allocations += memory::allocations() - before;
+        total += elapsed;
+        latencies.push(elapsed * 1000.0);
+    }
+
+    let calls = latencies.len();
+    latencies.sort_by(f64::total_cmp);
+    // Nearest rank: the smallest time at least that share of calls stayed within
+    let percentile = |p: f64| latencies[((p * calls as f64).ceil() as usize).clamp(1, calls) - 1];
+    Sustained {
+        key: "",
+        name: "",
+        calls,
+        gflops: 2.0 * (m * n * k) as f64 * calls as f64 / total / 1e9,
+        latency_ms: [
+            percentile(0.5),
+            percentile(0.9),
+            percentile(0.99),
+            latencies[calls - 1],
+        ],
+        allocs_per_call: allocations as f64 / calls as f64,
+    }
+}
+
+/// `--mode throughput --format csv`.
+fn print_throughput_csv(all_results: &[(Shape, Vec<Sustained>)]) {
+    println!("method,m,n,k,calls,gflops,p50_ms,p90_ms,p99_ms,max_ms,allocs_per_call");
+    for (shape, results) in all_results {
+        for row in results {
+            let [p50, p90, p99, max] = row.latency_ms;
+            println!(
+                "{},{},{},{},{},{:.4},{:.6},{:.6},{:.6},{:.6},{:.2}",
+                row.key,
+                shape.m,
+                shape.n,
+                shape.k,
+                row.calls,
+                row.gflops,
+                p50,
+                p90,
+                p99,
+                max,
+                row.allocs_per_call
+            );
+        }
+    }
+}
+
+/// `--mode throughput --format json`.
+fn print_throughput_json(all_results: &[(Shape, Vec<Sustained>)], options: &Options) {
+    let rows: Vec<String> = all_results
+        .iter()
+        .flat_map(|(shape, results)| {
+            results.iter().map(move |row| {
+                let [p50, p90, p99, max] = row.latency_ms.map(json_number);
+                format!(
+                    "    {{\"method\": \"{}\", \"name\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"calls\": {}, \"gflops\": {}, \"p50_ms\": {}, \"p90_ms\": {}, \"p99_ms\": {}, \"max_ms\": {}, \"allocs_per_call\": {}}}",
+                    row.key,
+                    row.name,
+                    shape.m,
+                    shape.n,
+                    shape.k,
+                    row.calls,
+                    json_number(row.gflops),
+                    p50,
+                    p90,
+                    p99,
+                    max,
+                    json_number(row.allocs_per_call)
+                )
+            })
+        })
+        .collect();
+
+    println!("{{");
+    println!("  \"mode\": \"throughput\",");
+    println!("  \"seed\": {},", options.seed);
+    println!("  \"threads\": {},", options.threads);
+    println!(
+        "  \"min_time_s\": {},",
+        json_number(options.timing.min_time)
+    );
+    println!("  \"results\": [");
+    println!("{}", rows.join(",\n"));
+    println!("  ]");
+    println!("}}");
+}
+
+/// `--mode scaling`: each threaded method at 1, 2, 4, … threads up to
+/// `available_parallelism()`, same inputs throughout, every result checked
+/// against the 1-thread one.
+fn bench_scaling(options: &Options) {
+    let format = options.format;
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = scaling_thread_counts(max_threads);
+    let keys: Vec<&str> = selected_methods(&[], options)
+        .into_iter()
+        .filter(|method| method.threaded)
+        .map(|method| method.key)
+        .collect();
+    if keys.is_empty() {
+        note!(format, "(none of the selected methods are threaded)\n");
+    }
+    note!(
+        format,
+        "Threads: {:?} (of {} available)\n",
+        thread_counts,
+        max_threads
+    );
+
+    let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        let Shape { m, n, k } = shape;
+        let (a, b) = inputs(shape, options.seed);
+        let mut bt = vec![0.0; k * n];
+        transpose(&b, &mut bt, k, n);
+
+        let mut results = Vec::new();
+        for &key in &keys {
+            note!(format, "Matrix: {}, {}", shape, key);
+            note!(format, "{}", "-".repeat(50));
+            let mut single = None;
+            for &threads in &thread_counts {
+                let method = methods(&bt, threads)
+                    .into_iter()
+                    .find(|method| method.key == key)
+                    .expect("selected methods exist at every thread count");
+                let measured = bench_fn(&a, &b, m, n, k, options.timing, &mut None, &method.run);
+                let mut c = vec![0.0; m * n];
+                (method.run)(&a, &b, &mut c, m, n, k);
+                // thread_counts starts at 1
+                let (reference, single_ms): &(Vec<f64>, f64) =
+                    single.get_or_insert((c.clone(), measured.time_ms));
+                let mismatch = check_close(reference, &c, VERIFY_RTOL, VERIFY_ATOL).err();
+                let row = Scaled {
+                    key: method.key,
+                    name: method.name,
+                    threads,
+                    time_ms: measured.time_ms,
+                    gflops: measured.gflops,
+                    speedup: single_ms / measured.time_ms,
+                    max_abs_diff: max_abs_diff(reference, &c),
+                };
+                let check = match &mismatch {
+                    Some(mismatch) => format!("FAILED: {}", mismatch),
+                    None => format!("ok (max diff {:.1e})", row.max_abs_diff),
+                };
+                note!(
+                    format,
+                    "{:3} thread(s) {:9.2} ms  {:6.2} GFLOPS  {:5.2}× speedup  {:3.0}% efficiency  {}",
+                    threads,
+                    row.time_ms,
+                    row.gflops,
+                    row.speedup,
+                    100.0 * row.efficiency(),
+                    check
+                );
+                if let Some(mismatch) = mismatch {
+                    eprintln!(
+                        "error: {} at {} with {} threads differs from 1 thread: {}",
+                        method.name, shape, threads, mismatch
+                    );
+                    std::process::exit(1);
+                }
+                results.push(row);
+            }
+            note!(format, "");
+        }
+        all_results.push((shape, results));
+    }
+
+    match format {
+        Format::Table => {}
+        Format::Csv => {
+            println!("method,m,n,k,threads,time_ms,gflops,speedup,efficiency,max_abs_diff");
+            for (shape, results) in &all_results {
+                for row in results {
+                    println!(
+                        "{},{},{},{},{},{:.6},{:.4},{:.4},{:.4},{:e}",
+                        row.key,
+                        shape.m,
+                        shape.n,
+                        shape.k,
+                        row.threads,
+                        row.time_ms,
+                        row.gflops,
+                        row.speedup,
+                        row.efficiency(),
+                        row.max_abs_diff
+                    );
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<String> = all_results
+                .iter()
+                .flat_map(|(shape, results)| {
+                    results.iter().map(move |row| {
+                        format!(
+                            "    {{\"method\": \"{}\", \"name\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"threads\": {}, \"time_ms\": {}, \"gflops\": {}, \"speedup\": {}, \"efficiency\": {}, \"max_abs_diff\": {}}}",
+                            row.key,
+                            row.name,
+                            shape.m,
+                            shape.n,
+                            shape.k,
+                            row.threads,
+                            json_number(row.time_ms),
+                            json_number(row.gflops),
+                            json_number(row.speedup),
+                            json_number(row.efficiency()),
+                            json_number(row.max_abs_diff)
+                        )
+                    })
+                })
+                .collect();
+            println!("{{");
+            println!("  \"mode\": \"scaling\",");
+            println!("  \"seed\": {},", options.seed);
+            println!("  \"available_parallelism\": {},", max_threads);
+            println!("  \"iterations\": {},", options.timing.iterations);
+            println!("  \"results\": [");
+            println!("{}", rows.join(",\n"));
+            println!("  ]");
+            println!("}}");
+        }
+    }
+}
+
+/// 1, 2, 4, … below `max`, then `max` itself.
+fn scaling_thread_counts(max: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |&t| Some(t * 2))
+        .take_while(|&t| t < max)
+        .collect();
+    counts.push(max);
+    counts
+}
+
+/// One threaded method at one thread count.
+struct Scaled {
+    key: &'static str,
+    name: &'static str,
+    threads: usize,
+    time_ms: f64,
+    gflops: f64,
+    /// Over the same method on 1 thread
+    speedup: f64,
+    /// Against the 1-thread result
+    max_abs_diff: f64,
+}
+
+impl Scaled {
+    /// Speedup per thread: 1.0 is perfect scaling
+    fn efficiency(&self) -> f64 {
+        self.speedup / self.threads as f64
+    }
+}
+
+/// `--mode primitives`: the transposes and panel packing the blocked drivers
+/// run before any arithmetic, timed on their own, in GB/s (bytes read plus
+/// bytes written).
+///
+/// Per shape, the transposes take B (k×n) and the packing sweeps a whole
+/// A (m×k) or Bᵀ in the drivers' kc × mc blocks.
+fn bench_primitives(options: &Options) {
+    let format = options.format;
+    let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        note!(format, "Matrix: {} (primitives)", shape);
+        note!(format, "{}", "-".repeat(50));
+
+        let Shape { n, k, .. } = shape;
+        let (a, b) = inputs(shape, options.seed);
+        let mut bt = vec![0.0; k * n];
+        transpose(&b, &mut bt, k, n);
+
+        let results: Vec<(&str, f64, f64)> = primitives(&a, &b, &bt, shape)
+            .into_iter()
+            .map(|(name, bytes, mut f)| {
+                let time_ms = time_per_call(options.timing, &mut f) * 1000.0;
+                (name, time_ms, bytes as f64 / (time_ms / 1000.0) / 1e9)
+            })
+            .collect();
+        for (i, (name, time_ms, gbps)) in results.iter().enumerate() {
+            note!(
+                format,
+                "{}. {:22} {:8.3} ms  {:6.2} GB/s",
+                i + 1,
+                name,
+                time_ms,
+                gbps
+            );
+        }
+        note!(format, "");
+        all_results.push((shape, results));
+    }
+
+    match format {
+        Format::Table => {}
+        Format::Csv => {
+            println!("primitive,m,n,k,time_ms,gbps");
+            for (shape, results) in &all_results {
+                for (name, time_ms, gbps) in results {
+                    println!(
+                        "{},{},{},{},{:.6},{:.4}",
+                        name, shape.m, shape.n, shape.k, time_ms, gbps
+                    );
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<String> = all_results
+                .iter()
+                .flat_map(|(shape, results)| {
+                    results.iter().map(move |(name, time_ms, gbps)| {
+                        format!(
+                            "    {{\"primitive\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"time_ms\": {}, \"gbps\": {}}}",
+                            name,
+                            shape.m,
+                            shape.n,
+                            shape.k,
+                            json_number(*time_ms),
+                            json_number(*gbps)
+                        )
+                    })
+                })
+                .collect();
+            println!("{{");
+            println!("  \"mode\": \"primitives\",");
+            println!("  \"iterations\": {},", options.timing.iterations);
+            println!("  \"results\": [");
+            println!("{}", rows.join(",\n"));
+            println!("  ]");
+            println!("}}");
+        }
+    }
+}
+
+/// Steps of [`scalar_workload`]: a few hundred microseconds, about the time
+/// the lower AVX-512 clock lasts after the last 512-bit instruction.
+const SCALAR_WORKLOAD_STEPS: usize = 150_000;
+
+/// `--mode downclock`'s stand-in for the application code around a
+/// multiply: one long chain of integer and scalar floating-point steps,
+/// nothing a compiler can vectorize.
+fn scalar_workload() -> f64 {
+    let mut x = std::hint::black_box(0x9e37_79b9_7f4a_7c15u64);
+    let mut y = 1.0f64;
+    for _ in 0..SCALAR_WORKLOAD_STEPS {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        y = y * 0.999_999 + (x >> 40) as f64 * 1e-12;
+    }
+    std::hint::black_box(y)
+}
+
+/// One `--mode downclock` row's medians, in ms: the multiply, the scalar
+/// workload alone, and the workload right after the multiply.
+struct Downclocked {
+    backend: matmul::Backend,
+    multiply_ms: f64,
+    alone_ms: f64,
+    after_ms: f64,
+}
+
+impl Downclocked {
+    /// How much slower the workload ran after the multiply, in percent
+    fn slowdown(&self) -> f64 {
+        100.0 * (self.after_ms / self.alone_ms - 1.0)
+    }
+}
+
+/// `--mode downclock`: whether a multiply on the AVX-512 kernel, by slowing
+/// the clock, costs the code after it more than it saves over the AVX2 one.
+/// Each of `--iters` rounds keeps the core on scalar code for a few ms (so
+/// any 512-bit license has lapsed), times the workload alone, then the
+/// multiply, then the workload again. The crossover `multiply` uses here is
+/// printed alongside; see `matmul::downclock`.
+fn bench_downclock(options: &Options) {
+    use matmul::{Backend, GemmBackend, multiply_with_backend};
+
+    let format = options.format;
+    let backends: Vec<Backend> = [Backend::Avx512_8x8, Backend::Avx2_12x4]
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .collect();
+    if backends.is_empty() {
+        note!(
+            format,
+            "(no SIMD kernels on this CPU: nothing to compare)\n"
+        );
+    }
+    note!(
+        format,
+        "AVX-512 crossover: {} (default for this CPU: {})\n",
+        matmul::avx512_crossover(),
+        matmul::default_avx512_crossover()
+    );
+
+    let median = |mut samples: Vec<f64>| {
+        samples.sort_by(f64::total_cmp);
+        samples[samples.len() / 2]
+    };
+    let elapsed_ms = |f: &mut dyn FnMut()| {
+        let start = Instant::now();
+        f();
+        start.elapsed().as_secs_f64() * 1000.0
+    };
+    let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        let Shape { m, n, k } = shape;
+        let (a, b) = inputs(shape, options.seed);
+        let mut c = vec![0.0; m * n];
+        note!(format, "Matrix: {} (downclock)", shape);
+        note!(format, "{}", "-".repeat(50));
+
+        let mut results = Vec::new();
+        for &backend in &backends {
+            let mut samples = [Vec::new(), Vec::new(), Vec::new()];
+            for _ in 0..options.timing.iterations {
+                let settle = Instant::now();
+                while settle.elapsed().as_secs_f64() < 0.005 {
+                    scalar_workload();
+                }
+                samples[0].push(elapsed_ms(&mut || {
+                    multiply_with_backend(backend, &a, &b, &mut c, m, n, k);
+                }));
+                samples[2].push(elapsed_ms(&mut || {
+                    scalar_workload();
+                }));
+                // Alone, after settling again
+                let settle = Instant::now();
+                while settle.elapsed().as_secs_f64() < 0.005 {
+                    scalar_workload();
+                }
+                samples[1].push(elapsed_ms(&mut || {
+                    scalar_workload();
+                }));
+            }
+            let [multiply_ms, alone_ms, after_ms] = samples.map(median);
+            let row = Downclocked {
+                backend,
+                multiply_ms,
+                alone_ms,
+                after_ms,
+            };
+            note!(
+                format,
+                "{:14} multiply {:8.3} ms  scalar after {:7.3} ms (alone {:7.3} ms, {:+5.1}%)  total {:8.3} ms",
+                backend.to_string(),
+                row.multiply_ms,
+                row.after_ms,
+                row.alone_ms,
+                row.slowdown(),
+                row.multiply_ms + row.after_ms
+            );
+            results.push(row);
+        }
+        if let [avx512, avx2] = &results[..] {
+            let total = |row: &Downclocked| row.multiply_ms + row.after_ms;
+            let faster = if total(avx512) <= total(avx2) {
+                avx512.backend
+            } else {
+                avx2.backend
+            };
+            note!(
+                format,
+                "Faster overall: {}; multiply() runs {}",
+                faster,
+                matmul::backend_for(m, n, k)
+            );
+        }
+        note!(format, "");
+        all_results.push((shape, results));
+    }
+
+    match format {
+        Format::Table => {}
+        Format::Csv => {
+            println!("backend,m,n,k,multiply_ms,alone_ms,after_ms");
+            for (shape, results) in &all_results {
+                for row in results {
+                    println!(
+                        "{},{},{},{},{:.6},{:.6},{:.6}",
+                        row.backend.key(),
+                        shape.m,
+                        shape.n,
+                        shape.k,
+                        row.multiply_ms,
+                        row.alone_ms,
+                        row.after_ms
+                    );
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<String> = all_results
+                .iter()
+                .flat_map(|(shape, results)| {
+                    results.iter().map(move |row| {
+                        format!(
+                            "    {{\"backend\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"multiply_ms\": {}, \"alone_ms\": {}, \"after_ms\": {}}}",
+                            row.backend.key(),
+                            shape.m,
+                            shape.n,
+                            shape.k,
+                            json_number(row.multiply_ms),
+                            json_number(row.alone_ms),
+                            json_number(row.after_ms)
+                        )
+                    })
+                })
+                .collect();
+            println!("{{");
+            println!("  \"mode\": \"downclock\",");
+            println!("  \"iterations\": {},", options.timing.iterations);
+            println!("  \"avx512_crossover\": {},", matmul::avx512_crossover());
+            println!("  \"results\": [");
+            println!("{}", rows.join(",\n"));
+            println!("  ]");
+            println!("}}");
+        }
+    }
+}
+
+/// The hidden first argument that makes this binary one `--mode first-call`
+/// sample: `FIRST_CALL_CHILD m n k start seed iterations`.
+const FIRST_CALL_CHILD: &str = "--first-call-child";
+
+/// What a `--mode first-call` sample does before its first multiply.
+#[derive(Clone, Copy)]
+enum Start {
+    /// Nothing: the process's first multiply pays every one-time cost
+    Cold,
+    /// `matmul::warmup()`
+    Warmup,
+    /// `matmul::warmup_for(m, n, k)`
+    WarmupFor,
+}
+
+impl Start {
+    const ALL: [Start; 3] = [Start::Cold, Start::Warmup, Start::WarmupFor];
+
+    fn key(self) -> &'static str {
+        match self {
+            Start::Cold => "cold",
+            Start::Warmup => "warmup",
+            Start::WarmupFor => "warmup_for",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Start::Cold => "cold",
+            Start::Warmup => "warmup()",
+            Start::WarmupFor => "warmup_for(m, n, k)",
+        }
+    }
+}
+
+/// One sample's times, in ms: the warmup, the first and second multiplies,
+/// and the average of the calls after.
+type FirstCall = [f64; 4];
+
+/// `--mode first-call`: the one-time costs of `multiply`, which a process
+/// pays only once, so each sample is a fresh child process of this binary.
+/// Per shape and [`Start`], `--iters` samples, reported as medians.
+///
+/// The first call gets a C fresh from the allocator, as a one-shot tool's
+/// would; its page faults count, whatever the warmup.
+fn bench_first_call(options: &Options) {
+    let format = options.format;
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("error: can't find this binary to rerun it: {}", e);
+            std::process::exit(1);
+        }
+    };
+    note!(
+        format,
+        "multiply() per fresh process, median of {} run(s)\n",
+        options.timing.iterations
+    );
 
-    let sizes = [256, 512, 1024];
-    let iterations = 3;
     let mut all_results = Vec::new();
+    for &shape in &options.shapes {
+        note!(format, "Matrix: {} (first call)", shape);
+        note!(format, "{}", "-".repeat(50));
+        let mut results = Vec::new();
+        for start in Start::ALL {
+            let samples: Vec<FirstCall> = (0..options.timing.iterations)
+                .map(|_| run_first_call_child(&exe, shape, start, options))
+                .collect();
+            let median = std::array::from_fn(|i| {
+                let mut column: Vec<f64> = samples.iter().map(|sample| sample[i]).collect();
+                column.sort_by(f64::total_cmp);
+                column[column.len() / 2]
+            });
+            let [warmup_ms, first_ms, second_ms, steady_ms]: FirstCall = median;
+            note!(
+                format,
+                "{:20} warmup {:8.3} ms  1st {:8.3} ms  2nd {:8.3} ms  steady {:8.3} ms  1st/steady {:5.2}×",
+                start.label(),
+                warmup_ms,
+                first_ms,
+                second_ms,
+                steady_ms,
+                first_ms / steady_ms
+            );
+            results.push((start, median));
+        }
+        note!(format, "");
+        all_results.push((shape, results));
+    }
+
+    match format {
+        Format::Table => {}
+        Format::Csv => {
+            println!("start,m,n,k,warmup_ms,first_ms,second_ms,steady_ms");
+            for (shape, results) in &all_results {
+                for (start, [warmup_ms, first_ms, second_ms, steady_ms]) in results {
+                    println!(
+                        "{},{},{},{},{:.6},{:.6},{:.6},{:.6}",
+                        start.key(),
+                        shape.m,
+                        shape.n,
+                        shape.k,
+                        warmup_ms,
+                        first_ms,
+                        second_ms,
+                        steady_ms
+                    );
+                }
+            }
+        }
+        Format::Json => {
+            let rows: Vec<String> = all_results
+                .iter()
+                .flat_map(|(shape, results)| {
+                    results.iter().map(move |(start, times)| {
+                        let [warmup_ms, first_ms, second_ms, steady_ms] = times.map(json_number);
+                        format!(
+                            "    {{\"start\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"warmup_ms\": {}, \"first_ms\": {}, \"second_ms\": {}, \"steady_ms\": {}}}",
+                            start.key(),
+                            shape.m,
+                            shape.n,
+                            shape.k,
+                            warmup_ms,
+                            first_ms,
+                            second_ms,
+                            steady_ms
+                        )
+                    })
+                })
+                .collect();
+            println!("{{");
+            println!("  \"mode\": \"first-call\",");
+            println!("  \"iterations\": {},", options.timing.iterations);
+            println!("  \"results\": [");
+            println!("{}", rows.join(",\n"));
+            println!("  ]");
+            println!("}}");
+        }
+    }
+}
+
+/// Runs one `--mode first-call` sample in a child process.
+fn run_first_call_child(
+    exe: &std::path::Path,
+    shape: Shape,
+    start: Start,
+    options: &Options,
+) -> FirstCall {
+    let output = std::process::Command::new(exe)
+        .arg(FIRST_CALL_CHILD)
+        .args([shape.m, shape.n, shape.k].map(|x| x.to_string()))
+        .arg(start.key())
+        .arg(options.seed.to_string())
+        .arg(options.timing.iterations.to_string())
+        .output();
+    let sample = output
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| {
+            let times: Vec<f64> = String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .map(|field| field.parse().ok())
+                .collect::<Option<_>>()?;
+            times.try_into().ok()
+        });
+    sample.unwrap_or_else(|| {
+        eprintln!("error: the {} sample at {} failed", start.label(), shape);
+        std::process::exit(1);
+    })
+}
+
+/// The child side of [`run_first_call_child`]: prints the sample's
+/// [`FirstCall`] times, space-separated.
+fn first_call_child(args: &[String]) -> Result<(), String> {
+    let [m, n, k, start, seed, iterations] = args else {
+        return Err(format!(
+            "{}: expected m n k start seed iterations",
+            FIRST_CALL_CHILD
+        ));
+    };
+    let [m, n, k, iterations] = [m, n, k, iterations].map(|x| x.parse::<usize>().ok());
+    let (Some(m), Some(n), Some(k), Some(iterations)) = (m, n, k, iterations) else {
+        return Err(format!("{}: bad sizes or iterations", FIRST_CALL_CHILD));
+    };
+    let start = Start::ALL
+        .into_iter()
+        .find(|s| s.key() == start)
+        .ok_or_else(|| format!("{}: unknown start '{}'", FIRST_CALL_CHILD, start))?;
+    let seed = seed
+        .parse()
+        .map_err(|_| format!("{}: '{}' is not a seed", FIRST_CALL_CHILD, seed))?;
+
+    let (a, b) = inputs(Shape::new(m, n, k), seed);
+    let elapsed_ms = |f: &mut dyn FnMut()| {
+        let started = Instant::now();
+        f();
+        started.elapsed().as_secs_f64() * 1000.0
+    };
+    let warmup_ms = elapsed_ms(&mut || match start {
+        Start::Cold => {}
+        Start::Warmup => {
+            matmul::warmup();
+        }
+        Start::WarmupFor => {
+            matmul::warmup_for(m, n, k);
+        }
+    });
+    let mut c = vec![0.0; m * n];
+    let first_ms = elapsed_ms(&mut || matmul::multiply(&a, &b, &mut c, m, n, k));
+    let second_ms = elapsed_ms(&mut || matmul::multiply(&a, &b, &mut c, m, n, k));
+    let timing = Timing {
+        iterations,
+        min_time: 0.0,
+    };
+    let steady_ms = time_per_call(timing, || matmul::multiply(&a, &b, &mut c, m, n, k)) * 1000.0;
+    println!("{} {} {} {}", warmup_ms, first_ms, second_ms, steady_ms);
+    Ok(())
+}
+
+type Primitive<'a> = (&'static str, usize, Box<dyn FnMut() + 'a>);
+
+/// Every primitive this CPU runs: name, bytes moved per call, and the call.
+fn primitives<'a>(a: &'a [f64], b: &'a [f64], bt: &'a [f64], shape: Shape) -> Vec<Primitive<'a>> {
+    let Shape { m, n, k } = shape;
+    let transpose_bytes = 2 * 8 * k * n;
+    let transposed = move |f: fn(&[f64], &mut [f64], usize, usize)| -> Box<dyn FnMut() + 'a> {
+        let mut dst = vec![0.0; k * n];
+        Box::new(move || f(b, &mut dst, k, n))
+    };
+    #[allow(unused_mut)]
+    let mut list: Vec<Primitive> = vec![
+        (
+            "transpose naive",
+            transpose_bytes,
+            transposed(transpose_naive),
+        ),
+        (
+            "transpose blocked",
+            transpose_bytes,
+            transposed(transpose_scalar),
+        ),
+        (
+            "transpose (dispatch)",
+            transpose_bytes,
+            transposed(transpose),
+        ),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    {
+        if matmul::features::has_avx2() {
+            list.push((
+                "transpose AVX 4×4",
+                transpose_bytes,
+                transposed(|s, d, r, c| unsafe { transpose_avx(s, d, r, c) }),
+            ));
+        }
+        if matmul::features::has_avx512() {
+            list.push((
+                "transpose AVX-512 8×8",
+                transpose_bytes,
+                transposed(|s, d, r, c| unsafe { transpose_avx512(s, d, r, c) }),
+            ));
+        }
+    }
+    list.extend([
+        pack_a_sweep::<4>("pack_a_panel 4-row", a, m, k),
+        pack_a_sweep::<8>("pack_a_panel 8-row", a, m, k),
+        pack_a_sweep::<12>("pack_a_panel 12-row", a, m, k),
+        pack_b_sweep::<4>("pack_b_panel 4-col", bt, n, k),
+        pack_b_sweep::<8>("pack_b_panel 8-col", bt, n, k),
+    ]);
+    list
+}
+
+/// Depth and height of the swept blocks: the drivers' KC, and an MC that
+/// every tile height divides
+const PACK_KC: usize = 256;
+const PACK_MC: usize = 120;
+
+/// Packs every whole `MR`-row group of A once, in kc × mc blocks.
+fn pack_a_sweep<'a, const MR: usize>(
+    name: &'static str,
+    a: &'a [f64],
+    m: usize,
+    k: usize,
+) -> Primitive<'a> {
+    let m_main = m / MR * MR;
+    let kc = k.min(PACK_KC);
+    let mut panel = vec![0.0; PACK_MC.min(m_main) * kc];
+    let sweep = move || {
+        for kk in (0..k).step_by(kc) {
+            let k_block = (kk + kc).min(k) - kk;
+            for ii in (0..m_main).step_by(PACK_MC) {
+                let m_block = (ii + PACK_MC).min(m_main) - ii;
+                pack_a_panel::<MR>(a, &mut panel, ii, kk, m_block, k_block, k);
+            }
+        }
+    };
+    (name, 2 * 8 * m_main * k, Box::new(sweep))
+}
+
+/// Packs every whole `NR`-column group of Bᵀ once, per kc block.
+fn pack_b_sweep<'a, const NR: usize>(
+    name: &'static str,
+    bt: &'a [f64],
+    n: usize,
+    k: usize,
+) -> Primitive<'a> {
+    let n_main = n / NR * NR;
+    let kc = k.min(PACK_KC);
+    let mut panel = vec![0.0; NR * kc];
+    let sweep = move || {
+        for kk in (0..k).step_by(kc) {
+            let k_block = (kk + kc).min(k) - kk;
+            for j in (0..n_main).step_by(NR) {
+                pack_b_panel::<NR>(bt, &mut panel, j, kk, k_block, k);
+            }
+        }
+    };
+    (name, 2 * 8 * n_main * k, Box::new(sweep))
+}
+
+/// One method's timing at one size.
+struct Timed {
+    key: &'static str,
+    name: &'static str,
+    time_ms: f64,
+    gflops: f64,
+    /// With `--verify`
+    verified: Option<Verified>,
+    perf: Option<perf::PerfCounts>,
+    memory: memory::PeakMemory,
+    checksum: Checksum,
+}
+
+/// How one method's output compared with the reference.
+struct Verified {
+    max_abs_err: f64,
+    /// The first element outside the tolerance
+    mismatch: Option<Mismatch>,
+}
+
+/// `--verify` tolerance, the same one the test suite uses
+const VERIFY_RTOL: f64 = 1e-10;
+const VERIFY_ATOL: f64 = 1e-10;
+
+/// The README's "% of OpenBLAS" number: the fastest single-threaded row
+/// against the BLAS row. Rows after BLAS are other libraries and not ranked.
+#[cfg(feature = "blas-bench")]
+fn blas_ratio(results: &[Timed]) -> Option<String> {
+    let blas = results.iter().position(|row| row.name == BLAS_ROW)?;
+    let best = results[..blas]
+        .iter()
+        .filter(|row| !row.name.ends_with(" MT"))
+        .max_by(|x, y| x.gflops.total_cmp(&y.gflops))?;
+    Some(format!(
+        "   Best single-thread ({}) is {:.0}% of OpenBLAS",
+        best.name,
+        100.0 * best.gflops / results[blas].gflops
+    ))
+}
+
+/// `--format csv`: one line per method and shape, speedup against the
+/// `baseline`th method at that shape.
+fn print_csv(all_results: &[(Shape, Vec<Timed>)], verify: Verify, baseline: usize) {
+    let verifying = verify != Verify::Off;
+    print!("method,m,n,k,time_ms,gflops,speedup,heap_bytes,rss_bytes,sum,bits");
+    println!(
+        "{}",
+        if verifying {
+            ",max_abs_err,verified"
+        } else {
+            ""
+        }
+    );
+    for (shape, results) in all_results {
+        for row in results {
+            print!(
+                "{},{},{},{},{:.6},{:.4},{:.4},{},{},{:e},{:016x}",
+                row.key,
+                shape.m,
+                shape.n,
+                shape.k,
+                row.time_ms,
+                row.gflops,
+                results[baseline].time_ms / row.time_ms,
+                row.memory.heap,
+                row.memory.rss.map_or(String::new(), |rss| rss.to_string()),
+                row.checksum.sum,
+                row.checksum.bits
+            );
+            match &row.verified {
+                Some(v) => println!(",{:e},{}", v.max_abs_err, v.mismatch.is_none()),
+                None => println!(),
+            }
+        }
+    }
+}
+
+/// `--format json`: the results plus what produced them (CPU features,
+/// threads, timing settings), for tracking numbers across runs and machines.
+fn print_json(
+    all_results: &[(Shape, Vec<Timed>)],
+    options: &Options,
+    baseline: usize,
+    has_avx2: bool,
+    has_avx512: bool,
+    machine: Option<&roofline::Machine>,
+) {
+    let rows: Vec<String> = all_results
+        .iter()
+        .flat_map(|(shape, results)| {
+            results.iter().map(move |row| {
+                format!(
+                    "    {{\"method\": \"{}\", \"name\": \"{}\", \"m\": {}, \"n\": {}, \"k\": {}, \"time_ms\": {}, \"gflops\": {}, \"speedup\": {}, \"max_abs_err\": {}, \"verified\": {}, \"heap_bytes\": {}, \"rss_bytes\": {}, \"checksum\": {{\"sum\": {}, \"bits\": \"{:016x}\"}}, \"perf\": {}}}",
+                    row.key,
+                    row.name,
+                    shape.m,
+                    shape.n,
+                    shape.k,
+                    json_number(row.time_ms),
+                    json_number(row.gflops),
+                    json_number(results[baseline].time_ms / row.time_ms),
+                    row.verified
+                        .as_ref()
+                        .map_or("null".to_string(), |v| json_number(v.max_abs_err)),
+                    row.verified
+                        .as_ref()
+                        .map_or("null", |v| if v.mismatch.is_none() { "true" } else { "false" }),
+                    row.memory.heap,
+                    row.memory.rss.map_or("null".to_string(), |rss| rss.to_string()),
+                    json_number(row.checksum.sum),
+                    row.checksum.bits,
+                    row.perf.as_ref().map_or("null".to_string(), |perf| perf.json())
+                )
+            })
+        })
+        .collect();
+
+    println!("{{");
+    println!(
+        "  \"cpu\": {{\"arch\": \"{}\", \"avx2\": {}, \"avx512\": {}}},",
+        std::env::consts::ARCH,
+        has_avx2,
+        has_avx512
+    );
+    match machine {
+        Some(machine) => println!(
+            "  \"peak\": {{\"ghz\": {}, \"ghz_source\": \"{}\", \"flops_per_cycle\": {}, \"cores\": {}, \"single_core_gflops\": {}, \"all_core_gflops\": {}, \"bandwidth_gbs\": {}}},",
+            json_number(machine.ghz),
+            machine.ghz_source,
+            machine.flops_per_cycle(),
+            machine.cores,
+            json_number(machine.single_core_gflops()),
+            json_number(machine.all_core_gflops()),
+            json_number(machine.bandwidth_gbs)
+        ),
+        None => println!("  \"peak\": null,"),
+    }
+    println!("  \"mode\": \"latency\",");
+    println!("  \"seed\": {},", options.seed);
+    match all_results.first() {
+        Some((_, results)) => println!("  \"baseline\": \"{}\",", results[baseline].key),
+        None => println!("  \"baseline\": null,"),
+    }
+    println!("  \"threads\": {},", options.threads);
+    println!("  \"iterations\": {},", options.timing.iterations);
+    println!(
+        "  \"min_time_s\": {},",
+        json_number(options.timing.min_time)
+    );
+    println!("  \"results\": [");
+    println!("{}", rows.join(",\n"));
+    println!("  ]");
+    println!("}}");
+}
+
+/// JSON has no NaN or infinity (a run too fast for the clock gives inf GFLOPS)
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// `--input-a FILE --input-b FILE [--output FILE]`: multiply two `.npy`
+/// matrices (e.g. the ones a Python run saved with `np.save`), report the
+/// timing, and optionally save C.
+fn multiply_files(options: &Options) -> Result<(), String> {
+    let (Some(path_a), Some(path_b)) = (&options.input_a, &options.input_b) else {
+        return Err("--input-a and --input-b are both required".to_string());
+    };
+
+    let load = |path: &String| read_npy(path).map_err(|e| format!("{}: {}", path, e));
+    let a = load(path_a)?;
+    let b = load(path_b)?;
+    if a.cols != b.rows {
+        return Err(format!(
+            "shapes don't multiply: A is {}x{}, B is {}x{}",
+            a.rows, a.cols, b.rows, b.cols
+        ));
+    }
+    let (m, n, k) = (a.rows, b.cols, a.cols);
+
+    println!("A: {}x{} ({})", m, k, path_a);
+    println!("B: {}x{} ({})", k, n, path_b);
+    let measured = bench_fn(
+        &a.data,
+        &b.data,
+        m,
+        n,
+        k,
+        options.timing,
+        &mut perf::Counters::open(),
+        matmul::multiply,
+    );
+    println!(
+        "multiply: {:.2} ms  {:.2} GFLOPS  {}",
+        measured.time_ms, measured.gflops, measured.checksum
+    );
+    if let Some(perf) = measured.perf {
+        println!("   {}", perf);
+    }
 
-    let has_avx2 = is_x86_feature_detected!("avx2");
-    let has_avx512 = is_x86_feature_detected!("avx512f");
+    if let Some(path) = &options.output {
+        let mut c = vec![0.0; m * n];
+        matmul::multiply(&a.data, &b.data, &mut c, m, n, k);
+        write_npy(path, &c, m, n).map_err(|e| format!("{}: {}", path, e))?;
+        println!("C: {}x{} written to {}", m, n, path);
+    }
+    Ok(())
+}
+
+/// Elementwise throughput. These stream memory once, so GB/s (bytes read plus
+/// written) is the number to compare against the machine's bandwidth.
+fn bench_elementwise() {
+    println!("=== Elementwise Throughput ===\n");
+
+    // 32 MB per buffer: well past the last-level cache
+    let len = 4 << 20;
+    let iterations = 10;
+    let other = vec![1.0; len];
+    let mut c = vec![1.0; len];
+
+    let mut run = |name: &str, bytes_per_elem: usize, f: &dyn Fn(&mut [f64])| {
+        f(&mut c);
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f(&mut c);
+        }
+        let secs = start.elapsed().as_secs_f64() / iterations as f64;
+        let gbps = (len * bytes_per_elem) as f64 / secs / 1e9;
+        println!("{:16} {:8.3} ms  {:6.2} GB/s", name, secs * 1000.0, gbps);
+    };
 
-    println!("CPU Features: AVX2={}, AVX-512={}\n", has_avx2, has_avx512);
+    run("scale", 16, &|c| scale(c, 0.5));
+    run("add_assign", 24, &|c| add_assign(c, &other));
+    run("axpy", 24, &|c| axpy(c, -0.5, &other));
+    println!();
+}
+
+/// Transpose throughput (the blocked GEMMs transpose B on every call).
+/// GB/s counts both the read of src and the write of dst.
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables, unused_mut))]
+fn bench_transpose(has_avx2: bool, has_avx512: bool) {
+    println!("=== Transpose Throughput ===\n");
 
-    for &size in &sizes {
-        println!("Matrix: {}×{}", size, size);
+    // Powers of two are the worst case for cache-set conflicts on the strided writes
+    let shapes = [(1024, 1024), (2048, 2048), (1000, 3000)];
+    let iterations = 10;
+
+    for &(rows, cols) in &shapes {
+        println!("Matrix: {}×{}", rows, cols);
         println!("{}", "-".repeat(50));
 
-        let (m, n, k) = (size, size, size);
-        let a: Vec<f64> = (0..m * k).map(|i| (i % 100) as f64).collect();
-        let b: Vec<f64> = (0..k * n).map(|i| (i % 100) as f64).collect();
+        let src: Vec<f64> = (0..rows * cols).map(|i| i as f64).collect();
 
-        let mut results: Vec<(&str, (f64, f64))> = vec![
+        let mut results: Vec<(&str, f64)> = vec![
             (
-                "Naive (i-j-k)",
-                bench_fn(&a, &b, m, n, k, iterations, matmul_naive_ijk),
+                "Naive",
+                bench_transpose_fn(&src, rows, cols, iterations, transpose_naive),
             ),
             (
-                "Scalar (i-k-j)",
-                bench_fn(&a, &b, m, n, k, iterations, matmul_naive_ikj),
+                "Blocked scalar",
+                bench_transpose_fn(&src, rows, cols, iterations, transpose_scalar),
             ),
         ];
-
+        #[cfg(target_arch = "x86_64")]
         if has_avx2 {
             results.push((
-                "4×4 AVX2",
-                bench_unsafe(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| unsafe {
-                    matmul_blocked_4x4(a, b, c, m, n, k, None, None)
-                }),
-            ));
-            results.push((
-                "4×4 AVX2 MT",
-                bench_fn(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| {
-                    matmul_blocked_4x4_mt(a, b, c, m, n, k, 4)
-                }),
-            ));
-            results.push((
-                "12×4 AVX2",
-                bench_unsafe(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| unsafe {
-                    matmul_blocked_12x4(a, b, c, m, n, k, None, None)
-                }),
-            ));
-            results.push((
-                "12×4 AVX2 MT",
-                bench_fn(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| {
-                    matmul_blocked_12x4_mt(a, b, c, m, n, k, 4)
+                "AVX 4×4",
+                bench_transpose_fn(&src, rows, cols, iterations, |s, d, r, c| unsafe {
+                    transpose_avx(s, d, r, c)
                 }),
             ));
         }
-
+        #[cfg(target_arch = "x86_64")]
         if has_avx512 {
             results.push((
-                "8×8 AVX-512",
-                bench_unsafe(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| unsafe {
-                    matmul_blocked_8x8(a, b, c, m, n, k, None, None)
-                }),
-            ));
-            results.push((
-                "8×8 AVX-512 MT",
-                bench_fn(&a, &b, m, n, k, iterations, |a, b, c, m, n, k| {
-                    matmul_blocked_8x8_mt(a, b, c, m, n, k, 4)
+                "AVX-512 8×8",
+                bench_transpose_fn(&src, rows, cols, iterations, |s, d, r, c| unsafe {
+                    transpose_avx512(s, d, r, c)
                 }),
             ));
         }
 
-        // Print results
-        let baseline_time = results[0].1.0;
-        for (i, (name, (time_ms, gflops))) in results.iter().enumerate() {
-            let speedup = baseline_time / time_ms;
+        let baseline_time = results[0].1;
+        for (i, (name, time_ms)) in results.iter().enumerate() {
+            let gbps = 2.0 * (rows * cols * 8) as f64 / (time_ms / 1000.0) / 1e9;
             println!(
-                "{}. {:16} {:8.2} ms  {:6.2} GFLOPS  ({:.1}×)",
+                "{}. {:16} {:8.3} ms  {:6.2} GB/s  ({:.1}×)",
                 i + 1,
                 name,
                 time_ms,
-                gflops,
-                speedup
+                gbps,
+                baseline_time / time_ms
             );
         }
         println!();
+    }
+}
+
+/// Average milliseconds per transpose, after one warmup run
+fn bench_transpose_fn<F>(src: &[f64], rows: usize, cols: usize, iterations: usize, f: F) -> f64
+where
+    F: Fn(&[f64], &mut [f64], usize, usize),
+{
+    let mut dst = vec![0.0; rows * cols];
+    let timing = Timing {
+        iterations,
+        min_time: 0.0,
+    };
+    time_per_call(timing, || f(src, &mut dst, rows, cols)) * 1000.0
+}
 
-        all_results.push((size, results));
+/// Average seconds per call of `f`: one warmup call, then at least
+/// `timing.iterations` timed calls, continuing until `timing.min_time`
+/// seconds have been spent in them.
+fn time_per_call(timing: Timing, mut f: impl FnMut()) -> f64 {
+    f();
+    let mut total = 0.0;
+    let mut runs = 0;
+    while runs < timing.iterations || total < timing.min_time {
+        let start = Instant::now();
+        f();
+        total += start.elapsed().as_secs_f64();
+        runs += 1;
     }
+    total / runs as f64
+}
 
-    print_summary_table(&all_results);
+/// A benchmarked method's numbers, per call.
+struct Measured {
+    time_ms: f64,
+    gflops: f64,
+    /// Hardware counters, when the `perf-events` feature could open them
+    perf: Option<perf::PerfCounts>,
+    /// Peak memory above what the caller already held (A, B, and any
+    /// pre-transposed B), so C and everything the method allocates
+    memory: memory::PeakMemory,
+    /// Of the warmup call's C
+    checksum: Checksum,
 }
 
-/// Benchmark a safe matmul function
+/// Benchmark a matmul function: average ms per call and GFLOPS, plus
+/// hardware counters over the same timed calls if `counters` is open.
+///
+/// One warmup call, then at least `timing.iterations` timed calls, continuing
+/// until `timing.min_time` seconds have been spent in them.
+#[allow(clippy::too_many_arguments)]
 fn bench_fn<F>(
     a: &[f64],
     b: &[f64],
     m: usize,
     n: usize,
     k: usize,
-    iterations: usize,
+    timing: Timing,
+    counters: &mut Option<perf::Counters>,
     f: F,
-) -> (f64, f64)
+) -> Measured
 where
     F: Fn(&[f64], &[f64], &mut [f64], usize, usize, usize),
 {
-    // Warmup
+    // Warmup, which also measures memory: one call's worth, C included, from
+    // an empty scratch cache so earlier methods' buffers don't hide it
+    matmul::clear_scratch_cache();
+    let mark = memory::mark();
     let mut c = vec![0.0; m * n];
     f(a, b, &mut c, m, n, k);
+    let memory = memory::peak_since(&mark);
+    let checksum = Checksum::of(&c);
+    drop(c);
 
-    // Timed runs
+    // Timed runs; counters only run while f does, like the clock
     let mut total = 0.0;
-    for _ in 0..iterations {
+    let mut runs = 0;
+    if let Some(counters) = counters {
+        counters.reset();
+    }
+    while runs < timing.iterations || total < timing.min_time {
         let mut c = vec![0.0; m * n];
+        if let Some(counters) = counters {
+            counters.enable();
+        }
         let start = Instant::now();
         f(a, b, &mut c, m, n, k);
         total += start.elapsed().as_secs_f64();
+        if let Some(counters) = counters {
+            counters.disable();
+        }
+        runs += 1;
     }
 
-    let avg = total / iterations as f64;
-    let gflops = 2.0 * (m * n * k) as f64 / avg / 1e9;
-    (avg * 1000.0, gflops)
+    let avg = total / runs as f64;
+    Measured {
+        time_ms: avg * 1000.0,
+        gflops: 2.0 * (m * n * k) as f64 / avg / 1e9,
+        perf: counters.as_mut().map(|counters| counters.read(runs)),
+        memory,
+        checksum,
+    }
 }
 
-/// Benchmark an unsafe matmul function (same logic, different type bounds)
-fn bench_unsafe<F>(
-    a: &[f64],
-    b: &[f64],
-    m: usize,
-    n: usize,
-    k: usize,
-    iterations: usize,
-    f: F,
-) -> (f64, f64)
-where
-    F: Fn(&[f64], &[f64], &mut [f64], usize, usize, usize),
-{
-    bench_fn(a, b, m, n, k, iterations, f)
-}
-#[allow(clippy::type_complexity)]
-fn print_summary_table(all_results: &[(usize, Vec<(&str, (f64, f64))>)]) {
-    println!("\n{}", "=".repeat(90));
+/// GFLOPS per method (rows) and shape (columns), plus the average speedup
+/// over the `baseline`th method.
+fn print_summary_table(all_results: &[(Shape, Vec<Timed>)], baseline: usize) {
+    let width = 18 + all_results.len() * 17 + 13;
+    println!("\n{}", "=".repeat(width));
     println!("SUMMARY");
-    println!("{}", "=".repeat(90));
+    println!("{}", "=".repeat(width));
 
-    println!(
-        "\n{:<18} {:>14} {:>14} {:>14} {:>12}",
-        "Method", "256×256", "512×512", "1024×1024", "Speedup"
-    );
-    println!("{}", "-".repeat(90));
+    print!("\n{:<18}", "Method");
+    for (shape, _) in all_results {
+        print!(" {:>16}", shape.to_string());
+    }
+    println!(" {:>12}", "Speedup");
+    println!("{}", "-".repeat(width));
 
     let num_methods = all_results[0].1.len();
+    let baseline_name = all_results[0].1[baseline].name;
 
     for method_idx in 0..num_methods {
-        let method_name = all_results[0].1[method_idx].0;
+        print!("{:<18}", all_results[0].1[method_idx].name);
 
-        let mut gflops_list = Vec::new();
         let mut speedups = Vec::new();
-
         for (_, results) in all_results {
-            let (time_ms, gflops) = results[method_idx].1;
-            let baseline_time = results[0].1.0;
-            gflops_list.push(gflops);
-            speedups.push(baseline_time / time_ms);
+            let row = &results[method_idx];
+            print!(" {:>13.2} GF", row.gflops);
+            speedups.push(results[baseline].time_ms / row.time_ms);
         }
 
         let avg_speedup: f64 = speedups.iter().sum::<f64>() / speedups.len() as f64;
-
-        println!(
-            "{:<18} {:>10.2} GF  {:>10.2} GF  {:>10.2} GF  {:>10.1}×",
-            method_name, gflops_list[0], gflops_list[1], gflops_list[2], avg_speedup
-        );
+        println!(" {:>11.1}×", avg_speedup);
     }
 
-    println!("{}", "=".repeat(90));
+    println!("{}", "=".repeat(width));
     println!("\nGF = GFLOPS (billion floating point operations per second)");
-    println!("Speedup relative to Naive (i-j-k). Higher is better.\n");
+    println!("Speedup relative to {}. Higher is better.\n", baseline_name);
+}
+
+/// Peak memory per method (rows) and shape (columns): heap from the counting
+/// allocator, and RSS where Linux reports it.
+fn print_memory_table(all_results: &[(Shape, Vec<Timed>)]) {
+    let width = 18 + all_results.len() * 25;
+    println!("PEAK MEMORY (above A and B; heap / RSS)");
+    println!("{}", "=".repeat(width));
+    print!("{:<18}", "Method");
+    for (shape, _) in all_results {
+        print!(" {:>24}", shape.to_string());
+    }
+    println!();
+    println!("{}", "-".repeat(width));
+
+    for method_idx in 0..all_results[0].1.len() {
+        print!("{:<18}", all_results[0].1[method_idx].name);
+        for (_, results) in all_results {
+            let memory = results[method_idx].memory;
+            let rss = memory.rss.map_or("n/a".to_string(), memory::format_bytes);
+            let cell = format!("{} / {}", memory::format_bytes(memory.heap as u64), rss);
+            print!(" {:>24}", cell);
+        }
+        println!();
+    }
+    println!("{}", "=".repeat(width));
+    println!("\nIncludes C (8·m·n bytes); the rest is what the method allocates itself.\n");
+}
+
+/// Hardware counters around the timed runs (`perf-events` feature, Linux).
+#[cfg(all(feature = "perf-events", target_os = "linux"))]
+mod perf {
+    use perf_event::events::{Cache, CacheOp, CacheResult, Event, Hardware, WhichCache};
+    use perf_event::{Builder, Counter};
+    use std::fmt;
+
+    /// Per-call averages; `None` for counters this machine doesn't have.
+    #[derive(Clone, Copy)]
+    pub struct PerfCounts {
+        cycles: Option<f64>,
+        instructions: Option<f64>,
+        l1d_misses: Option<f64>,
+        llc_misses: Option<f64>,
+        dtlb_misses: Option<f64>,
+    }
+
+    /// Open counters, in `PerfCounts` field order.
+    pub struct Counters([Option<Counter>; 5]);
+
+    const fn read_misses(which: WhichCache) -> Event {
+        Event::Cache(Cache {
+            which,
+            operation: CacheOp::READ,
+            result: CacheResult::MISS,
+        })
+    }
+
+    const EVENTS: [Event; 5] = [
+        Event::Hardware(Hardware::CPU_CYCLES),
+        Event::Hardware(Hardware::INSTRUCTIONS),
+        read_misses(WhichCache::L1D),
+        read_misses(WhichCache::LL),
+        read_misses(WhichCache::DTLB),
+    ];
+
+    impl Counters {
+        /// Opens whichever counters the kernel allows. Containers and
+        /// `perf_event_paranoid` often allow none; then this says so on
+        /// stderr and the benchmark runs without them.
+        pub fn open() -> Option<Counters> {
+            let mut error = None;
+            let counters = EVENTS.map(|event| {
+                let mut builder = Builder::new().kind(event);
+                // Separate counters rather than a group, so each one can
+                // inherit into the MT methods' worker threads
+                builder.inherit(true);
+                builder.build().map_err(|e| error = Some(e)).ok()
+            });
+            if counters.iter().all(Option::is_none) {
+                if let Some(e) = error {
+                    eprintln!("note: perf counters unavailable ({}); timing only", e);
+                }
+                return None;
+            }
+            Some(Counters(counters))
+        }
+
+        pub fn reset(&mut self) {
+            self.each(Counter::reset);
+        }
+
+        pub fn enable(&mut self) {
+            self.each(Counter::enable);
+        }
+
+        pub fn disable(&mut self) {
+            self.each(Counter::disable);
+        }
+
+        /// Averages since the last `reset` over `runs` calls.
+        pub fn read(&mut self, runs: usize) -> PerfCounts {
+            let [cycles, instructions, l1d_misses, llc_misses, dtlb_misses] =
+                self.0.each_mut().map(|counter| {
+                    let count = counter.as_mut()?.read().ok()?;
+                    Some(count as f64 / runs as f64)
+                });
+            PerfCounts {
+                cycles,
+                instructions,
+                l1d_misses,
+                llc_misses,
+                dtlb_misses,
+            }
+        }
+
+        fn each(&mut self, f: fn(&mut Counter) -> std::io::Result<()>) {
+            for counter in self.0.iter_mut().flatten() {
+                // A counter that fails to toggle just under-counts; not
+                // worth aborting the benchmark over
+                let _ = f(counter);
+            }
+        }
+    }
+
+    impl PerfCounts {
+        fn fields(&self) -> [(&'static str, Option<f64>); 5] {
+            [
+                ("cycles", self.cycles),
+                ("instructions", self.instructions),
+                ("l1d_misses", self.l1d_misses),
+                ("llc_misses", self.llc_misses),
+                ("dtlb_misses", self.dtlb_misses),
+            ]
+        }
+
+        pub fn json(&self) -> String {
+            let fields: Vec<String> = self
+                .fields()
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.map_or("null".to_string(), super::json_number);
+                    format!("\"{}\": {}", name, value)
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+
+    impl fmt::Display for PerfCounts {
+        /// One line per call: cycles, IPC, and the misses that point at
+        /// memory-bound (L1d, LLC) or TLB-bound loops.
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let show = |x: Option<f64>| x.map_or("n/a".to_string(), si);
+            write!(f, "{} cycles", show(self.cycles))?;
+            if let (Some(cycles), Some(instructions)) = (self.cycles, self.instructions) {
+                write!(f, ", IPC {:.2}", instructions / cycles)?;
+            }
+            write!(
+                f,
+                ", L1d miss {}, LLC miss {}, dTLB miss {}",
+                show(self.l1d_misses),
+                show(self.llc_misses),
+                show(self.dtlb_misses)
+            )
+        }
+    }
+
+    /// 1234567.0 -> "1.23M"
+    fn si(x: f64) -> String {
+        match x {
+            x if x >= 1e9 => format!("{:.2}G", x / 1e9),
+            x if x >= 1e6 => format!("{:.2}M", x / 1e6),
+            x if x >= 1e3 => format!("{:.2}k", x / 1e3),
+            x => format!("{:.0}", x),
+        }
+    }
+}
+
+/// Without the `perf-events` feature (or off Linux) there are no counters.
+#[cfg(not(all(feature = "perf-events", target_os = "linux")))]
+mod perf {
+    pub enum PerfCounts {}
+    pub enum Counters {}
+
+    impl Counters {
+        pub fn open() -> Option<Counters> {
+            None
+        }
+
+        pub fn reset(&mut self) {}
+
+        pub fn enable(&mut self) {}
+
+        pub fn disable(&mut self) {}
+
+        pub fn read(&mut self, _runs: usize) -> PerfCounts {
+            match *self {}
+        }
+    }
+
+    impl PerfCounts {
+        pub fn json(&self) -> String {
+            match *self {}
+        }
+    }
+
+    impl std::fmt::Display for PerfCounts {
+        fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match *self {}
+        }
+    }
+}
+
+/// Machine peak and memory ceiling estimates, to read GFLOPS against.
+mod roofline {
+    use std::time::Instant;
+
+    /// What the peak estimate is built from.
+    pub struct Machine {
+        /// Measured (or sysfs) clock, GHz
+        pub ghz: f64,
+        /// Where `ghz` came from
+        pub ghz_source: &'static str,
+        /// FP64 lanes per FMA instruction for the widest kernel this CPU runs
+        pub lanes: usize,
+        pub isa: &'static str,
+        pub cores: usize,
+        /// Copy bandwidth (read + write), GB/s
+        pub bandwidth_gbs: f64,
+    }
+
+    /// Assumed FMA units per core. Server and desktop cores since Haswell
+    /// have 2; some AVX-512 parts (e.g. Xeon Bronze/Silver) have only 1 for
+    /// 512-bit FMAs, which halves the real peak.
+    pub const FMA_UNITS: usize = 2;
+
+    impl Machine {
+        /// Measures the clock and bandwidth (about half a second). `None` if
+        /// the clock can't be determined on this target.
+        pub fn estimate(has_avx2: bool, has_avx512: bool) -> Option<Machine> {
+            let (ghz, ghz_source) = measure_ghz()?;
+            let (lanes, isa) = if has_avx512 {
+                (8, "AVX-512")
+            } else if has_avx2 {
+                (4, "AVX2")
+            } else {
+                (1, "scalar")
+            };
+            Some(Machine {
+                ghz,
+                ghz_source,
+                lanes,
+                isa,
+                cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+                bandwidth_gbs: measure_bandwidth(),
+            })
+        }
+
+        /// FLOPs per cycle per core: lanes × FMA units × 2 (an FMA is a
+        /// multiply and an add).
+        pub fn flops_per_cycle(&self) -> usize {
+            self.lanes * FMA_UNITS * 2
+        }
+
+        pub fn single_core_gflops(&self) -> f64 {
+            self.ghz * self.flops_per_cycle() as f64
+        }
+
+        /// Assumes every core holds the measured clock, which turbo usually
+        /// doesn't under all-core AVX load, so this is an upper bound.
+        pub fn all_core_gflops(&self) -> f64 {
+            self.single_core_gflops() * self.cores as f64
+        }
+
+        /// The bandwidth roof for an m×n×k multiply: arithmetic intensity
+        /// (FLOPs per byte of compulsory traffic: read A and B, read and
+        /// write C once) times the measured bandwidth. Cache reuse beyond
+        /// that is what blocking buys, so kernels can only approach this.
+        pub fn memory_ceiling_gflops(&self, m: usize, n: usize, k: usize) -> f64 {
+            intensity(m, n, k) * self.bandwidth_gbs
+        }
+    }
+
+    /// FLOPs per byte of compulsory traffic for an m×n×k multiply.
+    pub fn intensity(m: usize, n: usize, k: usize) -> f64 {
+        let flops = 2.0 * (m * n * k) as f64;
+        let bytes = 8.0 * (m * k + k * n + 2 * m * n) as f64;
+        flops / bytes
+    }
+
+    /// Clock from a chain of dependent register-register adds (one per
+    /// cycle on every x86 core since the P6; add-immediate chains can be
+    /// folded by newer renamers), best of a few runs so turbo has ramped up.
+    #[cfg(target_arch = "x86_64")]
+    fn measure_ghz() -> Option<(f64, &'static str)> {
+        const ITERATIONS: u64 = 20_000_000;
+        const ADDS_PER_ITERATION: u64 = 8;
+
+        let best = (0..3)
+            .map(|_| {
+                let mut x: u64 = 0;
+                let count = ITERATIONS;
+                let start = Instant::now();
+                // The loop counter runs on another port, so the add chain
+                // is the critical path: 8 cycles per iteration
+                unsafe {
+                    std::arch::asm!(
+                        "2:",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "add {x}, {x}",
+                        "dec {count}",
+                        "jnz 2b",
+                        x = inout(reg) x,
+                        count = inout(reg) count => _,
+                        options(nomem, nostack),
+                    );
+                }
+                std::hint::black_box(x);
+                start.elapsed().as_secs_f64()
+            })
+            .fold(f64::INFINITY, f64::min);
+        Some((
+            (ITERATIONS * ADDS_PER_ITERATION) as f64 / best / 1e9,
+            "spin loop",
+        ))
+    }
+
+    /// Elsewhere, the advertised maximum from sysfs, if the kernel has one.
+    #[cfg(not(target_arch = "x86_64"))]
+    fn measure_ghz() -> Option<(f64, &'static str)> {
+        let path = "/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq";
+        let khz: f64 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+        Some((khz / 1e6, "sysfs cpuinfo_max_freq"))
+    }
+
+    /// Single-threaded copy bandwidth over buffers larger than most LLCs,
+    /// best of three, counting the read and the write.
+    fn measure_bandwidth() -> f64 {
+        const LEN: usize = 8 << 20; // 64 MiB per buffer
+        let src = vec![1.0f64; LEN];
+        let mut dst = vec![0.0f64; LEN];
+        let best = (0..3)
+            .map(|_| {
+                let start = Instant::now();
+                dst.copy_from_slice(std::hint::black_box(&src));
+                std::hint::black_box(&mut dst);
+                start.elapsed().as_secs_f64()
+            })
+            .fold(f64::INFINITY, f64::min);
+        2.0 * (LEN * 8) as f64 / best / 1e9
+    }
+}
+
+/// Peak memory of each benchmarked call: heap bytes from a counting global
+/// allocator (any OS), and resident memory from `/proc/self/status` on Linux,
+/// which also sees stacks and anything allocated outside Rust.
+mod memory {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The system allocator, plus live and peak byte counts.
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new.is_null() {
+                CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+                grow(new_size);
+            }
+            new
+        }
+    }
+
+    fn grow(size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// Allocations (reallocations included) since the program started.
+    pub fn allocations() -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    /// Where a measurement started.
+    pub struct Mark {
+        heap: usize,
+        rss: Option<u64>,
+    }
+
+    /// Peak bytes above a [`Mark`].
+    #[derive(Clone, Copy)]
+    pub struct PeakMemory {
+        pub heap: usize,
+        /// Linux only
+        pub rss: Option<u64>,
+    }
+
+    /// Resets both high-water marks to the current usage.
+    pub fn mark() -> Mark {
+        let heap = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(heap, Ordering::Relaxed);
+        Mark {
+            heap,
+            rss: reset_rss_peak(),
+        }
+    }
+
+    /// How far usage rose above `mark` since it was taken.
+    pub fn peak_since(mark: &Mark) -> PeakMemory {
+        PeakMemory {
+            heap: PEAK.load(Ordering::Relaxed).saturating_sub(mark.heap),
+            rss: mark
+                .rss
+                .zip(status_bytes("VmHWM:"))
+                .map(|(start, peak)| peak.saturating_sub(start)),
+        }
+    }
+
+    /// Resets VmHWM to the current RSS (writing 5 to clear_refs, Linux 4.0+)
+    /// and returns that RSS; `None` if either step isn't possible.
+    #[cfg(target_os = "linux")]
+    fn reset_rss_peak() -> Option<u64> {
+        std::fs::write("/proc/self/clear_refs", "5").ok()?;
+        status_bytes("VmRSS:")
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn reset_rss_peak() -> Option<u64> {
+        None
+    }
+
+    /// A `kB` line from /proc/self/status, in bytes.
+    fn status_bytes(key: &str) -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with(key))?;
+        let kb: u64 = line[key.len()..]
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+
+    /// 1572864 -> "1.50 MiB"
+    pub fn format_bytes(bytes: u64) -> String {
+        match bytes {
+            b if b >= 1 << 30 => format!("{:.2} GiB", b as f64 / (1u64 << 30) as f64),
+            b if b >= 1 << 20 => format!("{:.2} MiB", b as f64 / (1u64 << 20) as f64),
+            b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / 1024.0),
+            b => format!("{} B", b),
+        }
+    }
 }
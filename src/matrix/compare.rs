@@ -0,0 +1,232 @@
+//! Comparing matrices: error norms and tolerance checks.
+//!
+//! Two GEMMs that sum in different orders agree only up to rounding, and the
+//! rounding grows with k and with the magnitude of C. A fixed absolute
+//! threshold is either too loose for small results or too tight for large
+//! ones, so [`assert_close`] uses the usual mixed test
+//! `|actual - expected| <= atol + rtol * |expected|`.
+//!
+//! NaN never compares close to anything, including another NaN, so a NaN
+//! anywhere in either matrix fails the check.
+
+use std::fmt;
+
+/// Largest `|expected[i] - actual[i]|`, or NaN if any difference is NaN.
+///
+/// # Panics
+///
+/// Panics if the slices differ in length.
+pub fn max_abs_diff(expected: &[f64], actual: &[f64]) -> f64 {
+    assert_eq!(expected.len(), actual.len(), "length mismatch");
+    expected
+        .iter()
+        .zip(actual)
+        .map(|(&e, &a)| (e - a).abs())
+        .fold(0.0, nan_max)
+}
+
+/// Largest `|expected[i] - actual[i]| / |expected[i]|`, or NaN if any
+/// difference is NaN.
+///
+/// Elements where both sides are exactly equal count as 0, so zeros in
+/// `expected` only matter when `actual` differs there (giving infinity).
+///
+/// # Panics
+///
+/// Panics if the slices differ in length.
+pub fn max_rel_diff(expected: &[f64], actual: &[f64]) -> f64 {
+    assert_eq!(expected.len(), actual.len(), "length mismatch");
+    expected
+        .iter()
+        .zip(actual)
+        .map(
+            |(&e, &a)| {
+                if e == a { 0.0 } else { (e - a).abs() / e.abs() }
+            },
+        )
+        .fold(0.0, nan_max)
+}
+
+/// Frobenius norm: the square root of the sum of squares of all elements.
+///
+/// Useful for normwise error bounds, e.g.
+/// `frobenius_norm(&residual) <= tol * frobenius_norm(&a) * frobenius_norm(&b)`.
+pub fn frobenius_norm(a: &[f64]) -> f64 {
+    a.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// The first element that failed [`check_close`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// Flat index into the matrices
+    pub index: usize,
+    pub expected: f64,
+    pub actual: f64,
+    /// `atol + rtol * |expected|` at this element
+    pub allowed: f64,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected.is_nan() || self.actual.is_nan() {
+            write!(
+                f,
+                "NaN at index {}: expected {}, got {}",
+                self.index, self.expected, self.actual
+            )
+        } else {
+            write!(
+                f,
+                "mismatch at index {}: expected {}, got {} (|diff| {:e} > allowed {:e})",
+                self.index,
+                self.expected,
+                self.actual,
+                (self.expected - self.actual).abs(),
+                self.allowed
+            )
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Checks `|actual[i] - expected[i]| <= atol + rtol * |expected[i]|` for every
+/// element and returns the first one that fails.
+///
+/// Equal values always pass, so matching infinities are fine; an infinity
+/// never matches anything else. NaN on either side always fails.
+///
+/// # Panics
+///
+/// Panics if the slices differ in length.
+pub fn check_close(expected: &[f64], actual: &[f64], rtol: f64, atol: f64) -> Result<(), Mismatch> {
+    assert_eq!(
+        expected.len(),
+        actual.len(),
+        "length mismatch: expected {} elements, got {}",
+        expected.len(),
+        actual.len()
+    );
+
+    for (index, (&e, &a)) in expected.iter().zip(actual).enumerate() {
+        let allowed = atol + rtol * e.abs();
+        // Written so that NaN anywhere lands in the failure branch. An infinite
+        // `expected` would allow any `actual`, so it has to match exactly.
+        if !(e == a || (e.is_finite() && (e - a).abs() <= allowed)) {
+            return Err(Mismatch {
+                index,
+                expected: e,
+                actual: a,
+                allowed,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Asserts that two matrices are elementwise close; see [`check_close`].
+///
+/// # Panics
+///
+/// Panics with the index and values of the first mismatch, or if the slices
+/// differ in length.
+///
+/// # Example
+///
+/// ```
+/// use matmul::matrix::compare::assert_close;
+///
+/// assert_close(&[1.0, 1e6], &[1.0 + 1e-13, 1e6 + 1e-7], 1e-12, 1e-12);
+/// ```
+#[track_caller]
+pub fn assert_close(expected: &[f64], actual: &[f64], rtol: f64, atol: f64) {
+    if let Err(mismatch) = check_close(expected, actual, rtol, atol) {
+        panic!("{}", mismatch);
+    }
+}
+
+/// `f64::max` ignores NaN; this propagates it.
+fn nan_max(acc: f64, x: f64) -> f64 {
+    if acc.is_nan() || x.is_nan() {
+        f64::NAN
+    } else {
+        acc.max(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diffs_and_norm() {
+        let expected = [1.0, -2.0, 4.0, 0.0];
+        let actual = [1.5, -2.0, 3.0, 0.0];
+
+        assert_eq!(max_abs_diff(&expected, &actual), 1.0);
+        assert_eq!(max_rel_diff(&expected, &actual), 0.5);
+        assert_eq!(frobenius_norm(&[3.0, 4.0]), 5.0);
+        assert_eq!(frobenius_norm(&[]), 0.0);
+
+        // A zero that stays zero is fine; one that moves is infinitely far off
+        assert_eq!(max_rel_diff(&[0.0], &[0.0]), 0.0);
+        assert_eq!(max_rel_diff(&[0.0], &[1e-300]), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_tolerance_scales_with_magnitude() {
+        // 1e-7 off 1e6 is within rtol = 1e-12, but 1e-7 off 1.0 is not
+        assert!(check_close(&[1e6], &[1e6 + 1e-7], 1e-12, 0.0).is_ok());
+        assert!(check_close(&[1.0], &[1.0 + 1e-7], 1e-12, 0.0).is_err());
+
+        // atol covers results that should be (near) zero
+        assert!(check_close(&[0.0], &[1e-13], 1e-12, 1e-12).is_ok());
+        assert!(check_close(&[0.0], &[1e-11], 1e-12, 1e-12).is_err());
+
+        // Boundary is inclusive
+        assert!(check_close(&[1.0], &[1.5], 0.0, 0.5).is_ok());
+    }
+
+    #[test]
+    fn test_reports_first_mismatch() {
+        let err = check_close(&[1.0, 2.0, 3.0, 4.0], &[1.0, 2.5, 3.5, 4.0], 0.0, 0.1).unwrap_err();
+        assert_eq!(err.index, 1);
+        assert_eq!(err.expected, 2.0);
+        assert_eq!(err.actual, 2.5);
+        assert!(
+            err.to_string()
+                .starts_with("mismatch at index 1: expected 2, got 2.5")
+        );
+    }
+
+    #[test]
+    fn test_nan_always_fails() {
+        for (expected, actual) in [(f64::NAN, 1.0), (1.0, f64::NAN), (f64::NAN, f64::NAN)] {
+            let err = check_close(&[0.0, expected], &[0.0, actual], 1.0, 1.0).unwrap_err();
+            assert_eq!(err.index, 1);
+            assert!(err.to_string().starts_with("NaN at index 1"), "{}", err);
+        }
+
+        assert!(max_abs_diff(&[1.0, f64::NAN, 1.0], &[1.0, 1.0, 5.0]).is_nan());
+        assert!(max_rel_diff(&[f64::NAN], &[f64::NAN]).is_nan());
+    }
+
+    #[test]
+    fn test_infinities() {
+        assert!(check_close(&[f64::INFINITY], &[f64::INFINITY], 0.0, 0.0).is_ok());
+        assert!(check_close(&[f64::INFINITY], &[f64::NEG_INFINITY], 1.0, 1.0).is_err());
+        assert!(check_close(&[f64::INFINITY], &[1e308], 1.0, 1.0).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN at index 2")]
+    fn test_assert_close_panics_on_nan() {
+        assert_close(&[1.0, 2.0, 3.0], &[1.0, 2.0, f64::NAN], 1e-12, 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "length mismatch")]
+    fn test_length_mismatch_panics() {
+        assert_close(&[1.0, 2.0], &[1.0], 1e-12, 1e-12);
+    }
+}
@@ -0,0 +1,197 @@
+//! Elementwise operations over flat matrix buffers.
+//!
+//! These are the pieces around a multiply: scaling C by beta, summing partial
+//! products, and `C += alpha * X` updates. Each one streams through memory once,
+//! so the SIMD versions exist to keep up with memory bandwidth, not to save
+//! arithmetic. Shapes don't matter here; any two buffers of the same length
+//! work.
+
+/// c = alpha * c
+pub fn scale(c: &mut [f64], alpha: f64) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            unsafe { scale_avx512(c, alpha) };
+            return;
+        }
+        if is_x86_feature_detected!("avx2") {
+            unsafe { scale_avx2(c, alpha) };
+            return;
+        }
+    }
+
+    scale_scalar(c, alpha);
+}
+
+/// c += other
+///
+/// # Panics
+///
+/// Panics if `c` and `other` differ in length.
+pub fn add_assign(c: &mut [f64], other: &[f64]) {
+    assert_eq!(c.len(), other.len(), "add_assign: length mismatch");
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            unsafe { add_assign_avx512(c, other) };
+            return;
+        }
+        if is_x86_feature_detected!("avx2") {
+            unsafe { add_assign_avx2(c, other) };
+            return;
+        }
+    }
+
+    add_assign_scalar(c, other);
+}
+
+/// c += alpha * other
+///
+/// The SIMD versions use fused multiply-add, so results can differ from the
+/// scalar fallback in the last bit.
+///
+/// # Panics
+///
+/// Panics if `c` and `other` differ in length.
+pub fn axpy(c: &mut [f64], alpha: f64, other: &[f64]) {
+    assert_eq!(c.len(), other.len(), "axpy: length mismatch");
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            unsafe { axpy_avx512(c, alpha, other) };
+            return;
+        }
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            unsafe { axpy_avx2(c, alpha, other) };
+            return;
+        }
+    }
+
+    axpy_scalar(c, alpha, other);
+}
+
+fn scale_scalar(c: &mut [f64], alpha: f64) {
+    for x in c {
+        *x *= alpha;
+    }
+}
+
+fn add_assign_scalar(c: &mut [f64], other: &[f64]) {
+    for (x, &y) in c.iter_mut().zip(other) {
+        *x += y;
+    }
+}
+
+fn axpy_scalar(c: &mut [f64], alpha: f64, other: &[f64]) {
+    for (x, &y) in c.iter_mut().zip(other) {
+        *x += alpha * y;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn scale_avx2(c: &mut [f64], alpha: f64) {
+    use std::arch::x86_64::*;
+
+    let va = _mm256_set1_pd(alpha);
+    let mut chunks = c.chunks_exact_mut(4);
+    for chunk in &mut chunks {
+        unsafe {
+            let v = _mm256_loadu_pd(chunk.as_ptr());
+            _mm256_storeu_pd(chunk.as_mut_ptr(), _mm256_mul_pd(v, va));
+        }
+    }
+    scale_scalar(chunks.into_remainder(), alpha);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn scale_avx512(c: &mut [f64], alpha: f64) {
+    use std::arch::x86_64::*;
+
+    let va = _mm512_set1_pd(alpha);
+    let mut chunks = c.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        unsafe {
+            let v = _mm512_loadu_pd(chunk.as_ptr());
+            _mm512_storeu_pd(chunk.as_mut_ptr(), _mm512_mul_pd(v, va));
+        }
+    }
+    scale_scalar(chunks.into_remainder(), alpha);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn add_assign_avx2(c: &mut [f64], other: &[f64]) {
+    use std::arch::x86_64::*;
+
+    let mut chunks = c.chunks_exact_mut(4);
+    let mut other_chunks = other.chunks_exact(4);
+    for (chunk, o) in (&mut chunks).zip(&mut other_chunks) {
+        unsafe {
+            let v = _mm256_add_pd(_mm256_loadu_pd(chunk.as_ptr()), _mm256_loadu_pd(o.as_ptr()));
+            _mm256_storeu_pd(chunk.as_mut_ptr(), v);
+        }
+    }
+    add_assign_scalar(chunks.into_remainder(), other_chunks.remainder());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn add_assign_avx512(c: &mut [f64], other: &[f64]) {
+    use std::arch::x86_64::*;
+
+    let mut chunks = c.chunks_exact_mut(8);
+    let mut other_chunks = other.chunks_exact(8);
+    for (chunk, o) in (&mut chunks).zip(&mut other_chunks) {
+        unsafe {
+            let v = _mm512_add_pd(_mm512_loadu_pd(chunk.as_ptr()), _mm512_loadu_pd(o.as_ptr()));
+            _mm512_storeu_pd(chunk.as_mut_ptr(), v);
+        }
+    }
+    add_assign_scalar(chunks.into_remainder(), other_chunks.remainder());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn axpy_avx2(c: &mut [f64], alpha: f64, other: &[f64]) {
+    use std::arch::x86_64::*;
+
+    let va = _mm256_set1_pd(alpha);
+    let mut chunks = c.chunks_exact_mut(4);
+    let mut other_chunks = other.chunks_exact(4);
+    for (chunk, o) in (&mut chunks).zip(&mut other_chunks) {
+        unsafe {
+            let v = _mm256_fmadd_pd(
+                va,
+                _mm256_loadu_pd(o.as_ptr()),
+                _mm256_loadu_pd(chunk.as_ptr()),
+            );
+            _mm256_storeu_pd(chunk.as_mut_ptr(), v);
+        }
+    }
+    axpy_scalar(chunks.into_remainder(), alpha, other_chunks.remainder());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn axpy_avx512(c: &mut [f64], alpha: f64, other: &[f64]) {
+    use std::arch::x86_64::*;
+
+    let va = _mm512_set1_pd(alpha);
+    let mut chunks = c.chunks_exact_mut(8);
+    let mut other_chunks = other.chunks_exact(8);
+    for (chunk, o) in (&mut chunks).zip(&mut other_chunks) {
+        unsafe {
+            let v = _mm512_fmadd_pd(
+                va,
+                _mm512_loadu_pd(o.as_ptr()),
+                _mm512_loadu_pd(chunk.as_ptr()),
+            );
+            _mm512_storeu_pd(chunk.as_mut_ptr(), v);
+        }
+    }
+    axpy_scalar(chunks.into_remainder(), alpha, other_chunks.remainder());
+}
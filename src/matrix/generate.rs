@@ -0,0 +1,153 @@
+//! Test-data generators: identity, sequential, and seeded random matrices.
+//!
+//! Periodic fill patterns like `(i % 10) as f64` repeat every few elements, so
+//! a kernel that reads a shifted or transposed element can still land on an
+//! equal value and pass. [`sequential`] makes every element distinct and
+//! [`random`] makes them unstructured, which catches that class of bug.
+//!
+//! All functions return row-major `Vec`s, of `f64` except for
+//! [`random_of`], which fills other element types from the same stream.
+
+/// n×n identity matrix.
+pub fn identity(n: usize) -> Vec<f64> {
+    let mut data = vec![0.0; n * n];
+    for i in 0..n {
+        data[i * n + i] = 1.0;
+    }
+    data
+}
+
+/// m×n matrix holding 0, 1, 2, ... in row-major order, so element (i, j) is
+/// `i * n + j` and no two elements are equal.
+pub fn sequential(m: usize, n: usize) -> Vec<f64> {
+    (0..m * n).map(|i| i as f64).collect()
+}
+
+/// m×n matrix of uniform values in [-1, 1), reproducible from `seed`.
+///
+/// Uses splitmix64, so the same seed gives the same matrix on every platform
+/// and no `rand` dependency is needed.
+///
+/// # Example
+///
+/// ```
+/// use matmul::matrix::generate::random;
+///
+/// let a = random(3, 4, 42);
+/// assert_eq!(a.len(), 12);
+/// assert_eq!(a, random(3, 4, 42));
+/// assert!(a.iter().all(|&x| (-1.0..1.0).contains(&x)));
+/// ```
+pub fn random(m: usize, n: usize, seed: u64) -> Vec<f64> {
+    random_of(m, n, seed)
+}
+
+/// An element type [`random_of`] can fill a matrix with.
+pub trait RandomElement: Copy {
+    /// The element for one uniform draw `u` in [0, 1).
+    fn from_unit(u: f64) -> Self;
+}
+
+/// Uniform in [-1, 1), as [`random`]
+impl RandomElement for f64 {
+    fn from_unit(u: f64) -> f64 {
+        u * 2.0 - 1.0
+    }
+}
+
+/// [`random`]'s values, rounded to `f32`
+impl RandomElement for f32 {
+    fn from_unit(u: f64) -> f32 {
+        (u * 2.0 - 1.0) as f32
+    }
+}
+
+/// Uniform over all of -128..=127
+impl RandomElement for i8 {
+    fn from_unit(u: f64) -> i8 {
+        ((u * 256.0) as i32 - 128) as i8
+    }
+}
+
+/// m×n matrix of `T`s from the same seeded stream as [`random`], so the f32
+/// matrix for a seed is the f64 one rounded.
+///
+/// ```
+/// use matmul::matrix::generate::{random, random_of};
+///
+/// let a: Vec<f32> = random_of(3, 4, 42);
+/// assert!(a.iter().zip(random(3, 4, 42)).all(|(&x, y)| x == y as f32));
+/// let q: Vec<i8> = random_of(3, 4, 42);
+/// assert_eq!(q, random_of::<i8>(3, 4, 42));
+/// ```
+pub fn random_of<T: RandomElement>(m: usize, n: usize, seed: u64) -> Vec<T> {
+    let mut rng = SplitMix64(seed);
+    (0..m * n).map(|_| T::from_unit(rng.next_f64())).collect()
+}
+
+/// splitmix64 (Steele, Lea & Flood): tiny, fast, and good enough for test data.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1): the top 53 bits scaled by 2^-53.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identity_and_sequential() {
+        assert_eq!(
+            identity(3),
+            vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+        assert!(identity(0).is_empty());
+        assert_eq!(sequential(2, 3), vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_splitmix64_reference_values() {
+        // First outputs for seed 0 from the reference implementation
+        let mut rng = SplitMix64(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn test_random_is_seeded_and_spread() {
+        let a = random(100, 100, 7);
+        assert_eq!(a, random(100, 100, 7));
+        assert_ne!(a, random(100, 100, 8));
+
+        assert!(a.iter().all(|&x| (-1.0..1.0).contains(&x)));
+        let mean = a.iter().sum::<f64>() / a.len() as f64;
+        assert!(mean.abs() < 0.05, "mean {}", mean);
+
+        // Both halves of the range show up
+        assert!(a.iter().any(|&x| x < -0.9) && a.iter().any(|&x| x > 0.9));
+    }
+
+    #[test]
+    fn test_random_of_other_types() {
+        let q: Vec<i8> = random_of(64, 64, 7);
+        assert!(q.contains(&i8::MIN) && q.contains(&i8::MAX));
+        let mean = q.iter().map(|&x| x as f64).sum::<f64>() / q.len() as f64;
+        assert!(mean.abs() < 3.0, "mean {}", mean);
+
+        let single: Vec<f32> = random_of(10, 10, 7);
+        let double = random(10, 10, 7);
+        assert!(single.iter().zip(&double).all(|(&x, &y)| x == y as f32));
+    }
+}
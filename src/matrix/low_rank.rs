@@ -0,0 +1,207 @@
+//! C += A·B for a very small inner dimension.
+//!
+//! With k = 1 the product is an outer product, and for any k up to
+//! [`LOW_RANK_MAX_K`] each element of C takes only a handful of multiply-adds.
+//! That's too little arithmetic for packing to pay off: the blocked drivers
+//! would spend most of their time transposing B and packing panels. Here each
+//! row of C is loaded once, gets `a[i, p]·B[p, :]` added for every p with
+//! broadcast FMAs along n, and is stored once.
+
+use crate::Backend;
+use std::ops::Range;
+
+/// The largest k [`multiply`](crate::multiply) sends here rather than to a
+/// blocked driver.
+pub const LOW_RANK_MAX_K: usize = 4;
+
+/// C += A·B, row by row, vectorized along n.
+///
+/// Works for any k, but is only quick when k is small: it streams all of B
+/// once per row of C. Each element sums its k products in order; the SIMD
+/// versions fuse them, so results can differ from the scalar fallback in the
+/// last bit.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn matmul_low_rank(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    crate::error::assert_dims(a, b, c, m, n, k);
+    if m == 0 || n == 0 || k == 0 {
+        return;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            unsafe { low_rank_avx512(a, b, c, n, k) };
+            return;
+        }
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            unsafe { low_rank_avx2(a, b, c, n, k) };
+            return;
+        }
+    }
+
+    low_rank_scalar(a, b, c, n, k);
+}
+
+/// Whether `backend` hands an inner dimension of `k` to the low-rank path
+/// rather than its blocked driver. The i-k-j loop never does: it's the
+/// plain reference, and does the same arithmetic anyway.
+pub(crate) fn uses_low_rank(backend: Backend, k: usize) -> bool {
+    k <= LOW_RANK_MAX_K && backend != Backend::ScalarIkj
+}
+
+/// Rows `rows` of C += A·B at `backend`'s vector width (so a backend pinned
+/// to AVX2 stays on AVX2). `c` holds just those rows; A and B are whole.
+///
+/// # Safety
+///
+/// `backend` is available, the slices match m, n, k, and `rows` is within
+/// `0..m`.
+pub(crate) unsafe fn low_rank_rows(
+    backend: Backend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    n: usize,
+    k: usize,
+    rows: Range<usize>,
+) {
+    let a = &a[rows.start * k..rows.end * k];
+    // Miri pretends every backend is available (see `features`), but can't
+    // run the intrinsics, so it gets the scalar loop
+    match backend {
+        #[cfg(all(target_arch = "x86_64", not(miri)))]
+        Backend::Avx2_4x4 | Backend::Avx2_12x4 => unsafe { low_rank_avx2(a, b, c, n, k) },
+        #[cfg(all(target_arch = "x86_64", not(miri)))]
+        Backend::Avx512_8x8 => unsafe { low_rank_avx512(a, b, c, n, k) },
+        #[cfg(all(target_arch = "x86_64", miri))]
+        Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8 => {
+            for (a_row, c_row) in a.chunks_exact(k).zip(c.chunks_exact_mut(n)) {
+                unsafe { row_fused(a_row, b, c_row, 0, n) };
+            }
+        }
+        _ => low_rank_scalar(a, b, c, n, k),
+    }
+}
+
+fn low_rank_scalar(a: &[f64], b: &[f64], c: &mut [f64], n: usize, k: usize) {
+    for (a_row, c_row) in a.chunks_exact(k).zip(c.chunks_exact_mut(n)) {
+        row_scalar(a_row, b, c_row, 0, n);
+    }
+}
+
+/// Columns `from..` of one row of C, one element at a time.
+fn row_scalar(a_row: &[f64], b: &[f64], c_row: &mut [f64], from: usize, n: usize) {
+    for (j, x) in c_row.iter_mut().enumerate().skip(from) {
+        for (p, &a_ip) in a_row.iter().enumerate() {
+            *x += a_ip * b[p * n + j];
+        }
+    }
+}
+
+/// [`row_scalar`] with each multiply-add fused, as the vector columns are,
+/// so every element rounds the way the blocked SIMD drivers' do.
+///
+/// # Safety
+///
+/// The CPU must support FMA.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(miri), target_feature(enable = "fma"))]
+unsafe fn row_fused(a_row: &[f64], b: &[f64], c_row: &mut [f64], from: usize, n: usize) {
+    for (j, x) in c_row.iter_mut().enumerate().skip(from) {
+        for (p, &a_ip) in a_row.iter().enumerate() {
+            *x = a_ip.mul_add(b[p * n + j], *x);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn low_rank_avx2(a: &[f64], b: &[f64], c: &mut [f64], n: usize, k: usize) {
+    use std::arch::x86_64::*;
+
+    let n_main = n / 4 * 4;
+    for (a_row, c_row) in a.chunks_exact(k).zip(c.chunks_exact_mut(n)) {
+        for j in (0..n_main).step_by(4) {
+            unsafe {
+                let mut acc = _mm256_loadu_pd(c_row.as_ptr().add(j));
+                for (p, &a_ip) in a_row.iter().enumerate() {
+                    let b_pj = _mm256_loadu_pd(b.as_ptr().add(p * n + j));
+                    acc = _mm256_fmadd_pd(_mm256_set1_pd(a_ip), b_pj, acc);
+                }
+                _mm256_storeu_pd(c_row.as_mut_ptr().add(j), acc);
+            }
+        }
+        unsafe { row_fused(a_row, b, c_row, n_main, n) };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,fma")]
+unsafe fn low_rank_avx512(a: &[f64], b: &[f64], c: &mut [f64], n: usize, k: usize) {
+    use std::arch::x86_64::*;
+
+    let n_main = n / 8 * 8;
+    for (a_row, c_row) in a.chunks_exact(k).zip(c.chunks_exact_mut(n)) {
+        for j in (0..n_main).step_by(8) {
+            unsafe {
+                let mut acc = _mm512_loadu_pd(c_row.as_ptr().add(j));
+                for (p, &a_ip) in a_row.iter().enumerate() {
+                    let b_pj = _mm512_loadu_pd(b.as_ptr().add(p * n + j));
+                    acc = _mm512_fmadd_pd(_mm512_set1_pd(a_ip), b_pj, acc);
+                }
+                _mm512_storeu_pd(c_row.as_mut_ptr().add(j), acc);
+            }
+        }
+        unsafe { row_fused(a_row, b, c_row, n_main, n) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::matrix::naive_ikj::matmul_naive_ikj;
+
+    #[test]
+    fn test_matches_naive() {
+        // n on both sides of the 4- and 8-wide vectors, and a 1-wide B
+        let ns: &[usize] = if cfg!(miri) {
+            &[1, 9]
+        } else {
+            &[1, 3, 4, 7, 8, 9, 15, 16, 17, 33, 100]
+        };
+        for k in 1..=LOW_RANK_MAX_K {
+            for &n in ns {
+                for m in [1, 2, 5] {
+                    let a = random(m, k, 1);
+                    let b = random(k, n, 2);
+                    let c0 = random(m, n, 3);
+                    let mut want = c0.clone();
+                    matmul_naive_ikj(&a, &b, &mut want, m, n, k);
+                    let mut c = c0.clone();
+                    matmul_low_rank(&a, &b, &mut c, m, n, k);
+                    assert_close(&want, &c, 1e-14, 1e-14);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_outer_product_exact() {
+        // One product per element, so nothing to round differently
+        let (m, n) = (6, 13);
+        let a = random(m, 1, 1);
+        let b = random(1, n, 2);
+        let mut c = vec![0.0; m * n];
+        matmul_low_rank(&a, &b, &mut c, m, n, 1);
+        for i in 0..m {
+            for j in 0..n {
+                assert_eq!(c[i * n + j], a[i] * b[j]);
+            }
+        }
+    }
+}
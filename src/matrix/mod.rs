@@ -3,6 +3,15 @@
 //! These provide correctness baselines and utility functions used by
 //! the optimized SIMD implementations.
 
+pub mod compare;
+pub mod elementwise;
+pub mod generate;
+pub mod low_rank;
 pub mod naive_ijk;
 pub mod naive_ikj;
+pub mod naive_jik;
+pub mod naive_jki;
+pub mod naive_kij;
+pub mod naive_kji;
+pub mod reference;
 pub mod transpose;
@@ -22,4 +22,4 @@ pub fn matmul_naive_ijk(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize,
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -24,19 +24,28 @@ pub fn matmul_naive_ikj(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize,
     }
 }
 
-/// i-k-j multiplication with pre-transposed B matrix.
+/// Scalar multiplication with a pre-transposed B matrix.
 ///
-/// When B is already transposed (stored as B^T), accessing `bt[j * k + p]`
-/// becomes sequential in j. Useful when multiplying the same B many times.
+/// With B stored as B^T, column j of B is the contiguous row `bt[j * k..]`,
+/// so each element of C is a dot product of two sequential streams: a row of
+/// A and a row of B^T. The loop runs i, j, then p innermost (the name is kept
+/// from when it shared the i-k-j order, which walked `bt` with stride k).
+/// Useful when multiplying the same B many times.
+///
+/// For large matrices see
+/// [`matmul_blocked_transposed`](crate::blocked::gemm_transposed::matmul_blocked_transposed),
+/// which tiles this loop for cache.
 ///
 /// # Arguments
 ///
 /// * `bt` - Transposed matrix B^T (n × k), row-major
 pub fn matmul_ikj_transposed(a: &[f64], bt: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
     for i in 0..m {
-        for p in 0..k {
-            for j in 0..n {
-                c[i * n + j] += a[i * k + p] * bt[j * k + p];
+        let a_row = &a[i * k..(i + 1) * k];
+        for j in 0..n {
+            let bt_row = &bt[j * k..(j + 1) * k];
+            for p in 0..k {
+                c[i * n + j] += a_row[p] * bt_row[p];
             }
         }
     }
@@ -0,0 +1,26 @@
+/// Naive matrix multiplication using j-i-k loop order.
+///
+/// Same innermost loop as i-j-k: a dot product of a row of A (stride 1) with
+/// a column of B (stride `n`), so every step of the inner loop touches a new
+/// cache line of B. Walking columns of C in the outer loop doesn't change
+/// that, so it's about as slow as i-j-k.
+///
+/// Use this as a correctness baseline, not for performance.
+///
+/// # Arguments
+///
+/// * `a` - Matrix A (m × k), row-major
+/// * `b` - Matrix B (k × n), row-major
+/// * `c` - Matrix C (m × n), row-major, accumulated into (C += A * B)
+/// * `m` - Rows of A and C
+/// * `n` - Columns of B and C
+/// * `k` - Columns of A, rows of B
+pub fn matmul_naive_jik(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    for j in 0..n {
+        for i in 0..m {
+            for p in 0..k {
+                c[i * n + j] += a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+}
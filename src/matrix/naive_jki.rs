@@ -0,0 +1,25 @@
+/// Naive matrix multiplication using j-k-i loop order.
+///
+/// The innermost loop runs down a column of A and a column of C, both with
+/// stride (`k` and `n`), while B is fixed. Two strided streams per step make
+/// this the slowest of the six loop orders on large matrices.
+///
+/// Use this as a correctness baseline, not for performance.
+///
+/// # Arguments
+///
+/// * `a` - Matrix A (m × k), row-major
+/// * `b` - Matrix B (k × n), row-major
+/// * `c` - Matrix C (m × n), row-major, accumulated into (C += A * B)
+/// * `m` - Rows of A and C
+/// * `n` - Columns of B and C
+/// * `k` - Columns of A, rows of B
+pub fn matmul_naive_jki(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    for j in 0..n {
+        for p in 0..k {
+            for i in 0..m {
+                c[i * n + j] += a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+}
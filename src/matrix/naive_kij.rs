@@ -0,0 +1,24 @@
+/// Matrix multiplication using k-i-j loop order.
+///
+/// Same innermost loop as i-k-j: a row of B and a row of C, both stride 1,
+/// scaled by one element of A. The difference is that each pass of the outer
+/// loop sweeps all of C, so C is re-read k times instead of staying hot one
+/// row at a time. Close to i-k-j while C fits in cache, slower once it doesn't.
+///
+/// # Arguments
+///
+/// * `a` - Matrix A (m × k), row-major
+/// * `b` - Matrix B (k × n), row-major
+/// * `c` - Matrix C (m × n), row-major, accumulated into (C += A * B)
+/// * `m` - Rows of A and C
+/// * `n` - Columns of B and C
+/// * `k` - Columns of A, rows of B
+pub fn matmul_naive_kij(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    for p in 0..k {
+        for i in 0..m {
+            for j in 0..n {
+                c[i * n + j] += a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+}
@@ -0,0 +1,25 @@
+/// Naive matrix multiplication using k-j-i loop order.
+///
+/// Same innermost loop as j-k-i: down a column of A and a column of C, both
+/// strided, with B fixed. On top of that, every pass of the outer loop sweeps
+/// all of C. As slow as j-k-i, or slower.
+///
+/// Use this as a correctness baseline, not for performance.
+///
+/// # Arguments
+///
+/// * `a` - Matrix A (m × k), row-major
+/// * `b` - Matrix B (k × n), row-major
+/// * `c` - Matrix C (m × n), row-major, accumulated into (C += A * B)
+/// * `m` - Rows of A and C
+/// * `n` - Columns of B and C
+/// * `k` - Columns of A, rows of B
+pub fn matmul_naive_kji(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    for p in 0..k {
+        for j in 0..n {
+            for i in 0..m {
+                c[i * n + j] += a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+}
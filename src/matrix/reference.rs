@@ -0,0 +1,187 @@
+//! Extended-precision reference products, for measuring how far a kernel's
+//! rounding strays.
+//!
+//! Checking a kernel against the naive loop with a fixed tolerance says little
+//! once k is large or the data is badly scaled: both results carry rounding
+//! error, and cancellation can make a correct answer look wrong (or hide a
+//! wrong one). [`matmul_reference`] instead sums each dot product in
+//! double-double arithmetic (the Ogita–Rump–Oishi `Dot2` algorithm), which is
+//! as accurate as summing in twice the precision and then rounding once, and
+//! [`measure`] reports a result's error against it.
+//!
+//! The error that matters for GEMM is relative to `|A|·|B|`, not to `|A·B|`:
+//! the standard bound for a dot product summed in any order is
+//!
+//! ```text
+//! |Ĉ[i][j] − (A·B)[i][j]| ≤ γ(k) · (|A|·|B|)[i][j],   γ(k) = k·u / (1 − k·u)
+//! ```
+//!
+//! with `u = 2⁻⁵³` the unit roundoff. [`ErrorReport::scaled`] is the largest
+//! left-hand side divided by `(|A|·|B|)[i][j]`, so it can be compared with
+//! [`error_bound`] directly; [`ErrorReport::relative`] is the familiar
+//! `|Ĉ − C| / |C|`, which has no useful bound when the sum cancels.
+
+/// Unit roundoff for `f64`: half the distance from 1.0 to the next float.
+pub const UNIT_ROUNDOFF: f64 = f64::EPSILON / 2.0;
+
+/// m×n product A·B (row-major), each element summed in double-double and
+/// rounded to `f64` once.
+///
+/// Every element is within one rounding of the exact product, plus a
+/// `k²·u²·(|A|·|B|)[i][j]` term that's negligible next to any `f64` kernel's
+/// error. Inputs must be finite, and the partial sums must not overflow.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+///
+/// # Example
+///
+/// ```
+/// use matmul::matrix::reference::matmul_reference;
+///
+/// // 1 + 1e-17 − 1 loses the small term in f64; the reference keeps it
+/// let a = [1.0, 1e-17, -1.0];
+/// let b = [1.0, 1.0, 1.0];
+/// assert_eq!(matmul_reference(&a, &b, 1, 1, 3), [1e-17]);
+/// ```
+pub fn matmul_reference(a: &[f64], b: &[f64], m: usize, n: usize, k: usize) -> Vec<f64> {
+    assert_eq!(a.len(), m * k, "A: expected {}x{} elements", m, k);
+    assert_eq!(b.len(), k * n, "B: expected {}x{} elements", k, n);
+
+    let mut c = vec![0.0; m * n];
+    for i in 0..m {
+        let row = &a[i * k..(i + 1) * k];
+        for j in 0..n {
+            let (mut sum, mut err) = (0.0, 0.0);
+            for (p, &x) in row.iter().enumerate() {
+                let (product, product_err) = two_prod(x, b[p * n + j]);
+                let (next, sum_err) = two_sum(sum, product);
+                sum = next;
+                err += product_err + sum_err;
+            }
+            c[i * n + j] = sum + err;
+        }
+    }
+    c
+}
+
+/// `γ(k) = k·u / (1 − k·u)`: the documented bound on [`ErrorReport::scaled`]
+/// for every [`Backend`](crate::Backend).
+///
+/// Infinite once `k·u` reaches 1 (k around 9·10¹⁵), where the bound says
+/// nothing.
+pub fn error_bound(k: usize) -> f64 {
+    let ku = k as f64 * UNIT_ROUNDOFF;
+    if ku >= 1.0 {
+        f64::INFINITY
+    } else {
+        ku / (1.0 - ku)
+    }
+}
+
+/// How far a computed product is from [`matmul_reference`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorReport {
+    /// Largest `|Ĉ − C| / (|A|·|B|)` over the elements (0 where
+    /// `(|A|·|B|)` is 0 and Ĉ is exact); compare with [`error_bound`]
+    pub scaled: f64,
+    /// Largest `|Ĉ − C| / |C|` (infinite where C is 0 and Ĉ isn't)
+    pub relative: f64,
+}
+
+impl ErrorReport {
+    /// [`scaled`](ErrorReport::scaled) in units of [`UNIT_ROUNDOFF`], the
+    /// number to compare with k.
+    pub fn scaled_ulps(&self) -> f64 {
+        self.scaled / UNIT_ROUNDOFF
+    }
+}
+
+/// Measures `computed` (m×n, from C = 0) against [`matmul_reference`].
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+pub fn measure(
+    a: &[f64],
+    b: &[f64],
+    computed: &[f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) -> ErrorReport {
+    assert_eq!(computed.len(), m * n, "C: expected {}x{} elements", m, n);
+    let reference = matmul_reference(a, b, m, n, k);
+
+    // |A|·|B| only scales the error, so plain f64 (all terms positive, so no
+    // cancellation) is plenty accurate
+    let abs_a: Vec<f64> = a.iter().map(|x| x.abs()).collect();
+    let abs_b: Vec<f64> = b.iter().map(|x| x.abs()).collect();
+    let mut magnitude = vec![0.0; m * n];
+    super::naive_ikj::matmul_naive_ikj(&abs_a, &abs_b, &mut magnitude, m, n, k);
+
+    let mut report = ErrorReport {
+        scaled: 0.0,
+        relative: 0.0,
+    };
+    for ((&exact, &got), &scale) in reference.iter().zip(computed).zip(&magnitude) {
+        let err = (got - exact).abs();
+        if err == 0.0 {
+            continue;
+        }
+        report.scaled = report.scaled.max(err / scale);
+        report.relative = report.relative.max(err / exact.abs());
+    }
+    report
+}
+
+/// `a + b` as an unevaluated sum `s + e`, exactly (Knuth's TwoSum).
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let b_virtual = s - a;
+    let a_virtual = s - b_virtual;
+    (s, (a - a_virtual) + (b - b_virtual))
+}
+
+/// `a · b` as an unevaluated sum `p + e`, exactly: the fused multiply-add
+/// computes the product's rounding error without rounding it.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_free_transformations() {
+        let (s, e) = two_sum(1.0, 1e-17);
+        assert_eq!((s, e), (1.0, 1e-17));
+
+        // (1 + 2⁻³⁰)² = 1 + 2⁻²⁹ + 2⁻⁶⁰, and the last term doesn't fit
+        let pow2 = |e: i32| f64::from_bits(((1023 + e) as u64) << 52);
+        let (p, e) = two_prod(1.0 + pow2(-30), 1.0 + pow2(-30));
+        assert_eq!((p, e), (1.0 + pow2(-29), pow2(-60)));
+    }
+
+    #[test]
+    fn test_measure_scales_by_abs_product() {
+        // A·B = 1 − 1 = 0 exactly; an answer of 1e-16 is infinitely wrong
+        // relative to |C| but only 1e-16 / 2 relative to |A|·|B|
+        let report = measure(&[1.0, 1.0], &[1.0, -1.0], &[1e-16], 1, 1, 2);
+        assert_eq!(report.relative, f64::INFINITY);
+        assert_eq!(report.scaled, 0.5e-16);
+
+        let exact = measure(&[2.0, 3.0], &[5.0, 7.0], &[31.0], 1, 1, 2);
+        assert_eq!((exact.scaled, exact.relative), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_error_bound() {
+        assert_eq!(error_bound(0), 0.0);
+        assert!((error_bound(1000) / (1000.0 * UNIT_ROUNDOFF) - 1.0).abs() < 1e-12);
+        assert_eq!(error_bound(usize::MAX), f64::INFINITY);
+    }
+}
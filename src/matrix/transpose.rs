@@ -1,8 +1,29 @@
+//! Matrix transpose, scalar and SIMD.
+//!
+//! The blocked GEMMs transpose B on every call, so this is on the critical
+//! path. Every version except [`transpose_naive`] walks the matrix in
+//! cache-sized blocks, so both the reads from `src` and the writes to `dst`
+//! stay in cache lines. Inside each block the scalar version copies element by
+//! element; the SIMD versions transpose register tiles (4×4 with AVX, 8×8 with
+//! AVX-512).
+//!
+//! The safe entry points are generic over any `Copy` element. f64 takes the
+//! SIMD path; every other type goes through the blocked scalar loop.
+
+use std::any::TypeId;
+
+/// Cache block edge: a 32×32 f64 block of src plus its image in dst is 16 KB,
+/// comfortably inside L1 (and less for narrower elements).
+const BLOCK: usize = 32;
+
 /// Transpose a matrix: dst = src^T
 ///
 /// Converts from row-major (rows × cols) to row-major (cols × rows).
 /// After transpose, what was column j of src becomes row j of dst.
 ///
+/// Works for any `Copy` element. For f64 it picks the fastest version for
+/// your CPU (AVX-512 > AVX > scalar); other types use the blocked scalar loop.
+///
 /// # Arguments
 ///
 /// * `src` - Source matrix (rows × cols), row-major
@@ -10,6 +31,10 @@
 /// * `rows` - Number of rows in src
 /// * `cols` - Number of columns in src
 ///
+/// # Panics
+///
+/// Panics if `src` or `dst` holds fewer than `rows * cols` elements.
+///
 /// # Example
 ///
 /// ```
@@ -25,10 +50,507 @@
 ///                      2.0, 5.0,
 ///                      3.0, 6.0]);
 /// ```
-pub fn transpose(src: &[f64], dst: &mut [f64], rows: usize, cols: usize) {
+pub fn transpose<T: Copy + 'static>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
+    transpose_strided(src, cols, dst, rows, rows, cols);
+}
+
+/// Transposes a rows × cols sub-block of one row-major buffer into a
+/// cols × rows sub-block of another.
+///
+/// Element (i, j) of the source block is `src[i * src_stride + j]` and lands
+/// in `dst[j * dst_stride + i]`. Nothing else in either buffer is read or
+/// written, so this can transpose a tile of a big matrix in place without
+/// copying it out first. [`transpose`] is this with `src_stride == cols` and
+/// `dst_stride == rows`.
+///
+/// # Arguments
+///
+/// * `src` - Source buffer; the block starts at `src[0]`
+/// * `src_stride` - Distance between source rows (at least `cols`)
+/// * `dst` - Destination buffer; the block starts at `dst[0]`
+/// * `dst_stride` - Distance between destination rows (at least `rows`)
+/// * `rows` - Rows in the source block
+/// * `cols` - Columns in the source block
+///
+/// # Panics
+///
+/// Panics if a stride is shorter than the row it steps over, or a buffer ends
+/// before the last element of its block.
+///
+/// # Example
+///
+/// ```
+/// use matmul::matrix::transpose::transpose_strided;
+///
+/// // Transpose the 2×2 block at row 1, column 1 of a 3×3 matrix
+/// let src = vec![1.0, 2.0, 3.0,
+///                4.0, 5.0, 6.0,
+///                7.0, 8.0, 9.0];
+/// let mut dst = vec![0.0; 4];
+///
+/// transpose_strided(&src[4..], 3, &mut dst, 2, 2, 2);
+///
+/// assert_eq!(dst, vec![5.0, 8.0,
+///                      6.0, 9.0]);
+/// ```
+pub fn transpose_strided<T: Copy + 'static>(
+    src: &[T],
+    src_stride: usize,
+    dst: &mut [T],
+    dst_stride: usize,
+    rows: usize,
+    cols: usize,
+) {
+    if rows == 0 || cols == 0 {
+        return;
+    }
+
+    assert!(
+        src_stride >= cols,
+        "src_stride {} is shorter than a row of {}",
+        src_stride,
+        cols
+    );
+    assert!(
+        dst_stride >= rows,
+        "dst_stride {} is shorter than a row of {}",
+        dst_stride,
+        rows
+    );
+    // Checked, so a block too big to address can't wrap to a length that fits
+    assert!(
+        block_len(rows, cols, src_stride).is_some_and(|needed| src.len() >= needed),
+        "src: {}x{} block with stride {} needs {} elements, got {}",
+        rows,
+        cols,
+        src_stride,
+        (rows - 1) as u128 * src_stride as u128 + cols as u128,
+        src.len()
+    );
+    assert!(
+        block_len(cols, rows, dst_stride).is_some_and(|needed| dst.len() >= needed),
+        "dst: {}x{} block with stride {} needs {} elements, got {}",
+        cols,
+        rows,
+        dst_stride,
+        (cols - 1) as u128 * dst_stride as u128 + rows as u128,
+        dst.len()
+    );
+
+    if let (Some(src), Some(dst)) = (as_f64(src), as_f64_mut(dst)) {
+        unsafe { transpose_dispatch(src, src_stride, dst, dst_stride, rows, cols) };
+    } else {
+        transpose_blocked::<T, 1>(
+            src,
+            src_stride,
+            dst,
+            dst_stride,
+            rows,
+            cols,
+            |src, dst, i, j| {
+                dst[j * dst_stride + i] = src[i * src_stride + j];
+            },
+        );
+    }
+}
+
+/// Elements from the first to the last of a rows × cols block whose rows are
+/// `stride` apart (`rows > 0`), or `None` if that overflows `usize`.
+fn block_len(rows: usize, cols: usize, stride: usize) -> Option<usize> {
+    (rows - 1).checked_mul(stride)?.checked_add(cols)
+}
+
+/// Typed alias of [`transpose`] for f64, e.g. to pass around as a `fn` pointer.
+pub fn transpose_f64(src: &[f64], dst: &mut [f64], rows: usize, cols: usize) {
+    transpose(src, dst, rows, cols);
+}
+
+/// Typed alias of [`transpose`] for f32.
+pub fn transpose_f32(src: &[f32], dst: &mut [f32], rows: usize, cols: usize) {
+    transpose(src, dst, rows, cols);
+}
+
+/// `Some` if `T` is f64: the stand-in for specialization that routes f64 to
+/// the SIMD tiles.
+fn as_f64<T: 'static>(data: &[T]) -> Option<&[f64]> {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        // T is f64, so this is the same slice with its real type
+        Some(unsafe { std::slice::from_raw_parts(data.as_ptr().cast(), data.len()) })
+    } else {
+        None
+    }
+}
+
+/// Mutable version of [`as_f64`].
+fn as_f64_mut<T: 'static>(data: &mut [T]) -> Option<&mut [f64]> {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        Some(unsafe { std::slice::from_raw_parts_mut(data.as_mut_ptr().cast(), data.len()) })
+    } else {
+        None
+    }
+}
+
+/// Below this many elements per thread, spawning costs more than it saves (1
+/// with [`SPLIT_ANY_SHAPE`](crate::threaded::SPLIT_ANY_SHAPE)).
+const MIN_ELEMENTS_PER_THREAD: usize = if crate::threaded::SPLIT_ANY_SHAPE {
+    1
+} else {
+    1 << 16
+};
+
+/// Multi-threaded [`transpose`].
+///
+/// Splits `dst` into contiguous row bands (a band of dst rows is a band of src
+/// columns) and transposes each on its own scoped thread, with the same
+/// blocked/SIMD tiles as the serial version. Worth it for big matrices, where
+/// the transpose is purely bandwidth-bound.
+///
+/// # Arguments
+///
+/// * `num_threads` - Maximum threads (actual may be fewer for small matrices)
+///
+/// # Panics
+///
+/// Panics if `src` or `dst` holds fewer than `rows * cols` elements.
+pub fn transpose_parallel<T: Copy + Send + Sync + 'static>(
+    src: &[T],
+    dst: &mut [T],
+    rows: usize,
+    cols: usize,
+    num_threads: usize,
+) {
+    assert!(
+        src.len() >= rows * cols,
+        "src: expected {}x{} elements",
+        rows,
+        cols
+    );
+    assert!(
+        dst.len() >= rows * cols,
+        "dst: expected {}x{} elements",
+        cols,
+        rows
+    );
+
+    let threads = num_threads
+        .min(cols)
+        .min(rows * cols / MIN_ELEMENTS_PER_THREAD)
+        .max(1);
+
+    if threads == 1 {
+        transpose(src, dst, rows, cols);
+        return;
+    }
+
+    // Whole 8×8 tiles per band, so only the last band has a ragged edge
+    let band_rows = cols.div_ceil(threads).next_multiple_of(8);
+
+    std::thread::scope(|s| {
+        for (band, dst_band) in dst[..rows * cols].chunks_mut(band_rows * rows).enumerate() {
+            let j0 = band * band_rows;
+            let width = dst_band.len() / rows;
+            let src_band = &src[j0..];
+            // Source columns j0..j0 + width, read with the full row stride
+            s.spawn(move || transpose_strided(src_band, cols, dst_band, rows, rows, width));
+        }
+    });
+}
+
+/// Transposes a square n×n matrix in place, without a second buffer.
+///
+/// Swaps each element with its mirror across the diagonal, one pair of 32×32
+/// blocks at a time: block (i, j) is swapped with block (j, i), and diagonal
+/// blocks are transposed within themselves. Both blocks stay in cache while
+/// they are being swapped.
+///
+/// # Panics
+///
+/// Panics unless `data` holds exactly `n * n` elements. Rectangular matrices
+/// are not supported in place; use [`transpose`] with a second buffer.
+///
+/// # Example
+///
+/// ```
+/// use matmul::matrix::transpose::transpose_in_place;
+///
+/// let mut m = vec![1.0, 2.0,
+///                  3.0, 4.0];
+/// transpose_in_place(&mut m, 2);
+/// assert_eq!(m, vec![1.0, 3.0,
+///                    2.0, 4.0]);
+/// ```
+pub fn transpose_in_place<T>(data: &mut [T], n: usize) {
+    assert_eq!(
+        data.len(),
+        n * n,
+        "transpose_in_place: expected a square {}x{} matrix",
+        n,
+        n
+    );
+
+    for ib in (0..n).step_by(BLOCK) {
+        let i_end = (ib + BLOCK).min(n);
+
+        // Diagonal block: swap across its own diagonal
+        for i in ib..i_end {
+            for j in i + 1..i_end {
+                data.swap(i * n + j, j * n + i);
+            }
+        }
+
+        // Blocks right of the diagonal, each swapped with its mirror below it
+        for jb in (i_end..n).step_by(BLOCK) {
+            let j_end = (jb + BLOCK).min(n);
+            for i in ib..i_end {
+                for j in jb..j_end {
+                    data.swap(i * n + j, j * n + i);
+                }
+            }
+        }
+    }
+}
+
+/// Transposes the `rows × cols` block at the start of `src` (row stride `lds`)
+/// into `dst` (row stride `ldd`), with the fastest version for this CPU.
+///
+/// # Safety
+///
+/// Caller must ensure `src` reaches element `(rows - 1) * lds + cols - 1` and
+/// `dst` reaches element `(cols - 1) * ldd + rows - 1`.
+unsafe fn transpose_dispatch(
+    src: &[f64],
+    lds: usize,
+    dst: &mut [f64],
+    ldd: usize,
+    rows: usize,
+    cols: usize,
+) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            unsafe { transpose_avx512_strided(src, lds, dst, ldd, rows, cols) };
+            return;
+        }
+        if is_x86_feature_detected!("avx") {
+            unsafe { transpose_avx_strided(src, lds, dst, ldd, rows, cols) };
+            return;
+        }
+    }
+
+    transpose_blocked::<f64, 1>(src, lds, dst, ldd, rows, cols, |src, dst, i, j| {
+        dst[j * ldd + i] = src[i * lds + j];
+    });
+}
+
+/// Cache-blocked scalar transpose: the portable fallback.
+///
+/// Same double loop as [`transpose_naive`], but run one 32×32 block at a time
+/// so the strided side touches at most 32 cache lines at once.
+pub fn transpose_scalar<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
+    transpose_blocked::<T, 1>(src, cols, dst, rows, rows, cols, |src, dst, i, j| {
+        dst[j * rows + i] = src[i * cols + j];
+    });
+}
+
+/// Naive transpose: a plain double loop with a strided store.
+///
+/// One of `src`/`dst` is always walked with a stride of a full row, so this
+/// falls far short of memory bandwidth on large matrices. Kept as the
+/// reference implementation and benchmark baseline.
+pub fn transpose_naive<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
     for i in 0..rows {
         for j in 0..cols {
             dst[j * rows + i] = src[i * cols + j];
         }
     }
 }
+
+/// Cache-blocked transpose using 4×4 AVX register tiles.
+///
+/// # Safety
+///
+/// Caller must ensure:
+/// - CPU supports AVX
+/// - `src` and `dst` each hold at least `rows * cols` elements
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+pub unsafe fn transpose_avx(src: &[f64], dst: &mut [f64], rows: usize, cols: usize) {
+    unsafe { transpose_avx_strided(src, cols, dst, rows, rows, cols) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn transpose_avx_strided(
+    src: &[f64],
+    lds: usize,
+    dst: &mut [f64],
+    ldd: usize,
+    rows: usize,
+    cols: usize,
+) {
+    transpose_blocked::<f64, 4>(src, lds, dst, ldd, rows, cols, |src, dst, i, j| unsafe {
+        transpose_4x4_avx(
+            src.as_ptr().add(i * lds + j),
+            lds,
+            dst.as_mut_ptr().add(j * ldd + i),
+            ldd,
+        )
+    });
+}
+
+/// Cache-blocked transpose using 8×8 AVX-512 register tiles.
+///
+/// # Safety
+///
+/// Caller must ensure:
+/// - CPU supports AVX-512F
+/// - `src` and `dst` each hold at least `rows * cols` elements
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+pub unsafe fn transpose_avx512(src: &[f64], dst: &mut [f64], rows: usize, cols: usize) {
+    unsafe { transpose_avx512_strided(src, cols, dst, rows, rows, cols) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+unsafe fn transpose_avx512_strided(
+    src: &[f64],
+    lds: usize,
+    dst: &mut [f64],
+    ldd: usize,
+    rows: usize,
+    cols: usize,
+) {
+    transpose_blocked::<f64, 8>(src, lds, dst, ldd, rows, cols, |src, dst, i, j| unsafe {
+        transpose_8x8_avx512(
+            src.as_ptr().add(i * lds + j),
+            lds,
+            dst.as_mut_ptr().add(j * ldd + i),
+            ldd,
+        )
+    });
+}
+
+/// Walks src in BLOCK×BLOCK cache blocks. Inside each block, `tile(src, dst, i, j)`
+/// transposes the TILE×TILE tile at (i, j); rows and columns left over at the
+/// block edges are copied one element at a time. `lds`/`ldd` are the row
+/// strides of src and dst.
+#[inline(always)]
+fn transpose_blocked<T: Copy, const TILE: usize>(
+    src: &[T],
+    lds: usize,
+    dst: &mut [T],
+    ldd: usize,
+    rows: usize,
+    cols: usize,
+    tile: impl Fn(&[T], &mut [T], usize, usize),
+) {
+    for ib in (0..rows).step_by(BLOCK) {
+        let i_end = (ib + BLOCK).min(rows);
+        let i_main = ib + ((i_end - ib) / TILE) * TILE;
+
+        for jb in (0..cols).step_by(BLOCK) {
+            let j_end = (jb + BLOCK).min(cols);
+            let j_main = jb + ((j_end - jb) / TILE) * TILE;
+
+            for i in (ib..i_main).step_by(TILE) {
+                for j in (jb..j_main).step_by(TILE) {
+                    tile(src, dst, i, j);
+                }
+            }
+
+            // Leftover columns of the full-tile rows, then leftover rows
+            for i in ib..i_main {
+                for j in j_main..j_end {
+                    dst[j * ldd + i] = src[i * lds + j];
+                }
+            }
+            for i in i_main..i_end {
+                for j in jb..j_end {
+                    dst[j * ldd + i] = src[i * lds + j];
+                }
+            }
+        }
+    }
+}
+
+/// Transposes one 4×4 tile: 4 row loads, unpack pairs, swap 128-bit halves.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+#[allow(clippy::identity_op)]
+#[allow(clippy::erasing_op)]
+#[allow(unsafe_op_in_unsafe_fn)]
+#[inline]
+unsafe fn transpose_4x4_avx(src: *const f64, lds: usize, dst: *mut f64, ldd: usize) {
+    use std::arch::x86_64::*;
+
+    let r0 = _mm256_loadu_pd(src.add(0 * lds));
+    let r1 = _mm256_loadu_pd(src.add(1 * lds));
+    let r2 = _mm256_loadu_pd(src.add(2 * lds));
+    let r3 = _mm256_loadu_pd(src.add(3 * lds));
+
+    // t0 = [r0[0] r1[0] r0[2] r1[2]], t1 = [r0[1] r1[1] r0[3] r1[3]], ...
+    let t0 = _mm256_unpacklo_pd(r0, r1);
+    let t1 = _mm256_unpackhi_pd(r0, r1);
+    let t2 = _mm256_unpacklo_pd(r2, r3);
+    let t3 = _mm256_unpackhi_pd(r2, r3);
+
+    // Low halves give columns 0 and 1, high halves columns 2 and 3
+    _mm256_storeu_pd(dst.add(0 * ldd), _mm256_permute2f128_pd(t0, t2, 0x20));
+    _mm256_storeu_pd(dst.add(1 * ldd), _mm256_permute2f128_pd(t1, t3, 0x20));
+    _mm256_storeu_pd(dst.add(2 * ldd), _mm256_permute2f128_pd(t0, t2, 0x31));
+    _mm256_storeu_pd(dst.add(3 * ldd), _mm256_permute2f128_pd(t1, t3, 0x31));
+}
+
+/// Transposes one 8×8 tile: unpack pairs, gather 4-row groups with two-source
+/// permutes, then stitch the 256-bit halves together.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f")]
+#[allow(clippy::identity_op)]
+#[allow(clippy::erasing_op)]
+#[allow(unsafe_op_in_unsafe_fn)]
+#[inline]
+unsafe fn transpose_8x8_avx512(src: *const f64, lds: usize, dst: *mut f64, ldd: usize) {
+    use std::arch::x86_64::*;
+
+    let r0 = _mm512_loadu_pd(src.add(0 * lds));
+    let r1 = _mm512_loadu_pd(src.add(1 * lds));
+    let r2 = _mm512_loadu_pd(src.add(2 * lds));
+    let r3 = _mm512_loadu_pd(src.add(3 * lds));
+    let r4 = _mm512_loadu_pd(src.add(4 * lds));
+    let r5 = _mm512_loadu_pd(src.add(5 * lds));
+    let r6 = _mm512_loadu_pd(src.add(6 * lds));
+    let r7 = _mm512_loadu_pd(src.add(7 * lds));
+
+    // Pairs of rows: t0 = [r0[0] r1[0] r0[2] r1[2] r0[4] r1[4] r0[6] r1[6]], ...
+    let t0 = _mm512_unpacklo_pd(r0, r1);
+    let t1 = _mm512_unpackhi_pd(r0, r1);
+    let t2 = _mm512_unpacklo_pd(r2, r3);
+    let t3 = _mm512_unpackhi_pd(r2, r3);
+    let t4 = _mm512_unpacklo_pd(r4, r5);
+    let t5 = _mm512_unpackhi_pd(r4, r5);
+    let t6 = _mm512_unpacklo_pd(r6, r7);
+    let t7 = _mm512_unpackhi_pd(r6, r7);
+
+    // Groups of four rows: u0 = [col 0 of rows 0-3 | col 4 of rows 0-3], ...
+    let even = _mm512_set_epi64(13, 12, 5, 4, 9, 8, 1, 0);
+    let odd = _mm512_set_epi64(15, 14, 7, 6, 11, 10, 3, 2);
+    let u0 = _mm512_permutex2var_pd(t0, even, t2); // cols 0, 4
+    let u1 = _mm512_permutex2var_pd(t0, odd, t2); // cols 2, 6
+    let u2 = _mm512_permutex2var_pd(t1, even, t3); // cols 1, 5
+    let u3 = _mm512_permutex2var_pd(t1, odd, t3); // cols 3, 7
+    let u4 = _mm512_permutex2var_pd(t4, even, t6);
+    let u5 = _mm512_permutex2var_pd(t4, odd, t6);
+    let u6 = _mm512_permutex2var_pd(t5, even, t7);
+    let u7 = _mm512_permutex2var_pd(t5, odd, t7);
+
+    // Rows 0-3 from the u0..u3 half, rows 4-7 from the u4..u7 half
+    _mm512_storeu_pd(dst.add(0 * ldd), _mm512_shuffle_f64x2(u0, u4, 0x44));
+    _mm512_storeu_pd(dst.add(1 * ldd), _mm512_shuffle_f64x2(u2, u6, 0x44));
+    _mm512_storeu_pd(dst.add(2 * ldd), _mm512_shuffle_f64x2(u1, u5, 0x44));
+    _mm512_storeu_pd(dst.add(3 * ldd), _mm512_shuffle_f64x2(u3, u7, 0x44));
+    _mm512_storeu_pd(dst.add(4 * ldd), _mm512_shuffle_f64x2(u0, u4, 0xEE));
+    _mm512_storeu_pd(dst.add(5 * ldd), _mm512_shuffle_f64x2(u2, u6, 0xEE));
+    _mm512_storeu_pd(dst.add(6 * ldd), _mm512_shuffle_f64x2(u1, u5, 0xEE));
+    _mm512_storeu_pd(dst.add(7 * ldd), _mm512_shuffle_f64x2(u3, u7, 0xEE));
+}
@@ -0,0 +1,231 @@
+//! Matrices held as a `Vec` per row.
+//!
+//! Every kernel here works on one contiguous row-major slice, so a
+//! `Vec<Vec<f64>>` has to be copied into one first, and nothing about
+//! `&[Vec<f64>]` says its rows are all the same length. [`multiply_nested`]
+//! checks that they are, flattens A and B, multiplies, and splits C back into
+//! rows; [`MatMul::compute_nested`] does the same with flat buffers it keeps
+//! between calls. Either way that's three extra copies and the per-row
+//! allocations of the result, so the flat API is faster for anything that
+//! can hold its matrices flat.
+
+use crate::MatMulError;
+use crate::builder::MatMul;
+
+/// Flat copies of the last nested operands, reused by
+/// [`MatMul::compute_nested`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Flattened {
+    a: Vec<f64>,
+    b: Vec<f64>,
+    c: Vec<f64>,
+}
+
+/// A·B for A and B given as rows.
+///
+/// Whatever the shapes, the result has A's row count and B's row length.
+/// Runs [`multiply`](crate::multiply) on flattened copies, so the product is
+/// the same as the flat API's, bit for bit.
+///
+/// ```
+/// use matmul::multiply_nested;
+///
+/// let a = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
+/// let b = vec![vec![5.0], vec![6.0]];
+/// assert_eq!(multiply_nested(&a, &b)?, [[17.0], [39.0]]);
+/// # Ok::<(), matmul::MatMulError>(())
+/// ```
+///
+/// # Errors
+///
+/// - [`MatMulError::Ragged`] if a row of A or B isn't as long as the first
+/// - [`MatMulError::WrongLength`] for B if it doesn't have a row per column
+///   of A
+/// - [`MatMulError::Overflow`] if an operand's size overflows `usize`
+pub fn multiply_nested(a: &[Vec<f64>], b: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, MatMulError> {
+    let mut flat = Flattened::default();
+    let (m, n, k) = flat.load(a, b)?;
+    crate::multiply(&flat.a, &flat.b, &mut flat.c, m, n, k);
+    Ok(nest(&flat.c, m, n))
+}
+
+impl MatMul {
+    /// alpha·A·B, for A and B given as rows.
+    ///
+    /// Like [`compute`](MatMul::compute), on this multiply's settings. A, B,
+    /// and C are flattened into buffers kept from one call to the next, so
+    /// only the returned rows are allocated.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`multiply_nested`].
+    pub fn compute_nested(
+        &mut self,
+        a: &[Vec<f64>],
+        b: &[Vec<f64>],
+    ) -> Result<Vec<Vec<f64>>, MatMulError> {
+        let mut flat = std::mem::take(&mut self.nested);
+        let result = flat.load(a, b).and_then(|(m, n, k)| {
+            self.run(&flat.a, &flat.b, &mut flat.c, m, n, k, 0.0, None)?;
+            Ok(nest(&flat.c, m, n))
+        });
+        self.nested = flat;
+        result
+    }
+}
+
+impl Flattened {
+    /// Copies A and B in, after checking their shapes, and zeroes an m×n C.
+    /// Returns `(m, n, k)`.
+    fn load(
+        &mut self,
+        a: &[Vec<f64>],
+        b: &[Vec<f64>],
+    ) -> Result<(usize, usize, usize), MatMulError> {
+        let (m, k) = (a.len(), b.len());
+        // With no rows to go on, A's width is B's height
+        let a_cols = a.first().map_or(k, Vec::len);
+        let n = b.first().map_or(0, Vec::len);
+        check_rectangular("A", a)?;
+        check_rectangular("B", b)?;
+        if a_cols != k {
+            return Err(MatMulError::WrongLength {
+                operand: "B",
+                rows: a_cols,
+                cols: n,
+                len: k * n,
+            });
+        }
+        let c_len = m.checked_mul(n).ok_or(MatMulError::Overflow {
+            operand: "C",
+            rows: m,
+            cols: n,
+        })?;
+        flatten(&mut self.a, a);
+        flatten(&mut self.b, b);
+        self.c.clear();
+        self.c.resize(c_len, 0.0);
+        Ok((m, n, k))
+    }
+}
+
+/// Every row as long as the first.
+fn check_rectangular(operand: &'static str, rows: &[Vec<f64>]) -> Result<(), MatMulError> {
+    let Some(first) = rows.first() else {
+        return Ok(());
+    };
+    match rows.iter().position(|row| row.len() != first.len()) {
+        Some(row) => Err(MatMulError::Ragged {
+            operand,
+            row,
+            len: rows[row].len(),
+            expected: first.len(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn flatten(flat: &mut Vec<f64>, rows: &[Vec<f64>]) {
+    flat.clear();
+    for row in rows {
+        flat.extend_from_slice(row);
+    }
+}
+
+/// Row-major m×n `flat` split into rows.
+fn nest(flat: &[f64], m: usize, n: usize) -> Vec<Vec<f64>> {
+    if n == 0 {
+        return vec![Vec::new(); m];
+    }
+    flat.chunks_exact(n).map(<[f64]>::to_vec).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    fn rows(flat: &[f64], m: usize, n: usize) -> Vec<Vec<f64>> {
+        (0..m).map(|i| flat[i * n..(i + 1) * n].to_vec()).collect()
+    }
+
+    #[test]
+    fn test_matches_flat() {
+        let mut matmul = MatMul::new().build().unwrap();
+        for (m, n, k) in [(13, 9, 5), (1, 1, 1), (7, 3, 1), (30, 20, 40)] {
+            let (a, b) = (random(m, k, 1), random(k, n, 2));
+            let mut want = vec![0.0; m * n];
+            multiply(&a, &b, &mut want, m, n, k);
+            let want = rows(&want, m, n);
+
+            let (a, b) = (rows(&a, m, k), rows(&b, k, n));
+            assert_eq!(multiply_nested(&a, &b).unwrap(), want);
+            // Twice, so the second call runs on the kept buffers
+            assert_eq!(matmul.compute_nested(&a, &b).unwrap(), want);
+            assert_eq!(matmul.compute_nested(&a, &b).unwrap(), want);
+        }
+    }
+
+    #[test]
+    fn test_alpha() {
+        let mut matmul = MatMul::new().alpha(2.0).beta(5.0).build().unwrap();
+        let a = vec![vec![1.0, 2.0]];
+        let b = vec![vec![3.0], vec![4.0]];
+        assert_eq!(matmul.compute_nested(&a, &b).unwrap(), [[22.0]]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let none: Vec<Vec<f64>> = Vec::new();
+        assert!(
+            multiply_nested(&none, &[vec![1.0, 2.0]])
+                .unwrap()
+                .is_empty()
+        );
+        // k = 0: two rows of A with nothing in them, and no B to give n
+        let empty_rows = vec![Vec::new(), Vec::new()];
+        assert_eq!(
+            multiply_nested(&empty_rows, &none).unwrap(),
+            vec![Vec::<f64>::new(); 2]
+        );
+    }
+
+    #[test]
+    fn test_rejected() {
+        let square = vec![vec![1.0, 2.0], vec![3.0, 4.0]];
+        let ragged = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0]];
+        assert_eq!(
+            multiply_nested(&ragged, &square),
+            Err(MatMulError::Ragged {
+                operand: "A",
+                row: 2,
+                len: 1,
+                expected: 2
+            })
+        );
+        assert!(matches!(
+            multiply_nested(&square, &ragged),
+            Err(MatMulError::Ragged { operand: "B", .. })
+        ));
+        // A is 2×2, so B needs 2 rows, not 3
+        let tall = vec![vec![1.0], vec![2.0], vec![3.0]];
+        assert_eq!(
+            multiply_nested(&square, &tall),
+            Err(MatMulError::WrongLength {
+                operand: "B",
+                rows: 2,
+                cols: 1,
+                len: 3
+            })
+        );
+
+        // A failed call leaves the kept buffers usable
+        let mut matmul = MatMul::new().build().unwrap();
+        assert!(matmul.compute_nested(&ragged, &square).is_err());
+        assert_eq!(
+            matmul.compute_nested(&square, &square).unwrap(),
+            [[7.0, 10.0], [15.0, 22.0]]
+        );
+    }
+}
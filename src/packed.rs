@@ -0,0 +1,982 @@
+//! A left operand packed once and multiplied many times.
+//!
+//! Every blocked driver starts by copying A into MR-row panels its
+//! microkernel reads sequentially. When the same A meets many right-hand
+//! sides (a weight matrix applied to a stream of inputs, say), that copy is
+//! repeated work. [`PackedA`] keeps the panels for one backend, and
+//! [`multiply_prepacked_a`] runs that backend's blocking over them, skipping
+//! the packing step. Results are bit for bit those of
+//! [`multiply_with_backend`](crate::multiply_with_backend) on the same
+//! backend.
+//!
+//! A multiply can also use just the first columns of a packed A (say, a
+//! weight matrix packed for the longest input it takes, applied to shorter
+//! ones): pass `k_used` and give B that many rows. The packed blocks are
+//! read only as far as the prefix goes, and the result is that of an
+//! unpacked multiply on A's first `k_used` columns.
+//!
+//! Packing a large A can take longer than the multiplies a short-lived
+//! process runs with it, so a packed A can be saved with
+//! [`PackedA::write_to`] and loaded with [`PackedA::read_from`], or used in
+//! place from a memory-mapped file with [`PackedA::from_bytes`]. The file
+//! records the backend, the tile and block sizes, and a checksum, and
+//! loading it on a machine that would select a different backend or KC is a
+//! [`PackedFileError::Layout`] rather than wrong results: the panels only
+//! make sense to the kernel they were laid out for.
+
+use crate::blocked::edge::multiply_edge_with;
+use crate::blocked::gemm_scalar;
+use crate::blocked::pack::pack_a_panel;
+use crate::error::{check_no_overlap, check_operands};
+use crate::matrix::low_rank;
+use crate::{Backend, GemmBackend, MatMulError};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[cfg(target_arch = "x86_64")]
+use crate::kernels::{
+    kernel_4x4::kernel_4x4_avx2, kernel_8x8::kernel_8x8_avx512, kernel_12x4::kernel_12x4_avx2,
+};
+
+/// A (m×k, row-major) in the panel layout of one backend's microkernel.
+///
+/// For each KC-deep block of k, the rows that fill whole MR-row tiles are
+/// stored tile after tile, each tile with its MR values per k step adjacent
+/// (what [`pack_a_panel`] produces). The last `m % MR` rows, which the
+/// drivers hand to their edge path, are kept row-major, as is all of
+/// an A with k too small to block.
+///
+/// ```
+/// use matmul::{Backend, PackedA, multiply_prepacked_a};
+///
+/// let (m, n, k) = (64, 8, 32);
+/// let weights = vec![0.5; m * k];
+/// let packed = PackedA::pack(&weights, m, k, Backend::Scalar)?;
+/// for _ in 0..3 {
+///     let input = vec![1.0; k * n];
+///     let mut out = vec![0.0; m * n];
+///     multiply_prepacked_a(&packed, &input, &mut out, m, n, k, None)?;
+///     assert!(out.iter().all(|&x| x == 16.0));
+/// }
+/// # Ok::<(), matmul::MatMulError>(())
+/// ```
+#[derive(Clone)]
+pub struct PackedA<'a> {
+    backend: Backend,
+    m: usize,
+    k: usize,
+    /// Rows in whole MR-row tiles
+    m_main: usize,
+    /// Rows `0..m_main`: the block at `kk` starts at `kk * m_main`
+    panels: Cow<'a, [f64]>,
+    /// Rows `m_main..m`, row-major
+    tail: Cow<'a, [f64]>,
+}
+
+impl PackedA<'static> {
+    /// Packs A (m×k, row-major) for `backend`.
+    ///
+    /// # Errors
+    ///
+    /// - [`MatMulError::WrongLength`] or [`MatMulError::Overflow`] if `a`
+    ///   doesn't hold m×k elements
+    /// - [`MatMulError::Backend`] if this CPU can't run `backend`, or for
+    ///   [`Backend::ScalarIkj`], which doesn't pack A at all
+    pub fn pack(
+        a: &[f64],
+        m: usize,
+        k: usize,
+        backend: Backend,
+    ) -> Result<PackedA<'static>, MatMulError> {
+        check_operands([("A", a.len(), m, k)])?;
+        check_backend(backend)?;
+
+        let m_main = main_rows(backend, m, k);
+        let panels = match backend.tile().0 {
+            4 => pack_panels::<4>(a, m_main, k, backend),
+            8 => pack_panels::<8>(a, m_main, k, backend),
+            12 => pack_panels::<12>(a, m_main, k, backend),
+            mr => unreachable!("{} has no {}-row panels", backend, mr),
+        };
+        Ok(PackedA {
+            backend,
+            m,
+            k,
+            m_main,
+            panels: Cow::Owned(panels),
+            tail: Cow::Owned(a[m_main * k..].to_vec()),
+        })
+    }
+}
+
+impl<'a> PackedA<'a> {
+    /// The backend the panels are laid out for.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// `(m, k)`: the shape of A.
+    pub fn dims(&self) -> (usize, usize) {
+        (self.m, self.k)
+    }
+
+    /// A(i, p) for a row in whole `mr`-row tiles, read back from the panels.
+    fn get(&self, mr: usize, i: usize, p: usize, kc: usize) -> f64 {
+        let kk = p / kc * kc;
+        let k_block = kc.min(self.k - kk);
+        let tile = i / mr * mr;
+        self.panels[kk * self.m_main + tile * k_block + (p - kk) * mr + i % mr]
+    }
+
+    /// A's first `k_used` columns, row-major, read back from the panels and
+    /// the tail.
+    fn prefix(&self, k_used: usize) -> Vec<f64> {
+        let (mr, _) = self.backend.tile();
+        let (kc, _) = self.backend.block_sizes(self.k);
+        let mut a = Vec::with_capacity(self.m * k_used);
+        for i in 0..self.m {
+            for p in 0..k_used {
+                a.push(if i < self.m_main {
+                    self.get(mr, i, p, kc)
+                } else {
+                    self.tail[(i - self.m_main) * self.k + p]
+                });
+            }
+        }
+        a
+    }
+}
+
+impl fmt::Debug for PackedA<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PackedA")
+            .field("backend", &self.backend)
+            .field("m", &self.m)
+            .field("k", &self.k)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The first bytes of a packed A [`PackedA::write_to`] writes.
+const MAGIC: &[u8; 8] = b"MMPACKDA";
+
+/// The version of the format [`PackedA::write_to`] writes, and the only
+/// one [`PackedA::read_from`] and [`PackedA::from_bytes`] accept.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Bytes before the values: a multiple of 8, so in an 8-byte aligned buffer
+/// the values are aligned too.
+const HEADER_LEN: usize = 64;
+
+/// Values per write in [`PackedA::write_to`].
+const WRITE_CHUNK: usize = 1 << 12;
+
+impl PackedA<'_> {
+    /// Writes the panels in a versioned binary format, to be loaded again
+    /// with [`read_from`](PackedA::read_from) or
+    /// [`from_bytes`](PackedA::from_bytes).
+    ///
+    /// A 64-byte header, all integers little-endian: the magic `MMPACKDA`,
+    /// [`FORMAT_VERSION`] (u32), MR (u32), the backend's
+    /// [`key`](crate::GemmBackend::key) padded with zeros to 16 bytes, then
+    /// m, k, KC, and a checksum (u64 each). After it come the m×k values as
+    /// little-endian f64, the panels and then the row-major tail, exactly as
+    /// they're held in memory. The checksum covers the header before it and
+    /// every value.
+    ///
+    /// ```
+    /// use matmul::{PackedA, detected_backend};
+    ///
+    /// let (m, k) = (20, 12);
+    /// let packed = PackedA::pack(&vec![0.5; m * k], m, k, detected_backend())?;
+    /// let mut file = Vec::new();
+    /// packed.write_to(&mut file)?;
+    /// assert_eq!(file.len(), 64 + 8 * m * k);
+    /// let loaded = PackedA::read_from(file.as_slice())?;
+    /// assert_eq!(loaded.dims(), (m, k));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&self.header())?;
+        let mut bytes = Vec::with_capacity(8 * WRITE_CHUNK.min(self.m * self.k));
+        for values in self
+            .panels
+            .chunks(WRITE_CHUNK)
+            .chain(self.tail.chunks(WRITE_CHUNK))
+        {
+            bytes.clear();
+            bytes.extend(values.iter().flat_map(|x| x.to_le_bytes()));
+            writer.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// A copy that owns its panels, for one [`from_bytes`](PackedA::from_bytes)
+    /// borrowed.
+    pub fn into_owned(self) -> PackedA<'static> {
+        PackedA {
+            panels: Cow::Owned(self.panels.into_owned()),
+            tail: Cow::Owned(self.tail.into_owned()),
+            ..self
+        }
+    }
+
+    /// [`write_to`](PackedA::write_to)'s header, checksum included.
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0; HEADER_LEN];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(self.backend.tile().0 as u32).to_le_bytes());
+        let key = self.backend.key().as_bytes();
+        header[16..16 + key.len()].copy_from_slice(key);
+        let (kc, _) = self.backend.block_sizes(self.k);
+        for (at, value) in [(32, self.m), (40, self.k), (48, kc)] {
+            header[at..at + 8].copy_from_slice(&(value as u64).to_le_bytes());
+        }
+        let checksum = checksum(&header, [&self.panels[..], &self.tail[..]]);
+        header[56..].copy_from_slice(&checksum.to_le_bytes());
+        header
+    }
+}
+
+impl PackedA<'static> {
+    /// Reads a packed A [`write_to`](PackedA::write_to) wrote, copying the
+    /// values into memory of its own.
+    ///
+    /// # Errors
+    ///
+    /// - [`PackedFileError::Io`] if reading failed
+    /// - [`PackedFileError::Format`] if it isn't a packed A, or ends early
+    /// - [`PackedFileError::Version`] for another version of the format
+    /// - [`PackedFileError::Checksum`] if the header or values changed
+    ///   since they were written
+    /// - [`PackedFileError::Layout`] if it was packed for a backend or KC
+    ///   other than the one this machine selects
+    pub fn read_from(mut reader: impl Read) -> Result<PackedA<'static>, PackedFileError> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header).map_err(truncated)?;
+        let len = values_len(&header)?;
+        // As far as the header says, and no further: a damaged length runs
+        // into the end of the file rather than allocating it up front
+        let mut bytes = Vec::new();
+        reader.take(8 * len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != 8 * len {
+            return Err(PackedFileError::Format("the values end early"));
+        }
+        let mut values: Vec<f64> = bytes
+            .chunks_exact(8)
+            .map(|x| f64::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+        drop(bytes);
+
+        let (backend, m, k, m_main) = check_header(&header, &values)?;
+        let tail = values.split_off(m_main * k);
+        Ok(PackedA {
+            backend,
+            m,
+            k,
+            m_main,
+            panels: Cow::Owned(values),
+            tail: Cow::Owned(tail),
+        })
+    }
+}
+
+impl<'a> PackedA<'a> {
+    /// A packed A [`write_to`](PackedA::write_to) wrote, using the values
+    /// where they are in `bytes` (a memory-mapped file, say) rather than
+    /// copying them. The checksum is still checked, which reads them once.
+    ///
+    /// `bytes` has to be all of what `write_to` wrote, starting at an 8-byte
+    /// aligned address (as a mapping of the file is); the values are read in
+    /// place, so only on a little-endian CPU. [`read_from`](PackedA::read_from)
+    /// has neither restriction.
+    ///
+    /// # Errors
+    ///
+    /// As [`read_from`](PackedA::read_from), with
+    /// [`PackedFileError::Format`] also if `bytes` is misaligned, has bytes
+    /// past the values, or this CPU is big-endian.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<PackedA<'a>, PackedFileError> {
+        let Some((header, bytes)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+            return Err(PackedFileError::Format("the header ends early"));
+        };
+        let len = values_len(header)?;
+        if bytes.len() != 8 * len {
+            return Err(PackedFileError::Format(if bytes.len() < 8 * len {
+                "the values end early"
+            } else {
+                "bytes past the values"
+            }));
+        }
+        if cfg!(target_endian = "big") {
+            return Err(PackedFileError::Format(
+                "the values are little-endian and can't be used in place here; use read_from",
+            ));
+        }
+        // Safety: every bit pattern is a valid f64, and on a little-endian
+        // CPU the bytes are the values `write_to` wrote
+        let (before, values, _) = unsafe { bytes.align_to::<f64>() };
+        if !before.is_empty() {
+            return Err(PackedFileError::Format("not 8-byte aligned"));
+        }
+
+        let (backend, m, k, m_main) = check_header(header, values)?;
+        let (panels, tail) = values.split_at(m_main * k);
+        Ok(PackedA {
+            backend,
+            m,
+            k,
+            m_main,
+            panels: Cow::Borrowed(panels),
+            tail: Cow::Borrowed(tail),
+        })
+    }
+}
+
+/// Why a packed A couldn't be read back.
+#[derive(Debug)]
+pub enum PackedFileError {
+    Io(io::Error),
+    /// Not a packed A, or cut short
+    Format(&'static str),
+    /// Written in a format version this build doesn't read
+    Version(u32),
+    /// The header or values don't match the checksum written with them
+    Checksum,
+    /// Packed for another backend or blocking than this machine's
+    Layout {
+        /// What it was packed for, if this build knows the backend
+        packed: Option<Backend>,
+        kc: usize,
+        /// [`detected_backend`](crate::detected_backend), and its KC for
+        /// this k
+        selected: Backend,
+        selected_kc: usize,
+    },
+}
+
+impl fmt::Display for PackedFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackedFileError::Io(e) => write!(f, "{}", e),
+            PackedFileError::Format(reason) => write!(f, "not a valid packed A: {}", reason),
+            PackedFileError::Version(version) => write!(
+                f,
+                "packed A in format version {}, but this build reads version {}",
+                version, FORMAT_VERSION
+            ),
+            PackedFileError::Checksum => {
+                write!(f, "the packed A doesn't match its checksum; it's damaged")
+            }
+            PackedFileError::Layout {
+                packed,
+                kc,
+                selected,
+                selected_kc,
+            } => {
+                match packed {
+                    Some(packed) => write!(f, "packed for {} with KC {}", packed, kc)?,
+                    None => write!(f, "packed for a backend this build doesn't know")?,
+                }
+                write!(
+                    f,
+                    ", but this machine runs {} with KC {}; pack A again",
+                    selected, selected_kc
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PackedFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackedFileError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PackedFileError {
+    fn from(e: io::Error) -> Self {
+        PackedFileError::Io(e)
+    }
+}
+
+/// A file that ends inside the header is a format problem, not an I/O one.
+fn truncated(e: io::Error) -> PackedFileError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        PackedFileError::Format("the header ends early")
+    } else {
+        PackedFileError::Io(e)
+    }
+}
+
+/// The little-endian u64 at `at` in the header.
+fn header_u64(header: &[u8; HEADER_LEN], at: usize) -> u64 {
+    u64::from_le_bytes(header[at..at + 8].try_into().unwrap())
+}
+
+/// The number of values after a header, once its magic and version check
+/// out.
+fn values_len(header: &[u8; HEADER_LEN]) -> Result<usize, PackedFileError> {
+    if &header[..8] != MAGIC {
+        return Err(PackedFileError::Format("no packed-A magic at the start"));
+    }
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(PackedFileError::Version(version));
+    }
+    let (m, k) = (header_u64(header, 32), header_u64(header, 40));
+    m.checked_mul(k)
+        .filter(|&len| {
+            len.checked_mul(8)
+                .is_some_and(|bytes| bytes <= isize::MAX as u64)
+        })
+        .map(|len| len as usize)
+        .ok_or(PackedFileError::Format("m×k is too large"))
+}
+
+/// Checks a header against its values and this machine, returning the
+/// backend, m, k, and the rows in panels.
+fn check_header(
+    header: &[u8; HEADER_LEN],
+    values: &[f64],
+) -> Result<(Backend, usize, usize, usize), PackedFileError> {
+    if checksum(header, [values]) != header_u64(header, 56) {
+        return Err(PackedFileError::Checksum);
+    }
+    let key = header[16..32].split(|&byte| byte == 0).next().unwrap();
+    let packed = Backend::ALL
+        .into_iter()
+        .find(|backend| backend != &Backend::ScalarIkj && backend.key().as_bytes() == key);
+    let mr = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    // values_len checked that m×k fits
+    let (m, k) = (
+        header_u64(header, 32) as usize,
+        header_u64(header, 40) as usize,
+    );
+    let kc = header_u64(header, 48) as usize;
+
+    let selected = crate::detected_backend();
+    let (selected_kc, _) = selected.block_sizes(k);
+    match packed {
+        Some(backend) if backend == selected && mr == backend.tile().0 && kc == selected_kc => {
+            Ok((backend, m, k, main_rows(backend, m, k)))
+        }
+        _ => Err(PackedFileError::Layout {
+            packed,
+            kc,
+            selected,
+            selected_kc,
+        }),
+    }
+}
+
+/// A hash of the header's first 56 bytes and then the values, 64 bits at a
+/// time: FNV-1a over words, with a rotation each step so a change in any bit
+/// reaches every bit of the result.
+fn checksum<'v>(header: &[u8; HEADER_LEN], values: impl IntoIterator<Item = &'v [f64]>) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    header[..56]
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .chain(values.into_iter().flatten().map(|x| x.to_bits()))
+        .fold(OFFSET, |hash, word| {
+            (hash ^ word).wrapping_mul(PRIME).rotate_left(29)
+        })
+}
+
+/// C += A·B with A already packed: the `multiply_with_backend` work minus
+/// packing A.
+///
+/// `m` and `k` must be the ones A was packed with. With `k_used` set, only
+/// A's first `k_used` columns take part, as if A had been packed m×`k_used`;
+/// B is then `k_used`×n rather than k×n. C is m×n; all row-major.
+///
+/// # Errors
+///
+/// - [`MatMulError::PackedShape`] if `packed_a` holds a different m×k, or
+///   `k_used` is more than k
+/// - [`MatMulError::Backend`] if this CPU can't run the backend `packed_a`
+///   was packed for
+/// - [`MatMulError::WrongLength`] or [`MatMulError::Overflow`] if B or C
+///   doesn't match n
+/// - [`MatMulError::Overlap`] if C overlaps B
+///
+/// C is untouched on error.
+pub fn multiply_prepacked_a(
+    packed_a: &PackedA<'_>,
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    k_used: Option<usize>,
+) -> Result<(), MatMulError> {
+    let k_used = k_used.unwrap_or(k);
+    if (m, k) != packed_a.dims() || k_used > k {
+        return Err(MatMulError::PackedShape {
+            packed: packed_a.dims(),
+            requested: (m, k.max(k_used)),
+        });
+    }
+    check_backend(packed_a.backend)?;
+    check_operands([("B", b.len(), k_used, n), ("C", c.len(), m, n)])?;
+    check_no_overlap(&[], b, c)?;
+    if m == 0 || n == 0 || k_used == 0 {
+        return Ok(());
+    }
+
+    // Safety: the backend is available and the shapes are checked
+    if low_rank::uses_low_rank(packed_a.backend, k_used) {
+        // An unpacked multiply this shallow reads A as it is, so rebuild
+        // that unless the whole of A is already row-major in the tail
+        let prefix;
+        let a: &[f64] = if k_used == k {
+            &packed_a.tail
+        } else {
+            prefix = packed_a.prefix(k_used);
+            &prefix
+        };
+        unsafe { low_rank::low_rank_rows(packed_a.backend, a, b, c, n, k_used, 0..m) };
+        return Ok(());
+    }
+    let a = packed_a;
+    unsafe {
+        match packed_a.backend {
+            Backend::Scalar => multiply_packed::<4, 4>(a, b, c, n, k_used, kernel_4x4_scalar),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_4x4 => multiply_packed::<4, 4>(a, b, c, n, k_used, kernel_4x4_avx2),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_12x4 => multiply_packed::<12, 4>(a, b, c, n, k_used, kernel_12x4_avx2),
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx512_8x8 => multiply_packed::<8, 8>(a, b, c, n, k_used, kernel_8x8_avx512),
+            #[cfg(not(target_arch = "x86_64"))]
+            Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8 => {
+                unreachable!("{} only exists on x86_64", packed_a.backend)
+            }
+            Backend::ScalarIkj => unreachable!("PackedA::pack rejects {}", packed_a.backend),
+        }
+    }
+    Ok(())
+}
+
+/// Rows of an m×k A that go in whole tiles: none when the low-rank path
+/// reads A as it is, so it all stays row-major.
+fn main_rows(backend: Backend, m: usize, k: usize) -> usize {
+    if low_rank::uses_low_rank(backend, k) {
+        0
+    } else {
+        m / backend.tile().0 * backend.tile().0
+    }
+}
+
+/// Backends a `PackedA` can be made for and used with here.
+fn check_backend(backend: Backend) -> Result<(), MatMulError> {
+    let reason = if backend == Backend::ScalarIkj {
+        "has no packed layout; pick a blocked backend"
+    } else if !backend.is_available() {
+        "not available on this CPU"
+    } else {
+        return Ok(());
+    };
+    Err(MatMulError::Backend { backend, reason })
+}
+
+/// The whole-tile rows of A, one KC-deep block after another.
+fn pack_panels<const MR: usize>(a: &[f64], m_main: usize, k: usize, backend: Backend) -> Vec<f64> {
+    let mut panels = vec![0.0; m_main * k];
+    if k == 0 {
+        return panels;
+    }
+    let (kc, _) = backend.block_sizes(k);
+    for kk in (0..k).step_by(kc) {
+        let k_block = (kk + kc).min(k) - kk;
+        pack_a_panel::<MR>(a, &mut panels[kk * m_main..], 0, kk, m_main, k_block, k);
+    }
+    panels
+}
+
+/// An MR×NR microkernel: `(a_pack, b_pack, c, c_len, k, ldc)`, as the
+/// blocked drivers call it.
+pub(crate) type Kernel = unsafe fn(*const f64, *const f64, *mut f64, usize, usize, usize);
+
+/// The scalar driver's kernel behind the SIMD kernels' signature.
+///
+/// # Safety
+///
+/// `a_pack` and `b_pack` hold `4 * k` values, and `c` points to `c_len`.
+/// Indexing the slice checks the tile fits in them.
+pub(crate) unsafe fn kernel_4x4_scalar(
+    a_pack: *const f64,
+    b_pack: *const f64,
+    c: *mut f64,
+    c_len: usize,
+    k: usize,
+    ldc: usize,
+) {
+    unsafe {
+        gemm_scalar::kernel_4x4(
+            std::slice::from_raw_parts(a_pack, 4 * k),
+            std::slice::from_raw_parts(b_pack, 4 * k),
+            std::slice::from_raw_parts_mut(c, c_len),
+            ldc,
+            false,
+        )
+    }
+}
+
+/// The blocked driver loop over prepacked panels: same blocks, same order,
+/// same edge paths, so the same rounding.
+///
+/// Only A's first `k_used` columns are read. The blocks are the ones an
+/// unpacked multiply of that depth makes: KC deep, or all of `k_used` when
+/// it's less than KC, which then fits in the first packed block.
+///
+/// # Safety
+///
+/// `kernel` is an MR×NR kernel this CPU can run, `k_used` is at most
+/// `packed`'s k, and B and C hold `k_used`×n and m×n, all nonzero.
+unsafe fn multiply_packed<const MR: usize, const NR: usize>(
+    packed: &PackedA<'_>,
+    b: &[f64],
+    c: &mut [f64],
+    n: usize,
+    k_used: usize,
+    kernel: Kernel,
+) {
+    let (m, k, m_main) = (packed.m, packed.k, packed.m_main);
+    let n_main = n / NR * NR;
+    let (kc, mc) = packed.backend.block_sizes(k);
+
+    let mut b_pack = vec![0.0; NR * kc];
+    for kk in (0..k_used).step_by(kc) {
+        // Tiles in the block are as deep as it was packed; the kernel reads
+        // the first k_block steps of each
+        let packed_block = (kk + kc).min(k) - kk;
+        let k_block = (kk + kc).min(k_used) - kk;
+        let block = &packed.panels[kk * m_main..kk * m_main + m_main * packed_block];
+
+        for ii in (0..m_main).step_by(mc) {
+            let m_block = (ii + mc).min(m_main) - ii;
+
+            for j in (0..n_main).step_by(NR) {
+                // B is row-major here, not transposed: the same values as
+                // `pack_b_panel` reads from Bᵀ, without the copy of B
+                for p in 0..k_block {
+                    let row = (kk + p) * n + j;
+                    b_pack[p * NR..(p + 1) * NR].copy_from_slice(&b[row..row + NR]);
+                }
+
+                for i in (0..m_block).step_by(MR) {
+                    let at = (ii + i) * n + j;
+                    unsafe {
+                        kernel(
+                            block.as_ptr().add((ii + i) * packed_block),
+                            b_pack.as_ptr(),
+                            c.as_mut_ptr().add(at),
+                            c.len() - at,
+                            k_block,
+                            n,
+                        )
+                    };
+                }
+            }
+        }
+    }
+
+    // The drivers' edge paths: leftover rows from the row-major tail, then
+    // leftover columns of the tiled rows, reading A from the panels
+    let fused = packed.backend != Backend::Scalar;
+    let tail = |i: usize, p: usize| packed.tail[(i - m_main) * k + p];
+    let panels = |i: usize, p: usize| packed.get(MR, i, p, kc);
+    let b = |p: usize, j: usize| b[p * n + j];
+    unsafe {
+        multiply_edge_with(fused, tail, b, c, m_main..m, 0..n, n, k_used);
+        multiply_edge_with(fused, panels, b, c, 0..m_main, n_main..n, n, k_used);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::{available_backends, multiply_with_backend};
+
+    /// Every backend with a packed layout, on shapes with edge rows and
+    /// columns, and (the last) more than one MC block and KC block.
+    fn check_matches_driver(shapes: &[(usize, usize, usize)]) {
+        for backend in available_backends() {
+            if backend == Backend::ScalarIkj {
+                continue;
+            }
+            for &(m, n, k) in shapes {
+                let a = random(m, k, 1);
+                let b = random(k, n, 2);
+                let c_initial = random(m, n, 3);
+                let mut want = c_initial.clone();
+                multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+
+                let packed = PackedA::pack(&a, m, k, backend).unwrap();
+                // Twice from one packing: the panels aren't consumed
+                for _ in 0..2 {
+                    let mut c = c_initial.clone();
+                    multiply_prepacked_a(&packed, &b, &mut c, m, n, k, None).unwrap();
+                    assert_eq!(c, want, "{} on {}x{}x{}", backend, m, n, k);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_driver() {
+        check_matches_driver(&[(1, 1, 1), (3, 5, 2), (13, 9, 7), (24, 16, 33), (0, 4, 4)]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; tests/miri.rs covers it")]
+    fn test_matches_driver_across_blocks() {
+        check_matches_driver(&[(261, 19, 600), (256, 24, 512)]);
+    }
+
+    /// One packing of A against multiplies on its first `k_used` columns,
+    /// each compared with an unpacked multiply of the truncated A and B.
+    fn check_prefixes(m: usize, n: usize, k: usize, k_used: &[usize]) {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let c_initial = random(m, n, 3);
+        for backend in available_backends() {
+            if backend == Backend::ScalarIkj {
+                continue;
+            }
+            let packed = PackedA::pack(&a, m, k, backend).unwrap();
+            for &k_used in k_used {
+                let a_used: Vec<f64> = a
+                    .chunks_exact(k)
+                    .flat_map(|row| &row[..k_used])
+                    .copied()
+                    .collect();
+                let b_used = &b[..k_used * n];
+                let mut want = c_initial.clone();
+                multiply_with_backend(backend, &a_used, b_used, &mut want, m, n, k_used);
+
+                let mut c = c_initial.clone();
+                multiply_prepacked_a(&packed, b_used, &mut c, m, n, k, Some(k_used)).unwrap();
+                assert_eq!(c, want, "{} on {}x{}x{} using {}", backend, m, n, k, k_used);
+            }
+        }
+    }
+
+    #[test]
+    fn test_k_used() {
+        // All of A row-major in the tail; panels and tail, down to the
+        // low-rank path; nothing at all
+        check_prefixes(13, 9, 4, &[1, 3, 4]);
+        check_prefixes(13, 9, 7, &[0, 2, 5, 7]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri")]
+    fn test_k_used_across_blocks() {
+        check_prefixes(29, 11, 1024, &[1, 63, 512, 1024]);
+    }
+
+    #[test]
+    fn test_k_used_past_k() {
+        let a = random(8, 6, 1);
+        let packed = PackedA::pack(&a, 8, 6, Backend::Scalar).unwrap();
+        let b = random(7, 4, 2);
+        let mut c = vec![0.0; 8 * 4];
+        assert_eq!(
+            multiply_prepacked_a(&packed, &b, &mut c, 8, 4, 6, Some(7)),
+            Err(MatMulError::PackedShape {
+                packed: (8, 6),
+                requested: (8, 7),
+            })
+        );
+        // B has the rows of the prefix, not of A
+        assert!(matches!(
+            multiply_prepacked_a(&packed, &b, &mut c, 8, 4, 6, Some(6)),
+            Err(MatMulError::WrongLength { operand: "B", .. })
+        ));
+        assert!(c.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let a = random(8, 6, 1);
+        let packed = PackedA::pack(&a, 8, 6, Backend::Scalar).unwrap();
+        let b = random(6, 4, 2);
+        let mut c = vec![0.0; 6 * 4];
+        assert_eq!(
+            multiply_prepacked_a(&packed, &b, &mut c, 6, 4, 8, None),
+            Err(MatMulError::PackedShape {
+                packed: (8, 6),
+                requested: (6, 8),
+            })
+        );
+        let mut c = vec![0.0; 8 * 4];
+        assert!(matches!(
+            multiply_prepacked_a(&packed, &b[1..], &mut c, 8, 4, 6, None),
+            Err(MatMulError::WrongLength { operand: "B", .. })
+        ));
+        assert!(c.iter().all(|&x| x == 0.0));
+    }
+
+    /// `file`'s bytes in memory starting on an 8-byte boundary, as a
+    /// mapping of the file would be.
+    fn aligned(file: &[u8]) -> Vec<f64> {
+        let mut buffer = vec![0.0; file.len().div_ceil(8)];
+        // Safety: any bytes are a valid f64
+        unsafe { buffer.align_to_mut::<u8>().1[..file.len()].copy_from_slice(file) };
+        buffer
+    }
+
+    /// Saved and loaded both ways, on the backend this machine selects:
+    /// every value comes back, and multiplies agree bit for bit. The shapes
+    /// are low-rank, have edge rows, and (the last) several KC blocks.
+    #[test]
+    #[cfg_attr(miri, ignore = "the checksum over the deep shape is slow")]
+    fn test_round_trip() {
+        let backend = crate::detected_backend();
+        for (m, n, k) in [(13, 9, 3), (29, 11, 37), (0, 4, 5), (21, 10, 700)] {
+            let (a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+            let packed = PackedA::pack(&a, m, k, backend).unwrap();
+            let mut want = c0.clone();
+            multiply_prepacked_a(&packed, &b, &mut want, m, n, k, None).unwrap();
+
+            let mut file = Vec::new();
+            packed.write_to(&mut file).unwrap();
+            assert_eq!(file.len(), HEADER_LEN + 8 * m * k);
+            let buffer = aligned(&file);
+            let bytes = &unsafe { buffer.align_to::<u8>() }.1[..file.len()];
+            let read = PackedA::read_from(file.as_slice()).unwrap();
+            let mapped = PackedA::from_bytes(bytes).unwrap();
+            assert!(matches!(mapped.panels, Cow::Borrowed(_)));
+            for loaded in [read, mapped] {
+                assert_eq!((loaded.backend(), loaded.dims()), (backend, (m, k)));
+                assert_eq!(loaded.prefix(k), a);
+                let mut c = c0.clone();
+                multiply_prepacked_a(&loaded, &b, &mut c, m, n, k, None).unwrap();
+                assert_eq!(c, want, "{}x{}x{}", m, n, k);
+            }
+        }
+    }
+
+    /// Damage anywhere in the header or the values is caught before
+    /// anything else about it is trusted.
+    #[test]
+    fn test_damaged_file() {
+        let (m, k) = (13, 7);
+        let packed = PackedA::pack(&random(m, k, 1), m, k, crate::detected_backend()).unwrap();
+        let mut file = Vec::new();
+        packed.write_to(&mut file).unwrap();
+        let read = |file: &[u8]| PackedA::read_from(file).unwrap_err();
+
+        // m, KC, the backend key, a bit of a value
+        for (at, bit) in [(32, 0), (48, 3), (17, 1), (HEADER_LEN + 62, 4)] {
+            let mut damaged = file.clone();
+            damaged[at] ^= 1 << bit;
+            assert!(
+                matches!(read(&damaged), PackedFileError::Checksum),
+                "byte {}",
+                at
+            );
+        }
+        let mut damaged = file.clone();
+        damaged[0] = b'X';
+        assert!(matches!(read(&damaged), PackedFileError::Format(_)));
+        let mut damaged = file.clone();
+        damaged[8] = 2;
+        assert!(matches!(read(&damaged), PackedFileError::Version(2)));
+        // A length far past the end of the file
+        let mut damaged = file.clone();
+        damaged[39] = 0x10;
+        assert!(matches!(read(&damaged), PackedFileError::Format(_)));
+        assert!(matches!(
+            read(&file[..file.len() - 1]),
+            PackedFileError::Format(_)
+        ));
+        assert!(matches!(read(&file[..20]), PackedFileError::Format(_)));
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let (m, k) = (9, 6);
+        let packed = PackedA::pack(&random(m, k, 1), m, k, crate::detected_backend()).unwrap();
+        let mut file = Vec::new();
+        packed.write_to(&mut file).unwrap();
+        file.extend([0; 8]);
+        let buffer = aligned(&file);
+        let bytes = unsafe { buffer.align_to::<u8>() }.1;
+        let format = |bytes: &[u8]| match PackedA::from_bytes(bytes) {
+            Err(PackedFileError::Format(reason)) => reason,
+            other => panic!("{:?}", other),
+        };
+        let end = file.len() - 8;
+        assert_eq!(format(&bytes[..end + 8]), "bytes past the values");
+        assert_eq!(format(&bytes[..end - 8]), "the values end early");
+        assert_eq!(format(&bytes[..HEADER_LEN - 1]), "the header ends early");
+        // The same file one byte further on
+        let mut shifted = aligned(&[&[0], &file[..end]].concat());
+        let shifted = &unsafe { shifted.align_to_mut::<u8>() }.1[1..end + 1];
+        assert_eq!(format(shifted), "not 8-byte aligned");
+        assert!(PackedA::from_bytes(&bytes[..end]).is_ok());
+    }
+
+    /// A file packed for a backend other than the selected one loads
+    /// nowhere, even where that backend runs.
+    #[test]
+    fn test_other_backend() {
+        let (m, k) = (12, 10);
+        let selected = crate::detected_backend();
+        let (selected_kc, _) = selected.block_sizes(k);
+        for backend in available_backends() {
+            if backend == Backend::ScalarIkj || backend == selected {
+                continue;
+            }
+            let packed = PackedA::pack(&random(m, k, 1), m, k, backend).unwrap();
+            let mut file = Vec::new();
+            packed.write_to(&mut file).unwrap();
+            let error = PackedA::read_from(file.as_slice()).unwrap_err();
+            assert!(
+                matches!(
+                    error,
+                    PackedFileError::Layout { packed: Some(packed), selected: s, selected_kc: kc, .. }
+                        if packed == backend && s == selected && kc == selected_kc
+                ),
+                "{:?}",
+                error
+            );
+            assert!(error.to_string().ends_with("pack A again"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_backend_errors() {
+        let a = random(4, 4, 1);
+        assert!(matches!(
+            PackedA::pack(&a, 4, 4, Backend::ScalarIkj),
+            Err(MatMulError::Backend {
+                backend: Backend::ScalarIkj,
+                ..
+            })
+        ));
+        for backend in Backend::ALL {
+            if !backend.is_available() {
+                assert!(matches!(
+                    PackedA::pack(&a, 4, 4, backend),
+                    Err(MatMulError::Backend { .. })
+                ));
+            }
+        }
+        assert!(matches!(
+            PackedA::pack(&a, 4, 3, Backend::Scalar),
+            Err(MatMulError::WrongLength { operand: "A", .. })
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+//! A multiply planned once for a fixed shape and run many times.
+//!
+//! Each call to [`multiply`](crate::multiply) or
+//! [`MatMul::compute_into`](crate::MatMul::compute_into) works out its thread
+//! count from the shape, splits C into row bands, and sizes (or allocates)
+//! its packing buffers. For a shape that never changes, [`MatmulPlan`] does
+//! all of that in [`MatmulPlan::new`], so [`MatmulPlan::execute`] only checks
+//! the slice lengths and multiplies.
+//!
+//! [`plan`] makes the same decisions for
+//! [`multiply_parallel`](crate::multiply_parallel) without acting on them,
+//! for a caller deciding how to schedule a job.
+
+use crate::builder::{MatMul, MatMulBuilder};
+use crate::threaded::parallel_rows::arithmetic_intensity;
+use crate::threaded::schedule::{CHUNK_ROWS, static_bands};
+use crate::workspace::Workspace;
+use crate::{Backend, MatMulError, threaded};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Row bands fixed ahead of time, one thread each, each with its own
+/// workspace sized for it.
+#[derive(Debug)]
+pub(crate) struct Partition {
+    /// The thread count the bands were split for
+    threads: usize,
+    /// `(row_start, row_end)`, in order
+    bands: Vec<(usize, usize)>,
+    workspaces: Vec<Mutex<Workspace>>,
+}
+
+impl Partition {
+    /// The thread count to hand the static schedule, which splits rows into
+    /// exactly these bands for it.
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The workspace of the band starting at row `start`. Each band runs
+    /// once per multiply, so the lock is never contended.
+    pub(crate) fn workspace(&self, start: usize) -> MutexGuard<'_, Workspace> {
+        let band = self
+            .bands
+            .binary_search_by_key(&start, |&(start, _)| start)
+            .expect("the static schedule splits rows as planned");
+        // A band that panicked last time left only scratch contents behind
+        self.workspaces[band]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// One m×n×k multiply with everything that depends only on the shape
+/// decided up front: the backend and block sizes, the thread count and row
+/// bands, and packing buffers already allocated for each band.
+///
+/// Built from the same settings as a [`MatMul`], and gives the same results.
+/// Plans are `Send`, so one can be handed to a worker thread and reused
+/// there.
+///
+/// ```
+/// use matmul::{MatMul, MatmulPlan};
+///
+/// let (m, n, k) = (96, 64, 128);
+/// let mut plan = MatmulPlan::new(m, n, k, &MatMul::new().threads(4))?;
+/// let a = vec![1.0; m * k];
+/// let b = vec![1.0; k * n];
+/// let mut c = vec![0.0; m * n];
+/// for _ in 0..3 {
+///     plan.execute(&a, &b, &mut c)?;
+/// }
+/// assert!(c.iter().all(|&x| x == 3.0 * k as f64));
+/// # Ok::<(), matmul::MatMulError>(())
+/// ```
+#[derive(Debug)]
+pub struct MatmulPlan {
+    matmul: MatMul,
+    m: usize,
+    n: usize,
+    k: usize,
+    /// `None` for a single thread, which uses `matmul`'s own workspace
+    partition: Option<Partition>,
+}
+
+impl MatmulPlan {
+    /// Plans C = alpha·A·B + beta·C for A m×k and B k×n with `config`'s
+    /// settings, allocating every buffer the multiply will use.
+    ///
+    /// # Errors
+    ///
+    /// - Whatever [`MatMulBuilder::build`] rejects
+    /// - [`MatMulError::Overflow`] if m·k, k·n, or m·n overflows `usize`
+    pub fn new(
+        m: usize,
+        n: usize,
+        k: usize,
+        config: &MatMulBuilder,
+    ) -> Result<MatmulPlan, MatMulError> {
+        for (operand, rows, cols) in [("A", m, k), ("B", k, n), ("C", m, n)] {
+            if rows.checked_mul(cols).is_none() {
+                return Err(MatMulError::Overflow {
+                    operand,
+                    rows,
+                    cols,
+                });
+            }
+        }
+        let mut matmul = config.clone().build()?;
+        let backend = matmul.backend();
+        let blocking = (matmul.kc, matmul.mc);
+        // Whether execute gets as far as multiplying
+        let multiplies = m > 0 && n > 0 && k > 0 && matmul.alpha != 0.0;
+
+        let threads = matmul.thread_count(m, n, k);
+        let partition = if threads > 1 {
+            let (mr, _) = backend.tile();
+            let bands = static_bands(m, mr, threads);
+            // The bands' shared Bᵀ, in the MatMul's workspace; theirs hold
+            // just panels
+            let shared = matmul.shares_transpose(k, n);
+            if multiplies && shared {
+                matmul.workspace.reserve_transpose(k, n);
+            }
+            let workspaces = bands
+                .iter()
+                .map(|&(start, end)| {
+                    let mut workspace = Workspace::new();
+                    if multiplies {
+                        workspace.lean = shared;
+                        workspace.reserve_for(backend, end - start, n, k, blocking);
+                        workspace.lean = false;
+                    }
+                    Mutex::new(workspace)
+                })
+                .collect();
+            Some(Partition {
+                threads,
+                bands,
+                workspaces,
+            })
+        } else {
+            if multiplies {
+                matmul.workspace.reserve_for(backend, m, n, k, blocking);
+            }
+            None
+        };
+        if multiplies && matmul.alpha != 1.0 {
+            matmul.product.reserve_exact(m * n);
+        }
+
+        Ok(MatmulPlan {
+            matmul,
+            m,
+            n,
+            k,
+            partition,
+        })
+    }
+
+    /// C = alpha·A·B + beta·C for the planned shape.
+    ///
+    /// # Errors
+    ///
+    /// [`MatMulError::WrongLength`] if a slice doesn't match the planned
+    /// shape, [`MatMulError::Overlap`] if C overlaps A or B. C is untouched
+    /// on error.
+    pub fn execute(&mut self, a: &[f64], b: &[f64], c: &mut [f64]) -> Result<(), MatMulError> {
+        let (m, n, k) = (self.m, self.n, self.k);
+        let beta = self.matmul.beta;
+        self.matmul
+            .run(a, b, c, m, n, k, beta, self.partition.as_ref())
+    }
+
+    /// `(m, n, k)`: the planned shape.
+    pub fn dims(&self) -> (usize, usize, usize) {
+        (self.m, self.n, self.k)
+    }
+
+    /// The backend every execution runs on.
+    pub fn backend(&self) -> Backend {
+        self.matmul.backend()
+    }
+
+    /// Threads each execution uses (one per row band).
+    pub fn threads(&self) -> usize {
+        self.partition.as_ref().map_or(1, Partition::threads)
+    }
+
+    /// Heap bytes the plan holds for executing: every band's workspace, the
+    /// Bᵀ they share, and the buffer for A·B when alpha ≠ 1.
+    pub fn workspace_bytes(&self) -> usize {
+        let bands = match &self.partition {
+            Some(partition) => partition
+                .workspaces
+                .iter()
+                .map(|w| w.lock().unwrap_or_else(PoisonError::into_inner).bytes())
+                .sum(),
+            None => 0,
+        };
+        let workspaces = bands + self.matmul.workspace().bytes();
+        workspaces + self.matmul.product.capacity() * size_of::<f64>()
+    }
+}
+
+/// What [`multiply_parallel`](crate::multiply_parallel) would do for a
+/// shape, from [`plan`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecutionPlan {
+    pub backend: Backend,
+    /// Threads that would compute C, after the adaptive scale-down for
+    /// small matrices
+    pub threads: usize,
+    /// Depth of each packed panel (k block); `k` for the i-k-j loop, which
+    /// doesn't block
+    pub kc: usize,
+    /// Rows of A per packed panel; 1 for the i-k-j loop
+    pub mc: usize,
+    /// Peak heap bytes of packing buffers, across all threads: one Bᵀ,
+    /// which every chunk packs B from, and each thread's panels, sized for
+    /// one chunk of rows. 0 when nothing gets packed: the i-k-j loop, and a
+    /// k small enough for the low-rank path. A thread's
+    /// [scratch cache](crate::workspace) may already hold some of it.
+    pub workspace_bytes: usize,
+    /// `2mnk`, the floating-point operations of the multiply
+    pub flops: f64,
+    /// `flops` per byte of A, B, and C, each counted once. A multiply with
+    /// little arithmetic per byte (a small k, say) is bound by memory
+    /// bandwidth, and `threads` is capped lower for it.
+    pub intensity: f64,
+}
+
+/// What [`multiply_parallel`](crate::multiply_parallel) would do for A m×k
+/// and B k×n, given `requested_threads`, without doing it.
+///
+/// Runs the same backend detection and thread-count heuristic as the
+/// multiply itself, so the two agree, and has no side effects: nothing is
+/// allocated or spawned.
+///
+/// ```
+/// use matmul::plan;
+///
+/// let plan = plan(4096, 4096, 4096, 8);
+/// assert_eq!(plan.threads, 8);
+/// assert_eq!(plan.flops, 2.0 * 4096f64.powi(3));
+///
+/// // Too small to be worth a second thread
+/// assert_eq!(matmul::plan(64, 64, 64, 8).threads, 1);
+///
+/// // Under 8 FLOPs per byte: memory-bound, so 4 blocked bands rather than 16
+/// let thin = matmul::plan(4096, 4096, 32, 16);
+/// assert!(thin.intensity < 8.0);
+/// if thin.backend != matmul::Backend::ScalarIkj {
+///     assert_eq!(thin.threads, 4);
+/// }
+/// ```
+pub fn plan(m: usize, n: usize, k: usize, requested_threads: usize) -> ExecutionPlan {
+    plan_for(crate::parallel_backend(m, n, k), m, n, k, requested_threads)
+}
+
+/// [`plan`] for a given backend.
+fn plan_for(
+    backend: Backend,
+    m: usize,
+    n: usize,
+    k: usize,
+    requested_threads: usize,
+) -> ExecutionPlan {
+    let (kc, mc) = crate::config::block_sizes(backend, k);
+    let flops = 2.0 * m as f64 * n as f64 * k as f64;
+    let intensity = arithmetic_intensity(m, n, k);
+    if m == 0 || n == 0 || k == 0 {
+        return ExecutionPlan {
+            backend,
+            threads: 1,
+            kc,
+            mc,
+            workspace_bytes: 0,
+            flops,
+            intensity,
+        };
+    }
+
+    let threads = threaded::thread_count(backend, m, n, k, requested_threads);
+    // One thread runs all of C as a single band; more take dynamic chunks
+    let rows = if threads == 1 {
+        m
+    } else {
+        let (mr, _) = backend.tile();
+        m.min(CHUNK_ROWS.div_ceil(mr) * mr)
+    };
+    ExecutionPlan {
+        backend,
+        threads,
+        kc,
+        mc,
+        workspace_bytes: Workspace::bytes_for_bands(backend, rows, n, k, (kc, mc), false, threads),
+        flops,
+        intensity,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::{available_backends, multiply, multiply_with_backend};
+
+    fn inputs(m: usize, n: usize, k: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        (random(m, k, 1), random(k, n, 2), random(m, n, 3))
+    }
+
+    #[test]
+    fn test_matches_multiply() {
+        for (m, n, k) in [(13, 9, 5), (1, 1, 1), (25, 17, 9), (0, 3, 3)] {
+            let (a, b, c0) = inputs(m, n, k);
+            let mut want = c0.clone();
+            multiply(&a, &b, &mut want, m, n, k);
+
+            let mut plan = MatmulPlan::new(m, n, k, &MatMul::new()).unwrap();
+            assert_eq!(plan.threads(), 1);
+            let mut c = c0.clone();
+            plan.execute(&a, &b, &mut c).unwrap();
+            assert_eq!(c, want, "{}x{}x{}", m, n, k);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri; tests/miri.rs covers the bands")]
+    fn test_threaded_plan() {
+        let (m, n, k) = (515, 203, 517);
+        let (a, b, c0) = inputs(m, n, k);
+        for backend in available_backends() {
+            let mut want = c0.clone();
+            multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+
+            let config = MatMul::new().backend(backend).threads(3);
+            let mut plan = MatmulPlan::new(m, n, k, &config).unwrap();
+            assert!(plan.threads() > 1, "{}", backend);
+            let bytes = plan.workspace_bytes();
+            for _ in 0..2 {
+                let mut c = c0.clone();
+                plan.execute(&a, &b, &mut c).unwrap();
+                assert_eq!(c, want, "{}", backend);
+            }
+            // Everything was allocated up front
+            assert_eq!(plan.workspace_bytes(), bytes, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_buffers_allocated_up_front() {
+        // More than one k block, natively
+        let (m, n, k) = (30, 20, if cfg!(miri) { 9 } else { 300 });
+        let (a, b, c0) = inputs(m, n, k);
+        for alpha in [1.0, 3.0] {
+            let config = MatMul::new().backend(Backend::Scalar).alpha(alpha);
+            let mut plan = MatmulPlan::new(m, n, k, &config).unwrap();
+            let bytes = plan.workspace_bytes();
+            assert!(bytes > 0);
+            let mut c = c0.clone();
+            plan.execute(&a, &b, &mut c).unwrap();
+            assert_eq!(plan.workspace_bytes(), bytes, "alpha = {}", alpha);
+        }
+    }
+
+    #[test]
+    fn test_wrong_lengths() {
+        let (m, n, k) = (4, 3, 2);
+        let (a, b, _) = inputs(m, n, k);
+        let mut plan = MatmulPlan::new(m, n, k, &MatMul::new()).unwrap();
+        let mut c = vec![1.0; m * n];
+        assert!(matches!(
+            plan.execute(&a, &b[1..], &mut c),
+            Err(MatMulError::WrongLength { operand: "B", .. })
+        ));
+        let mut c = vec![1.0; n * m + 1];
+        assert!(matches!(
+            plan.execute(&a, &b, &mut c),
+            Err(MatMulError::WrongLength { operand: "C", .. })
+        ));
+        assert!(c.iter().all(|&x| x == 1.0));
+        assert!(matches!(
+            MatmulPlan::new(usize::MAX, 2, 2, &MatMul::new()),
+            Err(MatMulError::Overflow { operand: "A", .. })
+        ));
+    }
+
+    #[test]
+    fn test_plan_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+        let mut plan = MatmulPlan::new(8, 8, 8, &MatMul::new()).unwrap();
+        assert_send(&plan);
+        let (a, b, mut c) = inputs(8, 8, 8);
+        std::thread::spawn(move || plan.execute(&a, &b, &mut c))
+            .join()
+            .unwrap()
+            .unwrap();
+    }
+
+    /// `(backend, m, n, k, requested threads)` and the plan expected for it.
+    /// A heuristic change that moves one of these should update it here on
+    /// purpose.
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri spreads any shape across every thread")]
+    fn test_pinned_plans() {
+        let pinned = [
+            // Big enough for every thread; the 64-row chunks share one Bᵀ
+            (
+                (Backend::Scalar, 1024, 1024, 1024, 4),
+                (4, 256, 128, (1024 * 1024 + 4 * (64 * 256 + 4 * 256)) * 8),
+            ),
+            // Under 100 MFLOPs: one thread, one band of every row
+            (
+                (Backend::Scalar, 100, 100, 100, 8),
+                (1, 100, 128, (100 * 100 + 100 * 100 + 4 * 100) * 8),
+            ),
+            // Under 300 MFLOPs: two threads
+            (
+                (Backend::Scalar, 300, 400, 500, 8),
+                (2, 256, 128, (500 * 400 + 2 * (64 * 256 + 4 * 256)) * 8),
+            ),
+            // Plenty of work, but one thread per 64 rows at most
+            (
+                (Backend::Scalar, 64, 4096, 4096, 8),
+                (1, 256, 128, (4096 * 4096 + 64 * 256 + 4 * 256) * 8),
+            ),
+            // Low rank: nothing packed
+            ((Backend::Scalar, 1000, 1000, 4, 8), (1, 4, 128, 0)),
+            // The i-k-j bands don't block or pack
+            ((Backend::ScalarIkj, 1024, 1024, 1024, 4), (4, 1024, 1, 0)),
+            ((Backend::Scalar, 0, 5, 5, 4), (1, 5, 128, 0)),
+        ];
+        for ((backend, m, n, k, requested), (threads, kc, mc, workspace_bytes)) in pinned {
+            let want = ExecutionPlan {
+                backend,
+                threads,
+                kc,
+                mc,
+                workspace_bytes,
+                flops: 2.0 * (m * n * k) as f64,
+                intensity: arithmetic_intensity(m, n, k),
+            };
+            assert_eq!(plan_for(backend, m, n, k, requested), want);
+        }
+
+        // 12-row tiles round the 64-row chunks up to 72
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            plan_for(Backend::Avx2_12x4, 1024, 1024, 1024, 4),
+            ExecutionPlan {
+                backend: Backend::Avx2_12x4,
+                threads: 4,
+                kc: 256,
+                mc: 120,
+                workspace_bytes: (1024 * 1024 + 4 * (72 * 256 + 4 * 256)) * 8,
+                flops: 2.0 * 1024f64.powi(3),
+                intensity: 1024.0 / 12.0,
+            }
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "too big for Miri")]
+    fn test_plan_matches_multiply_parallel() {
+        for (m, n, k, threads) in [(100, 100, 100, 4), (300, 400, 500, 3), (1, 1, 1, 2)] {
+            let (a, b, mut c) = inputs(m, n, k);
+            let stats = crate::multiply_parallel_with_stats(&a, &b, &mut c, m, n, k, threads);
+            let plan = plan(m, n, k, threads);
+            assert_eq!(
+                (plan.backend, plan.threads, plan.kc, plan.mc),
+                (stats.backend, stats.threads_used, stats.kc, stats.mc),
+                "{}x{}x{}",
+                m,
+                n,
+                k
+            );
+        }
+    }
+}
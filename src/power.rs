@@ -0,0 +1,146 @@
+//! Integer powers of a square matrix.
+//!
+//! [`matrix_power`] squares its way up to A^p: about log₂ p squarings plus
+//! one multiply per set bit of p, instead of p − 1 multiplies. Every product
+//! overwrites its target (beta = 0), so no temporary needs zeroing first,
+//! and the same two scratch matrices and packing workspace serve every step.
+
+use crate::MatMulError;
+use crate::builder::MatMul;
+use crate::error::check_operands;
+
+/// out = A^p, for A m×m, row-major.
+///
+/// A^0 is the identity and A^1 a copy of A; beyond that it's exponentiation
+/// by squaring on the detected backend. Whatever `out` held is overwritten.
+/// Each step rounds, so for large p the result can differ in the last bits
+/// from multiplying A in p − 1 times.
+///
+/// ```
+/// use matmul::matrix_power;
+///
+/// // Walks of length 3 in a directed 3-cycle end where they started
+/// let a = [0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0];
+/// let mut out = [0.0; 9];
+/// matrix_power(&a, 3, 3, &mut out)?;
+/// assert_eq!(out, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+/// # Ok::<(), matmul::MatMulError>(())
+/// ```
+///
+/// # Errors
+///
+/// [`MatMulError::WrongLength`] if `a` or `out` doesn't hold m×m elements
+/// (which is how a non-square A shows up), [`MatMulError::Overflow`] if m·m
+/// overflows `usize`. `out` is untouched on error.
+pub fn matrix_power(a: &[f64], m: usize, p: u32, out: &mut [f64]) -> Result<(), MatMulError> {
+    check_operands([("A", a.len(), m, m), ("C", out.len(), m, m)])?;
+    match p {
+        0 => {
+            out.fill(0.0);
+            for i in 0..m {
+                out[i * m + i] = 1.0;
+            }
+            return Ok(());
+        }
+        1 => {
+            out.copy_from_slice(a);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut matmul = MatMul::new().beta(0.0).build()?;
+    // A^(2^i) for the current bit i of p, and the target of each product
+    let mut base = a.to_vec();
+    let mut scratch = vec![0.0; m * m];
+    // Whether `out` holds the product of the bits so far yet
+    let mut started = false;
+    let mut bits = p;
+    loop {
+        if bits & 1 == 1 {
+            if started {
+                matmul.compute_into(out, &base, &mut scratch, m, m, m)?;
+                out.copy_from_slice(&scratch);
+            } else {
+                out.copy_from_slice(&base);
+                started = true;
+            }
+        }
+        bits >>= 1;
+        if bits == 0 {
+            return Ok(());
+        }
+        matmul.compute_into(&base, &base, &mut scratch, m, m, m)?;
+        std::mem::swap(&mut base, &mut scratch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::{identity, random};
+    use crate::matrix::naive_ikj::matmul_naive_ikj;
+
+    /// A^p by p − 1 plain multiplies.
+    fn naive_power(a: &[f64], m: usize, p: u32) -> Vec<f64> {
+        let mut power = identity(m);
+        for _ in 0..p {
+            let mut next = vec![0.0; m * m];
+            matmul_naive_ikj(&power, a, &mut next, m, m, m);
+            power = next;
+        }
+        power
+    }
+
+    #[test]
+    fn test_adjacency_powers_exact() {
+        // Walk counts stay small integers, so every order of summing them
+        // is exact
+        let m = 7;
+        let a: Vec<f64> = random(m, m, 4)
+            .iter()
+            .map(|&x| if x > 0.2 { 1.0 } else { 0.0 })
+            .collect();
+        for p in 0..=8 {
+            let mut out = vec![f64::NAN; m * m];
+            matrix_power(&a, m, p, &mut out).unwrap();
+            assert_eq!(out, naive_power(&a, m, p), "p = {}", p);
+        }
+    }
+
+    #[test]
+    fn test_matches_repeated_multiply() {
+        let sizes: &[usize] = if cfg!(miri) { &[3] } else { &[1, 5, 13, 40] };
+        for &m in sizes {
+            // Scaled so the powers neither blow up nor vanish
+            let a: Vec<f64> = random(m, m, 1)
+                .iter()
+                .map(|x| x * 1.5 / (m as f64).sqrt())
+                .collect();
+            for p in [2, 3, 6, 13, 30] {
+                let mut out = vec![0.0; m * m];
+                matrix_power(&a, m, p, &mut out).unwrap();
+                assert_close(&naive_power(&a, m, p), &out, 1e-10, 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_and_rejected() {
+        let mut out: [f64; 0] = [];
+        matrix_power(&[], 0, 5, &mut out).unwrap();
+
+        // A 2×3 A isn't square
+        let mut out = [1.0; 4];
+        assert!(matches!(
+            matrix_power(&[0.0; 6], 2, 2, &mut out),
+            Err(MatMulError::WrongLength { operand: "A", .. })
+        ));
+        assert!(matches!(
+            matrix_power(&[0.0; 4], 2, 0, &mut [0.0; 6]),
+            Err(MatMulError::WrongLength { operand: "C", .. })
+        ));
+        assert_eq!(out, [1.0; 4]);
+    }
+}
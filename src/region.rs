@@ -0,0 +1,223 @@
+//! Computing one rectangle of C.
+//!
+//! The blocked drivers already take a row range (that's how the threaded
+//! layer splits C). [`multiply_region`] adds the column side by handing the
+//! driver just the wanted columns of B and C, packed tight, so the work
+//! scales with the region rather than with all of C.
+
+use crate::builder::run_rows;
+use crate::workspace::Workspace;
+use crate::{detected_backend, error};
+use std::ops::Range;
+
+/// C[rows, cols] += A[rows, :] · B[:, cols], leaving the rest of C alone.
+///
+/// A, B, and C are the whole m×k, k×n, and m×n matrices, row-major, as for
+/// [`multiply`](crate::multiply). Only the A rows and B columns the region
+/// needs are packed, and nothing outside the region is written. Useful for
+/// filling C in display order, or for one tile of a distributed multiply.
+///
+/// The region's kernel tiles start at its own top-left corner rather than
+/// C's, so its rounding can differ from a full multiply's, within the same
+/// error bound. With all of C as the region, the result is exactly
+/// [`multiply`](crate::multiply)'s.
+///
+/// ```
+/// use matmul::multiply_region;
+///
+/// let (m, n, k) = (4, 4, 2);
+/// let a = vec![1.0; m * k];
+/// let b = vec![1.0; k * n];
+/// let mut c = vec![0.0; m * n];
+/// multiply_region(&a, &b, &mut c, m, n, k, 1..3, 2..4);
+/// assert_eq!(c[1 * n + 2], 2.0);
+/// assert_eq!(c[0], 0.0);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k, or if `rows` isn't within
+/// `0..m` or `cols` within `0..n`.
+#[allow(clippy::too_many_arguments)]
+pub fn multiply_region(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    rows: Range<usize>,
+    cols: Range<usize>,
+) {
+    error::assert_dims(a, b, c, m, n, k);
+    assert!(
+        rows.start <= rows.end && rows.end <= m,
+        "rows {:?} outside 0..{}",
+        rows,
+        m
+    );
+    assert!(
+        cols.start <= cols.end && cols.end <= n,
+        "cols {:?} outside 0..{}",
+        cols,
+        n
+    );
+    let width = cols.len();
+    if rows.is_empty() || width == 0 || k == 0 {
+        return;
+    }
+
+    let backend = detected_backend();
+    let blocking = backend.block_sizes(usize::MAX);
+    #[cfg(feature = "tracing")]
+    crate::backend::trace_dispatch(backend, 1, rows.len(), width, k);
+    let mut workspace = Workspace::new();
+
+    if width == n {
+        // Whole rows are contiguous in C: a plain row band
+        let c_rows = &mut c[rows.start * n..rows.end * n];
+        // Safety: the detected backend is available, and the shapes checked
+        unsafe {
+            run_rows(
+                backend,
+                a,
+                b,
+                c_rows,
+                m,
+                n,
+                k,
+                Some(rows.start),
+                Some(rows.end),
+                blocking.0,
+                blocking.1,
+                &mut workspace,
+            )
+        };
+        return;
+    }
+
+    // The region's columns of B and of C, as k×width and rows×width
+    let mut b_cols = Vec::with_capacity(k * width);
+    for p in 0..k {
+        b_cols.extend_from_slice(&b[p * n + cols.start..p * n + cols.end]);
+    }
+    let mut c_region = Vec::with_capacity(rows.len() * width);
+    for i in rows.clone() {
+        c_region.extend_from_slice(&c[i * n + cols.start..i * n + cols.end]);
+    }
+    // Safety: as above, with B and C now `width` columns wide
+    unsafe {
+        run_rows(
+            backend,
+            a,
+            &b_cols,
+            &mut c_region,
+            m,
+            width,
+            k,
+            Some(rows.start),
+            Some(rows.end),
+            blocking.0,
+            blocking.1,
+            &mut workspace,
+        )
+    };
+    for (i, row) in rows.zip(c_region.chunks_exact(width)) {
+        c[i * n + cols.start..i * n + cols.end].copy_from_slice(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    /// C0 + A·B over the whole matrix, and C0 with `rows`×`cols` done by
+    /// `multiply_region`.
+    fn full_and_region(
+        (m, n, k): (usize, usize, usize),
+        rows: Range<usize>,
+        cols: Range<usize>,
+    ) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let c0 = random(m, n, 3);
+        let mut full = c0.clone();
+        multiply(&a, &b, &mut full, m, n, k);
+        let mut c = c0.clone();
+        multiply_region(&a, &b, &mut c, m, n, k, rows, cols);
+        (c0, full, c)
+    }
+
+    #[test]
+    fn test_region_matches_full_multiply() {
+        let shape = (37, 29, if cfg!(miri) { 9 } else { 300 });
+        let (m, n, _) = shape;
+        // Unaligned on both sides, a corner, one element, single row and column
+        for (rows, cols) in [
+            (5..30, 3..22),
+            (24..37, 20..29),
+            (0..1, 0..1),
+            (17..18, 0..29),
+            (0..37, 11..12),
+        ] {
+            let (c0, full, c) = full_and_region(shape, rows.clone(), cols.clone());
+            for i in 0..m {
+                for j in 0..n {
+                    let at = i * n + j;
+                    if rows.contains(&i) && cols.contains(&j) {
+                        assert_close(&full[at..=at], &c[at..=at], 1e-12, 1e-12);
+                    } else {
+                        // Outside the region: not even rewritten with a
+                        // rounded copy
+                        assert_eq!(
+                            c[at].to_bits(),
+                            c0[at].to_bits(),
+                            "{:?}x{:?}: ({}, {})",
+                            rows,
+                            cols,
+                            i,
+                            j
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_whole_matrix_is_multiply() {
+        let shape = (37, 29, 19);
+        let (m, n, _) = shape;
+        let (_, full, c) = full_and_region(shape, 0..m, 0..n);
+        assert_eq!(c, full);
+    }
+
+    #[test]
+    fn test_empty_region() {
+        let shape = (6, 5, 4);
+        for (rows, cols) in [(3..3, 0..5), (0..6, 2..2)] {
+            let (c0, _, c) = full_and_region(shape, rows, cols);
+            assert_eq!(c, c0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cols 3..6 outside 0..5")]
+    fn test_region_out_of_range() {
+        let (m, n, k) = (4, 5, 3);
+        let mut c = vec![0.0; m * n];
+        multiply_region(
+            &vec![0.0; m * k],
+            &vec![0.0; k * n],
+            &mut c,
+            m,
+            n,
+            k,
+            0..4,
+            3..6,
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Picking a backend for a shape from a cost model rather than by CPU
+//! features alone.
+//!
+//! Each built-in backend carries a [`CostModel`]
+//! ([`GemmBackend::cost_model`]): how fast its microkernel runs whole tiles,
+//! how fast its edge paths run the rows and columns outside them, and what
+//! packing A and B costs per element. [`select_backend`] predicts an m×n×k
+//! multiply's time on each available backend and picks the smallest. Large
+//! multiplies still go to the widest kernel (AVX-512 over AVX2 12×4 over
+//! 4×4); the model changes the pick where a narrower tile leaves less to the
+//! edge paths. A 16-row C is four whole 4×4 tiles, but one 12×4 tile and
+//! four edge rows, so on an AVX2 machine it runs on 4×4.
+//!
+//! The numbers are one core's, from the benchmark on an AVX-512 Xeon over
+//! square, skinny, and shallow shapes. Only their ratios decide anything,
+//! and those carry over to other x86 cores better than the absolute rates
+//! do; where they don't, [`calibrate()`](crate::calibrate()) replaces the
+//! model with measurements. `matmul --explain` prints the estimates for the
+//! benchmark's shapes, and this module's tests pin the picks for a few
+//! representative ones, so a change to the model shows up in review.
+//!
+//! The SIMD backends agree bit for bit (see [`Backend`]), so the pick changes
+//! how long a multiply takes, never its result; the model never prefers the
+//! scalar backend to a SIMD one.
+
+use crate::backend::GemmBackend;
+use crate::{Backend, detected_backend};
+
+/// Nanoseconds to pack one element of A or B, the same for every backend:
+/// packing is memory traffic, whatever the kernel.
+pub(crate) const PACK_NS: f64 = 0.6;
+
+/// How fast a blocked backend runs, for [`select_backend`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    /// GFLOPS of the microkernel on whole `tile`s, one core
+    pub kernel_gflops: f64,
+    /// GFLOPS of the edge paths, on the rows and columns outside whole tiles
+    pub edge_gflops: f64,
+    /// Nanoseconds to pack one element of A or B
+    pub pack_ns: f64,
+    /// `(mr, nr)`: the C tile one microkernel call computes. A multiply
+    /// with fewer than mr rows or nr columns runs entirely on the edge paths.
+    pub tile: (usize, usize),
+    /// Rows of A per packed panel; B's panels are packed again for each
+    pub panel_rows: usize,
+}
+
+/// A [`CostModel`]'s prediction for one shape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Predicted wall time, in seconds
+    pub seconds: f64,
+    /// Share of the FLOPs in whole tiles, on the microkernel; 1 for an
+    /// empty multiply
+    pub tile_share: f64,
+    /// The part of `seconds` spent packing
+    pub packing_seconds: f64,
+}
+
+impl CostModel {
+    /// The predicted time of an m×n×k multiply: its whole tiles at
+    /// `kernel_gflops`, the rest at `edge_gflops`, plus packing A once and
+    /// B once into Bᵀ and then once per panel of A.
+    ///
+    /// ```
+    /// use matmul::{Backend, GemmBackend};
+    ///
+    /// let model = Backend::Avx2_12x4.cost_model().unwrap();
+    /// // Twelve rows are one whole tile high, eight are all edge
+    /// assert_eq!(model.estimate(12, 64, 64).tile_share, 1.0);
+    /// assert_eq!(model.estimate(8, 64, 64).tile_share, 0.0);
+    /// ```
+    pub fn estimate(&self, m: usize, n: usize, k: usize) -> Estimate {
+        let (mr, nr) = self.tile;
+        let flops = 2.0 * m as f64 * n as f64 * k as f64;
+        let tile_flops = 2.0 * (m - m % mr) as f64 * (n - n % nr) as f64 * k as f64;
+        let panels = m.div_ceil(self.panel_rows) as f64;
+        let packed = m as f64 * k as f64 + k as f64 * n as f64 * (panels + 1.0);
+        let packing_seconds = packed * self.pack_ns * 1e-9;
+        Estimate {
+            seconds: tile_flops / (self.kernel_gflops * 1e9)
+                + (flops - tile_flops) / (self.edge_gflops * 1e9)
+                + packing_seconds,
+            tile_share: if flops == 0.0 {
+                1.0
+            } else {
+                tile_flops / flops
+            },
+            packing_seconds,
+        }
+    }
+}
+
+/// The available backend the [`CostModel`]s predict is fastest for an
+/// m×n×k multiply; what [`backend_for`](crate::backend_for) starts from
+/// without a [calibrated](mod@crate::calibrate) table.
+///
+/// ```
+/// use matmul::{Backend, detected_backend, select_backend};
+///
+/// // Big squares go to the widest kernel
+/// assert_eq!(select_backend(2048, 2048, 2048), detected_backend());
+/// // Sixteen rows are whole 4×4 tiles, where 12×4 would leave four over
+/// if detected_backend() == Backend::Avx2_12x4 {
+///     assert_eq!(select_backend(16, 4096, 256), Backend::Avx2_4x4);
+/// }
+/// ```
+pub fn select_backend(m: usize, n: usize, k: usize) -> Backend {
+    select_from(
+        Backend::ALL
+            .into_iter()
+            .filter(|backend| backend.is_available()),
+        m,
+        n,
+        k,
+    )
+    .unwrap_or_else(detected_backend)
+}
+
+/// The candidate with a model and the smallest estimate; on a tie, the
+/// later one, so candidates go slowest first (like [`Backend::ALL`]).
+pub(crate) fn select_from(
+    candidates: impl IntoIterator<Item = Backend>,
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Option<Backend> {
+    let mut best: Option<(Backend, f64)> = None;
+    for backend in candidates {
+        let Some(model) = backend.cost_model() else {
+            continue;
+        };
+        let seconds = model.estimate(m, n, k).seconds;
+        if best.is_none_or(|(_, fastest)| seconds <= fastest) {
+            best = Some((backend, seconds));
+        }
+    }
+    best.map(|(backend, _)| backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALAR: [Backend; 1] = [Backend::Scalar];
+    const AVX2: [Backend; 3] = [Backend::Scalar, Backend::Avx2_4x4, Backend::Avx2_12x4];
+    const AVX512: [Backend; 4] = [
+        Backend::Scalar,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ];
+
+    fn pick(candidates: &[Backend], (m, n, k): (usize, usize, usize)) -> Backend {
+        select_from(candidates.iter().copied(), m, n, k).unwrap()
+    }
+
+    #[test]
+    fn test_tiny() {
+        // One 4×4 tile; everything else is all edge
+        assert_eq!(pick(&AVX2, (4, 4, 4)), Backend::Avx2_4x4);
+        assert_eq!(pick(&AVX512, (4, 4, 4)), Backend::Avx2_4x4);
+        // One 8×8 tile, or four 4×4 ones; 12×4 has none
+        assert_eq!(pick(&AVX2, (8, 8, 8)), Backend::Avx2_4x4);
+        assert_eq!(pick(&AVX512, (8, 8, 8)), Backend::Avx512_8x8);
+        assert_eq!(pick(&SCALAR, (3, 3, 3)), Backend::Scalar);
+    }
+
+    #[test]
+    fn test_skinny_m() {
+        assert_eq!(pick(&AVX2, (16, 4096, 256)), Backend::Avx2_4x4);
+        assert_eq!(pick(&AVX512, (16, 4096, 256)), Backend::Avx512_8x8);
+        // One row over a 12×4 tile is cheap enough
+        assert_eq!(pick(&AVX2, (13, 4096, 256)), Backend::Avx2_12x4);
+        // Fewer rows than any tile: all edge everywhere, so the widest
+        assert_eq!(pick(&AVX512, (3, 512, 512)), Backend::Avx512_8x8);
+    }
+
+    #[test]
+    fn test_skinny_n() {
+        assert_eq!(pick(&AVX2, (4096, 16, 256)), Backend::Avx2_12x4);
+        assert_eq!(pick(&AVX512, (4096, 16, 256)), Backend::Avx512_8x8);
+        // Four columns: whole 12×4 tiles, all edge for 8×8
+        assert_eq!(pick(&AVX512, (4096, 4, 256)), Backend::Avx2_12x4);
+    }
+
+    #[test]
+    fn test_huge_square() {
+        for size in [512, 1024, 4096] {
+            assert_eq!(pick(&SCALAR, (size, size, size)), Backend::Scalar);
+            assert_eq!(pick(&AVX2, (size, size, size)), Backend::Avx2_12x4);
+            assert_eq!(pick(&AVX512, (size, size, size)), Backend::Avx512_8x8);
+        }
+    }
+
+    #[test]
+    fn test_empty_and_modelless() {
+        assert_eq!(pick(&AVX512, (0, 10, 10)), Backend::Avx512_8x8);
+        assert_eq!(pick(&AVX2, (10, 10, 0)), Backend::Avx2_12x4);
+        assert_eq!(select_from([Backend::ScalarIkj], 64, 64, 64), None);
+        assert_eq!(
+            select_from([Backend::ScalarIkj, Backend::Scalar], 64, 64, 64),
+            Some(Backend::Scalar)
+        );
+    }
+
+    #[test]
+    fn test_simd_always_beats_scalar() {
+        for shape in [
+            (1, 1, 1),
+            (3, 3, 1000),
+            (1000, 1, 3),
+            (5, 7, 9),
+            (100, 100, 1),
+        ] {
+            assert_ne!(pick(&AVX2, shape), Backend::Scalar, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn test_models_match_the_drivers() {
+        for backend in crate::available_backends() {
+            if let Some(model) = backend.cost_model() {
+                assert_eq!(model.tile, backend.tile(), "{}", backend);
+                assert_eq!(model.panel_rows, backend.block_sizes(300).1, "{}", backend);
+            }
+        }
+    }
+}
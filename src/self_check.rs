@@ -0,0 +1,242 @@
+//! Checking every multiply against the i-k-j loop, for debugging.
+//!
+//! "The optimized path gives different results in my app, but I can't
+//! reproduce it on its own" is hard to chase from outside: by the time
+//! anything looks wrong, the multiply that went wrong is long gone. With
+//! [`MATMUL_SELF_CHECK`](ENV_VAR)`=1` in the environment, or
+//! [`self_check`](crate::config::Config::self_check) set in the
+//! [config](crate::config), [`multiply`](crate::multiply),
+//! [`try_multiply`](crate::try_multiply),
+//! [`multiply_with_backend`](crate::multiply_with_backend),
+//! [`multiply_parallel`](crate::multiply_parallel), and their `_with_stats`
+//! versions keep a copy of C, run as usual, and then compute the same C with
+//! [`matmul_naive_ikj`] from that copy. An element further from the
+//! reference than rounding allows panics, naming the call, its shape, and
+//! the largest error, at the multiply that produced it.
+//!
+//! The allowance is 2·γ(k + 1)·(|C₀ᵢⱼ| + (|A|·|B|)ᵢⱼ), γ as in
+//! [`error_bound`]: each side is within γ(k + 1) of the exact value. A NaN
+//! or infinity has to come out the same as the reference's. The check
+//! costs a copy of C and two passes of the i-k-j loop per multiply, several
+//! times the multiply itself, so it's for tracking a problem down, not for
+//! leaving on.
+
+use crate::matrix::naive_ikj::matmul_naive_ikj;
+use crate::matrix::reference::error_bound;
+use std::sync::OnceLock;
+
+/// The environment variable that turns the check on when the config
+/// doesn't say: `1` for on, anything else (or unset) for off. It's read
+/// once, at the first multiply.
+pub const ENV_VAR: &str = "MATMUL_SELF_CHECK";
+
+/// Whether multiplies on this thread are checked now: the
+/// [config](crate::config)'s `self_check` if it's set, otherwise
+/// [`ENV_VAR`].
+pub fn is_enabled() -> bool {
+    static FROM_ENV: OnceLock<bool> = OnceLock::new();
+    crate::config::current()
+        .self_check
+        .unwrap_or_else(|| *FROM_ENV.get_or_init(|| parse(std::env::var(ENV_VAR).ok())))
+}
+
+/// [`ENV_VAR`]'s value as a switch.
+fn parse(value: Option<String>) -> bool {
+    value.is_some_and(|value| value.trim() == "1")
+}
+
+/// Runs `run` on C, and with the check [on](is_enabled), checks the C it
+/// leaves against the reference computed from C's previous contents. `run`
+/// checks the shapes, so the check only starts once it has returned.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn checked<R>(
+    what: &str,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    run: impl FnOnce(&mut [f64]) -> R,
+) -> R {
+    let before = is_enabled().then(|| c.to_vec());
+    let result = run(c);
+    if let Some(before) = before {
+        verify(what, a, b, &before, c, m, n, k);
+    }
+    result
+}
+
+/// Panics if `c` isn't `before` + A·B to within the module's allowance.
+#[allow(clippy::too_many_arguments)]
+fn verify(
+    what: &str,
+    a: &[f64],
+    b: &[f64],
+    before: &[f64],
+    c: &[f64],
+    m: usize,
+    n: usize,
+    k: usize,
+) {
+    let mut reference = before.to_vec();
+    matmul_naive_ikj(a, b, &mut reference, m, n, k);
+    // |C₀| + |A|·|B|, what the rounding is relative to
+    let mut magnitude: Vec<f64> = before.iter().map(|x| x.abs()).collect();
+    for i in 0..m {
+        for p in 0..k {
+            let a_ip = a[i * k + p].abs();
+            for j in 0..n {
+                magnitude[i * n + j] += a_ip * b[p * n + j].abs();
+            }
+        }
+    }
+
+    let allowance = 2.0 * error_bound(k + 1);
+    let mut worst: Option<(usize, f64)> = None;
+    let mut max_error = 0.0f64;
+    for (at, ((&x, &want), &size)) in c.iter().zip(&reference).zip(&magnitude).enumerate() {
+        let error = (x - want).abs();
+        let agrees = if want.is_nan() {
+            x.is_nan()
+        } else if want.is_infinite() {
+            x == want
+        } else {
+            error <= allowance * size
+        };
+        if error.is_finite() {
+            max_error = max_error.max(error);
+        }
+        if !agrees && worst.is_none_or(|(_, worst)| error.total_cmp(&worst).is_gt()) {
+            worst = Some((at, error));
+        }
+    }
+    if let Some((at, _)) = worst {
+        panic!(
+            "self-check failed: {}x{}x{} {} is off by up to {:e}; C[{}][{}] is {}, the \
+             reference {}, allowing {:e}",
+            m,
+            n,
+            k,
+            what,
+            max_error,
+            at / n,
+            at % n,
+            c[at],
+            reference[at],
+            allowance * magnitude[at]
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, with_config};
+    use crate::matrix::generate::random;
+    use crate::threaded::{Schedule, parallel_rows};
+    use crate::{GemmBackend, multiply, multiply_parallel, try_multiply};
+    use std::ops::Range;
+
+    fn on() -> Config {
+        Config {
+            self_check: Some(true),
+            ..Config::default()
+        }
+    }
+
+    /// A backend that gets one element of each band wrong.
+    struct OffByOne;
+
+    impl GemmBackend for OffByOne {
+        fn name(&self) -> &str {
+            "off by one"
+        }
+
+        fn supported(&self) -> bool {
+            true
+        }
+
+        unsafe fn run(
+            &self,
+            a: &[f64],
+            b: &[f64],
+            c: &mut [f64],
+            _m: usize,
+            n: usize,
+            k: usize,
+            rows: Range<usize>,
+        ) {
+            let a = &a[rows.start * k..rows.end * k];
+            matmul_naive_ikj(a, b, c, rows.len(), n, k);
+            c[n - 1] += 1e-9;
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "self-check failed: 20x12x30 parallel_rows is off by up to")]
+    fn test_catches_a_broken_backend() {
+        let (m, n, k) = (20, 12, 30);
+        let (a, b, mut c) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+        with_config(on(), || {
+            checked("parallel_rows", &a, &b, &mut c, m, n, k, |c| unsafe {
+                parallel_rows(&OffByOne, &a, &b, c, m, n, k, 2, Schedule::Static)
+            })
+        });
+    }
+
+    /// Off, the same backend goes through unnoticed.
+    #[test]
+    fn test_off() {
+        let (m, n, k) = (20, 12, 30);
+        let (a, b, mut c) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+        let off = Config {
+            self_check: Some(false),
+            ..Config::default()
+        };
+        with_config(off, || {
+            checked("parallel_rows", &a, &b, &mut c, m, n, k, |c| unsafe {
+                parallel_rows(&OffByOne, &a, &b, c, m, n, k, 2, Schedule::Static)
+            })
+        });
+    }
+
+    /// The real entry points pass: tiny, low-rank, blocked with edges, deep
+    /// enough for several k blocks, threaded, k = 0, and with NaN and
+    /// infinity in the inputs.
+    #[test]
+    #[cfg_attr(miri, ignore = "the blocked shapes are too slow")]
+    fn test_silent_when_right() {
+        let shapes = [
+            (5, 7, 3),
+            (50, 20, 3),
+            (37, 29, 41),
+            (29, 19, 600),
+            (20, 20, 0),
+        ];
+        with_config(on(), || {
+            for (m, n, k) in shapes {
+                let (mut a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+                if k > 1 {
+                    a[0] = f64::NAN;
+                    a[k + 1] = f64::INFINITY;
+                }
+                let mut c = c0.clone();
+                multiply(&a, &b, &mut c, m, n, k);
+                let mut c = c0.clone();
+                multiply_parallel(&a, &b, &mut c, m, n, k, 3);
+                let mut c = c0.clone();
+                try_multiply(&a, &b, &mut c, m, n, k).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_env_value() {
+        assert!(parse(Some("1".to_string())));
+        assert!(parse(Some(" 1\n".to_string())));
+        assert!(!parse(Some("0".to_string())));
+        assert!(!parse(Some(String::new())));
+        assert!(!parse(None));
+    }
+}
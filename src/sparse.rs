@@ -0,0 +1,255 @@
+//! Sparse (CSR) times dense multiply.
+//!
+//! A mostly-zero A wastes a dense multiply's time and memory on its zeros.
+//! [`SparseCsr`] stores only the nonzeros, and [`spmm`] multiplies by them
+//! directly: each stored `A[i, p]` adds `A[i, p]·B[p, :]` to row i of C, one
+//! [`axpy`] across the whole row, so the SIMD runs along n and the zeros cost
+//! nothing.
+
+use crate::error;
+use crate::matrix::elementwise::axpy;
+use crate::threaded::parallel_rows::choose_thread_count;
+use crate::threaded::schedule::{BandError, Schedule, for_each_band};
+
+/// A sparse matrix in compressed sparse row form.
+///
+/// Row i's nonzeros are `values[indptr[i]..indptr[i + 1]]`, in columns
+/// `indices[indptr[i]..indptr[i + 1]]`. So for m rows, `indptr` has m + 1
+/// entries, starting at 0 and never decreasing, and its last is the number
+/// of nonzeros. Column indices within a row can be in any order; a repeated
+/// one adds its values.
+///
+/// ```
+/// use matmul::SparseCsr;
+///
+/// // [[1, 0, 2],
+/// //  [0, 0, 0],
+/// //  [0, 3, 0]]
+/// let a = SparseCsr {
+///     indptr: vec![0, 2, 2, 3],
+///     indices: vec![0, 2, 1],
+///     values: vec![1.0, 2.0, 3.0],
+/// };
+/// assert_eq!(a, SparseCsr::from_dense(&[1.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 3.0, 0.0], 3, 3));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SparseCsr {
+    /// Where each row's entries start in `indices` and `values`, then the
+    /// total
+    pub indptr: Vec<usize>,
+    /// The column of each entry
+    pub indices: Vec<usize>,
+    /// The value of each entry
+    pub values: Vec<f64>,
+}
+
+impl SparseCsr {
+    /// The nonzeros of a row-major rows×cols matrix, row by row, columns in
+    /// order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dense` doesn't hold rows×cols elements.
+    pub fn from_dense(dense: &[f64], rows: usize, cols: usize) -> SparseCsr {
+        if let Err(e) = error::check_operands([("A", dense.len(), rows, cols)]) {
+            panic!("{}", e);
+        }
+        let mut csr = SparseCsr {
+            indptr: Vec::with_capacity(rows + 1),
+            ..SparseCsr::default()
+        };
+        csr.indptr.push(0);
+        // `cols.max(1)`: with no columns there are no elements to chunk
+        for row in dense.chunks_exact(cols.max(1)).take(rows) {
+            for (j, &x) in row.iter().enumerate() {
+                if x != 0.0 {
+                    csr.indices.push(j);
+                    csr.values.push(x);
+                }
+            }
+            csr.indptr.push(csr.values.len());
+        }
+        csr.indptr.resize(rows + 1, 0);
+        csr
+    }
+
+    /// Number of stored entries.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Why this isn't a valid m×k matrix, if it isn't.
+    fn check(&self, m: usize, k: usize) -> Result<(), &'static str> {
+        if self.indptr.len() != m + 1 {
+            return Err("indptr must have m + 1 entries");
+        }
+        if self.indptr[0] != 0 || self.indptr.windows(2).any(|w| w[0] > w[1]) {
+            return Err("indptr must start at 0 and never decrease");
+        }
+        if self.indptr[m] != self.indices.len() || self.indices.len() != self.values.len() {
+            return Err("indptr must end at the number of indices and values");
+        }
+        if self.indices.iter().any(|&p| p >= k) {
+            return Err("a column index is out of range");
+        }
+        Ok(())
+    }
+}
+
+/// C += A·B for a sparse A.
+///
+/// A is m×k in CSR form, B is k×n and C is m×n, both row-major. Rows of C are
+/// split across threads when there's enough work (about 2·nnz·n FLOPs).
+/// The sum for each element of C runs over A's row entries in stored order,
+/// so it can differ from [`multiply`](crate::multiply) with the densified A
+/// in the last bits.
+///
+/// ```
+/// use matmul::{SparseCsr, spmm};
+///
+/// let a = SparseCsr::from_dense(&[0.0, 2.0, 0.0, 0.0], 2, 2);
+/// let b = [1.0, 2.0, 3.0, 4.0];
+/// let mut c = [0.0; 4];
+/// spmm(&a, &b, &mut c, 2, 2, 2);
+/// assert_eq!(c, [6.0, 8.0, 0.0, 0.0]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if A isn't a valid m×k CSR matrix (see [`SparseCsr`]), if B's or
+/// C's length doesn't match, or if k·n or m·n overflows `usize`.
+pub fn spmm(a: &SparseCsr, b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    if let Err(reason) = a.check(m, k) {
+        panic!("A: {}", reason);
+    }
+    if let Err(e) = error::check_operands([("B", b.len(), k, n), ("C", c.len(), m, n)]) {
+        panic!("{}", e);
+    }
+    if m == 0 || n == 0 || a.nnz() == 0 {
+        return;
+    }
+
+    let max_threads = std::thread::available_parallelism().map_or(1, |t| t.get());
+    // The work of a dense multiply with A's average row length as its depth
+    let threads = choose_thread_count(m, n, a.nnz().div_ceil(m), max_threads);
+    spmm_rows(a, b, c, m, n, threads);
+}
+
+/// [`spmm`] on `threads` threads, for a valid A and matching slices.
+fn spmm_rows(a: &SparseCsr, b: &[f64], c: &mut [f64], m: usize, n: usize, threads: usize) {
+    let rows = |start: usize, end: usize, c_band: &mut [f64]| {
+        for (i, c_row) in (start..end).zip(c_band.chunks_exact_mut(n)) {
+            let entries = a.indptr[i]..a.indptr[i + 1];
+            for (&p, &x) in a.indices[entries.clone()].iter().zip(&a.values[entries]) {
+                axpy(c_row, x, &b[p * n..(p + 1) * n]);
+            }
+        }
+    };
+    if threads == 1 {
+        rows(0, m, c);
+        return;
+    }
+    // Row lengths vary, so threads claim chunks as they go rather than
+    // taking equal bands
+    match for_each_band(c, m, n, 1, threads, Schedule::Dynamic, None, rows) {
+        Ok(()) => {}
+        Err(BandError::Panicked(err)) => panic!("{}", err),
+        Err(BandError::Cancelled) => unreachable!("no cancel flag was given"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    /// An m×k matrix with about `density` of its entries nonzero, plus an
+    /// empty first row and a fully dense last one.
+    fn sparse_dense(m: usize, k: usize, density: f64) -> Vec<f64> {
+        let keep = random(m, k, 7);
+        let mut a = random(m, k, 1);
+        for (x, &u) in a.iter_mut().zip(&keep) {
+            // u is uniform in [-1, 1)
+            if (u + 1.0) / 2.0 >= density {
+                *x = 0.0;
+            }
+        }
+        a[..k].fill(0.0);
+        a[(m - 1) * k..].copy_from_slice(&random(1, k, 9));
+        a
+    }
+
+    #[test]
+    fn test_matches_densified_multiply() {
+        let shapes: &[(usize, usize, usize)] = if cfg!(miri) {
+            &[(5, 7, 6), (2, 1, 1)]
+        } else {
+            &[(5, 7, 6), (2, 1, 1), (200, 130, 300), (64, 33, 17)]
+        };
+        for &(m, n, k) in shapes {
+            for density in [0.02, 0.3, 1.0] {
+                let dense = sparse_dense(m, k, density);
+                let a = SparseCsr::from_dense(&dense, m, k);
+                let b = random(k, n, 2);
+                let c0 = random(m, n, 3);
+                let mut want = c0.clone();
+                multiply(&dense, &b, &mut want, m, n, k);
+
+                let mut c = c0.clone();
+                spmm(&a, &b, &mut c, m, n, k);
+                assert_close(&want, &c, 1e-12, 1e-12);
+
+                // Threaded too (more than one chunk of rows, natively), with
+                // rows of very different lengths
+                for threads in [1, 3] {
+                    let mut c = c0.clone();
+                    spmm_rows(&a, &b, &mut c, m, n, threads);
+                    assert_close(&want, &c, 1e-12, 1e-12);
+                    // The empty first row is left alone
+                    assert_eq!(c[..n], c0[..n]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_dense() {
+        let dense = [0.0, 1.5, 0.0, -2.0, 0.0, 0.0];
+        let a = SparseCsr::from_dense(&dense, 3, 2);
+        assert_eq!(a.indptr, [0, 1, 2, 2]);
+        assert_eq!(a.indices, [1, 1]);
+        assert_eq!(a.values, [1.5, -2.0]);
+        assert_eq!(a.nnz(), 2);
+
+        let empty = SparseCsr::from_dense(&[], 3, 0);
+        assert_eq!(empty.indptr, [0, 0, 0, 0]);
+        let mut c = [0.0; 6];
+        spmm(&empty, &[], &mut c, 3, 2, 0);
+        assert_eq!(c, [0.0; 6]);
+    }
+
+    #[test]
+    fn test_invalid_csr() {
+        let valid = SparseCsr::from_dense(&[1.0, 0.0, 0.0, 2.0], 2, 2);
+        assert_eq!(valid.check(2, 2), Ok(()));
+        assert!(valid.check(3, 2).is_err());
+        assert!(valid.check(2, 1).is_err());
+
+        let mut decreasing = valid.clone();
+        decreasing.indptr = vec![0, 2, 1];
+        assert!(decreasing.check(2, 2).is_err());
+        let mut short = valid.clone();
+        short.values.pop();
+        assert!(short.check(2, 2).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "A: a column index is out of range")]
+    fn test_spmm_rejects_bad_index() {
+        let mut a = SparseCsr::from_dense(&[1.0, 0.0, 0.0, 2.0], 2, 2);
+        a.indices[1] = 2;
+        spmm(&a, &[0.0; 4], &mut [0.0; 4], 2, 2, 2);
+    }
+}
@@ -0,0 +1,248 @@
+//! Multiplying by a symmetric matrix stored as one triangle.
+//!
+//! A symmetric B is usually kept as its lower triangle only, with the upper
+//! half unused (or holding something else). [`symm`] runs the blocked
+//! drivers' loop with a B packing step that mirrors the triangle as it
+//! copies ([`pack_b_panel_symmetric`]), so the full matrix is never built
+//! and the unstored half never read.
+
+use crate::blocked::edge::multiply_edge_with;
+use crate::blocked::pack::{pack_a_panel, pack_b_panel_symmetric, symmetric_lower};
+use crate::matrix::low_rank;
+use crate::packed::{Kernel, kernel_4x4_scalar};
+use crate::{Backend, detected_backend, error};
+
+#[cfg(target_arch = "x86_64")]
+use crate::kernels::{
+    kernel_4x4::kernel_4x4_avx2, kernel_8x8::kernel_8x8_avx512, kernel_12x4::kernel_12x4_avx2,
+};
+
+/// C += A·B for B symmetric, given as its lower triangle.
+///
+/// A and C are m×n and B is n×n, row-major. Only B's lower triangle,
+/// diagonal included, is read; the entries above the diagonal can hold
+/// anything. The result is bit for bit that of a single-threaded
+/// [`multiply_with_backend`](crate::multiply_with_backend) with the full
+/// symmetric B.
+///
+/// ```
+/// use matmul::symm;
+///
+/// // B = [[1, 2], [2, 3]]; the upper entry isn't stored
+/// let b = [1.0, f64::NAN, 2.0, 3.0];
+/// let a = [1.0, 1.0];
+/// let mut c = [0.0; 2];
+/// symm(&a, &b, &mut c, 1, 2);
+/// assert_eq!(c, [3.0, 5.0]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m and n, or if a product of them
+/// overflows `usize`.
+pub fn symm(a: &[f64], b_lower: &[f64], c: &mut [f64], m: usize, n: usize) {
+    if let Err(e) = error::check_operands([
+        ("A", a.len(), m, n),
+        ("B", b_lower.len(), n, n),
+        ("C", c.len(), m, n),
+    ]) {
+        panic!("{}", e);
+    }
+    // Safety: the detected backend is available
+    unsafe { symm_with_backend(detected_backend(), a, b_lower, c, m, n) }
+}
+
+/// [`symm`] on `backend`.
+///
+/// # Safety
+///
+/// `backend` is available on this CPU, and the slices match m and n.
+unsafe fn symm_with_backend(
+    backend: Backend,
+    a: &[f64],
+    b_lower: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+    #[cfg(feature = "tracing")]
+    crate::backend::trace_dispatch(backend, 1, m, n, n);
+
+    if low_rank::uses_low_rank(backend, n) {
+        // B is at most 4×4, so copying it out costs nothing
+        unsafe { low_rank::low_rank_rows(backend, a, &symmetrize(b_lower, n), c, n, n, 0..m) };
+        return;
+    }
+    let (kc, mc) = backend.block_sizes(n);
+    let fused = backend != Backend::Scalar;
+    unsafe {
+        match backend {
+            Backend::Scalar => {
+                symm_blocked::<4, 4>(a, b_lower, c, m, n, kc, mc, fused, kernel_4x4_scalar)
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_4x4 => {
+                symm_blocked::<4, 4>(a, b_lower, c, m, n, kc, mc, fused, kernel_4x4_avx2)
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx2_12x4 => {
+                symm_blocked::<12, 4>(a, b_lower, c, m, n, kc, mc, fused, kernel_12x4_avx2)
+            }
+            #[cfg(target_arch = "x86_64")]
+            Backend::Avx512_8x8 => {
+                symm_blocked::<8, 8>(a, b_lower, c, m, n, kc, mc, fused, kernel_8x8_avx512)
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            Backend::Avx2_4x4 | Backend::Avx2_12x4 | Backend::Avx512_8x8 => {
+                unreachable!("{} only exists on x86_64", backend)
+            }
+            Backend::ScalarIkj => {
+                crate::matrix::naive_ikj::matmul_naive_ikj(a, &symmetrize(b_lower, n), c, m, n, n)
+            }
+        }
+    }
+}
+
+/// The blocked drivers' loop (same blocks, same order, same edge paths),
+/// with B packed from its lower triangle.
+///
+/// # Safety
+///
+/// `kernel` is an MR×NR kernel this CPU can run (and the edges are `fused`
+/// only if it's a SIMD one), and A, B, and C hold m×n, n×n, and m×n, all
+/// nonzero.
+#[allow(clippy::too_many_arguments)]
+unsafe fn symm_blocked<const MR: usize, const NR: usize>(
+    a: &[f64],
+    b_lower: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    kc: usize,
+    mc: usize,
+    fused: bool,
+    kernel: Kernel,
+) {
+    // B is square, so the inner dimension is n too
+    let k = n;
+    let m_main = m / MR * MR;
+    let n_main = n / NR * NR;
+    let mut a_panel = vec![0.0; mc.min(m_main) * kc];
+    let mut b_pack = vec![0.0; NR * kc];
+
+    for kk in (0..k).step_by(kc) {
+        let k_block = (kk + kc).min(k) - kk;
+
+        for ii in (0..m_main).step_by(mc) {
+            let m_block = (ii + mc).min(m_main) - ii;
+            pack_a_panel::<MR>(a, &mut a_panel, ii, kk, m_block, k_block, k);
+
+            for j in (0..n_main).step_by(NR) {
+                pack_b_panel_symmetric::<NR>(b_lower, &mut b_pack, j, kk, k_block, n);
+
+                for i in (0..m_block).step_by(MR) {
+                    let at = (ii + i) * n + j;
+                    unsafe {
+                        kernel(
+                            a_panel.as_ptr().add(i * k_block),
+                            b_pack.as_ptr(),
+                            c.as_mut_ptr().add(at),
+                            c.len() - at,
+                            k_block,
+                            n,
+                        )
+                    };
+                }
+            }
+        }
+    }
+
+    // The drivers' edge paths: leftover rows, then leftover columns of the
+    // tiled rows
+    let a = |i: usize, p: usize| a[i * k + p];
+    let b = |p: usize, j: usize| symmetric_lower(b_lower, n, p, j);
+    unsafe {
+        multiply_edge_with(fused, a, b, c, m_main..m, 0..n, n, k);
+        multiply_edge_with(fused, a, b, c, 0..m_main, n_main..n, n, k);
+    }
+}
+
+/// The full symmetric matrix from its lower triangle.
+fn symmetrize(b_lower: &[f64], n: usize) -> Vec<f64> {
+    (0..n * n)
+        .map(|at| symmetric_lower(b_lower, n, at / n, at % n))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::{available_backends, multiply_with_backend};
+
+    /// A random symmetric n×n matrix, and its lower triangle with NaN above
+    /// the diagonal.
+    fn symmetric(n: usize) -> (Vec<f64>, Vec<f64>) {
+        let random = random(n, n, 2);
+        let mut full = vec![0.0; n * n];
+        let mut lower = vec![f64::NAN; n * n];
+        for i in 0..n {
+            for j in 0..=i {
+                full[i * n + j] = random[i * n + j];
+                full[j * n + i] = random[i * n + j];
+                lower[i * n + j] = random[i * n + j];
+            }
+        }
+        (full, lower)
+    }
+
+    #[test]
+    fn test_matches_symmetrized_multiply() {
+        // Edge rows and columns for every tile, and (natively) more than one
+        // KC and MC block
+        let shapes: &[(usize, usize)] = if cfg!(miri) {
+            &[(13, 9), (1, 1)]
+        } else {
+            &[(13, 9), (1, 1), (300, 270), (27, 5), (9, 3)]
+        };
+        for backend in available_backends() {
+            for &(m, n) in shapes {
+                let a = random(m, n, 1);
+                let (full, lower) = symmetric(n);
+                let c0 = random(m, n, 3);
+
+                let mut want = c0.clone();
+                multiply_with_backend(backend, &a, &full, &mut want, m, n, n);
+                let mut c = c0.clone();
+                unsafe { symm_with_backend(backend, &a, &lower, &mut c, m, n) };
+                // NaN anywhere would mean the upper triangle was read
+                assert_eq!(c, want, "{} on {}x{}", backend, m, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_public_entry_point() {
+        let (m, n) = (6, 5);
+        let a = random(m, n, 1);
+        let (full, lower) = symmetric(n);
+        let mut want = vec![0.0; m * n];
+        crate::multiply(&a, &full, &mut want, m, n, n);
+        let mut c = vec![0.0; m * n];
+        symm(&a, &lower, &mut c, m, n);
+        assert_eq!(c, want);
+
+        let mut empty: [f64; 0] = [];
+        symm(&[], &[], &mut empty, 0, 0);
+        symm(&[], &lower, &mut empty, 0, n);
+    }
+
+    #[test]
+    #[should_panic(expected = "B")]
+    fn test_wrong_size() {
+        symm(&[0.0; 6], &[0.0; 6], &mut [0.0; 6], 2, 3);
+    }
+}
@@ -2,13 +2,53 @@
 //!
 //! These wrap the blocked GEMM functions with parallel execution across
 //! rows. Thread count adapts to matrix size - small matrices use fewer
-//! threads to avoid overhead.
+//! threads to avoid overhead. The shared driver is [`parallel_rows()`],
+//! which runs any [`GemmBackend`](crate::GemmBackend); rows are handed out
+//! by the [`schedule`] module, dynamically by default.
 //!
 //! Available implementations:
 //! - `gemm_4x4_mt`: Multi-threaded 4×4 AVX2
 //! - `gemm_12x4_mt`: Multi-threaded 12×4 AVX2
 //! - `gemm_8x8_mt`: Multi-threaded 8×8 AVX-512
+//! - `naive_ikj_mt`: Multi-threaded scalar i-k-j (fallback without SIMD)
+//!
+//! The SIMD wrappers only exist on x86_64.
 
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_12x4_mt;
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_4x4_mt;
+#[cfg(target_arch = "x86_64")]
 pub mod gemm_8x8_mt;
+pub mod naive_ikj_mt;
+pub mod parallel_rows;
+pub mod schedule;
+
+pub use parallel_rows::{BlockedGemm, parallel_rows, parallel_rows_cancellable};
+pub use schedule::{Cancelled, Schedule, WorkerPanic};
+
+use crate::Backend;
+
+/// Whether every multiply or transpose splits across threads, however
+/// small. Under Miri only tiny shapes are affordable, and the size
+/// thresholds would keep them all on one thread, so the thresholds are off
+/// there and the band splitting still gets checked.
+pub(crate) const SPLIT_ANY_SHAPE: bool = cfg!(miri);
+
+/// How many threads an m×n×k multiply on `backend` splits across, given at
+/// most `max_threads`: the i-k-j bands' own rule for
+/// [`Backend::ScalarIkj`], [`choose_thread_count`](parallel_rows::choose_thread_count)
+/// for the blocked backends. Everything that threads a multiply asks here,
+/// and so does [`plan`](crate::plan::plan).
+pub(crate) fn thread_count(
+    backend: Backend,
+    m: usize,
+    n: usize,
+    k: usize,
+    max_threads: usize,
+) -> usize {
+    match backend {
+        Backend::ScalarIkj => naive_ikj_mt::thread_count(m, n, k, max_threads),
+        _ => parallel_rows::choose_thread_count(m, n, k, max_threads),
+    }
+}
@@ -0,0 +1,86 @@
+//! Multi-threaded scalar i-k-j fallback.
+
+use crate::denormal::{DenormalMode, ModeGuard};
+use crate::matrix::naive_ikj::matmul_naive_ikj;
+use std::thread;
+
+/// Below this many FLOPs per thread, spawning costs more than it saves (1
+/// with [`SPLIT_ANY_SHAPE`](super::SPLIT_ANY_SHAPE)).
+const MIN_FLOPS_PER_THREAD: usize = if super::SPLIT_ANY_SHAPE { 1 } else { 1_000_000 };
+
+/// Multi-threaded i-k-j matrix multiplication, no SIMD required.
+///
+/// Used by `multiply_parallel` when the CPU has no SIMD kernel (e.g. aarch64),
+/// so the parallel API is still parallel everywhere. C is split into
+/// contiguous row bands with `chunks_mut`, and each scoped thread runs the
+/// scalar i-k-j loop on its own band.
+///
+/// # Arguments
+///
+/// * `num_threads` - Maximum threads (actual may be fewer for small matrices)
+pub fn matmul_naive_ikj_mt(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+) {
+    // Zero-sized shapes get one band, so the single-threaded loop (and its
+    // length checks) handles them
+    let threads = thread_count(m, n, k, num_threads);
+    if threads == 1 {
+        matmul_naive_ikj(a, b, c, m, n, k);
+        return;
+    }
+
+    let rows_per_band = m.div_ceil(threads);
+    let denormals = DenormalMode::current();
+
+    thread::scope(|s| {
+        for (a_band, c_band) in a
+            .chunks(rows_per_band * k)
+            .zip(c.chunks_mut(rows_per_band * n))
+        {
+            let rows = c_band.len() / n;
+            s.spawn(move || {
+                let _mode = ModeGuard::set(denormals);
+                matmul_naive_ikj(a_band, b, c_band, rows, n, k)
+            });
+        }
+    });
+}
+
+/// How many row bands (one thread each) [`matmul_naive_ikj_mt`] splits C
+/// into for at most `num_threads` threads, or the [config](crate::config)'s
+/// `max_threads` if that's fewer.
+pub(crate) fn thread_count(m: usize, n: usize, k: usize, num_threads: usize) -> usize {
+    let num_threads = crate::config::current().cap_threads(num_threads);
+    // Saturating: m·n·k can overflow even when each matrix fits
+    let flops = 2usize.saturating_mul(m).saturating_mul(n).saturating_mul(k);
+    let threads = num_threads.min(m).min(flops / MIN_FLOPS_PER_THREAD).max(1);
+    // Equal bands of ceil(m / threads) rows can need fewer than `threads`
+    m.max(1).div_ceil(m.max(1).div_ceil(threads))
+}
+
+/// Scalar i-k-j over rows `row_start..row_end` only, in the [`BlockedGemm`]
+/// shape; this is what [`Backend::ScalarIkj`](crate::Backend::ScalarIkj)
+/// runs on a row band. With a row range, `c` holds just those rows.
+///
+/// [`BlockedGemm`]: super::BlockedGemm
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn matmul_naive_ikj_rows(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    row_start: Option<usize>,
+    row_end: Option<usize>,
+) {
+    let start = row_start.unwrap_or(0);
+    let end = row_end.unwrap_or(m);
+    matmul_naive_ikj(&a[start * k..end * k], b, c, end - start, n, k);
+}
@@ -0,0 +1,407 @@
+//! Generic row-parallel driver shared by all the multi-threaded GEMMs.
+//!
+//! The `gemm_*_mt` modules used to be near-identical copies that only
+//! differed in which blocked function they called. Everything they have in
+//! common - the adaptive thread count, row scheduling, and splitting C into
+//! per-thread bands - lives here now, for any [`GemmBackend`].
+
+use super::schedule::{BandError, Cancelled, Schedule, for_each_band};
+use crate::backend::GemmBackend;
+use crate::workspace::{transpose_into_workspace, with_scratch};
+use std::sync::atomic::AtomicBool;
+
+/// Signature shared by the blocked GEMMs: `(a, b, c, m, n, k, row_start, row_end)`.
+///
+/// With a row range, `c` holds only rows `row_start..row_end` of C.
+pub type BlockedGemm =
+    unsafe fn(&[f64], &[f64], &mut [f64], usize, usize, usize, Option<usize>, Option<usize>);
+
+/// Runs a GEMM backend across multiple threads, splitting C by rows.
+///
+/// Picks the thread count adaptively (more threads for more work), then hands
+/// each thread row bands whose starts are multiples of the backend's
+/// [`mr`](GemmBackend::mr). C is split into disjoint `&mut` bands, so the
+/// backend only ever sees its own rows. With a single thread it just runs
+/// the backend on the whole matrix.
+///
+/// A backend that [packs from Bᵀ](GemmBackend::uses_transpose) gets one
+/// made here, in the calling thread's scratch cache, and every band packs
+/// from it; the workers allocate only their own panels.
+///
+/// # Panics
+///
+/// If a worker panics, the remaining workers still run to completion and are
+/// joined, then this panics with a message naming the row band that failed.
+/// C is left partially updated: its contents are unspecified, but every
+/// thread has finished writing to it.
+///
+/// # Safety
+///
+/// Caller must ensure:
+/// - The backend is [supported](GemmBackend::supported)
+/// - All slice lengths match the provided dimensions
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn parallel_rows(
+    backend: &dyn GemmBackend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+    schedule: Schedule,
+) {
+    if m == 0 || n == 0 || k == 0 {
+        return;
+    }
+    let effective_threads = choose_thread_count(m, n, k, num_threads);
+
+    if effective_threads == 1 {
+        unsafe {
+            backend.run(a, b, c, m, n, k, 0..m);
+        }
+        return;
+    }
+
+    match unsafe { run_bands(backend, a, b, c, m, n, k, effective_threads, schedule, None) } {
+        Ok(()) => {}
+        Err(BandError::Panicked(err)) => panic!("{}", err),
+        Err(BandError::Cancelled) => unreachable!("no cancel flag was given"),
+    }
+}
+
+/// Same as [`parallel_rows`], but gives up early once `cancel` is set.
+///
+/// Always uses [`Schedule::Dynamic`] (even for one thread) and checks the flag
+/// each time a worker claims a chunk of rows, so the call returns within
+/// roughly one chunk's worth of work after the flag is raised.
+///
+/// Returns `Err(Cancelled)` if any chunk was skipped. C's contents are
+/// unspecified in that case.
+///
+/// # Panics
+///
+/// Same as [`parallel_rows`].
+///
+/// # Safety
+///
+/// Same as [`parallel_rows`].
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn parallel_rows_cancellable(
+    backend: &dyn GemmBackend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    num_threads: usize,
+    cancel: &AtomicBool,
+) -> Result<(), Cancelled> {
+    if m == 0 || n == 0 || k == 0 {
+        return Ok(());
+    }
+    let effective_threads = choose_thread_count(m, n, k, num_threads);
+
+    match unsafe {
+        run_bands(
+            backend,
+            a,
+            b,
+            c,
+            m,
+            n,
+            k,
+            effective_threads,
+            Schedule::Dynamic,
+            Some(cancel),
+        )
+    } {
+        Ok(()) => Ok(()),
+        Err(BandError::Panicked(err)) => panic!("{}", err),
+        Err(BandError::Cancelled) => Err(Cancelled),
+    }
+}
+
+/// Runs every band, packing from one shared Bᵀ if the backend uses one.
+#[allow(clippy::too_many_arguments)]
+unsafe fn run_bands(
+    backend: &dyn GemmBackend,
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    threads: usize,
+    schedule: Schedule,
+    cancel: Option<&AtomicBool>,
+) -> Result<(), BandError> {
+    // The bands run on the workers, so this thread's cache is free to hold it
+    with_scratch(|workspace| {
+        let bt = if backend.uses_transpose(k, n) {
+            transpose_into_workspace(b, k, n, workspace)
+        } else {
+            None
+        };
+        for_each_band(
+            c,
+            m,
+            n,
+            backend.mr(),
+            threads,
+            schedule,
+            cancel,
+            |start_row, end_row, c_band| unsafe {
+                match bt {
+                    Some(bt) => {
+                        backend.run_transposed(a, b, bt, c_band, m, n, k, start_row..end_row)
+                    }
+                    None => backend.run(a, b, c_band, m, n, k, start_row..end_row),
+                }
+            },
+        )
+    })
+}
+
+/// The arithmetic intensity each thread needs: a thread's kernel, rather
+/// than memory, sets its pace only while the multiply does at least this
+/// many FLOPs per byte of A, B, and C for each thread running it.
+const INTENSITY_PER_THREAD: f64 = 2.0;
+
+/// Threads a bandwidth-bound multiply still gets, whatever its intensity:
+/// about what it takes to saturate memory bandwidth.
+const BANDWIDTH_THREADS: usize = 4;
+
+/// FLOPs per byte an m×n×k multiply moves, counting A, B, and C once each:
+/// about n/12 for an n×n×n cube, but only about k/4 when k is small next
+/// to m and n, where the multiply waits on memory rather than on the
+/// kernels. 0 for an empty multiply.
+pub(crate) fn arithmetic_intensity(m: usize, n: usize, k: usize) -> f64 {
+    let (m, n, k) = (m as f64, n as f64, k as f64);
+    let bytes = 8.0 * (m * k + k * n + m * n);
+    if bytes == 0.0 {
+        0.0
+    } else {
+        2.0 * m * n * k / bytes
+    }
+}
+
+/// Adaptive thread count for an m×n×k multiply:
+/// - < 100M FLOPs: 1 thread
+/// - < 300M FLOPs: 2 threads
+/// - Otherwise: up to `max_threads`
+///
+/// Never uses more than one thread per 64 rows, nor, for a bandwidth-bound
+/// shape, more than one per 2 FLOPs per byte of [`arithmetic_intensity`]
+/// (but always up to 4): a 4096×4096×32 multiply does about 8 FLOPs per
+/// byte, and threads past 4 would only contend for memory and for the
+/// shared Bᵀ. With [`SPLIT_ANY_SHAPE`](super::SPLIT_ANY_SHAPE), it's up to
+/// `max_threads` for any shape.
+/// The [config](crate::config)'s `max_threads` caps `max_threads`.
+pub(crate) fn choose_thread_count(m: usize, n: usize, k: usize, max_threads: usize) -> usize {
+    let max_threads = crate::config::current().cap_threads(max_threads);
+    if super::SPLIT_ANY_SHAPE {
+        return max_threads.min(m).max(1);
+    }
+    // In f64: m·n·k can overflow usize even when each matrix fits
+    let flops = 2.0 * m as f64 * n as f64 * k as f64;
+
+    const SINGLE_THREAD_THRESHOLD: f64 = 100_000_000.0;
+    const TWO_THREAD_THRESHOLD: f64 = 300_000_000.0;
+
+    let optimal_threads = if flops < SINGLE_THREAD_THRESHOLD {
+        1
+    } else if flops < TWO_THREAD_THRESHOLD {
+        2
+    } else {
+        max_threads
+    };
+
+    let threads_by_rows = (m / 64).max(1);
+    let threads_by_intensity =
+        ((arithmetic_intensity(m, n, k) / INTENSITY_PER_THREAD) as usize).max(BANDWIDTH_THREADS);
+
+    optimal_threads
+        .min(threads_by_rows)
+        .min(threads_by_intensity)
+        .min(max_threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backend;
+    use crate::matrix::generate::random;
+    use crate::threaded::SPLIT_ANY_SHAPE;
+    use std::ops::Range;
+    use std::panic::AssertUnwindSafe;
+
+    /// Stand-in for a blocked GEMM: adds 1.0 to every element of its row band
+    /// and checks the band starts on a tile boundary and C is band-local.
+    /// Panics on whichever band contains `fail_at`, if set.
+    struct MockBackend {
+        fail_at: Option<usize>,
+    }
+
+    impl GemmBackend for MockBackend {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn supported(&self) -> bool {
+            true
+        }
+
+        fn mr(&self) -> usize {
+            12
+        }
+
+        unsafe fn run(
+            &self,
+            _a: &[f64],
+            _b: &[f64],
+            c: &mut [f64],
+            _m: usize,
+            n: usize,
+            _k: usize,
+            rows: Range<usize>,
+        ) {
+            if self.fail_at.is_some_and(|row| rows.contains(&row)) {
+                panic!("injected failure");
+            }
+            assert_eq!(
+                rows.start % 12,
+                0,
+                "band starts mid-tile at row {}",
+                rows.start
+            );
+            assert_eq!(c.len(), rows.len() * n);
+            for x in c.iter_mut() {
+                *x += 1.0;
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_rows_worker_panic_names_band() {
+        // Just over the FLOPs that get all 4 threads; under Miri, which
+        // splits every shape, small enough to allocate
+        let (m, n, k) = if SPLIT_ANY_SHAPE {
+            (1001, 2, 2)
+        } else {
+            (1001, 1024, 160)
+        };
+        assert_eq!(choose_thread_count(m, n, k, 4), 4);
+        let (a, b) = (vec![0.0; m * k], vec![0.0; k * n]);
+        let failing = MockBackend { fail_at: Some(300) };
+
+        // Static: 4 bands of 252 rows. Dynamic: 72-row chunks (64 rounded up to 12).
+        for (schedule, band) in [(Schedule::Static, 252..504), (Schedule::Dynamic, 288..360)] {
+            let mut c = vec![0.0; m * n];
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                parallel_rows(&failing, &a, &b, &mut c, m, n, k, 4, schedule);
+            }));
+            let payload = result.expect_err("worker panic should propagate");
+            let message = payload.downcast_ref::<String>().unwrap();
+            assert_eq!(
+                *message,
+                format!(
+                    "worker panicked computing rows {}..{}: injected failure",
+                    band.start, band.end
+                )
+            );
+
+            // Every other band still ran: all workers were joined before the re-panic
+            for i in 0..m {
+                let expected = if band.contains(&i) { 0.0 } else { 1.0 };
+                assert_eq!(c[i * n], expected, "{:?}: row {}", schedule, i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_intensity() {
+        assert_eq!(arithmetic_intensity(1200, 1200, 1200), 100.0);
+        assert_eq!(arithmetic_intensity(0, 5, 5), 0.0);
+        assert_eq!(arithmetic_intensity(0, 0, 5), 0.0);
+        let thin = arithmetic_intensity(4096, 4096, 32);
+        assert!((7.8..8.0).contains(&thin), "{}", thin);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri threads every shape")]
+    fn test_thread_count_for_intensity() {
+        // Compute-bound cube: every thread it has rows for
+        assert_eq!(choose_thread_count(4096, 4096, 4096, 64), 64);
+        assert_eq!(choose_thread_count(1024, 1024, 1024, 64), 16);
+        // Thin k, bandwidth-bound: 8 FLOPs per byte holds 4 threads busy
+        assert_eq!(choose_thread_count(4096, 4096, 32, 64), 4);
+        assert_eq!(choose_thread_count(4096, 4096, 64, 64), 7);
+        assert_eq!(choose_thread_count(4096, 4096, 32, 2), 2);
+        // Tall and skinny: streaming A is most of the work
+        assert_eq!(choose_thread_count(65536, 16, 256, 16), 4);
+        assert_eq!(choose_thread_count(65536, 256, 256, 64), 15);
+    }
+
+    #[test]
+    fn test_bands_share_one_transpose() {
+        // Small enough for Miri, and one column, which isn't transposed
+        for (m, n, k) in [(75, 13, 9), (75, 1, 9)] {
+            let a = random(m, k, 1);
+            let b = random(k, n, 2);
+            let c0 = random(m, n, 3);
+            for backend in crate::available_backends() {
+                assert_eq!(
+                    backend.uses_transpose(k, n),
+                    backend != Backend::ScalarIkj,
+                    "{}",
+                    backend
+                );
+                let mut want = c0.clone();
+                unsafe { backend.run(&a, &b, &mut want, m, n, k, 0..m) };
+                let mut c = c0.clone();
+                let result = unsafe {
+                    run_bands(&backend, &a, &b, &mut c, m, n, k, 3, Schedule::Static, None)
+                };
+                assert!(result.is_ok());
+                assert_eq!(c, want, "{} on {}x{}x{}", backend, m, n, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_disjoint_bands_scalar_backend() {
+        // Small and intrinsic-free so it also runs under Miri, which checks
+        // the band splitting for aliasing: `cargo +nightly miri test --lib disjoint_bands`
+        use crate::matrix::naive_ikj::matmul_naive_ikj;
+
+        let (m, n, k) = (150, 3, 4);
+        let a: Vec<f64> = (0..m * k).map(|i| (i % 7) as f64).collect();
+        let b: Vec<f64> = (0..k * n).map(|i| (i % 5) as f64).collect();
+
+        let mut c_serial = vec![1.0; m * n];
+        matmul_naive_ikj(&a, &b, &mut c_serial, m, n, k);
+
+        for schedule in [Schedule::Static, Schedule::Dynamic] {
+            let mut c = vec![1.0; m * n];
+            let result = unsafe {
+                run_bands(
+                    &Backend::ScalarIkj,
+                    &a,
+                    &b,
+                    &mut c,
+                    m,
+                    n,
+                    k,
+                    3,
+                    schedule,
+                    None,
+                )
+            };
+            assert!(result.is_ok());
+            assert_eq!(c, c_serial, "{:?}", schedule);
+        }
+    }
+}
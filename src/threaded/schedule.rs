@@ -0,0 +1,407 @@
+//! Row scheduling for the multi-threaded GEMMs.
+//!
+//! Static scheduling gives each thread one contiguous band of rows up front.
+//! Dynamic scheduling hands out fixed-size row chunks from a shared atomic
+//! counter, so if one thread gets descheduled (noisy machine, another process
+//! stealing a core) the others just pick up its share instead of waiting.
+
+use crate::config;
+use crate::denormal::{DenormalMode, ModeGuard};
+use std::any::Any;
+use std::fmt;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// Under `--cfg loom` the chunk counter uses loom's atomics so its claiming
+// protocol can be model-checked (see `just test-loom`).
+#[cfg(loom)]
+use loom::sync::atomic::AtomicUsize;
+#[cfg(not(loom))]
+use std::sync::atomic::AtomicUsize;
+
+/// Rows per chunk for dynamic scheduling, before rounding up to the kernel height.
+pub const CHUNK_ROWS: usize = 64;
+
+/// How rows of C are distributed across threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Schedule {
+    /// One contiguous band per thread. The row-to-thread mapping depends only
+    /// on the shape and thread count, never on timing.
+    Static,
+    /// Threads repeatedly claim the next chunk of rows until none remain.
+    #[default]
+    Dynamic,
+}
+
+/// A worker thread panicked while computing a band of rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerPanic {
+    /// Rows of C the worker was computing when it panicked.
+    pub rows: Range<usize>,
+    /// The panic message, if the payload was a string.
+    pub message: String,
+}
+
+impl fmt::Display for WorkerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "worker panicked computing rows {}..{}: {}",
+            self.rows.start, self.rows.end, self.message
+        )
+    }
+}
+
+impl std::error::Error for WorkerPanic {}
+
+/// The multiply was abandoned because its cancel flag was set.
+///
+/// C's contents are unspecified afterwards: some row bands may have been
+/// fully computed, others partially or not at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "multiply was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Why [`for_each_band`] stopped before covering every row.
+#[derive(Debug)]
+pub(crate) enum BandError {
+    Panicked(WorkerPanic),
+    Cancelled,
+}
+
+/// Runs `f(row_start, row_end, c_band)` over every row of an m×n matrix C
+/// using `num_threads` threads.
+///
+/// C is split into disjoint row bands up front, so each call gets exclusive
+/// `&mut` access to exactly rows `row_start..row_end` (band-local: row
+/// `row_start` of C is row 0 of `c_band`). No two threads ever hold a
+/// reference to the same part of C.
+///
+/// Every band boundary except `m` itself is a multiple of `mr`, so only the
+/// last band has leftover rows for the scalar edge path. Each row is covered
+/// exactly once.
+///
+/// A panic inside `f` doesn't stop the other workers: every thread is joined
+/// before returning, and the first panic is reported with the band it hit.
+///
+/// If `cancel` is given, workers check it before starting each band and skip
+/// the band once it's set. With [`Schedule::Dynamic`] that's once per chunk,
+/// so cancellation takes effect within one chunk's worth of work.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn for_each_band<F>(
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    mr: usize,
+    num_threads: usize,
+    schedule: Schedule,
+    cancel: Option<&AtomicBool>,
+    f: F,
+) -> Result<(), BandError>
+where
+    F: Fn(usize, usize, &mut [f64]) + Sync,
+{
+    let failure = Mutex::new(None);
+    let skipped = AtomicBool::new(false);
+    // Workers start with the default FP mode and the global config; give
+    // them the caller's
+    let denormals = DenormalMode::current();
+    let config = config::current();
+    let run_band = |start: usize, end: usize, c_band: &mut [f64]| {
+        if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+            skipped.store(true, Ordering::Relaxed);
+            return;
+        }
+        let _mode = ModeGuard::set(denormals);
+        let _config = config::Scope::inherit(config);
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(start, end, c_band))) {
+            failure.lock().unwrap().get_or_insert(WorkerPanic {
+                rows: start..end,
+                message: panic_message(payload.as_ref()),
+            });
+        }
+    };
+
+    match schedule {
+        Schedule::Static => {
+            let bands = static_bands(m, mr, num_threads);
+            run_static(split_rows(c, n, &bands), &bands, &run_band)
+        }
+        Schedule::Dynamic => {
+            let bands = dynamic_chunks(m, CHUNK_ROWS.div_ceil(mr) * mr);
+            run_dynamic(split_rows(c, n, &bands), &bands, num_threads, &run_band)
+        }
+    }
+
+    if let Some(err) = failure.into_inner().unwrap() {
+        return Err(BandError::Panicked(err));
+    }
+    if skipped.into_inner() {
+        return Err(BandError::Cancelled);
+    }
+    Ok(())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+/// One contiguous band per thread. The band height is rounded up to a whole
+/// number of tiles; the last band takes whatever is left, including the rows
+/// that don't fill a tile.
+pub(crate) fn static_bands(m: usize, mr: usize, num_threads: usize) -> Vec<(usize, usize)> {
+    let rows_per_thread = m.div_ceil(num_threads).div_ceil(mr) * mr;
+
+    (0..num_threads)
+        .map(|tid| {
+            let start = (tid * rows_per_thread).min(m);
+            let end = if tid == num_threads - 1 {
+                m
+            } else {
+                (start + rows_per_thread).min(m)
+            };
+            (start, end)
+        })
+        .filter(|&(start, end)| start < end)
+        .collect()
+}
+
+fn dynamic_chunks(m: usize, chunk_rows: usize) -> Vec<(usize, usize)> {
+    (0..m)
+        .step_by(chunk_rows)
+        .map(|start| (start, (start + chunk_rows).min(m)))
+        .collect()
+}
+
+/// Splits C into one disjoint `&mut` slice per band. `bands` must be
+/// contiguous and start at row 0.
+fn split_rows<'c>(mut c: &'c mut [f64], n: usize, bands: &[(usize, usize)]) -> Vec<&'c mut [f64]> {
+    bands
+        .iter()
+        .map(|&(start, end)| {
+            let (band, rest) = std::mem::take(&mut c).split_at_mut((end - start) * n);
+            c = rest;
+            band
+        })
+        .collect()
+}
+
+fn run_static<F>(c_bands: Vec<&mut [f64]>, bands: &[(usize, usize)], f: &F)
+where
+    F: Fn(usize, usize, &mut [f64]) + Sync,
+{
+    thread::scope(|s| {
+        for (c_band, &(start, end)) in c_bands.into_iter().zip(bands) {
+            s.spawn(move || f(start, end, c_band));
+        }
+    });
+}
+
+fn run_dynamic<F>(c_bands: Vec<&mut [f64]>, bands: &[(usize, usize)], num_threads: usize, f: &F)
+where
+    F: Fn(usize, usize, &mut [f64]) + Sync,
+{
+    // Each slot is taken exactly once, by whichever thread claims that chunk
+    let slots: Vec<Mutex<Option<&mut [f64]>>> = c_bands
+        .into_iter()
+        .map(|band| Mutex::new(Some(band)))
+        .collect();
+    let chunks = ChunkCounter::new(bands.len());
+
+    thread::scope(|s| {
+        for _ in 0..num_threads.min(chunks.num_chunks) {
+            s.spawn(|| {
+                while let Some(chunk) = chunks.claim() {
+                    let c_band = slots[chunk].lock().unwrap().take().unwrap();
+                    let (start, end) = bands[chunk];
+                    f(start, end, c_band);
+                }
+            });
+        }
+    });
+}
+
+/// Hands out chunk indices `0..num_chunks`, each exactly once, to however
+/// many threads ask.
+struct ChunkCounter {
+    next: AtomicUsize,
+    num_chunks: usize,
+}
+
+impl ChunkCounter {
+    fn new(num_chunks: usize) -> Self {
+        Self {
+            next: AtomicUsize::new(0),
+            num_chunks,
+        }
+    }
+
+    /// Claims the next unclaimed chunk, or `None` once all are taken.
+    ///
+    /// Relaxed is enough: the counter only decides ownership. Each chunk's
+    /// rows of C are written by exactly one thread, and the scope join
+    /// publishes those writes to the caller.
+    fn claim(&self) -> Option<usize> {
+        let chunk = self.next.fetch_add(1, Ordering::Relaxed);
+        (chunk < self.num_chunks).then_some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn collect_bands(
+        m: usize,
+        mr: usize,
+        threads: usize,
+        schedule: Schedule,
+    ) -> Vec<(usize, usize)> {
+        let bands = Mutex::new(Vec::new());
+        let mut c = vec![0.0; m];
+        for_each_band(
+            &mut c,
+            m,
+            1,
+            mr,
+            threads,
+            schedule,
+            None,
+            |start, end, c_band| {
+                assert_eq!(c_band.len(), end - start);
+                bands.lock().unwrap().push((start, end));
+            },
+        )
+        .unwrap();
+        let mut bands = bands.into_inner().unwrap();
+        bands.sort();
+        bands
+    }
+
+    fn assert_covers_exactly_once(bands: &[(usize, usize)], m: usize, mr: usize) {
+        let mut next = 0;
+        for &(start, end) in bands {
+            assert_eq!(start, next, "gap or overlap at row {}", next);
+            assert!(start < end);
+            assert_eq!(start % mr, 0, "band starts mid-tile at row {}", start);
+            next = end;
+        }
+        assert_eq!(next, m);
+    }
+
+    #[test]
+    fn test_static_bands_cover_all_rows() {
+        for &(m, mr, threads) in &[
+            (256, 4, 4),
+            (257, 4, 4),
+            (130, 12, 3),
+            (5, 8, 4),
+            (1000, 8, 7),
+        ] {
+            let bands = collect_bands(m, mr, threads, Schedule::Static);
+            assert_covers_exactly_once(&bands, m, mr);
+        }
+    }
+
+    #[test]
+    fn test_dynamic_chunks_cover_all_rows() {
+        for &(m, mr, threads) in &[
+            (256, 4, 4),
+            (257, 4, 4),
+            (130, 12, 3),
+            (5, 8, 4),
+            (1000, 8, 7),
+        ] {
+            let bands = collect_bands(m, mr, threads, Schedule::Dynamic);
+            assert_covers_exactly_once(&bands, m, mr);
+        }
+    }
+
+    #[test]
+    fn test_dynamic_with_skewed_worker() {
+        // The thread that claims chunk 0 sleeps on every chunk it gets,
+        // simulating a core stolen by another process. The others should
+        // drain the queue instead of waiting for it.
+        let m = 64 * 16;
+        let slow_thread = Mutex::new(None);
+        let claimed = Mutex::new(Vec::new());
+
+        let mut c = vec![0.0; m];
+        for_each_band(
+            &mut c,
+            m,
+            1,
+            4,
+            4,
+            Schedule::Dynamic,
+            None,
+            |start, end, _| {
+                let me = thread::current().id();
+                if start == 0 {
+                    *slow_thread.lock().unwrap() = Some(me);
+                }
+                if *slow_thread.lock().unwrap() == Some(me) {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                claimed.lock().unwrap().push((start, end, me));
+            },
+        )
+        .unwrap();
+
+        let claimed = claimed.into_inner().unwrap();
+        let slow = slow_thread.into_inner().unwrap().unwrap();
+        let mut bands: Vec<_> = claimed.iter().map(|&(s, e, _)| (s, e)).collect();
+        bands.sort();
+        assert_covers_exactly_once(&bands, m, 4);
+
+        let slow_chunks = claimed.iter().filter(|&&(_, _, id)| id == slow).count();
+        assert!(slow_chunks < 16, "slow worker ended up doing every chunk");
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_chunk_claims_are_exclusive() {
+        use loom::sync::Arc;
+
+        loom::model(|| {
+            let chunks = Arc::new(ChunkCounter::new(3));
+
+            let workers: Vec<_> = (0..2)
+                .map(|_| {
+                    let chunks = Arc::clone(&chunks);
+                    loom::thread::spawn(move || {
+                        let mut claimed = Vec::new();
+                        while let Some(chunk) = chunks.claim() {
+                            claimed.push(chunk);
+                        }
+                        claimed
+                    })
+                })
+                .collect();
+
+            let mut all: Vec<usize> = workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect();
+            all.sort();
+            assert_eq!(all, vec![0, 1, 2]);
+        });
+    }
+}
@@ -0,0 +1,242 @@
+//! The path [`multiply`](crate::multiply) takes when m, n, and k are all at
+//! most [`TINY_MAX`].
+//!
+//! An 8×8 product is 512 multiply-adds, a few dozen nanoseconds. Through
+//! the blocked drivers, choosing a backend, fetching scratch buffers, and
+//! packing A and B cost several times that. Here there's none of it: the
+//! lengths are compared without building an error, nothing is allocated, and
+//! the operands are read where they lie, each row of C held in registers
+//! while the rows of B are added into it.
+//!
+//! The rounding is a blocked backend's, picked from the CPU alone. With AVX2
+//! and FMA it's the SIMD backends' (C's value plus one fused multiply-add
+//! per k step, in ascending k); without, the scalar backend's, whole 4×4
+//! tiles summed from zero and added to C, and leftover rows and columns
+//! rounded one product at a time. Asking
+//! [`backend_for`](crate::backend_for) would cost about what the shortcut
+//! saves, so a [calibrated](mod@crate::calibrate) table that sends small
+//! shapes to [`Backend::Scalar`](crate::Backend::Scalar) or
+//! [`Backend::ScalarIkj`](crate::Backend::ScalarIkj) isn't followed here,
+//! and the shortcut can show in C then.
+//!
+//! [`multiply_fixed`](crate::multiply_fixed) is faster still when the shape
+//! is known at compile time, but rounds like the i-k-j loop instead.
+
+use crate::error;
+use crate::matrix::low_rank::LOW_RANK_MAX_K;
+
+/// The largest m, n, and k [`multiply`](crate::multiply) runs on this path.
+pub const TINY_MAX: usize = 16;
+
+/// Whether an m×n×k multiply takes this path.
+#[inline]
+pub(crate) fn is_tiny(m: usize, n: usize, k: usize) -> bool {
+    m <= TINY_MAX && n <= TINY_MAX && k <= TINY_MAX
+}
+
+/// C += A·B for a shape [`is_tiny`] accepts, with the checks
+/// [`multiply`](crate::multiply) makes.
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m, n, k.
+#[inline]
+pub(crate) fn multiply_tiny(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    debug_assert!(is_tiny(m, n, k));
+    // The products are at most 16², so they can't overflow; the full check
+    // (which also looks for overlap in debug builds) only runs to report
+    if a.len() != m * k || b.len() != k * n || c.len() != m * n || cfg!(debug_assertions) {
+        error::assert_dims(a, b, c, m, n, k);
+    }
+    run(a, b, c, m, n, k);
+}
+
+/// [`multiply_tiny`] once the slices are checked.
+#[inline]
+pub(crate) fn run(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    if m == 0 || n == 0 || k == 0 {
+        return;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if crate::features::has_avx2() {
+        // Safety: just checked
+        unsafe { fused_any(a, b, c, m, n, k) };
+        return;
+    }
+    rounded(a, b, c, m, n, k);
+}
+
+/// The SIMD backends' rounding: one fused multiply-add per k step, for C
+/// `n` wide.
+///
+/// # Safety
+///
+/// The CPU must support AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+unsafe fn fused_any(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    // A constant width lets the compiler keep rows of C in registers; with
+    // the width in a variable they go through memory every k step
+    match n {
+        1 => unsafe { fused::<1>(a, b, c, m, k) },
+        2 => unsafe { fused::<2>(a, b, c, m, k) },
+        3 => unsafe { fused::<3>(a, b, c, m, k) },
+        4 => unsafe { fused::<4>(a, b, c, m, k) },
+        5 => unsafe { fused::<5>(a, b, c, m, k) },
+        6 => unsafe { fused::<6>(a, b, c, m, k) },
+        7 => unsafe { fused::<7>(a, b, c, m, k) },
+        8 => unsafe { fused::<8>(a, b, c, m, k) },
+        9 => unsafe { fused::<9>(a, b, c, m, k) },
+        10 => unsafe { fused::<10>(a, b, c, m, k) },
+        11 => unsafe { fused::<11>(a, b, c, m, k) },
+        12 => unsafe { fused::<12>(a, b, c, m, k) },
+        13 => unsafe { fused::<13>(a, b, c, m, k) },
+        14 => unsafe { fused::<14>(a, b, c, m, k) },
+        15 => unsafe { fused::<15>(a, b, c, m, k) },
+        16 => unsafe { fused::<16>(a, b, c, m, k) },
+        _ => unreachable!("{} columns is not tiny", n),
+    }
+}
+
+/// [`fused_any`] for C N wide: four rows at a time up to 8 columns (two
+/// past that, to stay within the 16 vector registers), so each k step has
+/// independent multiply-adds to overlap.
+///
+/// # Safety
+///
+/// The CPU must support AVX2 and FMA.
+#[cfg(target_arch = "x86_64")]
+#[cfg_attr(not(miri), target_feature(enable = "avx2,fma"))]
+unsafe fn fused<const N: usize>(a: &[f64], b: &[f64], c: &mut [f64], m: usize, k: usize) {
+    let rows = if N <= 8 { 4 } else { 2 };
+    let mut i = 0;
+    while i + rows <= m {
+        if N <= 8 {
+            fused_rows::<N, 4>(a, b, c, i, k);
+        } else {
+            fused_rows::<N, 2>(a, b, c, i, k);
+        }
+        i += rows;
+    }
+    for i in i..m {
+        fused_rows::<N, 1>(a, b, c, i, k);
+    }
+}
+
+/// Rows `i..i + R` of [`fused`].
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn fused_rows<const N: usize, const R: usize>(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    i: usize,
+    k: usize,
+) {
+    let c: &mut [[f64; N]; R] = c[i * N..][..R * N].as_chunks_mut().0.try_into().unwrap();
+    let a = &a[i * k..][..R * k];
+    let mut acc = *c;
+    for (p, b_row) in b[..k * N].as_chunks::<N>().0.iter().enumerate() {
+        for (r, acc_row) in acc.iter_mut().enumerate() {
+            let a_ip = a[r * k + p];
+            for (x, &b_pj) in acc_row.iter_mut().zip(b_row) {
+                *x = a_ip.mul_add(b_pj, *x);
+            }
+        }
+    }
+    *c = acc;
+}
+
+/// The scalar backend's rounding, without SIMD. For k up to
+/// [`LOW_RANK_MAX_K`] it takes the low-rank path, which rounds every
+/// product in turn.
+fn rounded(a: &[f64], b: &[f64], c: &mut [f64], m: usize, n: usize, k: usize) {
+    let (m_main, n_main) = if k <= LOW_RANK_MAX_K {
+        (0, 0)
+    } else {
+        (m / 4 * 4, n / 4 * 4)
+    };
+
+    for i in (0..m_main).step_by(4) {
+        for j in (0..n_main).step_by(4) {
+            let mut acc = [[0.0; 4]; 4];
+            for p in 0..k {
+                for (ii, row) in acc.iter_mut().enumerate() {
+                    let a_ip = a[(i + ii) * k + p];
+                    for (jj, x) in row.iter_mut().enumerate() {
+                        *x += a_ip * b[p * n + j + jj];
+                    }
+                }
+            }
+            for (ii, row) in acc.iter().enumerate() {
+                for (jj, &sum) in row.iter().enumerate() {
+                    c[(i + ii) * n + j + jj] += sum;
+                }
+            }
+        }
+    }
+
+    // Leftover columns of the tiled rows, then the leftover rows
+    for i in 0..m {
+        let from = if i < m_main { n_main } else { 0 };
+        for p in 0..k {
+            let a_ip = a[i * k + p];
+            for j in from..n {
+                c[i * n + j] += a_ip * b[p * n + j];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::generate::random;
+    use crate::{Backend, multiply_with_backend};
+
+    /// Every tiny shape from a non-zero C, against the backend whose
+    /// rounding each path copies.
+    #[cfg_attr(miri, ignore = "1,792 shapes; tests/miri.rs covers it")]
+    #[test]
+    fn test_matches_the_blocked_backends() {
+        for m in 1..=TINY_MAX {
+            for n in 1..=TINY_MAX {
+                for k in [1, 3, 4, 5, 8, 13, 16] {
+                    let a = random(m, k, 1);
+                    let b = random(k, n, 2);
+                    let c0 = random(m, n, 3);
+
+                    let mut want = c0.clone();
+                    multiply_with_backend(Backend::Scalar, &a, &b, &mut want, m, n, k);
+                    let mut c = c0.clone();
+                    rounded(&a, &b, &mut c, m, n, k);
+                    assert_eq!(c, want, "scalar {}x{}x{}", m, n, k);
+
+                    #[cfg(target_arch = "x86_64")]
+                    if crate::features::has_avx2() {
+                        let mut want = c0.clone();
+                        multiply_with_backend(Backend::Avx2_12x4, &a, &b, &mut want, m, n, k);
+                        let mut c = c0.clone();
+                        unsafe { fused_any(&a, &b, &mut c, m, n, k) };
+                        assert_eq!(c, want, "fused {}x{}x{}", m, n, k);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_tiny() {
+        assert!(is_tiny(16, 16, 16));
+        assert!(is_tiny(0, 1, 16));
+        assert!(!is_tiny(17, 1, 1));
+        assert!(!is_tiny(1, 1, 17));
+    }
+
+    #[test]
+    #[should_panic(expected = "B")]
+    fn test_wrong_length_panics() {
+        let mut c = [0.0; 4];
+        multiply_tiny(&[0.0; 4], &[0.0; 3], &mut c, 2, 2, 2);
+    }
+}
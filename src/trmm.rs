@@ -0,0 +1,338 @@
+//! Multiplying by a triangular matrix.
+//!
+//! Half of a triangular matrix is structural zeros, so a general multiply
+//! spends about half its FLOPs on them. [`trmm`] runs the same blocked
+//! drivers as [`multiply`](crate::multiply) on one slice of the triangle at
+//! a time, each against only the part of C it reaches.
+
+use crate::builder::run_rows;
+use crate::workspace::Workspace;
+use crate::{detected_backend, error};
+use std::ops::Range;
+
+/// Which side of the product the triangular matrix is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// C += T·A
+    Left,
+    /// C += A·T
+    Right,
+}
+
+/// Which triangle of a matrix holds its entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Uplo {
+    /// On and above the diagonal
+    Upper,
+    /// On and below the diagonal
+    Lower,
+}
+
+/// Whether a triangular matrix's diagonal is stored or all ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Diag {
+    /// Read from the matrix
+    NonUnit,
+    /// All ones, and not read (a unit-triangular factor from LU, say)
+    Unit,
+}
+
+/// C += T·A (`Side::Left`) or C += A·T (`Side::Right`), for T triangular.
+///
+/// A and C are m×n, row-major. T is square, m×m on the left and n×n on the
+/// right, and only its `uplo` triangle is read (without the diagonal, for
+/// `Diag::Unit`); the other triangle can hold anything. The multiply skips
+/// the zero triangle, so it does about half the FLOPs of a
+/// [`multiply`](crate::multiply) by T with its zeros filled in, and
+/// matches that to rounding. On the right, each block of C's columns is
+/// copied out and back (with the columns of A it needs), so the saving is
+/// smaller there than on the left.
+///
+/// ```
+/// use matmul::{Diag, Side, Uplo, trmm};
+///
+/// // A 2×2 upper-triangular T; the lower entry is never read
+/// let t = [1.0, 2.0, f64::NAN, 3.0];
+/// let a = [1.0, 1.0];
+/// let mut c = [0.0; 2];
+/// trmm(&a, &t, &mut c, 1, 2, Side::Right, Uplo::Upper, Diag::NonUnit);
+/// assert_eq!(c, [1.0, 5.0]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the slice sizes don't match m and n, or if a product of them
+/// overflows `usize`.
+#[allow(clippy::too_many_arguments)]
+pub fn trmm(
+    a: &[f64],
+    t: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    side: Side,
+    uplo: Uplo,
+    diag: Diag,
+) {
+    let order = match side {
+        Side::Left => m,
+        Side::Right => n,
+    };
+    if let Err(e) = error::check_operands([
+        ("A", a.len(), m, n),
+        ("T", t.len(), order, order),
+        ("C", c.len(), m, n),
+    ]) {
+        panic!("{}", e);
+    }
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let backend = detected_backend();
+    #[cfg(feature = "tracing")]
+    crate::backend::trace_dispatch(backend, 1, m, n, order);
+    let (kc, mc) = backend.block_sizes(usize::MAX);
+    let mut workspace = Workspace::new();
+    let mut t_panel = Vec::new();
+    // Columns of A and C for the right-hand side
+    let (mut a_cols, mut c_cols) = (Vec::new(), Vec::new());
+    // T a kc-wide slice at a time, rows of it on the left and columns on
+    // the right, each multiplying only the rows (or columns) of C it reaches
+    for start in (0..order).step_by(kc) {
+        let end = (start + kc).min(order);
+        match side {
+            Side::Left => {
+                // C[rows] += T[rows, start..end]·A[start..end]
+                let rows = match uplo {
+                    Uplo::Upper => 0..end,
+                    Uplo::Lower => start..order,
+                };
+                t_panel.clear();
+                for i in rows.clone() {
+                    copy_row(
+                        &mut t_panel,
+                        &t[i * order..(i + 1) * order],
+                        i,
+                        start..end,
+                        uplo,
+                        diag,
+                    );
+                }
+                // Safety: the detected backend is available, and the panel's
+                // shapes are consistent by construction
+                unsafe {
+                    run_rows(
+                        backend,
+                        &t_panel,
+                        &a[start * n..end * n],
+                        &mut c[rows.start * n..rows.end * n],
+                        rows.len(),
+                        n,
+                        end - start,
+                        None,
+                        None,
+                        kc,
+                        mc,
+                        &mut workspace,
+                    )
+                };
+            }
+            Side::Right => {
+                // C[:, start..end] += A[:, depth]·T[depth, start..end], with
+                // the columns of A and C copied out and C's copied back
+                let depth = match uplo {
+                    Uplo::Upper => 0..end,
+                    Uplo::Lower => start..order,
+                };
+                let width = end - start;
+                t_panel.clear();
+                for p in depth.clone() {
+                    copy_row(
+                        &mut t_panel,
+                        &t[p * order..(p + 1) * order],
+                        p,
+                        start..end,
+                        uplo,
+                        diag,
+                    );
+                }
+                c_cols.clear();
+                for c_row in c.chunks_exact(n) {
+                    c_cols.extend_from_slice(&c_row[start..end]);
+                }
+                // The block reaching all of T's rows uses all of A
+                let a_block = if depth.len() == n {
+                    a
+                } else {
+                    a_cols.clear();
+                    for a_row in a.chunks_exact(n) {
+                        a_cols.extend_from_slice(&a_row[depth.clone()]);
+                    }
+                    &a_cols
+                };
+                // Safety: as above
+                unsafe {
+                    run_rows(
+                        backend,
+                        a_block,
+                        &t_panel,
+                        &mut c_cols,
+                        m,
+                        width,
+                        depth.len(),
+                        None,
+                        None,
+                        kc,
+                        mc,
+                        &mut workspace,
+                    )
+                };
+                for (row, block_row) in c.chunks_exact_mut(n).zip(c_cols.chunks_exact(width)) {
+                    row[start..end].copy_from_slice(block_row);
+                }
+            }
+        }
+    }
+}
+
+/// Appends columns `cols` of row `i` of a triangular matrix, whose full row
+/// is `row`: zeros outside the `uplo` triangle, and a one on the diagonal for
+/// `Diag::Unit`. Entries outside the triangle aren't read.
+fn copy_row(
+    panel: &mut Vec<f64>,
+    row: &[f64],
+    i: usize,
+    cols: Range<usize>,
+    uplo: Uplo,
+    diag: Diag,
+) {
+    panel.extend(cols.map(|j| {
+        let stored = match uplo {
+            Uplo::Upper => j > i,
+            Uplo::Lower => j < i,
+        };
+        if j == i {
+            match diag {
+                Diag::NonUnit => row[i],
+                Diag::Unit => 1.0,
+            }
+        } else if stored {
+            row[j]
+        } else {
+            0.0
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::compare::assert_close;
+    use crate::matrix::generate::random;
+    use crate::multiply;
+
+    /// `t` (order × order) with the triangle opposite `uplo` zeroed and,
+    /// for `Diag::Unit`, ones on the diagonal: the dense matrix `trmm`
+    /// multiplies by.
+    fn dense(t: &[f64], order: usize, uplo: Uplo, diag: Diag) -> Vec<f64> {
+        let mut dense = t.to_vec();
+        for i in 0..order {
+            for p in 0..order {
+                let zero = match uplo {
+                    Uplo::Upper => p < i,
+                    Uplo::Lower => p > i,
+                };
+                if zero {
+                    dense[i * order + p] = 0.0;
+                } else if p == i && diag == Diag::Unit {
+                    dense[i * order + p] = 1.0;
+                }
+            }
+        }
+        dense
+    }
+
+    /// The unread part of T: the opposite triangle, and the diagonal for
+    /// `Diag::Unit`.
+    fn unread(i: usize, p: usize, uplo: Uplo, diag: Diag) -> bool {
+        let opposite = match uplo {
+            Uplo::Upper => p < i,
+            Uplo::Lower => p > i,
+        };
+        opposite || (p == i && diag == Diag::Unit)
+    }
+
+    #[test]
+    fn test_matches_general_multiply() {
+        // Several row blocks natively, with a ragged last one
+        let shapes: &[(usize, usize)] = if cfg!(miri) {
+            &[(7, 5), (1, 1)]
+        } else {
+            &[(300, 45), (45, 300), (1, 1), (129, 129)]
+        };
+        for &(m, n) in shapes {
+            let a = random(m, n, 1);
+            let c0 = random(m, n, 3);
+            for side in [Side::Left, Side::Right] {
+                let order = if side == Side::Left { m } else { n };
+                for uplo in [Uplo::Upper, Uplo::Lower] {
+                    for diag in [Diag::NonUnit, Diag::Unit] {
+                        let t = random(order, order, 2);
+                        let full = dense(&t, order, uplo, diag);
+                        let mut want = c0.clone();
+                        match side {
+                            Side::Left => multiply(&full, &a, &mut want, m, n, m),
+                            Side::Right => multiply(&a, &full, &mut want, m, n, n),
+                        }
+
+                        // NaN wherever T isn't read, so reading it shows
+                        let mut poisoned = t.clone();
+                        for i in 0..order {
+                            for p in 0..order {
+                                if unread(i, p, uplo, diag) {
+                                    poisoned[i * order + p] = f64::NAN;
+                                }
+                            }
+                        }
+                        let mut c = c0.clone();
+                        trmm(&a, &poisoned, &mut c, m, n, side, uplo, diag);
+                        assert_close(&want, &c, 1e-12, 1e-12);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_sized() {
+        let mut c: [f64; 0] = [];
+        trmm(&[], &[], &mut c, 0, 3, Side::Left, Uplo::Upper, Diag::Unit);
+        trmm(
+            &[],
+            &[],
+            &mut c,
+            3,
+            0,
+            Side::Right,
+            Uplo::Lower,
+            Diag::NonUnit,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "T")]
+    fn test_wrong_triangle_size() {
+        let mut c = vec![0.0; 6];
+        trmm(
+            &[0.0; 6],
+            &[0.0; 4],
+            &mut c,
+            2,
+            3,
+            Side::Right,
+            Uplo::Upper,
+            Diag::NonUnit,
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Paying the first multiply's one-time costs when it suits the caller.
+//!
+//! The first [`multiply`](crate::multiply) in a process does work the later
+//! ones skip: it detects the CPU's features and picks a backend, allocates
+//! the calling thread's scratch buffers, and takes a page fault on every
+//! page of them as packing first writes it. For a tool that multiplies once,
+//! that's most of the call. [`warmup`] does the detection ahead of time, and
+//! [`warmup_for`] the allocation and faulting as well.
+//!
+//! The benchmark runner's `--mode first-call` measures what's left: the
+//! first call in a fresh process, with and without these, against the steady
+//! state.
+
+use crate::workspace::{scratch_bytes, with_scratch};
+use crate::{Backend, cpu_features, detected_backend};
+
+/// Detects the CPU's features and the backend [`multiply`](crate::multiply)
+/// will run, so the first multiply doesn't. Cheap to call again; returns the
+/// backend.
+///
+/// ```
+/// let backend = matmul::warmup();
+/// assert_eq!(backend, matmul::detected_backend());
+/// ```
+pub fn warmup() -> Backend {
+    cpu_features();
+    detected_backend()
+}
+
+/// [`warmup`], then grows the calling thread's scratch cache to what an
+/// m×n×k [`multiply`](crate::multiply) packs into, writing every page, so
+/// that multiply on this thread neither allocates nor faults in scratch
+/// memory. Returns the bytes now cached on this thread.
+///
+/// The cache is per thread: warm up on the thread that will multiply. A
+/// cache that would pass [`set_scratch_cache_cap`](crate::set_scratch_cache_cap)
+/// isn't kept, so the call returns what was cached before. The threaded entry
+/// points' workers have caches of their own, which this doesn't touch, and C
+/// is the caller's: a freshly allocated C still faults in on first write.
+///
+/// ```
+/// let (m, n, k) = (256, 256, 256);
+/// // At startup, before the latency counts
+/// matmul::warmup_for(m, n, k);
+///
+/// let (a, b) = (vec![1.0; m * k], vec![1.0; k * n]);
+/// let mut c = vec![0.0; m * n];
+/// matmul::multiply(&a, &b, &mut c, m, n, k);
+/// ```
+pub fn warmup_for(m: usize, n: usize, k: usize) -> usize {
+    let backend = warmup();
+    if m > 0 && n > 0 && k > 0 {
+        with_scratch(|workspace| {
+            workspace.reserve_for(backend, m, n, k, crate::config::block_sizes(backend, k))
+        });
+    }
+    scratch_bytes()
+}
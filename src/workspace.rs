@@ -0,0 +1,335 @@
+//! Scratch buffers for the blocked drivers, kept between calls.
+//!
+//! Every blocked multiply transposes B and packs panels of A and B before
+//! any arithmetic. A [`Workspace`] holds on to those buffers, so a caller
+//! multiplying over and over (a [`MatMul`](crate::MatMul), say) pays for the
+//! allocations once.
+//!
+//! The plain entry points ([`multiply`](crate::multiply) and friends) borrow
+//! a workspace cached per thread instead, so repeating a shape doesn't
+//! reallocate either. The cache keeps whatever the last multiply grew it to,
+//! up to [`set_scratch_cache_cap`] bytes; [`clear_scratch_cache`] releases
+//! it.
+//!
+//! A threaded multiply transposes B once, into the calling thread's
+//! workspace (`transpose_into_workspace`), and every band packs from that
+//! copy; the workers' own workspaces hold just their panels.
+
+use crate::epilogue::Epilogue;
+use crate::matrix::low_rank;
+use crate::matrix::transpose::transpose;
+use crate::{Backend, MatMulError};
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The per-thread scratch cache's starting cap: 64 MiB, enough for the
+/// buffers of a 2048² multiply.
+pub const DEFAULT_SCRATCH_CACHE_CAP: usize = 64 << 20;
+
+/// The largest Bᵀ the blocked drivers make, in bytes: 256 MiB, B for a
+/// 5792² multiply. Past it they pack B's panels straight from B, which needs
+/// no k×n copy but runs about 10% slower at 1024² and up. They never make
+/// one for a single row or column of B, which is laid out like its
+/// transpose already.
+pub const TRANSPOSE_B_MAX_BYTES: usize = 256 << 20;
+
+static SCRATCH_CACHE_CAP: AtomicUsize = AtomicUsize::new(DEFAULT_SCRATCH_CACHE_CAP);
+
+thread_local! {
+    /// The workspace the plain entry points pack into on this thread
+    static SCRATCH: Cell<Workspace> = const { Cell::new(Workspace::new()) };
+}
+
+/// Sets the most each thread's scratch cache keeps between multiplies, in
+/// bytes, for every thread.
+///
+/// A multiply that needs more still gets it, but frees its buffers when
+/// done rather than caching them; one that fits leaves them cached, grown to
+/// the largest shape seen so far. 0 turns caching off. Caches already over
+/// a lowered cap shrink at their thread's next multiply.
+pub fn set_scratch_cache_cap(bytes: usize) {
+    SCRATCH_CACHE_CAP.store(bytes, Ordering::Relaxed);
+}
+
+/// Frees the calling thread's scratch cache. Other threads' caches are
+/// untouched; the threaded entry points' workers free theirs when they
+/// exit.
+pub fn clear_scratch_cache() {
+    // During thread teardown the cache may already be gone
+    let _ = SCRATCH.try_with(Cell::take);
+}
+
+/// Heap bytes in the calling thread's scratch cache.
+pub(crate) fn scratch_bytes() -> usize {
+    SCRATCH
+        .try_with(|cache| {
+            let workspace = cache.take();
+            let bytes = workspace.bytes();
+            cache.set(workspace);
+            bytes
+        })
+        .unwrap_or(0)
+}
+
+/// Runs `f` on this thread's cached workspace, and keeps the result cached
+/// if it's within the cap.
+pub(crate) fn with_scratch<R>(f: impl FnOnce(&mut Workspace) -> R) -> R {
+    // Taken rather than borrowed, so a multiply nested in `f` (or a panic
+    // out of it) just finds the cache empty
+    let mut workspace = SCRATCH.try_with(Cell::take).unwrap_or_default();
+    let result = f(&mut workspace);
+    if workspace.bytes() <= SCRATCH_CACHE_CAP.load(Ordering::Relaxed) {
+        let _ = SCRATCH.try_with(|cache| cache.set(workspace));
+    }
+    result
+}
+
+/// The transposed B and the A and B panels a blocked driver packs into.
+///
+/// Buffers grow to the largest shape seen and are never shrunk; drop the
+/// workspace to release them. Contents don't carry over between multiplies:
+/// every buffer is fully written before it's read.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    bt: Vec<f64>,
+    a_panel: Vec<f64>,
+    b_pack: Vec<f64>,
+    /// A's block columns and the partial products, for
+    /// [`Accumulation::Pairwise`](crate::Accumulation::Pairwise)
+    pub(crate) pairwise: Vec<f64>,
+    /// Pack B's panels straight from B at every size, never making Bᵀ
+    pub(crate) lean: bool,
+    /// Ignore C's contents (beta = 0): the first k block is stored into C
+    /// rather than added to it, so C is never read
+    pub(crate) overwrite: bool,
+    /// Applied to each tile of C once its last k block is in
+    pub(crate) epilogue: Option<Arc<Epilogue>>,
+}
+
+impl Workspace {
+    /// An empty workspace. Allocates nothing until first used.
+    pub const fn new() -> Workspace {
+        Workspace {
+            bt: Vec::new(),
+            a_panel: Vec::new(),
+            b_pack: Vec::new(),
+            pairwise: Vec::new(),
+            lean: false,
+            overwrite: false,
+            epilogue: None,
+        }
+    }
+
+    /// Heap bytes held.
+    pub fn bytes(&self) -> usize {
+        (self.bt.capacity()
+            + self.a_panel.capacity()
+            + self.b_pack.capacity()
+            + self.pairwise.capacity())
+            * size_of::<f64>()
+    }
+
+    /// Grows the buffers to what `backend` needs for `rows` rows of C in a
+    /// multiply with inner dimension `k`, `n` columns, and the given block
+    /// sizes, so running it allocates nothing.
+    pub(crate) fn reserve_for(
+        &mut self,
+        backend: Backend,
+        rows: usize,
+        n: usize,
+        k: usize,
+        blocking: (usize, usize),
+    ) {
+        let transpose_b = self.transposes_b(k, n);
+        if let Some([bt, a_panel, b_pack]) = lengths(backend, rows, n, k, blocking, transpose_b) {
+            self.buffers(bt, a_panel, b_pack);
+        }
+    }
+
+    /// [`reserve_for`](Workspace::reserve_for), but an allocation failure is
+    /// an error rather than an abort. The error's `requested_bytes` is what
+    /// the whole workspace needed; buffers that did grow stay grown.
+    pub(crate) fn try_reserve_for(
+        &mut self,
+        backend: Backend,
+        rows: usize,
+        n: usize,
+        k: usize,
+        blocking: (usize, usize),
+    ) -> Result<(), MatMulError> {
+        let transpose_b = self.transposes_b(k, n);
+        let Some(lengths) = lengths(backend, rows, n, k, blocking, transpose_b) else {
+            return Ok(());
+        };
+        let out_of_memory = || MatMulError::OutOfMemory {
+            requested_bytes: lengths
+                .iter()
+                .fold(0usize, |sum, &len| sum.saturating_add(len))
+                .saturating_mul(size_of::<f64>()),
+        };
+        for (buffer, len) in [&mut self.bt, &mut self.a_panel, &mut self.b_pack]
+            .into_iter()
+            .zip(lengths)
+        {
+            if buffer.len() < len {
+                buffer
+                    .try_reserve_exact(len - buffer.len())
+                    .map_err(|_| out_of_memory())?;
+                buffer.resize(len, 0.0);
+            }
+        }
+        Ok(())
+    }
+
+    /// [`try_reserve_for`](Workspace::try_reserve_for) for a run that packs B
+    /// without its transpose if `lean`, or if there's no memory for it:
+    /// then the buffers are dropped and reserved again without Bᵀ. Returns
+    /// whether the run has to be lean.
+    pub(crate) fn try_reserve_or_lean(
+        &mut self,
+        backend: Backend,
+        rows: usize,
+        n: usize,
+        k: usize,
+        blocking: (usize, usize),
+        mut lean: bool,
+    ) -> Result<bool, MatMulError> {
+        self.lean = lean;
+        let mut reserved = self.try_reserve_for(backend, rows, n, k, blocking);
+        if reserved.is_err() && !lean {
+            *self = Workspace::new();
+            self.lean = true;
+            lean = true;
+            reserved = self.try_reserve_for(backend, rows, n, k, blocking);
+        }
+        self.lean = false;
+        reserved.map(|()| lean)
+    }
+
+    /// Heap bytes [`reserve_for`](Workspace::reserve_for) grows an empty
+    /// workspace to: what one band of `rows` rows allocates when it runs
+    /// without a workspace of its own. With `lean`, what it needs when it
+    /// doesn't make Bᵀ.
+    pub(crate) fn bytes_for(
+        backend: Backend,
+        rows: usize,
+        n: usize,
+        k: usize,
+        blocking: (usize, usize),
+        lean: bool,
+    ) -> usize {
+        let transpose_b = !lean && worth_transposing(k, n);
+        lengths(backend, rows, n, k, blocking, transpose_b).map_or(0, |lengths| {
+            lengths
+                .iter()
+                .fold(0usize, |sum, &len| sum.saturating_add(len))
+                .saturating_mul(size_of::<f64>())
+        })
+    }
+
+    /// Grows the Bᵀ buffer to what [`transpose_into_workspace`] fills for a
+    /// k×n B, if it makes one.
+    pub(crate) fn reserve_transpose(&mut self, k: usize, n: usize) {
+        if self.transposes_b(k, n) {
+            self.buffers(k * n, 0, 0);
+        }
+    }
+
+    /// [`bytes_for`](Workspace::bytes_for) across `threads` bands of up to
+    /// `rows` rows each, which share one Bᵀ: every band's panels, and Bᵀ
+    /// once.
+    pub(crate) fn bytes_for_bands(
+        backend: Backend,
+        rows: usize,
+        n: usize,
+        k: usize,
+        blocking: (usize, usize),
+        lean: bool,
+        threads: usize,
+    ) -> usize {
+        let panels = Workspace::bytes_for(backend, rows, n, k, blocking, true);
+        let bt = Workspace::bytes_for(backend, rows, n, k, blocking, lean) - panels;
+        panels.saturating_mul(threads).saturating_add(bt)
+    }
+
+    /// Whether the drivers make Bᵀ for a k×n B with this workspace, or pack
+    /// its panels straight from B.
+    pub(crate) fn transposes_b(&self, k: usize, n: usize) -> bool {
+        !self.lean && worth_transposing(k, n)
+    }
+
+    /// Buffers for Bᵀ, the A panel, and the B panel, of exactly these
+    /// lengths, growing whichever is too short.
+    pub(crate) fn buffers(
+        &mut self,
+        bt: usize,
+        a_panel: usize,
+        b_pack: usize,
+    ) -> (&mut [f64], &mut [f64], &mut [f64]) {
+        for (buffer, len) in [
+            (&mut self.bt, bt),
+            (&mut self.a_panel, a_panel),
+            (&mut self.b_pack, b_pack),
+        ] {
+            if buffer.len() < len {
+                buffer.resize(len, 0.0);
+            }
+        }
+        (
+            &mut self.bt[..bt],
+            &mut self.a_panel[..a_panel],
+            &mut self.b_pack[..b_pack],
+        )
+    }
+}
+
+/// B (k×n) transposed into `workspace`, for row bands on other threads to
+/// pack from rather than each transposing B again. `None` where the drivers
+/// wouldn't make Bᵀ at all: a lean workspace, a B past
+/// [`TRANSPOSE_B_MAX_BYTES`], or a single row or column of B.
+pub(crate) fn transpose_into_workspace<'w>(
+    b: &[f64],
+    k: usize,
+    n: usize,
+    workspace: &'w mut Workspace,
+) -> Option<&'w [f64]> {
+    if !workspace.transposes_b(k, n) {
+        return None;
+    }
+    let (bt, _, _) = workspace.buffers(k * n, 0, 0);
+    transpose(b, bt, k, n);
+    Some(bt)
+}
+
+/// Whether a k×n Bᵀ is worth making: within [`TRANSPOSE_B_MAX_BYTES`], and
+/// not a single row or column, whose transpose is the same values in the
+/// same order.
+pub(crate) fn worth_transposing(k: usize, n: usize) -> bool {
+    k > 1
+        && n > 1
+        && k.checked_mul(n)
+            .and_then(|len| len.checked_mul(size_of::<f64>()))
+            .is_some_and(|bytes| bytes <= TRANSPOSE_B_MAX_BYTES)
+}
+
+/// Lengths of Bᵀ (0 without `transpose_b`) and the A and B panels the
+/// blocked driver for `backend` packs `rows` rows of C into, or `None` if it
+/// packs nothing.
+fn lengths(
+    backend: Backend,
+    rows: usize,
+    n: usize,
+    k: usize,
+    (kc, mc): (usize, usize),
+    transpose_b: bool,
+) -> Option<[usize; 3]> {
+    // The i-k-j loop and the low-rank path pack nothing
+    if backend == Backend::ScalarIkj || low_rank::uses_low_rank(backend, k) {
+        return None;
+    }
+    let (mr, nr) = backend.tile();
+    let kc = k.min(kc);
+    let bt = if transpose_b { k * n } else { 0 };
+    Some([bt, mc.min(rows / mr * mr) * kc, nr * kc])
+}
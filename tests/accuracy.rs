@@ -0,0 +1,289 @@
+//! Rounding error of every backend against an extended-precision reference.
+//!
+//! The assertions run by default. The error-growth table is ignored; print it
+//! with
+//!
+//! ```text
+//! cargo test --release --test accuracy -- --ignored --nocapture
+//! ```
+
+// Thousands-deep dot products: hours under Miri
+#![cfg(not(miri))]
+
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::simple_simd;
+use matmul::matrix::generate::random;
+use matmul::matrix::reference::{ErrorReport, error_bound, matmul_reference, measure};
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::{
+    Accumulation, Backend, MatMul, Tuning, available_backends, multiply_parallel,
+    multiply_with_backend,
+};
+
+type Multiply = Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>;
+
+/// Every backend this CPU runs, plus the threaded entry points.
+fn backends() -> Vec<(String, Multiply)> {
+    let mut entries: Vec<(String, Multiply)> = vec![
+        (
+            "multiply_parallel".to_string(),
+            Box::new(|a, b, c, m, n, k| multiply_parallel(a, b, c, m, n, k, 4)),
+        ),
+        (
+            "matmul_naive_ikj_mt".to_string(),
+            Box::new(|a, b, c, m, n, k| matmul_naive_ikj_mt(a, b, c, m, n, k, 4)),
+        ),
+    ];
+    for backend in [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ] {
+        if backend.is_available() {
+            entries.insert(
+                0,
+                (
+                    backend.to_string(),
+                    Box::new(move |a, b, c, m, n, k| {
+                        multiply_with_backend(backend, a, b, c, m, n, k);
+                    }),
+                ),
+            );
+        }
+    }
+    entries
+}
+
+/// Seeded inputs for an m×k by k×n product, of three kinds:
+///
+/// - `"uniform"`: values in [-1, 1)
+/// - `"ill-scaled"`: the same, times powers of two from 2⁻⁴⁰ to 2⁴⁰, so a
+///   few huge products swamp the rest
+/// - `"cancelling"`: each dot product's second half nearly undoes its first,
+///   so C is tiny next to |A|·|B|
+fn inputs(kind: &str, m: usize, n: usize, k: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut a = random(m, k, 1);
+    let mut b = random(k, n, 2);
+    match kind {
+        "uniform" => {}
+        "ill-scaled" => {
+            let exponents = random(m * k + k * n, 1, 3);
+            for (x, e) in a.iter_mut().chain(b.iter_mut()).zip(exponents) {
+                *x *= 2f64.powi((e * 40.0) as i32);
+            }
+        }
+        "cancelling" => {
+            // a[i][k/2 + p] = -a[i][p] and b[k/2 + p][j] ≈ b[p][j]
+            let half = k / 2;
+            let nudge = random(k, n, 4);
+            for i in 0..m {
+                for p in 0..half {
+                    a[i * k + half + p] = -a[i * k + p];
+                }
+            }
+            for p in 0..half {
+                for j in 0..n {
+                    b[(half + p) * n + j] = b[p * n + j] * (1.0 + 1e-6 * nudge[p * n + j]);
+                }
+            }
+        }
+        _ => unreachable!("unknown input kind {}", kind),
+    }
+    (a, b)
+}
+
+const KINDS: [&str; 3] = ["uniform", "ill-scaled", "cancelling"];
+
+fn run(f: &Multiply, a: &[f64], b: &[f64], m: usize, n: usize, k: usize) -> ErrorReport {
+    let mut c = vec![0.0; m * n];
+    f(a, b, &mut c, m, n, k);
+    measure(a, b, &c, m, n, k)
+}
+
+#[test]
+fn test_reference_rounds_exact_integer_products() {
+    // Integers up to 2⁴⁰ give products up to 2⁸⁰, which f64 can't sum
+    // exactly but i128 can
+    let (m, n, k) = (5, 6, 300);
+    let to_int = |x: f64| (x * 2f64.powi(40)).trunc();
+    let a: Vec<f64> = random(m, k, 1).into_iter().map(to_int).collect();
+    let b: Vec<f64> = random(k, n, 2).into_iter().map(to_int).collect();
+
+    let reference = matmul_reference(&a, &b, m, n, k);
+    for i in 0..m {
+        for j in 0..n {
+            let exact: i128 = (0..k)
+                .map(|p| a[i * k + p] as i128 * b[p * n + j] as i128)
+                .sum();
+            let got = reference[i * n + j];
+            // Within one rounding of the exact sum
+            assert!(
+                (got - exact as f64).abs() <= (exact as f64).abs() * f64::EPSILON,
+                "({}, {}): {} vs {}",
+                i,
+                j,
+                got,
+                exact
+            );
+        }
+    }
+}
+
+#[test]
+fn test_every_backend_within_error_bound() {
+    // Tiles of every kernel, plus edges; k from one term to several KC blocks
+    let (m, n) = (26, 19);
+    for k in [1, 7, 256, 300, 1031] {
+        for kind in KINDS {
+            let (a, b) = inputs(kind, m, n, k);
+            for (name, f) in backends() {
+                let report = run(&f, &a, &b, m, n, k);
+                assert!(
+                    report.scaled <= error_bound(k),
+                    "{} on {} data, k = {}: {} ulps of |A|·|B|, bound {}",
+                    name,
+                    kind,
+                    k,
+                    report.scaled_ulps(),
+                    k
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_single_term_products_are_exact() {
+    // k = 1 rounds once, in the multiply, so C is correctly rounded
+    let (m, n) = (13, 9);
+    let (a, b) = inputs("ill-scaled", m, n, 1);
+    for (name, f) in backends() {
+        let report = run(&f, &a, &b, m, n, 1);
+        assert!(report.relative <= f64::EPSILON / 2.0, "{}", name);
+    }
+}
+
+#[test]
+#[ignore = "prints a table; run with --ignored --nocapture"]
+fn accuracy_table() {
+    let (m, n) = (24, 24);
+    println!(
+        "{:<22} {:>7} {:<10} {:>12} {:>10} {:>12}",
+        "backend", "k", "data", "scaled ulps", "ulps / k", "relative"
+    );
+    for (name, f) in backends() {
+        for k in [16, 256, 1024, 4096, 16384, 65536] {
+            for kind in KINDS {
+                let (a, b) = inputs(kind, m, n, k);
+                let report = run(&f, &a, &b, m, n, k);
+                println!(
+                    "{:<22} {:>7} {:<10} {:>12.3} {:>10.5} {:>12.3e}",
+                    name,
+                    k,
+                    kind,
+                    report.scaled_ulps(),
+                    report.scaled_ulps() / k as f64,
+                    report.relative
+                );
+                assert!(report.scaled <= error_bound(k));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_pairwise_accumulation_error() {
+    // A million same-signed terms: the sequential sum's rounding errors
+    // pile up in one direction, while pairwise adds each kc-deep block's
+    // sum into a tree only log₂(k / kc) deep
+    let (m, n, k) = (4, 4, 1 << 20);
+    let a: Vec<f64> = random(m, k, 1).iter().map(|x| x.abs() + 0.5).collect();
+    let b: Vec<f64> = random(k, n, 2).iter().map(|x| x.abs() + 0.5).collect();
+    let reports: Vec<ErrorReport> = [Accumulation::Sequential, Accumulation::Pairwise]
+        .into_iter()
+        .map(|accumulation| {
+            let mut matmul = MatMul::new().accumulation(accumulation).build().unwrap();
+            let c = matmul.compute(&a, &b, m, n, k).unwrap();
+            measure(&a, &b, &c, m, n, k)
+        })
+        .collect();
+    // Measured: about 400 ulps sequential, 2 pairwise
+    let (sequential, pairwise) = (reports[0].scaled_ulps(), reports[1].scaled_ulps());
+    assert!(
+        pairwise * 20.0 < sequential,
+        "pairwise {} ulps, sequential {}",
+        pairwise,
+        sequential
+    );
+}
+
+#[test]
+fn test_simd_backends_agree_bitwise() {
+    // Every SIMD backend promises one fused multiply-add per k step into C's
+    // starting value, in ascending k: that's this loop, whatever the tiles,
+    // k blocks, or threads
+    let fused = |a: &[f64], b: &[f64], c0: &[f64], m: usize, n: usize, k: usize| {
+        let mut c = c0.to_vec();
+        for i in 0..m {
+            for j in 0..n {
+                for p in 0..k {
+                    c[i * n + j] = a[i * k + p].mul_add(b[p * n + j], c[i * n + j]);
+                }
+            }
+        }
+        c
+    };
+    let simd: Vec<Backend> = available_backends()
+        .into_iter()
+        .filter(|backend| !matches!(backend, Backend::Scalar | Backend::ScalarIkj))
+        .collect();
+    // Shapes that leave edge rows and columns for some tile and not others,
+    // k past one block, and k small enough to skip the tiles altogether
+    for (m, n, k) in [
+        (29, 27, 300),
+        (24, 24, 24),
+        (13, 9, 5),
+        (13, 11, 3),
+        (100, 70, 600),
+        (1, 1, 513),
+    ] {
+        let (a, b, c0) = (random(m, k, 1), random(k, n, 2), random(m, n, 3));
+        let want = fused(&a, &b, &c0, m, n, k);
+        for &backend in &simd {
+            let mut c = c0.clone();
+            multiply_with_backend(backend, &a, &b, &mut c, m, n, k);
+            assert!(c == want, "{} at {}x{}x{}", backend, m, n, k);
+            // The unblocked 4×4 path rounds the same way
+            #[cfg(target_arch = "x86_64")]
+            if backend == Backend::Avx2_4x4 {
+                let mut c = c0.clone();
+                simple_simd::run(&a, &b, &mut c, m, n, k).unwrap();
+                assert!(c == want, "simple_simd at {}x{}x{}", m, n, k);
+            }
+
+            for (threads, kc) in [(3, Some(64)), (4, None), (1, Some(7))] {
+                let mut matmul = MatMul::new()
+                    .backend(backend)
+                    .threads(threads)
+                    .beta(1.0)
+                    .tuning(Tuning { kc, mc: None })
+                    .build()
+                    .unwrap();
+                let mut c = c0.clone();
+                matmul.compute_into(&a, &b, &mut c, m, n, k).unwrap();
+                assert!(
+                    c == want,
+                    "{} at {}x{}x{}, {} threads, kc {:?}",
+                    backend,
+                    m,
+                    n,
+                    k,
+                    threads,
+                    kc
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Calibrated dispatch: saved tables round-trip, and the plain entry points
+//! run what the installed table says.
+
+use matmul::calibrate::{
+    CalibrationError, DispatchTable, calibrate_with_file, dispatch_table, use_dispatch_table,
+};
+use matmul::matrix::generate::random;
+use matmul::{Backend, backend_for, detected_backend, multiply_with_stats};
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("matmul-{}-{}", name, std::process::id()))
+}
+
+#[test]
+fn test_saved_table_round_trips() {
+    let available: Vec<Backend> = Backend::ALL
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .collect();
+    let mut buckets: Vec<(usize, Backend)> = available
+        .iter()
+        .enumerate()
+        .map(|(i, &backend)| (16 << i, backend))
+        .collect();
+    buckets.last_mut().unwrap().0 = usize::MAX;
+    let table = DispatchTable::new(buckets).unwrap();
+
+    let path = temp_path("table-round-trip");
+    table.save(&path).unwrap();
+    assert_eq!(DispatchTable::load(&path).unwrap(), table);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        DispatchTable::load(&path),
+        Err(CalibrationError::Io(_))
+    ));
+    // A table from a machine with backends this one lacks
+    if let Some(missing) = Backend::ALL.into_iter().find(|b| !b.is_available()) {
+        let text = format!(
+            "# matmul dispatch table v1\nmax {}\n",
+            matmul::GemmBackend::key(&missing)
+        );
+        assert!(matches!(
+            DispatchTable::parse(&text),
+            Err(CalibrationError::Unavailable(backend)) if backend == missing
+        ));
+    }
+}
+
+// The table is per process, so everything that installs one is in this test
+#[test]
+fn test_dispatch_follows_installed_table() {
+    assert_eq!(dispatch_table(), None);
+
+    // Small multiplies on the 4×4 kernel, the rest on the scalar one: the
+    // opposite of the static order, so following it shows
+    let small = if Backend::Avx2_4x4.is_available() {
+        Backend::Avx2_4x4
+    } else {
+        Backend::ScalarIkj
+    };
+    let table = DispatchTable::new(vec![(64, small), (usize::MAX, Backend::Scalar)]).unwrap();
+    let path = temp_path("table-installed");
+    table.save(&path).unwrap();
+    let installed = calibrate_with_file(&path).unwrap();
+    assert_eq!(installed, &table);
+    assert_eq!(dispatch_table(), Some(&table));
+    assert!(use_dispatch_table(table.clone()).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    let run = |m: usize, n: usize, k: usize| {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let mut c = vec![0.0; m * n];
+        multiply_with_stats(&a, &b, &mut c, m, n, k).backend
+    };
+    assert_eq!(run(64, 20, 33), small);
+    assert_eq!(backend_for(64, 20, 33), small);
+    assert_eq!(run(65, 20, 33), Backend::Scalar);
+    assert_eq!(backend_for(300, 300, 300), Backend::Scalar);
+}
+
+// Times 512³ multiplies: hours under Miri
+#[cfg_attr(miri, ignore)]
+#[test]
+fn test_measured_table_uses_available_backends() {
+    let table = DispatchTable::measure();
+    let buckets = table.buckets();
+    assert_eq!(buckets.last().unwrap().0, usize::MAX);
+    for &(_, backend) in buckets {
+        assert!(backend.is_available(), "{}", backend);
+        assert_ne!(backend, Backend::ScalarIkj);
+    }
+    if detected_backend() == Backend::Scalar {
+        assert_eq!(buckets, [(usize::MAX, Backend::Scalar)]);
+    }
+}
@@ -0,0 +1,460 @@
+//! Runs the benchmark binary the way a tracking script would and checks its
+//! machine-readable output.
+
+// Miri can't spawn the benchmark binary
+#![cfg(not(miri))]
+
+use matmul::Backend;
+use serde_json::Value;
+use std::process::Command;
+
+fn run(args: &[&str]) -> std::process::Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_matmul"))
+        .args(args)
+        .output()
+        .expect("failed to run the matmul binary");
+    assert!(
+        output.status.success(),
+        "matmul {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn test_json_output_schema() {
+    let output = run(&[
+        "--format",
+        "json",
+        "--sizes",
+        "8,16",
+        "--methods",
+        "naive,ikj",
+        "--iters",
+        "1",
+        "--threads",
+        "2",
+    ]);
+    // Progress goes to stderr, so stdout is exactly one JSON document
+    let doc: Value = serde_json::from_slice(&output.stdout).expect("stdout is not JSON");
+
+    assert!(doc["cpu"]["arch"].is_string());
+    assert!(doc["cpu"]["avx2"].is_boolean());
+    assert!(doc["cpu"]["avx512"].is_boolean());
+    assert_eq!(doc["threads"], 2);
+    assert_eq!(doc["iterations"], 1);
+    assert!(doc["min_time_s"].is_number());
+    // Peak estimate; null where the clock can't be determined
+    let peak = &doc["peak"];
+    assert!(peak.is_null() || peak["all_core_gflops"].as_f64().unwrap() > 0.0);
+
+    let results = doc["results"].as_array().unwrap();
+    let rows: Vec<(&str, u64, u64, u64)> = results
+        .iter()
+        .map(|row| {
+            (
+                row["method"].as_str().unwrap(),
+                row["m"].as_u64().unwrap(),
+                row["n"].as_u64().unwrap(),
+                row["k"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("naive", 8, 8, 8),
+            ("ikj", 8, 8, 8),
+            ("naive", 16, 16, 16),
+            ("ikj", 16, 16, 16)
+        ]
+    );
+    for row in results {
+        assert!(row["name"].is_string());
+        // null only when a run beat the clock's resolution
+        for field in ["time_ms", "gflops", "speedup"] {
+            assert!(row[field].is_number() || row[field].is_null(), "{}", row);
+        }
+    }
+    assert_eq!(results[0]["speedup"], 1.0);
+}
+
+#[test]
+fn test_csv_output() {
+    let output = run(&[
+        "--format",
+        "csv",
+        "--shapes",
+        "8x3x5",
+        "--methods",
+        "ikj",
+        "--iters",
+        "1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "method,m,n,k,time_ms,gflops,speedup,heap_bytes,rss_bytes,sum,bits"
+    );
+    assert_eq!(lines.len(), 2);
+    let fields: Vec<&str> = lines[1].split(',').collect();
+    assert_eq!(fields[..4], ["ikj", "8", "3", "5"]);
+    assert!(fields[4..8].iter().all(|x| x.parse::<f64>().is_ok()));
+}
+
+#[test]
+fn test_verify_reports_every_method() {
+    let output = run(&[
+        "--format",
+        "json",
+        "--shapes",
+        "37x37x37,5x70x33,70x5x9",
+        "--methods",
+        "naive,blocked-bt,4x4scalar,12x4,8x8mt",
+        "--iters",
+        "1",
+        "--verify-strict",
+    ]);
+    let doc: Value = serde_json::from_slice(&output.stdout).unwrap();
+    for row in doc["results"].as_array().unwrap() {
+        assert_eq!(row["verified"], true, "{}", row);
+        assert!(row["max_abs_err"].as_f64().unwrap() < 1e-10, "{}", row);
+    }
+}
+
+#[test]
+fn test_peak_memory_covers_the_obvious_buffers() {
+    let (m, n, k) = (64, 48, 80);
+    let output = run(&[
+        "--format",
+        "json",
+        "--shapes",
+        "64x48x80",
+        "--methods",
+        "ikj,4x4scalar",
+        "--iters",
+        "1",
+    ]);
+    let doc: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let results = doc["results"].as_array().unwrap();
+    let heap = |i: usize| results[i]["heap_bytes"].as_u64().unwrap() as usize;
+
+    // i-k-j allocates nothing beyond C; the blocked driver also transposes B
+    // and packs panels
+    assert!(heap(0) >= 8 * m * n, "{}", results[0]);
+    assert!(heap(1) >= 8 * (m * n + k * n), "{}", results[1]);
+    assert!(heap(1) > heap(0));
+}
+
+#[test]
+fn test_throughput_mode_json() {
+    let output = run(&[
+        "--mode",
+        "throughput",
+        "--format",
+        "json",
+        "--shapes",
+        "24x16x40",
+        "--methods",
+        "ikj,4x4scalar",
+        "--calls",
+        "7",
+    ]);
+    let doc: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(doc["mode"], "throughput");
+    let results = doc["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    for row in results {
+        assert_eq!(row["calls"], 7, "{}", row);
+        let percentiles: Vec<f64> = ["p50_ms", "p90_ms", "p99_ms", "max_ms"]
+            .iter()
+            .map(|field| row[field].as_f64().unwrap())
+            .collect();
+        assert!(percentiles.windows(2).all(|w| w[0] <= w[1]), "{}", row);
+    }
+    // The i-k-j loop writes straight into the reused C
+    assert_eq!(results[0]["allocs_per_call"], 0.0);
+}
+
+#[test]
+fn test_scaling_mode_csv() {
+    let output = run(&[
+        "--mode",
+        "scaling",
+        "--format",
+        "csv",
+        "--shapes",
+        "50x20x30",
+        "--methods",
+        "ikj,4x4mt",
+        "--iters",
+        "1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "method,m,n,k,threads,time_ms,gflops,speedup,efficiency,max_abs_diff"
+    );
+    let rows: Vec<Vec<&str>> = lines[1..].iter().map(|l| l.split(',').collect()).collect();
+    // ikj isn't threaded, so only 4x4mt is swept (where AVX2 runs it)
+    assert_eq!(rows.is_empty(), !matmul::features::has_avx2(), "{}", stdout);
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row[..4], ["4x4mt", "50", "20", "30"]);
+        if i == 0 {
+            assert_eq!(row[4], "1");
+            assert_eq!(row[7].parse::<f64>().unwrap(), 1.0);
+        }
+        assert_eq!(row[9].parse::<f64>().unwrap(), 0.0, "{:?}", row);
+    }
+}
+
+#[test]
+fn test_primitives_mode_csv() {
+    let output = run(&[
+        "--mode",
+        "primitives",
+        "--format",
+        "csv",
+        "--shapes",
+        "40x24x300",
+        "--iters",
+        "1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "primitive,m,n,k,time_ms,gbps");
+    let names: Vec<&str> = lines[1..]
+        .iter()
+        .map(|l| l.split(',').next().unwrap())
+        .collect();
+    for expected in [
+        "transpose blocked",
+        "pack_a_panel 12-row",
+        "pack_b_panel 8-col",
+    ] {
+        assert!(names.contains(&expected), "{}", stdout);
+    }
+}
+
+#[test]
+fn test_first_call_mode_csv() {
+    let output = run(&[
+        "--mode",
+        "first-call",
+        "--format",
+        "csv",
+        "--shapes",
+        "40x24x300",
+        "--iters",
+        "1",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        "start,m,n,k,warmup_ms,first_ms,second_ms,steady_ms"
+    );
+    let starts: Vec<&str> = lines[1..]
+        .iter()
+        .map(|l| l.split(',').next().unwrap())
+        .collect();
+    assert_eq!(starts, ["cold", "warmup", "warmup_for"], "{}", stdout);
+    for line in &lines[1..] {
+        let fields: Vec<&str> = line.split(',').collect();
+        assert_eq!(fields[1..4], ["40", "24", "300"]);
+        for time in &fields[4..] {
+            assert!(time.parse::<f64>().unwrap() >= 0.0, "{}", line);
+        }
+    }
+}
+
+#[test]
+fn test_downclock_mode_json() {
+    let output = run(&[
+        "--mode",
+        "downclock",
+        "--format",
+        "json",
+        "--shapes",
+        "40x24x300",
+        "--iters",
+        "1",
+    ]);
+    let doc: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(doc["mode"], "downclock");
+    assert!(doc["avx512_crossover"].is_u64());
+    let backends: Vec<&str> = doc["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            for field in ["multiply_ms", "alone_ms", "after_ms"] {
+                assert!(row[field].as_f64().unwrap() >= 0.0, "{}", row);
+            }
+            row["backend"].as_str().unwrap()
+        })
+        .collect();
+    let expected: Vec<&str> = [(Backend::Avx512_8x8, "8x8"), (Backend::Avx2_12x4, "12x4")]
+        .into_iter()
+        .filter(|(backend, _)| backend.is_available())
+        .map(|(_, key)| key)
+        .collect();
+    assert_eq!(backends, expected);
+}
+
+#[test]
+fn test_seed_reproduces_checksums() {
+    let checksums = |seed: &str| {
+        let output = run(&[
+            "--format",
+            "json",
+            "--shapes",
+            "30x20x50",
+            "--methods",
+            "naive,ikj,4x4scalar",
+            "--iters",
+            "1",
+            "--seed",
+            seed,
+        ]);
+        let doc: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(doc["seed"].to_string(), seed);
+        doc["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["checksum"].clone())
+            .collect::<Vec<Value>>()
+    };
+    let first = checksums("7");
+    assert_eq!(first, checksums("7"));
+    assert_ne!(first, checksums("8"));
+    // With k inside one block, these scalar loops all add in k order, so
+    // they agree bit for bit
+    assert!(first.iter().all(|c| c == &first[0]), "{:?}", first);
+}
+
+#[test]
+fn test_method_selection_and_baseline() {
+    let selected = |args: &[&str]| {
+        let mut all = vec!["--format", "json", "--sizes", "16", "--iters", "1"];
+        all.extend(args);
+        let doc: Value = serde_json::from_slice(&run(&all).stdout).unwrap();
+        let rows: Vec<(String, f64)> = doc["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row["method"].as_str().unwrap().to_string(),
+                    row["speedup"].as_f64().unwrap_or(f64::NAN),
+                )
+            })
+            .collect();
+        (doc["baseline"].clone(), rows)
+    };
+
+    // Table names and keys mix, in any case, with x for ×
+    let (baseline, rows) = selected(&["--only", "Naive (i-j-k),IKJ,4x4 Scalar"]);
+    assert_eq!(baseline, "naive");
+    let keys: Vec<&str> = rows.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["naive", "ikj", "4x4scalar"]);
+
+    let (baseline, rows) = selected(&[
+        "--only",
+        "naive,ikj,kij",
+        "--exclude",
+        "naive",
+        "--baseline",
+        "Scalar (k-i-j)",
+    ]);
+    assert_eq!(baseline, "kij");
+    assert_eq!(rows[0].0, "ikj");
+    assert_eq!(rows[1], ("kij".to_string(), 1.0));
+
+    // A baseline that doesn't run falls back to the first method
+    let (baseline, rows) = selected(&["--only", "ikj,kij", "--baseline", "naive"]);
+    assert_eq!(baseline, "ikj");
+    assert_eq!(rows[0].1, 1.0);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_matmul"))
+        .args(["--only", "nonesuch"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--only: unknown method 'nonesuch'"));
+}
+
+#[test]
+fn test_list_methods() {
+    let stdout = String::from_utf8(run(&["--list"]).stdout).unwrap();
+    assert!(stdout.starts_with("Key"));
+    for row in ["naive", "ikj", "4x4scalar", "4x4scalarmt"] {
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.split_whitespace().next() == Some(row)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn test_explain() {
+    let stdout =
+        String::from_utf8(run(&["--explain", "--shapes", "16x4096x256,3x3x3"]).stdout).unwrap();
+    let runs = matmul::backend_for(16, 4096, 256);
+    assert!(
+        stdout.starts_with(&format!("16×4096×256: multiply() runs {}", runs)),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("3×3: multiply() runs"), "{}", stdout);
+    // Every backend gets a row per shape, chosen or not
+    for backend in matmul::Backend::ALL {
+        let rows = stdout
+            .lines()
+            .filter(|line| line.trim_start().starts_with(&backend.to_string()))
+            .count();
+        assert_eq!(rows, 2, "{}\n{}", backend, stdout);
+    }
+    assert_eq!(
+        stdout.matches("chosen: fastest estimate").count(),
+        2,
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_dtype_sections() {
+    let output = run(&[
+        "--sizes",
+        "16",
+        "--only",
+        "ikj",
+        "--iters",
+        "1",
+        "--dtype",
+        "f64,f32,i8",
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for heading in ["f32 (GFLOPS", "i8 (GOPS", "OPS/SEC BY PRECISION"] {
+        assert!(stdout.contains(heading), "{}", stdout);
+    }
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line.starts_with("i8 ") && line.ends_with('×'))
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_matmul"))
+        .args(["--dtype", "f32", "--format", "json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
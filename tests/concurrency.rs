@@ -0,0 +1,198 @@
+//! Concurrency tests for the threaded module.
+//!
+//! These are ordinary tests, but sized so every case actually spawns several
+//! workers, which makes them the suite to run under ThreadSanitizer:
+//!
+//! ```text
+//! just test-tsan
+//! ```
+
+// Sized to spawn many workers, which takes hours under Miri; tests/miri.rs
+// has small threaded cases instead
+#![cfg(not(miri))]
+
+use matmul::matrix::compare::assert_close;
+use matmul::matrix::generate::random;
+use matmul::matrix::naive_ikj::matmul_naive_ikj;
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::threaded::{Schedule, parallel_rows};
+use matmul::{Cancelled, GemmBackend, multiply_parallel_cancellable};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::AtomicBool;
+
+#[cfg(target_arch = "x86_64")]
+use matmul::features::{has_avx2, has_avx512};
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::{
+    gemm_4x4_mt::matmul_blocked_4x4_mt_scheduled, gemm_8x8_mt::matmul_blocked_8x8_mt_scheduled,
+    gemm_12x4_mt::matmul_blocked_12x4_mt_scheduled,
+};
+
+/// The [`MarkRows`] tests' m×n×k: just over the FLOP count that gets all 4
+/// threads, with m ragged for the 64-row chunks and k small enough that A
+/// and B stay cheap to allocate.
+const MARKED: (usize, usize, usize) = (1001, 512, 320);
+
+/// A backend that adds `row + 1` to every element of its (band-local) C, so
+/// both a missed row (0.0) and a doubly-written one (2 × value) show up.
+/// With `fail_first`, the band starting at row 0 panics instead.
+struct MarkRows {
+    fail_first: bool,
+}
+
+impl GemmBackend for MarkRows {
+    fn name(&self) -> &str {
+        "mark rows"
+    }
+
+    fn supported(&self) -> bool {
+        true
+    }
+
+    fn mr(&self) -> usize {
+        8
+    }
+
+    unsafe fn run(
+        &self,
+        _a: &[f64],
+        _b: &[f64],
+        c: &mut [f64],
+        _m: usize,
+        n: usize,
+        _k: usize,
+        rows: Range<usize>,
+    ) {
+        if self.fail_first && rows.start == 0 {
+            panic!("first band failed");
+        }
+        for i in rows.clone() {
+            for j in 0..n {
+                c[(i - rows.start) * n + j] += (i + 1) as f64;
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_mt_kernels_share_c_without_races() {
+    if !has_avx2() {
+        println!("Skipping - AVX2 not available");
+        return;
+    }
+
+    // 2 threads by the adaptive heuristic; m is ragged for every kernel height
+    let (m, n, k) = (389, 200, 700);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    let mut c_naive = vec![0.0; m * n];
+    matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+
+    for schedule in [Schedule::Static, Schedule::Dynamic] {
+        let mut c = vec![0.0; m * n];
+        matmul_blocked_4x4_mt_scheduled(&a, &b, &mut c, m, n, k, 4, schedule);
+        assert_close(&c_naive, &c, 1e-10, 1e-10);
+
+        let mut c = vec![0.0; m * n];
+        matmul_blocked_12x4_mt_scheduled(&a, &b, &mut c, m, n, k, 4, schedule);
+        assert_close(&c_naive, &c, 1e-10, 1e-10);
+
+        if has_avx512() {
+            let mut c = vec![0.0; m * n];
+            matmul_blocked_8x8_mt_scheduled(&a, &b, &mut c, m, n, k, 4, schedule);
+            assert_close(&c_naive, &c, 1e-10, 1e-10);
+        }
+    }
+}
+
+#[test]
+fn test_naive_mt_bands_are_disjoint() {
+    let (m, n, k) = (203, 64, 100);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    let mut c_naive = vec![0.0; m * n];
+    matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+
+    for threads in [2, 3, 8] {
+        let mut c = vec![0.0; m * n];
+        matmul_naive_ikj_mt(&a, &b, &mut c, m, n, k, threads);
+        assert_close(&c_naive, &c, 1e-10, 1e-10);
+    }
+}
+
+#[test]
+fn test_parallel_rows_writes_each_row_once() {
+    let (m, n, k) = MARKED;
+    let (a, b) = (vec![0.0; m * k], vec![0.0; k * n]);
+
+    for schedule in [Schedule::Static, Schedule::Dynamic] {
+        let mut c = vec![0.0; m * n];
+        unsafe {
+            let mark = MarkRows { fail_first: false };
+            parallel_rows(&mark, &a, &b, &mut c, m, n, k, 4, schedule);
+        }
+        for i in 0..m {
+            assert_eq!(c[i * n], (i + 1) as f64, "{:?}: row {}", schedule, i);
+        }
+    }
+}
+
+#[test]
+fn test_worker_panic_joins_remaining_workers() {
+    let (m, n, k) = MARKED;
+    let (a, b) = (vec![0.0; m * k], vec![0.0; k * n]);
+    let mut c = vec![0.0; m * n];
+    let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        parallel_rows(
+            &MarkRows { fail_first: true },
+            &a,
+            &b,
+            &mut c,
+            m,
+            n,
+            k,
+            4,
+            Schedule::Dynamic,
+        );
+    }));
+
+    assert!(result.is_err());
+    // Chunks are 64 rows; everything after the failed one was still computed
+    for i in 64..m {
+        assert_eq!(c[i * n], (i + 1) as f64, "row {}", i);
+    }
+}
+
+#[test]
+fn test_cancel_flag_read_concurrently() {
+    let size = 512;
+    let a = vec![1.0; size * size];
+    let b = vec![1.0; size * size];
+    let mut c = vec![0.0; size * size];
+    let cancel = AtomicBool::new(false);
+
+    let result = std::thread::scope(|s| {
+        s.spawn(|| cancel.store(true, std::sync::atomic::Ordering::Relaxed));
+        multiply_parallel_cancellable(&a, &b, &mut c, size, size, size, 4, &cancel)
+    });
+
+    // Either outcome is fine, as long as it's a consistent one: every row,
+    // or whole chunks of rows and nothing of the rest
+    let full = size as f64;
+    match result {
+        Ok(()) => assert!(c.iter().all(|&x| x == full)),
+        Err(Cancelled) => {
+            for (i, row) in c.chunks_exact(size).enumerate() {
+                assert!(
+                    row.iter().all(|&x| x == 0.0) || row.iter().all(|&x| x == full),
+                    "row {} is partly computed",
+                    i
+                );
+            }
+        }
+    }
+}
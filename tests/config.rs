@@ -0,0 +1,132 @@
+//! The global config, read from several threads while it changes and while
+//! a scope overrides it.
+//!
+//! Its own test binary, since a global setting would reach every other test
+//! running in the same process; the tests here take turns with [`LOCK`].
+
+use matmul::config::{self, Config};
+use matmul::matrix::generate::random;
+use matmul::{MatMul, multiply, multiply_parallel_with_stats};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Holds [`LOCK`], and puts the default global config back when dropped,
+/// also after a failed assertion.
+struct Global(#[allow(dead_code)] MutexGuard<'static, ()>);
+
+impl Global {
+    fn take() -> Global {
+        Global(LOCK.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Drop for Global {
+    fn drop(&mut self) {
+        config::set_global(Config::default());
+    }
+}
+
+fn threads(max_threads: usize) -> Config {
+    Config {
+        max_threads: Some(max_threads),
+        ..Config::default()
+    }
+}
+
+/// Readers on other threads see each global config whole, and never the
+/// calling thread's scope; multiplies on both sides agree with one made
+/// under no config at all.
+#[test]
+#[cfg_attr(miri, ignore = "thousands of multiplies")]
+fn test_concurrent_readers_during_an_override() {
+    let _global = Global::take();
+    let (m, n, k) = (48, 40, 36);
+    let (a, b) = (random(m, k, 1), random(k, n, 2));
+    let mut want = vec![0.0; m * n];
+    multiply(&a, &b, &mut want, m, n, k);
+
+    let globals = [threads(2), threads(4)];
+    let scoped = Config {
+        deterministic: Some(true),
+        ..threads(1)
+    };
+    config::set_global(globals[0]);
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut reads = 0;
+                    while !stop.load(Ordering::Relaxed) || reads < 100 {
+                        let seen = config::current();
+                        assert!(globals.contains(&seen), "read {:?}", seen);
+                        let mut c = vec![0.0; m * n];
+                        MatMul::new()
+                            .threads(3)
+                            .build()
+                            .unwrap()
+                            .compute_into(&a, &b, &mut c, m, n, k)
+                            .unwrap();
+                        assert_eq!(c, want);
+                        reads += 1;
+                    }
+                    reads
+                })
+            })
+            .collect();
+
+        for round in 0..200 {
+            config::with_config(scoped, || {
+                config::set_global(globals[round % 2]);
+                assert_eq!(config::current(), scoped.or(globals[round % 2]));
+                let mut c = vec![0.0; m * n];
+                multiply(&a, &b, &mut c, m, n, k);
+                assert_eq!(c, want);
+            });
+            assert_eq!(config::current(), globals[round % 2]);
+        }
+        stop.store(true, Ordering::Relaxed);
+        for reader in readers {
+            assert!(reader.join().unwrap() >= 100);
+        }
+    });
+}
+
+/// A global cap reaches multiplies on every thread; a scope on one of them
+/// overrides it there only.
+#[test]
+#[cfg_attr(miri, ignore = "a 512³ multiply")]
+fn test_global_max_threads() {
+    let _global = Global::take();
+    let n = 512;
+    let (a, b) = (random(n, n, 1), random(n, n, 2));
+    let threads_used = || {
+        let mut c = vec![0.0; n * n];
+        multiply_parallel_with_stats(&a, &b, &mut c, n, n, n, 4).threads_used
+    };
+    let uncapped = threads_used();
+    assert!(uncapped > 1);
+
+    config::set_global(threads(1));
+    assert_eq!(thread::scope(|s| s.spawn(threads_used).join().unwrap()), 1);
+    assert_eq!(config::with_config(threads(4), threads_used), uncapped);
+    assert_eq!(threads_used(), 1);
+}
+
+/// A scope left by a panic leaves the thread on the global config again.
+#[test]
+fn test_scope_restored_after_a_panic() {
+    let _global = Global::take();
+    config::set_global(threads(2));
+    let result = std::panic::catch_unwind(|| {
+        config::with_config(threads(1), || {
+            assert_eq!(config::current().max_threads, Some(1));
+            panic!("in the scope");
+        })
+    });
+    assert!(result.is_err());
+    assert_eq!(config::current(), threads(2));
+}
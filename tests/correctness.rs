@@ -1,23 +1,57 @@
-use matmul::blocked::gemm_4x4::matmul_blocked_4x4;
-use matmul::blocked::gemm_8x8::matmul_blocked_8x8;
-use matmul::blocked::gemm_12x4::matmul_blocked_12x4;
+// Sizes in the hundreds take hours under Miri; tests/miri.rs is the reduced
+// set that runs there
+#![cfg(not(miri))]
+
+use matmul::blocked::gemm_scalar::matmul_blocked_scalar;
+use matmul::io::{read_npy, write_npy};
+use matmul::matrix::compare::check_close;
+use matmul::matrix::elementwise::{add_assign, axpy, scale};
+use matmul::matrix::generate::{random, sequential};
 use matmul::matrix::naive_ikj::matmul_naive_ikj;
-use matmul::threaded::gemm_4x4_mt::matmul_blocked_4x4_mt;
-use matmul::threaded::gemm_8x8_mt::matmul_blocked_8x8_mt;
-use matmul::threaded::gemm_12x4_mt::matmul_blocked_12x4_mt;
-use matmul::{multiply, multiply_parallel};
+use matmul::matrix::transpose::{
+    transpose, transpose_f32, transpose_in_place, transpose_naive, transpose_parallel,
+    transpose_scalar, transpose_strided,
+};
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::threaded::{BlockedGemm, parallel_rows_cancellable};
+use matmul::{
+    Backend, Cancelled, GemmBackend, MatMulError, available_backends, backend_for, cpu_features,
+    detected_backend, matmul_blocked_transposed, matmul_ikj_transposed, matmul_naive_ijk,
+    matmul_naive_jik, matmul_naive_jki, matmul_naive_kij, matmul_naive_kji, multiply,
+    multiply_parallel, multiply_parallel_cancellable, multiply_parallel_with_stats,
+    multiply_with_backend, multiply_with_stats, try_multiply,
+};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// SIMD kernels only exist on x86_64; their tests are compiled out elsewhere
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::{
+    gemm_4x4, gemm_4x4::matmul_blocked_4x4, gemm_8x8, gemm_8x8::matmul_blocked_8x8, gemm_12x4,
+    gemm_12x4::matmul_blocked_12x4, simple_simd,
+};
+#[cfg(target_arch = "x86_64")]
+use matmul::features::{has_avx2, has_avx512};
+#[cfg(target_arch = "x86_64")]
+use matmul::matrix::transpose::{transpose_avx, transpose_avx512};
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::Schedule;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_4x4_mt::{matmul_blocked_4x4_mt, matmul_blocked_4x4_mt_scheduled};
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_8x8_mt::{matmul_blocked_8x8_mt, matmul_blocked_8x8_mt_scheduled};
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_12x4_mt::{matmul_blocked_12x4_mt, matmul_blocked_12x4_mt_scheduled};
+
+/// Blocked kernels sum in a different order than the naive reference, so
+/// results agree to rounding relative to their magnitude, not absolutely.
+const RTOL: f64 = 1e-10;
+const ATOL: f64 = 1e-10;
 
 fn assert_matrices_equal(expected: &[f64], actual: &[f64], name: &str) {
-    assert_eq!(expected.len(), actual.len(), "{}: length mismatch", name);
-    for i in 0..expected.len() {
-        assert!(
-            (expected[i] - actual[i]).abs() < 1e-8,
-            "{}: mismatch at index {}: expected {}, got {}",
-            name,
-            i,
-            expected[i],
-            actual[i]
-        );
+    if let Err(mismatch) = check_close(expected, actual, RTOL, ATOL) {
+        panic!("{}: {}", name, mismatch);
     }
 }
 
@@ -67,8 +101,8 @@ fn test_small_odd_sizes() {
     ];
 
     for (m, n, k) in test_sizes {
-        let a: Vec<f64> = (0..m * k).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..k * n).map(|i| (i % 10) as f64).collect();
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
 
         let mut c_naive = vec![0.0; m * n];
         let mut c_fast = vec![0.0; m * n];
@@ -89,8 +123,8 @@ fn test_tile_boundary_4x4() {
     let test_sizes = [3, 4, 5, 7, 8, 9, 15, 16, 17];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_fast = vec![0.0; size * size];
@@ -107,8 +141,8 @@ fn test_tile_boundary_12x4() {
     let test_sizes = [11, 12, 13, 23, 24, 25, 35, 36, 37];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_fast = vec![0.0; size * size];
@@ -125,8 +159,8 @@ fn test_tile_boundary_8x8() {
     let test_sizes = [7, 8, 9, 15, 16, 17, 23, 24, 25];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_fast = vec![0.0; size * size];
@@ -142,9 +176,10 @@ fn test_tile_boundary_8x8() {
 // Direct kernel tests (bypassing auto-dispatch)
 // ============================================================
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_gemm_4x4_direct() {
-    if !is_x86_feature_detected!("avx2") {
+    if !has_avx2() {
         println!("Skipping - AVX2 not available");
         return;
     }
@@ -152,24 +187,23 @@ fn test_gemm_4x4_direct() {
     let test_sizes = [4, 8, 16, 17, 31, 32, 33, 64, 65];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_gemm = vec![0.0; size * size];
 
         matmul_naive_ikj(&a, &b, &mut c_naive, size, size, size);
-        unsafe {
-            matmul_blocked_4x4(&a, &b, &mut c_gemm, size, size, size, None, None);
-        }
+        gemm_4x4::run(&a, &b, &mut c_gemm, size, size, size).unwrap();
 
         assert_matrices_equal(&c_naive, &c_gemm, &format!("gemm_4x4_size_{}", size));
     }
 }
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_gemm_12x4_direct() {
-    if !is_x86_feature_detected!("avx2") {
+    if !has_avx2() {
         println!("Skipping - AVX2 not available");
         return;
     }
@@ -177,24 +211,23 @@ fn test_gemm_12x4_direct() {
     let test_sizes = [4, 12, 13, 24, 25, 36, 37, 48, 49];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_gemm = vec![0.0; size * size];
 
         matmul_naive_ikj(&a, &b, &mut c_naive, size, size, size);
-        unsafe {
-            matmul_blocked_12x4(&a, &b, &mut c_gemm, size, size, size, None, None);
-        }
+        gemm_12x4::run(&a, &b, &mut c_gemm, size, size, size).unwrap();
 
         assert_matrices_equal(&c_naive, &c_gemm, &format!("gemm_12x4_size_{}", size));
     }
 }
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_gemm_8x8_direct() {
-    if !is_x86_feature_detected!("avx512f") {
+    if !has_avx512() {
         println!("Skipping - AVX-512 not available");
         return;
     }
@@ -202,21 +235,63 @@ fn test_gemm_8x8_direct() {
     let test_sizes = [8, 9, 16, 17, 24, 25, 32, 33, 64, 65];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_naive = vec![0.0; size * size];
         let mut c_gemm = vec![0.0; size * size];
 
         matmul_naive_ikj(&a, &b, &mut c_naive, size, size, size);
-        unsafe {
-            matmul_blocked_8x8(&a, &b, &mut c_gemm, size, size, size, None, None);
-        }
+        gemm_8x8::run(&a, &b, &mut c_gemm, size, size, size).unwrap();
 
         assert_matrices_equal(&c_naive, &c_gemm, &format!("gemm_8x8_size_{}", size));
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_simple_simd_direct() {
+    if !has_avx2() {
+        assert!(simple_simd::run(&[1.0], &[1.0], &mut [0.0], 1, 1, 1).is_err());
+        return;
+    }
+
+    // Leftover rows, leftover columns, both, and neither
+    for (m, n, k) in [
+        (1, 1, 1),
+        (4, 4, 4),
+        (5, 7, 3),
+        (7, 5, 9),
+        (8, 13, 1),
+        (13, 6, 17),
+        (33, 31, 20),
+        (64, 65, 64),
+    ] {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        // C += A·B, from a C that isn't zero
+        let c0 = random(m, n, 3);
+        let mut expected = c0.clone();
+        matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+        let mut c = c0.clone();
+        simple_simd::run(&a, &b, &mut c, m, n, k).unwrap();
+        assert_matrices_equal(&expected, &c, &format!("simple_simd {}x{}x{}", m, n, k));
+
+        // With all-ones inputs every element is exactly k: an element done
+        // twice (the corner both edge loops reach, say) would be 2k
+        let mut c = vec![0.0; m * n];
+        simple_simd::run(&vec![1.0; m * k], &vec![1.0; k * n], &mut c, m, n, k).unwrap();
+        assert!(
+            c.iter().all(|&x| x == k as f64),
+            "simple_simd {}x{}x{}: {:?}",
+            m,
+            n,
+            k,
+            c
+        );
+    }
+}
+
 // ============================================================
 // Multi-threaded tests
 // ============================================================
@@ -226,8 +301,8 @@ fn test_parallel_matches_single_threaded() {
     let test_sizes = [64, 128, 256];
 
     for size in test_sizes {
-        let a: Vec<f64> = (0..size * size).map(|i| (i % 17) as f64).collect();
-        let b: Vec<f64> = (0..size * size).map(|i| (i % 13) as f64).collect();
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
 
         let mut c_single = vec![0.0; size * size];
         let mut c_parallel = vec![0.0; size * size];
@@ -253,16 +328,17 @@ fn test_parallel_small_matrix() {
     assert_matrices_equal(&c_naive, &c_parallel, "parallel_small");
 }
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_mt_4x4_direct() {
-    if !is_x86_feature_detected!("avx2") {
+    if !has_avx2() {
         println!("Skipping - AVX2 not available");
         return;
     }
 
     let size = 256;
-    let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-    let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
 
     let mut c_naive = vec![0.0; size * size];
     let mut c_mt = vec![0.0; size * size];
@@ -273,16 +349,17 @@ fn test_mt_4x4_direct() {
     assert_matrices_equal(&c_naive, &c_mt, "mt_4x4");
 }
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_mt_12x4_direct() {
-    if !is_x86_feature_detected!("avx2") {
+    if !has_avx2() {
         println!("Skipping - AVX2 not available");
         return;
     }
 
     let size = 256;
-    let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-    let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
 
     let mut c_naive = vec![0.0; size * size];
     let mut c_mt = vec![0.0; size * size];
@@ -293,16 +370,17 @@ fn test_mt_12x4_direct() {
     assert_matrices_equal(&c_naive, &c_mt, "mt_12x4");
 }
 
+#[cfg(target_arch = "x86_64")]
 #[test]
 fn test_mt_8x8_direct() {
-    if !is_x86_feature_detected!("avx512f") {
+    if !has_avx512() {
         println!("Skipping - AVX-512 not available");
         return;
     }
 
     let size = 256;
-    let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-    let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
 
     let mut c_naive = vec![0.0; size * size];
     let mut c_mt = vec![0.0; size * size];
@@ -313,6 +391,35 @@ fn test_mt_8x8_direct() {
     assert_matrices_equal(&c_naive, &c_mt, "mt_8x8");
 }
 
+#[test]
+fn test_naive_ikj_mt_matches_serial() {
+    // No SIMD needed, so this runs on every architecture
+    let test_cases = [
+        (200, 150, 170),
+        (201, 33, 170),
+        (7, 500, 300),
+        (150, 1, 4000),
+    ];
+
+    for (m, n, k) in test_cases {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+
+        let mut c_serial = vec![0.0; m * n];
+        matmul_naive_ikj(&a, &b, &mut c_serial, m, n, k);
+
+        for threads in [1, 2, 3, 4, 8] {
+            let mut c_mt = vec![0.0; m * n];
+            matmul_naive_ikj_mt(&a, &b, &mut c_mt, m, n, k, threads);
+            assert_matrices_equal(
+                &c_serial,
+                &c_mt,
+                &format!("naive_ikj_mt_{}x{}x{}_t{}", m, n, k, threads),
+            );
+        }
+    }
+}
+
 // ============================================================
 // Non-square matrix tests
 // ============================================================
@@ -328,8 +435,8 @@ fn test_non_square_matrices() {
     ];
 
     for (m, n, k) in test_cases {
-        let a: Vec<f64> = (0..m * k).map(|i| (i % 10) as f64).collect();
-        let b: Vec<f64> = (0..k * n).map(|i| (i % 10) as f64).collect();
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
 
         let mut c_naive = vec![0.0; m * n];
         let mut c_fast = vec![0.0; m * n];
@@ -348,8 +455,8 @@ fn test_non_square_matrices() {
 #[test]
 fn test_accumulation() {
     let size = 64;
-    let a: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
-    let b: Vec<f64> = (0..size * size).map(|i| (i % 10) as f64).collect();
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
 
     // Start with non-zero C
     let mut c_naive = vec![5.0; size * size];
@@ -360,6 +467,1217 @@ fn test_accumulation() {
 
     assert_matrices_equal(&c_naive, &c_fast, "accumulation");
 
-    // Verify values are actually > 5 (not overwritten)
-    assert!(c_fast[0] > 5.0, "Should accumulate, not overwrite");
+    // Verify C is the old 5.0 plus A*B (not overwritten)
+    let mut product = vec![0.0; size * size];
+    matmul_naive_ikj(&a, &b, &mut product, size, size, size);
+    let shifted: Vec<f64> = product.iter().map(|x| x + 5.0).collect();
+    assert_matrices_equal(&shifted, &c_fast, "accumulate, not overwrite");
+}
+
+// ============================================================
+// Row scheduling tests (bands > 1 thread, ragged row counts)
+// ============================================================
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_mt_schedules_match_single_threaded() {
+    if !has_avx2() {
+        println!("Skipping - AVX2 not available");
+        return;
+    }
+
+    // Big enough that choose_thread_count actually picks several threads,
+    // and m isn't a multiple of any kernel height or of the chunk size.
+    let (m, n, k) = (389, 200, 700);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    let mut c_single = vec![0.0; m * n];
+    gemm_12x4::run(&a, &b, &mut c_single, m, n, k).unwrap();
+
+    for schedule in [Schedule::Static, Schedule::Dynamic] {
+        let mut c_4x4 = vec![0.0; m * n];
+        matmul_blocked_4x4_mt_scheduled(&a, &b, &mut c_4x4, m, n, k, 4, schedule);
+        assert_matrices_equal(&c_single, &c_4x4, &format!("mt_4x4_{:?}", schedule));
+
+        let mut c_12x4 = vec![0.0; m * n];
+        matmul_blocked_12x4_mt_scheduled(&a, &b, &mut c_12x4, m, n, k, 4, schedule);
+        assert_matrices_equal(&c_single, &c_12x4, &format!("mt_12x4_{:?}", schedule));
+
+        if has_avx512() {
+            let mut c_8x8 = vec![0.0; m * n];
+            matmul_blocked_8x8_mt_scheduled(&a, &b, &mut c_8x8, m, n, k, 4, schedule);
+            assert_matrices_equal(&c_single, &c_8x8, &format!("mt_8x8_{:?}", schedule));
+        }
+    }
+}
+
+// ============================================================
+// Cancellation tests
+// ============================================================
+
+#[test]
+fn test_cancellable_runs_to_completion() {
+    let (m, n, k) = (130, 70, 90);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    let mut c_naive = vec![0.0; m * n];
+    let mut c_cancellable = vec![0.0; m * n];
+    let cancel = AtomicBool::new(false);
+
+    matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+    let result = multiply_parallel_cancellable(&a, &b, &mut c_cancellable, m, n, k, 4, &cancel);
+
+    assert_eq!(result, Ok(()));
+    assert_matrices_equal(&c_naive, &c_cancellable, "cancellable_uncancelled");
+}
+
+#[test]
+fn test_cancel_before_start() {
+    let size = 256;
+    let a = vec![1.0; size * size];
+    let b = vec![1.0; size * size];
+    let mut c = vec![0.0; size * size];
+    let cancel = AtomicBool::new(true);
+
+    let result = multiply_parallel_cancellable(&a, &b, &mut c, size, size, size, 4, &cancel);
+
+    assert_eq!(result, Err(Cancelled));
+    assert!(c.iter().all(|&x| x == 0.0), "no work should start");
+}
+
+/// A backend that raises the cancel flag while it computes the first chunk,
+/// marking every row it computes with 1.0.
+struct CancelDuringFirstChunk<'a> {
+    cancel: &'a AtomicBool,
+}
+
+impl GemmBackend for CancelDuringFirstChunk<'_> {
+    fn name(&self) -> &str {
+        "cancel during first chunk"
+    }
+
+    fn supported(&self) -> bool {
+        true
+    }
+
+    unsafe fn run(
+        &self,
+        _a: &[f64],
+        _b: &[f64],
+        c: &mut [f64],
+        _m: usize,
+        n: usize,
+        _k: usize,
+        rows: Range<usize>,
+    ) {
+        if rows.start == 0 {
+            self.cancel.store(true, Ordering::Relaxed);
+        }
+        c[..rows.len() * n].fill(1.0);
+    }
+}
+
+#[test]
+fn test_cancel_mid_multiply() {
+    // Small enough for one thread, which takes the 64-row chunks in order
+    let (m, n, k) = (256, 8, 4);
+    let (a, b) = (vec![0.0; m * k], vec![0.0; k * n]);
+    let mut c = vec![0.0; m * n];
+    let cancel = AtomicBool::new(false);
+    let backend = CancelDuringFirstChunk { cancel: &cancel };
+
+    let result =
+        unsafe { parallel_rows_cancellable(&backend, &a, &b, &mut c, m, n, k, 4, &cancel) };
+
+    assert_eq!(result, Err(Cancelled));
+    // The chunk that raised the flag finishes; none after it starts
+    let (first, rest) = c.split_at(64 * n);
+    assert!(first.iter().all(|&x| x == 1.0));
+    assert!(rest.iter().all(|&x| x == 0.0), "a later chunk ran");
+}
+
+/// The public entry point, cancelled from another thread while it runs.
+/// When the flag lands depends on the machine, so a flag that came before
+/// the first chunk (nothing changed) or after the last (`Ok`) just retries
+/// with a longer or shorter wait; a cancel partway through has to come
+/// out as `Err(Cancelled)` with some rows computed and some not.
+#[test]
+fn test_cancel_from_another_thread() {
+    let size = 1024;
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
+    let mut wait = Duration::from_millis(20);
+
+    for _ in 0..12 {
+        let mut c = vec![0.0; size * size];
+        let cancel = AtomicBool::new(false);
+        let result = std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(wait);
+                cancel.store(true, Ordering::Relaxed);
+            });
+            multiply_parallel_cancellable(&a, &b, &mut c, size, size, size, 2, &cancel)
+        });
+        if result.is_ok() {
+            wait /= 4;
+            continue;
+        }
+        assert_eq!(result, Err(Cancelled));
+        let changed = c
+            .chunks_exact(size)
+            .filter(|row| row.iter().any(|&x| x != 0.0))
+            .count();
+        if changed == 0 {
+            wait *= 2;
+            continue;
+        }
+        assert!(changed < size, "cancelled, but every row was computed");
+        return;
+    }
+    panic!("never caught the multiply partway through");
+}
+
+// ============================================================
+// Row band tests (blocked functions called on arbitrary bands)
+// ============================================================
+
+/// The 12×4 driver over whole rows, as a [`BlockedGemm`]
+#[cfg(target_arch = "x86_64")]
+#[allow(clippy::too_many_arguments)]
+unsafe fn rows_12x4(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    start: Option<usize>,
+    end: Option<usize>,
+) {
+    unsafe { matmul_blocked_12x4(a, b, c, m, n, k, start, end, None, None) }
+}
+
+/// The 8×8 driver over whole rows, as a [`BlockedGemm`]
+#[cfg(target_arch = "x86_64")]
+#[allow(clippy::too_many_arguments)]
+unsafe fn rows_8x8(
+    a: &[f64],
+    b: &[f64],
+    c: &mut [f64],
+    m: usize,
+    n: usize,
+    k: usize,
+    start: Option<usize>,
+    end: Option<usize>,
+) {
+    unsafe { matmul_blocked_8x8(a, b, c, m, n, k, start, end, None, None) }
+}
+
+/// Runs `gemm` band by band over `splits` and checks the sum equals the full product.
+fn check_row_bands(
+    gemm: BlockedGemm,
+    name: &str,
+    (m, n, k): (usize, usize, usize),
+    splits: &[usize],
+) {
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    // Non-zero start so double accumulation and skipped rows both show up
+    let mut c_naive = vec![1.0; m * n];
+    let mut c_bands = vec![1.0; m * n];
+
+    matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+    for band in splits.windows(2) {
+        unsafe {
+            let c_band = &mut c_bands[band[0] * n..band[1] * n];
+            gemm(&a, &b, c_band, m, n, k, Some(band[0]), Some(band[1]));
+        }
+    }
+
+    assert_matrices_equal(&c_naive, &c_bands, &format!("{}_bands_{:?}", name, splits));
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_misaligned_row_bands() {
+    if !has_avx2() {
+        println!("Skipping - AVX2 not available");
+        return;
+    }
+
+    let shape = (301, 67, 90);
+    let splits: &[&[usize]] = &[
+        &[0, 130, 258, 301],
+        &[0, 1, 2, 3, 301],
+        &[0, 7, 19, 133, 134, 300, 301],
+        &[0, 301],
+    ];
+
+    for s in splits {
+        check_row_bands(matmul_blocked_4x4, "4x4", shape, s);
+        check_row_bands(rows_12x4, "12x4", shape, s);
+        if has_avx512() {
+            check_row_bands(rows_8x8, "8x8", shape, s);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_narrow_row_bands_on_large_matrix() {
+    if !has_avx2() {
+        println!("Skipping - AVX2 not available");
+        return;
+    }
+
+    // Several kc blocks and several mc panels in play, bands much narrower than mc
+    let (m, n, k) = (530, 20, 300);
+
+    for width in [8, 16, 72] {
+        for offset in [0, 3] {
+            let mut splits: Vec<usize> = (offset..m).step_by(width).collect();
+            if splits[0] != 0 {
+                splits.insert(0, 0);
+            }
+            splits.push(m);
+
+            check_row_bands(matmul_blocked_4x4, "4x4", (m, n, k), &splits);
+            check_row_bands(rows_12x4, "12x4", (m, n, k), &splits);
+            if has_avx512() {
+                check_row_bands(rows_8x8, "8x8", (m, n, k), &splits);
+            }
+        }
+    }
+}
+
+// ============================================================
+// Column range tests (a 3×3 grid of row and column bands)
+// ============================================================
+
+/// The 12×4 or 8×8 driver, taking a row range and a column range
+#[cfg(target_arch = "x86_64")]
+type RectGemm = unsafe fn(
+    &[f64],
+    &[f64],
+    &mut [f64],
+    usize,
+    usize,
+    usize,
+    Option<usize>,
+    Option<usize>,
+    Option<usize>,
+    Option<usize>,
+);
+
+/// Tiles C with one call per (row band, column band) pair and checks the
+/// result is bit-for-bit the one-shot call over all of C.
+#[cfg(target_arch = "x86_64")]
+fn check_grid(
+    gemm: RectGemm,
+    name: &str,
+    (m, n, k): (usize, usize, usize),
+    rows: [usize; 4],
+    cols: [usize; 4],
+) {
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let c0 = random(m, n, 3);
+
+    let mut c_once = c0.clone();
+    unsafe { gemm(&a, &b, &mut c_once, m, n, k, None, None, None, None) };
+
+    let mut c_grid = c0.clone();
+    for band in rows.windows(2) {
+        let c_band = &mut c_grid[band[0] * n..band[1] * n];
+        for span in cols.windows(2) {
+            unsafe {
+                gemm(
+                    &a,
+                    &b,
+                    c_band,
+                    m,
+                    n,
+                    k,
+                    Some(band[0]),
+                    Some(band[1]),
+                    Some(span[0]),
+                    Some(span[1]),
+                )
+            };
+        }
+    }
+
+    assert!(
+        c_grid == c_once,
+        "{} grid rows {:?} cols {:?} differs from one call",
+        name,
+        rows,
+        cols
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_column_ranges_tile_the_product() {
+    if !has_avx2() {
+        println!("Skipping - AVX2 not available");
+        return;
+    }
+
+    let mut drivers: Vec<(&str, RectGemm)> = vec![("12x4", matmul_blocked_12x4)];
+    if has_avx512() {
+        drivers.push(("8x8", matmul_blocked_8x8));
+    }
+
+    // Even thirds, bands that split tiles, and empty and one-wide bands;
+    // the deep shape crosses the 256-deep k block
+    let cases = [
+        ((37, 29, 45), [0, 12, 24, 37], [0, 8, 16, 29]),
+        ((37, 29, 45), [0, 5, 19, 37], [0, 3, 17, 29]),
+        ((37, 29, 45), [0, 0, 1, 37], [0, 1, 1, 29]),
+        ((64, 67, 300), [0, 13, 50, 64], [0, 31, 33, 67]),
+        ((5, 3, 7), [0, 1, 4, 5], [0, 1, 2, 3]),
+    ];
+
+    for (name, gemm) in &drivers {
+        for (shape, rows, cols) in cases {
+            check_grid(*gemm, name, shape, rows, cols);
+        }
+    }
+}
+
+// ============================================================
+// Transpose tests
+// ============================================================
+
+#[test]
+fn test_transpose_ragged_shapes() {
+    // Square, tall, wide, smaller than a tile, and not multiples of the
+    // 4×4 / 8×8 tiles or the 32×32 cache block
+    let shapes = [
+        (1, 1),
+        (3, 5),
+        (4, 4),
+        (8, 8),
+        (7, 13),
+        (32, 32),
+        (33, 31),
+        (64, 9),
+        (9, 64),
+        (100, 37),
+        (129, 257),
+    ];
+
+    for (rows, cols) in shapes {
+        let src = sequential(rows, cols);
+
+        let mut expected = vec![0.0; rows * cols];
+        transpose_naive(&src, &mut expected, rows, cols);
+
+        let mut dst = vec![-1.0; rows * cols];
+        transpose(&src, &mut dst, rows, cols);
+        assert_eq!(dst, expected, "dispatch {}x{}", rows, cols);
+
+        let mut dst = vec![-1.0; rows * cols];
+        transpose_scalar(&src, &mut dst, rows, cols);
+        assert_eq!(dst, expected, "blocked scalar {}x{}", rows, cols);
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx") {
+            let mut dst = vec![-1.0; rows * cols];
+            unsafe { transpose_avx(&src, &mut dst, rows, cols) };
+            assert_eq!(dst, expected, "avx {}x{}", rows, cols);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx512f") {
+            let mut dst = vec![-1.0; rows * cols];
+            unsafe { transpose_avx512(&src, &mut dst, rows, cols) };
+            assert_eq!(dst, expected, "avx512 {}x{}", rows, cols);
+        }
+    }
+}
+
+#[test]
+fn test_transpose_round_trip() {
+    let (rows, cols) = (45, 71);
+    let src: Vec<f64> = (0..rows * cols).map(|i| (i % 97) as f64 - 48.0).collect();
+
+    let mut t = vec![0.0; rows * cols];
+    let mut back = vec![0.0; rows * cols];
+    transpose(&src, &mut t, rows, cols);
+    transpose(&t, &mut back, cols, rows);
+
+    assert_eq!(back, src);
+    assert_eq!(t[5 * rows + 2], src[2 * cols + 5]);
+}
+
+#[test]
+fn test_transpose_parallel_matches_serial() {
+    // Big enough for several threads; column counts that no thread count divides
+    let shapes = [(300, 701), (517, 389), (1024, 129), (5, 9)];
+
+    for (rows, cols) in shapes {
+        let src = sequential(rows, cols);
+
+        let mut expected = vec![0.0; rows * cols];
+        transpose(&src, &mut expected, rows, cols);
+
+        for threads in [1, 2, 3, 5, 7] {
+            let mut dst = vec![-1.0; rows * cols];
+            transpose_parallel(&src, &mut dst, rows, cols, threads);
+            assert_eq!(dst, expected, "{}x{} with {} threads", rows, cols, threads);
+        }
+    }
+}
+
+#[test]
+fn test_transpose_in_place_matches_out_of_place() {
+    // Below, at, and straddling the 32×32 block size
+    for n in [0, 1, 2, 7, 31, 32, 33, 64, 100] {
+        let original = sequential(n, n);
+
+        let mut expected = vec![0.0; n * n];
+        transpose(&original, &mut expected, n, n);
+
+        let mut data = original.clone();
+        transpose_in_place(&mut data, n);
+        assert_eq!(data, expected, "n = {}", n);
+
+        // Twice is the identity
+        transpose_in_place(&mut data, n);
+        assert_eq!(data, original, "round trip n = {}", n);
+    }
+}
+
+#[test]
+#[should_panic(expected = "expected a square 4x4 matrix")]
+fn test_transpose_in_place_rejects_non_square() {
+    let mut data = vec![0.0; 12];
+    transpose_in_place(&mut data, 4);
+}
+
+#[test]
+fn test_transpose_strided_interior_blocks() {
+    // (rows, cols) of the block, placed at (row, col) of a 150×170 source and
+    // (col, row) of a 180×160 destination. Covers SIMD tiles plus ragged edges.
+    let (src_rows, src_cols) = (150, 170);
+    let (dst_rows, dst_cols) = (180, 160);
+    let cases = [
+        (1, 1, 0, 0),
+        (8, 8, 3, 5),
+        (37, 45, 11, 29),
+        (100, 64, 50, 100),
+    ];
+
+    let src = sequential(src_rows, src_cols);
+
+    for (rows, cols, r0, c0) in cases {
+        let mut dst = vec![-1.0; dst_rows * dst_cols];
+        transpose_strided(
+            &src[r0 * src_cols + c0..],
+            src_cols,
+            &mut dst[c0 * dst_cols + r0..],
+            dst_cols,
+            rows,
+            cols,
+        );
+
+        for i in 0..dst_rows {
+            for j in 0..dst_cols {
+                // dst (i, j) is inside the block iff it mirrors src (j, i)
+                let inside = (c0..c0 + cols).contains(&i) && (r0..r0 + rows).contains(&j);
+                let expected = if inside { src[j * src_cols + i] } else { -1.0 };
+                assert_eq!(
+                    dst[i * dst_cols + j],
+                    expected,
+                    "{}x{} block at ({}, {}): dst ({}, {})",
+                    rows,
+                    cols,
+                    r0,
+                    c0,
+                    i,
+                    j
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "needs 23 elements, got 22")]
+fn test_transpose_strided_rejects_short_buffer() {
+    let src = vec![0.0; 22];
+    let mut dst = vec![0.0; 100];
+    transpose_strided(&src, 10, &mut dst, 10, 3, 3);
+}
+
+/// 16-byte element, to catch any assumption that elements are 8 bytes wide
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+fn check_transpose_generic<T: Copy + PartialEq + std::fmt::Debug + Send + Sync + 'static>(
+    make: impl Fn(usize) -> T,
+    name: &str,
+) {
+    for (rows, cols) in [(1, 1), (3, 5), (8, 8), (33, 31), (100, 37), (300, 701)] {
+        let src: Vec<T> = (0..rows * cols).map(&make).collect();
+
+        let mut expected = vec![make(0); rows * cols];
+        transpose_naive(&src, &mut expected, rows, cols);
+
+        let mut dst = vec![make(0); rows * cols];
+        transpose(&src, &mut dst, rows, cols);
+        assert_eq!(dst, expected, "{} {}x{}", name, rows, cols);
+
+        let mut dst = vec![make(0); rows * cols];
+        transpose_parallel(&src, &mut dst, rows, cols, 3);
+        assert_eq!(dst, expected, "{} parallel {}x{}", name, rows, cols);
+
+        if rows == cols {
+            let mut data = src.clone();
+            transpose_in_place(&mut data, rows);
+            assert_eq!(data, expected, "{} in place {}x{}", name, rows, cols);
+        }
+    }
+}
+
+#[test]
+fn test_transpose_generic_element_types() {
+    check_transpose_generic(|i| i as f32, "f32");
+    check_transpose_generic(|i| i as u16, "u16");
+    check_transpose_generic(
+        |i| Complex {
+            re: i as f64,
+            im: -(i as f64),
+        },
+        "complex",
+    );
+
+    // The typed aliases are plain fns
+    let f: fn(&[f32], &mut [f32], usize, usize) = transpose_f32;
+    let mut dst = [0.0f32; 6];
+    f(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &mut dst, 2, 3);
+    assert_eq!(dst, [1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+}
+
+// ============================================================
+// Elementwise tests
+// ============================================================
+
+#[test]
+fn test_elementwise_matches_scalar() {
+    // Every tail length for 4- and 8-wide vectors, plus a longer buffer
+    for len in (0..=17).chain([1000, 1003]) {
+        let c0 = random(1, len, 10);
+        let other = random(1, len, 11);
+        let alpha = -1.75;
+
+        let mut c = c0.clone();
+        scale(&mut c, alpha);
+        let expected: Vec<f64> = c0.iter().map(|x| x * alpha).collect();
+        assert_eq!(c, expected, "scale len {}", len);
+
+        let mut c = c0.clone();
+        add_assign(&mut c, &other);
+        let expected: Vec<f64> = c0.iter().zip(&other).map(|(x, y)| x + y).collect();
+        assert_eq!(c, expected, "add_assign len {}", len);
+
+        // SIMD axpy uses FMA, so allow rounding
+        let mut c = c0.clone();
+        axpy(&mut c, alpha, &other);
+        let expected: Vec<f64> = c0.iter().zip(&other).map(|(x, y)| x + alpha * y).collect();
+        assert_matrices_equal(&expected, &c, &format!("axpy len {}", len));
+    }
+}
+
+#[test]
+#[should_panic(expected = "axpy: length mismatch")]
+fn test_axpy_rejects_length_mismatch() {
+    let mut c = vec![0.0; 4];
+    axpy(&mut c, 1.0, &[1.0; 3]);
+}
+
+// ============================================================
+// Loop-order reference tests
+// ============================================================
+
+#[test]
+fn test_loop_orders_agree() {
+    type Reference = fn(&[f64], &[f64], &mut [f64], usize, usize, usize);
+    let orders: [(&str, Reference); 5] = [
+        ("ijk", matmul_naive_ijk),
+        ("jik", matmul_naive_jik),
+        ("jki", matmul_naive_jki),
+        ("kij", matmul_naive_kij),
+        ("kji", matmul_naive_kji),
+    ];
+
+    for (m, n, k) in [(1, 1, 1), (7, 3, 5), (13, 17, 19), (40, 33, 9)] {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+
+        // C starts non-zero so accumulation is checked too
+        let mut expected = random(m, n, 3);
+        let c0 = expected.clone();
+        matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+
+        for (name, f) in orders {
+            let mut c = c0.clone();
+            f(&a, &b, &mut c, m, n, k);
+            assert_matrices_equal(&expected, &c, &format!("{} {}x{}x{}", name, m, n, k));
+        }
+    }
+}
+
+// ============================================================
+// Portable scalar GEMM tests
+// ============================================================
+
+#[test]
+fn test_gemm_scalar_ragged_sizes() {
+    // Tile edges in every dimension, plus a k larger than one 256-wide block
+    for (m, n, k) in [
+        (1, 1, 1),
+        (3, 5, 7),
+        (4, 4, 4),
+        (13, 17, 19),
+        (130, 67, 300),
+    ] {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+
+        let mut c_naive = random(m, n, 3);
+        let mut c_scalar = c_naive.clone();
+        matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+        matmul_blocked_scalar(&a, &b, &mut c_scalar, m, n, k, None, None);
+
+        assert_matrices_equal(&c_naive, &c_scalar, &format!("scalar {}x{}x{}", m, n, k));
+    }
+}
+
+#[test]
+fn test_gemm_scalar_row_bands() {
+    check_row_bands(
+        matmul_blocked_scalar,
+        "scalar",
+        (301, 67, 90),
+        &[0, 5, 77, 150, 301],
+    );
+}
+
+// ============================================================
+// Pre-transposed B tests
+// ============================================================
+
+#[test]
+fn test_transposed_b_variants() {
+    // Ragged against the 4-wide dot product and every block size
+    for (m, n, k) in [(1, 1, 1), (5, 3, 7), (64, 64, 256), (70, 130, 517)] {
+        let a = random(m, k, 1);
+        let b = random(k, n, 2);
+        let mut bt = vec![0.0; k * n];
+        transpose(&b, &mut bt, k, n);
+
+        let mut c_naive = random(m, n, 3);
+        let c0 = c_naive.clone();
+        matmul_naive_ikj(&a, &b, &mut c_naive, m, n, k);
+
+        let mut c = c0.clone();
+        matmul_ikj_transposed(&a, &bt, &mut c, m, n, k);
+        assert_matrices_equal(&c_naive, &c, &format!("ikj_transposed {}x{}x{}", m, n, k));
+
+        let mut c = c0.clone();
+        matmul_blocked_transposed(&a, &bt, &mut c, m, n, k);
+        assert_matrices_equal(
+            &c_naive,
+            &c,
+            &format!("blocked_transposed {}x{}x{}", m, n, k),
+        );
+    }
+}
+
+// ============================================================
+// .npy files
+// ============================================================
+
+#[test]
+fn test_npy_files_round_trip_through_multiply() {
+    let (m, n, k) = (37, 29, 41);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+
+    let dir = std::env::temp_dir().join(format!("matmul-npy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (path_a, path_b, path_c) = (dir.join("a.npy"), dir.join("b.npy"), dir.join("c.npy"));
+    write_npy(&path_a, &a, m, k).unwrap();
+    write_npy(&path_b, &b, k, n).unwrap();
+
+    let loaded_a = read_npy(&path_a).unwrap();
+    let loaded_b = read_npy(&path_b).unwrap();
+    assert_eq!((loaded_a.rows, loaded_a.cols), (m, k));
+    assert_eq!(loaded_a.data, a);
+    assert_eq!(loaded_b.data, b);
+
+    let mut expected = vec![0.0; m * n];
+    multiply(&a, &b, &mut expected, m, n, k);
+    let mut c = vec![0.0; m * n];
+    multiply(&loaded_a.data, &loaded_b.data, &mut c, m, n, k);
+    assert_eq!(c, expected);
+
+    write_npy(&path_c, &c, m, n).unwrap();
+    assert_eq!(read_npy(&path_c).unwrap().data, expected);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stats_report_the_backend_that_ran() {
+    let (m, n, k) = (67, 45, 300);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let mut expected = vec![0.0; m * n];
+    matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+
+    let mut c = vec![0.0; m * n];
+    let stats = multiply_with_stats(&a, &b, &mut c, m, n, k);
+    assert_eq!(stats.backend, backend_for(m, n, k));
+    assert_eq!(stats.threads_used, 1);
+    assert_matrices_equal(&expected, &c, "multiply_with_stats");
+
+    let backends = [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ];
+    for backend in backends.into_iter().filter(|b| b.is_available()) {
+        let mut c = vec![0.0; m * n];
+        let stats = multiply_with_backend(backend, &a, &b, &mut c, m, n, k);
+        assert_eq!(stats.backend, backend);
+        if backend == Backend::ScalarIkj {
+            assert_eq!((stats.kc, stats.mc), (k, 1));
+        } else {
+            // k = 300 spans two k blocks
+            assert_eq!(stats.kc, 256, "{}", backend);
+        }
+        assert!(stats.gflops > 0.0);
+        assert_matrices_equal(&expected, &c, &backend.to_string());
+    }
+}
+
+#[test]
+fn test_introspection_matches_what_runs() {
+    let (m, n, k) = (20, 9, 5);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let mut c = vec![0.0; m * n];
+    let stats = multiply_with_stats(&a, &b, &mut c, m, n, k);
+    assert_eq!(stats.backend, backend_for(m, n, k));
+    assert_eq!(backend_for(4096, 4096, 4096), detected_backend());
+    assert_eq!(detected_backend(), Backend::detect());
+
+    let available = available_backends();
+    assert_eq!(available.last(), Some(&detected_backend()));
+    for backend in Backend::ALL {
+        assert_eq!(
+            available.contains(&backend),
+            backend.is_available(),
+            "{}",
+            backend
+        );
+    }
+
+    let features = cpu_features();
+    assert_eq!(features, cpu_features());
+    assert_eq!(
+        features.avx2 && features.fma,
+        Backend::Avx2_12x4.is_available()
+    );
+    assert_eq!(
+        features.avx512f && features.fma,
+        Backend::Avx512_8x8.is_available()
+    );
+    assert!(!features.to_string().is_empty());
+}
+
+#[test]
+fn test_parallel_stats_report_thread_scale_down() {
+    let run = |size: usize| {
+        let a = random(size, size, 1);
+        let b = random(size, size, 2);
+        let mut c = vec![0.0; size * size];
+        multiply_parallel_with_stats(&a, &b, &mut c, size, size, size, 4)
+    };
+
+    let small = run(64);
+    assert_eq!(small.threads_used, 1);
+    let large = run(512);
+    assert!(large.threads_used > 1, "{:?}", large);
+    assert!(large.threads_used <= 4);
+    // Without SIMD, multiply_parallel falls back to i-k-j, unlike multiply
+    let expected = match backend_for(64, 64, 64) {
+        Backend::Scalar => Backend::ScalarIkj,
+        simd => simd,
+    };
+    assert_eq!(small.backend, expected);
+}
+
+#[test]
+fn test_try_multiply_reports_wrong_lengths() {
+    let (m, n, k) = (3, 4, 5);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let mut c = vec![0.0; m * n];
+    assert_eq!(try_multiply(&a, &b, &mut c, m, n, k), Ok(()));
+
+    let mut expected = vec![0.0; m * n];
+    matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+    assert_matrices_equal(&expected, &c, "try_multiply");
+
+    let err = try_multiply(&a, &b[1..], &mut c, m, n, k).unwrap_err();
+    assert_eq!(
+        err,
+        MatMulError::WrongLength {
+            operand: "B",
+            rows: k,
+            cols: n,
+            len: k * n - 1
+        }
+    );
+    assert_eq!(err.to_string(), "B: expected 5x4=20 elements, got 19");
+}
+
+/// 2^(bits/2) squared is exactly 2^bits, which wraps to 0: without checked
+/// products, empty slices would pass the length checks
+const WRAPS_TO_ZERO: usize = 1 << (usize::BITS / 2);
+
+#[test]
+fn test_dimension_products_that_wrap_are_rejected() {
+    let big = WRAPS_TO_ZERO;
+    assert_eq!(
+        try_multiply(&[], &[], &mut [], big, 0, big),
+        Err(MatMulError::Overflow {
+            operand: "A",
+            rows: big,
+            cols: big
+        })
+    );
+    assert!(matches!(
+        try_multiply(&[], &[], &mut [], 0, big, big),
+        Err(MatMulError::Overflow { operand: "B", .. })
+    ));
+    assert!(matches!(
+        try_multiply(&[], &[], &mut [], big, big, 0),
+        Err(MatMulError::Overflow { operand: "C", .. })
+    ));
+}
+
+#[test]
+#[should_panic(expected = "overflows usize")]
+fn test_multiply_panics_on_wrapped_dimensions() {
+    multiply(&[], &[], &mut [], WRAPS_TO_ZERO, 0, WRAPS_TO_ZERO);
+}
+
+#[test]
+#[should_panic(expected = "overflows usize")]
+fn test_multiply_parallel_panics_on_wrapped_dimensions() {
+    multiply_parallel(&[], &[], &mut [], 0, WRAPS_TO_ZERO, WRAPS_TO_ZERO, 4);
+}
+
+#[test]
+#[should_panic(expected = "src: 3x3 block with stride")]
+fn test_transpose_strided_rejects_wrapping_stride() {
+    // 2 * stride wraps to 0, so the block would "need" just 3 elements
+    let stride = usize::MAX / 2 + 1;
+    let src = vec![0.0; 3];
+    let mut dst = vec![0.0; 9];
+    transpose_strided(&src, stride, &mut dst, 3, 3, 3);
+}
+
+/// The realistic case on 32-bit targets: 70000² elements is 39 GiB, past
+/// `usize::MAX` there.
+#[test]
+#[cfg(target_pointer_width = "32")]
+fn test_large_square_overflows_on_32_bit() {
+    let size = 70_000;
+    assert!(matches!(
+        try_multiply(&[], &[], &mut [], size, size, size),
+        Err(MatMulError::Overflow { operand: "A", .. })
+    ));
+}
+
+/// Every entry point, for the zero-size tests: `(name, f)`.
+type Entry = (
+    &'static str,
+    Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>,
+);
+
+fn every_entry_point() -> Vec<Entry> {
+    let mut entries: Vec<Entry> = vec![
+        ("multiply", Box::new(multiply)),
+        (
+            "try_multiply",
+            Box::new(|a, b, c, m, n, k| try_multiply(a, b, c, m, n, k).unwrap()),
+        ),
+        (
+            "multiply_parallel",
+            Box::new(|a, b, c, m, n, k| multiply_parallel(a, b, c, m, n, k, 4)),
+        ),
+        (
+            "multiply_parallel_cancellable",
+            Box::new(|a, b, c, m, n, k| {
+                let cancel = AtomicBool::new(false);
+                multiply_parallel_cancellable(a, b, c, m, n, k, 4, &cancel).unwrap()
+            }),
+        ),
+        (
+            "matmul_naive_ikj_mt",
+            Box::new(|a, b, c, m, n, k| matmul_naive_ikj_mt(a, b, c, m, n, k, 4)),
+        ),
+        (
+            "matmul_blocked_scalar",
+            Box::new(|a, b, c, m, n, k| matmul_blocked_scalar(a, b, c, m, n, k, None, None)),
+        ),
+    ];
+    for backend in [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ] {
+        if backend.is_available() {
+            entries.push((
+                "multiply_with_backend",
+                Box::new(move |a, b, c, m, n, k| {
+                    multiply_with_backend(backend, a, b, c, m, n, k);
+                }),
+            ));
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            entries.extend([
+                (
+                    "matmul_blocked_4x4",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        gemm_4x4::run(a, b, c, m, n, k).unwrap()
+                    })
+                        as Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>,
+                ),
+                (
+                    "matmul_blocked_12x4",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        gemm_12x4::run(a, b, c, m, n, k).unwrap()
+                    }),
+                ),
+                (
+                    "matmul_simple_simd",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        simple_simd::run(a, b, c, m, n, k).unwrap()
+                    }),
+                ),
+                (
+                    "matmul_blocked_4x4_mt",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        matmul_blocked_4x4_mt(a, b, c, m, n, k, 4)
+                    }),
+                ),
+                (
+                    "matmul_blocked_12x4_mt",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        matmul_blocked_12x4_mt(a, b, c, m, n, k, 4)
+                    }),
+                ),
+            ]);
+        }
+        if has_avx512() {
+            entries.extend([
+                (
+                    "matmul_blocked_8x8",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        gemm_8x8::run(a, b, c, m, n, k).unwrap()
+                    })
+                        as Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>,
+                ),
+                (
+                    "matmul_blocked_8x8_mt",
+                    Box::new(|a: &[f64], b: &[f64], c: &mut [f64], m, n, k| {
+                        matmul_blocked_8x8_mt(a, b, c, m, n, k, 4)
+                    }),
+                ),
+            ]);
+        }
+    }
+    entries
+}
+
+#[test]
+fn test_zero_sized_dimensions_leave_c_alone() {
+    // Every combination with at least one zero; 13 isn't a multiple of any
+    // tile size
+    for m in [0, 13] {
+        for n in [0, 13] {
+            for k in [0, 13] {
+                if m * n * k != 0 {
+                    continue;
+                }
+                let a = random(m, k, 1);
+                let b = random(k, n, 2);
+                for (name, f) in every_entry_point() {
+                    // k = 0 adds an all-zero product: C keeps its values
+                    let mut c = vec![7.0; m * n];
+                    f(&a, &b, &mut c, m, n, k);
+                    assert!(
+                        c.iter().all(|&x| x == 7.0),
+                        "{} changed C at {}x{}x{}",
+                        name,
+                        m,
+                        n,
+                        k
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_zero_sized_parallel_stats_use_one_thread() {
+    let stats = multiply_parallel_with_stats(&[], &random(5, 9, 2), &mut [], 0, 9, 5, 8);
+    assert_eq!(stats.threads_used, 1);
+    let stats = multiply_parallel_with_stats(&[], &[], &mut vec![1.0; 300 * 400], 300, 400, 0, 8);
+    assert_eq!(stats.threads_used, 1);
+}
+
+/// A and C as two views of one buffer, which only `unsafe` code can build
+/// (and which Miri rightly rejects, hence the ignore)
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_try_multiply_rejects_c_overlapping_a() {
+    let (m, n, k) = (4, 4, 4);
+    let mut buffer = random(m, k, 1);
+    let b = random(k, n, 2);
+    let ptr = buffer.as_mut_ptr();
+    let (a, c) = unsafe {
+        (
+            std::slice::from_raw_parts(ptr, m * k),
+            std::slice::from_raw_parts_mut(ptr, m * n),
+        )
+    };
+    let err = try_multiply(a, &b, c, m, n, k).unwrap_err();
+    assert_eq!(err, MatMulError::Overlap { operand: "A" });
+    assert!(
+        err.to_string()
+            .contains("in-place multiply isn't supported")
+    );
+    // Nothing was written
+    assert_eq!(buffer, random(m, k, 1));
+
+    // Neighbouring parts of one buffer are fine
+    let mut buffer = random(2 * m * k, 1, 3);
+    let (a, c) = buffer.split_at_mut(m * k);
+    assert_eq!(try_multiply(a, &b, &mut c[..m * n], m, n, k), Ok(()));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "C overlaps B")]
+fn test_multiply_debug_asserts_c_does_not_overlap_b() {
+    let size = 3;
+    let a = random(size, size, 1);
+    // C starts one row into B: a partial overlap
+    let mut buffer = random(2 * size, size, 2);
+    let ptr = buffer.as_mut_ptr();
+    let (b, c) = unsafe {
+        (
+            std::slice::from_raw_parts(ptr, size * size),
+            std::slice::from_raw_parts_mut(ptr.add(size), size * size),
+        )
+    };
+    multiply(&a, b, c, size, size, size);
+}
+
+/// NaN, +Inf, -Inf, or finite: what IEEE propagation decides per element.
+/// Summation order can change finite values but never this.
+fn class(x: f64) -> &'static str {
+    if x.is_nan() {
+        "NaN"
+    } else if x == f64::INFINITY {
+        "+Inf"
+    } else if x == f64::NEG_INFINITY {
+        "-Inf"
+    } else {
+        "finite"
+    }
+}
+
+#[test]
+fn test_nan_and_inf_propagate_like_the_reference() {
+    // Edge rows and columns for every tile size, and two k blocks
+    let (m, n, k) = (29, 23, 300);
+    // (matrix, row, col, value): an interior tile, the last edge row or
+    // column, and the second k block
+    let injections: [&[(char, usize, usize, f64)]; 7] = [
+        &[('A', 5, 10, f64::NAN)],
+        &[('A', m - 1, k - 1, f64::INFINITY)],
+        &[('B', 280, n - 1, f64::NEG_INFINITY)],
+        &[('B', 3, 6, f64::NAN)],
+        // +Inf and -Inf meeting in one sum: NaN
+        &[
+            ('A', 12, 0, f64::INFINITY),
+            ('A', 12, 299, f64::NEG_INFINITY),
+        ],
+        // Inf · 0 is NaN, even where the other products are finite
+        &[('A', 20, 7, f64::INFINITY), ('B', 7, 4, 0.0)],
+        &[('B', 100, 0, f64::INFINITY), ('A', m - 1, 100, 0.0)],
+    ];
+
+    for injected in injections {
+        let mut a = random(m, k, 1);
+        let mut b = random(k, n, 2);
+        for &(matrix, row, col, value) in injected {
+            match matrix {
+                'A' => a[row * k + col] = value,
+                _ => b[row * n + col] = value,
+            }
+        }
+        let mut expected = vec![0.0; m * n];
+        matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+        assert!(expected.iter().any(|x| !x.is_finite()));
+
+        for (name, f) in every_entry_point() {
+            let mut c = vec![0.0; m * n];
+            f(&a, &b, &mut c, m, n, k);
+            for i in 0..m {
+                for j in 0..n {
+                    let (e, x) = (expected[i * n + j], c[i * n + j]);
+                    assert_eq!(
+                        class(x),
+                        class(e),
+                        "{} with {:?}: C[{}][{}] = {}, reference {}",
+                        name,
+                        injected,
+                        i,
+                        j,
+                        x,
+                        e
+                    );
+                    if e.is_finite() {
+                        assert!((x - e).abs() <= ATOL + RTOL * e.abs(), "{}", name);
+                    }
+                }
+            }
+        }
+    }
 }
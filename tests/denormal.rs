@@ -0,0 +1,83 @@
+//! `DenormalMode`: scoping, restoring, and the effect on every path.
+
+use matmul::{DenormalMode, with_denormal_mode};
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn test_mode_is_scoped_and_restored() {
+    assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+
+    let (outer, inner) = with_denormal_mode(DenormalMode::FlushToZero, || {
+        let inner = with_denormal_mode(DenormalMode::Preserve, DenormalMode::current);
+        (DenormalMode::current(), inner)
+    });
+    if cfg!(all(
+        any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "aarch64"
+        ),
+        not(miri)
+    )) {
+        assert_eq!(outer, DenormalMode::FlushToZero);
+    }
+    assert_eq!(inner, DenormalMode::Preserve);
+    assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+
+    // Restored on unwind too
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        with_denormal_mode(DenormalMode::FlushToZero, || panic!("inside"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+}
+
+// Only where the mode has an effect: natively, on targets with a control
+// register
+#[cfg(all(
+    any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"),
+    not(miri)
+))]
+mod flush {
+    use matmul::matrix::generate::random;
+    use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+    use matmul::{DenormalMode, multiply, multiply_parallel, with_denormal_mode};
+
+    /// A multiply writing into C
+    type Path<'a> = dyn Fn(&mut [f64]) + 'a;
+
+    /// A whose every element is denormal, and a normal B
+    fn denormal_inputs(m: usize, n: usize, k: usize) -> (Vec<f64>, Vec<f64>) {
+        let a: Vec<f64> = random(m, k, 1).iter().map(|x| x * 1e-310).collect();
+        assert!(a.iter().all(|x| x.is_subnormal() || *x == 0.0));
+        (a, random(k, n, 2))
+    }
+
+    #[test]
+    fn test_flush_to_zero_reaches_every_path() {
+        // Big enough that multiply_parallel splits it across threads
+        let size = 384;
+        let (a, b) = denormal_inputs(size, size, size);
+        let paths: [(&str, &Path); 3] = [
+            ("multiply", &|c| multiply(&a, &b, c, size, size, size)),
+            ("multiply_parallel", &|c| {
+                multiply_parallel(&a, &b, c, size, size, size, 4)
+            }),
+            ("matmul_naive_ikj_mt", &|c| {
+                matmul_naive_ikj_mt(&a, &b, c, size, size, size, 4)
+            }),
+        ];
+
+        for (name, f) in paths {
+            let mut preserved = vec![0.0; size * size];
+            f(&mut preserved);
+            assert!(preserved.iter().any(|&x| x != 0.0), "{}", name);
+
+            // Denormal inputs read as zero, in the workers as well as here
+            let mut flushed = vec![0.0; size * size];
+            with_denormal_mode(DenormalMode::FlushToZero, || f(&mut flushed));
+            assert!(flushed.iter().all(|&x| x == 0.0), "{}", name);
+            assert_eq!(DenormalMode::current(), DenormalMode::Preserve);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! The AVX-512 crossover: what the plain entry points run small multiplies
+//! on, and that moving them doesn't change C.
+
+use matmul::matrix::generate::random;
+use matmul::{
+    Backend, avx512_crossover, backend_for, default_avx512_crossover, detected_backend,
+    multiply_parallel_with_stats, multiply_with_stats, plan, set_avx512_crossover,
+};
+
+#[test]
+fn test_crossover_moves_small_multiplies_to_avx2() {
+    assert_eq!(avx512_crossover(), default_avx512_crossover());
+
+    let (m, n, k) = (40, 70, 100);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let run = || {
+        let mut c = vec![0.0; m * n];
+        let stats = multiply_with_stats(&a, &b, &mut c, m, n, k);
+        (stats.backend, c)
+    };
+
+    set_avx512_crossover(Some(0));
+    assert_eq!(avx512_crossover(), 0);
+    let (wide, wide_c) = run();
+    assert_eq!(wide, detected_backend());
+
+    set_avx512_crossover(Some(100));
+    let (narrow, narrow_c) = run();
+    let expected = if detected_backend() == Backend::Avx512_8x8 {
+        Backend::Avx2_12x4
+    } else {
+        detected_backend()
+    };
+    assert_eq!(narrow, expected);
+    assert_eq!(backend_for(m, n, k), expected);
+    // The SIMD backends agree bit for bit, so the switch doesn't show
+    assert_eq!(wide_c, narrow_c);
+
+    // One dimension past it is enough to keep AVX-512
+    assert_eq!(backend_for(m, n, 101), detected_backend());
+
+    // The threaded entry points and the planner follow it too
+    let mut c = vec![0.0; m * n];
+    let stats = multiply_parallel_with_stats(&a, &b, &mut c, m, n, k, 2);
+    if expected != Backend::Scalar {
+        assert_eq!(c, narrow_c);
+        assert_eq!(stats.backend, expected);
+        assert_eq!(plan(m, n, k, 2).backend, expected);
+    }
+
+    set_avx512_crossover(None);
+    assert_eq!(avx512_crossover(), default_avx512_crossover());
+}
@@ -0,0 +1,165 @@
+//! Every backend against every other, on every combination of small and
+//! tile-boundary dimensions: 26³ shapes, each run through each serial
+//! backend and each threaded one at 1 to 4 threads.
+//!
+//! ```text
+//! cargo test --release --features exhaustive --test exhaustive
+//! ```
+//!
+//! A failure doesn't stop the run. The test finishes the grid and then panics
+//! with a table of disagreeing shapes per backend pair, so a bad edge path
+//! shows up as the whole pattern of shapes it gets wrong. To check a new
+//! kernel, register it in `gemm_backends` (or add a line to [`backends`]).
+
+#![cfg(feature = "exhaustive")]
+
+use matmul::matrix::compare::{Mismatch, check_close};
+use matmul::matrix::generate::random;
+use matmul::matrix::reference::error_bound;
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::threaded::{Schedule, parallel_rows};
+use matmul::{gemm_backends, matmul_naive_ijk, matmul_naive_ikj, multiply_parallel};
+use std::collections::BTreeMap;
+
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::simple_simd;
+#[cfg(target_arch = "x86_64")]
+use matmul::features::has_avx2;
+
+/// C += A·B on m, n, k
+type Multiply = Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>;
+
+/// Every size from 1 to 17 (each remainder of every tile width and height),
+/// and one either side of 32, 64, and 128
+const DIMS: [usize; 26] = [
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 31, 32, 33, 63, 64, 65, 127, 128,
+    129,
+];
+
+/// Highest thread count each threaded backend runs with
+const MAX_THREADS: usize = 4;
+
+/// Every backend this CPU can run, by name: each registered
+/// [`GemmBackend`](matmul::GemmBackend) alone and through the threaded
+/// driver, plus the entry points and loops outside the registry.
+fn backends() -> Vec<(String, Multiply)> {
+    let registered = || gemm_backends().iter().filter(|backend| backend.supported());
+    let mut backends = Vec::new();
+    let mut serial = |name: &str, f: Multiply| backends.push((name.to_string(), f));
+    serial("naive_ijk", Box::new(matmul_naive_ijk));
+    serial("naive_ikj", Box::new(matmul_naive_ikj));
+    for backend in registered() {
+        serial(
+            backend.name(),
+            Box::new(|a, b, c, m, n, k| unsafe { backend.run(a, b, c, m, n, k, 0..m) }),
+        );
+    }
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2() {
+        serial(
+            "simple_simd",
+            Box::new(|a, b, c, m, n, k| simple_simd::run(a, b, c, m, n, k).unwrap()),
+        );
+    }
+
+    threaded(&mut backends, "multiply_parallel", multiply_parallel);
+    threaded(&mut backends, "naive_ikj_mt", matmul_naive_ikj_mt);
+    for backend in registered() {
+        threaded(
+            &mut backends,
+            &format!("{} MT", backend.name()),
+            |a, b, c, m, n, k, threads| unsafe {
+                parallel_rows(*backend, a, b, c, m, n, k, threads, Schedule::default())
+            },
+        );
+    }
+    backends
+}
+
+/// Adds `f` at each thread count from 1 to [`MAX_THREADS`].
+fn threaded<F>(backends: &mut Vec<(String, Multiply)>, name: &str, f: F)
+where
+    F: Fn(&[f64], &[f64], &mut [f64], usize, usize, usize, usize) + Copy + 'static,
+{
+    for threads in 1..=MAX_THREADS {
+        let run = move |a: &[f64], b: &[f64], c: &mut [f64], m, n, k| f(a, b, c, m, n, k, threads);
+        backends.push((format!("{} x{}", name, threads), Box::new(run)));
+    }
+}
+
+/// A disagreement between two backends on one shape.
+struct Failure {
+    shape: (usize, usize, usize),
+    mismatch: Mismatch,
+}
+
+#[test]
+fn test_backends_agree_pairwise() {
+    let backends = backends();
+    // Keyed by the pair's indices into `backends`, first < second
+    let mut failures: BTreeMap<(usize, usize), Vec<Failure>> = BTreeMap::new();
+
+    for m in DIMS {
+        for n in DIMS {
+            for k in DIMS {
+                let a = random(m, k, 1);
+                let b = random(k, n, 2);
+                let c_initial = random(m, n, 3);
+                let results: Vec<Vec<f64>> = backends
+                    .iter()
+                    .map(|(_, f)| {
+                        let mut c = c_initial.clone();
+                        f(&a, &b, &mut c, m, n, k);
+                        c
+                    })
+                    .collect();
+
+                // Each result is within γ(k + 1)·(k + 1) of the exact one
+                // (inputs are in [-1, 1)), so any two are within twice that
+                let tolerance = 2.0 * error_bound(k + 1) * (k + 1) as f64;
+                // Everything within half the tolerance of the first result
+                // agrees pairwise too, which saves comparing every pair
+                let all_close = results
+                    .iter()
+                    .all(|c| check_close(&results[0], c, 0.0, tolerance / 2.0).is_ok());
+                if all_close {
+                    continue;
+                }
+                for i in 0..results.len() {
+                    for j in i + 1..results.len() {
+                        if let Err(mismatch) = check_close(&results[i], &results[j], 0.0, tolerance)
+                        {
+                            failures.entry((i, j)).or_default().push(Failure {
+                                shape: (m, n, k),
+                                mismatch,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        return;
+    }
+    let shapes = DIMS.len().pow(3);
+    let mut report = String::new();
+    for (&(i, j), pair_failures) in &failures {
+        report += &format!(
+            "\n{} vs {}: {} of {} shapes\n",
+            backends[i].0,
+            backends[j].0,
+            pair_failures.len(),
+            shapes
+        );
+        for Failure { shape, mismatch } in pair_failures {
+            report += &format!("  {}x{}x{}: {}\n", shape.0, shape.1, shape.2, mismatch);
+        }
+    }
+    panic!(
+        "{} backend pairs disagree on some shapes:\n{}",
+        failures.len(),
+        report
+    );
+}
@@ -0,0 +1,274 @@
+//! Calls the exported `matmul_dgemm` symbol through the C ABI, the way a C or
+//! Julia caller would.
+
+#![cfg(feature = "capi")]
+
+use matmul::ffi::{
+    CBLAS_COL_MAJOR, CBLAS_CONJ_TRANS, CBLAS_NO_TRANS, CBLAS_ROW_MAJOR, CBLAS_TRANS, MATMUL_SUCCESS,
+};
+use matmul::matrix::compare::assert_close;
+use matmul::matrix::generate::random;
+use std::ffi::c_int;
+use std::ptr;
+
+// Declared by hand rather than through the Rust path, so the test goes
+// through the unmangled symbol and the C calling convention.
+unsafe extern "C" {
+    fn matmul_dgemm(
+        layout: c_int,
+        transa: c_int,
+        transb: c_int,
+        m: c_int,
+        n: c_int,
+        k: c_int,
+        alpha: f64,
+        a: *const f64,
+        lda: c_int,
+        b: *const f64,
+        ldb: c_int,
+        beta: f64,
+        c: *mut f64,
+        ldc: c_int,
+    ) -> c_int;
+}
+
+/// Element (i, j) of a stored matrix in either layout
+fn at(x: &[f64], row_major: bool, ld: usize, i: usize, j: usize) -> f64 {
+    if row_major {
+        x[i * ld + j]
+    } else {
+        x[j * ld + i]
+    }
+}
+
+#[test]
+fn test_dgemm_all_layouts_and_transposes() {
+    let (m, n, k) = (13, 9, 21);
+    let (alpha, beta) = (1.5, -0.5);
+    let pad = 3;
+
+    for layout in [CBLAS_ROW_MAJOR, CBLAS_COL_MAJOR] {
+        let row_major = layout == CBLAS_ROW_MAJOR;
+        for transa in [CBLAS_NO_TRANS, CBLAS_TRANS, CBLAS_CONJ_TRANS] {
+            for transb in [CBLAS_NO_TRANS, CBLAS_TRANS] {
+                let ta = transa != CBLAS_NO_TRANS;
+                let tb = transb != CBLAS_NO_TRANS;
+
+                // Stored shapes, with padded leading dimensions
+                let (a_rows, a_cols) = if ta { (k, m) } else { (m, k) };
+                let (b_rows, b_cols) = if tb { (n, k) } else { (k, n) };
+                let lead = |rows: usize, cols: usize| {
+                    if row_major {
+                        (cols + pad, rows)
+                    } else {
+                        (rows + pad, cols)
+                    }
+                };
+                let (lda, a_lines) = lead(a_rows, a_cols);
+                let (ldb, b_lines) = lead(b_rows, b_cols);
+                let (ldc, c_lines) = lead(m, n);
+
+                let a = random(a_lines, lda, 1);
+                let b = random(b_lines, ldb, 2);
+                let c0 = random(c_lines, ldc, 3);
+
+                let mut expected = c0.clone();
+                for i in 0..m {
+                    for j in 0..n {
+                        let mut sum = 0.0;
+                        for p in 0..k {
+                            let a_ip = if ta {
+                                at(&a, row_major, lda, p, i)
+                            } else {
+                                at(&a, row_major, lda, i, p)
+                            };
+                            let b_pj = if tb {
+                                at(&b, row_major, ldb, j, p)
+                            } else {
+                                at(&b, row_major, ldb, p, j)
+                            };
+                            sum += a_ip * b_pj;
+                        }
+                        let idx = if row_major { i * ldc + j } else { j * ldc + i };
+                        expected[idx] = alpha * sum + beta * c0[idx];
+                    }
+                }
+
+                let mut c = c0.clone();
+                let status = unsafe {
+                    matmul_dgemm(
+                        layout,
+                        transa,
+                        transb,
+                        m as c_int,
+                        n as c_int,
+                        k as c_int,
+                        alpha,
+                        a.as_ptr(),
+                        lda as c_int,
+                        b.as_ptr(),
+                        ldb as c_int,
+                        beta,
+                        c.as_mut_ptr(),
+                        ldc as c_int,
+                    )
+                };
+
+                assert_eq!(status, MATMUL_SUCCESS);
+                // Padding included: elements outside C must be untouched
+                assert_close(&expected, &c, 1e-12, 1e-12);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dgemm_beta_zero_ignores_c() {
+    let a = [1.0, 2.0, 3.0, 4.0];
+    let b = [1.0, 0.0, 0.0, 1.0];
+    let mut c = [f64::NAN; 4];
+
+    let status = unsafe {
+        matmul_dgemm(
+            CBLAS_ROW_MAJOR,
+            CBLAS_NO_TRANS,
+            CBLAS_NO_TRANS,
+            2,
+            2,
+            2,
+            2.0,
+            a.as_ptr(),
+            2,
+            b.as_ptr(),
+            2,
+            0.0,
+            c.as_mut_ptr(),
+            2,
+        )
+    };
+
+    assert_eq!(status, MATMUL_SUCCESS);
+    assert_eq!(c, [2.0, 4.0, 6.0, 8.0]);
+}
+
+#[test]
+fn test_dgemm_reports_bad_argument_position() {
+    let a = [0.0; 16];
+    let b = [0.0; 16];
+    let mut c = [0.0; 16];
+    let (ap, bp, cp) = (a.as_ptr(), b.as_ptr(), c.as_mut_ptr());
+    let (row, nt) = (CBLAS_ROW_MAJOR, CBLAS_NO_TRANS);
+
+    let cases: [(c_int, [c_int; 9], [*const f64; 2], *mut f64); 9] = [
+        (1, [0, nt, nt, 2, 2, 2, 2, 2, 2], [ap, bp], cp),
+        (2, [row, 7, nt, 2, 2, 2, 2, 2, 2], [ap, bp], cp),
+        (3, [row, nt, 0, 2, 2, 2, 2, 2, 2], [ap, bp], cp),
+        (4, [row, nt, nt, -1, 2, 2, 2, 2, 2], [ap, bp], cp),
+        (6, [row, nt, nt, 2, 2, -3, 2, 2, 2], [ap, bp], cp),
+        (8, [row, nt, nt, 2, 2, 2, 2, 2, 2], [ptr::null(), bp], cp),
+        (9, [row, nt, nt, 2, 2, 3, 2, 2, 2], [ap, bp], cp),
+        (11, [row, nt, nt, 2, 4, 2, 2, 3, 4], [ap, bp], cp),
+        (14, [row, nt, nt, 2, 3, 2, 2, 3, 2], [ap, bp], cp),
+    ];
+
+    for (expected, [layout, ta, tb, m, n, k, lda, ldb, ldc], [a, b], c) in cases {
+        let status =
+            unsafe { matmul_dgemm(layout, ta, tb, m, n, k, 1.0, a, lda, b, ldb, 1.0, c, ldc) };
+        assert_eq!(status, expected);
+    }
+
+    // Null C is caught too
+    let status = unsafe {
+        matmul_dgemm(
+            row,
+            nt,
+            nt,
+            2,
+            2,
+            2,
+            1.0,
+            ap,
+            2,
+            bp,
+            2,
+            1.0,
+            ptr::null_mut(),
+            2,
+        )
+    };
+    assert_eq!(status, 13);
+    assert!(c.iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn test_dgemm_alpha_zero_ignores_a_and_b() {
+    // C = beta * C without reading A or B, which may then be null
+    let mut c = [1.0, 2.0, 3.0, 4.0];
+    let status = unsafe {
+        matmul_dgemm(
+            CBLAS_ROW_MAJOR,
+            CBLAS_NO_TRANS,
+            CBLAS_NO_TRANS,
+            2,
+            2,
+            3,
+            0.0,
+            ptr::null(),
+            3,
+            ptr::null(),
+            2,
+            2.0,
+            c.as_mut_ptr(),
+            2,
+        )
+    };
+    assert_eq!(status, MATMUL_SUCCESS);
+    assert_eq!(c, [2.0, 4.0, 6.0, 8.0]);
+}
+
+#[test]
+fn test_dgemm_empty_dimensions() {
+    // Nothing to compute: null pointers are fine and C is left alone
+    let mut c = [7.0; 4];
+    let status = unsafe {
+        matmul_dgemm(
+            CBLAS_ROW_MAJOR,
+            CBLAS_NO_TRANS,
+            CBLAS_NO_TRANS,
+            0,
+            0,
+            0,
+            1.0,
+            ptr::null(),
+            1,
+            ptr::null(),
+            1,
+            0.0,
+            ptr::null_mut(),
+            1,
+        )
+    };
+    assert_eq!(status, MATMUL_SUCCESS);
+
+    // k == 0: C = beta * C
+    let status = unsafe {
+        matmul_dgemm(
+            CBLAS_ROW_MAJOR,
+            CBLAS_NO_TRANS,
+            CBLAS_NO_TRANS,
+            2,
+            2,
+            0,
+            1.0,
+            ptr::null(),
+            1,
+            ptr::null(),
+            2,
+            0.5,
+            c.as_mut_ptr(),
+            2,
+        )
+    };
+    assert_eq!(status, MATMUL_SUCCESS);
+    assert_eq!(c, [3.5; 4]);
+}
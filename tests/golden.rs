@@ -0,0 +1,239 @@
+//! Golden files for the runner's output: the table, csv, and json of a tiny
+//! run (seed 1, 8×8, scalar methods only, so it runs the same anywhere),
+//! with whatever depends on the machine or the clock masked out.
+//!
+//! A formatting change fails here until the files in `tests/golden/` are
+//! regenerated, so it shows up in review as a diff of them:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+
+// Miri can't spawn the benchmark binary
+#![cfg(not(miri))]
+
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// The run every golden file comes from, plus `extra`.
+fn run(extra: &[&str]) -> String {
+    let mut args = vec![
+        "--sizes",
+        "8",
+        "--only",
+        "naive,ikj,4x4scalar",
+        "--iters",
+        "1",
+        "--threads",
+        "1",
+        "--seed",
+        "1",
+    ];
+    args.extend(extra);
+    let output = Command::new(env!("CARGO_BIN_EXE_matmul"))
+        .args(&args)
+        .output()
+        .expect("failed to run the matmul binary");
+    assert!(
+        output.status.success(),
+        "matmul {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Compares `actual` with `tests/golden/<name>`, or rewrites the file with
+/// `UPDATE_GOLDEN` set.
+fn check_golden(name: &str, actual: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (UPDATE_GOLDEN=1 creates it)", path.display(), e));
+    if expected != actual {
+        let line = expected
+            .lines()
+            .zip(actual.lines())
+            .position(|(x, y)| x != y)
+            .unwrap_or(expected.lines().count().min(actual.lines().count()));
+        panic!(
+            "{} differs from the output, first at line {}:\n  golden: {:?}\n  actual: {:?}\n\
+             If the change is deliberate, rerun with UPDATE_GOLDEN=1 and review the diff.\n\n{}",
+            path.display(),
+            line + 1,
+            expected.lines().nth(line),
+            actual.lines().nth(line),
+            actual
+        );
+    }
+}
+
+/// Whether `token` is a number, with the table's wrappers (`(1.1×)`)
+/// around it.
+fn is_number(token: &str) -> bool {
+    let core = token
+        .trim_start_matches('(')
+        .trim_end_matches(&[')', '×'][..]);
+    !core.is_empty() && core.parse::<f64>().is_ok()
+}
+
+/// The table output with machine lines dropped and measurements (a number
+/// before a unit, or a speedup) masked; the words, units, and checksums
+/// stay. Runs of spaces become one.
+fn normalize_table(stdout: &str) -> String {
+    let machine_lines = [
+        "CPU features:",
+        "Peak (estimate):",
+        "  Assumes ",
+        "Roofline:",
+    ];
+    let units = ["ms", "GFLOPS", "GF"];
+    let bytes = ["B", "KiB", "MiB", "GiB"];
+    let mut out = String::new();
+    for line in stdout.lines() {
+        if machine_lines.iter().any(|prefix| line.starts_with(prefix)) {
+            continue;
+        }
+        // Each row's share of the peak estimate, only there with one
+        let line = match (line.find("  ["), line.find("peak]")) {
+            (Some(start), Some(end)) => format!("{}{}", &line[..start], &line[end + 5..]),
+            _ => line.to_string(),
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let masked: Vec<&str> = tokens
+            .iter()
+            .enumerate()
+            .map(|(i, &token)| {
+                let next = tokens.get(i + 1).copied().unwrap_or("");
+                let after_number = i > 0 && is_number(tokens[i - 1]);
+                if is_number(token) && token.contains('×') {
+                    "<speedup>"
+                } else if is_number(token) && (units.contains(&next) || bytes.contains(&next)) {
+                    "<n>"
+                } else if after_number && bytes.contains(&token) {
+                    "<bytes>"
+                } else {
+                    token
+                }
+            })
+            .collect();
+        out.push_str(&masked.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// Csv columns that are measurements, not results
+const MEASURED: [&str; 6] = [
+    "time_ms",
+    "gflops",
+    "speedup",
+    "heap_bytes",
+    "rss_bytes",
+    "max_abs_err",
+];
+
+fn normalize_csv(stdout: &str) -> String {
+    let mut lines = stdout.lines();
+    let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+    let mut out = format!("{}\n", header.join(","));
+    for line in lines {
+        let fields: Vec<&str> = line
+            .split(',')
+            .zip(&header)
+            .map(|(field, column)| {
+                if MEASURED.contains(column) {
+                    "<n>"
+                } else {
+                    field
+                }
+            })
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Every value replaced by its type, except the ones a run with these
+/// settings always gives, so what's left is the schema: field names, where
+/// they nest, and which are numbers, strings, or flags.
+fn normalize_json(value: &mut Value, key: &str) {
+    const DETERMINISTIC: [&str; 13] = [
+        "method",
+        "name",
+        "m",
+        "n",
+        "k",
+        "mode",
+        "seed",
+        "baseline",
+        "threads",
+        "iterations",
+        "verified",
+        "sum",
+        "bits",
+    ];
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                normalize_json(value, key);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| normalize_json(item, key)),
+        Value::Null => {}
+        _ if DETERMINISTIC.contains(&key) => {}
+        Value::Number(_) => *value = "<number>".into(),
+        Value::String(_) => *value = "<string>".into(),
+        Value::Bool(_) => *value = "<bool>".into(),
+    }
+}
+
+#[test]
+fn test_table_golden() {
+    check_golden("summary.txt", &normalize_table(&run(&[])));
+}
+
+#[test]
+fn test_csv_golden() {
+    check_golden(
+        "summary.csv",
+        &normalize_csv(&run(&["--format", "csv", "--verify"])),
+    );
+}
+
+#[test]
+fn test_json_golden() {
+    let stdout = run(&["--format", "json", "--verify"]);
+    let mut doc: Value = serde_json::from_str(&stdout).expect("stdout is not JSON");
+    // Present wherever the clock and bandwidth can be measured, and the
+    // counters only with `perf-events`; the golden file has the peak's
+    // fields, and no counters
+    if doc["peak"].is_null() {
+        let golden = std::fs::read_to_string(
+            [
+                env!("CARGO_MANIFEST_DIR"),
+                "tests",
+                "golden",
+                "summary.json",
+            ]
+            .iter()
+            .collect::<PathBuf>(),
+        )
+        .unwrap();
+        doc["peak"] = serde_json::from_str::<Value>(&golden).unwrap()["peak"].clone();
+    }
+    for row in doc["results"].as_array_mut().unwrap() {
+        row["perf"] = Value::Null;
+    }
+    normalize_json(&mut doc, "");
+    let mut pretty = serde_json::to_string_pretty(&doc).unwrap();
+    pretty.push('\n');
+    check_golden("summary.json", &pretty);
+}
@@ -0,0 +1,329 @@
+//! The reduced set for Miri: every driver, the dispatch, and the thread
+//! splitting, at shapes small enough to interpret.
+//!
+//! ```text
+//! cargo +nightly miri test --lib --test miri
+//! ```
+//!
+//! Under Miri the SIMD microkernels are scalar stand-ins (Miri can't execute
+//! their intrinsics), so this checks everything around them: panel packing,
+//! edge loops, blocked index arithmetic, and band splitting, for
+//! out-of-bounds accesses, aliasing violations, and data races. Miri also
+//! splits every threaded multiply across all the threads it's given, however
+//! small. The same tests run natively, where they're just a fast smoke test.
+
+use matmul::blocked::gemm_scalar::matmul_blocked_scalar;
+use matmul::matrix::compare::{assert_close, check_close};
+use matmul::matrix::generate::random;
+use matmul::matrix::naive_ikj::matmul_naive_ikj;
+use matmul::matrix::transpose::{
+    transpose, transpose_in_place, transpose_naive, transpose_parallel, transpose_strided,
+};
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::threaded::{BlockedGemm, Schedule, parallel_rows};
+use matmul::{
+    Backend, MatMul, MatmulPlan, PackedA, available_backends, gemm_backends,
+    matmul_blocked_transposed, matmul_naive_ijk, matmul_naive_jik, matmul_naive_jki,
+    matmul_naive_kij, matmul_naive_kji, multiply, multiply_parallel, multiply_parallel_cancellable,
+    multiply_prepacked_a, multiply_with_backend,
+};
+use std::sync::atomic::AtomicBool;
+
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::{
+    gemm_4x4::matmul_blocked_4x4, gemm_8x8::matmul_blocked_8x8, gemm_12x4::matmul_blocked_12x4,
+};
+#[cfg(target_arch = "x86_64")]
+use matmul::features::{has_avx2, has_avx512};
+
+/// C += A·B on m, n, k
+type Naive = fn(&[f64], &[f64], &mut [f64], usize, usize, usize);
+
+/// Whole tiles with edges on both sides, a single element, and a depth
+/// that crosses the 256-deep k block
+const SHAPES: [(usize, usize, usize); 4] = [(13, 9, 5), (1, 1, 1), (25, 17, 3), (12, 8, 257)];
+
+/// `C0 + A·B` by the naive loop, for inputs seeded by the shape.
+fn expected(m: usize, n: usize, k: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>) {
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let c = random(m, n, 3);
+    let mut want = c.clone();
+    matmul_naive_ikj(&a, &b, &mut want, m, n, k);
+    (a, b, c, want)
+}
+
+/// The blocked drivers this CPU can run, with their kernel heights.
+fn drivers() -> Vec<(&'static str, BlockedGemm, usize)> {
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused_mut))]
+    let mut drivers: Vec<(&'static str, BlockedGemm, usize)> =
+        vec![("matmul_blocked_scalar", matmul_blocked_scalar, 4)];
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            drivers.push(("matmul_blocked_4x4", matmul_blocked_4x4, 4));
+            drivers.push((
+                "matmul_blocked_12x4",
+                |a, b, c, m, n, k, start, end| unsafe {
+                    matmul_blocked_12x4(a, b, c, m, n, k, start, end, None, None)
+                },
+                12,
+            ));
+        }
+        if has_avx512() {
+            drivers.push((
+                "matmul_blocked_8x8",
+                |a, b, c, m, n, k, start, end| unsafe {
+                    matmul_blocked_8x8(a, b, c, m, n, k, start, end, None, None)
+                },
+                8,
+            ));
+        }
+    }
+    drivers
+}
+
+#[test]
+fn test_every_backend() {
+    for (m, n, k) in SHAPES {
+        let (a, b, c0, want) = expected(m, n, k);
+        for backend in [
+            Backend::Scalar,
+            Backend::ScalarIkj,
+            Backend::Avx2_4x4,
+            Backend::Avx2_12x4,
+            Backend::Avx512_8x8,
+        ] {
+            if backend.is_available() {
+                let mut c = c0.clone();
+                multiply_with_backend(backend, &a, &b, &mut c, m, n, k);
+                assert_close(&want, &c, 1e-12, 1e-12);
+            }
+        }
+        let mut c = c0.clone();
+        multiply(&a, &b, &mut c, m, n, k);
+        assert_close(&want, &c, 1e-12, 1e-12);
+    }
+}
+
+#[test]
+fn test_prepacked_a() {
+    for (m, n, k) in SHAPES {
+        let (a, b, c0, _) = expected(m, n, k);
+        for backend in [
+            Backend::Scalar,
+            Backend::Avx2_4x4,
+            Backend::Avx2_12x4,
+            Backend::Avx512_8x8,
+        ] {
+            if backend.is_available() {
+                let mut want = c0.clone();
+                multiply_with_backend(backend, &a, &b, &mut want, m, n, k);
+                let packed = PackedA::pack(&a, m, k, backend).unwrap();
+                let mut c = c0.clone();
+                multiply_prepacked_a(&packed, &b, &mut c, m, n, k, None).unwrap();
+                assert_eq!(c, want, "{} on {}x{}x{}", backend, m, n, k);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_matmul_and_plan_bands() {
+    // Miri splits any shape across every thread it's allowed
+    let (m, n, k) = SHAPES[0];
+    let (a, b, c0, want) = expected(m, n, k);
+    for backend in available_backends() {
+        let config = MatMul::new().backend(backend).threads(3);
+        let mut c = c0.clone();
+        config
+            .clone()
+            .build()
+            .unwrap()
+            .compute_into(&a, &b, &mut c, m, n, k)
+            .unwrap();
+        assert_close(&want, &c, 1e-12, 1e-12);
+
+        let mut plan = MatmulPlan::new(m, n, k, &config).unwrap();
+        let mut c = c0.clone();
+        plan.execute(&a, &b, &mut c).unwrap();
+        assert_close(&want, &c, 1e-12, 1e-12);
+    }
+}
+
+#[test]
+fn test_drivers_on_unaligned_row_bands() {
+    let (m, n, k) = (25, 17, 3);
+    let (a, b, c0, want) = expected(m, n, k);
+    for (name, gemm, _) in drivers() {
+        // Bands that start and end mid-tile, as a dynamic schedule can hand out
+        let mut c = c0.clone();
+        let mut rest = &mut c[..];
+        for (start, end) in [(0, 5), (5, 6), (6, 19), (19, 25)] {
+            let (band, tail) = rest.split_at_mut((end - start) * n);
+            rest = tail;
+            unsafe { gemm(&a, &b, band, m, n, k, Some(start), Some(end)) };
+        }
+        if let Err(mismatch) = check_close(&want, &c, 1e-12, 1e-12) {
+            panic!("{}: {}", name, mismatch);
+        }
+    }
+}
+
+/// The 12×4 or 8×8 driver, taking a row range and a column range
+#[cfg(target_arch = "x86_64")]
+type RectGemm = unsafe fn(
+    &[f64],
+    &[f64],
+    &mut [f64],
+    usize,
+    usize,
+    usize,
+    Option<usize>,
+    Option<usize>,
+    Option<usize>,
+    Option<usize>,
+);
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_drivers_on_column_ranges() {
+    let (m, n, k) = (25, 17, 3);
+    let (a, b, c0, want) = expected(m, n, k);
+    let mut rects: Vec<(&str, RectGemm)> = Vec::new();
+    if has_avx2() {
+        rects.push(("matmul_blocked_12x4", matmul_blocked_12x4));
+    }
+    if has_avx512() {
+        rects.push(("matmul_blocked_8x8", matmul_blocked_8x8));
+    }
+    for (name, gemm) in rects {
+        // Row and column bands that start and end mid-tile
+        let mut c = c0.clone();
+        let mut rest = &mut c[..];
+        for (start, end) in [(0, 5), (5, 19), (19, 25)] {
+            let (band, tail) = rest.split_at_mut((end - start) * n);
+            rest = tail;
+            for (col_start, col_end) in [(0, 3), (3, 13), (13, 17)] {
+                unsafe {
+                    gemm(
+                        &a,
+                        &b,
+                        band,
+                        m,
+                        n,
+                        k,
+                        Some(start),
+                        Some(end),
+                        Some(col_start),
+                        Some(col_end),
+                    )
+                };
+            }
+        }
+        if let Err(mismatch) = check_close(&want, &c, 1e-12, 1e-12) {
+            panic!("{}: {}", name, mismatch);
+        }
+    }
+}
+
+#[test]
+fn test_threaded_drivers() {
+    let (m, n, k) = (29, 7, 6);
+    let (a, b, c0, want) = expected(m, n, k);
+    for backend in gemm_backends().iter().filter(|backend| backend.supported()) {
+        for schedule in [Schedule::Static, Schedule::Dynamic] {
+            let mut c = c0.clone();
+            unsafe { parallel_rows(*backend, &a, &b, &mut c, m, n, k, 3, schedule) };
+            if let Err(mismatch) = check_close(&want, &c, 1e-12, 1e-12) {
+                panic!("{} {:?}: {}", backend.name(), schedule, mismatch);
+            }
+        }
+    }
+
+    let mut c = c0.clone();
+    multiply_parallel(&a, &b, &mut c, m, n, k, 3);
+    assert_close(&want, &c, 1e-12, 1e-12);
+
+    let mut c = c0.clone();
+    let cancel = AtomicBool::new(false);
+    multiply_parallel_cancellable(&a, &b, &mut c, m, n, k, 3, &cancel).unwrap();
+    assert_close(&want, &c, 1e-12, 1e-12);
+
+    let mut c = c0.clone();
+    matmul_naive_ikj_mt(&a, &b, &mut c, m, n, k, 3);
+    assert_close(&want, &c, 1e-12, 1e-12);
+}
+
+#[test]
+fn test_naive_orders_and_transposed_driver() {
+    let (m, n, k) = (6, 5, 7);
+    let (a, b, c0, want) = expected(m, n, k);
+    let orders: [(&str, Naive); 5] = [
+        ("ijk", matmul_naive_ijk),
+        ("jik", matmul_naive_jik),
+        ("jki", matmul_naive_jki),
+        ("kij", matmul_naive_kij),
+        ("kji", matmul_naive_kji),
+    ];
+    for (name, f) in orders {
+        let mut c = c0.clone();
+        f(&a, &b, &mut c, m, n, k);
+        if let Err(mismatch) = check_close(&want, &c, 1e-12, 1e-12) {
+            panic!("{}: {}", name, mismatch);
+        }
+    }
+
+    let mut bt = vec![0.0; k * n];
+    transpose(&b, &mut bt, k, n);
+    let mut c = c0.clone();
+    matmul_blocked_transposed(&a, &bt, &mut c, m, n, k);
+    assert_close(&want, &c, 1e-12, 1e-12);
+}
+
+#[test]
+fn test_zero_sized_dimensions() {
+    for (m, n, k) in [(0, 3, 4), (3, 0, 4), (3, 4, 0)] {
+        let (a, b) = (vec![1.0; m * k], vec![1.0; k * n]);
+        let mut c = vec![2.0; m * n];
+        multiply(&a, &b, &mut c, m, n, k);
+        multiply_parallel(&a, &b, &mut c, m, n, k, 3);
+        for (_, gemm, _) in drivers() {
+            unsafe { gemm(&a, &b, &mut c, m, n, k, None, None) };
+        }
+        assert!(c.iter().all(|&x| x == 2.0));
+    }
+}
+
+#[test]
+fn test_transposes() {
+    let (rows, cols) = (11, 37);
+    let src = random(rows, cols, 1);
+    let mut want = vec![0.0; rows * cols];
+    transpose_naive(&src, &mut want, rows, cols);
+
+    let mut dst = vec![0.0; rows * cols];
+    transpose(&src, &mut dst, rows, cols);
+    assert_eq!(dst, want);
+
+    let mut dst = vec![0.0; rows * cols];
+    transpose_parallel(&src, &mut dst, rows, cols, 3);
+    assert_eq!(dst, want);
+
+    // A 3×4 block out of the middle, into a wider destination
+    let mut dst = vec![0.0; 4 * 5];
+    transpose_strided(&src[2 * cols + 6..], cols, &mut dst, 5, 3, 4);
+    for i in 0..3 {
+        for j in 0..4 {
+            assert_eq!(dst[j * 5 + i], src[(2 + i) * cols + 6 + j]);
+        }
+    }
+
+    let n = 35;
+    let square = random(n, n, 2);
+    let mut in_place = square.clone();
+    transpose_in_place(&mut in_place, n);
+    let mut want = vec![0.0; n * n];
+    transpose_naive(&square, &mut want, n, n);
+    assert_eq!(in_place, want);
+}
@@ -0,0 +1,164 @@
+//! Property tests: random shapes, initial C, thread counts, and backends, all
+//! checked against the naive i-k-j loop.
+//!
+//! The hand-picked sizes in `correctness.rs` cover the edges someone thought
+//! of; these cover the combinations nobody did (an 11-row remainder for the
+//! 12-row kernel next to a 7-column one for the 8-column kernel, say). When a
+//! case fails, proptest shrinks it to a minimal shape and records the seed in
+//! `tests/properties.proptest-regressions`, which is worth committing.
+
+// Hundreds of random shapes up to 200³: hours under Miri
+#![cfg(not(miri))]
+
+use matmul::blocked::gemm_scalar::matmul_blocked_scalar;
+use matmul::matrix::compare::check_close;
+use matmul::matrix::generate::random;
+use matmul::matrix::naive_ikj::matmul_naive_ikj;
+use matmul::matrix::reference::error_bound;
+use matmul::threaded::BlockedGemm;
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::{Backend, multiply_parallel, multiply_with_backend};
+use proptest::prelude::*;
+use proptest::sample::Index;
+
+#[cfg(target_arch = "x86_64")]
+use matmul::blocked::{
+    gemm_4x4::matmul_blocked_4x4, gemm_8x8::matmul_blocked_8x8, gemm_12x4::matmul_blocked_12x4,
+};
+#[cfg(target_arch = "x86_64")]
+use matmul::features::{has_avx2, has_avx512};
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::Schedule;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_4x4_mt::matmul_blocked_4x4_mt_scheduled;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_8x8_mt::matmul_blocked_8x8_mt_scheduled;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_12x4_mt::matmul_blocked_12x4_mt_scheduled;
+
+/// C += A·B on m, n, k, with a thread (or band) count
+type Target = Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize, usize)>;
+
+/// Runs a blocked driver over `bands` equal row bands, one call each, the way
+/// a worker would. Band starts land anywhere, not just on tile boundaries,
+/// and the adaptive thread count doesn't get a say.
+fn in_bands(gemm: BlockedGemm) -> Target {
+    Box::new(move |a, b, c, m, n, k, bands| {
+        let rows = m.div_ceil(bands);
+        for (i, c_band) in c.chunks_mut(rows * n).enumerate() {
+            let start = i * rows;
+            let end = start + c_band.len() / n;
+            unsafe { gemm(a, b, c_band, m, n, k, Some(start), Some(end)) };
+        }
+    })
+}
+
+/// Every entry point this CPU can run.
+fn targets() -> Vec<(String, Target)> {
+    let mut targets: Vec<(String, Target)> = vec![
+        ("multiply_parallel".to_string(), Box::new(multiply_parallel)),
+        (
+            "matmul_naive_ikj_mt".to_string(),
+            Box::new(matmul_naive_ikj_mt),
+        ),
+        (
+            "matmul_blocked_scalar in bands".to_string(),
+            in_bands(matmul_blocked_scalar),
+        ),
+    ];
+    for backend in [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ] {
+        if backend.is_available() {
+            targets.push((
+                backend.to_string(),
+                Box::new(move |a, b, c, m, n, k, _| {
+                    multiply_with_backend(backend, a, b, c, m, n, k);
+                }),
+            ));
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx2() {
+            targets.push((
+                "matmul_blocked_4x4 in bands".to_string(),
+                in_bands(matmul_blocked_4x4),
+            ));
+            targets.push((
+                "matmul_blocked_12x4 in bands".to_string(),
+                in_bands(|a, b, c, m, n, k, start, end| unsafe {
+                    matmul_blocked_12x4(a, b, c, m, n, k, start, end, None, None)
+                }),
+            ));
+            for schedule in [Schedule::Static, Schedule::Dynamic] {
+                targets.push((
+                    format!("matmul_blocked_4x4_mt {:?}", schedule),
+                    Box::new(move |a, b, c, m, n, k, threads| {
+                        matmul_blocked_4x4_mt_scheduled(a, b, c, m, n, k, threads, schedule)
+                    }),
+                ));
+                targets.push((
+                    format!("matmul_blocked_12x4_mt {:?}", schedule),
+                    Box::new(move |a, b, c, m, n, k, threads| {
+                        matmul_blocked_12x4_mt_scheduled(a, b, c, m, n, k, threads, schedule)
+                    }),
+                ));
+            }
+        }
+        if has_avx512() {
+            targets.push((
+                "matmul_blocked_8x8 in bands".to_string(),
+                in_bands(|a, b, c, m, n, k, start, end| unsafe {
+                    matmul_blocked_8x8(a, b, c, m, n, k, start, end, None, None)
+                }),
+            ));
+            for schedule in [Schedule::Static, Schedule::Dynamic] {
+                targets.push((
+                    format!("matmul_blocked_8x8_mt {:?}", schedule),
+                    Box::new(move |a, b, c, m, n, k, threads| {
+                        matmul_blocked_8x8_mt_scheduled(a, b, c, m, n, k, threads, schedule)
+                    }),
+                ));
+            }
+        }
+    }
+    targets
+}
+
+proptest! {
+    // A few hundred cases over every backend stays within a few seconds
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn test_matches_naive(
+        m in 1..200usize,
+        n in 1..200usize,
+        k in 1..200usize,
+        threads in 1..=8usize,
+        target in any::<Index>(),
+        seed in any::<u64>(),
+    ) {
+        let targets = targets();
+        let (name, f) = &targets[target.index(targets.len())];
+        let a = random(m, k, seed);
+        let b = random(k, n, seed.wrapping_add(1));
+        let c_initial = random(m, n, seed.wrapping_add(2));
+
+        let mut expected = c_initial.clone();
+        matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+        let mut c = c_initial;
+        f(&a, &b, &mut c, m, n, k, threads);
+
+        // Each side is within γ(k + 1)·(|A|·|B| + |C|) of the exact result, and
+        // with every input in [-1, 1) that's at most γ(k + 1)·(k + 1)
+        let tolerance = 2.0 * error_bound(k + 1) * (k + 1) as f64;
+        if let Err(mismatch) = check_close(&expected, &c, 0.0, tolerance) {
+            return Err(TestCaseError::fail(format!("{}: {}", name, mismatch)));
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! The per-thread scratch cache behind the plain entry points: repeating a
+//! shape allocates nothing after the first call, the cap bounds what stays
+//! cached however the shapes vary, and a threaded multiply's workers share
+//! one Bᵀ.
+
+// Counts every allocation, Miri's included; nothing here needs checking
+// under it
+#![cfg(not(miri))]
+
+use matmul::matrix::generate::random;
+use matmul::workspace::DEFAULT_SCRATCH_CACHE_CAP;
+use matmul::{
+    available_backends, clear_scratch_cache, multiply, multiply_parallel, multiply_with_backend,
+    plan, set_scratch_cache_cap, warmup_for,
+};
+use std::sync::Mutex;
+
+#[global_allocator]
+static ALLOCATOR: heap::CountingAllocator = heap::CountingAllocator;
+
+/// Allocations and live bytes, per thread, so tests running alongside don't
+/// count; and bytes allocated on every thread, for threaded multiplies.
+mod heap {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        /// Signed: a thread can free what another allocated
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// The system allocator, plus per-thread counts.
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            let _ = LIVE.try_with(|live| live.set(live.get() - layout.size() as isize));
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new.is_null() {
+                let _ = LIVE.try_with(|live| live.set(live.get() - layout.size() as isize));
+                grow(new_size);
+            }
+            new
+        }
+    }
+
+    fn grow(size: usize) {
+        ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        let _ = LIVE.try_with(|live| live.set(live.get() + size as isize));
+    }
+
+    /// Allocations this thread made while `f` ran.
+    pub fn allocations_during(f: impl FnOnce()) -> usize {
+        let start = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - start
+    }
+
+    /// Bytes allocated on any thread while `f` ran, threads it spawned
+    /// included. Only meaningful while no other test is allocating.
+    pub fn bytes_allocated_during(f: impl FnOnce()) -> usize {
+        let start = ALLOCATED.load(Ordering::Relaxed);
+        f();
+        ALLOCATED.load(Ordering::Relaxed) - start
+    }
+
+    /// Bytes `f` left allocated on this thread.
+    pub fn retained_by(f: impl FnOnce()) -> isize {
+        let start = LIVE.with(Cell::get);
+        f();
+        LIVE.with(Cell::get) - start
+    }
+}
+
+/// The cap is global, so the tests take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+#[test]
+fn test_repeats_allocate_nothing() {
+    let _serial = SERIAL.lock().unwrap();
+    set_scratch_cache_cap(DEFAULT_SCRATCH_CACHE_CAP);
+
+    let n = 512;
+    let (a, b) = (random(n, n, 1), random(n, n, 2));
+    let mut c = vec![0.0; n * n];
+    multiply(&a, &b, &mut c, n, n, n);
+    let allocations = heap::allocations_during(|| {
+        for _ in 0..3 {
+            multiply(&a, &b, &mut c, n, n, n);
+        }
+    });
+    assert_eq!(allocations, 0);
+
+    for backend in available_backends() {
+        multiply_with_backend(backend, &a, &b, &mut c, n, n, n);
+        let allocations = heap::allocations_during(|| {
+            multiply_with_backend(backend, &a, &b, &mut c, n, n, n);
+        });
+        assert_eq!(allocations, 0, "{}", backend);
+    }
+    clear_scratch_cache();
+}
+
+#[test]
+fn test_warmup_for_makes_first_call_allocation_free() {
+    let _serial = SERIAL.lock().unwrap();
+    clear_scratch_cache();
+    set_scratch_cache_cap(DEFAULT_SCRATCH_CACHE_CAP);
+
+    for (m, n, k) in [(512, 512, 512), (300, 20, 700), (9, 333, 41)] {
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let mut c = vec![0.0; m * n];
+        let cached = warmup_for(m, n, k);
+        assert_eq!(
+            heap::retained_by(|| assert_eq!(warmup_for(m, n, k), cached)),
+            0
+        );
+        let allocations = heap::allocations_during(|| multiply(&a, &b, &mut c, m, n, k));
+        assert_eq!(allocations, 0, "{}x{}x{}", m, n, k);
+    }
+
+    // Past the cap, nothing is kept
+    clear_scratch_cache();
+    set_scratch_cache_cap(1 << 20);
+    assert_eq!(warmup_for(512, 512, 512), 0);
+    set_scratch_cache_cap(DEFAULT_SCRATCH_CACHE_CAP);
+}
+
+#[test]
+fn test_cap_bounds_cache() {
+    let _serial = SERIAL.lock().unwrap();
+    clear_scratch_cache();
+    let cap = 1 << 20;
+    set_scratch_cache_cap(cap);
+
+    // Bᵀ alone is 2 MiB, so nothing stays cached
+    let n = 512;
+    let (a, b) = (random(n, n, 1), random(n, n, 2));
+    let mut c = vec![0.0; n * n];
+    assert_eq!(heap::retained_by(|| multiply(&a, &b, &mut c, n, n, n)), 0);
+
+    // Shapes all over the place: the cache grows to fit, up to the cap
+    let mut cached = 0;
+    for (m, n, k) in [
+        (64, 64, 64),
+        (300, 20, 700),
+        (9, 333, 41),
+        (1, 900, 900),
+        (200, 200, 5),
+    ] {
+        let (a, b) = (random(m, k, 1), random(k, n, 2));
+        let mut c = vec![0.0; m * n];
+        cached += heap::retained_by(|| multiply(&a, &b, &mut c, m, n, k));
+        assert!(
+            (0..=cap as isize).contains(&cached),
+            "{}x{}x{} left {} bytes cached",
+            m,
+            n,
+            k,
+            cached
+        );
+    }
+    assert!(cached > 0);
+
+    assert_eq!(heap::retained_by(clear_scratch_cache), -cached);
+
+    // With caching off, every call frees what it allocated
+    set_scratch_cache_cap(0);
+    let (a, b) = (random(64, 64, 1), random(64, 64, 2));
+    let mut c = vec![0.0; 64 * 64];
+    assert_eq!(
+        heap::retained_by(|| multiply(&a, &b, &mut c, 64, 64, 64)),
+        0
+    );
+    set_scratch_cache_cap(DEFAULT_SCRATCH_CACHE_CAP);
+}
+
+#[test]
+fn test_threads_share_one_transpose() {
+    let _serial = SERIAL.lock().unwrap();
+    clear_scratch_cache();
+    set_scratch_cache_cap(DEFAULT_SCRATCH_CACHE_CAP);
+
+    // Enough work for four threads
+    let (m, n, k) = (512, 512, 600);
+    let threads = 4;
+    assert_eq!(plan(m, n, k, threads).threads, threads);
+    let (a, b) = (random(m, k, 1), random(k, n, 2));
+    let mut c = vec![0.0; m * n];
+    let bt = k * n * size_of::<f64>();
+
+    // Bᵀ, made once on this thread and all that's kept in its cache
+    multiply_parallel(&a, &b, &mut c, m, n, k, threads);
+    assert_eq!(heap::retained_by(clear_scratch_cache), -(bt as isize));
+    multiply_parallel(&a, &b, &mut c, m, n, k, threads);
+
+    // The workers start with empty caches every call, but allocate only
+    // their panels: together, less than one more Bᵀ
+    let allocated =
+        heap::bytes_allocated_during(|| multiply_parallel(&a, &b, &mut c, m, n, k, threads));
+    assert!(
+        allocated < bt,
+        "{} bytes allocated; Bᵀ is {}",
+        allocated,
+        bt
+    );
+    clear_scratch_cache();
+}
@@ -0,0 +1,245 @@
+//! Big-matrix stress tests, ignored by default: several KC and MC blocks deep
+//! at once, with thread remainders, where panel sizing and offset arithmetic
+//! bugs show up that the small shapes in `correctness.rs` never reach.
+//!
+//! ```text
+//! cargo test --release --test stress -- --ignored
+//! ```
+//!
+//! Each backend and threaded path is checked against the naive i-k-j loop,
+//! and its peak heap use during the call against the buffers it's documented
+//! to allocate. The 4096³ test needs about 1.5 GB.
+
+// Billions of flops per shape: far beyond Miri
+#![cfg(not(miri))]
+
+use matmul::matrix::compare::check_close;
+use matmul::matrix::generate::random;
+use matmul::matrix::naive_ikj::matmul_naive_ikj;
+use matmul::matrix::reference::error_bound;
+use matmul::threaded::naive_ikj_mt::matmul_naive_ikj_mt;
+use matmul::{Backend, multiply, multiply_parallel, multiply_with_backend};
+use std::sync::Mutex;
+
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::Schedule;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_4x4_mt::matmul_blocked_4x4_mt_scheduled;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_8x8_mt::matmul_blocked_8x8_mt_scheduled;
+#[cfg(target_arch = "x86_64")]
+use matmul::threaded::gemm_12x4_mt::matmul_blocked_12x4_mt_scheduled;
+
+#[global_allocator]
+static ALLOCATOR: heap::CountingAllocator = heap::CountingAllocator;
+
+/// Live and peak heap bytes, for the whole test binary.
+mod heap {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    /// The system allocator, plus live and peak byte counts.
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            let ptr = unsafe { System.alloc_zeroed(layout) };
+            if !ptr.is_null() {
+                grow(layout.size());
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) };
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = unsafe { System.realloc(ptr, layout, new_size) };
+            if !new.is_null() {
+                CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+                grow(new_size);
+            }
+            new
+        }
+    }
+
+    fn grow(size: usize) {
+        let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// Peak bytes allocated (and not yet freed) while `f` ran, above what was
+    /// live when it started. Only meaningful with nothing else allocating.
+    pub fn peak_during(f: impl FnOnce()) -> usize {
+        let start = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(start, Ordering::Relaxed);
+        f();
+        PEAK.load(Ordering::Relaxed).saturating_sub(start)
+    }
+}
+
+/// The peak measurements need the heap to themselves, so the tests take
+/// turns (which also keeps a few GB of matrices from being live at once).
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Odd, so the rows don't split evenly
+const THREADS: usize = 3;
+
+/// C += A·B on m, n, k, and how many row bands may run at once
+type Target = (
+    String,
+    Box<dyn Fn(&[f64], &[f64], &mut [f64], usize, usize, usize)>,
+    usize,
+);
+
+/// Every entry point this CPU can run.
+fn targets() -> Vec<Target> {
+    let mut targets: Vec<Target> = vec![
+        ("multiply".to_string(), Box::new(multiply), 1),
+        (
+            "multiply_parallel".to_string(),
+            Box::new(|a, b, c, m, n, k| multiply_parallel(a, b, c, m, n, k, THREADS)),
+            THREADS,
+        ),
+        (
+            "matmul_naive_ikj_mt".to_string(),
+            Box::new(|a, b, c, m, n, k| matmul_naive_ikj_mt(a, b, c, m, n, k, THREADS)),
+            THREADS,
+        ),
+    ];
+    for backend in [
+        Backend::Scalar,
+        Backend::ScalarIkj,
+        Backend::Avx2_4x4,
+        Backend::Avx2_12x4,
+        Backend::Avx512_8x8,
+    ] {
+        if backend.is_available() {
+            targets.push((
+                backend.to_string(),
+                Box::new(move |a, b, c, m, n, k| {
+                    multiply_with_backend(backend, a, b, c, m, n, k);
+                }),
+                1,
+            ));
+        }
+    }
+    #[cfg(target_arch = "x86_64")]
+    for schedule in [Schedule::Static, Schedule::Dynamic] {
+        if Backend::Avx2_4x4.is_available() {
+            targets.push((
+                format!("matmul_blocked_4x4_mt {:?}", schedule),
+                Box::new(move |a, b, c, m, n, k| {
+                    matmul_blocked_4x4_mt_scheduled(a, b, c, m, n, k, THREADS, schedule)
+                }),
+                THREADS,
+            ));
+            targets.push((
+                format!("matmul_blocked_12x4_mt {:?}", schedule),
+                Box::new(move |a, b, c, m, n, k| {
+                    matmul_blocked_12x4_mt_scheduled(a, b, c, m, n, k, THREADS, schedule)
+                }),
+                THREADS,
+            ));
+        }
+        if Backend::Avx512_8x8.is_available() {
+            targets.push((
+                format!("matmul_blocked_8x8_mt {:?}", schedule),
+                Box::new(move |a, b, c, m, n, k| {
+                    matmul_blocked_8x8_mt_scheduled(a, b, c, m, n, k, THREADS, schedule)
+                }),
+                THREADS,
+            ));
+        }
+    }
+    targets
+}
+
+/// Most heap a blocked driver holds: one transposed copy of B, which the
+/// bands share, and per band an MC×KC panel of A and a KC-deep panel of B (8
+/// columns at most). The i-k-j loops allocate nothing. 1 MiB on top covers
+/// thread bookkeeping.
+fn workspace_bound(k: usize, n: usize, bands: usize) -> usize {
+    const MC: usize = 128;
+    const KC: usize = 256;
+    let kc = k.min(KC);
+    (k * n + bands * (MC * kc + 8 * kc)) * size_of::<f64>() + (1 << 20)
+}
+
+/// Runs every target on one seeded m×k by k×n product.
+fn stress(m: usize, n: usize, k: usize) {
+    let _serial = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let c_initial = random(m, n, 3);
+    let mut expected = c_initial.clone();
+    matmul_naive_ikj(&a, &b, &mut expected, m, n, k);
+
+    // Each side is within γ(k + 1)·(|A|·|B| + |C|) of the exact result, and
+    // with every input in [-1, 1) that's at most γ(k + 1)·(k + 1)
+    let tolerance = 2.0 * error_bound(k + 1) * (k + 1) as f64;
+    let mut c = c_initial.clone();
+    for (name, f, bands) in targets() {
+        c.copy_from_slice(&c_initial);
+        let peak = heap::peak_during(|| f(&a, &b, &mut c, m, n, k));
+
+        if let Err(mismatch) = check_close(&expected, &c, 1e-12, tolerance) {
+            panic!("{} on {}x{}x{}: {}", name, m, n, k, mismatch);
+        }
+        let bound = workspace_bound(k, n, bands);
+        assert!(
+            peak <= bound,
+            "{} on {}x{}x{}: peak heap {} bytes, bound {}",
+            name,
+            m,
+            n,
+            k,
+            peak,
+            bound
+        );
+    }
+}
+
+#[test]
+#[ignore = "slow; run with --release -- --ignored"]
+fn test_stress_1024() {
+    stress(1024, 1024, 1024);
+}
+
+#[test]
+#[ignore = "slow; run with --release -- --ignored"]
+fn test_stress_2048() {
+    stress(2048, 2048, 2048);
+}
+
+#[test]
+#[ignore = "slow; run with --release -- --ignored"]
+fn test_stress_4096() {
+    stress(4096, 4096, 4096);
+}
+
+#[test]
+#[ignore = "slow; run with --release -- --ignored"]
+fn test_stress_rectangular() {
+    // Tall, wide, and neither, with a part-filled last MC block, columns
+    // past the last 8-wide tile, and a KC block of 1 or 255 at the end
+    stress(4099, 1031, 257);
+    stress(1031, 4099, 16 * 256 + 1);
+    stress(2053, 2047, 3 * 256 - 1);
+}
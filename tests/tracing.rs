@@ -0,0 +1,118 @@
+//! The `tracing` feature's events, captured with a minimal subscriber.
+
+#![cfg(feature = "tracing")]
+
+use matmul::matrix::generate::random;
+use matmul::{backend_for, multiply, multiply_parallel_with_stats};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// One captured event: its level and fields, values formatted with `{:?}`.
+#[derive(Debug)]
+struct Captured {
+    level: Level,
+    fields: HashMap<String, String>,
+}
+
+/// Records every event; spans aren't used by the crate.
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<Captured>>>);
+
+impl Capture {
+    /// Events with `message`, in order.
+    fn events(&self, message: &str) -> Vec<HashMap<String, String>> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e.fields.get("message").is_some_and(|m| m == message))
+            .map(|e| e.fields.clone())
+            .collect()
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.0.lock().unwrap().push(Captured {
+            level: *event.metadata().level(),
+            fields,
+        });
+    }
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn test_dispatch_event_names_the_detected_backend() {
+    let capture = Capture::default();
+    let (m, n, k) = (70, 45, 300);
+    let a = random(m, k, 1);
+    let b = random(k, n, 2);
+    let mut c = vec![0.0; m * n];
+
+    tracing::subscriber::with_default(capture.clone(), || {
+        multiply(&a, &b, &mut c, m, n, k);
+    });
+
+    let dispatch = capture.events("multiply dispatch");
+    assert_eq!(dispatch.len(), 1, "{:?}", dispatch);
+    let event = &dispatch[0];
+    assert_eq!(event["backend"], backend_for(m, n, k).to_string());
+    assert_eq!(event["threads"], "1");
+    assert_eq!(
+        (&*event["m"], &*event["n"], &*event["k"]),
+        ("70", "45", "300")
+    );
+    assert_eq!(event["kc"], "256");
+    let edge: f64 = event["edge_fraction"].parse().unwrap();
+    assert!(edge > 0.0 && edge < 1.0, "70×45 has edge rows and columns");
+
+    let levels: Vec<Level> = capture.0.lock().unwrap().iter().map(|e| e.level).collect();
+    assert_eq!(levels[0], Level::DEBUG);
+    // The blocked drivers also report each kc×mc block; k = 300 is two k blocks
+    let blocks = capture.events("gemm block");
+    assert!(blocks.len() >= 2, "{:?}", blocks);
+    assert!(levels[1..].iter().all(|&l| l == Level::TRACE));
+}
+
+#[test]
+fn test_parallel_dispatch_event_matches_stats() {
+    let capture = Capture::default();
+    let size = 512;
+    let a = random(size, size, 1);
+    let b = random(size, size, 2);
+    let mut c = vec![0.0; size * size];
+
+    let stats = tracing::subscriber::with_default(capture.clone(), || {
+        multiply_parallel_with_stats(&a, &b, &mut c, size, size, size, 4)
+    });
+
+    let dispatch = capture.events("multiply dispatch");
+    assert_eq!(dispatch.len(), 1, "{:?}", dispatch);
+    assert_eq!(dispatch[0]["backend"], stats.backend.to_string());
+    assert_eq!(dispatch[0]["threads"], stats.threads_used.to_string());
+    assert_eq!(dispatch[0]["edge_fraction"], "0.0");
+}